@@ -46,6 +46,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    // The server's chain was checked against the wallet's network on connect
+    let info = light_client.server_info().await?;
+    println!(
+        "  Server: lightwalletd {} ({}) on chain {}",
+        info.version, info.vendor, info.chain_name
+    );
+
     // Get latest block height
     println!("\nQuerying latest block height...");
     match light_client.get_latest_block_height().await {
@@ -66,7 +73,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("  Tip hash: {}", hex::encode(&hash));
         }
         Err(e) => {
-            eprintln!("Note: get_tip is not yet fully implemented.");
             eprintln!("Error: {}", e);
         }
     }
@@ -1,6 +1,7 @@
 //! Transaction sending example demonstrating how to build and send Zcash transactions
 
 use zcash_numi_sdk::transaction::TransactionBuilder;
+use zcash_numi_sdk::wallet::async_wallet::AsyncWallet;
 use zcash_numi_sdk::wallet::Wallet;
 use zcash_numi_sdk::Result;
 
@@ -13,11 +14,11 @@ async fn main() -> Result<()> {
     println!("==========================");
 
     // Create a wallet
-    let wallet = Wallet::new()?;
+    let wallet = AsyncWallet::new(Wallet::new()?);
     println!("✓ Wallet loaded");
 
-    // Get balance
-    let balance = match wallet.get_balance() {
+    // Get balance without blocking the async runtime
+    let balance = match wallet.get_balance().await {
         Ok(balance) => {
             println!(
                 "Current balance: {} ZEC",
@@ -34,13 +35,14 @@ async fn main() -> Result<()> {
     // Check if we have sufficient balance
     if balance.total == 0 {
         println!("⚠ Wallet has no balance. Please fund the wallet first.");
-        println!("Receiving address: {}", wallet.get_unified_address()?);
+        println!("Receiving address: {}", wallet.wallet().get_unified_address()?);
         return Ok(());
     }
 
     // Create transaction builder
     // Note: To actually send transactions, you need to connect to a zcashd node
     // via RPC. For this example, we'll demonstrate the API without an RPC client.
+    let wallet = wallet.into_inner().expect("wallet is not shared");
     let _tx_builder = TransactionBuilder::new(wallet);
 
     println!("\n📝 Transaction Builder Ready");
@@ -1,8 +1,8 @@
 //! Address parsing and validation using official Zcash address crate
 
 use crate::error::{Error, Result};
+use crate::params::NetworkParams;
 use zcash_address::ZcashAddress;
-use zcash_protocol::consensus::Network as ConsensusNetwork;
 use zcash_protocol::{PoolType, ShieldedProtocol};
 
 /// Parse and validate a Zcash address
@@ -10,14 +10,14 @@ use zcash_protocol::{PoolType, ShieldedProtocol};
 /// Supports Unified Addresses, Sapling addresses, Orchard addresses, and transparent addresses.
 pub fn parse_address(
     address: &str,
-    _network: ConsensusNetwork,
+    _network: NetworkParams,
 ) -> Result<ZcashAddress> {
     address.parse::<ZcashAddress>()
         .map_err(|e| Error::Address(format!("Failed to parse address: {}", e)))
 }
 
 /// Parse a Unified Address
-pub fn parse_unified_address(address: &str, network: ConsensusNetwork) -> Result<ZcashAddress> {
+pub fn parse_unified_address(address: &str, network: NetworkParams) -> Result<ZcashAddress> {
     let addr = parse_address(address, network)?;
     // Unified addresses can receive in multiple pools, check if it can receive as Sapling or Orchard
     // (Unified addresses support both)
@@ -30,12 +30,12 @@ pub fn parse_unified_address(address: &str, network: ConsensusNetwork) -> Result
 }
 
 /// Validate an address format without parsing
-pub fn is_valid_address(address: &str, _network: ConsensusNetwork) -> bool {
+pub fn is_valid_address(address: &str, _network: NetworkParams) -> bool {
     address.parse::<ZcashAddress>().is_ok()
 }
 
 /// Get address type from string
-pub fn get_address_type(address: &str, network: ConsensusNetwork) -> Result<AddressType> {
+pub fn get_address_type(address: &str, network: NetworkParams) -> Result<AddressType> {
     let addr = parse_address(address, network)?;
     // Check pool types to determine address type
     let can_sapling = addr.can_receive_as(PoolType::Shielded(ShieldedProtocol::Sapling));
@@ -86,7 +86,7 @@ impl AddressType {
 }
 
 /// Check if an address is shielded (supports memos)
-pub fn is_shielded_address(address: &str, network: ConsensusNetwork) -> Result<bool> {
+pub fn is_shielded_address(address: &str, network: NetworkParams) -> Result<bool> {
     let addr = parse_address(address, network)?;
     let can_sapling = addr.can_receive_as(PoolType::Shielded(ShieldedProtocol::Sapling));
     let can_orchard = addr.can_receive_as(PoolType::Shielded(ShieldedProtocol::Orchard));
@@ -101,7 +101,7 @@ mod tests {
     fn test_address_validation() {
         // Testnet Unified Address example (this is a placeholder - real addresses are longer)
         // In practice, you'd use real testnet addresses
-        let _testnet = ConsensusNetwork::TestNetwork;
+        let _testnet = NetworkParams::Testnet;
         
         // This test would need actual valid addresses to work
         // For now, we just verify the function exists and works
@@ -0,0 +1,476 @@
+//! Address book with zcashd label sync
+//!
+//! [`AddressBook`] stores human-readable labels for addresses in a
+//! `numi_address_book` table, by default inside the wallet database. When an
+//! [`RpcClient`] is attached, [`AddressBook::sync`] reconciles the labels with
+//! the node's account (label) metadata, so labels zcashd holds for its
+//! transparent addresses show up in the SDK.
+//!
+//! Sync is a three-way merge against the label recorded at the previous
+//! sync: a label changed on only one side is copied to the other, and a label
+//! changed on both sides is resolved by the configured [`SyncPreference`].
+
+use crate::client::RpcClient;
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::rpc::AddressSource;
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// A labelled address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressLabel {
+    pub address: String,
+    pub label: String,
+    /// Unix time the label was last changed
+    pub updated_at: u64,
+}
+
+/// Which side wins when a label was changed both locally and on the node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncPreference {
+    /// Keep the SDK label and push it to the node
+    #[default]
+    PreferLocal,
+    /// Keep the node label and pull it into the SDK
+    PreferNode,
+}
+
+/// How a single address is reconciled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelResolution {
+    /// Both sides already agree
+    InSync,
+    /// Write the local label (or clear the node label) on the node
+    PushToNode(Option<String>),
+    /// Store the node label (or remove the local label) in the SDK
+    PullFromNode(Option<String>),
+}
+
+/// Result of a label sync
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelSyncReport {
+    /// Addresses whose label was cleared on the node
+    pub pushed: Vec<String>,
+    /// Addresses whose label was taken from the node
+    pub pulled: Vec<String>,
+    /// Addresses labelled only in the SDK, as zcashd cannot store their label
+    pub local_only: Vec<String>,
+    /// Addresses the node failed to update, with the error
+    pub failed: Vec<(String, String)>,
+}
+
+/// Decide how to reconcile one address
+///
+/// # Arguments
+/// * `local` - Current SDK label (`None` if unlabelled or removed)
+/// * `synced` - Label both sides agreed on at the previous sync
+/// * `node` - Current node label
+/// * `prefer` - Winner when both sides changed
+pub fn resolve_label(
+    local: Option<&str>,
+    synced: Option<&str>,
+    node: Option<&str>,
+    prefer: SyncPreference,
+) -> LabelResolution {
+    if local == node {
+        return LabelResolution::InSync;
+    }
+    let push = LabelResolution::PushToNode(local.map(str::to_string));
+    let pull = LabelResolution::PullFromNode(node.map(str::to_string));
+    match (local != synced, node != synced) {
+        (true, false) => push,
+        (false, true) => pull,
+        _ => match prefer {
+            SyncPreference::PreferLocal => push,
+            SyncPreference::PreferNode => pull,
+        },
+    }
+}
+
+/// (current label, label at the last sync) of a stored address
+type LabelState = (Option<String>, Option<String>);
+
+const DB_CONTEXT: &str = "Address book error";
+
+/// Persistent address labels, optionally synced with zcashd
+pub struct AddressBook {
+    conn: Connection,
+    rpc: Option<RpcClient>,
+    preference: SyncPreference,
+}
+
+impl AddressBook {
+    /// Open (or create) an address book in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        // `label` is NULL for a locally removed label not yet synced;
+        // `synced_label` is the label agreed with the node at the last sync.
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_address_book (
+                address TEXT PRIMARY KEY,
+                label TEXT,
+                synced_label TEXT,
+                updated_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self {
+            conn,
+            rpc: None,
+            preference: SyncPreference::default(),
+        })
+    }
+
+    /// Open the address book stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Attach a zcashd client to sync labels with
+    pub fn attach_rpc(&mut self, client: RpcClient) {
+        self.rpc = Some(client);
+    }
+
+    /// Detach the zcashd client
+    pub fn detach_rpc(&mut self) -> Option<RpcClient> {
+        self.rpc.take()
+    }
+
+    /// Set which side wins when a label changed in both places
+    pub fn set_sync_preference(&mut self, preference: SyncPreference) {
+        self.preference = preference;
+    }
+
+    /// Label an address
+    pub fn set_label(&self, address: &str, label: &str) -> Result<()> {
+        if label.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Label must not be empty".to_string(),
+            ));
+        }
+        self.conn
+            .execute(
+                "INSERT INTO numi_address_book (address, label, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(address) DO UPDATE SET label = ?2, updated_at = ?3",
+                params![address, label, unix_now() as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Remove an address's label
+    ///
+    /// The removal is propagated to the node on the next sync.
+    pub fn remove_label(&self, address: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE numi_address_book SET label = NULL, updated_at = ?2 WHERE address = ?1",
+                params![address, unix_now() as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        self.conn
+            .execute(
+                "DELETE FROM numi_address_book WHERE address = ?1 AND synced_label IS NULL",
+                params![address],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Get an address's label
+    pub fn label(&self, address: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT label FROM numi_address_book WHERE address = ?1",
+                params![address],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?
+            .flatten())
+    }
+
+    /// Find the address with a label
+    pub fn address_for_label(&self, label: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT address FROM numi_address_book WHERE label = ?1 ORDER BY address LIMIT 1",
+                params![label],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// All labelled addresses, ordered by address
+    pub fn list(&self) -> Result<Vec<AddressLabel>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT address, label, updated_at FROM numi_address_book
+                 WHERE label IS NOT NULL ORDER BY address",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(AddressLabel {
+                    address: row.get(0)?,
+                    label: row.get(1)?,
+                    updated_at: row.get::<_, i64>(2)? as u64,
+                })
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(rows)
+    }
+
+    /// Label state of every stored address, including removals
+    fn sync_state(&self) -> Result<HashMap<String, LabelState>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT address, label, synced_label FROM numi_address_book")
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<HashMap<_, _>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(rows)
+    }
+
+    /// Record the label both sides agree on after a sync
+    fn store_synced(&self, address: &str, label: Option<&str>) -> Result<()> {
+        match label {
+            Some(label) => self
+                .conn
+                .execute(
+                    "INSERT INTO numi_address_book (address, label, synced_label, updated_at)
+                     VALUES (?1, ?2, ?2, ?3)
+                     ON CONFLICT(address) DO UPDATE SET label = ?2, synced_label = ?2,
+                        updated_at = CASE WHEN label IS ?2 THEN updated_at ELSE ?3 END",
+                    params![address, label, unix_now() as i64],
+                )
+                .map_err(db_error(DB_CONTEXT))?,
+            None => self
+                .conn
+                .execute(
+                    "DELETE FROM numi_address_book WHERE address = ?1",
+                    params![address],
+                )
+                .map_err(db_error(DB_CONTEXT))?,
+        };
+        Ok(())
+    }
+
+    /// Reconcile labels with the attached zcashd node
+    ///
+    /// zcashd keeps labels (its legacy account names) only for transparent
+    /// addresses, and `setaccount` rejects any name but the empty one: node
+    /// labels are pulled into the SDK and removals are pushed to the node,
+    /// but SDK labels cannot be written to it and are reported in
+    /// [`LabelSyncReport::local_only`] instead.
+    ///
+    /// Node labels are read with `getaccount` for the transparent addresses
+    /// in the address book and in the node's wallet, listed with
+    /// `listaddresses` on zcashd 4.7.0 and later and with
+    /// `getaddressesbyaccount` on older nodes (see
+    /// [`Capabilities::unified_accounts`](crate::rpc::Capabilities::unified_accounts)).
+    /// Addresses the node fails to update are reported in
+    /// [`LabelSyncReport::failed`] and retried on the next sync.
+    pub async fn sync(&self) -> Result<LabelSyncReport> {
+        let rpc = self
+            .rpc
+            .as_ref()
+            .ok_or_else(|| Error::Rpc("No RPC client attached to the address book".to_string()))?;
+
+        let local = self.sync_state()?;
+        let node_addresses = if rpc.capabilities().await?.unified_accounts {
+            node_transparent_addresses(&rpc.list_addresses().await?)
+        } else {
+            rpc.get_addresses_by_account("").await?
+        };
+        let candidates: Vec<&String> = node_addresses
+            .iter()
+            .chain(local.keys())
+            .filter(|address| is_transparent(address))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let node_labels: HashMap<String, String> = if candidates.is_empty() {
+            HashMap::new()
+        } else {
+            let labels: Vec<String> = rpc
+                .call_batch("getaccount", candidates.iter().map(|address| [address]))
+                .await?;
+            candidates
+                .into_iter()
+                .cloned()
+                .zip(labels)
+                .filter(|(_, label)| !label.is_empty())
+                .collect()
+        };
+
+        let addresses: BTreeSet<&String> = local.keys().chain(node_labels.keys()).collect();
+        let mut report = LabelSyncReport::default();
+        for address in addresses {
+            let (label, synced) = local.get(address).cloned().unwrap_or_default();
+            let node = node_labels.get(address).map(String::as_str);
+            match resolve_label(label.as_deref(), synced.as_deref(), node, self.preference) {
+                LabelResolution::InSync => {
+                    if synced.as_deref() != node {
+                        self.store_synced(address, node)?;
+                    }
+                }
+                LabelResolution::PushToNode(Some(_)) => report.local_only.push(address.clone()),
+                LabelResolution::PushToNode(None) => match rpc.set_account(address, "").await {
+                    Ok(()) => {
+                        self.store_synced(address, None)?;
+                        report.pushed.push(address.clone());
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "Failed to clear the label of {} on the node: {}",
+                            address,
+                            e
+                        );
+                        report.failed.push((address.clone(), e.to_string()));
+                    }
+                },
+                LabelResolution::PullFromNode(label) => {
+                    self.store_synced(address, label.as_deref())?;
+                    report.pulled.push(address.clone());
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Whether an address is a transparent (P2PKH or P2SH) address, the only
+/// kind zcashd can label
+fn is_transparent(address: &str) -> bool {
+    address.starts_with('t') && !address.starts_with("tex")
+}
+
+/// Receiving transparent addresses in a `listaddresses` response
+fn node_transparent_addresses(sources: &[AddressSource]) -> Vec<String> {
+    sources
+        .iter()
+        .filter_map(|source| source.transparent.as_ref())
+        .flat_map(|transparent| transparent.addresses.iter().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_resolve_label() {
+        use LabelResolution::*;
+        let local = SyncPreference::PreferLocal;
+        assert_eq!(resolve_label(Some("a"), None, Some("a"), local), InSync);
+        // Changed on one side only
+        assert_eq!(
+            resolve_label(Some("b"), Some("a"), Some("a"), local),
+            PushToNode(Some("b".to_string()))
+        );
+        assert_eq!(
+            resolve_label(Some("a"), Some("a"), None, local),
+            PullFromNode(None)
+        );
+        assert_eq!(
+            resolve_label(None, None, Some("n"), local),
+            PullFromNode(Some("n".to_string()))
+        );
+        // Changed on both sides
+        assert_eq!(
+            resolve_label(Some("b"), Some("a"), Some("c"), local),
+            PushToNode(Some("b".to_string()))
+        );
+        assert_eq!(
+            resolve_label(Some("b"), Some("a"), Some("c"), SyncPreference::PreferNode),
+            PullFromNode(Some("c".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_labels_persist() {
+        let path = TempDb::new();
+        let book = AddressBook::open(&path).unwrap();
+        book.set_label("t1abc", "Exchange").unwrap();
+        book.set_label("t1abc", "Cold storage").unwrap();
+        book.set_label("u1xyz", "Payroll").unwrap();
+        book.remove_label("u1xyz").unwrap();
+
+        let reopened = AddressBook::open(&path).unwrap();
+        assert_eq!(
+            reopened.label("t1abc").unwrap().as_deref(),
+            Some("Cold storage")
+        );
+        assert_eq!(
+            reopened
+                .address_for_label("Cold storage")
+                .unwrap()
+                .as_deref(),
+            Some("t1abc")
+        );
+        assert_eq!(reopened.list().unwrap().len(), 1);
+        assert_eq!(reopened.label("u1xyz").unwrap(), None);
+    }
+
+    #[test]
+    fn test_node_transparent_addresses() {
+        // Shape of zcashd 5.x's `listaddresses` result
+        let sources: Vec<AddressSource> = serde_json::from_value(serde_json::json!([
+            {
+                "source": "legacy_random",
+                "transparent": {
+                    "addresses": ["t1legacy"],
+                    "changeAddresses": ["t1change"]
+                }
+            },
+            {
+                "source": "imported",
+                "sapling": [{ "addresses": ["zs1imported"] }]
+            },
+            {
+                "source": "mnemonic_seed",
+                "transparent": { "addresses": ["t1seed"] },
+                "sapling": [
+                    { "zip32KeyPath": "m/32'/133'/2147483647'", "addresses": ["zs1legacy"] }
+                ],
+                "unified": [
+                    {
+                        "account": 0,
+                        "seedfp": "a1b2",
+                        "addresses": [
+                            {
+                                "diversifier_index": 0,
+                                "receiver_types": ["p2pkh", "sapling", "orchard"],
+                                "address": "u1account0"
+                            }
+                        ]
+                    }
+                ]
+            }
+        ]))
+        .unwrap();
+        assert_eq!(sources[2].unified[0].addresses[0].address, "u1account0");
+        assert_eq!(
+            node_transparent_addresses(&sources),
+            vec!["t1legacy".to_string(), "t1seed".to_string()]
+        );
+
+        assert!(is_transparent("t1legacy") && is_transparent("tmTestnet"));
+        assert!(!is_transparent("tex1abc") && !is_transparent("u1account0"));
+    }
+}
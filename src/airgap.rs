@@ -0,0 +1,682 @@
+//! Air-gapped (offline) signing workflow
+//!
+//! The flow uses Partially Created Zcash Transactions (PCZTs):
+//! 1. The online wallet (holding only viewing keys and sync state) builds a
+//!    proposal and exports it as an unsigned PCZT
+//! 2. The PCZT is moved to the offline machine as a file or a sequence of
+//!    animated QR frames
+//! 3. The offline wallet (holding the seed) signs the transparent, Sapling,
+//!    and Orchard spends it can authorize
+//! 4. The signed PCZT is moved back, proven, extracted, stored, and broadcast
+//!
+//! The transport layer ([`AirgapEnvelope`], [`QrFrameDecoder`]) is always
+//! available. The PCZT construction and signing helpers require the `pczt`
+//! feature.
+
+use crate::error::{Error, Result};
+use crate::signer::Signer;
+use crate::types::Network;
+use base64::Engine;
+use std::path::Path;
+
+/// Magic bytes identifying an air-gap envelope
+const ENVELOPE_MAGIC: &[u8; 4] = b"ZNAG";
+
+/// Current envelope format version
+const ENVELOPE_VERSION: u8 = 1;
+
+/// Length of the truncated BLAKE2b checksum appended to envelopes
+const CHECKSUM_LEN: usize = 4;
+
+/// Personalization for the envelope checksum
+const CHECKSUM_PERSONALIZATION: &[u8; 16] = b"NumiAirgapCheck_";
+
+/// UR type used for QR frames
+const UR_TYPE: &str = "zcash-airgap";
+
+/// Default maximum characters per QR frame payload
+pub const DEFAULT_QR_FRAGMENT_LEN: usize = 400;
+
+/// What an envelope carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// PCZT awaiting spend authorization signatures
+    UnsignedPczt,
+    /// PCZT signed by the offline wallet
+    SignedPczt,
+}
+
+impl PayloadKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            PayloadKind::UnsignedPczt => 0,
+            PayloadKind::SignedPczt => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(PayloadKind::UnsignedPczt),
+            1 => Ok(PayloadKind::SignedPczt),
+            other => Err(envelope_error(format!("Unknown payload kind: {}", other))),
+        }
+    }
+}
+
+fn network_to_byte(network: Network) -> u8 {
+    match network {
+        Network::Mainnet => 0,
+        Network::Testnet => 1,
+        Network::Regtest => 2,
+    }
+}
+
+fn network_from_byte(byte: u8) -> Result<Network> {
+    match byte {
+        0 => Ok(Network::Mainnet),
+        1 => Ok(Network::Testnet),
+        2 => Ok(Network::Regtest),
+        other => Err(envelope_error(format!("Unknown network tag: {}", other))),
+    }
+}
+
+fn envelope_error(msg: String) -> Error {
+    Error::Transaction(format!("Invalid air-gap envelope: {}", msg))
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(CHECKSUM_PERSONALIZATION)
+        .hash(data);
+    let mut out = [0u8; CHECKSUM_LEN];
+    out.copy_from_slice(&hash.as_bytes()[..CHECKSUM_LEN]);
+    out
+}
+
+/// A payload moved across the air gap
+///
+/// Binary layout: magic (4) | version (1) | kind (1) | network (1) |
+/// payload length (4, LE) | payload | checksum (4)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AirgapEnvelope {
+    pub kind: PayloadKind,
+    pub network: Network,
+    pub payload: Vec<u8>,
+}
+
+impl AirgapEnvelope {
+    /// Create an envelope for the given payload
+    pub fn new(kind: PayloadKind, network: Network, payload: Vec<u8>) -> Self {
+        Self {
+            kind,
+            network,
+            payload,
+        }
+    }
+
+    /// Serialize to the compact binary encoding
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.payload.len() + 15);
+        out.extend_from_slice(ENVELOPE_MAGIC);
+        out.push(ENVELOPE_VERSION);
+        out.push(self.kind.to_byte());
+        out.push(network_to_byte(self.network));
+        out.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.payload);
+        let sum = checksum(&out);
+        out.extend_from_slice(&sum);
+        out
+    }
+
+    /// Parse the compact binary encoding, verifying the checksum
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 11 + CHECKSUM_LEN {
+            return Err(envelope_error("too short".to_string()));
+        }
+        let (body, sum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if checksum(body) != sum {
+            return Err(envelope_error("checksum mismatch".to_string()));
+        }
+        if &body[..4] != ENVELOPE_MAGIC {
+            return Err(envelope_error("bad magic".to_string()));
+        }
+        if body[4] != ENVELOPE_VERSION {
+            return Err(envelope_error(format!("unsupported version {}", body[4])));
+        }
+        let kind = PayloadKind::from_byte(body[5])?;
+        let network = network_from_byte(body[6])?;
+        let len = u32::from_le_bytes([body[7], body[8], body[9], body[10]]) as usize;
+        let payload = &body[11..];
+        if payload.len() != len {
+            return Err(envelope_error(format!(
+                "payload length {} does not match header {}",
+                payload.len(),
+                len
+            )));
+        }
+        Ok(Self::new(kind, network, payload.to_vec()))
+    }
+
+    /// Write the envelope to a file
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Read an envelope from a file
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Split the envelope into UR-style QR frames for animated display
+    ///
+    /// Each frame has the form `ur:zcash-airgap/<seq>-<total>/<fragment>`,
+    /// where fragments are base64url chunks of the binary encoding. Frames can
+    /// be scanned in any order.
+    pub fn to_qr_frames(&self, max_fragment_len: usize) -> Vec<String> {
+        let encoded =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(self.to_bytes());
+        let max_fragment_len = max_fragment_len.max(1);
+        let chunks: Vec<&str> = encoded
+            .as_bytes()
+            .chunks(max_fragment_len)
+            .map(|c| std::str::from_utf8(c).expect("base64 output is ASCII"))
+            .collect();
+        let total = chunks.len();
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| format!("ur:{}/{}-{}/{}", UR_TYPE, i + 1, total, chunk))
+            .collect()
+    }
+}
+
+/// Reassembles an [`AirgapEnvelope`] from scanned QR frames
+#[derive(Debug, Default)]
+pub struct QrFrameDecoder {
+    fragments: Vec<Option<String>>,
+}
+
+impl QrFrameDecoder {
+    /// Create an empty decoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of distinct frames received so far, and the total expected
+    pub fn progress(&self) -> (usize, usize) {
+        let received = self.fragments.iter().filter(|f| f.is_some()).count();
+        (received, self.fragments.len())
+    }
+
+    /// Add a scanned frame
+    ///
+    /// Duplicate frames are ignored. Returns the envelope once every frame
+    /// has been received.
+    pub fn receive(&mut self, frame: &str) -> Result<Option<AirgapEnvelope>> {
+        let rest = frame
+            .trim()
+            .strip_prefix("ur:")
+            .and_then(|r| r.strip_prefix(UR_TYPE))
+            .and_then(|r| r.strip_prefix('/'))
+            .ok_or_else(|| envelope_error("not a zcash-airgap frame".to_string()))?;
+        let (seq, fragment) = rest
+            .split_once('/')
+            .ok_or_else(|| envelope_error("missing sequence".to_string()))?;
+        let (index, total) = seq
+            .split_once('-')
+            .and_then(|(i, t)| Some((i.parse::<usize>().ok()?, t.parse::<usize>().ok()?)))
+            .ok_or_else(|| envelope_error(format!("bad sequence '{}'", seq)))?;
+        if total == 0 || index == 0 || index > total {
+            return Err(envelope_error(format!("bad sequence '{}'", seq)));
+        }
+
+        if self.fragments.is_empty() {
+            self.fragments = vec![None; total];
+        } else if self.fragments.len() != total {
+            return Err(envelope_error(format!(
+                "frame belongs to a different sequence ({} frames, expected {})",
+                total,
+                self.fragments.len()
+            )));
+        }
+        self.fragments[index - 1] = Some(fragment.to_string());
+
+        if self.fragments.iter().any(|f| f.is_none()) {
+            return Ok(None);
+        }
+        let encoded: String = self.fragments.iter().flatten().map(String::as_str).collect();
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|e| envelope_error(format!("invalid base64: {}", e)))?;
+        AirgapEnvelope::from_bytes(&bytes).map(Some)
+    }
+}
+
+/// Sign an unsigned PCZT envelope with any [`Signer`]
+///
+/// Lets a hardware wallet or HSM take the place of the offline wallet in
+/// the flow above. Spends the signer holds no key for are left untouched.
+pub async fn sign_request_with(
+    signer: &dyn Signer,
+    envelope: &AirgapEnvelope,
+) -> Result<AirgapEnvelope> {
+    if envelope.kind != PayloadKind::UnsignedPczt {
+        return Err(Error::InvalidParameter(
+            "Expected an unsigned PCZT envelope".to_string(),
+        ));
+    }
+    let signed = signer
+        .sign_pczt(envelope.network, envelope.payload.clone())
+        .await?;
+    Ok(AirgapEnvelope::new(
+        PayloadKind::SignedPczt,
+        envelope.network,
+        signed,
+    ))
+}
+
+#[cfg(feature = "pczt")]
+pub(crate) use self::signing::build_signing_request;
+#[cfg(feature = "pczt")]
+pub use self::signing::{create_signing_request, extract_and_broadcast, sign_request};
+
+#[cfg(feature = "pczt")]
+mod signing {
+    use super::{AirgapEnvelope, PayloadKind};
+    use crate::approval::{check_threshold, request_total};
+    use crate::error::{Error, Result};
+    use crate::light_client::LightClient;
+    use crate::policy::{request_payments, SpendingPolicyEngine};
+    use crate::signer::UskSigner;
+    use crate::types::{NoteId, Pool};
+    use crate::wallet::frozen_notes::FrozenNotes;
+    use crate::wallet::Wallet;
+    use pczt::roles::prover::Prover;
+    use pczt::Pczt;
+    use std::collections::HashSet;
+    use zcash_client_backend::data_api::wallet::input_selection::{
+        GreedyInputSelector, InputSelector,
+    };
+    use zcash_client_backend::data_api::wallet::{
+        create_pczt_from_proposal, extract_and_store_transaction_from_pczt, ConfirmationsPolicy,
+        TargetHeight,
+    };
+    use zcash_client_backend::data_api::{
+        Account, AccountMeta, InputSource, NoteFilter, SpendableNotes, TargetValue, WalletRead,
+    };
+    use zcash_client_backend::fees::{zip317::SingleOutputChangeStrategy, DustOutputPolicy};
+    use zcash_client_backend::wallet::{Note, OvkPolicy, ReceivedNote};
+    use zcash_primitives::transaction::fees::zip317::FeeRule;
+    use zcash_primitives::transaction::TxId;
+    use zcash_protocol::{PoolType, ShieldedProtocol};
+
+    /// Wallet database as seen by input selection, without frozen notes
+    ///
+    /// Every selection query gets the frozen notes added to its exclusions,
+    /// so proposals are planned around them. Frozen transparent outputs
+    /// need no filtering: transfers only spend shielded notes, and
+    /// transparent funds are spent by shielding them.
+    struct Unfrozen<'a, DbT: InputSource> {
+        db: &'a DbT,
+        frozen: Vec<DbT::NoteRef>,
+    }
+
+    impl<'a, DbT: InputSource> Unfrozen<'a, DbT>
+    where
+        DbT::Error: std::fmt::Display,
+    {
+        /// Look up the wallet's references to the frozen shielded notes
+        ///
+        /// Notes that are spent or not yet mined have no reference and could
+        /// not be selected anyway.
+        fn new(db: &'a DbT, frozen: &HashSet<NoteId>, target_height: TargetHeight) -> Result<Self> {
+            let mut refs = Vec::new();
+            for note in frozen {
+                let protocol = match note.pool {
+                    Pool::Sapling => ShieldedProtocol::Sapling,
+                    Pool::Orchard => ShieldedProtocol::Orchard,
+                    Pool::Transparent => continue,
+                };
+                let mut txid: [u8; 32] = hex::decode(&note.txid)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| {
+                        Error::Database(format!("Invalid frozen note txid {}", note.txid))
+                    })?;
+                // Transaction IDs are displayed byte-reversed
+                txid.reverse();
+                let received = db
+                    .get_spendable_note(
+                        &TxId::from_bytes(txid),
+                        protocol,
+                        note.output_index,
+                        target_height,
+                    )
+                    .map_err(|e| Error::Database(format!("Failed to look up note: {}", e)))?;
+                if let Some(received) = received {
+                    refs.push(received.internal_note_id().clone());
+                }
+            }
+            Ok(Self { db, frozen: refs })
+        }
+
+        fn exclude(&self, exclude: &[DbT::NoteRef]) -> Vec<DbT::NoteRef> {
+            exclude.iter().chain(&self.frozen).cloned().collect()
+        }
+    }
+
+    impl<DbT: InputSource> InputSource for Unfrozen<'_, DbT> {
+        type Error = DbT::Error;
+        type AccountId = DbT::AccountId;
+        type NoteRef = DbT::NoteRef;
+
+        fn get_spendable_note(
+            &self,
+            txid: &TxId,
+            protocol: ShieldedProtocol,
+            index: u32,
+            target_height: TargetHeight,
+        ) -> std::result::Result<Option<ReceivedNote<Self::NoteRef, Note>>, Self::Error> {
+            let note = self
+                .db
+                .get_spendable_note(txid, protocol, index, target_height)?;
+            Ok(note.filter(|note| !self.frozen.contains(note.internal_note_id())))
+        }
+
+        fn select_spendable_notes(
+            &self,
+            account: Self::AccountId,
+            target_value: TargetValue,
+            sources: &[ShieldedProtocol],
+            target_height: TargetHeight,
+            confirmations_policy: ConfirmationsPolicy,
+            exclude: &[Self::NoteRef],
+        ) -> std::result::Result<SpendableNotes<Self::NoteRef>, Self::Error> {
+            self.db.select_spendable_notes(
+                account,
+                target_value,
+                sources,
+                target_height,
+                confirmations_policy,
+                &self.exclude(exclude),
+            )
+        }
+
+        fn select_unspent_notes(
+            &self,
+            account: Self::AccountId,
+            sources: &[ShieldedProtocol],
+            target_height: TargetHeight,
+            exclude: &[Self::NoteRef],
+        ) -> std::result::Result<SpendableNotes<Self::NoteRef>, Self::Error> {
+            self.db
+                .select_unspent_notes(account, sources, target_height, &self.exclude(exclude))
+        }
+
+        fn get_account_metadata(
+            &self,
+            account: Self::AccountId,
+            selector: &NoteFilter,
+            target_height: TargetHeight,
+            exclude: &[Self::NoteRef],
+        ) -> std::result::Result<AccountMeta, Self::Error> {
+            self.db
+                .get_account_metadata(account, selector, target_height, &self.exclude(exclude))
+        }
+    }
+
+    /// Build an unsigned PCZT paying the given ZIP-321 request
+    ///
+    /// Runs on the online (view-only) wallet. The wallet must be synced so
+    /// that spendable notes and witnesses are available. Change goes to the
+    /// pool of the wallet's [`ChangePolicy`](crate::wallet::ChangePolicy);
+    /// the builder keeps change in a pool the transaction already spends
+    /// from or pays to, so a proposal that would put it elsewhere fails.
+    /// Notes frozen with [`Wallet::freeze_note`] are left out of input
+    /// selection.
+    ///
+    /// The request is evaluated against the spending policies stored in the
+    /// wallet database (see [`SpendingPolicyEngine::for_wallet`]) and, once
+    /// built, recorded in its audit log as submitted.
+    ///
+    /// Requests at or above the threshold of the wallet's
+    /// [`ApprovalWorkflow`](crate::approval::ApprovalWorkflow) fail with
+    /// [`Error::PolicyViolation`]; build those from an approved proposal with
+    /// [`ApprovalWorkflow::create_signing_request`](crate::approval::ApprovalWorkflow::create_signing_request).
+    pub fn create_signing_request(
+        wallet: &Wallet,
+        request: zip321::TransactionRequest,
+    ) -> Result<AirgapEnvelope> {
+        check_threshold(wallet.db_path(), request_total(&request))?;
+        build_signing_request(wallet, request)
+    }
+
+    /// Build like [`create_signing_request`], without the approval check
+    pub(crate) fn build_signing_request(
+        wallet: &Wallet,
+        request: zip321::TransactionRequest,
+    ) -> Result<AirgapEnvelope> {
+        let policy = SpendingPolicyEngine::for_wallet(wallet)?;
+        let account = wallet.account_index();
+        let from_address = wallet.default_unified_address()?;
+        let payments = request_payments(&request);
+        policy.evaluate(account, &from_address, &payments, None, None)?;
+
+        let frozen = FrozenNotes::for_wallet(wallet)?.list()?;
+        let mut db = wallet.write_wallet_db()?;
+        let params = wallet.consensus_network();
+        let ufvk = wallet.unified_full_viewing_key()?;
+        let account_id = db
+            .get_account_for_ufvk(&ufvk)
+            .map_err(|e| Error::Database(format!("Failed to look up account: {}", e)))?
+            .ok_or_else(|| Error::Wallet("Wallet account has not been synced".to_string()))?
+            .id();
+
+        let change_pool = wallet.change_policy().pool();
+        let change_strategy = SingleOutputChangeStrategy::new(
+            FeeRule::standard(),
+            None,
+            change_pool.unwrap_or(ShieldedProtocol::Orchard),
+            DustOutputPolicy::default(),
+        );
+        let input_selector = GreedyInputSelector::new();
+
+        let confirmations_policy = ConfirmationsPolicy::default();
+        let (target_height, anchor_height) = db
+            .get_target_and_anchor_heights(confirmations_policy.trusted())
+            .map_err(|e| Error::Database(format!("Failed to read chain tip: {}", e)))?
+            .ok_or_else(|| Error::Wallet("Wallet has not been synced".to_string()))?;
+        let unfrozen = Unfrozen::new(&*db, &frozen, target_height)?;
+        let proposal = input_selector
+            .propose_transaction(
+                &params,
+                &unfrozen,
+                target_height,
+                anchor_height,
+                confirmations_policy,
+                account_id,
+                request,
+                &change_strategy,
+            )
+            .map_err(|e| Error::Transaction(format!("Failed to create proposal: {}", e)))?;
+        if let Some(required) = change_pool {
+            let misplaced = proposal
+                .steps()
+                .iter()
+                .flat_map(|step| step.balance().proposed_change())
+                .find(|change| change.output_pool() != PoolType::Shielded(required));
+            if let Some(change) = misplaced {
+                return Err(Error::Transaction(format!(
+                    "Change would go to the {:?} pool, but the change policy requires {:?}",
+                    change.output_pool(),
+                    required
+                )));
+            }
+        }
+
+        let pczt = create_pczt_from_proposal(
+            &mut db,
+            &params,
+            account_id,
+            OvkPolicy::Sender,
+            &proposal,
+        )
+        .map_err(|e| Error::Transaction(format!("Failed to create PCZT: {}", e)))?;
+        drop(db);
+
+        // The signed transaction may be broadcast anywhere, so the send
+        // counts towards the daily limit from now on
+        policy.record_submitted(account, &from_address, &payments, None, None)?;
+        Ok(AirgapEnvelope::new(
+            PayloadKind::UnsignedPczt,
+            wallet.network(),
+            pczt.serialize(),
+        ))
+    }
+
+    /// Sign every spend in an unsigned PCZT that this wallet can authorize
+    ///
+    /// Runs on the offline wallet holding the seed. Spends belonging to other
+    /// keys are left untouched. Use [`sign_request_with`](super::sign_request_with)
+    /// to sign with another [`Signer`](crate::signer::Signer).
+    pub fn sign_request(wallet: &Wallet, envelope: &AirgapEnvelope) -> Result<AirgapEnvelope> {
+        if envelope.kind != PayloadKind::UnsignedPczt {
+            return Err(Error::InvalidParameter(
+                "Expected an unsigned PCZT envelope".to_string(),
+            ));
+        }
+        if envelope.network != wallet.network() {
+            return Err(Error::InvalidParameter(format!(
+                "Envelope is for {:?} but wallet is on {:?}",
+                envelope.network,
+                wallet.network()
+            )));
+        }
+
+        let pczt = Pczt::parse(&envelope.payload)
+            .map_err(|e| Error::Transaction(format!("Failed to parse PCZT: {:?}", e)))?;
+        let signed = UskSigner::for_wallet(wallet)?.sign(pczt)?;
+
+        Ok(AirgapEnvelope::new(
+            PayloadKind::SignedPczt,
+            envelope.network,
+            signed.serialize(),
+        ))
+    }
+
+    /// Prove a signed PCZT, store the final transaction, and broadcast it
+    ///
+    /// Runs on the online wallet. Sapling proving parameters must be present in
+    /// the default zcash-params location.
+    ///
+    /// # Returns
+    /// The transaction ID (hex encoded)
+    pub async fn extract_and_broadcast(
+        wallet: &Wallet,
+        envelope: &AirgapEnvelope,
+        light_client: &mut LightClient,
+    ) -> Result<String> {
+        if envelope.kind != PayloadKind::SignedPczt {
+            return Err(Error::InvalidParameter(
+                "Expected a signed PCZT envelope".to_string(),
+            ));
+        }
+        let pczt = Pczt::parse(&envelope.payload)
+            .map_err(|e| Error::Transaction(format!("Failed to parse PCZT: {:?}", e)))?;
+
+        let sapling_prover = zcash_proofs::prover::LocalTxProver::with_default_location()
+            .ok_or_else(|| {
+                Error::Transaction("Sapling proving parameters not found".to_string())
+            })?;
+        let orchard_pk = orchard::circuit::ProvingKey::build();
+        let pczt = Prover::new(pczt)
+            .create_orchard_proof(&orchard_pk)
+            .map_err(|e| Error::Transaction(format!("Orchard proving failed: {:?}", e)))?
+            .create_sapling_proofs(&sapling_prover, &sapling_prover)
+            .map_err(|e| Error::Transaction(format!("Sapling proving failed: {:?}", e)))?
+            .finish();
+
+        let (spend_vk, output_vk) = sapling_prover.verifying_keys();
+        let orchard_vk = orchard::circuit::VerifyingKey::build();
+        let mut db = wallet.write_wallet_db()?;
+        let txid = extract_and_store_transaction_from_pczt::<_, ()>(
+            &mut db,
+            pczt,
+            Some((&spend_vk, &output_vk)),
+            Some(&orchard_vk),
+        )
+        .map_err(|e| Error::Transaction(format!("Failed to extract transaction: {}", e)))?;
+
+        let tx = db
+            .get_transaction(txid)
+            .map_err(|e| Error::Database(format!("Failed to load transaction: {}", e)))?
+            .ok_or_else(|| Error::Transaction("Extracted transaction not stored".to_string()))?;
+        let mut raw = Vec::new();
+        tx.write(&mut raw)?;
+
+        light_client.broadcast_transaction(&raw).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope() -> AirgapEnvelope {
+        AirgapEnvelope::new(PayloadKind::UnsignedPczt, Network::Testnet, (0u8..=255).collect())
+    }
+
+    /// Stands in for a hardware device: "signs" by appending a marker byte
+    struct DeviceSigner;
+
+    #[async_trait::async_trait]
+    impl Signer for DeviceSigner {
+        fn name(&self) -> &str {
+            "test device"
+        }
+
+        async fn sign_pczt(&self, _network: Network, mut pczt: Vec<u8>) -> Result<Vec<u8>> {
+            pczt.push(0xff);
+            Ok(pczt)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_request_with_external_signer() {
+        let signed = sign_request_with(&DeviceSigner, &envelope()).await.unwrap();
+        assert_eq!(signed.kind, PayloadKind::SignedPczt);
+        assert_eq!(signed.network, Network::Testnet);
+        assert_eq!(signed.payload.last(), Some(&0xff));
+
+        // Already signed envelopes are rejected
+        assert!(sign_request_with(&DeviceSigner, &signed).await.is_err());
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let env = envelope();
+        assert_eq!(AirgapEnvelope::from_bytes(&env.to_bytes()).unwrap(), env);
+
+        let mut corrupted = env.to_bytes();
+        corrupted[20] ^= 1;
+        assert!(AirgapEnvelope::from_bytes(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_qr_frames_out_of_order() {
+        let env = envelope();
+        let frames = env.to_qr_frames(50);
+        assert!(frames.len() > 1);
+
+        let mut decoder = QrFrameDecoder::new();
+        let mut result = None;
+        for frame in frames.iter().rev().chain(frames.iter().take(1)) {
+            if let Some(decoded) = decoder.receive(frame).unwrap() {
+                result = Some(decoded);
+            }
+        }
+        assert_eq!(result.unwrap(), env);
+    }
+}
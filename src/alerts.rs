@@ -0,0 +1,356 @@
+//! Velocity and anomaly alerts
+//!
+//! [`AnomalyDetector`] compares recent send activity recorded in the
+//! [`AuditLog`] against per-account baselines and raises [`Alert`]s when:
+//! - Outgoing volume within the window exceeds the baseline
+//! - Too many never-before-paid recipients appear within the window
+//! - The share of failed or blocked sends exceeds the baseline
+//!
+//! The detector re-evaluates whenever the chain advances on the wallet
+//! [`EventBus`](crate::events::EventBus). An alert is raised once when its
+//! condition starts and again only after activity has returned to normal.
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::error::Result;
+use crate::events::WalletEvent;
+use crate::store::unix_now;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{broadcast, mpsc};
+
+/// Expected send activity for an account
+///
+/// Limits left as `None` are not checked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertBaseline {
+    /// Length of the evaluation window in seconds
+    pub window_secs: u64,
+    /// Maximum submitted send total within the window (zatoshis)
+    pub max_outgoing: Option<u64>,
+    /// Maximum number of first-time recipients within the window
+    pub max_new_recipients: Option<u32>,
+    /// Maximum percentage of send attempts that fail or are blocked
+    pub max_failure_percent: Option<u32>,
+    /// Minimum attempts in the window before the failure rate is considered
+    pub min_attempts: u32,
+}
+
+impl Default for AlertBaseline {
+    fn default() -> Self {
+        Self {
+            window_secs: 60 * 60,
+            max_outgoing: None,
+            max_new_recipients: None,
+            max_failure_percent: None,
+            min_attempts: 5,
+        }
+    }
+}
+
+impl AlertBaseline {
+    /// Set the evaluation window
+    pub fn with_window(mut self, secs: u64) -> Self {
+        self.window_secs = secs;
+        self
+    }
+
+    /// Alert when outgoing volume in the window exceeds `zatoshis`
+    pub fn with_max_outgoing(mut self, zatoshis: u64) -> Self {
+        self.max_outgoing = Some(zatoshis);
+        self
+    }
+
+    /// Alert when more than `count` new recipients are paid in the window
+    pub fn with_max_new_recipients(mut self, count: u32) -> Self {
+        self.max_new_recipients = Some(count);
+        self
+    }
+
+    /// Alert when more than `percent` of at least `min_attempts` sends fail
+    pub fn with_max_failure_percent(mut self, percent: u32, min_attempts: u32) -> Self {
+        self.max_failure_percent = Some(percent);
+        self.min_attempts = min_attempts;
+        self
+    }
+}
+
+/// What deviated from the baseline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertKind {
+    /// Outgoing volume above the baseline
+    OutgoingVolume { total: u64, limit: u64 },
+    /// More first-time recipients than expected
+    RecipientNovelty {
+        new_recipients: Vec<String>,
+        limit: u32,
+    },
+    /// Failed or blocked sends above the baseline rate
+    FailureRate {
+        failed: u32,
+        attempts: u32,
+        limit_percent: u32,
+    },
+}
+
+impl AlertKind {
+    fn key(&self) -> &'static str {
+        match self {
+            AlertKind::OutgoingVolume { .. } => "outgoing_volume",
+            AlertKind::RecipientNovelty { .. } => "recipient_novelty",
+            AlertKind::FailureRate { .. } => "failure_rate",
+        }
+    }
+}
+
+/// An anomaly detected for an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    /// ZIP-32 account index
+    pub account: u32,
+    /// Unix time of the evaluation that raised the alert
+    pub timestamp: u64,
+    pub kind: AlertKind,
+}
+
+/// Raises alerts when account activity deviates from its baseline
+pub struct AnomalyDetector {
+    audit: AuditLog,
+    baselines: HashMap<u32, AlertBaseline>,
+    /// (account, alert kind) pairs currently raised
+    active: HashSet<(u32, &'static str)>,
+}
+
+impl AnomalyDetector {
+    /// Create a detector reading send history from `audit`
+    pub fn new(audit: AuditLog) -> Self {
+        Self {
+            audit,
+            baselines: HashMap::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    /// Monitor an account against a baseline
+    pub fn set_baseline(&mut self, account: u32, baseline: AlertBaseline) {
+        self.baselines.insert(account, baseline);
+    }
+
+    /// Stop monitoring an account
+    pub fn remove_baseline(&mut self, account: u32) {
+        self.baselines.remove(&account);
+        self.active.retain(|(a, _)| *a != account);
+    }
+
+    /// Alert conditions that currently hold for an account
+    ///
+    /// # Arguments
+    /// * `account` - ZIP-32 account index
+    /// * `now` - Evaluation time (unix seconds)
+    pub fn check(&self, account: u32, now: u64) -> Result<Vec<AlertKind>> {
+        let Some(baseline) = self.baselines.get(&account) else {
+            return Ok(Vec::new());
+        };
+        let since = now.saturating_sub(baseline.window_secs);
+        let entries = self.audit.entries_since(account, since)?;
+        let new_recipients = self.audit.new_recipients_since(account, since)?;
+
+        let mut outgoing = 0u64;
+        let mut attempts = 0u32;
+        let mut failed = 0u32;
+        for entry in &entries {
+            match &entry.event {
+                AuditEvent::SendSubmitted { amount, .. } => {
+                    outgoing = outgoing.saturating_add(*amount);
+                    attempts += 1;
+                }
+                AuditEvent::SendFailed { .. } | AuditEvent::PolicyViolation { .. } => {
+                    attempts += 1;
+                    failed += 1;
+                }
+                AuditEvent::DuplicatePaymentWarning { .. }
+                | AuditEvent::SendCompleted { .. }
+                | AuditEvent::SendConfirmed { .. } => {}
+            }
+        }
+
+        let mut kinds = Vec::new();
+        if let Some(limit) = baseline.max_outgoing {
+            if outgoing > limit {
+                kinds.push(AlertKind::OutgoingVolume {
+                    total: outgoing,
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = baseline.max_new_recipients {
+            if new_recipients.len() > limit as usize {
+                kinds.push(AlertKind::RecipientNovelty {
+                    new_recipients,
+                    limit,
+                });
+            }
+        }
+        if let Some(limit_percent) = baseline.max_failure_percent {
+            if attempts >= baseline.min_attempts.max(1)
+                && u64::from(failed) * 100 > u64::from(limit_percent) * u64::from(attempts)
+            {
+                kinds.push(AlertKind::FailureRate {
+                    failed,
+                    attempts,
+                    limit_percent,
+                });
+            }
+        }
+        Ok(kinds)
+    }
+
+    /// Evaluate all monitored accounts, returning newly raised alerts
+    pub fn evaluate(&mut self, now: u64) -> Result<Vec<Alert>> {
+        let mut accounts: Vec<u32> = self.baselines.keys().copied().collect();
+        accounts.sort_unstable();
+
+        let mut alerts = Vec::new();
+        for account in accounts {
+            let kinds = self.check(account, now)?;
+            self.active
+                .retain(|(a, key)| *a != account || kinds.iter().any(|k| k.key() == *key));
+            for kind in kinds {
+                if self.active.insert((account, kind.key())) {
+                    tracing::warn!("Anomaly on account {}: {:?}", account, kind);
+                    alerts.push(Alert {
+                        account,
+                        timestamp: now,
+                        kind,
+                    });
+                }
+            }
+        }
+        Ok(alerts)
+    }
+
+    /// Process a single wallet event, returning any new alerts
+    ///
+    /// Accounts are re-evaluated whenever the chain tip moves, blocks are
+    /// scanned or the chain is reorganized; other events are ignored.
+    pub fn handle_event(&mut self, event: &WalletEvent) -> Result<Vec<Alert>> {
+        match event {
+            WalletEvent::ChainTip { .. }
+            | WalletEvent::BlocksScanned { .. }
+            | WalletEvent::Reorg { .. } => self.evaluate(unix_now()),
+            WalletEvent::PaymentReceived(_) | WalletEvent::BandwidthCapReached { .. } => {
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Consume events from a subscription and forward alerts
+    ///
+    /// Runs until the event bus is closed or the output channel is dropped.
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<WalletEvent>,
+        output: mpsc::Sender<Alert>,
+    ) -> Result<()> {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Anomaly detector lagged, skipped {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            for alert in self.handle_event(&event)? {
+                if output.send(alert).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn detector() -> (TempDb, AnomalyDetector) {
+        let path = TempDb::new();
+        let detector = AnomalyDetector::new(AuditLog::open(&path).unwrap());
+        (path, detector)
+    }
+
+    fn sent(to: &str, amount: u64) -> AuditEvent {
+        AuditEvent::SendSubmitted {
+            from_address: "u1from".to_string(),
+            recipients: vec![to.to_string()],
+            amount,
+            operation_id: None,
+            payments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_volume_and_novelty_alerts_raised_once() {
+        let (_db, mut detector) = detector();
+        detector.set_baseline(
+            0,
+            AlertBaseline::default()
+                .with_window(1_000)
+                .with_max_outgoing(500)
+                .with_max_new_recipients(1),
+        );
+        let log = &detector.audit;
+        log.record_at(0, sent("u1old", 100), 100).unwrap();
+        log.record_at(0, sent("u1old", 300), 5_000).unwrap();
+        log.record_at(0, sent("u1new", 300), 5_100).unwrap();
+        assert_eq!(detector.evaluate(5_200).unwrap().len(), 1);
+
+        detector
+            .audit
+            .record_at(0, sent("u1newer", 1), 5_150)
+            .unwrap();
+        let alerts = detector.evaluate(5_200).unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(
+            alerts[0].kind,
+            AlertKind::RecipientNovelty {
+                new_recipients: vec!["u1new".to_string(), "u1newer".to_string()],
+                limit: 1,
+            }
+        );
+
+        // Already raised; once the window passes the alerts clear
+        assert!(detector.evaluate(5_300).unwrap().is_empty());
+        assert!(detector.evaluate(10_000).unwrap().is_empty());
+        assert!(detector.active.is_empty());
+    }
+
+    #[test]
+    fn test_failure_rate_alert() {
+        let (_db, mut detector) = detector();
+        detector.set_baseline(1, AlertBaseline::default().with_max_failure_percent(50, 3));
+        let failed = AuditEvent::SendFailed {
+            from_address: "u1from".to_string(),
+            amount: 10,
+            reason: "insufficient funds".to_string(),
+        };
+        detector.audit.record_at(1, failed.clone(), 1_000).unwrap();
+        detector.audit.record_at(1, failed, 1_001).unwrap();
+        assert!(detector.evaluate(1_002).unwrap().is_empty());
+
+        detector
+            .audit
+            .record_at(1, sent("u1to", 10), 1_002)
+            .unwrap();
+        let alerts = detector.evaluate(1_003).unwrap();
+        assert_eq!(
+            alerts[0].kind,
+            AlertKind::FailureRate {
+                failed: 2,
+                attempts: 3,
+                limit_percent: 50,
+            }
+        );
+    }
+}
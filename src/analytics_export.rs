@@ -0,0 +1,256 @@
+//! Read-only wallet exports for analytics
+//!
+//! Analytics and reporting pipelines need the wallet's history but should
+//! never get anything that could spend or watch its funds. An analytics
+//! export, written with
+//! [`Wallet::export_analytics_snapshot`](crate::wallet::Wallet::export_analytics_snapshot),
+//! is a new SQLite file with two plain tables, `transactions` and `notes`,
+//! one row per account and transaction or note, and an `export_info` table
+//! holding an [`AnalyticsExportInfo`]. Unlike a warm-start snapshot (see
+//! [`crate::snapshot`]) it is not a copy of the wallet database: it holds no
+//! keys of any kind, viewing keys included, no nullifiers and no commitment
+//! trees. Memos are included as text. The file is marked read-only.
+
+use crate::error::{Error, Result};
+use crate::store::db_error;
+use crate::types::{Network, Pool, Transaction, TransactionStatus, WalletNote};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Current analytics export format version
+pub const ANALYTICS_EXPORT_VERSION: u32 = 1;
+
+/// Metadata stored in an analytics export
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnalyticsExportInfo {
+    pub version: u32,
+    pub network: Network,
+    /// Unix time the export was written
+    pub created_at: u64,
+    /// ZIP-32 indexes of the exported accounts
+    pub accounts: Vec<u32>,
+    /// Number of rows in the `transactions` table
+    pub transactions: usize,
+    /// Number of rows in the `notes` table
+    pub notes: usize,
+}
+
+/// History of one account to export
+pub(crate) struct AccountHistory {
+    pub account: u32,
+    pub transactions: Vec<Transaction>,
+    pub notes: Vec<WalletNote>,
+}
+
+const DB_CONTEXT: &str = "Analytics export error";
+
+fn pool_name(pool: Pool) -> &'static str {
+    match pool {
+        Pool::Transparent => "transparent",
+        Pool::Sapling => "sapling",
+        Pool::Orchard => "orchard",
+    }
+}
+
+/// Write the history of `accounts` to a new read-only database at `dest`
+///
+/// `dest` must not exist.
+pub(crate) fn write_analytics_export(
+    dest: &Path,
+    info: &AnalyticsExportInfo,
+    accounts: &[AccountHistory],
+) -> Result<()> {
+    if dest.exists() {
+        return Err(Error::InvalidParameter(format!(
+            "Export file {} already exists",
+            dest.display()
+        )));
+    }
+    let written = (|| {
+        let mut conn = Connection::open(dest).map_err(db_error(DB_CONTEXT))?;
+        let tx = conn.transaction().map_err(db_error(DB_CONTEXT))?;
+        tx.execute_batch(
+            "CREATE TABLE export_info (info TEXT NOT NULL);
+             CREATE TABLE transactions (
+                account INTEGER NOT NULL,
+                txid TEXT NOT NULL,
+                status TEXT NOT NULL,
+                mined_height INTEGER,
+                amount INTEGER NOT NULL,
+                fee INTEGER NOT NULL,
+                memo TEXT,
+                block_time INTEGER,
+                PRIMARY KEY (account, txid)
+             );
+             CREATE TABLE notes (
+                account INTEGER NOT NULL,
+                pool TEXT NOT NULL,
+                txid TEXT NOT NULL,
+                output_index INTEGER NOT NULL,
+                value INTEGER NOT NULL,
+                mined_height INTEGER,
+                is_change INTEGER NOT NULL,
+                spent INTEGER NOT NULL,
+                pending_spend INTEGER NOT NULL,
+                spendable INTEGER NOT NULL,
+                frozen INTEGER NOT NULL,
+                PRIMARY KEY (account, pool, txid, output_index)
+             );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        tx.execute(
+            "INSERT INTO export_info (info) VALUES (?1)",
+            [serde_json::to_string(info)?],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        for history in accounts {
+            for t in &history.transactions {
+                let (status, height) = match t.status {
+                    TransactionStatus::Pending => ("pending", None),
+                    TransactionStatus::Confirmed { height } => ("confirmed", Some(height as i64)),
+                    TransactionStatus::Rejected => ("rejected", None),
+                };
+                tx.execute(
+                    "INSERT INTO transactions VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        history.account,
+                        t.txid,
+                        status,
+                        height,
+                        t.amount,
+                        t.fee as i64,
+                        t.memo,
+                        t.timestamp.map(|time| time as i64),
+                    ],
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+            }
+            for note in &history.notes {
+                tx.execute(
+                    "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        history.account,
+                        pool_name(note.pool),
+                        note.txid,
+                        note.output_index,
+                        note.value as i64,
+                        note.height.map(|height| height as i64),
+                        note.is_change,
+                        note.spent,
+                        note.pending_spend,
+                        note.spendable,
+                        note.frozen,
+                    ],
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+            }
+        }
+        tx.commit().map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(dest);
+        return Err(e);
+    }
+
+    let mut permissions = std::fs::metadata(dest)?.permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(dest, permissions)?;
+    Ok(())
+}
+
+/// Read the metadata of an analytics export
+pub fn read_analytics_export_info(path: &Path) -> Result<AnalyticsExportInfo> {
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(db_error(DB_CONTEXT))?;
+    let info: String = conn
+        .query_row("SELECT info FROM export_info", [], |row| row.get(0))
+        .map_err(|_| Error::InvalidParameter("Not a wallet analytics export".to_string()))?;
+    Ok(serde_json::from_str(&info)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_analytics_export() {
+        let path = TempDb::new();
+        let history = AccountHistory {
+            account: 0,
+            transactions: vec![
+                Transaction {
+                    txid: "aa".repeat(32),
+                    status: TransactionStatus::Confirmed { height: 100 },
+                    amount: 5000,
+                    fee: 0,
+                    memo: Some("Order 17".to_string()),
+                    timestamp: Some(1_700_000_000),
+                },
+                Transaction {
+                    txid: "bb".repeat(32),
+                    status: TransactionStatus::Pending,
+                    amount: -2000,
+                    fee: 10_000,
+                    memo: None,
+                    timestamp: None,
+                },
+            ],
+            notes: vec![WalletNote {
+                pool: Pool::Orchard,
+                txid: "aa".repeat(32),
+                output_index: 1,
+                value: 5000,
+                height: Some(100),
+                is_change: false,
+                nullifier: Some("cc".repeat(32)),
+                spent: false,
+                pending_spend: true,
+                spendable: false,
+                frozen: false,
+            }],
+        };
+        let info = AnalyticsExportInfo {
+            version: ANALYTICS_EXPORT_VERSION,
+            network: Network::Testnet,
+            created_at: 1_700_000_100,
+            accounts: vec![0],
+            transactions: 2,
+            notes: 1,
+        };
+        write_analytics_export(&path, &info, &[history]).unwrap();
+        assert_eq!(read_analytics_export_info(&path).unwrap(), info);
+        assert!(std::fs::metadata(&path).unwrap().permissions().readonly());
+        assert!(write_analytics_export(&path, &info, &[]).is_err());
+
+        let conn = Connection::open(&path).unwrap();
+        let (status, memo): (String, Option<String>) = conn
+            .query_row(
+                "SELECT status, memo FROM transactions WHERE amount > 0",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (status.as_str(), memo.as_deref()),
+            ("confirmed", Some("Order 17"))
+        );
+        let (pool, pending): (String, bool) = conn
+            .query_row("SELECT pool, pending_spend FROM notes", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((pool.as_str(), pending), ("orchard", true));
+        // Nothing that identifies spends or keys is exported
+        let columns: Vec<String> = conn
+            .prepare("SELECT name FROM pragma_table_info('notes')")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert!(!columns.iter().any(|c| c.contains("nullifier") || c == "nf"));
+    }
+}
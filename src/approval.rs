@@ -0,0 +1,734 @@
+//! Two-person approval for large sends
+//!
+//! Sends at or above a configured threshold are created as proposals and
+//! only sent once a second person, distinct from the proposer, has approved
+//! the proposal hash. Approvals are either:
+//! - A signature over the proposal hash, checked by a registered
+//!   [`SignatureVerifier`] (HSM, hardware wallet, or any other scheme)
+//! - An approval token from a callback service, computed as a keyed
+//!   BLAKE2b MAC of the proposal hash with a secret shared with the SDK
+//!
+//! Proposals and the threshold are stored in the wallet database. Every
+//! [`TransactionBuilder`](crate::transaction::TransactionBuilder) send and every
+//! [`airgap::create_signing_request`](crate::airgap) on that database fails
+//! at or above the threshold; approved proposals are sent with
+//! [`TransactionBuilder::execute_proposal`](crate::transaction::TransactionBuilder::execute_proposal)
+//! (zcashd) or
+//! [`ApprovalWorkflow::create_signing_request`] (local builder). Sends below
+//! the threshold can be made without approval.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::policy::{payments_total, request_payments, PolicyViolation};
+use crate::rpc::Payment;
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Verifies an approver's signature over a proposal hash
+pub trait SignatureVerifier: Send + Sync {
+    /// Whether `signature` is a valid signature of `message`
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// How an approver proves their approval
+enum ApproverKey {
+    Signature(Box<dyn SignatureVerifier>),
+    Token(Vec<u8>),
+}
+
+/// An approval submitted for a proposal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Approval {
+    /// Signature over the proposal hash
+    Signature {
+        approver: String,
+        signature: Vec<u8>,
+    },
+    /// Token issued by an approval callback service
+    Token { approver: String, token: String },
+}
+
+impl Approval {
+    fn approver(&self) -> &str {
+        match self {
+            Approval::Signature { approver, .. } | Approval::Token { approver, .. } => approver,
+        }
+    }
+}
+
+/// State of a send proposal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    /// Waiting for co-approval
+    PendingApproval,
+    /// Ready to execute
+    Approved,
+    /// Sent; carries the operation ID
+    Executed { operation_id: String },
+    /// Unsigned PCZT built by the local builder; signing and broadcast
+    /// happen outside the workflow
+    Built,
+    /// Cancelled by an operator
+    Cancelled,
+}
+
+/// A send awaiting approval and execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendProposal {
+    /// Hex-encoded proposal hash; approvals are made over these bytes
+    pub id: String,
+    pub proposer: String,
+    /// Source address of a zcashd send; empty for local-builder proposals
+    pub from_address: String,
+    pub payments: Vec<Payment>,
+    /// ZIP-321 URI of a local-builder proposal (see
+    /// [`ApprovalWorkflow::propose_request`])
+    #[serde(default)]
+    pub payment_request: Option<String>,
+    pub minconf: Option<u32>,
+    pub fee: Option<f64>,
+    /// Total payment amount in zatoshis
+    pub amount: u64,
+    pub created_at: u64,
+    /// Whether the amount reached the approval threshold
+    pub requires_approval: bool,
+    /// Approvers who have approved
+    pub approved_by: Vec<String>,
+    pub status: ProposalStatus,
+}
+
+impl SendProposal {
+    /// The proposal hash approvers sign
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        let id = hex::decode(&self.id)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid proposal ID: {}", e)))?;
+        id.try_into()
+            .map_err(|_| Error::InvalidParameter("Invalid proposal ID length".to_string()))
+    }
+}
+
+/// Hash a new proposal, whose ID is not set yet
+fn proposal_hash(proposal: &SendProposal, nonce: &[u8; 16]) -> Result<[u8; 32]> {
+    let encoded = serde_json::to_vec(&(
+        &proposal.proposer,
+        &proposal.from_address,
+        &proposal.payments,
+        &proposal.payment_request,
+        proposal.minconf,
+        proposal.fee,
+        proposal.created_at,
+        hex::encode(nonce),
+    ))?;
+    let hash = blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(b"NumiSendProposal")
+        .hash(&encoded);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(hash.as_bytes());
+    Ok(out)
+}
+
+/// Compute the approval token for a proposal hash
+///
+/// Approval services holding `secret` return this value to approve a proposal.
+pub fn approval_token(secret: &[u8], proposal_hash: &[u8; 32]) -> String {
+    let mac = blake2b_simd::Params::new()
+        .hash_length(32)
+        .key(secret)
+        .personal(b"NumiApprovalTokn")
+        .hash(proposal_hash);
+    hex::encode(mac.as_bytes())
+}
+
+/// Compare two byte strings without early exit
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+const DB_CONTEXT: &str = "Approval store error";
+
+fn create_tables(conn: &Connection) -> Result<()> {
+    // `executing` is set while a proposal is being sent, so two callers
+    // cannot send the same proposal
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS numi_send_proposals (
+            id TEXT PRIMARY KEY,
+            proposal TEXT NOT NULL,
+            executing INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS numi_approval_threshold (
+            id INTEGER PRIMARY KEY CHECK (id = 0),
+            threshold INTEGER NOT NULL
+        );",
+    )
+    .map_err(db_error(DB_CONTEXT))
+}
+
+/// Fail if a send of `amount` zatoshis needs an approved proposal
+///
+/// Checks against the threshold recorded in the wallet database at
+/// `db_path` by [`ApprovalWorkflow::open`]; sends are unrestricted while
+/// none is recorded.
+pub(crate) fn check_threshold(db_path: &Path, amount: u64) -> Result<()> {
+    let conn = open_connection(db_path).map_err(db_error(DB_CONTEXT))?;
+    create_tables(&conn)?;
+    let threshold = conn
+        .query_row("SELECT threshold FROM numi_approval_threshold", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .optional()
+        .map_err(db_error(DB_CONTEXT))?;
+    match threshold {
+        Some(threshold) if amount >= threshold as u64 => {
+            Err(Error::PolicyViolation(PolicyViolation::ApprovalRequired {
+                amount,
+                threshold: threshold as u64,
+            }))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Total amount of a ZIP-321 request in zatoshis
+pub(crate) fn request_total(request: &zip321::TransactionRequest) -> u64 {
+    request
+        .payments()
+        .values()
+        .map(|payment| payment.amount().map_or(0, u64::from))
+        .fold(0, u64::saturating_add)
+}
+
+/// A proposal taken by [`ApprovalWorkflow::claim`]
+pub(crate) enum Claim {
+    /// Approved and reserved for the caller, who must call
+    /// [`ApprovalWorkflow::finish`]
+    Ready(SendProposal),
+    /// Already sent under this operation ID
+    Executed(String),
+}
+
+/// Proposal and co-approval workflow for large sends
+pub struct ApprovalWorkflow {
+    /// Sends of at least this many zatoshis need a second approval
+    threshold: u64,
+    approvers: HashMap<String, ApproverKey>,
+    conn: Connection,
+}
+
+impl ApprovalWorkflow {
+    /// Open (or create) the workflow in the SQLite database at `path`,
+    /// requiring co-approval for sends of at least `threshold` zatoshis
+    ///
+    /// The threshold is recorded in the database, replacing any earlier
+    /// one, and enforced on every send from the wallet using it.
+    pub fn open(path: &Path, threshold: u64) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        create_tables(&conn)?;
+        conn.execute(
+            "INSERT INTO numi_approval_threshold (id, threshold) VALUES (0, ?1)
+             ON CONFLICT(id) DO UPDATE SET threshold = ?1",
+            params![threshold.min(i64::MAX as u64) as i64],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self {
+            threshold,
+            approvers: HashMap::new(),
+            conn,
+        })
+    }
+
+    /// Open the workflow in the wallet's database
+    pub fn for_wallet(wallet: &Wallet, threshold: u64) -> Result<Self> {
+        Self::open(wallet.db_path(), threshold)
+    }
+
+    /// Sends of at least this many zatoshis need a second approval
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    /// Register an approver who approves by signing the proposal hash
+    pub fn add_signature_approver(
+        &mut self,
+        approver: impl Into<String>,
+        verifier: Box<dyn SignatureVerifier>,
+    ) {
+        self.approvers
+            .insert(approver.into(), ApproverKey::Signature(verifier));
+    }
+
+    /// Register an approver whose callback service issues approval tokens
+    ///
+    /// # Arguments
+    /// * `approver` - Approver name
+    /// * `secret` - Secret shared with the service (at most 64 bytes), see [`approval_token`]
+    pub fn add_token_approver(
+        &mut self,
+        approver: impl Into<String>,
+        secret: Vec<u8>,
+    ) -> Result<()> {
+        if secret.is_empty() || secret.len() > blake2b_simd::KEYBYTES {
+            return Err(Error::InvalidParameter(format!(
+                "Approval secret must be 1 to {} bytes",
+                blake2b_simd::KEYBYTES
+            )));
+        }
+        self.approvers
+            .insert(approver.into(), ApproverKey::Token(secret));
+        Ok(())
+    }
+
+    /// Create a send proposal for zcashd
+    ///
+    /// # Arguments
+    /// * `proposer` - Person requesting the send; cannot approve it
+    /// * `from_address` - Source address (must be in the wallet managed by zcashd)
+    /// * `payments` - Vector of payments to send
+    /// * `minconf` - Minimum confirmations for source funds (default: 1)
+    /// * `fee` - Optional transaction fee in ZEC
+    pub fn propose(
+        &self,
+        proposer: impl Into<String>,
+        from_address: impl Into<String>,
+        payments: Vec<Payment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<SendProposal> {
+        if payments.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Proposal must contain at least one payment".to_string(),
+            ));
+        }
+        let amount = payments_total(&payments);
+        self.insert(SendProposal {
+            id: String::new(),
+            proposer: proposer.into(),
+            from_address: from_address.into(),
+            payments,
+            payment_request: None,
+            minconf,
+            fee,
+            amount,
+            created_at: unix_now(),
+            requires_approval: false,
+            approved_by: Vec::new(),
+            status: ProposalStatus::PendingApproval,
+        })
+    }
+
+    /// Create a send proposal for the local builder
+    ///
+    /// Once approved, the unsigned transaction is built with
+    /// [`create_signing_request`](Self::create_signing_request).
+    ///
+    /// # Arguments
+    /// * `proposer` - Person requesting the send; cannot approve it
+    /// * `request` - ZIP-321 payment request to pay
+    pub fn propose_request(
+        &self,
+        proposer: impl Into<String>,
+        request: &zip321::TransactionRequest,
+    ) -> Result<SendProposal> {
+        let payments = request_payments(request);
+        if payments.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Proposal must contain at least one payment".to_string(),
+            ));
+        }
+        self.insert(SendProposal {
+            id: String::new(),
+            proposer: proposer.into(),
+            from_address: String::new(),
+            payments,
+            payment_request: Some(request.to_uri()),
+            minconf: None,
+            fee: None,
+            amount: request_total(request),
+            created_at: unix_now(),
+            requires_approval: false,
+            approved_by: Vec::new(),
+            status: ProposalStatus::PendingApproval,
+        })
+    }
+
+    /// Hash, classify and store a new proposal
+    fn insert(&self, mut proposal: SendProposal) -> Result<SendProposal> {
+        proposal.id = hex::encode(proposal_hash(&proposal, &rand::random())?);
+        proposal.requires_approval = proposal.amount >= self.threshold;
+        if !proposal.requires_approval {
+            proposal.status = ProposalStatus::Approved;
+        }
+        self.conn
+            .execute(
+                "INSERT INTO numi_send_proposals (id, proposal, created_at) VALUES (?1, ?2, ?3)",
+                params![
+                    proposal.id,
+                    serde_json::to_string(&proposal)?,
+                    proposal.created_at as i64
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(proposal)
+    }
+
+    fn save(&self, proposal: &SendProposal) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE numi_send_proposals SET proposal = ?2 WHERE id = ?1",
+                params![proposal.id, serde_json::to_string(proposal)?],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Look up a proposal
+    pub fn get(&self, proposal_id: &str) -> Result<Option<SendProposal>> {
+        let json = self
+            .conn
+            .query_row(
+                "SELECT proposal FROM numi_send_proposals WHERE id = ?1",
+                params![proposal_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(json.map(|json| serde_json::from_str(&json)).transpose()?)
+    }
+
+    fn require(&self, proposal_id: &str) -> Result<SendProposal> {
+        self.get(proposal_id)?
+            .ok_or_else(|| Error::InvalidParameter(format!("Unknown proposal {}", proposal_id)))
+    }
+
+    /// Proposals waiting for co-approval, oldest first
+    pub fn pending(&self) -> Result<Vec<SendProposal>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT proposal FROM numi_send_proposals ORDER BY created_at, id")
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        let mut pending = Vec::new();
+        for json in rows {
+            let proposal: SendProposal = serde_json::from_str(&json)?;
+            if proposal.status == ProposalStatus::PendingApproval {
+                pending.push(proposal);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Submit an approval for a proposal
+    ///
+    /// # Returns
+    /// The proposal's new status
+    pub fn approve(&self, proposal_id: &str, approval: Approval) -> Result<ProposalStatus> {
+        let mut proposal = self.require(proposal_id)?;
+        if !matches!(
+            proposal.status,
+            ProposalStatus::PendingApproval | ProposalStatus::Approved
+        ) {
+            return Err(Error::Transaction(format!(
+                "Proposal {} can no longer be approved",
+                proposal_id
+            )));
+        }
+
+        let approver = approval.approver().to_string();
+        if approver == proposal.proposer {
+            return Err(Error::Transaction(
+                "The proposer cannot approve their own proposal".to_string(),
+            ));
+        }
+        let key = self
+            .approvers
+            .get(&approver)
+            .ok_or_else(|| Error::InvalidParameter(format!("Unknown approver {}", approver)))?;
+
+        let hash = proposal.hash()?;
+        let valid = match (key, &approval) {
+            (ApproverKey::Signature(verifier), Approval::Signature { signature, .. }) => {
+                verifier.verify(&hash, signature)
+            }
+            (ApproverKey::Token(secret), Approval::Token { token, .. }) => {
+                constant_time_eq(approval_token(secret, &hash).as_bytes(), token.as_bytes())
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(Error::Transaction(format!(
+                "Invalid approval from {} for proposal {}",
+                approver, proposal_id
+            )));
+        }
+
+        if !proposal.approved_by.contains(&approver) {
+            proposal.approved_by.push(approver);
+        }
+        proposal.status = ProposalStatus::Approved;
+        self.save(&proposal)?;
+        Ok(proposal.status)
+    }
+
+    /// Cancel a proposal that has not been executed
+    pub fn cancel(&self, proposal_id: &str) -> Result<()> {
+        let mut proposal = self.require(proposal_id)?;
+        if matches!(
+            proposal.status,
+            ProposalStatus::Executed { .. } | ProposalStatus::Built
+        ) {
+            return Err(Error::Transaction(format!(
+                "Proposal {} was already executed",
+                proposal_id
+            )));
+        }
+        proposal.status = ProposalStatus::Cancelled;
+        self.save(&proposal)
+    }
+
+    /// Reserve an approved proposal for sending
+    ///
+    /// # Arguments
+    /// * `proposal_id` - ID returned by [`propose`](Self::propose) or
+    ///   [`propose_request`](Self::propose_request)
+    /// * `local` - Whether the caller is the local builder
+    pub(crate) fn claim(&self, proposal_id: &str, local: bool) -> Result<Claim> {
+        let proposal = self.require(proposal_id)?;
+        match &proposal.status {
+            ProposalStatus::Approved => {}
+            ProposalStatus::PendingApproval => {
+                return Err(Error::Transaction(format!(
+                    "Proposal {} is awaiting co-approval",
+                    proposal_id
+                )))
+            }
+            ProposalStatus::Executed { operation_id } => {
+                return Ok(Claim::Executed(operation_id.clone()))
+            }
+            ProposalStatus::Built => {
+                return Err(Error::Transaction(format!(
+                    "A transaction was already built for proposal {}",
+                    proposal_id
+                )))
+            }
+            ProposalStatus::Cancelled => {
+                return Err(Error::Transaction(format!(
+                    "Proposal {} was cancelled",
+                    proposal_id
+                )))
+            }
+        }
+        if proposal.payment_request.is_some() != local {
+            return Err(Error::Transaction(format!(
+                "Proposal {} is for the {} builder",
+                proposal_id,
+                if local { "zcashd" } else { "local" }
+            )));
+        }
+
+        let claimed = self
+            .conn
+            .execute(
+                "UPDATE numi_send_proposals SET executing = 1 WHERE id = ?1 AND executing = 0",
+                params![proposal_id],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        if claimed == 0 {
+            return Err(Error::Transaction(format!(
+                "Proposal {} is already being executed",
+                proposal_id
+            )));
+        }
+        Ok(Claim::Ready(proposal))
+    }
+
+    /// Record the outcome of sending a claimed proposal
+    ///
+    /// # Arguments
+    /// * `proposal` - The claimed proposal
+    /// * `status` - Its new status, or `None` to release it for another
+    ///   attempt when nothing was sent
+    pub(crate) fn finish(
+        &self,
+        mut proposal: SendProposal,
+        status: Option<ProposalStatus>,
+    ) -> Result<()> {
+        match status {
+            Some(status) => {
+                proposal.status = status;
+                self.save(&proposal)
+            }
+            None => {
+                self.conn
+                    .execute(
+                        "UPDATE numi_send_proposals SET executing = 0 WHERE id = ?1",
+                        params![proposal.id],
+                    )
+                    .map_err(db_error(DB_CONTEXT))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Build the unsigned PCZT of an approved local-builder proposal
+    ///
+    /// Like [`airgap::create_signing_request`](crate::airgap::create_signing_request),
+    /// but allowed at or above the threshold. The proposal is marked
+    /// [`ProposalStatus::Built`]; sign and broadcast the PCZT as usual.
+    ///
+    /// # Arguments
+    /// * `proposal_id` - ID returned by [`propose_request`](Self::propose_request)
+    /// * `wallet` - Wallet to build the transaction from
+    #[cfg(feature = "pczt")]
+    pub fn create_signing_request(
+        &self,
+        proposal_id: &str,
+        wallet: &Wallet,
+    ) -> Result<crate::airgap::AirgapEnvelope> {
+        let proposal = match self.claim(proposal_id, true)? {
+            Claim::Ready(proposal) => proposal,
+            Claim::Executed(_) => unreachable!("local-builder proposals are never executed"),
+        };
+        let built = proposal
+            .payment_request
+            .as_deref()
+            .ok_or_else(|| Error::Transaction("Proposal has no payment request".to_string()))
+            .and_then(|uri| {
+                zip321::TransactionRequest::from_uri(uri)
+                    .map_err(|e| Error::Transaction(format!("Invalid payment request: {}", e)))
+            })
+            .and_then(|request| crate::airgap::build_signing_request(wallet, request));
+        match built {
+            Ok(envelope) => {
+                self.finish(proposal, Some(ProposalStatus::Built))?;
+                Ok(envelope)
+            }
+            Err(e) => {
+                self.finish(proposal, None)?;
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn payment(amount: f64) -> Vec<Payment> {
+        vec![Payment {
+            address: "u1recipient".to_string(),
+            amount,
+            memo: None,
+        }]
+    }
+
+    fn temp_workflow(threshold: u64) -> (TempDb, ApprovalWorkflow) {
+        let path = TempDb::new();
+        let workflow = ApprovalWorkflow::open(&path, threshold).unwrap();
+        (path, workflow)
+    }
+
+    #[test]
+    fn test_small_sends_need_no_approval() {
+        let (_db, workflow) = temp_workflow(100_000_000);
+        let proposal = workflow
+            .propose("alice", "u1from", payment(0.5), None, None)
+            .unwrap();
+        assert_eq!(proposal.status, ProposalStatus::Approved);
+    }
+
+    #[test]
+    fn test_token_co_approval() {
+        let (_db, mut workflow) = temp_workflow(100_000_000);
+        workflow
+            .add_token_approver("bob", b"bob-secret".to_vec())
+            .unwrap();
+        workflow
+            .add_token_approver("alice", b"alice-secret".to_vec())
+            .unwrap();
+
+        let proposal = workflow
+            .propose("alice", "u1from", payment(2.0), None, None)
+            .unwrap();
+        assert_eq!(proposal.status, ProposalStatus::PendingApproval);
+        let hash = proposal.hash().unwrap();
+
+        // Proposer cannot self-approve, and a wrong token is rejected
+        let own = Approval::Token {
+            approver: "alice".to_string(),
+            token: approval_token(b"alice-secret", &hash),
+        };
+        assert!(workflow.approve(&proposal.id, own).is_err());
+        let forged = Approval::Token {
+            approver: "bob".to_string(),
+            token: approval_token(b"guess", &hash),
+        };
+        assert!(workflow.approve(&proposal.id, forged).is_err());
+
+        let valid = Approval::Token {
+            approver: "bob".to_string(),
+            token: approval_token(b"bob-secret", &hash),
+        };
+        assert_eq!(
+            workflow.approve(&proposal.id, valid).unwrap(),
+            ProposalStatus::Approved
+        );
+        assert_eq!(
+            workflow.get(&proposal.id).unwrap().unwrap().approved_by,
+            vec!["bob"]
+        );
+    }
+
+    #[test]
+    fn test_proposals_persist_and_claim_once() {
+        let path = TempDb::new();
+        let workflow = ApprovalWorkflow::open(&path, 100_000_000).unwrap();
+        let pending = workflow
+            .propose("alice", "u1from", payment(2.0), None, None)
+            .unwrap();
+        let small = workflow
+            .propose("alice", "u1from", payment(0.5), None, None)
+            .unwrap();
+
+        let reopened = ApprovalWorkflow::open(&path, 100_000_000).unwrap();
+        assert!(check_threshold(&path, 99_999_999).is_ok());
+        assert!(matches!(
+            check_threshold(&path, 100_000_000),
+            Err(Error::PolicyViolation(_))
+        ));
+        let ids: Vec<_> = reopened
+            .pending()
+            .unwrap()
+            .into_iter()
+            .map(|p| p.id)
+            .collect();
+        assert_eq!(ids, vec![pending.id.clone()]);
+        assert!(reopened.claim(&pending.id, false).is_err());
+
+        // An approved proposal is sent by one caller only, and only by its builder
+        assert!(reopened.claim(&small.id, true).is_err());
+        let Claim::Ready(claimed) = reopened.claim(&small.id, false).unwrap() else {
+            panic!("proposal not claimed");
+        };
+        assert!(workflow.claim(&small.id, false).is_err());
+        let executed = ProposalStatus::Executed {
+            operation_id: "opid-1".to_string(),
+        };
+        reopened.finish(claimed, Some(executed)).unwrap();
+        assert!(matches!(
+            workflow.claim(&small.id, false).unwrap(),
+            Claim::Executed(operation_id) if operation_id == "opid-1"
+        ));
+        assert!(workflow.cancel(&small.id).is_err());
+    }
+}
@@ -0,0 +1,513 @@
+//! Audit log
+//!
+//! An append-only record of security-relevant wallet activity: sends that
+//! were requested and submitted, and sends that were blocked by the spending
+//! policy. Entries are stored in a `numi_audit_log` table, by default inside
+//! the wallet database, so they survive restarts and can be exported for
+//! compliance review, e.g. as an [`AuditPackage`]. Entries of one send share
+//! its [`CorrelationId`].
+//!
+//! The recipients each account has sent to are indexed in a
+//! `numi_audit_recipients` table with the time they were first paid, so
+//! first-time recipients can be found without reading the whole log.
+
+use crate::correlation::CorrelationId;
+use crate::db_encryption::open_connection;
+use crate::error::Result;
+use crate::price_history::FiatValue;
+use crate::store::{db_error, unix_now};
+use crate::types::Transaction;
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// One payment of a submitted send
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditPayment {
+    pub address: String,
+    /// Amount in zatoshis
+    pub amount: u64,
+    pub memo: Option<String>,
+}
+
+/// An auditable event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A send was handed to the node or light client
+    SendSubmitted {
+        from_address: String,
+        recipients: Vec<String>,
+        /// Total amount in zatoshis, excluding fees
+        amount: u64,
+        operation_id: Option<String>,
+        /// The individual payments; empty for entries recorded before
+        /// payments were logged
+        #[serde(default)]
+        payments: Vec<AuditPayment>,
+    },
+    /// A send was rejected before submission
+    SendFailed {
+        from_address: String,
+        amount: u64,
+        reason: String,
+    },
+    /// A send was blocked by the spending policy
+    PolicyViolation {
+        from_address: String,
+        amount: u64,
+        violation: String,
+    },
+    /// A payment repeated a recently submitted one but was allowed
+    DuplicatePaymentWarning {
+        from_address: String,
+        address: String,
+        amount: u64,
+    },
+    /// The operation of a submitted send produced a transaction
+    SendCompleted { operation_id: String, txid: String },
+    /// The transaction of a send reached the awaited number of confirmations
+    SendConfirmed { txid: String, confirmations: u64 },
+}
+
+/// A recorded audit event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// Unix time the event was recorded
+    pub timestamp: u64,
+    /// ZIP-32 account index the event relates to
+    pub account: u32,
+    pub event: AuditEvent,
+    /// Send the event belongs to, if recorded with one
+    #[serde(default)]
+    pub correlation_id: Option<CorrelationId>,
+}
+
+const DB_CONTEXT: &str = "Audit log error";
+
+/// Persistent, append-only audit log
+pub struct AuditLog {
+    conn: Connection,
+}
+
+impl AuditLog {
+    /// Open (or create) an audit log in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                account INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                event TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS numi_audit_log_account_time
+                ON numi_audit_log (account, timestamp);",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        // Logs created before correlation IDs lack the column
+        let has_correlation: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('numi_audit_log')
+                 WHERE name = 'correlation_id'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(db_error(DB_CONTEXT))?
+            > 0;
+        if !has_correlation {
+            conn.execute_batch(
+                "ALTER TABLE numi_audit_log ADD COLUMN correlation_id TEXT;
+                 CREATE INDEX IF NOT EXISTS numi_audit_log_correlation
+                    ON numi_audit_log (correlation_id);",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        }
+        // Logs created before recipients were indexed are indexed once
+        let has_recipients: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master
+                 WHERE type = 'table' AND name = 'numi_audit_recipients'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(db_error(DB_CONTEXT))?
+            > 0;
+        if !has_recipients {
+            conn.execute_batch(
+                "BEGIN;
+                 CREATE TABLE numi_audit_recipients (
+                    account INTEGER NOT NULL,
+                    recipient TEXT NOT NULL,
+                    first_paid INTEGER NOT NULL,
+                    PRIMARY KEY (account, recipient)
+                 );
+                 CREATE INDEX numi_audit_recipients_first_paid
+                    ON numi_audit_recipients (account, first_paid);
+                 INSERT INTO numi_audit_recipients (account, recipient, first_paid)
+                    SELECT account, recipient.value, MIN(timestamp)
+                    FROM numi_audit_log, json_each(event, '$.recipients') AS recipient
+                    WHERE kind = 'send_submitted'
+                    GROUP BY account, recipient.value;
+                 COMMIT;",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Open the audit log stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Append an event for `account` at the current time
+    pub fn record(&self, account: u32, event: AuditEvent) -> Result<AuditEntry> {
+        self.record_at(account, event, unix_now())
+    }
+
+    /// Append an event of the send identified by `correlation_id`
+    pub fn record_correlated(
+        &self,
+        account: u32,
+        event: AuditEvent,
+        correlation_id: Option<&CorrelationId>,
+    ) -> Result<AuditEntry> {
+        self.insert(account, event, unix_now(), correlation_id)
+    }
+
+    /// Append an event with an explicit timestamp (unix seconds)
+    pub fn record_at(&self, account: u32, event: AuditEvent, timestamp: u64) -> Result<AuditEntry> {
+        self.insert(account, event, timestamp, None)
+    }
+
+    fn insert(
+        &self,
+        account: u32,
+        event: AuditEvent,
+        timestamp: u64,
+        correlation_id: Option<&CorrelationId>,
+    ) -> Result<AuditEntry> {
+        let encoded = serde_json::to_string(&event)?;
+        let kind = event_kind(&event);
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(db_error(DB_CONTEXT))?;
+        tx.execute(
+            "INSERT INTO numi_audit_log (timestamp, account, kind, event, correlation_id)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                timestamp as i64,
+                account,
+                kind,
+                encoded,
+                correlation_id.map(CorrelationId::as_str)
+            ],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        let id = tx.last_insert_rowid();
+        if let AuditEvent::SendSubmitted { recipients, .. } = &event {
+            for recipient in recipients {
+                tx.execute(
+                    "INSERT INTO numi_audit_recipients (account, recipient, first_paid)
+                     VALUES (?1, ?2, ?3)
+                     ON CONFLICT(account, recipient) DO UPDATE SET
+                        first_paid = MIN(first_paid, excluded.first_paid)",
+                    params![account, recipient, timestamp as i64],
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+            }
+        }
+        tx.commit().map_err(db_error(DB_CONTEXT))?;
+
+        Ok(AuditEntry {
+            id,
+            timestamp,
+            account,
+            event,
+            correlation_id: correlation_id.cloned(),
+        })
+    }
+
+    /// Entries for `account` recorded at or after `since` (unix seconds), oldest first
+    pub fn entries_since(&self, account: u32, since: u64) -> Result<Vec<AuditEntry>> {
+        self.query(
+            "WHERE account = ?1 AND timestamp >= ?2",
+            params![account, since as i64],
+        )
+    }
+
+    /// Recipients `account` first sent to at or after `since` (unix seconds),
+    /// in the order they were first paid
+    pub fn new_recipients_since(&self, account: u32, since: u64) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT recipient FROM numi_audit_recipients
+                 WHERE account = ?1 AND first_paid >= ?2
+                 ORDER BY first_paid, recipient",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let recipients = stmt
+            .query_map(params![account, since as i64], |row| row.get(0))
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(recipients)
+    }
+
+    /// Entries of the send identified by `correlation_id`, oldest first
+    pub fn entries_for(&self, correlation_id: &CorrelationId) -> Result<Vec<AuditEntry>> {
+        self.query(
+            "WHERE correlation_id = ?1",
+            params![correlation_id.as_str()],
+        )
+    }
+
+    /// Correlation ID of the send with this zcashd operation ID or txid
+    pub fn correlation_for(&self, operation_id_or_txid: &str) -> Result<Option<CorrelationId>> {
+        self.conn
+            .query_row(
+                "SELECT correlation_id FROM numi_audit_log
+                 WHERE correlation_id IS NOT NULL
+                   AND ((kind = 'send_submitted'
+                         AND json_extract(event, '$.operation_id') = ?1)
+                        OR (kind = 'send_completed' AND json_extract(event, '$.txid') = ?1))
+                 ORDER BY id DESC LIMIT 1",
+                [operation_id_or_txid],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map(|id| id.map(CorrelationId::from))
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    fn query(&self, filter: &str, params: impl rusqlite::Params) -> Result<Vec<AuditEntry>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT id, timestamp, account, event, correlation_id FROM numi_audit_log
+                 {} ORDER BY id",
+                filter
+            ))
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+
+        rows.into_iter()
+            .map(|(id, timestamp, account, event, correlation_id)| {
+                Ok(AuditEntry {
+                    id,
+                    timestamp: timestamp as u64,
+                    account,
+                    event: serde_json::from_str(&event)?,
+                    correlation_id: correlation_id.map(CorrelationId::from),
+                })
+            })
+            .collect()
+    }
+
+    /// Total submitted send amount for `account` since `since` (unix seconds)
+    pub fn submitted_total_since(&self, account: u32, since: u64) -> Result<u64> {
+        Ok(self
+            .entries_since(account, since)?
+            .iter()
+            .filter_map(|entry| match entry.event {
+                AuditEvent::SendSubmitted { amount, .. } => Some(amount),
+                _ => None,
+            })
+            .sum())
+    }
+}
+
+/// Audit log entries of an account with its transactions, for a review
+///
+/// Exported as JSON in an [`ExportDocument`](crate::export_schema::ExportDocument).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditPackage {
+    /// ZIP-32 account index
+    pub account: u32,
+    /// Start of the period covered (unix seconds)
+    pub since: u64,
+    /// Audit entries recorded in the period, oldest first
+    pub entries: Vec<AuditEntry>,
+    /// Transactions mined in the period, and unmined ones
+    pub transactions: Vec<Transaction>,
+    /// Historical fiat values of the transactions that were priced
+    #[serde(default)]
+    pub fiat_values: Vec<FiatValue>,
+}
+
+impl AuditLog {
+    /// Package the audit entries of `account` since `since` with its
+    /// transactions in that period
+    ///
+    /// # Arguments
+    /// * `account` - ZIP-32 account index
+    /// * `since` - Start of the period (unix seconds)
+    /// * `transactions` - Transaction history of the account, e.g. from
+    ///   [`Wallet::get_transactions`]
+    /// * `fiat_values` - Fiat values by txid, e.g. from
+    ///   [`PriceHistory::values`](crate::price_history::PriceHistory::values)
+    pub fn package(
+        &self,
+        account: u32,
+        since: u64,
+        transactions: &[Transaction],
+        fiat_values: &HashMap<String, FiatValue>,
+    ) -> Result<AuditPackage> {
+        let transactions: Vec<Transaction> = transactions
+            .iter()
+            .filter(|tx| tx.timestamp.is_none_or(|time| time >= since))
+            .cloned()
+            .collect();
+        let fiat_values = transactions
+            .iter()
+            .filter_map(|tx| fiat_values.get(&tx.txid).cloned())
+            .collect();
+        Ok(AuditPackage {
+            account,
+            since,
+            entries: self.entries_since(account, since)?,
+            transactions,
+            fiat_values,
+        })
+    }
+}
+
+fn event_kind(event: &AuditEvent) -> &'static str {
+    match event {
+        AuditEvent::SendSubmitted { .. } => "send_submitted",
+        AuditEvent::SendFailed { .. } => "send_failed",
+        AuditEvent::PolicyViolation { .. } => "policy_violation",
+        AuditEvent::DuplicatePaymentWarning { .. } => "duplicate_payment_warning",
+        AuditEvent::SendCompleted { .. } => "send_completed",
+        AuditEvent::SendConfirmed { .. } => "send_confirmed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_record_and_query() {
+        let path = TempDb::new();
+        let log = AuditLog::open(&path).unwrap();
+
+        let submitted = |amount| AuditEvent::SendSubmitted {
+            from_address: "u1from".to_string(),
+            recipients: vec!["u1to".to_string()],
+            amount,
+            operation_id: None,
+            payments: Vec::new(),
+        };
+        log.record_at(0, submitted(100), 1_000).unwrap();
+        log.record_at(0, submitted(200), 2_000).unwrap();
+        log.record_at(1, submitted(400), 2_000).unwrap();
+
+        assert_eq!(log.entries_since(0, 0).unwrap().len(), 2);
+        assert_eq!(log.submitted_total_since(0, 1_500).unwrap(), 200);
+
+        // Entries survive reopening
+        let reopened = AuditLog::open(&path).unwrap();
+        assert_eq!(reopened.submitted_total_since(1, 0).unwrap(), 400);
+    }
+
+    #[test]
+    fn test_correlated_entries() {
+        let path = TempDb::new();
+        let log = AuditLog::open(&path).unwrap();
+        let send = CorrelationId::from("payout-7");
+
+        log.record_correlated(
+            0,
+            AuditEvent::SendSubmitted {
+                from_address: "u1from".to_string(),
+                recipients: vec!["u1to".to_string()],
+                amount: 100,
+                operation_id: Some("opid-7".to_string()),
+                payments: Vec::new(),
+            },
+            Some(&send),
+        )
+        .unwrap();
+        log.record_correlated(
+            0,
+            AuditEvent::SendCompleted {
+                operation_id: "opid-7".to_string(),
+                txid: "ab".repeat(32),
+            },
+            Some(&send),
+        )
+        .unwrap();
+        log.record_at(
+            0,
+            AuditEvent::SendConfirmed {
+                txid: "cd".repeat(32),
+                confirmations: 1,
+            },
+            5,
+        )
+        .unwrap();
+
+        let entries = log.entries_for(&send).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries
+            .iter()
+            .all(|e| e.correlation_id.as_ref() == Some(&send)));
+        assert_eq!(log.correlation_for("opid-7").unwrap(), Some(send.clone()));
+        assert_eq!(log.correlation_for(&"ab".repeat(32)).unwrap(), Some(send));
+        assert_eq!(log.correlation_for("opid-8").unwrap(), None);
+        assert_eq!(log.entries_since(0, 0).unwrap()[2].correlation_id, None);
+    }
+
+    #[test]
+    fn test_new_recipients() {
+        let path = TempDb::new();
+        let log = AuditLog::open(&path).unwrap();
+        let sent = |to: &[&str]| AuditEvent::SendSubmitted {
+            from_address: "u1from".to_string(),
+            recipients: to.iter().map(|r| r.to_string()).collect(),
+            amount: 1,
+            operation_id: None,
+            payments: Vec::new(),
+        };
+        log.record_at(0, sent(&["u1old"]), 100).unwrap();
+        log.record_at(0, sent(&["u1old", "u1new"]), 2_000).unwrap();
+        log.record_at(0, sent(&["u1newer"]), 1_500).unwrap();
+        log.record_at(1, sent(&["u1other"]), 2_000).unwrap();
+        assert_eq!(
+            log.new_recipients_since(0, 1_000).unwrap(),
+            vec!["u1newer".to_string(), "u1new".to_string()]
+        );
+
+        // Logs from before the index are indexed when opened
+        log.conn
+            .execute_batch("DROP TABLE numi_audit_recipients")
+            .unwrap();
+        let reopened = AuditLog::open(&path).unwrap();
+        assert_eq!(
+            reopened.new_recipients_since(0, 1_000).unwrap(),
+            vec!["u1newer".to_string(), "u1new".to_string()]
+        );
+        assert_eq!(reopened.new_recipients_since(1, 0).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,300 @@
+//! Automatic wallet backups
+//!
+//! An [`AutoBackup`] backs a wallet up to a directory after every N scanned
+//! blocks and/or every N sent transactions, and deletes old backups beyond a
+//! retention count. Attach it to a light client with
+//! [`LightClient::set_auto_backup`](crate::light_client::LightClient::set_auto_backup),
+//! which reports scanned batches and broadcast transactions to it.
+//!
+//! Two kinds of backup can be written, each keeping its own retention count:
+//! - a copy of the wallet database (`<name>-<millis>.db`), taken with
+//!   `VACUUM INTO` so it is consistent while the wallet is in use. It
+//!   restores scan progress, but holds viewing keys in the clear and no seed.
+//! - an encrypted backup in the format of [`crate::backup`]
+//!   (`<name>-<millis>.bak`), which needs the wallet to be unlocked.
+//!
+//! `<name>` is the file stem of the wallet database, so several wallets can
+//! share a backup directory.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::{db_error, unix_now_millis};
+use crate::wallet::Wallet;
+use secrecy::{ExposeSecret, SecretString};
+use std::path::{Path, PathBuf};
+
+/// Backups of each kind kept by default
+pub const DEFAULT_RETAIN: usize = 7;
+
+const DATABASE_EXTENSION: &str = "db";
+const ENCRYPTED_EXTENSION: &str = "bak";
+
+/// Files written by one backup
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupFiles {
+    /// Copy of the wallet database, if database copies are enabled
+    pub database: Option<PathBuf>,
+    /// Encrypted backup, if a passphrase is set
+    pub encrypted: Option<PathBuf>,
+}
+
+/// Backs a wallet up after a number of scanned blocks or sent transactions
+pub struct AutoBackup {
+    wallet: Wallet,
+    dir: PathBuf,
+    every_blocks: Option<u64>,
+    every_sends: Option<u64>,
+    database_copies: bool,
+    passphrase: Option<SecretString>,
+    retain: usize,
+    blocks_since: u64,
+    sends_since: u64,
+}
+
+const DB_CONTEXT: &str = "Automatic backup error";
+
+impl AutoBackup {
+    /// Back `wallet` up to `dir`, which is created if needed
+    ///
+    /// Writes database copies only, keeping [`DEFAULT_RETAIN`]; set a
+    /// trigger with [`every_blocks`](Self::every_blocks) or
+    /// [`every_sends`](Self::every_sends).
+    pub fn new(wallet: &Wallet, dir: impl Into<PathBuf>) -> Result<Self> {
+        if wallet.is_ephemeral() {
+            return Err(Error::Wallet(
+                "An ephemeral wallet cannot be backed up automatically".to_string(),
+            ));
+        }
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            wallet: wallet.clone(),
+            dir,
+            every_blocks: None,
+            every_sends: None,
+            database_copies: true,
+            passphrase: None,
+            retain: DEFAULT_RETAIN,
+            blocks_since: 0,
+            sends_since: 0,
+        })
+    }
+
+    /// Back up once at least `blocks` blocks were scanned since the last
+    /// backup
+    pub fn every_blocks(mut self, blocks: u64) -> Result<Self> {
+        if blocks == 0 {
+            return Err(Error::InvalidParameter(
+                "Backup interval must be at least one block".to_string(),
+            ));
+        }
+        self.every_blocks = Some(blocks);
+        Ok(self)
+    }
+
+    /// Back up after every `sends` transactions sent
+    pub fn every_sends(mut self, sends: u64) -> Result<Self> {
+        if sends == 0 {
+            return Err(Error::InvalidParameter(
+                "Backup interval must be at least one transaction".to_string(),
+            ));
+        }
+        self.every_sends = Some(sends);
+        Ok(self)
+    }
+
+    /// Also write an encrypted backup, sealed with `passphrase`
+    pub fn with_encrypted_backup(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(SecretString::new(passphrase.to_string()));
+        self
+    }
+
+    /// Do not write database copies, only encrypted backups
+    pub fn without_database_copy(mut self) -> Self {
+        self.database_copies = false;
+        self
+    }
+
+    /// Keep the newest `count` backups of each kind
+    pub fn with_retention(mut self, count: usize) -> Result<Self> {
+        if count == 0 {
+            return Err(Error::InvalidParameter(
+                "At least one backup must be retained".to_string(),
+            ));
+        }
+        self.retain = count;
+        Ok(self)
+    }
+
+    /// Count scanned blocks and back up if the block interval is reached
+    ///
+    /// # Returns
+    /// The files written, if a backup was taken
+    pub fn blocks_scanned(&mut self, blocks: u64) -> Result<Option<BackupFiles>> {
+        let Some(every) = self.every_blocks else {
+            return Ok(None);
+        };
+        self.blocks_since = self.blocks_since.saturating_add(blocks);
+        if self.blocks_since < every {
+            return Ok(None);
+        }
+        self.backup_now().map(Some)
+    }
+
+    /// Count a sent transaction and back up if the send interval is reached
+    ///
+    /// # Returns
+    /// The files written, if a backup was taken
+    pub fn transaction_sent(&mut self) -> Result<Option<BackupFiles>> {
+        let Some(every) = self.every_sends else {
+            return Ok(None);
+        };
+        self.sends_since += 1;
+        if self.sends_since < every {
+            return Ok(None);
+        }
+        self.backup_now().map(Some)
+    }
+
+    /// Back up now, reset both intervals and delete backups beyond the
+    /// retention count
+    pub fn backup_now(&mut self) -> Result<BackupFiles> {
+        // Names must be unique for retention to count backups correctly
+        let mut millis = unix_now_millis();
+        let name = loop {
+            let name = format!("{}-{:013}", self.stem(), millis);
+            let taken = [DATABASE_EXTENSION, ENCRYPTED_EXTENSION]
+                .iter()
+                .any(|extension| self.dir.join(format!("{}.{}", name, extension)).exists());
+            if !taken {
+                break name;
+            }
+            millis += 1;
+        };
+        let mut files = BackupFiles::default();
+
+        if self.database_copies {
+            let path = self.dir.join(format!("{}.{}", name, DATABASE_EXTENSION));
+            copy_database(self.wallet.db_path(), &path)?;
+            files.database = Some(path);
+        }
+        if let Some(passphrase) = &self.passphrase {
+            let path = self.dir.join(format!("{}.{}", name, ENCRYPTED_EXTENSION));
+            self.wallet
+                .export_backup(&path, passphrase.expose_secret())?;
+            files.encrypted = Some(path);
+        }
+        self.blocks_since = 0;
+        self.sends_since = 0;
+
+        for extension in [DATABASE_EXTENSION, ENCRYPTED_EXTENSION] {
+            for old in self.backups(extension)?.into_iter().skip(self.retain) {
+                std::fs::remove_file(old)?;
+            }
+        }
+        Ok(files)
+    }
+
+    /// Database copies of this wallet in the backup directory, newest first
+    pub fn database_copies(&self) -> Result<Vec<PathBuf>> {
+        self.backups(DATABASE_EXTENSION)
+    }
+
+    /// Encrypted backups of this wallet in the backup directory, newest first
+    pub fn encrypted_backups(&self) -> Result<Vec<PathBuf>> {
+        self.backups(ENCRYPTED_EXTENSION)
+    }
+
+    /// File stem of the wallet database, which backup names start with
+    fn stem(&self) -> &str {
+        self.wallet
+            .db_path()
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("wallet")
+    }
+
+    fn backups(&self, extension: &str) -> Result<Vec<PathBuf>> {
+        let stem = self.stem();
+        let mut backups = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let matches = path.extension().and_then(|e| e.to_str()) == Some(extension)
+                && path
+                    .file_stem()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_prefix(stem))
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    .is_some_and(|millis| {
+                        !millis.is_empty() && millis.bytes().all(|b| b.is_ascii_digit())
+                    });
+            if matches {
+                backups.push(path);
+            }
+        }
+        // Zero-padded timestamps sort chronologically
+        backups.sort_by(|a, b| b.cmp(a));
+        Ok(backups)
+    }
+}
+
+/// Write a consistent copy of the database at `src` to the new file `dest`
+fn copy_database(src: &Path, dest: &Path) -> Result<()> {
+    if dest.exists() {
+        return Err(Error::InvalidParameter(format!(
+            "Backup file {} already exists",
+            dest.display()
+        )));
+    }
+    let dest_str = dest
+        .to_str()
+        .ok_or_else(|| Error::InvalidParameter("Backup path is not valid UTF-8".to_string()))?;
+    open_connection(src)
+        .and_then(|conn| conn.execute("VACUUM INTO ?1", [dest_str]))
+        .map_err(db_error(DB_CONTEXT))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intervals_and_retention() {
+        let dir = std::env::temp_dir().join(format!("test_auto_backup_{}", rand::random::<u64>()));
+        let wallet =
+            Wallet::with_path_and_seed(dir.join("treasury.db"), Some(vec![7u8; 32])).unwrap();
+        wallet.get_balance().unwrap();
+        let mut backup = AutoBackup::new(&wallet, dir.join("backups"))
+            .unwrap()
+            .every_blocks(100)
+            .unwrap()
+            .every_sends(2)
+            .unwrap()
+            .with_retention(2)
+            .unwrap()
+            .with_encrypted_backup("passphrase");
+
+        assert!(backup.blocks_scanned(60).unwrap().is_none());
+        let files = backup.blocks_scanned(60).unwrap().unwrap();
+        assert!(files.database.unwrap().exists());
+        assert!(files.encrypted.unwrap().exists());
+        assert!(backup.transaction_sent().unwrap().is_none());
+        assert!(backup.transaction_sent().unwrap().is_some());
+        backup.backup_now().unwrap();
+
+        assert_eq!(backup.database_copies().unwrap().len(), 2);
+        let encrypted = backup.encrypted_backups().unwrap();
+        assert_eq!(encrypted.len(), 2);
+        let restored =
+            Wallet::import_backup(&encrypted[0], "passphrase", dir.join("restored.db")).unwrap();
+        assert_eq!(
+            restored.get_unified_address().unwrap(),
+            wallet.get_unified_address().unwrap()
+        );
+        assert!(AutoBackup::new(&wallet, &dir)
+            .unwrap()
+            .every_blocks(0)
+            .is_err());
+    }
+}
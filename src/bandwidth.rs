@@ -0,0 +1,287 @@
+//! Bandwidth accounting
+//!
+//! [`BandwidthMeter`] counts the bytes a light client exchanges with
+//! lightwalletd (compact blocks and tree states downloaded, transactions
+//! uploaded) and supports a soft cap. When the cap is exceeded,
+//! [`LightClient::sync`](crate::light_client::LightClient::sync) stops after
+//! the current batch and publishes [`WalletEvent::BandwidthCapReached`]
+//! (see [`crate::events`]); the host can raise the cap or [`reset`] the meter
+//! (e.g. once the device is on Wi-Fi) and call `sync` again to resume.
+//!
+//! Two sizes are tracked. Payload bytes are protobuf message sizes before
+//! transport compression. Wire bytes are what actually crossed the socket
+//! (compressed messages plus HTTP/2 and TLS framing); they are only counted
+//! when the light client's connection is metered (plain-HTTP or pinned
+//! endpoints). Comparing the two shows the benefit of lightwalletd response
+//! compression, see [`BandwidthUsage::compression_ratio`]. When wire bytes are
+//! available the soft cap applies to them, since that is what a data plan is
+//! charged for.
+//!
+//! [`WalletEvent::BandwidthCapReached`]: crate::events::WalletEvent::BandwidthCapReached
+//! [`reset`]: BandwidthMeter::reset
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Value of the cap counter meaning "no cap"
+const NO_CAP: u64 = u64::MAX;
+
+/// Bytes transferred since the meter was created or reset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthUsage {
+    /// Payload bytes received
+    pub downloaded: u64,
+    /// Payload bytes sent
+    pub uploaded: u64,
+    /// Socket bytes received, zero if the connection is not metered
+    #[serde(default)]
+    pub wire_downloaded: u64,
+    /// Socket bytes sent, zero if the connection is not metered
+    #[serde(default)]
+    pub wire_uploaded: u64,
+}
+
+impl BandwidthUsage {
+    /// Payload bytes transferred in both directions
+    pub fn total(&self) -> u64 {
+        self.downloaded.saturating_add(self.uploaded)
+    }
+
+    /// Socket bytes transferred in both directions
+    pub fn wire_total(&self) -> u64 {
+        self.wire_downloaded.saturating_add(self.wire_uploaded)
+    }
+
+    /// Bytes counted against the soft cap: wire bytes if measured, otherwise
+    /// payload bytes
+    pub fn billed(&self) -> u64 {
+        match self.wire_total() {
+            0 => self.total(),
+            wire => wire,
+        }
+    }
+
+    /// Wire bytes received per payload byte
+    ///
+    /// Values well below 1.0 mean lightwalletd is compressing responses;
+    /// values slightly above 1.0 mean it is not (framing overhead only).
+    /// `None` until both sizes have been measured.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.downloaded > 0 && self.wire_downloaded > 0)
+            .then(|| self.wire_downloaded as f64 / self.downloaded as f64)
+    }
+}
+
+#[derive(Debug)]
+struct Counters {
+    downloaded: AtomicU64,
+    uploaded: AtomicU64,
+    wire_downloaded: AtomicU64,
+    wire_uploaded: AtomicU64,
+    soft_cap: AtomicU64,
+}
+
+/// Shared byte counter with an optional soft cap
+///
+/// Cloning the meter is cheap; all clones update the same counters, so one
+/// meter can be shared by several clients and a block cache.
+#[derive(Debug, Clone)]
+pub struct BandwidthMeter {
+    counters: Arc<Counters>,
+}
+
+impl BandwidthMeter {
+    /// Create a meter without a cap
+    pub fn new() -> Self {
+        Self {
+            counters: Arc::new(Counters {
+                downloaded: AtomicU64::new(0),
+                uploaded: AtomicU64::new(0),
+                wire_downloaded: AtomicU64::new(0),
+                wire_uploaded: AtomicU64::new(0),
+                soft_cap: AtomicU64::new(NO_CAP),
+            }),
+        }
+    }
+
+    /// Create a meter that pauses sync after `bytes` have been transferred
+    pub fn with_soft_cap(bytes: u64) -> Self {
+        let meter = Self::new();
+        meter.set_soft_cap(Some(bytes));
+        meter
+    }
+
+    /// Set or clear the soft cap (billed bytes in both directions)
+    pub fn set_soft_cap(&self, bytes: Option<u64>) {
+        self.counters
+            .soft_cap
+            .store(bytes.unwrap_or(NO_CAP), Ordering::Relaxed);
+    }
+
+    /// The current soft cap
+    pub fn soft_cap(&self) -> Option<u64> {
+        Some(self.counters.soft_cap.load(Ordering::Relaxed)).filter(|cap| *cap != NO_CAP)
+    }
+
+    /// Record downloaded payload bytes
+    pub fn record_download(&self, bytes: u64) {
+        self.counters.downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record uploaded payload bytes
+    pub fn record_upload(&self, bytes: u64) {
+        self.counters.uploaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record bytes read from the socket
+    pub fn record_wire_download(&self, bytes: u64) {
+        self.counters
+            .wire_downloaded
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record bytes written to the socket
+    pub fn record_wire_upload(&self, bytes: u64) {
+        self.counters
+            .wire_uploaded
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Bytes transferred so far
+    pub fn usage(&self) -> BandwidthUsage {
+        BandwidthUsage {
+            downloaded: self.counters.downloaded.load(Ordering::Relaxed),
+            uploaded: self.counters.uploaded.load(Ordering::Relaxed),
+            wire_downloaded: self.counters.wire_downloaded.load(Ordering::Relaxed),
+            wire_uploaded: self.counters.wire_uploaded.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether usage has exceeded the soft cap
+    pub fn is_over_cap(&self) -> bool {
+        self.soft_cap()
+            .is_some_and(|cap| self.usage().billed() > cap)
+    }
+
+    /// Reset the counters to zero, keeping the cap
+    pub fn reset(&self) {
+        self.counters.downloaded.store(0, Ordering::Relaxed);
+        self.counters.uploaded.store(0, Ordering::Relaxed);
+        self.counters.wire_downloaded.store(0, Ordering::Relaxed);
+        self.counters.wire_uploaded.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for BandwidthMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Socket wrapper that counts bytes read and written as wire bytes
+pub(crate) struct MeteredIo<T> {
+    inner: T,
+    meter: Option<BandwidthMeter>,
+}
+
+impl<T> MeteredIo<T> {
+    pub(crate) fn new(inner: T, meter: Option<BandwidthMeter>) -> Self {
+        Self { inner, meter }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for MeteredIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(meter)) = (&poll, &self.meter) {
+            meter.record_wire_download((buf.filled().len() - before) as u64);
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for MeteredIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(written)), Some(meter)) = (&poll, &self.meter) {
+            meter.record_wire_upload(*written as u64);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_soft_cap() {
+        let meter = BandwidthMeter::with_soft_cap(1_000);
+        let shared = meter.clone();
+        shared.record_download(900);
+        assert!(!meter.is_over_cap());
+        shared.record_upload(200);
+        assert!(meter.is_over_cap());
+        assert_eq!(
+            meter.usage(),
+            BandwidthUsage {
+                downloaded: 900,
+                uploaded: 200,
+                ..Default::default()
+            }
+        );
+
+        meter.set_soft_cap(Some(2_000));
+        assert!(!meter.is_over_cap());
+        meter.set_soft_cap(None);
+        meter.reset();
+        assert_eq!(meter.usage().total(), 0);
+        assert_eq!(meter.soft_cap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_metered_io_counts_wire_bytes() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let meter = BandwidthMeter::with_soft_cap(500);
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut io = MeteredIo::new(client, Some(meter.clone()));
+
+        io.write_all(&[0u8; 40]).await.unwrap();
+        let mut received = [0u8; 40];
+        server.read_exact(&mut received).await.unwrap();
+        server.write_all(&[1u8; 30]).await.unwrap();
+        let mut buf = [0u8; 30];
+        io.read_exact(&mut buf).await.unwrap();
+
+        // 1000 payload bytes arrived as 30 compressed wire bytes
+        meter.record_download(1_000);
+        let usage = meter.usage();
+        assert_eq!((usage.wire_uploaded, usage.wire_downloaded), (40, 30));
+        assert_eq!(usage.compression_ratio(), Some(0.03));
+        assert_eq!(usage.billed(), 70);
+        assert!(!meter.is_over_cap());
+    }
+}
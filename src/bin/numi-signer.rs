@@ -0,0 +1,170 @@
+//! Numi signer - reference signer process for dual-control deployments
+//!
+//! Holds the wallet seed, sealed in its own database, and signs the PCZTs an
+//! online service holding only the viewing key puts in a signing queue (see
+//! `zcash_numi_sdk::dual_control`). Run it on a hardened host that shares
+//! nothing with the service but the queue directories.
+//!
+//! The passphrase sealing the seed is read from `NUMI_SIGNER_PASSPHRASE`,
+//! never from the command line.
+
+use clap::{Parser, Subcommand};
+use secrecy::zeroize::Zeroizing;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::time::Duration;
+use zcash_numi_sdk::airgap::sign_request;
+use zcash_numi_sdk::dual_control::{SigningQueue, ViewingKeyExport};
+use zcash_numi_sdk::types::Network;
+use zcash_numi_sdk::wallet::Wallet;
+use zcash_numi_sdk::{Error, Result};
+
+/// Environment variable holding the passphrase that seals the seed
+const PASSPHRASE_VAR: &str = "NUMI_SIGNER_PASSPHRASE";
+
+#[derive(Parser)]
+#[command(name = "numi-signer")]
+#[command(about = "Signer process of a dual-control Zcash Numi deployment", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+
+    /// Network to use (mainnet, testnet, regtest)
+    #[arg(short, long, default_value = "mainnet")]
+    network: String,
+
+    /// Signer database path, holding the sealed seed
+    #[arg(short, long)]
+    wallet_path: PathBuf,
+
+    /// ZIP-32 account to sign for
+    #[arg(short, long, default_value = "0")]
+    account: u32,
+
+    /// Enable verbose logging
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Seal the seed of a mnemonic phrase, read from stdin, in the signer database
+    Init,
+    /// Print the account's viewing key as JSON, for the online service
+    ExportViewingKey,
+    /// Sign the PCZTs queued by the online service
+    Sign {
+        /// Directory of unsigned PCZTs, written by the service
+        #[arg(long)]
+        requests: PathBuf,
+        /// Directory of signed PCZTs, read by the service
+        #[arg(long)]
+        responses: PathBuf,
+        /// Seconds between checks of the queue
+        #[arg(long, default_value = "5")]
+        interval: u64,
+        /// Sign the queued requests once and exit
+        #[arg(long)]
+        once: bool,
+    },
+}
+
+fn parse_network(network_str: &str) -> Network {
+    match network_str.to_lowercase().as_str() {
+        "testnet" => Network::Testnet,
+        "regtest" => Network::Regtest,
+        _ => Network::Mainnet,
+    }
+}
+
+fn passphrase() -> Result<String> {
+    std::env::var(PASSPHRASE_VAR).map_err(|_| {
+        Error::InvalidParameter(format!(
+            "Set {} to the passphrase sealing the seed",
+            PASSPHRASE_VAR
+        ))
+    })
+}
+
+fn open_wallet(cli: &Cli) -> Result<Wallet> {
+    let mut wallet = Wallet::open_with_stored_seed(cli.wallet_path.clone(), &passphrase()?)?;
+    wallet.set_network(parse_network(&cli.network))?;
+    wallet.use_account(cli.account)?;
+    Ok(wallet)
+}
+
+/// Sign every pending request, rejecting those that cannot be signed
+fn sign_pending(wallet: &Wallet, queue: &SigningQueue) -> Result<()> {
+    for id in queue.pending()? {
+        let signed = queue
+            .read_request(&id)
+            .and_then(|request| sign_request(wallet, &request));
+        match signed {
+            Ok(signed) => {
+                queue.respond(&id, &signed)?;
+                tracing::info!("Signed request {}", id);
+            }
+            Err(e) => {
+                tracing::warn!("Rejected request {}: {}", id, e);
+                queue.reject(&id, &e.to_string())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(if cli.verbose { "debug" } else { "info" })
+        .init();
+
+    match &cli.command {
+        Commands::Init => {
+            let passphrase = passphrase()?;
+            eprintln!("Enter the mnemonic phrase:");
+            let mut phrase = Zeroizing::new(String::new());
+            std::io::stdin().lock().read_line(&mut phrase)?;
+            let mut wallet =
+                Wallet::with_path_and_mnemonic(cli.wallet_path.clone(), phrase.trim(), "")?;
+            wallet.set_network(parse_network(&cli.network))?;
+            wallet.store_seed(&passphrase)?;
+            println!(
+                "Seed {} sealed in {}",
+                wallet.seed_fingerprint()?,
+                cli.wallet_path.display()
+            );
+        }
+        Commands::ExportViewingKey => {
+            let wallet = open_wallet(&cli)?;
+            let export = ViewingKeyExport::from_wallet(&wallet, cli.account)?;
+            let json = serde_json::to_string_pretty(&export)
+                .map_err(|e| Error::Wallet(format!("Failed to encode viewing key: {}", e)))?;
+            println!("{}", json);
+        }
+        Commands::Sign {
+            requests,
+            responses,
+            interval,
+            once,
+        } => {
+            let wallet = open_wallet(&cli)?;
+            let queue = SigningQueue::new(requests, responses)?;
+            tracing::info!(
+                "Signing for account {} of seed {}",
+                cli.account,
+                wallet.seed_fingerprint()?
+            );
+            loop {
+                sign_pending(&wallet, &queue)?;
+                if *once {
+                    break;
+                }
+                std::thread::sleep(Duration::from_secs(*interval));
+            }
+        }
+    }
+
+    Ok(())
+}
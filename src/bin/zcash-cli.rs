@@ -6,9 +6,12 @@
 
 use clap::{Parser, Subcommand};
 use zcash_numi_sdk::client::RpcClient;
+use zcash_numi_sdk::doctor::{doctor, CheckStatus, DoctorOptions};
 use zcash_numi_sdk::light_client::{default_endpoints, LightClient};
+use zcash_numi_sdk::transaction::decode::decode_hex;
 use zcash_numi_sdk::transaction::TransactionBuilder;
 use zcash_numi_sdk::types::{Network, utils};
+use zcash_numi_sdk::wallet::async_wallet::AsyncWallet;
 use zcash_numi_sdk::wallet::Wallet;
 use zcash_numi_sdk::Result;
 
@@ -122,6 +125,30 @@ enum Commands {
         #[arg(short, long)]
         count: bool,
     },
+    /// Decode a raw transaction without a node
+    Decode {
+        /// Transaction bytes, hex encoded
+        raw_tx: String,
+    },
+    /// Check the wallet, endpoints and environment for problems
+    Doctor {
+        /// Lightwalletd endpoint URL (default: the network's default
+        /// endpoint, unless --rpc-url is given)
+        #[arg(short, long)]
+        endpoint: Option<String>,
+        /// RPC endpoint URL of a zcashd node to check
+        #[arg(long)]
+        rpc_url: Option<String>,
+        /// RPC username
+        #[arg(long)]
+        rpc_user: Option<String>,
+        /// RPC password
+        #[arg(long)]
+        rpc_password: Option<String>,
+        /// Print the report as JSON, e.g. for a support ticket
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -170,11 +197,11 @@ fn load_wallet(cli: &Cli) -> Result<Wallet> {
     let wallet = if let Some(ref path) = cli.wallet_path {
         let db_path = std::path::PathBuf::from(path);
         let mut wallet = Wallet::with_path(db_path)?;
-        wallet.set_network(network);
+        wallet.set_network(network)?;
         wallet
     } else {
         let mut wallet = Wallet::new()?;
-        wallet.set_network(network);
+        wallet.set_network(network)?;
         wallet
     };
     
@@ -346,12 +373,12 @@ async fn main() -> Result<()> {
                 }
             } else {
                 // Local wallet balance
-                let wallet = load_wallet(&cli)?;
-                match wallet.get_balance() {
+                let wallet = AsyncWallet::new(load_wallet(&cli)?);
+                match wallet.get_balance().await {
                     Ok(balance) => {
                         println!("Wallet Balance");
                         println!("==============");
-                        println!("Network: {:?}", wallet.network());
+                        println!("Network: {:?}", wallet.wallet().network());
                         println!("Transparent: {}", utils::format_zec(balance.transparent as f64 / 100_000_000.0));
                         println!("Sapling: {}", utils::format_zec(balance.sapling as f64 / 100_000_000.0));
                         println!("Orchard: {}", utils::format_zec(balance.orchard as f64 / 100_000_000.0));
@@ -576,6 +603,66 @@ async fn main() -> Result<()> {
                 }
             }
         }
+        Commands::Decode { raw_tx } => {
+            match decode_hex(raw_tx, parse_network(&cli.network)) {
+                Ok(decoded) => println!("{}", serde_json::to_string_pretty(&decoded)?),
+                Err(e) => {
+                    eprintln!("Error decoding transaction: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Doctor {
+            endpoint,
+            rpc_url,
+            rpc_user,
+            rpc_password,
+            json,
+        } => {
+            let wallet = load_wallet(&cli)?;
+            let mut options = DoctorOptions::new();
+            if let Some(url) = rpc_url {
+                let rpc_client = if let (Some(user), Some(pass)) = (rpc_user, rpc_password) {
+                    RpcClient::with_auth(url.clone(), user.clone(), pass.clone())
+                } else {
+                    RpcClient::new(url.clone())
+                };
+                options = options.with_rpc(rpc_client);
+            }
+            let lightwalletd = match endpoint {
+                Some(endpoint) => Some(endpoint.clone()),
+                None if rpc_url.is_none() => default_endpoints(wallet.network()).first().cloned(),
+                None => None,
+            };
+            if let Some(endpoint) = lightwalletd {
+                options = options.with_lightwalletd(endpoint);
+            }
+
+            let report = doctor(&wallet, &options).await;
+            if *json {
+                println!("{}", report.to_json()?);
+            } else {
+                println!("Self-check ({:?}, SDK {})", report.network, report.sdk_version);
+                println!("=================================");
+                for result in &report.checks {
+                    let mark = match result.status {
+                        CheckStatus::Pass => "✓",
+                        CheckStatus::Warn => "⚠",
+                        CheckStatus::Fail => "✗",
+                        CheckStatus::Skipped => "-",
+                    };
+                    match &result.endpoint {
+                        Some(endpoint) => {
+                            println!("{} {} ({}): {}", mark, result.check, endpoint, result.detail)
+                        }
+                        None => println!("{} {}: {}", mark, result.check, result.detail),
+                    }
+                }
+            }
+            if !report.is_healthy() {
+                std::process::exit(1);
+            }
+        }
     }
 
     Ok(())
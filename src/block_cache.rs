@@ -0,0 +1,199 @@
+//! Shared compact block cache
+//!
+//! Compact blocks downloaded once can be scanned by any number of wallets.
+//! A [`BlockCache`] is a cheaply cloneable handle; every clone sees the same
+//! blocks. Wallets register with the height they will scan next, and each
+//! cached block is referenced by the registered wallets that have not yet
+//! scanned past it. Blocks with no remaining references are pruned as
+//! wallets report progress.
+
+use crate::bandwidth::BandwidthMeter;
+use crate::error::Result;
+use crate::light_client::{fetch_compact_blocks, LightwalletdChannel};
+use prost::Message;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use zcash_client_backend::proto::compact_formats::CompactBlock;
+
+#[derive(Default)]
+struct CacheInner {
+    blocks: BTreeMap<u64, CompactBlock>,
+    /// Wallet ID -> next height the wallet will scan
+    wallets: HashMap<String, u64>,
+    /// Meter charged for blocks the cache downloads
+    meter: Option<BandwidthMeter>,
+}
+
+impl CacheInner {
+    /// Number of registered wallets that still need the block at `height`
+    fn ref_count(&self, height: u64) -> usize {
+        self.wallets
+            .values()
+            .filter(|next| **next <= height)
+            .count()
+    }
+
+    /// Drop blocks that no registered wallet still needs
+    fn prune(&mut self) {
+        match self.wallets.values().min().copied() {
+            Some(min_next) => self.blocks = self.blocks.split_off(&min_next),
+            None => self.blocks.clear(),
+        }
+    }
+}
+
+/// Compact block cache shared between wallets
+#[derive(Clone, Default)]
+pub struct BlockCache {
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+impl BlockCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheInner> {
+        // A panic while holding the lock cannot leave the maps inconsistent
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Count bytes of blocks downloaded by the cache on `meter`
+    pub fn set_bandwidth_meter(&self, meter: BandwidthMeter) {
+        self.lock().meter = Some(meter);
+    }
+
+    /// Register a wallet that will scan from `next_height`
+    ///
+    /// Re-registering an existing wallet updates its height.
+    pub fn register_wallet(&self, wallet_id: impl Into<String>, next_height: u64) {
+        self.lock().wallets.insert(wallet_id.into(), next_height);
+    }
+
+    /// Unregister a wallet, releasing its references
+    pub fn unregister_wallet(&self, wallet_id: &str) {
+        let mut inner = self.lock();
+        inner.wallets.remove(wallet_id);
+        inner.prune();
+    }
+
+    /// Record that a wallet has scanned every block up to and including `height`
+    ///
+    /// Blocks no longer referenced by any registered wallet are pruned.
+    pub fn mark_scanned(&self, wallet_id: &str, height: u64) {
+        let mut inner = self.lock();
+        if let Some(next) = inner.wallets.get_mut(wallet_id) {
+            *next = (*next).max(height + 1);
+        }
+        inner.prune();
+    }
+
+    /// Add downloaded blocks to the cache
+    ///
+    /// Blocks already scanned by every registered wallet are not retained.
+    pub fn insert(&self, blocks: impl IntoIterator<Item = CompactBlock>) {
+        let mut inner = self.lock();
+        for block in blocks {
+            let height = block.height;
+            if inner.ref_count(height) > 0 {
+                inner.blocks.insert(height, block);
+            }
+        }
+    }
+
+    /// Cached blocks in the inclusive range, stopping at the first gap
+    pub fn get_range(&self, start_height: u64, end_height: u64) -> Vec<CompactBlock> {
+        let inner = self.lock();
+        let mut blocks = Vec::new();
+        for height in start_height..=end_height {
+            match inner.blocks.get(&height) {
+                Some(block) => blocks.push(block.clone()),
+                None => break,
+            }
+        }
+        blocks
+    }
+
+    /// Number of registered wallets that still need the block at `height`
+    pub fn ref_count(&self, height: u64) -> usize {
+        self.lock().ref_count(height)
+    }
+
+    /// Number of cached blocks
+    pub fn len(&self) -> usize {
+        self.lock().blocks.len()
+    }
+
+    /// Whether the cache holds no blocks
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get blocks for the inclusive range, downloading only those not cached
+    ///
+    /// # Arguments
+    /// * `channel` - lightwalletd channel used for missing blocks
+    /// * `start_height` - First height (inclusive)
+    /// * `end_height` - Last height (inclusive)
+    pub async fn get_or_fetch(
+        &self,
+        channel: LightwalletdChannel,
+        start_height: u64,
+        end_height: u64,
+    ) -> Result<Vec<CompactBlock>> {
+        let mut blocks = self.get_range(start_height, end_height);
+        let next = start_height + blocks.len() as u64;
+        if next <= end_height {
+            let fetched = fetch_compact_blocks(channel, next, end_height).await?;
+            if let Some(meter) = &self.lock().meter {
+                meter.record_download(fetched.iter().map(|b| b.encoded_len() as u64).sum());
+            }
+            self.insert(fetched.iter().cloned());
+            blocks.extend(fetched);
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u64) -> CompactBlock {
+        CompactBlock {
+            height,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pruned_once_all_wallets_pass() {
+        let cache = BlockCache::new();
+        cache.register_wallet("a", 10);
+        cache.register_wallet("b", 10);
+        cache.insert((10..20).map(block));
+        assert_eq!(cache.len(), 10);
+        assert_eq!(cache.ref_count(12), 2);
+
+        cache.mark_scanned("a", 19);
+        assert_eq!(cache.ref_count(12), 1);
+        assert_eq!(cache.len(), 10);
+
+        cache.mark_scanned("b", 14);
+        assert_eq!(cache.len(), 5);
+        assert_eq!(cache.get_range(15, 30).len(), 5);
+
+        cache.unregister_wallet("b");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_unreferenced_blocks_not_retained() {
+        let cache = BlockCache::new();
+        cache.register_wallet("a", 50);
+        cache.insert((40..55).map(block));
+        assert_eq!(cache.len(), 5);
+        assert_eq!(cache.get_range(40, 60).len(), 0);
+    }
+}
@@ -0,0 +1,188 @@
+//! Block time and confirmation ETA estimation
+//!
+//! Zcash targets one block every 75 seconds, but actual intervals vary. A
+//! [`BlockTimeEstimator`] learns the recent average from block header
+//! timestamps and uses it to convert between heights and approximate unix
+//! times, e.g. for invoice expiry heights or a "confirmed in about 4
+//! minutes" countdown. Samples come from
+//! [`LightClient::block_time_estimator`](crate::light_client::LightClient::block_time_estimator)
+//! or any source of `(height, time)` pairs.
+//!
+//! Estimates are approximate: header times are set by miners and may be off
+//! by minutes, and block intervals are exponentially distributed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Target spacing between blocks since the Blossom upgrade (seconds)
+pub const TARGET_BLOCK_TIME_SECS: u64 = 75;
+
+/// Default number of recent blocks to sample
+pub const DEFAULT_SAMPLE_BLOCKS: u64 = 24;
+
+/// Maximum number of samples kept; older ones are dropped
+const MAX_SAMPLES: usize = 1_000;
+
+/// Estimated time until a transaction reaches its confirmation target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationEta {
+    /// Blocks still to be mined before the target is reached
+    pub remaining_blocks: u64,
+    /// Estimated seconds from now (0 if overdue or already confirmed)
+    pub seconds: u64,
+    /// Estimated unix time the target is reached
+    pub estimated_at: u64,
+}
+
+/// Estimates block times from recent header timestamps
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockTimeEstimator {
+    /// Height -> header time (unix seconds)
+    samples: BTreeMap<u64, u64>,
+}
+
+impl BlockTimeEstimator {
+    /// Create an estimator without samples, using the target block time
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an estimator from `(height, time)` header samples
+    pub fn from_headers(headers: impl IntoIterator<Item = (u64, u64)>) -> Self {
+        let mut estimator = Self::new();
+        for (height, time) in headers {
+            estimator.add_block(height, time);
+        }
+        estimator
+    }
+
+    /// Record a block header timestamp
+    pub fn add_block(&mut self, height: u64, time: u64) {
+        self.samples.insert(height, time);
+        while self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_first();
+        }
+    }
+
+    /// Latest sampled block as `(height, time)`
+    pub fn tip(&self) -> Option<(u64, u64)> {
+        self.samples
+            .last_key_value()
+            .map(|(height, time)| (*height, *time))
+    }
+
+    /// Average seconds per block over the samples
+    ///
+    /// Falls back to [`TARGET_BLOCK_TIME_SECS`] with fewer than two samples
+    /// or when header times do not increase across the window.
+    pub fn average_block_time(&self) -> f64 {
+        match (
+            self.samples.first_key_value(),
+            self.samples.last_key_value(),
+        ) {
+            (Some((first_height, first_time)), Some((last_height, last_time)))
+                if last_height > first_height && last_time > first_time =>
+            {
+                (last_time - first_time) as f64 / (last_height - first_height) as f64
+            }
+            _ => TARGET_BLOCK_TIME_SECS as f64,
+        }
+    }
+
+    /// Approximate unix time of a block
+    ///
+    /// Sampled heights return their header time; other heights are
+    /// extrapolated from the nearest sample at or below them (or the first
+    /// sample for earlier heights). Returns `None` without samples.
+    pub fn timestamp_at(&self, height: u64) -> Option<u64> {
+        let average = self.average_block_time();
+        if let Some((base_height, base_time)) = self.samples.range(..=height).next_back() {
+            let offset = (height - base_height) as f64 * average;
+            return Some(base_time.saturating_add(offset.round() as u64));
+        }
+        let (base_height, base_time) = self.samples.first_key_value()?;
+        let offset = (base_height - height) as f64 * average;
+        Some(base_time.saturating_sub(offset.round() as u64))
+    }
+
+    /// Approximate height of the chain at a unix time
+    ///
+    /// Returns `None` without samples.
+    pub fn height_at(&self, time: u64) -> Option<u64> {
+        let (tip_height, tip_time) = self.tip()?;
+        let average = self.average_block_time();
+        if time >= tip_time {
+            let blocks = ((time - tip_time) as f64 / average).floor() as u64;
+            Some(tip_height.saturating_add(blocks))
+        } else {
+            let blocks = ((tip_time - time) as f64 / average).ceil() as u64;
+            Some(tip_height.saturating_sub(blocks))
+        }
+    }
+
+    /// Estimate when a transaction reaches a number of confirmations
+    ///
+    /// # Arguments
+    /// * `mined_height` - Height the transaction was mined at, or `None` if
+    ///   it is still in the mempool (assumed to be mined in the next block)
+    /// * `confirmations` - Confirmation target; the mining block counts as one
+    /// * `now` - Current unix time
+    pub fn confirmation_eta(
+        &self,
+        mined_height: Option<u64>,
+        confirmations: u32,
+        now: u64,
+    ) -> ConfirmationEta {
+        let confirmations = u64::from(confirmations.max(1));
+        let (tip_height, tip_time) = self.tip().unwrap_or((0, now));
+        let remaining_blocks = match mined_height {
+            Some(height) => (height + confirmations - 1).saturating_sub(tip_height),
+            None => confirmations,
+        };
+        let estimated_at = tip_time
+            .saturating_add((remaining_blocks as f64 * self.average_block_time()).round() as u64);
+        ConfirmationEta {
+            remaining_blocks,
+            seconds: estimated_at.saturating_sub(now),
+            estimated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_time_estimation() {
+        let empty = BlockTimeEstimator::new();
+        assert_eq!(empty.average_block_time(), 75.0);
+        assert_eq!(empty.timestamp_at(10), None);
+        assert_eq!(empty.height_at(10), None);
+
+        // Blocks every 60 seconds
+        let estimator =
+            BlockTimeEstimator::from_headers((100..=110).map(|h| (h, 1_000 + (h - 100) * 60)));
+        assert_eq!(estimator.average_block_time(), 60.0);
+        assert_eq!(estimator.timestamp_at(105), Some(1_300));
+        assert_eq!(estimator.timestamp_at(112), Some(1_720));
+        assert_eq!(estimator.timestamp_at(98), Some(880));
+        assert_eq!(estimator.height_at(1_600), Some(110));
+        assert_eq!(estimator.height_at(1_719), Some(111));
+        assert_eq!(estimator.height_at(1_550), Some(109));
+
+        // Mined at the tip: one of three confirmations done
+        let eta = estimator.confirmation_eta(Some(110), 3, 1_630);
+        assert_eq!(eta.remaining_blocks, 2);
+        assert_eq!(eta.estimated_at, 1_720);
+        assert_eq!(eta.seconds, 90);
+
+        let eta = estimator.confirmation_eta(None, 1, 1_600);
+        assert_eq!(eta.remaining_blocks, 1);
+        assert_eq!(eta.seconds, 60);
+
+        let eta = estimator.confirmation_eta(Some(100), 3, 2_000);
+        assert_eq!(eta.remaining_blocks, 0);
+        assert_eq!(eta.seconds, 0);
+    }
+}
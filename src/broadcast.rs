@@ -0,0 +1,517 @@
+//! Rate-limited broadcast queue
+//!
+//! High-volume senders (payout batches, exchange withdrawals) should not fire
+//! transactions at lightwalletd as fast as they are built. The
+//! [`BroadcastQueue`]:
+//! - Persists every transaction before it is sent, so pending broadcasts
+//!   survive restarts
+//! - Sends one transaction at a time, oldest first
+//! - Stays under a configurable number of broadcasts per interval
+//! - Retries transient failures with exponential backoff and gives up on
+//!   transactions the server rejects
+//!
+//! The queue is stored in a `numi_broadcast_queue` table, by default inside
+//! the wallet database.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::light_client::LightClient;
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Broadcast queue settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastConfig {
+    /// Maximum broadcasts per `rate_interval`
+    pub max_per_interval: u32,
+    /// Window for the rate limit
+    pub rate_interval: Duration,
+    /// Attempts before a transiently failing transaction is marked failed
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on each further attempt
+    pub initial_backoff: Duration,
+    /// Upper bound on the retry delay
+    pub max_backoff: Duration,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self {
+            max_per_interval: 10,
+            rate_interval: Duration::from_secs(60),
+            max_attempts: 10,
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(600),
+        }
+    }
+}
+
+/// State of a queued broadcast
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BroadcastStatus {
+    /// Waiting to be sent or retried
+    Pending,
+    /// Accepted by the server
+    Sent,
+    /// Rejected by the server or out of attempts
+    Failed,
+}
+
+impl BroadcastStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BroadcastStatus::Pending => "pending",
+            BroadcastStatus::Sent => "sent",
+            BroadcastStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "pending" => Ok(BroadcastStatus::Pending),
+            "sent" => Ok(BroadcastStatus::Sent),
+            "failed" => Ok(BroadcastStatus::Failed),
+            other => Err(Error::Database(format!(
+                "Unknown broadcast status: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A transaction in the broadcast queue
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedBroadcast {
+    pub txid: String,
+    pub raw_tx: Vec<u8>,
+    pub status: BroadcastStatus,
+    pub attempts: u32,
+    /// Unix time of the next attempt
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    /// Unix time the transaction was queued
+    pub created_at: u64,
+}
+
+/// Result of one broadcast attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastOutcome {
+    pub txid: String,
+    pub status: BroadcastStatus,
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+/// How a submit attempt should be treated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SubmitResult {
+    Accepted,
+    Transient(String),
+    Rejected(String),
+}
+
+/// Classify a lightwalletd `SendTransaction` response
+pub(crate) fn classify_response(error_code: i32, error_message: &str) -> SubmitResult {
+    if error_code == 0 {
+        return SubmitResult::Accepted;
+    }
+    let message = error_message.to_lowercase().replace('-', " ");
+    // A previous attempt may have reached the node before the connection dropped
+    if message.contains("already in") || message.contains("already have") {
+        return SubmitResult::Accepted;
+    }
+    let transient = [
+        "timeout",
+        "timed out",
+        "unavailable",
+        "connection",
+        "try again",
+    ];
+    if transient.iter().any(|needle| message.contains(needle)) {
+        SubmitResult::Transient(error_message.to_string())
+    } else {
+        SubmitResult::Rejected(format!("code {}: {}", error_code, error_message))
+    }
+}
+
+/// Sliding-window rate limiter
+#[derive(Debug)]
+struct RateLimiter {
+    max: usize,
+    interval: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    fn new(max: u32, interval: Duration) -> Self {
+        Self {
+            max: max.max(1) as usize,
+            interval,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Time to wait before another send is allowed
+    fn wait_time(&mut self, now: Instant) -> Option<Duration> {
+        while self
+            .sent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= self.interval)
+        {
+            self.sent.pop_front();
+        }
+        if self.sent.len() < self.max {
+            None
+        } else {
+            self.sent
+                .front()
+                .map(|t| self.interval - now.duration_since(*t))
+        }
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.sent.push_back(now);
+    }
+}
+
+const DB_CONTEXT: &str = "Broadcast queue error";
+
+/// Persistent, rate-limited queue of transactions to broadcast
+pub struct BroadcastQueue {
+    conn: Connection,
+    config: BroadcastConfig,
+    limiter: RateLimiter,
+}
+
+impl BroadcastQueue {
+    /// Open (or create) a queue stored in the SQLite database at `path`
+    pub fn open(path: &Path, config: BroadcastConfig) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_broadcast_queue (
+                txid TEXT PRIMARY KEY,
+                raw_tx BLOB NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at INTEGER NOT NULL,
+                last_error TEXT,
+                created_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+
+        Ok(Self {
+            conn,
+            limiter: RateLimiter::new(config.max_per_interval, config.rate_interval),
+            config,
+        })
+    }
+
+    /// Open the queue stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet, config: BroadcastConfig) -> Result<Self> {
+        Self::open(wallet.db_path(), config)
+    }
+
+    /// Add a transaction to the queue
+    ///
+    /// Queuing a transaction that is already present is a no-op, so callers
+    /// can safely re-enqueue after a crash.
+    pub fn enqueue(&self, txid: &str, raw_tx: &[u8]) -> Result<()> {
+        let now = unix_now();
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO numi_broadcast_queue
+                    (txid, raw_tx, status, attempts, next_attempt_at, created_at)
+                 VALUES (?1, ?2, ?3, 0, ?4, ?4)",
+                params![txid, raw_tx, BroadcastStatus::Pending.as_str(), now as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Queue a transaction under its locally computed txid
+    ///
+    /// # Returns
+    /// The transaction ID (hex encoded)
+    pub fn enqueue_transaction(&self, raw_tx: &[u8]) -> Result<String> {
+        let txid = crate::transaction::txid::txid(raw_tx)?;
+        self.enqueue(&txid, raw_tx)?;
+        Ok(txid)
+    }
+
+    fn read_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<(QueuedBroadcast, String)> {
+        Ok((
+            QueuedBroadcast {
+                txid: row.get(0)?,
+                raw_tx: row.get(1)?,
+                status: BroadcastStatus::Pending,
+                attempts: row.get(3)?,
+                next_attempt_at: row.get::<_, i64>(4)? as u64,
+                last_error: row.get(5)?,
+                created_at: row.get::<_, i64>(6)? as u64,
+            },
+            row.get(2)?,
+        ))
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<QueuedBroadcast>> {
+        let mut stmt = self.conn.prepare(sql).map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map(params, Self::read_row)
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        rows.into_iter()
+            .map(|(mut entry, status)| {
+                entry.status = BroadcastStatus::parse(&status)?;
+                Ok(entry)
+            })
+            .collect()
+    }
+
+    const COLUMNS: &'static str =
+        "txid, raw_tx, status, attempts, next_attempt_at, last_error, created_at";
+
+    /// Look up a queued transaction
+    pub fn get(&self, txid: &str) -> Result<Option<QueuedBroadcast>> {
+        let sql = format!(
+            "SELECT {} FROM numi_broadcast_queue WHERE txid = ?1",
+            Self::COLUMNS
+        );
+        Ok(self.query(&sql, params![txid])?.into_iter().next())
+    }
+
+    /// Transactions waiting to be sent or retried, oldest first
+    pub fn pending(&self) -> Result<Vec<QueuedBroadcast>> {
+        let sql = format!(
+            "SELECT {} FROM numi_broadcast_queue WHERE status = ?1 ORDER BY created_at, rowid",
+            Self::COLUMNS
+        );
+        self.query(&sql, params![BroadcastStatus::Pending.as_str()])
+    }
+
+    /// Requeue a failed transaction for another round of attempts
+    pub fn retry(&self, txid: &str) -> Result<bool> {
+        let updated = self
+            .conn
+            .execute(
+                "UPDATE numi_broadcast_queue
+                 SET status = ?1, attempts = 0, next_attempt_at = ?2
+                 WHERE txid = ?3 AND status = ?4",
+                params![
+                    BroadcastStatus::Pending.as_str(),
+                    unix_now() as i64,
+                    txid,
+                    BroadcastStatus::Failed.as_str()
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(updated > 0)
+    }
+
+    /// Remove sent and failed entries queued before `before` (unix seconds)
+    pub fn purge_finished(&self, before: u64) -> Result<usize> {
+        self.conn
+            .execute(
+                "DELETE FROM numi_broadcast_queue WHERE status != ?1 AND created_at < ?2",
+                params![BroadcastStatus::Pending.as_str(), before as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// Next pending transaction whose retry time has arrived
+    fn next_due(&self, now: u64) -> Result<Option<QueuedBroadcast>> {
+        let sql = format!(
+            "SELECT {} FROM numi_broadcast_queue
+             WHERE status = ?1 AND next_attempt_at <= ?2
+             ORDER BY created_at, rowid LIMIT 1",
+            Self::COLUMNS
+        );
+        Ok(self
+            .query(&sql, params![BroadcastStatus::Pending.as_str(), now as i64])?
+            .into_iter()
+            .next())
+    }
+
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 1u32 << attempts.saturating_sub(1).min(16);
+        self.config
+            .initial_backoff
+            .saturating_mul(factor)
+            .min(self.config.max_backoff)
+    }
+
+    /// Apply the result of an attempt to the stored entry
+    fn record_attempt(
+        &self,
+        mut entry: QueuedBroadcast,
+        result: SubmitResult,
+        now: u64,
+    ) -> Result<BroadcastOutcome> {
+        entry.attempts += 1;
+        let (status, error) = match result {
+            SubmitResult::Accepted => (BroadcastStatus::Sent, None),
+            SubmitResult::Rejected(e) => (BroadcastStatus::Failed, Some(e)),
+            SubmitResult::Transient(e) if entry.attempts >= self.config.max_attempts => {
+                (BroadcastStatus::Failed, Some(e))
+            }
+            SubmitResult::Transient(e) => (BroadcastStatus::Pending, Some(e)),
+        };
+        let next_attempt_at = now + self.backoff(entry.attempts).as_secs();
+
+        self.conn
+            .execute(
+                "UPDATE numi_broadcast_queue
+                 SET status = ?1, attempts = ?2, next_attempt_at = ?3, last_error = ?4
+                 WHERE txid = ?5",
+                params![
+                    status.as_str(),
+                    entry.attempts,
+                    next_attempt_at as i64,
+                    error,
+                    entry.txid
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+
+        Ok(BroadcastOutcome {
+            txid: entry.txid,
+            status,
+            attempts: entry.attempts,
+            error,
+        })
+    }
+
+    /// Send the next due transaction, if the rate limit allows
+    ///
+    /// # Returns
+    /// The outcome of the attempt, or `None` if nothing is due or the rate
+    /// limit has been reached
+    pub async fn process_next(
+        &mut self,
+        client: &mut LightClient,
+    ) -> Result<Option<BroadcastOutcome>> {
+        if self.limiter.wait_time(Instant::now()).is_some() {
+            return Ok(None);
+        }
+        let now = unix_now();
+        let Some(entry) = self.next_due(now)? else {
+            return Ok(None);
+        };
+
+        self.limiter.record(Instant::now());
+        let result = match client.send_raw_transaction(&entry.raw_tx).await {
+            Ok((code, message)) => classify_response(code, &message),
+            Err(e) => SubmitResult::Transient(e.to_string()),
+        };
+
+        let outcome = self.record_attempt(entry, result, now)?;
+        match outcome.status {
+            BroadcastStatus::Sent => tracing::info!("Broadcast {}", outcome.txid),
+            BroadcastStatus::Pending => tracing::warn!(
+                "Broadcast of {} failed (attempt {}), will retry: {:?}",
+                outcome.txid,
+                outcome.attempts,
+                outcome.error
+            ),
+            BroadcastStatus::Failed => tracing::error!(
+                "Broadcast of {} failed permanently: {:?}",
+                outcome.txid,
+                outcome.error
+            ),
+        }
+        Ok(Some(outcome))
+    }
+
+    /// Process the queue until an error occurs
+    ///
+    /// # Arguments
+    /// * `client` - Light client used to broadcast
+    /// * `poll_interval` - Sleep between checks when nothing can be sent
+    pub async fn run(&mut self, client: &mut LightClient, poll_interval: Duration) -> Result<()> {
+        loop {
+            if self.process_next(client).await?.is_none() {
+                let wait = self
+                    .limiter
+                    .wait_time(Instant::now())
+                    .unwrap_or(poll_interval);
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn temp_queue(config: BroadcastConfig) -> (TempDb, BroadcastQueue) {
+        let path = TempDb::new();
+        let queue = BroadcastQueue::open(&path, config).unwrap();
+        (path, queue)
+    }
+
+    #[test]
+    fn test_classify_response() {
+        assert_eq!(classify_response(0, ""), SubmitResult::Accepted);
+        assert_eq!(
+            classify_response(-26, "txn-already-in-mempool"),
+            SubmitResult::Accepted
+        );
+        assert!(matches!(
+            classify_response(-1, "Connection reset"),
+            SubmitResult::Transient(_)
+        ));
+        assert!(matches!(
+            classify_response(-26, "bad-txns-inputs-spent"),
+            SubmitResult::Rejected(_)
+        ));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(limiter.wait_time(start).is_none());
+        limiter.record(start);
+        limiter.record(start);
+        assert!(limiter.wait_time(start).is_some());
+        assert!(limiter.wait_time(start + Duration::from_secs(10)).is_none());
+    }
+
+    #[test]
+    fn test_retry_and_persistence() {
+        let config = BroadcastConfig {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let (_db, queue) = temp_queue(config);
+        queue.enqueue("aa", &[1, 2, 3]).unwrap();
+        queue.enqueue("aa", &[1, 2, 3]).unwrap();
+        assert_eq!(queue.pending().unwrap().len(), 1);
+
+        let entry = queue.next_due(unix_now()).unwrap().unwrap();
+        let outcome = queue
+            .record_attempt(entry, SubmitResult::Transient("timeout".into()), 100)
+            .unwrap();
+        assert_eq!(outcome.status, BroadcastStatus::Pending);
+        assert_eq!(queue.get("aa").unwrap().unwrap().next_attempt_at, 105);
+        assert!(queue.next_due(104).unwrap().is_none());
+
+        let entry = queue.next_due(105).unwrap().unwrap();
+        let outcome = queue
+            .record_attempt(entry, SubmitResult::Transient("timeout".into()), 105)
+            .unwrap();
+        assert_eq!(outcome.status, BroadcastStatus::Failed);
+        assert!(queue.pending().unwrap().is_empty());
+
+        assert!(queue.retry("aa").unwrap());
+        assert_eq!(queue.pending().unwrap()[0].attempts, 0);
+    }
+}
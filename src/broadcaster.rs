@@ -0,0 +1,326 @@
+//! Broadcasting to several backends at once
+//!
+//! A single lightwalletd server or zcashd node is a single point of failure
+//! for payouts. A [`Broadcaster`] submits a transaction to every configured
+//! [`BroadcastBackend`] in parallel — zcashd over RPC and any number of
+//! lightwalletd servers — and succeeds if at least one of them accepts it.
+//! Backends that report the transaction as already known count as accepting.
+//!
+//! The txid is computed locally; a backend that reports a different txid is
+//! treated as a failure. With [`Broadcaster::with_mempool_check`], the
+//! transaction is first checked against the mempool so a double spend fails
+//! with [`Error::MempoolConflict`] instead of a rejection from every backend.
+
+use crate::broadcast::{classify_response, SubmitResult};
+use crate::client::RpcClient;
+use crate::error::{Error, Result};
+use crate::light_client::LightClient;
+use crate::mempool::{check_mempool, MempoolSource};
+use crate::transaction::txid::{check_reported_txid, is_txid, txid};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// Default time each backend has to answer
+pub const DEFAULT_BROADCAST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a lightwalletd mempool stream may stay silent before the
+/// mempool is taken as fully received
+pub const MEMPOOL_STREAM_IDLE: Duration = Duration::from_secs(2);
+
+/// A service transactions can be submitted to
+#[async_trait]
+pub trait BroadcastBackend: Send + Sync {
+    /// Name used in reports, e.g. the endpoint URL
+    fn name(&self) -> String;
+
+    /// Submit a raw transaction
+    ///
+    /// # Returns
+    /// The txid reported by the backend, if it reports one
+    async fn submit(&self, raw_tx: &[u8]) -> Result<Option<String>>;
+}
+
+#[async_trait]
+impl BroadcastBackend for RpcClient {
+    fn name(&self) -> String {
+        format!("zcashd {}", self.endpoint())
+    }
+
+    async fn submit(&self, raw_tx: &[u8]) -> Result<Option<String>> {
+        match self.send_raw_transaction(raw_tx).await {
+            Ok(txid) => Ok(Some(txid)),
+            Err(e) => match classify_response(-1, &e.to_string()) {
+                SubmitResult::Accepted => Ok(None),
+                _ => Err(e),
+            },
+        }
+    }
+}
+
+/// A lightwalletd server used as a broadcast backend
+pub struct LightwalletdBackend {
+    name: String,
+    client: tokio::sync::Mutex<LightClient>,
+}
+
+impl LightwalletdBackend {
+    /// Use a connected light client as a backend
+    pub fn new(client: LightClient) -> Self {
+        Self {
+            name: format!("lightwalletd {}", client.endpoint()),
+            client: tokio::sync::Mutex::new(client),
+        }
+    }
+}
+
+#[async_trait]
+impl BroadcastBackend for LightwalletdBackend {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn submit(&self, raw_tx: &[u8]) -> Result<Option<String>> {
+        let (code, message) = self
+            .client
+            .lock()
+            .await
+            .send_raw_transaction(raw_tx)
+            .await?;
+        match classify_response(code, &message) {
+            SubmitResult::Accepted => {
+                let reported = message.trim().trim_matches('"');
+                Ok(is_txid(reported).then(|| reported.to_string()))
+            }
+            SubmitResult::Transient(e) | SubmitResult::Rejected(e) => Err(Error::Rpc(e)),
+        }
+    }
+}
+
+#[async_trait]
+impl MempoolSource for LightwalletdBackend {
+    async fn mempool_transactions(&self) -> Result<Vec<Vec<u8>>> {
+        self.client
+            .lock()
+            .await
+            .get_mempool_transactions(MEMPOOL_STREAM_IDLE)
+            .await
+    }
+}
+
+/// A backend that did not accept the transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackendFailure {
+    pub backend: String,
+    pub error: String,
+}
+
+/// Result of a broadcast accepted by at least one backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastReport {
+    /// Locally computed transaction ID
+    pub txid: String,
+    /// Backends that accepted the transaction
+    pub accepted_by: Vec<String>,
+    /// Backends that failed, timed out or rejected it
+    pub failures: Vec<BackendFailure>,
+}
+
+/// Submits transactions to several backends in parallel
+pub struct Broadcaster {
+    backends: Vec<Arc<dyn BroadcastBackend>>,
+    timeout: Duration,
+    mempool: Option<Box<dyn MempoolSource>>,
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Broadcaster {
+    /// Create a broadcaster without backends
+    pub fn new() -> Self {
+        Self {
+            backends: Vec::new(),
+            timeout: DEFAULT_BROADCAST_TIMEOUT,
+            mempool: None,
+        }
+    }
+
+    /// Add a backend
+    pub fn with_backend(mut self, backend: impl BroadcastBackend + 'static) -> Self {
+        self.backends.push(Arc::new(backend));
+        self
+    }
+
+    /// Set how long each backend has to answer
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Check each transaction against this mempool before submitting it
+    pub fn with_mempool_check(mut self, source: impl MempoolSource + 'static) -> Self {
+        self.mempool = Some(Box::new(source));
+        self
+    }
+
+    /// Names of the configured backends
+    pub fn backends(&self) -> Vec<String> {
+        self.backends.iter().map(|b| b.name()).collect()
+    }
+
+    /// Submit a transaction to every backend
+    ///
+    /// Waits until every backend has answered or timed out, so the report
+    /// is complete.
+    ///
+    /// # Returns
+    /// The report if at least one backend accepted the transaction;
+    /// [`Error::MempoolConflict`] if the mempool check finds a double spend;
+    /// otherwise [`Error::Rpc`] listing every failure
+    pub async fn broadcast(&self, raw_tx: &[u8]) -> Result<BroadcastReport> {
+        if self.backends.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Broadcaster has no backends".to_string(),
+            ));
+        }
+        if let Some(source) = &self.mempool {
+            check_mempool(source.as_ref(), raw_tx).await?;
+        }
+        self.submit_all(txid(raw_tx)?, raw_tx).await
+    }
+
+    async fn submit_all(&self, txid: String, raw_tx: &[u8]) -> Result<BroadcastReport> {
+        let mut tasks = JoinSet::new();
+        for backend in &self.backends {
+            let backend = Arc::clone(backend);
+            let raw_tx = raw_tx.to_vec();
+            let timeout = self.timeout;
+            tasks.spawn(async move {
+                let result = match tokio::time::timeout(timeout, backend.submit(&raw_tx)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(Error::Rpc(format!("No answer within {:?}", timeout))),
+                };
+                (backend.name(), result)
+            });
+        }
+
+        let mut report = BroadcastReport {
+            txid,
+            accepted_by: Vec::new(),
+            failures: Vec::new(),
+        };
+        while let Some(joined) = tasks.join_next().await {
+            let (backend, result) =
+                joined.map_err(|e| Error::Rpc(format!("Broadcast task failed: {}", e)))?;
+            let checked = result.and_then(|reported| match reported {
+                Some(reported) => check_reported_txid(&report.txid, &reported),
+                None => Ok(()),
+            });
+            match checked {
+                Ok(()) => report.accepted_by.push(backend),
+                Err(e) => report.failures.push(BackendFailure {
+                    backend,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        report.accepted_by.sort();
+        report.failures.sort_by(|a, b| a.backend.cmp(&b.backend));
+
+        if report.accepted_by.is_empty() {
+            let failures: Vec<String> = report
+                .failures
+                .iter()
+                .map(|f| format!("{}: {}", f.backend, f.error))
+                .collect();
+            return Err(Error::Rpc(format!(
+                "No backend accepted transaction {}: {}",
+                report.txid,
+                failures.join("; ")
+            )));
+        }
+        for failure in &report.failures {
+            tracing::warn!(
+                "Broadcast of {} to {} failed: {}",
+                report.txid,
+                failure.backend,
+                failure.error
+            );
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockBackend {
+        name: &'static str,
+        result: std::result::Result<Option<&'static str>, &'static str>,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl BroadcastBackend for MockBackend {
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn submit(&self, _raw_tx: &[u8]) -> Result<Option<String>> {
+            tokio::time::sleep(self.delay).await;
+            self.result
+                .map(|txid| txid.map(str::to_string))
+                .map_err(|e| Error::Rpc(e.to_string()))
+        }
+    }
+
+    fn mock(
+        name: &'static str,
+        result: std::result::Result<Option<&'static str>, &'static str>,
+    ) -> MockBackend {
+        MockBackend {
+            name,
+            result,
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_any_backend_accepts() {
+        let txid = "ab".repeat(32);
+        let broadcaster = Broadcaster::new()
+            .with_timeout(Duration::from_millis(50))
+            .with_backend(mock("down", Err("connection refused")))
+            .with_backend(mock("lwd", Ok(None)))
+            .with_backend(mock(
+                "wrong",
+                Ok(Some(
+                    "cdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd",
+                )),
+            ))
+            .with_backend(MockBackend {
+                name: "slow",
+                result: Ok(None),
+                delay: Duration::from_secs(5),
+            });
+        let report = broadcaster.submit_all(txid.clone(), &[]).await.unwrap();
+        assert_eq!(report.txid, txid);
+        assert_eq!(report.accepted_by, vec!["lwd"]);
+        let failed: Vec<&str> = report.failures.iter().map(|f| f.backend.as_str()).collect();
+        assert_eq!(failed, vec!["down", "slow", "wrong"]);
+
+        let none = Broadcaster::new().with_backend(mock("down", Err("connection refused")));
+        assert!(matches!(
+            none.submit_all(txid, &[]).await,
+            Err(Error::Rpc(_))
+        ));
+        assert!(Broadcaster::new().broadcast(&[]).await.is_err());
+    }
+}
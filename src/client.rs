@@ -1,11 +1,27 @@
 //! Client implementations for connecting to Zcash infrastructure
 use crate::error::{Error, Result};
+use crate::fault_injection::{truncate_body, FaultInjector};
+use crate::headers::RequestHeaders;
 use crate::rpc::{
-    AddressInfo, BlockchainInfo, Payment, RpcRequest, RpcResponse, TransactionDetails,
+    AddressBalance, AddressDelta, AddressInfo, AddressSource, Block, BlockTemplate, BlockchainInfo,
+    Capabilities, Payment, PrivacyPolicy, RawPayment, RawTransaction, RpcRequest, RpcResponse,
+    SubmitBlockOutcome, TransactionDetails, UnspentNote, UnspentOutput,
 };
 use rand::random;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// zcashd error code for calls made while the node is starting up
+pub const RPC_IN_WARMUP: i64 = -28;
+
+/// Interval between readiness checks in [`RpcClient::wait_until_ready`]
+const READY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Blocks requested per batch by [`RpcClient::get_blocks_range`]
+pub const BLOCK_BATCH_SIZE: u64 = 20;
 
 /// RPC client for connecting to `zcashd` nodes.
 ///
@@ -15,6 +31,10 @@ pub struct RpcClient {
     endpoint: String,
     http: reqwest::Client,
     auth: Option<String>,
+    headers: RequestHeaders,
+    /// Detected on first use, see [`capabilities`](Self::capabilities)
+    capabilities: OnceCell<Capabilities>,
+    faults: Option<FaultInjector>,
 }
 
 impl RpcClient {
@@ -24,6 +44,9 @@ impl RpcClient {
             endpoint: endpoint.into(),
             http: reqwest::Client::new(),
             auth: None,
+            headers: RequestHeaders::default(),
+            capabilities: OnceCell::new(),
+            faults: None,
         }
     }
 
@@ -38,11 +61,135 @@ impl RpcClient {
         client
     }
 
+    /// Send a custom User-Agent and extra headers with every request.
+    ///
+    /// An `Authorization` header (e.g. a managed node provider's bearer
+    /// token) replaces the basic-auth credentials of [`with_auth`](Self::with_auth).
+    pub fn with_headers(mut self, headers: RequestHeaders) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Inject latency, dropped connections, malformed responses and stale
+    /// heights into every call, for resilience tests.
+    ///
+    /// See [`fault_injection`](crate::fault_injection).
+    pub fn with_fault_injector(mut self, faults: FaultInjector) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// The User-Agent and extra headers sent with every request.
+    pub fn headers(&self) -> &RequestHeaders {
+        &self.headers
+    }
+
+    /// The RPC endpoint URL.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Features of the node, detected on first use and then cached.
+    ///
+    /// Queries `getinfo` for the node's version and probes the address
+    /// index with an empty `getaddressbalance` call.
+    pub async fn capabilities(&self) -> Result<&Capabilities> {
+        self.capabilities
+            .get_or_try_init(|| async {
+                let info: serde_json::Value = self.call("getinfo", serde_json::json!([])).await?;
+                let address_index = self
+                    .call::<serde_json::Value, _>(
+                        "getaddressbalance",
+                        serde_json::json!([{ "addresses": [] }]),
+                    )
+                    .await
+                    .is_ok();
+                let capabilities = Capabilities::from_info(&info, address_index);
+                tracing::debug!("Node capabilities: {:?}", capabilities);
+                Ok(capabilities)
+            })
+            .await
+    }
+
+    /// Fail unless the node has a capability.
+    async fn require(
+        &self,
+        supported: impl FnOnce(&Capabilities) -> bool,
+        feature: &str,
+    ) -> Result<()> {
+        let capabilities = self.capabilities().await?;
+        if supported(capabilities) {
+            return Ok(());
+        }
+        Err(Error::Rpc(format!(
+            "Node {} does not support {}",
+            if capabilities.subversion.is_empty() {
+                &self.endpoint
+            } else {
+                &capabilities.subversion
+            },
+            feature
+        )))
+    }
+
     /// Call a JSON-RPC method and deserialize the result into the requested type.
     ///
     /// This is the low-level method for making RPC calls. Prefer using the
     /// typed convenience methods when available.
     pub async fn call<T, P>(&self, method: &str, params: P) -> Result<T>
+    where
+        T: DeserializeOwned,
+        P: Serialize,
+    {
+        self.send_request(method, params)
+            .await?
+            .ok_or_else(|| Error::Rpc("RPC response missing result".to_string()))
+    }
+
+    /// Call a JSON-RPC method that returns no result (`null`).
+    pub async fn call_void<P>(&self, method: &str, params: P) -> Result<()>
+    where
+        P: Serialize,
+    {
+        let _: Option<serde_json::Value> = self.send_request(method, params).await?;
+        Ok(())
+    }
+
+    /// Call a JSON-RPC method once per parameter set, in a single batch
+    /// request.
+    ///
+    /// # Returns
+    /// The results in the order of `params`; any failed call fails the batch
+    pub async fn call_batch<T, P>(
+        &self,
+        method: &str,
+        params: impl IntoIterator<Item = P>,
+    ) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+        P: Serialize,
+    {
+        let first_id = u64::from(random::<u32>());
+        let requests = params
+            .into_iter()
+            .zip(first_id..)
+            .map(|(params, id)| {
+                Ok(RpcRequest {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    method: method.to_string(),
+                    params: serde_json::to_value(params)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let responses: Vec<RpcResponse<T>> = self.decode(self.post(&requests).await?).await?;
+        order_batch(first_id, requests.len(), responses)
+    }
+
+    async fn send_request<T, P>(&self, method: &str, params: P) -> Result<Option<T>>
     where
         T: DeserializeOwned,
         P: Serialize,
@@ -55,49 +202,133 @@ impl RpcClient {
             params,
         };
 
-        let mut req = self
-            .http
-            .post(&self.endpoint)
-            .json(&request)
-            .header("Content-Type", "application/json");
+        let rpc_response: RpcResponse<T> = self.decode(self.post(&request).await?).await?;
+
+        if let Some(error) = rpc_response.error {
+            return Err(Error::Rpc(format!(
+                "RPC error {}: {}",
+                error.code, error.message
+            )));
+        }
+
+        Ok(rpc_response.result)
+    }
+
+    /// Post a request (or batch) and check the HTTP status
+    async fn post(&self, body: &impl Serialize) -> Result<reqwest::Response> {
+        if let Some(ref faults) = self.faults {
+            faults.wait().await;
+            if faults.drop_connection() {
+                // A request nothing listens for fails like a dropped connection
+                self.http.post("http://127.0.0.1:0/").send().await?;
+            }
+        }
+
+        let mut req = self.headers.apply(
+            self.http
+                .post(&self.endpoint)
+                .json(body)
+                .header("Content-Type", "application/json"),
+        );
 
         if let Some(ref auth) = self.auth {
-            req = req.header("Authorization", format!("Basic {}", auth));
+            if self.headers.get("authorization").is_none() {
+                req = req.header("Authorization", format!("Basic {}", auth));
+            }
         }
 
         let response = req.send().await?;
 
         if !response.status().is_success() {
+            // zcashd answers failed calls with an error status and a JSON-RPC
+            // error body; keep its code so callers can tell errors apart
+            let status = response.status();
+            let body: Option<serde_json::Value> = response.json().await.ok();
+            if let Some(error) = body.as_ref().and_then(|body| body.get("error")) {
+                if let (Some(code), Some(message)) = (
+                    error.get("code").and_then(|c| c.as_i64()),
+                    error.get("message").and_then(|m| m.as_str()),
+                ) {
+                    return Err(Error::Rpc(format!("RPC error {}: {}", code, message)));
+                }
+            }
             return Err(Error::Rpc(format!(
                 "RPC request failed with status: {}",
-                response.status()
+                status
             )));
         }
 
-        let rpc_response: RpcResponse<T> = response.json().await?;
+        Ok(response)
+    }
 
-        if let Some(error) = rpc_response.error {
-            return Err(Error::Rpc(format!(
-                "RPC error {}: {}",
-                error.code, error.message
-            )));
+    /// Parse a response body, truncating it if a fault is injected
+    async fn decode<T: DeserializeOwned>(&self, response: reqwest::Response) -> Result<T> {
+        if !self.faults.as_ref().is_some_and(|faults| faults.malform()) {
+            return Ok(response.json().await?);
         }
+        let body = response.bytes().await?;
+        Ok(serde_json::from_slice(truncate_body(&body))?)
+    }
 
-        rpc_response
-            .result
-            .ok_or_else(|| Error::Rpc("RPC response missing result".to_string()))
+    /// A tip height as reported with the injected staleness
+    fn reported_height(&self, height: u64) -> u64 {
+        match self.faults {
+            Some(ref faults) => faults.stale_height(height),
+            None => height,
+        }
     }
 
     // ============================================================================
     // Bitcoin-Compatible RPC Methods
     // ============================================================================
 
+    /// Wait until the node can serve wallet calls.
+    ///
+    /// Polls `getblockchaininfo` while the node is starting: connection
+    /// failures and [`RPC_IN_WARMUP`] errors (loading the block index,
+    /// verifying blocks) are retried, as is a node still in initial block
+    /// download. Other RPC errors, such as bad credentials, fail at once.
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum time to wait
+    ///
+    /// # Returns
+    /// Blockchain information of the ready node
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<BlockchainInfo> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let status = match self.get_blockchain_info().await {
+                Ok(info) if info.is_synced() => return Ok(info),
+                Ok(info) => format!(
+                    "initial block download at {} of {} blocks",
+                    info.blocks, info.headers
+                ),
+                Err(Error::Network(e)) => format!("node unreachable: {}", e),
+                Err(e) if rpc_error_code(&e) == Some(RPC_IN_WARMUP) => e.to_string(),
+                Err(e) => return Err(e),
+            };
+            if tokio::time::Instant::now() + READY_POLL_INTERVAL > deadline {
+                return Err(Error::Rpc(format!(
+                    "Node not ready after {:?}: {}",
+                    timeout, status
+                )));
+            }
+            tracing::debug!("Waiting for node: {}", status);
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
     /// Get blockchain information (Bitcoin-compatible).
     ///
     /// Returns information about the blockchain state including chain name,
     /// block height, difficulty, and sync status.
     pub async fn get_blockchain_info(&self) -> Result<BlockchainInfo> {
-        self.call("getblockchaininfo", serde_json::json!([])).await
+        let mut info: BlockchainInfo = self
+            .call("getblockchaininfo", serde_json::json!([]))
+            .await?;
+        info.blocks = self.reported_height(info.blocks);
+        info.headers = self.reported_height(info.headers);
+        Ok(info)
     }
 
     /// Get the raw blockchain info as JSON value.
@@ -115,9 +346,74 @@ impl RpcClient {
         self.call("getblock", serde_json::json!([hash])).await
     }
 
+    /// Get a block with decoded transactions by hash or height.
+    ///
+    /// Uses `getblock` with verbosity 2.
+    pub async fn get_block_verbose(&self, hash_or_height: &str) -> Result<Block> {
+        self.call("getblock", serde_json::json!([hash_or_height, 2])).await
+    }
+
+    /// Get a block with decoded transactions by height.
+    pub async fn get_block_by_height(&self, height: u64) -> Result<Block> {
+        self.get_block_verbose(&height.to_string()).await
+    }
+
+    /// Get the blocks in an inclusive height range, in height order.
+    ///
+    /// Blocks are fetched in batches of [`BLOCK_BATCH_SIZE`] `getblock` calls,
+    /// one HTTP request per batch.
+    pub async fn get_blocks_range(&self, heights: RangeInclusive<u64>) -> Result<Vec<Block>> {
+        let (mut start, end) = heights.into_inner();
+        let mut blocks = Vec::new();
+        while start <= end {
+            let batch_end = end.min(start.saturating_add(BLOCK_BATCH_SIZE - 1));
+            let params =
+                (start..=batch_end).map(|height| serde_json::json!([height.to_string(), 2]));
+            blocks.extend(self.call_batch::<Block, _>("getblock", params).await?);
+            match batch_end.checked_add(1) {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Get a decoded transaction by ID.
+    ///
+    /// Requires `-txindex` for transactions not in the wallet or mempool.
+    pub async fn get_raw_transaction(&self, txid: &str) -> Result<RawTransaction> {
+        self.call("getrawtransaction", serde_json::json!([txid, 1])).await
+    }
+
+    /// Submit a raw transaction to the node's mempool and relay it.
+    ///
+    /// # Returns
+    /// The transaction ID reported by the node
+    pub async fn send_raw_transaction(&self, raw_tx: &[u8]) -> Result<String> {
+        self.call("sendrawtransaction", serde_json::json!([hex::encode(raw_tx)]))
+            .await
+    }
+
+    /// Get the IDs of the transactions in the node's mempool.
+    pub async fn get_raw_mempool(&self) -> Result<Vec<String>> {
+        self.call("getrawmempool", serde_json::json!([])).await
+    }
+
+    /// Get a serialized transaction by ID.
+    ///
+    /// Requires `-txindex` for transactions not in the wallet or mempool.
+    pub async fn get_raw_transaction_bytes(&self, txid: &str) -> Result<Vec<u8>> {
+        let raw_hex: String = self
+            .call("getrawtransaction", serde_json::json!([txid, 0]))
+            .await?;
+        hex::decode(raw_hex.trim())
+            .map_err(|e| Error::Rpc(format!("Invalid transaction hex from node: {}", e)))
+    }
+
     /// Get the current block count.
     pub async fn get_block_count(&self) -> Result<u64> {
-        self.call("getblockcount", serde_json::json!([])).await
+        let height = self.call("getblockcount", serde_json::json!([])).await?;
+        Ok(self.reported_height(height))
     }
 
     /// Get network information.
@@ -125,6 +421,44 @@ impl RpcClient {
         self.call("getnetworkinfo", serde_json::json!([])).await
     }
 
+    // ============================================================================
+    // Mining RPC Methods
+    // ============================================================================
+
+    /// Get a template for the next block to mine.
+    ///
+    /// The coinbase transaction pays the node's configured miner address
+    /// (zcashd `-mineraddress`, zebrad `mining.miner_address`).
+    ///
+    /// # Arguments
+    /// * `long_poll_id` - `long_poll_id` of a previous template to wait until
+    ///   it is outdated, or `None` to return at once
+    ///
+    /// # Returns
+    /// The block template
+    pub async fn get_block_template(&self, long_poll_id: Option<&str>) -> Result<BlockTemplate> {
+        let mut request = serde_json::json!({ "mode": "template" });
+        if let Some(long_poll_id) = long_poll_id {
+            request["longpollid"] = serde_json::json!(long_poll_id);
+        }
+        self.call("getblocktemplate", serde_json::json!([request]))
+            .await
+    }
+
+    /// Submit a solved block to the node.
+    ///
+    /// # Arguments
+    /// * `block` - Serialized block
+    ///
+    /// # Returns
+    /// Whether the node accepted the block, and if not, why
+    pub async fn submit_block(&self, block: &[u8]) -> Result<SubmitBlockOutcome> {
+        let result: Option<String> = self
+            .send_request("submitblock", serde_json::json!([hex::encode(block)]))
+            .await?;
+        Ok(SubmitBlockOutcome::from_result(result.as_deref()))
+    }
+
     // ============================================================================
     // Zcash-Specific Shielded RPC Methods (Zcash Payment API)
     // ============================================================================
@@ -202,6 +536,85 @@ impl RpcClient {
         self.call("z_listaddresses", serde_json::json!([])).await
     }
 
+    /// List the wallet's addresses, grouped by key source.
+    ///
+    /// zcashd 4.7.0 and later; use
+    /// [`get_addresses_by_account`](Self::get_addresses_by_account) on older
+    /// nodes.
+    pub async fn list_addresses(&self) -> Result<Vec<AddressSource>> {
+        self.require(|c| c.unified_accounts, "listaddresses")
+            .await?;
+        self.call("listaddresses", serde_json::json!([])).await
+    }
+
+    /// List the transparent addresses assigned to an account (label).
+    ///
+    /// zcashd only accepts the empty account name, which lists the
+    /// addresses without a label.
+    ///
+    /// # Arguments
+    /// * `account` - Account name
+    pub async fn get_addresses_by_account(&self, account: &str) -> Result<Vec<String>> {
+        self.call("getaddressesbyaccount", serde_json::json!([account]))
+            .await
+    }
+
+    /// Get the account (label) assigned to an address.
+    ///
+    /// # Arguments
+    /// * `address` - Address to look up
+    pub async fn get_account(&self, address: &str) -> Result<String> {
+        self.call("getaccount", serde_json::json!([address])).await
+    }
+
+    /// Assign an account (label) to an address.
+    ///
+    /// An empty account name clears the label; zcashd rejects any other
+    /// name, so labels cannot be written to it.
+    ///
+    /// # Arguments
+    /// * `address` - Address to label
+    /// * `account` - Account name to assign
+    pub async fn set_account(&self, address: &str, account: &str) -> Result<()> {
+        self.call_void("setaccount", serde_json::json!([address, account]))
+            .await
+    }
+
+    /// Import a Sapling extended full viewing key as watch-only.
+    ///
+    /// zcashd only; the node tracks payments to the key's addresses but
+    /// cannot spend them.
+    ///
+    /// # Arguments
+    /// * `key` - Encoded Sapling extended full viewing key (`zxviews…`)
+    /// * `rescan` - `"yes"`, `"no"` or `"whenkeyisnew"`
+    /// * `start_height` - Height to start the rescan at (None for genesis)
+    pub async fn z_importviewingkey(
+        &self,
+        key: &str,
+        rescan: &str,
+        start_height: Option<u64>,
+    ) -> Result<()> {
+        self.require(|c| c.zcashd, "viewing key import (zcashd only)")
+            .await?;
+        let params = match start_height {
+            Some(height) => serde_json::json!([key, rescan, height]),
+            None => serde_json::json!([key, rescan]),
+        };
+        self.call_void("z_importviewingkey", params).await
+    }
+
+    /// Import a transparent address as watch-only.
+    ///
+    /// # Arguments
+    /// * `address` - Transparent address to watch
+    /// * `label` - Account (label) to assign
+    /// * `rescan` - Rescan the whole chain for the address's transactions
+    pub async fn import_address(&self, address: &str, label: &str, rescan: bool) -> Result<()> {
+        self.call_void("importaddress", serde_json::json!([address, label, rescan]))
+            .await
+    }
+
     /// View transaction details.
     ///
     /// Returns detailed information about a transaction, including shielded
@@ -265,6 +678,141 @@ impl RpcClient {
         self.call("z_sendmany", params).await
     }
 
+    /// Send funds with an explicit privacy policy (Zcash Payment API).
+    ///
+    /// Like [`z_sendmany`](Self::z_sendmany), but passes zcashd's `privacyPolicy`
+    /// argument, which is required for transfers that cross value pools or
+    /// spend transparent funds.
+    ///
+    /// # Arguments
+    /// * `from_address` - Source address (must be in wallet)
+    /// * `payments` - Vector of payments to send
+    /// * `minconf` - Minimum confirmations for source funds (default: 1)
+    /// * `fee` - Optional transaction fee in ZEC (None uses the ZIP-317 fee)
+    /// * `privacy_policy` - Maximum information zcashd may reveal
+    ///
+    /// # Returns
+    /// Operation ID (string) that can be used to check transaction status
+    pub async fn z_sendmany_with_policy(
+        &self,
+        from_address: &str,
+        payments: Vec<Payment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+        privacy_policy: PrivacyPolicy,
+    ) -> Result<String> {
+        self.require(|c| c.privacy_policy, "the privacyPolicy argument (zcashd 5.0.0+)")
+            .await?;
+        let payment_json: Vec<serde_json::Value> = payments
+            .into_iter()
+            .map(|p| {
+                let mut payment_obj = serde_json::json!({
+                    "address": p.address,
+                    "amount": p.amount
+                });
+                if let Some(memo) = p.memo {
+                    payment_obj["memo"] = serde_json::json!(memo);
+                }
+                payment_obj
+            })
+            .collect();
+
+        let params = serde_json::json!([
+            from_address,
+            payment_json,
+            minconf.unwrap_or(1),
+            fee,
+            privacy_policy.as_str(),
+        ]);
+        self.call("z_sendmany", params).await
+    }
+
+    /// Send funds with exact amounts and raw memos (Zcash Payment API).
+    ///
+    /// Like [`z_sendmany`](Self::z_sendmany), but amounts are passed as
+    /// decimal strings and memos hex encoded (see [`RawPayment`]).
+    ///
+    /// # Arguments
+    /// * `from_address` - Source address (must be in wallet)
+    /// * `payments` - Payments to send
+    /// * `minconf` - Minimum confirmations for source funds (default: 1)
+    /// * `fee` - Optional transaction fee in ZEC (None uses the ZIP-317 fee)
+    /// * `privacy_policy` - Maximum information zcashd may reveal, if set
+    ///
+    /// # Returns
+    /// Operation ID (string) that can be used to check transaction status
+    pub async fn z_sendmany_raw(
+        &self,
+        from_address: &str,
+        payments: &[RawPayment],
+        minconf: Option<u32>,
+        fee: Option<f64>,
+        privacy_policy: Option<PrivacyPolicy>,
+    ) -> Result<String> {
+        if privacy_policy.is_some() {
+            self.require(|c| c.privacy_policy, "the privacyPolicy argument (zcashd 5.0.0+)")
+                .await?;
+        }
+        let payment_json: Vec<serde_json::Value> =
+            payments.iter().map(RawPayment::to_json).collect();
+        let mut params = vec![
+            serde_json::json!(from_address),
+            serde_json::json!(payment_json),
+            serde_json::json!(minconf.unwrap_or(1)),
+        ];
+        if fee.is_some() || privacy_policy.is_some() {
+            params.push(serde_json::json!(fee));
+        }
+        if let Some(privacy_policy) = privacy_policy {
+            params.push(serde_json::json!(privacy_policy.as_str()));
+        }
+        self.call("z_sendmany", params).await
+    }
+
+    /// Create a new account in the node's wallet (unified accounts API).
+    ///
+    /// Requires zcashd 4.7.0 or later.
+    ///
+    /// # Returns
+    /// The ZIP-32 index of the new account
+    pub async fn z_getnewaccount(&self) -> Result<u32> {
+        self.require(|c| c.unified_accounts, "the unified accounts API (zcashd 4.7.0+)")
+            .await?;
+        let result: serde_json::Value = self.call("z_getnewaccount", serde_json::json!([])).await?;
+        result
+            .get("account")
+            .and_then(|a| a.as_u64())
+            .and_then(|a| u32::try_from(a).ok())
+            .ok_or_else(|| Error::Rpc("z_getnewaccount returned no account".to_string()))
+    }
+
+    /// Get a unified address of an account in the node's wallet.
+    ///
+    /// Requires zcashd 4.7.0 or later.
+    ///
+    /// # Arguments
+    /// * `account` - ZIP-32 account index from [`z_getnewaccount`](Self::z_getnewaccount)
+    /// * `receiver_types` - Receivers to include, e.g. `["orchard", "sapling"]`
+    ///   (None for the node's default)
+    pub async fn z_getaddressforaccount(
+        &self,
+        account: u32,
+        receiver_types: Option<&[&str]>,
+    ) -> Result<String> {
+        self.require(|c| c.unified_accounts, "the unified accounts API (zcashd 4.7.0+)")
+            .await?;
+        let params = match receiver_types {
+            Some(types) => serde_json::json!([account, types]),
+            None => serde_json::json!([account]),
+        };
+        let result: serde_json::Value = self.call("z_getaddressforaccount", params).await?;
+        result
+            .get("address")
+            .and_then(|a| a.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| Error::Rpc("z_getaddressforaccount returned no address".to_string()))
+    }
+
     /// Get the status of a z_sendmany operation.
     ///
     /// # Arguments
@@ -289,6 +837,66 @@ impl RpcClient {
             .await
     }
 
+    /// Wait for a z_sendmany operation to complete.
+    ///
+    /// Polls `z_getoperationresult` until the operation succeeds or fails.
+    ///
+    /// # Arguments
+    /// * `operation_id` - The operation ID returned by z_sendmany
+    /// * `max_wait_seconds` - Maximum time to wait in seconds (default: 300)
+    ///
+    /// # Returns
+    /// Transaction ID when the operation completes successfully
+    pub async fn wait_for_operation(
+        &self,
+        operation_id: &str,
+        max_wait_seconds: Option<u64>,
+    ) -> Result<String> {
+        use tokio::time::sleep;
+
+        let max_wait = max_wait_seconds.unwrap_or(300);
+        let start = std::time::Instant::now();
+
+        loop {
+            if start.elapsed().as_secs() > max_wait {
+                return Err(Error::Transaction(format!(
+                    "Operation {} timed out after {} seconds",
+                    operation_id, max_wait
+                )));
+            }
+
+            let results = self.z_getoperationresult(operation_id).await?;
+
+            for result in results {
+                if let Some(status) = result.get("status") {
+                    if status == "success" {
+                        if let Some(txid) = result
+                            .get("result")
+                            .and_then(|r| r.get("txid"))
+                            .or_else(|| result.get("txid"))
+                            .and_then(|t| t.as_str())
+                        {
+                            return Ok(txid.to_string());
+                        }
+                    } else if status == "failed" {
+                        let error = result
+                            .get("error")
+                            .and_then(|e| e.get("message").or(Some(e)))
+                            .and_then(|e| e.as_str())
+                            .unwrap_or("Unknown error");
+                        return Err(Error::Transaction(format!(
+                            "Operation {} failed: {}",
+                            operation_id, error
+                        )));
+                    }
+                }
+            }
+
+            // Wait before polling again
+            sleep(Duration::from_secs(2)).await;
+        }
+    }
+
     /// List all pending z_sendmany operations.
     pub async fn z_listoperationids(&self) -> Result<Vec<String>> {
         self.call("z_listoperationids", serde_json::json!([])).await
@@ -334,6 +942,90 @@ impl RpcClient {
         self.call("z_listreceivedbyaddress", params).await
     }
 
+    /// List unspent shielded notes.
+    ///
+    /// # Arguments
+    /// * `minconf` - Minimum confirmations (default: 1)
+    /// * `maxconf` - Maximum confirmations (default: 9999999)
+    /// * `include_watchonly` - Include notes for watch-only addresses
+    /// * `addresses` - Only return notes received by these addresses (empty for all)
+    pub async fn z_listunspent(
+        &self,
+        minconf: Option<u32>,
+        maxconf: Option<u32>,
+        include_watchonly: bool,
+        addresses: &[String],
+    ) -> Result<Vec<UnspentNote>> {
+        let params = serde_json::json!([
+            minconf.unwrap_or(1),
+            maxconf.unwrap_or(9_999_999),
+            include_watchonly,
+            addresses,
+        ]);
+        self.call("z_listunspent", params).await
+    }
+
+    /// List unspent transparent outputs (Bitcoin-compatible).
+    ///
+    /// # Arguments
+    /// * `minconf` - Minimum confirmations (default: 1)
+    /// * `maxconf` - Maximum confirmations (default: 9999999)
+    /// * `addresses` - Only return outputs paying these addresses (empty for all)
+    pub async fn listunspent(
+        &self,
+        minconf: Option<u32>,
+        maxconf: Option<u32>,
+        addresses: &[String],
+    ) -> Result<Vec<UnspentOutput>> {
+        let params = serde_json::json!([
+            minconf.unwrap_or(1),
+            maxconf.unwrap_or(9_999_999),
+            addresses,
+        ]);
+        self.call("listunspent", params).await
+    }
+
+    // ============================================================================
+    // Address Index Methods (zcashd with -insightexplorer)
+    // ============================================================================
+
+    /// Get the balance of transparent addresses.
+    pub async fn get_address_balance(&self, addresses: &[String]) -> Result<AddressBalance> {
+        self.require_address_index().await?;
+        self.call(
+            "getaddressbalance",
+            serde_json::json!([{ "addresses": addresses }]),
+        )
+        .await
+    }
+
+    /// Get all balance changes for transparent addresses.
+    ///
+    /// # Arguments
+    /// * `addresses` - Transparent addresses to query
+    /// * `range` - Optional inclusive (start, end) block height range
+    pub async fn get_address_deltas(
+        &self,
+        addresses: &[String],
+        range: Option<(u64, u64)>,
+    ) -> Result<Vec<AddressDelta>> {
+        self.require_address_index().await?;
+        let mut query = serde_json::json!({ "addresses": addresses });
+        if let Some((start, end)) = range {
+            query["start"] = serde_json::json!(start);
+            query["end"] = serde_json::json!(end);
+        }
+        self.call("getaddressdeltas", serde_json::json!([query])).await
+    }
+
+    async fn require_address_index(&self) -> Result<()> {
+        self.require(
+            |c| c.address_index,
+            "address index RPCs (start zcashd with -insightexplorer or -lightwalletd)",
+        )
+        .await
+    }
+
     // ============================================================================
     // Convenience Methods (Backward Compatibility)
     // ============================================================================
@@ -347,3 +1039,155 @@ impl RpcClient {
         self.z_getbalance(address, None).await
     }
 }
+
+/// JSON-RPC error code of an [`Error::Rpc`] returned by [`RpcClient`]
+pub fn rpc_error_code(error: &Error) -> Option<i64> {
+    match error {
+        Error::Rpc(message) => message
+            .strip_prefix("RPC error ")?
+            .split(':')
+            .next()?
+            .parse()
+            .ok(),
+        _ => None,
+    }
+}
+
+/// Match the responses of a batch to its requests
+///
+/// Servers may answer batch calls in any order, so responses are placed by
+/// ID; request `i` has ID `first_id + i`.
+fn order_batch<T>(first_id: u64, len: usize, responses: Vec<RpcResponse<T>>) -> Result<Vec<T>> {
+    let mut results: Vec<Option<T>> = std::iter::repeat_with(|| None).take(len).collect();
+    for response in responses {
+        if let Some(error) = response.error {
+            return Err(Error::Rpc(format!(
+                "RPC error {}: {}",
+                error.code, error.message
+            )));
+        }
+        let slot = response
+            .id
+            .checked_sub(first_id)
+            .and_then(|i| results.get_mut(usize::try_from(i).ok()?))
+            .ok_or_else(|| Error::Rpc(format!("Unexpected batch response ID {}", response.id)))?;
+        *slot = response.result;
+    }
+    results
+        .into_iter()
+        .map(|result| result.ok_or_else(|| Error::Rpc("Batch response missing result".to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_error_code() {
+        let warmup = Error::Rpc("RPC error -28: Loading block index...".to_string());
+        assert_eq!(rpc_error_code(&warmup), Some(RPC_IN_WARMUP));
+        let status = Error::Rpc("RPC request failed with status: 401".to_string());
+        assert_eq!(rpc_error_code(&status), None);
+        let wallet = Error::Wallet("RPC error -28: x".to_string());
+        assert_eq!(rpc_error_code(&wallet), None);
+    }
+
+    #[test]
+    fn test_capabilities_from_info() {
+        let zcashd = serde_json::json!({ "version": 5_060_050, "subversion": "/MagicBean:5.6.0/" });
+        let caps = Capabilities::from_info(&zcashd, true);
+        assert!(caps.zcashd && caps.unified_accounts && caps.privacy_policy && caps.address_index);
+
+        let old = serde_json::json!({ "version": 4_070_050, "subversion": "/MagicBean:4.7.0/" });
+        let caps = Capabilities::from_info(&old, false);
+        assert!(caps.unified_accounts && !caps.privacy_policy && !caps.address_index);
+
+        let zebra = serde_json::json!({ "build": "v1.6.0", "subversion": "/Zebra:1.6.0/" });
+        let caps = Capabilities::from_info(&zebra, true);
+        assert!(!caps.zcashd && !caps.unified_accounts && !caps.privacy_policy);
+        assert_eq!(caps.version, 0);
+    }
+
+    #[test]
+    fn test_order_batch() {
+        let responses: Vec<RpcResponse<u64>> = serde_json::from_value(serde_json::json!([
+            { "jsonrpc": "2.0", "id": 12, "result": 300 },
+            { "jsonrpc": "2.0", "id": 10, "result": 100 },
+            { "jsonrpc": "2.0", "id": 11, "result": 200 },
+        ]))
+        .unwrap();
+        assert_eq!(order_batch(10, 3, responses).unwrap(), vec![100, 200, 300]);
+
+        let failed: Vec<RpcResponse<u64>> = serde_json::from_value(serde_json::json!([
+            { "jsonrpc": "2.0", "id": 10, "result": 100 },
+            { "jsonrpc": "2.0", "id": 11, "error": { "code": -8, "message": "Block height out of range" } },
+        ]))
+        .unwrap();
+        assert_eq!(
+            rpc_error_code(&order_batch(10, 2, failed).unwrap_err()),
+            Some(-8)
+        );
+
+        let missing: Vec<RpcResponse<u64>> = serde_json::from_value(serde_json::json!([
+            { "jsonrpc": "2.0", "id": 10, "result": 100 },
+        ]))
+        .unwrap();
+        assert!(order_batch(10, 2, missing).is_err());
+    }
+
+    #[test]
+    fn test_block_template() {
+        let template: BlockTemplate = serde_json::from_value(serde_json::json!({
+            "capabilities": ["proposal"],
+            "version": 4,
+            "previousblockhash": "0029",
+            "blockcommitmentshash": "ab01",
+            "defaultroots": {
+                "merkleroot": "01",
+                "chainhistoryroot": "02",
+                "authdataroot": "03",
+                "blockcommitmentshash": "ab01"
+            },
+            "transactions": [
+                {
+                    "data": "0500", "hash": "aa", "authdigest": "bb",
+                    "depends": [], "fee": 10000, "sigops": 1
+                }
+            ],
+            "coinbasetxn": {
+                "data": "0400", "hash": "cc",
+                "depends": [], "fee": -10000, "sigops": 1, "required": true
+            },
+            "longpollid": "00012a",
+            "target": "0f0f",
+            "mintime": 1_700_000_000u64,
+            "mutable": ["time", "transactions", "prevblock"],
+            "noncerange": "00000000ffffffff",
+            "sigoplimit": 20000,
+            "sizelimit": 2000000,
+            "curtime": 1_700_000_100u64,
+            "bits": "200f0f0f",
+            "height": 42
+        }))
+        .unwrap();
+        assert_eq!(template.height, 42);
+        assert_eq!(template.default_roots.auth_data_root, "03");
+        assert!(template.coinbase_txn.required);
+        assert_eq!(template.transactions[0].fee, 10_000);
+        assert_eq!(template.long_poll_id.as_deref(), Some("00012a"));
+
+        assert_eq!(
+            SubmitBlockOutcome::from_result(None),
+            SubmitBlockOutcome::Accepted
+        );
+        assert_eq!(
+            SubmitBlockOutcome::from_result(Some("duplicate")),
+            SubmitBlockOutcome::Duplicate
+        );
+        assert_eq!(
+            SubmitBlockOutcome::from_result(Some("high-hash")),
+            SubmitBlockOutcome::Rejected("high-hash".to_string())
+        );
+    }
+}
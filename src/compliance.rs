@@ -3,14 +3,17 @@
 //! This module provides:
 //! - Exportable viewing keys for compliance reviews
 //! - Redaction utilities for safe logging/sharing
-//! - CSV export for audit/reporting workflows
+//! - CSV export for audit/reporting workflows, optionally with historical
+//!   fiat values (see [`crate::price_history`])
 //
 use crate::error::{Error, Result};
+use crate::params::NetworkParams;
+use crate::price_history::FiatValue;
 use crate::types::Transaction;
 use crate::wallet::Wallet;
+use std::collections::HashMap;
 use zcash_keys::encoding::AddressCodec;
 use zcash_keys::keys::UnifiedFullViewingKey;
-use zcash_protocol::consensus::{MainNetwork, TestNetwork};
 use zcash_transparent::keys::IncomingViewingKey;
 use zip32::DiversifierIndex;
 //
@@ -29,11 +32,9 @@ pub fn export_viewing_keys(wallet: &Wallet) -> Result<ExportedViewingKeys> {
 	let ufvk: UnifiedFullViewingKey = wallet
 		.unified_full_viewing_key()
 		.map_err(|e| Error::KeyDerivation(format!("Failed to get UFVK: {}", e)))?;
+	let params = NetworkParams::from(wallet.network());
 	//
-	let ufvk_str = match wallet.network() {
-		crate::types::Network::Mainnet => ufvk.encode(&MainNetwork),
-		crate::types::Network::Testnet | crate::types::Network::Regtest => ufvk.encode(&TestNetwork),
-	};
+	let ufvk_str = ufvk.encode(&params);
 	//
 	// Sapling DFVK (encode representative address for attestation)
 	let sapling_fvk = ufvk.sapling().map(|dfvk| {
@@ -41,10 +42,7 @@ pub fn export_viewing_keys(wallet: &Wallet) -> Result<ExportedViewingKeys> {
 		dfvk
 			.address(DiversifierIndex::new())
 			.and_then(|addr| {
-				Some(match wallet.network() {
-					crate::types::Network::Mainnet => addr.encode(&MainNetwork),
-					crate::types::Network::Testnet | crate::types::Network::Regtest => addr.encode(&TestNetwork),
-				})
+				Some(addr.encode(&params))
 			})
 	}).flatten();
 	//
@@ -52,10 +50,7 @@ pub fn export_viewing_keys(wallet: &Wallet) -> Result<ExportedViewingKeys> {
 	let transparent_ivk = ufvk.transparent().and_then(|dfvk| {
 		let external_ivk = dfvk.derive_external_ivk().ok()?;
 		let (addr, _) = external_ivk.default_address();
-		Some(match wallet.network() {
-			crate::types::Network::Mainnet => addr.encode(&MainNetwork),
-			crate::types::Network::Testnet | crate::types::Network::Regtest => addr.encode(&TestNetwork),
-		})
+		Some(addr.encode(&params))
 	});
 	//
 	Ok(ExportedViewingKeys {
@@ -83,19 +78,47 @@ pub fn redact_middle(input: &str, keep_start: usize, keep_end: usize) -> String
 pub fn export_transactions_csv(transactions: &[Transaction]) -> String {
 	let mut out = String::from("txid,status,height,amount_zec,fee_zec,memo\n");
 	for tx in transactions {
-		let (status, height) = match &tx.status {
-			crate::types::TransactionStatus::Pending => ("pending".to_string(), "".to_string()),
-			crate::types::TransactionStatus::Confirmed { height } => ("confirmed".to_string(), height.to_string()),
-			crate::types::TransactionStatus::Rejected => ("rejected".to_string(), "".to_string()),
-		};
-		let amount_zec = (tx.amount as f64) / 100_000_000.0;
-		let fee_zec = (tx.fee as f64) / 100_000_000.0;
-		let memo = tx.memo.clone().unwrap_or_default().replace(',', ";");
-		out.push_str(&format!("{},{},{},{:.8},{:.8},{}\n", tx.txid, status, height, amount_zec, fee_zec, memo));
+		out.push_str(&transaction_csv_row(tx));
+		out.push('\n');
+	}
+	out
+}
+//
+/// Export transactions to the audit CSV with their historical fiat values.
+///
+/// Adds fiat_currency, fiat_price and fiat_value columns to
+/// [`export_transactions_csv`], filled from values stored by a
+/// [`PriceBackfill`](crate::price_history::PriceBackfill) (see
+/// [`PriceHistory::values`](crate::price_history::PriceHistory::values)) and
+/// left empty for transactions that were not priced.
+pub fn export_transactions_csv_with_fiat(
+	transactions: &[Transaction],
+	currency: &str,
+	values: &HashMap<String, FiatValue>,
+) -> String {
+	let mut out = String::from("txid,status,height,amount_zec,fee_zec,memo,fiat_currency,fiat_price,fiat_value\n");
+	for tx in transactions {
+		let row = transaction_csv_row(tx);
+		match values.get(&tx.txid) {
+			Some(value) => out.push_str(&format!("{},{},{:.2},{:.2}\n", row, currency, value.price, value.value)),
+			None => out.push_str(&format!("{},{},,\n", row, currency)),
+		}
 	}
 	out
 }
 //
+fn transaction_csv_row(tx: &Transaction) -> String {
+	let (status, height) = match &tx.status {
+		crate::types::TransactionStatus::Pending => ("pending".to_string(), "".to_string()),
+		crate::types::TransactionStatus::Confirmed { height } => ("confirmed".to_string(), height.to_string()),
+		crate::types::TransactionStatus::Rejected => ("rejected".to_string(), "".to_string()),
+	};
+	let amount_zec = (tx.amount as f64) / 100_000_000.0;
+	let fee_zec = (tx.fee as f64) / 100_000_000.0;
+	let memo = tx.memo.clone().unwrap_or_default().replace(',', ";");
+	format!("{},{},{},{:.8},{:.8},{}", tx.txid, status, height, amount_zec, fee_zec, memo)
+}
+//
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -0,0 +1,60 @@
+//! Correlation IDs for sends
+//!
+//! A payout passes through several steps — validation, fee estimation,
+//! submission, the zcashd operation, the resulting txid and its
+//! confirmations — that are logged separately and often from different
+//! tasks. Each send made through
+//! [`TransactionBuilder`](crate::transaction::TransactionBuilder) gets a
+//! [`CorrelationId`], which is recorded as the `correlation_id` field of a
+//! `send` tracing span around every step and stored with the send's
+//! [`AuditLog`](crate::audit::AuditLog) entries, so a single payout can be
+//! followed across logs with one search.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Identifier shared by every log event and audit entry of one send
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CorrelationId(String);
+
+impl CorrelationId {
+    /// Generate a random ID (16 hex digits)
+    pub fn new() -> Self {
+        Self(hex::encode(rand::random::<[u8; 8]>()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Tracing span for the steps of the send with this ID
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!("send", correlation_id = %self.0)
+    }
+}
+
+impl Default for CorrelationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Use an existing identifier, e.g. the caller's payout ID
+impl From<String> for CorrelationId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&str> for CorrelationId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,197 @@
+//! Transaction history aggregated by counterparty
+//!
+//! Compliance reviews and business analytics ask "how much did we pay to or
+//! receive from whom, and when". [`read_counterparties`] groups an account's
+//! payments by counterparty and reports totals and first/last activity.
+//!
+//! Payments sent are grouped by recipient address. Shielded payments do not
+//! reveal their sender, so payments received are grouped by the wallet
+//! address they arrived at — e.g. the deposit address handed to a customer.
+//! Addresses saved as [`Contacts`](crate::wallet::contacts::Contacts) are
+//! grouped under the contact's label. Change and transfers between the
+//! wallet's own addresses are left out, as are expired transactions.
+
+use crate::db_encryption::open_connection;
+use crate::error::Result;
+use crate::store::db_error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+/// Payments to and from one counterparty
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterpartyActivity {
+    /// Contact label, or the address if it is not a contact's
+    pub counterparty: String,
+    /// Whether [`counterparty`](Self::counterparty) is a contact label
+    pub is_contact: bool,
+    /// Addresses grouped under the counterparty
+    pub addresses: Vec<String>,
+    /// Total sent to the counterparty (zatoshis, excluding fees)
+    pub total_sent: u64,
+    /// Total received from the counterparty (zatoshis)
+    pub total_received: u64,
+    /// Number of transactions paying the counterparty
+    pub transactions_sent: usize,
+    /// Number of transactions received from the counterparty
+    pub transactions_received: usize,
+    /// Block time (Unix seconds) of the first mined payment
+    pub first_activity: Option<u64>,
+    /// Block time (Unix seconds) of the last mined payment
+    pub last_activity: Option<u64>,
+}
+
+/// One payment output to or from the account
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PaymentRow {
+    txid: Vec<u8>,
+    address: String,
+    value: u64,
+    sent: bool,
+    block_time: Option<u64>,
+}
+
+const DB_CONTEXT: &str = "Counterparty report error";
+
+/// Aggregate an account's payments by counterparty
+///
+/// # Arguments
+/// * `path` - Wallet database path
+/// * `account_index` - ZIP-32 index of the account
+/// * `contacts` - Contact label of each contact address
+///
+/// # Returns
+/// One entry per counterparty, largest total volume first
+pub fn read_counterparties(
+    path: &Path,
+    account_index: u32,
+    contacts: &HashMap<String, String>,
+) -> Result<Vec<CounterpartyActivity>> {
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT o.txid, o.to_address, o.value, o.from_account_uuid IS a.uuid,
+                    t.block_time
+             FROM v_tx_outputs o
+             JOIN accounts a ON a.hd_account_index = ?1
+             JOIN v_transactions t ON t.txid = o.txid AND t.account_uuid = a.uuid
+             WHERE NOT o.is_change AND o.to_address IS NOT NULL
+               AND NOT COALESCE(t.expired_unmined, 0)
+               AND (o.from_account_uuid IS a.uuid) != (o.to_account_uuid IS a.uuid)",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+    let rows = stmt
+        .query_map([account_index], |row| {
+            Ok(PaymentRow {
+                txid: row.get(0)?,
+                address: row.get(1)?,
+                value: row.get::<_, i64>(2)? as u64,
+                sent: row.get(3)?,
+                block_time: row.get::<_, Option<i64>>(4)?.map(|time| time as u64),
+            })
+        })
+        .map_err(db_error(DB_CONTEXT))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_error(DB_CONTEXT))?;
+    Ok(aggregate(&rows, contacts))
+}
+
+fn aggregate(rows: &[PaymentRow], contacts: &HashMap<String, String>) -> Vec<CounterpartyActivity> {
+    let mut groups: BTreeMap<String, CounterpartyActivity> = BTreeMap::new();
+    let mut seen: HashSet<(&str, &[u8], bool)> = HashSet::new();
+    for row in rows {
+        let contact = contacts.get(&row.address);
+        let key = contact.unwrap_or(&row.address);
+        let activity = groups
+            .entry(key.clone())
+            .or_insert_with(|| CounterpartyActivity {
+                counterparty: key.clone(),
+                is_contact: contact.is_some(),
+                ..Default::default()
+            });
+        if !activity.addresses.contains(&row.address) {
+            activity.addresses.push(row.address.clone());
+        }
+        // A transaction can pay several outputs to the same counterparty
+        let new_tx = seen.insert((key.as_str(), row.txid.as_slice(), row.sent));
+        if row.sent {
+            activity.total_sent = activity.total_sent.saturating_add(row.value);
+            activity.transactions_sent += usize::from(new_tx);
+        } else {
+            activity.total_received = activity.total_received.saturating_add(row.value);
+            activity.transactions_received += usize::from(new_tx);
+        }
+        if let Some(time) = row.block_time {
+            activity.first_activity = Some(activity.first_activity.map_or(time, |t| t.min(time)));
+            activity.last_activity = Some(activity.last_activity.map_or(time, |t| t.max(time)));
+        }
+    }
+
+    let mut activities: Vec<CounterpartyActivity> = groups.into_values().collect();
+    activities.sort_by_key(|activity| {
+        std::cmp::Reverse(activity.total_sent.saturating_add(activity.total_received))
+    });
+    activities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_counterparties() {
+        let path = TempDb::new();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE accounts (uuid BLOB, hd_account_index INTEGER);
+             CREATE TABLE v_transactions (txid BLOB, account_uuid BLOB, block_time INTEGER,
+                                          expired_unmined INTEGER);
+             CREATE TABLE v_tx_outputs (txid BLOB, from_account_uuid BLOB,
+                                        to_account_uuid BLOB, to_address TEXT,
+                                        value INTEGER, is_change INTEGER);
+             INSERT INTO accounts VALUES (x'aa', 0);
+             INSERT INTO v_transactions VALUES (x'01', x'aa', 1000, 0);
+             INSERT INTO v_transactions VALUES (x'02', x'aa', 3000, 0);
+             INSERT INTO v_transactions VALUES (x'03', x'aa', NULL, 1);
+             INSERT INTO v_transactions VALUES (x'04', x'aa', 2000, 0);
+             -- Two outputs to bob in one transaction, plus change
+             INSERT INTO v_tx_outputs VALUES (x'01', x'aa', NULL, 'zs1bob', 300, 0);
+             INSERT INTO v_tx_outputs VALUES (x'01', x'aa', NULL, 'zs1bob', 200, 0);
+             INSERT INTO v_tx_outputs VALUES (x'01', x'aa', x'aa', 'zs1self', 900, 1);
+             -- Received at a deposit address
+             INSERT INTO v_tx_outputs VALUES (x'02', NULL, x'aa', 'u1deposit', 1500, 0);
+             -- Expired
+             INSERT INTO v_tx_outputs VALUES (x'03', x'aa', NULL, 'zs1bob', 7, 0);
+             -- Bob's second address, and a transfer between own addresses
+             INSERT INTO v_tx_outputs VALUES (x'04', x'aa', NULL, 't1bob', 100, 0);
+             INSERT INTO v_tx_outputs VALUES (x'04', x'aa', x'aa', 'u1deposit', 50, 0);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let contacts = HashMap::from([
+            ("zs1bob".to_string(), "Bob".to_string()),
+            ("t1bob".to_string(), "Bob".to_string()),
+        ]);
+        let report = read_counterparties(&path, 0, &contacts).unwrap();
+        assert_eq!(report.len(), 2);
+
+        assert_eq!(report[0].counterparty, "u1deposit");
+        assert!(!report[0].is_contact);
+        assert_eq!(report[0].total_received, 1500);
+        assert_eq!(report[0].transactions_received, 1);
+
+        let bob = &report[1];
+        assert_eq!(bob.counterparty, "Bob");
+        assert!(bob.is_contact);
+        assert_eq!(bob.addresses, vec!["zs1bob", "t1bob"]);
+        assert_eq!(bob.total_sent, 600);
+        assert_eq!(bob.transactions_sent, 2);
+        assert_eq!(bob.first_activity, Some(1000));
+        assert_eq!(bob.last_activity, Some(2000));
+
+        assert!(read_counterparties(&path, 1, &contacts).unwrap().is_empty());
+    }
+}
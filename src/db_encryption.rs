@@ -0,0 +1,223 @@
+//! Encrypted wallet databases
+//!
+//! By default the wallet database is a plain SQLite file: anyone who can read
+//! it sees the wallet's notes, transactions, memos and addresses (keys are
+//! sealed separately, see [`crate::wallet::key_store`]). With the `sqlcipher`
+//! feature the SDK is built against SQLCipher, which encrypts every page of
+//! the file with a key derived from a passphrase, e.g. for wallets on mobile
+//! devices.
+//!
+//! Register the passphrase of a database before opening a wallet on it;
+//! every connection the SDK opens to that path (the wallet's own and its
+//! stores, such as the address book) is then keyed with it:
+//!
+//! ```no_run
+//! # fn example() -> zcash_numi_sdk::Result<()> {
+//! use zcash_numi_sdk::db_encryption::set_database_passphrase;
+//! use zcash_numi_sdk::wallet::Wallet;
+//! use std::path::PathBuf;
+//!
+//! let path = PathBuf::from("wallet.db");
+//! set_database_passphrase(&path, "correct horse battery staple")?;
+//! let wallet = Wallet::with_path(path)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! An existing plain database is converted with [`encrypt_database`].
+//! Snapshots cannot be created from or installed into encrypted databases.
+
+use crate::error::{Error, Result};
+use crate::store::db_error;
+use rusqlite::Connection;
+use secrecy::{ExposeSecret, SecretString};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Passphrases of encrypted databases by absolute path
+static PASSPHRASES: OnceLock<Mutex<HashMap<PathBuf, SecretString>>> = OnceLock::new();
+
+const DB_CONTEXT: &str = "Database encryption error";
+
+fn passphrases() -> std::sync::MutexGuard<'static, HashMap<PathBuf, SecretString>> {
+    PASSPHRASES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// The same database file can be opened through relative and absolute paths
+fn registry_key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn require_sqlcipher() -> Result<()> {
+    if !cfg!(feature = "sqlcipher") {
+        return Err(Error::InvalidParameter(
+            "Database encryption requires the SDK's sqlcipher feature".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(Error::InvalidParameter(
+            "Database passphrase must not be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Key the connection if its database is encrypted, and check the key
+fn apply_key(conn: &Connection, passphrase: &SecretString) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "key", passphrase.expose_secret())?;
+    // SQLCipher only reads the file, and so checks the key, on first use
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))
+}
+
+/// Open a connection to the database at `path`, keyed with its registered
+/// passphrase if it has one
+///
+/// Every SDK connection to a wallet database is opened through this.
+pub(crate) fn open_connection(path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    if let Some(passphrase) = passphrases().get(&registry_key(path)) {
+        apply_key(&conn, passphrase)?;
+    }
+    Ok(conn)
+}
+
+/// Register the passphrase of the encrypted database at `path`
+///
+/// Connections the SDK opens to `path` from then on are keyed with it. A new
+/// database created at `path` is encrypted. Fails with
+/// [`Error::Database`] if the file exists and the passphrase does not open
+/// it.
+///
+/// # Arguments
+/// * `path` - Wallet database path
+/// * `passphrase` - Passphrase; SQLCipher derives the key with PBKDF2
+pub fn set_database_passphrase(path: &Path, passphrase: &str) -> Result<()> {
+    require_sqlcipher()?;
+    check_passphrase(passphrase)?;
+    let passphrase = SecretString::new(passphrase.to_string());
+    if path.exists() {
+        let conn = Connection::open(path).map_err(db_error(DB_CONTEXT))?;
+        apply_key(&conn, &passphrase).map_err(|_| {
+            Error::Database(format!(
+                "Passphrase does not open the database {}",
+                path.display()
+            ))
+        })?;
+    }
+    passphrases().insert(registry_key(path), passphrase);
+    Ok(())
+}
+
+/// Forget the passphrase registered for `path`
+///
+/// # Returns
+/// Whether a passphrase was registered
+pub fn forget_database_passphrase(path: &Path) -> bool {
+    passphrases().remove(&registry_key(path)).is_some()
+}
+
+/// Whether a passphrase is registered for the database at `path`
+pub fn is_encrypted(path: &Path) -> bool {
+    passphrases().contains_key(&registry_key(path))
+}
+
+/// Write an encrypted copy of the plain database at `source` to `dest`
+///
+/// Close every connection to `source` first, e.g. by dropping the wallet,
+/// then open the wallet on `dest` after registering the passphrase with
+/// [`set_database_passphrase`]. `source` is left unchanged; delete it once
+/// the copy has been checked, as it still holds the data unencrypted.
+pub fn encrypt_database(source: &Path, dest: &Path, passphrase: &str) -> Result<()> {
+    require_sqlcipher()?;
+    check_passphrase(passphrase)?;
+    if dest.exists() {
+        return Err(Error::InvalidParameter(format!(
+            "{} already exists",
+            dest.display()
+        )));
+    }
+    let dest_str = dest
+        .to_str()
+        .ok_or_else(|| Error::InvalidParameter("Database path is not valid UTF-8".to_string()))?;
+    let conn = Connection::open(source).map_err(db_error(DB_CONTEXT))?;
+    conn.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        [dest_str, passphrase],
+    )
+    .map_err(db_error(DB_CONTEXT))?;
+    conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+        .map_err(db_error(DB_CONTEXT))?;
+    conn.execute("DETACH DATABASE encrypted", [])
+        .map_err(db_error(DB_CONTEXT))?;
+    Ok(())
+}
+
+/// Re-encrypt the database at `path` under a new passphrase
+///
+/// The database's current passphrase must be registered; the new one
+/// replaces it.
+pub fn change_database_passphrase(path: &Path, new_passphrase: &str) -> Result<()> {
+    require_sqlcipher()?;
+    check_passphrase(new_passphrase)?;
+    if !is_encrypted(path) {
+        return Err(Error::InvalidParameter(format!(
+            "No passphrase is registered for {}",
+            path.display()
+        )));
+    }
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    conn.pragma_update(None, "rekey", new_passphrase)
+        .map_err(db_error(DB_CONTEXT))?;
+    passphrases().insert(
+        registry_key(path),
+        SecretString::new(new_passphrase.to_string()),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_database_passphrases() {
+        let path = TempDb::new();
+        if !cfg!(feature = "sqlcipher") {
+            assert!(set_database_passphrase(&path, "secret").is_err());
+            assert!(!is_encrypted(&path));
+            return;
+        }
+
+        set_database_passphrase(&path, "secret").unwrap();
+        assert!(is_encrypted(&path));
+        open_connection(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (7);")
+            .unwrap();
+        // Unkeyed connections cannot read the file
+        assert!(Connection::open(&path)
+            .unwrap()
+            .query_row("SELECT x FROM t", [], |row| row.get::<_, i64>(0))
+            .is_err());
+
+        change_database_passphrase(&path, "new secret").unwrap();
+        assert!(forget_database_passphrase(&path));
+        assert!(set_database_passphrase(&path, "secret").is_err());
+        set_database_passphrase(&path, "new secret").unwrap();
+        let x: i64 = open_connection(&path)
+            .unwrap()
+            .query_row("SELECT x FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(x, 7);
+        forget_database_passphrase(&path);
+    }
+}
@@ -0,0 +1,562 @@
+//! Exchange deposit detection
+//!
+//! This module implements the deposit loop most exchanges and custodial
+//! services end up writing themselves:
+//! - Map deposit addresses (diversified UAs or transparent addresses) to external user IDs
+//! - Follow chain and payment [`WalletEvent`]s from sync or the RPC watcher
+//! - Apply a confirmation policy that can require more confirmations for larger
+//!   amounts, as named profiles such as "retail" and "high-value"
+//! - Emit "credit user X with Y zatoshis" events carrying an idempotency key
+//!
+//! Each payment output is credited at most once per detector. The idempotency
+//! key is derived from the transaction output, so a downstream ledger can also
+//! reject duplicates after a restart.
+//!
+//! When a [`WalletEvent::Reorg`] disconnects the block of a deposit credited
+//! within the last [`MAX_REORG_DEPTH`] blocks, the detector reports it as
+//! [`DepositEvent::Reorged`] and re-verifies its confirmations: once it is
+//! mined again and reaches its target, [`DepositEvent::Reconfirmed`] follows.
+
+use crate::error::Result;
+use crate::events::{ReceivedPayment, WalletEvent, MAX_REORG_DEPTH};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{broadcast, mpsc};
+
+/// Default number of confirmations before a deposit is credited
+pub const DEFAULT_DEPOSIT_CONFIRMATIONS: u32 = 10;
+
+/// Name reported for requirements that were not given a profile name
+pub const DEFAULT_PROFILE_NAME: &str = "default";
+
+/// A named confirmation requirement, e.g. "retail: 3 confirmations"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationProfile {
+    pub name: String,
+    /// Confirmations required before crediting
+    pub confirmations: u32,
+}
+
+impl ConfirmationProfile {
+    /// Create a profile
+    pub fn new(name: impl Into<String>, confirmations: u32) -> Self {
+        Self {
+            name: name.into(),
+            confirmations,
+        }
+    }
+
+    /// "retail": 3 confirmations, for everyday amounts
+    pub fn retail() -> Self {
+        Self::new("retail", 3)
+    }
+
+    /// "high-value": 10 confirmations, for amounts worth a reorg attack
+    pub fn high_value() -> Self {
+        Self::new("high-value", 10)
+    }
+}
+
+/// Confirmation requirement that applies to deposits of at least `min_amount`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationTier {
+    /// Minimum deposit amount (zatoshis) for this tier to apply
+    pub min_amount: u64,
+    /// Confirmations required before crediting
+    pub confirmations: u32,
+    /// Profile name, if the tier was added as a profile
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// Confirmation policy keyed on deposit amount
+///
+/// The tier with the highest `min_amount` not exceeding the deposit amount
+/// applies; smaller deposits use the default requirement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationPolicy {
+    default_confirmations: u32,
+    tiers: Vec<ConfirmationTier>,
+    /// Profile name of the default requirement
+    #[serde(default)]
+    default_profile: Option<String>,
+}
+
+impl ConfirmationPolicy {
+    /// Create a policy with a single confirmation requirement
+    pub fn new(default_confirmations: u32) -> Self {
+        Self {
+            default_confirmations,
+            tiers: Vec::new(),
+            default_profile: None,
+        }
+    }
+
+    /// Create a policy whose default requirement is a named profile
+    pub fn from_profile(profile: ConfirmationProfile) -> Self {
+        Self {
+            default_profile: Some(profile.name),
+            ..Self::new(profile.confirmations)
+        }
+    }
+
+    /// The "retail" profile below `high_value_threshold` zatoshis and the
+    /// "high-value" profile from it
+    pub fn retail_and_high_value(high_value_threshold: u64) -> Self {
+        Self::from_profile(ConfirmationProfile::retail())
+            .with_profile(high_value_threshold, ConfirmationProfile::high_value())
+    }
+
+    /// Require `confirmations` for deposits of at least `min_amount` zatoshis
+    pub fn with_tier(self, min_amount: u64, confirmations: u32) -> Self {
+        self.insert_tier(ConfirmationTier {
+            min_amount,
+            confirmations,
+            profile: None,
+        })
+    }
+
+    /// Apply a named profile to deposits of at least `min_amount` zatoshis
+    pub fn with_profile(self, min_amount: u64, profile: ConfirmationProfile) -> Self {
+        self.insert_tier(ConfirmationTier {
+            min_amount,
+            confirmations: profile.confirmations,
+            profile: Some(profile.name),
+        })
+    }
+
+    fn insert_tier(mut self, tier: ConfirmationTier) -> Self {
+        self.tiers.retain(|t| t.min_amount != tier.min_amount);
+        self.tiers.push(tier);
+        self.tiers.sort_by_key(|t| t.min_amount);
+        self
+    }
+
+    /// Number of confirmations required for a deposit of `amount` zatoshis
+    pub fn required_confirmations(&self, amount: u64) -> u32 {
+        self.profile_for(amount).confirmations
+    }
+
+    /// The profile that applies to a deposit of `amount` zatoshis
+    ///
+    /// Requirements added without a name are reported as
+    /// [`DEFAULT_PROFILE_NAME`].
+    pub fn profile_for(&self, amount: u64) -> ConfirmationProfile {
+        let (confirmations, name) = match self.tiers.iter().rev().find(|t| amount >= t.min_amount) {
+            Some(tier) => (tier.confirmations, tier.profile.as_deref()),
+            None => (self.default_confirmations, self.default_profile.as_deref()),
+        };
+        ConfirmationProfile::new(name.unwrap_or(DEFAULT_PROFILE_NAME), confirmations)
+    }
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEPOSIT_CONFIRMATIONS)
+    }
+}
+
+/// A deposit that has been detected but not yet credited
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingDeposit {
+    pub idempotency_key: String,
+    pub user_id: String,
+    pub payment: ReceivedPayment,
+    pub required_confirmations: u32,
+    /// Name of the confirmation profile that applies
+    #[serde(default)]
+    pub profile: String,
+}
+
+/// Instruction to credit a user's balance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credit {
+    /// Stable key for this payment output; apply each key at most once
+    pub idempotency_key: String,
+    pub user_id: String,
+    pub address: String,
+    pub txid: String,
+    pub output_index: u32,
+    /// Amount in zatoshis
+    pub amount: u64,
+    /// Confirmations at the time of crediting
+    pub confirmations: u32,
+    /// Name of the confirmation profile the deposit was credited under
+    #[serde(default)]
+    pub profile: String,
+    pub memo: Option<String>,
+}
+
+/// Events emitted by the [`DepositDetector`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepositEvent {
+    /// A deposit was seen (mempool or block) but has not reached its confirmation target
+    Detected(PendingDeposit),
+    /// A deposit reached its confirmation target and should be credited
+    Credit(Credit),
+    /// The block of a credited deposit was disconnected by a reorg; the
+    /// deposit is unconfirmed until it is mined again
+    Reorged(Credit),
+    /// A [`Reorged`](Self::Reorged) deposit reached its confirmation target
+    /// again; it was already credited and must not be credited twice
+    Reconfirmed(Credit),
+}
+
+/// Build the idempotency key for a payment output
+pub fn idempotency_key(txid: &str, output_index: u32) -> String {
+    format!("{}:{}", txid, output_index)
+}
+
+/// Tracks deposit addresses and turns chain events into credit events
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DepositDetector {
+    policy: ConfirmationPolicy,
+    /// Deposit address -> external user ID
+    addresses: HashMap<String, String>,
+    /// Pending deposits by idempotency key
+    pending: HashMap<String, PendingDeposit>,
+    /// Idempotency keys that have already been credited
+    credited: HashSet<String>,
+    /// Credits within [`MAX_REORG_DEPTH`] blocks of the tip, with the height
+    /// they were mined at
+    #[serde(default)]
+    recent_credits: HashMap<String, (Credit, u64)>,
+    /// Keys of credited deposits being re-verified after a reorg
+    #[serde(default)]
+    reverifying: HashSet<String>,
+    tip: Option<u64>,
+}
+
+impl DepositDetector {
+    /// Create a detector with the given confirmation policy
+    pub fn new(policy: ConfirmationPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Assign a deposit address to an external user ID
+    pub fn register_address(&mut self, address: impl Into<String>, user_id: impl Into<String>) {
+        self.addresses.insert(address.into(), user_id.into());
+    }
+
+    /// Stop tracking a deposit address
+    pub fn unregister_address(&mut self, address: &str) -> Option<String> {
+        self.addresses.remove(address)
+    }
+
+    /// Look up the user ID a deposit address belongs to
+    pub fn user_for_address(&self, address: &str) -> Option<&str> {
+        self.addresses.get(address).map(String::as_str)
+    }
+
+    /// Record an idempotency key as already credited (e.g. when restoring from a ledger)
+    pub fn mark_credited(&mut self, idempotency_key: impl Into<String>) {
+        let key = idempotency_key.into();
+        self.pending.remove(&key);
+        self.credited.insert(key);
+    }
+
+    /// Deposits seen but not yet credited
+    pub fn pending(&self) -> impl Iterator<Item = &PendingDeposit> {
+        self.pending.values()
+    }
+
+    /// The confirmation policy in use
+    pub fn policy(&self) -> &ConfirmationPolicy {
+        &self.policy
+    }
+
+    /// Confirmations for a deposit at `height` given the current tip
+    fn confirmations(&self, height: Option<u64>) -> u32 {
+        match (height, self.tip) {
+            (Some(h), Some(tip)) if tip >= h => (tip - h + 1).min(u32::MAX as u64) as u32,
+            _ => 0,
+        }
+    }
+
+    /// Process a single event, returning any resulting deposit events
+    pub fn handle_event(&mut self, event: &WalletEvent) -> Vec<DepositEvent> {
+        match event {
+            WalletEvent::ChainTip { height } => {
+                self.tip = Some(*height);
+                self.credit_matured()
+            }
+            WalletEvent::BlocksScanned { end_height, .. } => {
+                if self.tip.is_none_or(|tip| *end_height > tip) {
+                    self.tip = Some(*end_height);
+                }
+                self.credit_matured()
+            }
+            WalletEvent::PaymentReceived(payment) => self.handle_payment(payment),
+            WalletEvent::Reorg {
+                depth,
+                old_tip,
+                new_tip,
+            } => self.handle_reorg(old_tip.saturating_sub(*depth), *new_tip),
+            WalletEvent::BandwidthCapReached { .. } => Vec::new(),
+        }
+    }
+
+    /// Return deposits mined above `fork_height` to unconfirmed, reporting
+    /// credited ones as [`DepositEvent::Reorged`]
+    fn handle_reorg(&mut self, fork_height: u64, new_tip: u64) -> Vec<DepositEvent> {
+        self.tip = Some(new_tip);
+        for pending in self.pending.values_mut() {
+            if pending.payment.height.is_some_and(|h| h > fork_height) {
+                pending.payment.height = None;
+            }
+        }
+
+        let reorged: Vec<String> = self
+            .recent_credits
+            .iter()
+            .filter(|(_, (_, height))| *height > fork_height)
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut events = Vec::new();
+        for key in reorged {
+            let Some((credit, _)) = self.recent_credits.remove(&key) else {
+                continue;
+            };
+            self.credited.remove(&key);
+            self.reverifying.insert(key.clone());
+            let profile = self.policy.profile_for(credit.amount);
+            self.pending.insert(
+                key.clone(),
+                PendingDeposit {
+                    idempotency_key: key,
+                    user_id: credit.user_id.clone(),
+                    payment: ReceivedPayment {
+                        txid: credit.txid.clone(),
+                        output_index: credit.output_index,
+                        address: credit.address.clone(),
+                        amount: credit.amount,
+                        memo: credit.memo.clone(),
+                        height: None,
+                    },
+                    required_confirmations: profile.confirmations,
+                    profile: profile.name,
+                },
+            );
+            events.push(DepositEvent::Reorged(credit));
+        }
+        events
+    }
+
+    fn handle_payment(&mut self, payment: &ReceivedPayment) -> Vec<DepositEvent> {
+        let Some(user_id) = self.addresses.get(&payment.address).cloned() else {
+            return Vec::new();
+        };
+        let key = idempotency_key(&payment.txid, payment.output_index);
+        if self.credited.contains(&key) {
+            return Vec::new();
+        }
+
+        if let Some(height) = payment.height {
+            if self.tip.is_none_or(|tip| height > tip) {
+                self.tip = Some(height);
+            }
+        }
+
+        let is_new = !self.pending.contains_key(&key);
+        let profile = self.policy.profile_for(payment.amount);
+        let pending = PendingDeposit {
+            idempotency_key: key.clone(),
+            user_id,
+            payment: payment.clone(),
+            required_confirmations: profile.confirmations,
+            profile: profile.name,
+        };
+        self.pending.insert(key, pending.clone());
+
+        let mut events = Vec::new();
+        if is_new {
+            events.push(DepositEvent::Detected(pending));
+        }
+        events.extend(self.credit_matured());
+        events
+    }
+
+    fn credit_matured(&mut self) -> Vec<DepositEvent> {
+        let matured: Vec<String> = self
+            .pending
+            .values()
+            .filter(|p| {
+                p.payment.height.is_some()
+                    && self.confirmations(p.payment.height) >= p.required_confirmations
+            })
+            .map(|p| p.idempotency_key.clone())
+            .collect();
+
+        let mut events = Vec::new();
+        for key in matured {
+            if let Some(deposit) = self.pending.remove(&key) {
+                let confirmations = self.confirmations(deposit.payment.height);
+                let height = deposit.payment.height.unwrap_or_default();
+                let credit = Credit {
+                    idempotency_key: deposit.idempotency_key,
+                    user_id: deposit.user_id,
+                    address: deposit.payment.address,
+                    txid: deposit.payment.txid,
+                    output_index: deposit.payment.output_index,
+                    amount: deposit.payment.amount,
+                    confirmations,
+                    profile: deposit.profile,
+                    memo: deposit.payment.memo,
+                };
+                self.credited.insert(key.clone());
+                self.recent_credits
+                    .insert(key.clone(), (credit.clone(), height));
+                events.push(if self.reverifying.remove(&key) {
+                    DepositEvent::Reconfirmed(credit)
+                } else {
+                    DepositEvent::Credit(credit)
+                });
+            }
+        }
+
+        let tip = self.tip;
+        self.recent_credits.retain(|_, (_, height)| {
+            tip.is_none_or(|tip| tip.saturating_sub(*height) < MAX_REORG_DEPTH)
+        });
+        events
+    }
+
+    /// Consume events from a subscription and forward deposit events
+    ///
+    /// Runs until the event bus is closed or the output channel is dropped.
+    /// Lagged subscriptions are logged; missed payments are picked up again on
+    /// the watcher's next poll.
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<WalletEvent>,
+        output: mpsc::Sender<DepositEvent>,
+    ) -> Result<()> {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Deposit detector lagged, skipped {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            for deposit_event in self.handle_event(&event) {
+                if output.send(deposit_event).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(height: Option<u64>, amount: u64) -> WalletEvent {
+        WalletEvent::PaymentReceived(ReceivedPayment {
+            txid: "aa".to_string(),
+            output_index: 0,
+            address: "u1deposit".to_string(),
+            amount,
+            memo: None,
+            height,
+        })
+    }
+
+    #[test]
+    fn test_confirmation_policy_tiers() {
+        let policy = ConfirmationPolicy::new(3).with_tier(100_000_000, 10);
+        assert_eq!(policy.required_confirmations(1_000), 3);
+        assert_eq!(policy.required_confirmations(100_000_000), 10);
+        assert_eq!(policy.profile_for(1_000).name, DEFAULT_PROFILE_NAME);
+    }
+
+    #[test]
+    fn test_named_confirmation_profiles() {
+        let policy = ConfirmationPolicy::retail_and_high_value(100_000_000)
+            .with_profile(1_000_000_000, ConfirmationProfile::new("treasury", 30));
+        assert_eq!(policy.profile_for(1_000), ConfirmationProfile::retail());
+        assert_eq!(
+            policy.profile_for(100_000_000),
+            ConfirmationProfile::high_value()
+        );
+        assert_eq!(policy.required_confirmations(5_000_000_000), 30);
+
+        let mut detector = DepositDetector::new(policy);
+        detector.register_address("u1deposit", "user-1");
+        let events = detector.handle_event(&payment(Some(100), 200_000_000));
+        let [DepositEvent::Detected(pending)] = events.as_slice() else {
+            panic!("expected a detected deposit, got {:?}", events);
+        };
+        assert_eq!(pending.profile, "high-value");
+        assert_eq!(pending.required_confirmations, 10);
+    }
+
+    #[test]
+    fn test_deposit_credited_once() {
+        let mut detector = DepositDetector::new(ConfirmationPolicy::new(2));
+        detector.register_address("u1deposit", "user-1");
+
+        let events = detector.handle_event(&payment(None, 5_000));
+        assert!(matches!(events.as_slice(), [DepositEvent::Detected(_)]));
+
+        assert!(detector.handle_event(&payment(Some(100), 5_000)).is_empty());
+
+        let events = detector.handle_event(&WalletEvent::ChainTip { height: 101 });
+        match events.as_slice() {
+            [DepositEvent::Credit(credit)] => {
+                assert_eq!(credit.user_id, "user-1");
+                assert_eq!(credit.idempotency_key, "aa:0");
+                assert_eq!(credit.confirmations, 2);
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+
+        assert!(detector.handle_event(&payment(Some(100), 5_000)).is_empty());
+        assert!(detector.handle_event(&WalletEvent::ChainTip { height: 102 }).is_empty());
+    }
+
+    #[test]
+    fn test_reorged_credit_reverified() {
+        let mut detector = DepositDetector::new(ConfirmationPolicy::new(2));
+        detector.register_address("u1deposit", "user-1");
+        detector.handle_event(&payment(Some(100), 5_000));
+        let events = detector.handle_event(&WalletEvent::ChainTip { height: 101 });
+        assert!(matches!(events.as_slice(), [DepositEvent::Credit(_)]));
+
+        // Blocks 100 and 101 are replaced
+        let events = detector.handle_event(&WalletEvent::Reorg {
+            depth: 2,
+            old_tip: 101,
+            new_tip: 101,
+        });
+        let [DepositEvent::Reorged(credit)] = events.as_slice() else {
+            panic!("expected a reorged credit, got {:?}", events);
+        };
+        assert_eq!(credit.idempotency_key, "aa:0");
+        assert_eq!(detector.pending().count(), 1);
+
+        // Mined again at 101, confirmed at 102
+        assert!(detector.handle_event(&payment(Some(101), 5_000)).is_empty());
+        let events = detector.handle_event(&WalletEvent::ChainTip { height: 102 });
+        assert!(matches!(events.as_slice(), [DepositEvent::Reconfirmed(_)]));
+
+        // A reorg above the deposit's block leaves it credited
+        let events = detector.handle_event(&WalletEvent::Reorg {
+            depth: 1,
+            old_tip: 102,
+            new_tip: 102,
+        });
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_address_ignored() {
+        let mut detector = DepositDetector::default();
+        assert!(detector.handle_event(&payment(Some(1), 5_000)).is_empty());
+    }
+}
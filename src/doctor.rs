@@ -0,0 +1,575 @@
+//! Startup self-check diagnostics
+//!
+//! [`doctor`] checks what an app needs to work and returns a [`DoctorReport`]
+//! that can be attached to a support ticket as JSON. It never includes keys
+//! or addresses. The checks are:
+//! - Wallet database integrity ([`Wallet::verify_integrity`])
+//! - Key derivation: the selected account's keys derive from the seed (or
+//!   key provider) and match the viewing key stored in the database
+//! - Reachability of the configured zcashd and lightwalletd endpoints
+//! - Network and chain consistency: each endpoint follows the wallet's chain
+//! - Availability of the Sapling proving parameters
+//! - Clock skew between the local clock and the chain tip's block time
+//!
+//! Each check reports [`CheckStatus::Pass`], [`CheckStatus::Warn`] or
+//! [`CheckStatus::Fail`]; checks that need an endpoint are skipped if none is
+//! configured. A failed check does not stop the others.
+//!
+//! ```no_run
+//! use zcash_numi_sdk::doctor::DoctorOptions;
+//! use zcash_numi_sdk::wallet::Wallet;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let wallet = Wallet::new()?;
+//! let options = DoctorOptions::new().with_lightwalletd("https://mainnet.lightwalletd.com:9067");
+//! let report = zcash_numi_sdk::doctor(&wallet, &options).await;
+//! if !report.is_healthy() {
+//!     eprintln!("{}", report.to_json()?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::RpcClient;
+use crate::error::{Error, Result};
+use crate::light_client::{check_chain, LightClient};
+use crate::store::unix_now;
+use crate::types::Network;
+use crate::wallet::Wallet;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Default tolerance of [`DoctorOptions::with_max_clock_skew`]
+pub const DEFAULT_MAX_CLOCK_SKEW: Duration = Duration::from_secs(10 * 60);
+
+/// Sapling proving parameter files and their sizes in bytes
+const SAPLING_PARAMS: [(&str, u64); 2] = [
+    ("sapling-spend.params", 47_958_396),
+    ("sapling-output.params", 3_592_860),
+];
+
+/// What a check verifies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DoctorCheck {
+    WalletDatabase,
+    KeyDerivation,
+    /// Whether an endpoint answers
+    Endpoint,
+    /// Whether an endpoint follows the wallet's chain
+    ChainConsistency,
+    ProverParameters,
+    ClockSkew,
+}
+
+impl fmt::Display for DoctorCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DoctorCheck::WalletDatabase => "Wallet database",
+            DoctorCheck::KeyDerivation => "Key derivation",
+            DoctorCheck::Endpoint => "Endpoint",
+            DoctorCheck::ChainConsistency => "Network/chain",
+            DoctorCheck::ProverParameters => "Prover parameters",
+            DoctorCheck::ClockSkew => "Clock skew",
+        })
+    }
+}
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    /// Works, but may cause problems
+    Warn,
+    Fail,
+    /// Not run, e.g. because no endpoint is configured
+    Skipped,
+}
+
+/// Result of one check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub check: DoctorCheck,
+    pub status: CheckStatus,
+    /// Endpoint the check was run against, if any
+    pub endpoint: Option<String>,
+    pub detail: String,
+}
+
+/// Result of [`doctor`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoctorReport {
+    /// Version of the SDK that ran the checks
+    pub sdk_version: String,
+    pub network: Network,
+    /// Unix time the checks were run
+    pub generated_at: u64,
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    /// Whether no check failed
+    pub fn is_healthy(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks
+            .iter()
+            .filter(|result| result.status == CheckStatus::Fail)
+    }
+
+    /// Serialize the report as pretty-printed JSON, e.g. for a support ticket
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    fn push(
+        &mut self,
+        check: DoctorCheck,
+        status: CheckStatus,
+        endpoint: Option<&str>,
+        detail: String,
+    ) {
+        self.checks.push(CheckResult {
+            check,
+            status,
+            endpoint: endpoint.map(str::to_string),
+            detail,
+        });
+    }
+}
+
+/// Endpoints and tolerances for [`doctor`]
+pub struct DoctorOptions {
+    rpc: Option<RpcClient>,
+    lightwalletd: Option<String>,
+    max_clock_skew: Duration,
+    params_dir: Option<PathBuf>,
+}
+
+impl Default for DoctorOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DoctorOptions {
+    /// Check the wallet and prover parameters only
+    pub fn new() -> Self {
+        Self {
+            rpc: None,
+            lightwalletd: None,
+            max_clock_skew: DEFAULT_MAX_CLOCK_SKEW,
+            params_dir: None,
+        }
+    }
+
+    /// Check a zcashd (or zebrad) node
+    pub fn with_rpc(mut self, client: RpcClient) -> Self {
+        self.rpc = Some(client);
+        self
+    }
+
+    /// Check a lightwalletd server
+    pub fn with_lightwalletd(mut self, endpoint: impl Into<String>) -> Self {
+        self.lightwalletd = Some(endpoint.into());
+        self
+    }
+
+    /// Tolerated difference between the local clock and the chain tip's
+    /// block time (default [`DEFAULT_MAX_CLOCK_SKEW`])
+    pub fn with_max_clock_skew(mut self, max_clock_skew: Duration) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+
+    /// Look for the proving parameters in `dir` instead of
+    /// [`default_params_dir`]
+    pub fn with_params_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.params_dir = Some(dir.into());
+        self
+    }
+}
+
+/// Run the startup self-checks
+///
+/// # Arguments
+/// * `wallet` - Wallet to check, with its selected account
+/// * `options` - Endpoints to check and tolerances
+///
+/// # Returns
+/// The result of every check; see [`DoctorReport::is_healthy`]
+pub async fn doctor(wallet: &Wallet, options: &DoctorOptions) -> DoctorReport {
+    let mut report = DoctorReport {
+        sdk_version: env!("CARGO_PKG_VERSION").to_string(),
+        network: wallet.network(),
+        generated_at: unix_now(),
+        checks: Vec::new(),
+    };
+    check_database(&mut report, wallet);
+    check_keys(&mut report, wallet);
+    if let Some(client) = &options.rpc {
+        check_rpc(&mut report, wallet, client, options.max_clock_skew).await;
+    }
+    if let Some(endpoint) = &options.lightwalletd {
+        check_lightwalletd(&mut report, wallet, endpoint, options.max_clock_skew).await;
+    }
+    if options.rpc.is_none() && options.lightwalletd.is_none() {
+        for check in [
+            DoctorCheck::Endpoint,
+            DoctorCheck::ChainConsistency,
+            DoctorCheck::ClockSkew,
+        ] {
+            report.push(
+                check,
+                CheckStatus::Skipped,
+                None,
+                "No endpoint configured".to_string(),
+            );
+        }
+    }
+    match options.params_dir.clone().or_else(default_params_dir) {
+        Some(dir) => check_params(&mut report, &dir),
+        None => report.push(
+            DoctorCheck::ProverParameters,
+            CheckStatus::Warn,
+            None,
+            "No home directory to look for the parameters in".to_string(),
+        ),
+    }
+    tracing::info!(
+        "Self-check ran {} checks, {} failed",
+        report.checks.len(),
+        report.failures().count()
+    );
+    report
+}
+
+/// Directory zcashd and `zcash-fetch-params` download the proving
+/// parameters to: `~/.zcash-params`, or `ZcashParams` in the user's data
+/// directory on macOS and Windows
+pub fn default_params_dir() -> Option<PathBuf> {
+    if cfg!(any(windows, target_os = "macos")) {
+        dirs::data_dir().map(|dir| dir.join("ZcashParams"))
+    } else {
+        dirs::home_dir().map(|dir| dir.join(".zcash-params"))
+    }
+}
+
+fn check_database(report: &mut DoctorReport, wallet: &Wallet) {
+    let (status, detail) = match wallet.verify_integrity() {
+        Ok(integrity) if integrity.is_ok() => (CheckStatus::Pass, "No issues found".to_string()),
+        Ok(integrity) => {
+            let issues: Vec<&str> = integrity
+                .issues
+                .iter()
+                .map(|issue| issue.description.as_str())
+                .collect();
+            let action = if integrity.is_repairable() {
+                "repairable in place"
+            } else {
+                "restore from a backup or the seed"
+            };
+            (
+                CheckStatus::Fail,
+                format!(
+                    "{} issues ({}): {}",
+                    issues.len(),
+                    action,
+                    issues.join("; ")
+                ),
+            )
+        }
+        Err(e) => (CheckStatus::Fail, e.to_string()),
+    };
+    report.push(DoctorCheck::WalletDatabase, status, None, detail);
+}
+
+fn check_keys(report: &mut DoctorReport, wallet: &Wallet) {
+    let index = wallet.account_index();
+    let derived = wallet.account_ufvk(index).and_then(|ufvk| {
+        wallet.account_unified_address(index)?;
+        Ok(ufvk.encode(&wallet.consensus_network()))
+    });
+    let (status, detail) = match (derived, wallet.list_accounts()) {
+        (Err(e), _) | (_, Err(e)) => (CheckStatus::Fail, e.to_string()),
+        (Ok(derived), Ok(accounts)) => {
+            match accounts.iter().find(|account| account.index == Some(index)) {
+                None => (
+                    CheckStatus::Warn,
+                    format!("Account {} is not in the wallet database", index),
+                ),
+                Some(account) if account.ufvk.as_deref() != Some(derived.as_str()) => (
+                    CheckStatus::Fail,
+                    format!(
+                        "Keys derived for account {} do not match its viewing key in the \
+                         database; the wallet may be opened with the wrong seed",
+                        index
+                    ),
+                ),
+                Some(_) => (
+                    CheckStatus::Pass,
+                    format!("Account {} keys match the wallet database", index),
+                ),
+            }
+        }
+    };
+    report.push(DoctorCheck::KeyDerivation, status, None, detail);
+}
+
+async fn check_rpc(
+    report: &mut DoctorReport,
+    wallet: &Wallet,
+    client: &RpcClient,
+    max_clock_skew: Duration,
+) {
+    let endpoint = Some(client.endpoint());
+    let start = Instant::now();
+    let info = match client.get_blockchain_info().await {
+        Ok(info) => info,
+        Err(e) => {
+            report.push(
+                DoctorCheck::Endpoint,
+                CheckStatus::Fail,
+                endpoint,
+                e.to_string(),
+            );
+            return;
+        }
+    };
+    let elapsed = start.elapsed().as_millis();
+    if info.is_synced() {
+        report.push(
+            DoctorCheck::Endpoint,
+            CheckStatus::Pass,
+            endpoint,
+            format!("Node answered in {} ms at height {}", elapsed, info.blocks),
+        );
+    } else {
+        report.push(
+            DoctorCheck::Endpoint,
+            CheckStatus::Warn,
+            endpoint,
+            format!(
+                "Node answered in {} ms but is still syncing, at {} of {} blocks",
+                elapsed, info.blocks, info.headers
+            ),
+        );
+    }
+    check_endpoint_chain(report, client.endpoint(), &info.chain, wallet.network());
+
+    let tip_time = client
+        .get_block(&info.bestblockhash)
+        .await
+        .and_then(|block| {
+            block
+                .get("time")
+                .and_then(|time| time.as_u64())
+                .ok_or_else(|| Error::Rpc("Block has no time".to_string()))
+        });
+    check_clock(report, client.endpoint(), tip_time, max_clock_skew);
+}
+
+async fn check_lightwalletd(
+    report: &mut DoctorReport,
+    wallet: &Wallet,
+    endpoint: &str,
+    max_clock_skew: Duration,
+) {
+    let start = Instant::now();
+    let connected = match LightClient::open(endpoint.to_string(), wallet.clone()) {
+        Ok(mut client) => client.query_server_info().await.map(|info| (client, info)),
+        Err(e) => Err(e),
+    };
+    let (mut client, info) = match connected {
+        Ok(connected) => connected,
+        Err(e) => {
+            report.push(
+                DoctorCheck::Endpoint,
+                CheckStatus::Fail,
+                Some(endpoint),
+                e.to_string(),
+            );
+            return;
+        }
+    };
+    report.push(
+        DoctorCheck::Endpoint,
+        CheckStatus::Pass,
+        Some(endpoint),
+        format!(
+            "lightwalletd {} answered in {} ms at height {}",
+            info.version,
+            start.elapsed().as_millis(),
+            info.block_height
+        ),
+    );
+    check_endpoint_chain(report, endpoint, &info.chain_name, wallet.network());
+
+    let tip_time = client
+        .get_compact_blocks(info.block_height, info.block_height)
+        .await
+        .and_then(|blocks| {
+            blocks
+                .first()
+                .map(|block| u64::from(block.time))
+                .ok_or_else(|| Error::Rpc("Server returned no tip block".to_string()))
+        });
+    check_clock(report, endpoint, tip_time, max_clock_skew);
+}
+
+fn check_endpoint_chain(report: &mut DoctorReport, endpoint: &str, chain: &str, network: Network) {
+    let (status, detail) = match check_chain(endpoint, chain, network) {
+        Ok(()) => (CheckStatus::Pass, format!("Follows chain {}", chain)),
+        Err(e) => (CheckStatus::Fail, e.to_string()),
+    };
+    report.push(
+        DoctorCheck::ChainConsistency,
+        status,
+        Some(endpoint),
+        detail,
+    );
+}
+
+fn check_clock(
+    report: &mut DoctorReport,
+    endpoint: &str,
+    tip_time: Result<u64>,
+    max_clock_skew: Duration,
+) {
+    let (status, detail) = match tip_time {
+        Ok(tip_time) => clock_skew(unix_now(), tip_time, max_clock_skew),
+        Err(e) => (
+            CheckStatus::Warn,
+            format!("Could not get the tip's block time: {}", e),
+        ),
+    };
+    report.push(DoctorCheck::ClockSkew, status, Some(endpoint), detail);
+}
+
+/// Compare the local clock with the chain tip's block time
+///
+/// A tip from the future means the local clock is behind, which breaks
+/// expiry heights and certificate checks. An old tip is only a warning:
+/// the local clock may be ahead, or the server may have fallen behind.
+fn clock_skew(now: u64, tip_time: u64, max_clock_skew: Duration) -> (CheckStatus, String) {
+    let skew = now as i64 - tip_time as i64;
+    let max = max_clock_skew.as_secs() as i64;
+    if skew < -max {
+        (
+            CheckStatus::Fail,
+            format!(
+                "Local clock is {} s behind the chain tip's block time",
+                -skew
+            ),
+        )
+    } else if skew > max {
+        (
+            CheckStatus::Warn,
+            format!(
+                "Chain tip's block time is {} s old; the local clock is ahead or the \
+                 server is behind",
+                skew
+            ),
+        )
+    } else {
+        (
+            CheckStatus::Pass,
+            format!("Local clock within {} s of the chain tip", skew.abs()),
+        )
+    }
+}
+
+/// Check that the Sapling proving parameters are in `dir` and complete
+///
+/// They are only needed to prove transactions locally (air-gapped PCZT
+/// signing), so missing parameters fail the check only with the `pczt`
+/// feature.
+fn check_params(report: &mut DoctorReport, dir: &Path) {
+    let problems: Vec<String> = SAPLING_PARAMS
+        .iter()
+        .filter_map(|(name, size)| match std::fs::metadata(dir.join(name)) {
+            Ok(metadata) if metadata.len() == *size => None,
+            Ok(metadata) => Some(format!(
+                "{} is {} bytes instead of {}",
+                name,
+                metadata.len(),
+                size
+            )),
+            Err(_) => Some(format!("{} is missing", name)),
+        })
+        .collect();
+    let (status, detail) = if problems.is_empty() {
+        (
+            CheckStatus::Pass,
+            format!("Sapling parameters found in {}", dir.display()),
+        )
+    } else {
+        let status = if cfg!(feature = "pczt") {
+            CheckStatus::Fail
+        } else {
+            CheckStatus::Warn
+        };
+        (
+            status,
+            format!(
+                "{} in {}; run zcash-fetch-params to download them",
+                problems.join(", "),
+                dir.display()
+            ),
+        )
+    };
+    report.push(DoctorCheck::ProverParameters, status, None, detail);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_and_clock_checks() {
+        let dir = std::env::temp_dir().join(format!("test_doctor_{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("sapling-output.params"), vec![0u8; 3_592_860]).unwrap();
+        std::fs::write(dir.join("sapling-spend.params"), b"truncated").unwrap();
+        let mut report = DoctorReport {
+            sdk_version: "0.1.0".to_string(),
+            network: Network::Testnet,
+            generated_at: 0,
+            checks: Vec::new(),
+        };
+
+        check_params(&mut report, &dir);
+        let params = &report.checks[0];
+        assert_ne!(params.status, CheckStatus::Pass);
+        assert!(params.detail.contains("sapling-spend.params is 9 bytes"));
+        assert!(!params.detail.contains("sapling-output"));
+
+        std::fs::write(dir.join("sapling-spend.params"), vec![0u8; 47_958_396]).unwrap();
+        check_params(&mut report, &dir);
+        assert_eq!(report.checks[1].status, CheckStatus::Pass);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let max = DEFAULT_MAX_CLOCK_SKEW;
+        assert_eq!(clock_skew(1_000_000, 999_925, max).0, CheckStatus::Pass);
+        assert_eq!(clock_skew(1_000_000, 1_000_900, max).0, CheckStatus::Fail);
+        assert_eq!(clock_skew(1_000_000, 990_000, max).0, CheckStatus::Warn);
+
+        check_clock(&mut report, "http://localhost:8232", Ok(unix_now()), max);
+        assert!(report.is_healthy());
+        check_endpoint_chain(
+            &mut report,
+            "http://localhost:8232",
+            "main",
+            Network::Testnet,
+        );
+        assert_eq!(report.failures().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["checks"][3]["check"], "chain_consistency");
+    }
+}
@@ -0,0 +1,326 @@
+//! Dual control: split viewing and spending deployments
+//!
+//! The online service holds only the unified full viewing key of an
+//! account: it syncs, monitors payments and builds proposals. A separate,
+//! hardened signer process holds the seed and does nothing but sign. Neither
+//! can move funds alone: the service cannot sign, and the signer cannot see
+//! the chain or build transactions.
+//!
+//! 1. The signer exports a [`ViewingKeyExport`] of its account (JSON).
+//! 2. The service opens its wallet with
+//!    [`ViewingKeyExport::open_wallet`]; the seed never reaches it.
+//! 3. The service builds unsigned PCZTs with
+//!    [`airgap::create_signing_request`](crate::airgap) and puts them in a
+//!    [`SigningQueue`].
+//! 4. The signer takes them from the queue, signs them and puts the signed
+//!    PCZTs back (see the `numi-signer` binary).
+//! 5. The service proves, stores and broadcasts them with
+//!    [`airgap::extract_and_broadcast`](crate::airgap).
+//!
+//! The queue is a pair of directories, e.g. on a volume shared by the two
+//! processes. Files are written under a temporary name and renamed, so
+//! neither side reads a half-written PCZT.
+
+use crate::airgap::AirgapEnvelope;
+use crate::error::{Error, Result};
+use crate::key_provider::KeyProvider;
+use crate::signer::Signer;
+use crate::types::Network;
+use crate::wallet::{consensus_network, Wallet};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zcash_keys::keys::UnifiedFullViewingKey;
+use zip32::{fingerprint::SeedFingerprint, AccountId};
+
+/// Extension of queued PCZT envelopes
+const ENVELOPE_EXTENSION: &str = "pczt";
+
+/// Extension of rejection notes written by the signer
+const REJECTION_EXTENSION: &str = "rejected";
+
+/// Viewing key of one account, handed from the signer to the online service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewingKeyExport {
+    /// Network the key is for
+    pub network: Network,
+    /// ZIP-32 account index
+    pub account: u32,
+    /// ZIP-32 fingerprint of the seed, hex encoded
+    pub seed_fingerprint: String,
+    /// Encoded unified full viewing key
+    pub ufvk: String,
+}
+
+impl ViewingKeyExport {
+    /// Export the viewing key of an account of a wallet holding the seed
+    ///
+    /// # Arguments
+    /// * `wallet` - The signer's wallet
+    /// * `account` - ZIP-32 account index
+    pub fn from_wallet(wallet: &Wallet, account: u32) -> Result<Self> {
+        Ok(Self {
+            network: wallet.network(),
+            account,
+            seed_fingerprint: wallet.seed_fingerprint()?,
+            ufvk: wallet
+                .account_ufvk(account)?
+                .encode(&consensus_network(wallet.network())),
+        })
+    }
+
+    /// Open the online service's wallet on this viewing key
+    ///
+    /// The account is selected, but is only recorded in the wallet database
+    /// once created with [`Wallet::create_account_at`] and its birthday.
+    ///
+    /// # Arguments
+    /// * `db_path` - The service's wallet database path
+    pub fn open_wallet(&self, db_path: PathBuf) -> Result<Wallet> {
+        let keys = Arc::new(ViewOnlyKeys::new(self)?);
+        let mut wallet = Wallet::with_key_provider(db_path, keys)?;
+        wallet.set_network(self.network)?;
+        wallet.use_account(self.account)?;
+        Ok(wallet)
+    }
+}
+
+/// [`KeyProvider`] of an online service holding only a viewing key
+///
+/// Derives nothing: it hands out the one viewing key it was given, and has
+/// no signer, as spends are signed by the separate signer process.
+pub struct ViewOnlyKeys {
+    network: Network,
+    account: AccountId,
+    seed_fingerprint: SeedFingerprint,
+    ufvk: UnifiedFullViewingKey,
+}
+
+impl ViewOnlyKeys {
+    /// Decode an exported viewing key
+    pub fn new(export: &ViewingKeyExport) -> Result<Self> {
+        let account = AccountId::try_from(export.account).map_err(|_| {
+            Error::InvalidParameter(format!("Invalid ZIP-32 account index {}", export.account))
+        })?;
+        let fingerprint: [u8; 32] = hex::decode(&export.seed_fingerprint)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                Error::InvalidParameter("Seed fingerprint must be 32 hex-encoded bytes".to_string())
+            })?;
+        let ufvk = UnifiedFullViewingKey::decode(&consensus_network(export.network), &export.ufvk)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid viewing key: {}", e)))?;
+        Ok(Self {
+            network: export.network,
+            account,
+            seed_fingerprint: SeedFingerprint::from_bytes(fingerprint),
+            ufvk,
+        })
+    }
+}
+
+impl KeyProvider for ViewOnlyKeys {
+    fn name(&self) -> &str {
+        "view-only deployment"
+    }
+
+    fn seed_fingerprint(&self) -> Result<SeedFingerprint> {
+        Ok(self.seed_fingerprint)
+    }
+
+    fn unified_full_viewing_key(
+        &self,
+        network: Network,
+        account: AccountId,
+    ) -> Result<UnifiedFullViewingKey> {
+        if network != self.network || account != self.account {
+            return Err(Error::Wallet(format!(
+                "Only the viewing key of {:?} account {} is available",
+                self.network,
+                u32::from(self.account)
+            )));
+        }
+        Ok(self.ufvk.clone())
+    }
+
+    fn signer(&self, _network: Network, _account: AccountId) -> Result<Box<dyn Signer>> {
+        Err(Error::Wallet(
+            "Spends are signed by the separate signer process; submit them to its queue"
+                .to_string(),
+        ))
+    }
+}
+
+/// Directories through which the online service and the signer exchange
+/// PCZTs
+///
+/// Each request has an ID; the service writes `<id>.pczt` to the request
+/// directory, and the signer answers with `<id>.pczt` in the response
+/// directory, or `<id>.rejected` holding the reason it refused to sign.
+#[derive(Debug, Clone)]
+pub struct SigningQueue {
+    requests: PathBuf,
+    responses: PathBuf,
+}
+
+impl SigningQueue {
+    /// Open the queue, creating its directories if needed
+    ///
+    /// # Arguments
+    /// * `requests` - Directory of unsigned PCZTs, written by the service
+    /// * `responses` - Directory of signed PCZTs, written by the signer
+    pub fn new(requests: impl Into<PathBuf>, responses: impl Into<PathBuf>) -> Result<Self> {
+        let queue = Self {
+            requests: requests.into(),
+            responses: responses.into(),
+        };
+        std::fs::create_dir_all(&queue.requests)?;
+        std::fs::create_dir_all(&queue.responses)?;
+        Ok(queue)
+    }
+
+    /// Queue an unsigned PCZT for signing
+    ///
+    /// # Returns
+    /// The request ID to collect the response with
+    pub fn submit(&self, envelope: &AirgapEnvelope) -> Result<String> {
+        let id = hex::encode(rand::random::<[u8; 16]>());
+        write_atomically(
+            &self.requests,
+            &id,
+            ENVELOPE_EXTENSION,
+            &envelope.to_bytes(),
+        )?;
+        Ok(id)
+    }
+
+    /// Collect the signer's response to a request
+    ///
+    /// # Returns
+    /// The signed PCZT, or `None` while the request is pending;
+    /// [`Error::Transaction`] if the signer rejected it
+    pub fn take_response(&self, id: &str) -> Result<Option<AirgapEnvelope>> {
+        let rejection = entry_path(&self.responses, id, REJECTION_EXTENSION)?;
+        if rejection.exists() {
+            let reason = std::fs::read_to_string(&rejection)?;
+            std::fs::remove_file(&rejection)?;
+            return Err(Error::Transaction(format!(
+                "Signer rejected request {}: {}",
+                id,
+                reason.trim()
+            )));
+        }
+        let path = entry_path(&self.responses, id, ENVELOPE_EXTENSION)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let envelope = AirgapEnvelope::read_file(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(Some(envelope))
+    }
+
+    /// IDs of the requests awaiting the signer, oldest first
+    pub fn pending(&self) -> Result<Vec<String>> {
+        let mut pending = Vec::new();
+        for entry in std::fs::read_dir(&self.requests)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(ENVELOPE_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                let modified = std::fs::metadata(&path)?.modified()?;
+                pending.push((modified, id.to_string()));
+            }
+        }
+        pending.sort();
+        Ok(pending.into_iter().map(|(_, id)| id).collect())
+    }
+
+    /// Read a pending request
+    pub fn read_request(&self, id: &str) -> Result<AirgapEnvelope> {
+        AirgapEnvelope::read_file(entry_path(&self.requests, id, ENVELOPE_EXTENSION)?)
+    }
+
+    /// Answer a request with the signed PCZT, removing the request
+    pub fn respond(&self, id: &str, signed: &AirgapEnvelope) -> Result<()> {
+        write_atomically(&self.responses, id, ENVELOPE_EXTENSION, &signed.to_bytes())?;
+        std::fs::remove_file(entry_path(&self.requests, id, ENVELOPE_EXTENSION)?)?;
+        Ok(())
+    }
+
+    /// Refuse to sign a request, removing it
+    pub fn reject(&self, id: &str, reason: &str) -> Result<()> {
+        write_atomically(&self.responses, id, REJECTION_EXTENSION, reason.as_bytes())?;
+        std::fs::remove_file(entry_path(&self.requests, id, ENVELOPE_EXTENSION)?)?;
+        Ok(())
+    }
+}
+
+/// Path of a queue entry; IDs are hex, so they cannot leave the directory
+fn entry_path(dir: &Path, id: &str, extension: &str) -> Result<PathBuf> {
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::InvalidParameter(format!(
+            "Invalid signing request ID {:?}",
+            id
+        )));
+    }
+    Ok(dir.join(format!("{}.{}", id, extension)))
+}
+
+/// Write a queue entry under a temporary name and rename it into place
+fn write_atomically(dir: &Path, id: &str, extension: &str, contents: &[u8]) -> Result<()> {
+    let path = entry_path(dir, id, extension)?;
+    let staging = dir.join(format!(".{}.{}.tmp", id, extension));
+    std::fs::write(&staging, contents)?;
+    std::fs::rename(&staging, &path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::airgap::PayloadKind;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_view_only_deployment() {
+        let signer_wallet = Wallet::ephemeral(Network::Testnet).unwrap();
+        let export = ViewingKeyExport::from_wallet(&signer_wallet, 0).unwrap();
+        let json = serde_json::to_string(&export).unwrap();
+        let export: ViewingKeyExport = serde_json::from_str(&json).unwrap();
+
+        let path = TempDb::new();
+        let service = export.open_wallet(path.to_path_buf()).unwrap();
+        assert_eq!(
+            service.get_unified_address().unwrap(),
+            signer_wallet.get_unified_address().unwrap()
+        );
+        assert!(service.account_ufvk(1).is_err());
+        assert!(service.export_mnemonic().is_err());
+        let keys = ViewOnlyKeys::new(&export).unwrap();
+        assert!(keys.signer(Network::Testnet, AccountId::ZERO).is_err());
+
+        let dir =
+            std::env::temp_dir().join(format!("test_signing_queue_{}", rand::random::<u64>()));
+        let queue = SigningQueue::new(dir.join("requests"), dir.join("responses")).unwrap();
+        let unsigned = AirgapEnvelope::new(PayloadKind::UnsignedPczt, Network::Testnet, vec![1]);
+        let first = queue.submit(&unsigned).unwrap();
+        let second = queue.submit(&unsigned).unwrap();
+        assert_eq!(queue.pending().unwrap().len(), 2);
+        assert_eq!(queue.read_request(&first).unwrap(), unsigned);
+        assert!(queue.take_response(&first).unwrap().is_none());
+
+        let signed = AirgapEnvelope::new(PayloadKind::SignedPczt, Network::Testnet, vec![2]);
+        queue.respond(&first, &signed).unwrap();
+        queue.reject(&second, "amount above limit").unwrap();
+        assert!(queue.pending().unwrap().is_empty());
+        assert_eq!(queue.take_response(&first).unwrap(), Some(signed));
+        assert!(queue.take_response(&first).unwrap().is_none());
+        assert!(matches!(
+            queue.take_response(&second),
+            Err(Error::Transaction(_))
+        ));
+        assert!(queue.read_request("../requests").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -35,6 +35,12 @@ pub enum Error {
 
     #[error("Invalid parameter: {0}")]
     InvalidParameter(String),
+
+    #[error("Spending policy violation: {0}")]
+    PolicyViolation(crate::policy::PolicyViolation),
+
+    #[error("Mempool conflict: {0}")]
+    MempoolConflict(crate::mempool::MempoolConflict),
 }
 
 /// Result type alias for SDK operations
@@ -0,0 +1,97 @@
+//! Wallet and chain event stream
+//!
+//! Sync and watcher components publish [`WalletEvent`]s onto an [`EventBus`];
+//! higher-level subsystems (deposit detection, invoicing, monitoring) subscribe
+//! to the bus and react to chain progress and incoming payments.
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Default number of events buffered per subscriber before lagging
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// Deepest reorg detected by watchers; payments credited within this many
+/// blocks of the tip are re-verified after a reorg
+pub const MAX_REORG_DEPTH: u64 = 100;
+
+/// A payment output received by an address the SDK is watching
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceivedPayment {
+    /// Transaction ID (hex encoded)
+    pub txid: String,
+    /// Index of the output within its pool in the transaction
+    pub output_index: u32,
+    /// Receiving address
+    pub address: String,
+    /// Amount in zatoshis
+    pub amount: u64,
+    /// Decoded memo text, if any
+    pub memo: Option<String>,
+    /// Mined height, or `None` while the transaction is in the mempool
+    pub height: Option<u64>,
+}
+
+/// Events emitted while following the chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalletEvent {
+    /// The best chain tip moved to a new height
+    ChainTip { height: u64 },
+    /// The light client finished scanning a range of blocks (inclusive)
+    BlocksScanned { start_height: u64, end_height: u64 },
+    /// A payment to a watched address was seen in the mempool or in a block
+    PaymentReceived(ReceivedPayment),
+    /// The best chain was reorganized
+    ///
+    /// The `depth` blocks of the old chain above height `old_tip - depth`
+    /// were disconnected. Payments mined in them are unconfirmed until they
+    /// are published again with their new height.
+    Reorg {
+        depth: u64,
+        old_tip: u64,
+        new_tip: u64,
+    },
+    /// Sync paused because the bandwidth soft cap was exceeded
+    ///
+    /// Sync can be resumed from `next_height` once the cap is raised or reset.
+    BandwidthCapReached {
+        next_height: u64,
+        downloaded: u64,
+        uploaded: u64,
+        cap: u64,
+    },
+}
+
+/// Broadcast channel for [`WalletEvent`]s
+///
+/// Cloning the bus is cheap; all clones publish to the same subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<WalletEvent>,
+}
+
+impl EventBus {
+    /// Create a new event bus buffering up to `capacity` events per subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Subscribe to events published after this call
+    pub fn subscribe(&self) -> broadcast::Receiver<WalletEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Publish an event to all current subscribers
+    ///
+    /// Returns the number of subscribers that received the event. Publishing
+    /// with no subscribers is not an error.
+    pub fn publish(&self, event: WalletEvent) -> usize {
+        self.sender.send(event).unwrap_or(0)
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
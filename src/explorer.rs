@@ -0,0 +1,332 @@
+//! Block explorer queries
+//!
+//! Read-only views over zcashd's typed RPC responses for internal explorers
+//! and support tooling:
+//! - A block with a summary of each of its transactions
+//! - A single decoded transaction
+//! - Activity and balance of a transparent address
+//!
+//! Transaction lookups by ID require zcashd's `-txindex`; address activity
+//! requires `-insightexplorer` (or `-lightwalletd`).
+
+use crate::client::RpcClient;
+use crate::error::{Error, Result};
+use crate::rpc::{AddressDelta, Block, RawTransaction};
+use crate::types::utils::zec_to_zatoshis;
+use serde::{Deserialize, Serialize};
+
+/// Summary of a transaction as shown in a block listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionSummary {
+    pub txid: String,
+    pub is_coinbase: bool,
+    pub transparent_inputs: usize,
+    pub transparent_outputs: usize,
+    pub sapling_spends: usize,
+    pub sapling_outputs: usize,
+    pub orchard_actions: usize,
+    /// Total value of transparent outputs (zatoshis)
+    pub transparent_output_total: u64,
+}
+
+/// A block and its transactions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockView {
+    pub height: u64,
+    pub hash: String,
+    pub time: u64,
+    pub confirmations: i64,
+    pub previous_hash: Option<String>,
+    pub next_hash: Option<String>,
+    pub transactions: Vec<TransactionSummary>,
+}
+
+/// A transparent input of a decoded transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputView {
+    /// Outpoint being spent, or `None` for coinbase
+    pub prevout: Option<(String, u32)>,
+    /// Address being spent from, when known
+    pub address: Option<String>,
+    /// Value being spent (zatoshis), when known
+    pub value: Option<u64>,
+}
+
+/// A transparent output of a decoded transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputView {
+    pub index: u32,
+    pub address: Option<String>,
+    /// Value in zatoshis
+    pub value: u64,
+    pub script_type: Option<String>,
+}
+
+/// A fully decoded transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionView {
+    pub summary: TransactionSummary,
+    pub block_hash: Option<String>,
+    pub height: Option<u64>,
+    pub confirmations: u64,
+    pub time: Option<u64>,
+    pub expiry_height: Option<u64>,
+    pub inputs: Vec<InputView>,
+    pub outputs: Vec<OutputView>,
+    /// Net value leaving the Sapling pool (zatoshis, negative when shielding)
+    pub sapling_value_balance: i64,
+    /// Net value leaving the Orchard pool (zatoshis, negative when shielding)
+    pub orchard_value_balance: i64,
+    /// Fee in zatoshis, when all input values are known
+    pub fee: Option<u64>,
+}
+
+/// Net effect of one transaction on a transparent address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressActivityEntry {
+    pub txid: String,
+    pub height: u64,
+    /// Net change in zatoshis (negative for spends)
+    pub delta: i64,
+}
+
+/// Balance and history of a transparent address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressActivity {
+    pub address: String,
+    /// Current balance in zatoshis
+    pub balance: i64,
+    /// Total received in zatoshis
+    pub received: i64,
+    /// Per-transaction changes, newest first
+    pub entries: Vec<AddressActivityEntry>,
+}
+
+/// Explorer queries against a zcashd node
+pub struct Explorer {
+    client: RpcClient,
+}
+
+impl Explorer {
+    /// Create an explorer backed by the given RPC client
+    pub fn new(client: RpcClient) -> Self {
+        Self { client }
+    }
+
+    /// The underlying RPC client
+    pub fn client(&self) -> &RpcClient {
+        &self.client
+    }
+
+    /// Get block `height` with a summary of its transactions
+    pub async fn block(&self, height: u64) -> Result<BlockView> {
+        let block = self.client.get_block_verbose(&height.to_string()).await?;
+        Ok(block_view(block))
+    }
+
+    /// Get a block by hash with a summary of its transactions
+    pub async fn block_by_hash(&self, hash: &str) -> Result<BlockView> {
+        let block = self.client.get_block_verbose(hash).await?;
+        Ok(block_view(block))
+    }
+
+    /// Get a decoded transaction
+    pub async fn transaction(&self, txid: &str) -> Result<TransactionView> {
+        let tx = self.client.get_raw_transaction(txid).await?;
+        Ok(transaction_view(tx))
+    }
+
+    /// Get the balance and activity of a transparent address
+    ///
+    /// # Arguments
+    /// * `address` - Transparent address (t1/t3 or tm/t2)
+    /// * `range` - Optional inclusive (start, end) block height range
+    pub async fn address_activity(
+        &self,
+        address: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<AddressActivity> {
+        if !address.starts_with('t') {
+            return Err(Error::Address(format!(
+                "Address activity is only indexed for transparent addresses: {}",
+                address
+            )));
+        }
+
+        let addresses = vec![address.to_string()];
+        let balance = self.client.get_address_balance(&addresses).await?;
+        let deltas = self.client.get_address_deltas(&addresses, range).await?;
+
+        Ok(AddressActivity {
+            address: address.to_string(),
+            balance: balance.balance,
+            received: balance.received,
+            entries: activity_entries(deltas),
+        })
+    }
+}
+
+fn summarize(tx: &RawTransaction) -> TransactionSummary {
+    TransactionSummary {
+        txid: tx.txid.clone(),
+        is_coinbase: tx.vin.iter().any(|input| input.coinbase.is_some()),
+        transparent_inputs: tx.vin.len(),
+        transparent_outputs: tx.vout.len(),
+        sapling_spends: tx.shielded_spends.len(),
+        sapling_outputs: tx.shielded_outputs.len(),
+        orchard_actions: tx.orchard.as_ref().map_or(0, |o| o.actions.len()),
+        transparent_output_total: tx
+            .vout
+            .iter()
+            .map(|output| {
+                output
+                    .value_zat
+                    .unwrap_or_else(|| zec_to_zatoshis(output.value))
+            })
+            .sum(),
+    }
+}
+
+fn block_view(block: Block) -> BlockView {
+    BlockView {
+        height: block.height,
+        hash: block.hash,
+        time: block.time,
+        confirmations: block.confirmations,
+        previous_hash: block.previousblockhash,
+        next_hash: block.nextblockhash,
+        transactions: block.tx.iter().map(summarize).collect(),
+    }
+}
+
+fn transaction_view(tx: RawTransaction) -> TransactionView {
+    let summary = summarize(&tx);
+    let sapling_value_balance = tx.value_balance_zat.unwrap_or(0);
+    let orchard_value_balance = tx
+        .orchard
+        .as_ref()
+        .and_then(|o| o.value_balance_zat)
+        .unwrap_or(0);
+
+    let inputs: Vec<InputView> = tx
+        .vin
+        .iter()
+        .map(|input| InputView {
+            prevout: input.txid.clone().zip(input.vout),
+            address: input.address.clone(),
+            value: input.value_sat,
+        })
+        .collect();
+
+    let outputs: Vec<OutputView> = tx
+        .vout
+        .iter()
+        .map(|output| OutputView {
+            index: output.n,
+            address: output.script_pub_key.addresses.first().cloned(),
+            value: output
+                .value_zat
+                .unwrap_or_else(|| zec_to_zatoshis(output.value)),
+            script_type: output.script_pub_key.script_type.clone(),
+        })
+        .collect();
+
+    // fee = transparent in - transparent out + value leaving shielded pools
+    let fee = if summary.is_coinbase {
+        None
+    } else {
+        inputs
+            .iter()
+            .map(|input| input.value)
+            .sum::<Option<u64>>()
+            .and_then(|transparent_in| {
+                let total = transparent_in as i128 - summary.transparent_output_total as i128
+                    + sapling_value_balance as i128
+                    + orchard_value_balance as i128;
+                u64::try_from(total).ok()
+            })
+    };
+
+    TransactionView {
+        summary,
+        block_hash: tx.blockhash,
+        height: tx.height,
+        confirmations: tx.confirmations.unwrap_or(0),
+        time: tx.blocktime.or(tx.time),
+        expiry_height: tx.expiry_height,
+        inputs,
+        outputs,
+        sapling_value_balance,
+        orchard_value_balance,
+        fee,
+    }
+}
+
+/// Combine per-output deltas into one entry per transaction, newest first
+fn activity_entries(deltas: Vec<AddressDelta>) -> Vec<AddressActivityEntry> {
+    let mut entries: Vec<AddressActivityEntry> = Vec::new();
+    for delta in deltas {
+        match entries
+            .iter_mut()
+            .find(|entry| entry.txid == delta.txid && entry.height == delta.height)
+        {
+            Some(entry) => entry.delta += delta.satoshis,
+            None => entries.push(AddressActivityEntry {
+                txid: delta.txid,
+                height: delta.height,
+                delta: delta.satoshis,
+            }),
+        }
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.height));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_view_fee() {
+        let tx: RawTransaction = serde_json::from_value(serde_json::json!({
+            "txid": "aa",
+            "vin": [{ "txid": "bb", "vout": 1, "valueSat": 100_000, "address": "t1from" }],
+            "vout": [{
+                "value": 0.0008,
+                "valueZat": 80_000,
+                "n": 0,
+                "scriptPubKey": { "type": "pubkeyhash", "addresses": ["t1to"] }
+            }],
+            "valueBalanceZat": 0,
+            "orchard": { "actions": [{}, {}], "valueBalanceZat": -10_000 },
+            "height": 10,
+            "confirmations": 3
+        }))
+        .unwrap();
+
+        let view = transaction_view(tx);
+        assert_eq!(view.summary.orchard_actions, 2);
+        assert_eq!(view.outputs[0].address.as_deref(), Some("t1to"));
+        assert_eq!(view.inputs[0].prevout, Some(("bb".to_string(), 1)));
+        assert_eq!(view.fee, Some(10_000));
+    }
+
+    #[test]
+    fn test_activity_entries_grouped_by_tx() {
+        let delta = |txid: &str, height, satoshis| AddressDelta {
+            address: "t1addr".to_string(),
+            txid: txid.to_string(),
+            index: 0,
+            height,
+            satoshis,
+        };
+        let entries = activity_entries(vec![
+            delta("aa", 1, 5_000),
+            delta("bb", 2, -3_000),
+            delta("aa", 1, 2_000),
+        ]);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].txid, "bb");
+        assert_eq!(entries[1].delta, 7_000);
+    }
+}
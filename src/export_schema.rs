@@ -0,0 +1,382 @@
+//! Versioned JSON exports
+//!
+//! Downstream pipelines (accounting, BI, compliance tooling) consume the
+//! SDK's records as JSON. To keep them working as the SDK evolves, every
+//! JSON export is wrapped in an [`ExportDocument`]:
+//!
+//! ```json
+//! {
+//!   "kind": "transactions",
+//!   "schema_version": 1,
+//!   "sdk_version": "0.1.0",
+//!   "network": "Mainnet",
+//!   "generated_at": 1700000000,
+//!   "items": [ ... ]
+//! }
+//! ```
+//!
+//! `kind` names the item type ([`ExportArtifact::KIND`]):
+//!
+//! | kind | item |
+//! |------|------|
+//! | `transactions` | [`Transaction`] |
+//! | `invoices` | [`Invoice`] |
+//! | `audit_packages` | [`AuditPackage`] |
+//! | `receipts` | [`PaymentReceipt`] |
+//! | `counterparty_report` | [`CounterpartyActivity`] |
+//! | `reconciliation_report` | [`ReconciliationReport`] |
+//! | `balance_history` | [`BalanceSnapshot`] |
+//! | `fiat_values` | [`FiatValue`] |
+//!
+//! The items are the serde serialization of those types, documented on
+//! their fields. Amounts are in zatoshis and times in Unix seconds unless a
+//! field says otherwise.
+//!
+//! Compatibility rules for [`EXPORT_SCHEMA_VERSION`]:
+//! - Within a schema version, fields are only added, never renamed,
+//!   removed or changed in meaning; consumers should ignore unknown fields.
+//! - Any other change bumps the schema version.
+//! - [`ExportDocument::from_json`] reads documents of the current and
+//!   earlier schema versions and rejects newer ones.
+//!
+//! The tests below hold a version 1 document of each main kind; current
+//! exports must stay readable from them and serialize every field they
+//! contain unchanged.
+//!
+//! Sealed or binary exports ([`crate::backup`], [`crate::snapshot`],
+//! [`crate::key_export`], [`crate::interchange`]) carry their own `version`.
+
+use crate::audit::AuditPackage;
+use crate::counterparty::CounterpartyActivity;
+use crate::error::{Error, Result};
+use crate::invoices::Invoice;
+use crate::price_history::FiatValue;
+use crate::receipt::PaymentReceipt;
+use crate::reconcile::ReconciliationReport;
+use crate::store::unix_now;
+use crate::types::{Network, Transaction};
+use crate::wallet::balance_history::BalanceSnapshot;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Current schema version of JSON exports
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+/// A record type exported as JSON
+pub trait ExportArtifact: Serialize + DeserializeOwned {
+    /// `kind` of documents holding this type
+    const KIND: &'static str;
+}
+
+impl ExportArtifact for Transaction {
+    const KIND: &'static str = "transactions";
+}
+
+impl ExportArtifact for Invoice {
+    const KIND: &'static str = "invoices";
+}
+
+impl ExportArtifact for AuditPackage {
+    const KIND: &'static str = "audit_packages";
+}
+
+impl ExportArtifact for PaymentReceipt {
+    const KIND: &'static str = "receipts";
+}
+
+impl ExportArtifact for CounterpartyActivity {
+    const KIND: &'static str = "counterparty_report";
+}
+
+impl ExportArtifact for ReconciliationReport {
+    const KIND: &'static str = "reconciliation_report";
+}
+
+impl ExportArtifact for BalanceSnapshot {
+    const KIND: &'static str = "balance_history";
+}
+
+impl ExportArtifact for FiatValue {
+    const KIND: &'static str = "fiat_values";
+}
+
+/// Versioned JSON export of records of one kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportDocument<T> {
+    /// [`ExportArtifact::KIND`] of the items
+    pub kind: String,
+    /// Schema version the document was written with
+    pub schema_version: u32,
+    /// Version of the SDK that wrote the document
+    pub sdk_version: String,
+    pub network: Network,
+    /// Unix time the document was written
+    pub generated_at: u64,
+    pub items: Vec<T>,
+}
+
+/// Fields read before the items, to check the kind and version first
+#[derive(Deserialize)]
+struct DocumentHeader {
+    kind: String,
+    schema_version: u32,
+}
+
+impl<T: ExportArtifact> ExportDocument<T> {
+    /// Wrap records in a document of the current schema version
+    pub fn new(network: Network, items: Vec<T>) -> Self {
+        Self {
+            kind: T::KIND.to_string(),
+            schema_version: EXPORT_SCHEMA_VERSION,
+            sdk_version: env!("CARGO_PKG_VERSION").to_string(),
+            network,
+            generated_at: unix_now(),
+            items,
+        }
+    }
+
+    /// Serialize the document as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse a document, checking its kind and schema version
+    ///
+    /// # Returns
+    /// The document, or [`Error::InvalidParameter`] if it holds another kind
+    /// of record or was written with a newer schema version
+    pub fn from_json(json: &str) -> Result<Self> {
+        let header: DocumentHeader = serde_json::from_str(json)
+            .map_err(|e| Error::InvalidParameter(format!("Not an SDK export: {}", e)))?;
+        if header.kind != T::KIND {
+            return Err(Error::InvalidParameter(format!(
+                "Expected an export of {}, got {}",
+                T::KIND,
+                header.kind
+            )));
+        }
+        if header.schema_version > EXPORT_SCHEMA_VERSION {
+            return Err(Error::InvalidParameter(format!(
+                "Export schema version {} is newer than the supported version {}",
+                header.schema_version, EXPORT_SCHEMA_VERSION
+            )));
+        }
+        serde_json::from_str(json)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid {} export: {}", T::KIND, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSACTIONS_V1: &str = r#"{
+        "kind": "transactions",
+        "schema_version": 1,
+        "sdk_version": "0.1.0",
+        "network": "Mainnet",
+        "generated_at": 1700000000,
+        "items": [
+            {
+                "txid": "5f2b",
+                "status": { "Confirmed": { "height": 2500000 } },
+                "amount": -150000,
+                "fee": 10000,
+                "memo": "Invoice 17",
+                "timestamp": 1699990000
+            },
+            {
+                "txid": "7c01",
+                "status": "Pending",
+                "amount": 200000,
+                "fee": 0,
+                "memo": null,
+                "timestamp": null
+            }
+        ]
+    }"#;
+
+    const INVOICES_V1: &str = r#"{
+        "kind": "invoices",
+        "schema_version": 1,
+        "sdk_version": "0.1.0",
+        "network": "Testnet",
+        "generated_at": 1700000000,
+        "items": [
+            {
+                "id": "inv-1",
+                "address": "utest1abc",
+                "diversifier_index": 3,
+                "amount": 500000,
+                "memo": "Order 9",
+                "created_at": 1699990000,
+                "expires_at": 1699993600,
+                "status": "Paid",
+                "payments": [
+                    {
+                        "txid": "5f2b",
+                        "output_index": 0,
+                        "amount": 500000,
+                        "height": 2500000,
+                        "profile": "default"
+                    }
+                ],
+                "confirmed_amount": 500000,
+                "pending_amount": 0
+            }
+        ]
+    }"#;
+
+    const AUDIT_PACKAGES_V1: &str = r#"{
+        "kind": "audit_packages",
+        "schema_version": 1,
+        "sdk_version": "0.1.0",
+        "network": "Mainnet",
+        "generated_at": 1700000000,
+        "items": [
+            {
+                "account": 0,
+                "since": 1699900000,
+                "entries": [
+                    {
+                        "id": 1,
+                        "timestamp": 1699990000,
+                        "account": 0,
+                        "event": {
+                            "type": "send_submitted",
+                            "from_address": "u1from",
+                            "recipients": ["u1to"],
+                            "amount": 150000,
+                            "operation_id": "opid-1",
+                            "payments": [
+                                { "address": "u1to", "amount": 150000, "memo": null }
+                            ]
+                        },
+                        "correlation_id": "payout-1"
+                    },
+                    {
+                        "id": 2,
+                        "timestamp": 1699990100,
+                        "account": 0,
+                        "event": {
+                            "type": "send_completed",
+                            "operation_id": "opid-1",
+                            "txid": "5f2b"
+                        },
+                        "correlation_id": "payout-1"
+                    }
+                ],
+                "transactions": [
+                    {
+                        "txid": "5f2b",
+                        "status": { "Confirmed": { "height": 2500000 } },
+                        "amount": -150000,
+                        "fee": 10000,
+                        "memo": null,
+                        "timestamp": 1699990000
+                    }
+                ],
+                "fiat_values": [
+                    {
+                        "txid": "5f2b",
+                        "currency": "USD",
+                        "price": 30.0,
+                        "value": -0.045,
+                        "time": 1699990000,
+                        "source": "exchange"
+                    }
+                ]
+            }
+        ]
+    }"#;
+
+    const RECEIPTS_V1: &str = r#"{
+        "kind": "receipts",
+        "schema_version": 1,
+        "sdk_version": "0.1.0",
+        "network": "Mainnet",
+        "generated_at": 1700000000,
+        "items": [
+            {
+                "txid": "7c01",
+                "network": "Mainnet",
+                "amount": 200000,
+                "outputs": [
+                    {
+                        "pool": "Orchard",
+                        "output_index": 1,
+                        "value": 200000,
+                        "address": "u1me",
+                        "memo": "Thanks",
+                        "opening": {
+                            "diversifier": "00112233445566778899aa",
+                            "rcm": null,
+                            "rho": "aa",
+                            "rseed": "bb"
+                        }
+                    }
+                ],
+                "height": 2500010,
+                "block_hash": "0000abcd",
+                "block_time": 1699991000,
+                "confirmations": 10,
+                "explorer_url": "https://mainnet.zcashexplorer.app/transactions/7c01"
+            }
+        ]
+    }"#;
+
+    /// Whether every field of `old` is in `new` with the same value
+    fn contains_fields(new: &serde_json::Value, old: &serde_json::Value) -> bool {
+        match (new, old) {
+            (serde_json::Value::Object(new), serde_json::Value::Object(old)) => old
+                .iter()
+                .all(|(key, old)| new.get(key).is_some_and(|new| contains_fields(new, old))),
+            (serde_json::Value::Array(new), serde_json::Value::Array(old)) => {
+                new.len() == old.len()
+                    && new
+                        .iter()
+                        .zip(old)
+                        .all(|(new, old)| contains_fields(new, old))
+            }
+            _ => new == old,
+        }
+    }
+
+    /// A v1 document can be read and is written back without losing or
+    /// changing any of its fields
+    fn check_compatible<T: ExportArtifact>(fixture: &str) -> ExportDocument<T> {
+        let document = ExportDocument::<T>::from_json(fixture).unwrap();
+        let written = serde_json::to_value(&document).unwrap();
+        let fixture: serde_json::Value = serde_json::from_str(fixture).unwrap();
+        assert!(
+            contains_fields(&written, &fixture),
+            "{} export no longer matches schema version 1",
+            T::KIND
+        );
+        document
+    }
+
+    #[test]
+    fn test_schema_v1_compatibility() {
+        let transactions = check_compatible::<Transaction>(TRANSACTIONS_V1);
+        assert_eq!(transactions.items[0].amount, -150_000);
+        let invoices = check_compatible::<Invoice>(INVOICES_V1);
+        assert_eq!(invoices.items[0].payments.len(), 1);
+        let packages = check_compatible::<AuditPackage>(AUDIT_PACKAGES_V1);
+        assert_eq!(packages.items[0].entries.len(), 2);
+        let receipts = check_compatible::<PaymentReceipt>(RECEIPTS_V1);
+        assert_eq!(receipts.items[0].network, Network::Mainnet);
+
+        // Kind and version are checked before the items
+        assert!(ExportDocument::<Invoice>::from_json(TRANSACTIONS_V1).is_err());
+        let newer = TRANSACTIONS_V1.replace("\"schema_version\": 1", "\"schema_version\": 2");
+        assert!(ExportDocument::<Transaction>::from_json(&newer).is_err());
+
+        // New documents round-trip
+        let document = ExportDocument::new(Network::Testnet, transactions.items);
+        let parsed =
+            ExportDocument::<Transaction>::from_json(&document.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.schema_version, EXPORT_SCHEMA_VERSION);
+        assert_eq!(parsed.items.len(), 2);
+    }
+}
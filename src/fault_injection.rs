@@ -0,0 +1,304 @@
+//! Fault injection for resilience tests
+//!
+//! Retry, failover and stale-tip handling are hard to test against healthy
+//! infrastructure. A [`FaultInjector`] makes a client misbehave the way
+//! Zcash infrastructure does in practice:
+//! - Latency added to every call (RPC) or response (lightwalletd)
+//! - Dropped connections: RPC calls fail with a connection error, and
+//!   lightwalletd connections are reset, also in the middle of a stream
+//! - Malformed responses: RPC bodies are truncated, and lightwalletd bytes
+//!   are corrupted on the wire (surfacing as protocol or decode errors)
+//! - Stale heights: chain tip heights are reported some blocks behind
+//!
+//! Attach it with [`RpcClient::with_fault_injector`] or
+//! [`LightClient::set_fault_injector`]; lightwalletd faults require a
+//! plain-HTTP endpoint. The plan can be changed while the client is in use,
+//! e.g. to simulate an outage and a recovery, and [`FaultInjector::stats`]
+//! counts the faults injected so tests can check they were exercised.
+//!
+//! [`RpcClient::with_fault_injector`]: crate::client::RpcClient::with_fault_injector
+//! [`LightClient::set_fault_injector`]: crate::light_client::LightClient::set_fault_injector
+
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Faults to inject
+///
+/// Rates are probabilities between 0 and 1, applied per RPC call or, for
+/// lightwalletd, per socket read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaultPlan {
+    /// Delay before each RPC call and each lightwalletd response
+    pub latency: Duration,
+    /// Random extra delay of up to this much
+    pub jitter: Duration,
+    /// Rate of dropped connections
+    pub drop_rate: f64,
+    /// Rate of malformed responses
+    pub malformed_rate: f64,
+    /// Blocks by which reported tip heights lag behind
+    pub stale_blocks: u64,
+}
+
+impl FaultPlan {
+    /// No faults
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Every connection drops, as during an outage
+    pub fn outage() -> Self {
+        Self {
+            drop_rate: 1.0,
+            ..Self::default()
+        }
+    }
+}
+
+/// Faults injected so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FaultStats {
+    pub delayed: u64,
+    pub dropped: u64,
+    pub malformed: u64,
+    pub stale_heights: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    delayed: AtomicU64,
+    dropped: AtomicU64,
+    malformed: AtomicU64,
+    stale_heights: AtomicU64,
+}
+
+/// Shared, adjustable fault plan of one or more clients
+#[derive(Clone)]
+pub struct FaultInjector {
+    plan: Arc<Mutex<FaultPlan>>,
+    counters: Arc<Counters>,
+}
+
+impl FaultInjector {
+    /// Inject the faults of `plan`
+    pub fn new(plan: FaultPlan) -> Self {
+        Self {
+            plan: Arc::new(Mutex::new(plan)),
+            counters: Arc::new(Counters::default()),
+        }
+    }
+
+    /// The current plan
+    pub fn plan(&self) -> FaultPlan {
+        self.plan.lock().unwrap().clone()
+    }
+
+    /// Replace the plan, taking effect for the next calls of every client
+    /// sharing this injector
+    pub fn set_plan(&self, plan: FaultPlan) {
+        *self.plan.lock().unwrap() = plan;
+    }
+
+    /// Faults injected so far
+    pub fn stats(&self) -> FaultStats {
+        FaultStats {
+            delayed: self.counters.delayed.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            malformed: self.counters.malformed.load(Ordering::Relaxed),
+            stale_heights: self.counters.stale_heights.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Delay to add before a call or response, if any
+    pub(crate) fn delay(&self) -> Option<Duration> {
+        let plan = self.plan.lock().unwrap();
+        let jitter = plan.jitter.mul_f64(rand::random::<f64>());
+        let delay = plan.latency + jitter;
+        if delay.is_zero() {
+            return None;
+        }
+        self.counters.delayed.fetch_add(1, Ordering::Relaxed);
+        Some(delay)
+    }
+
+    /// Sleep for the injected latency
+    pub(crate) async fn wait(&self) {
+        if let Some(delay) = self.delay() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Whether to drop the connection now
+    pub(crate) fn drop_connection(&self) -> bool {
+        let rate = self.plan.lock().unwrap().drop_rate;
+        self.roll(rate, &self.counters.dropped)
+    }
+
+    /// Whether to corrupt the response now
+    pub(crate) fn malform(&self) -> bool {
+        let rate = self.plan.lock().unwrap().malformed_rate;
+        self.roll(rate, &self.counters.malformed)
+    }
+
+    /// A tip height as reported by a server lagging behind
+    pub(crate) fn stale_height(&self, height: u64) -> u64 {
+        let lag = self.plan.lock().unwrap().stale_blocks;
+        if lag == 0 {
+            return height;
+        }
+        self.counters.stale_heights.fetch_add(1, Ordering::Relaxed);
+        height.saturating_sub(lag)
+    }
+
+    fn roll(&self, rate: f64, counter: &AtomicU64) -> bool {
+        let hit = rate > 0.0 && rand::random::<f64>() < rate;
+        if hit {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        hit
+    }
+}
+
+/// First half of a response body, which is never valid JSON
+pub(crate) fn truncate_body(body: &[u8]) -> &[u8] {
+    &body[..body.len() / 2]
+}
+
+/// Socket wrapper injecting latency, resets and corrupted bytes into the
+/// responses read from it
+pub(crate) struct FaultyIo<T> {
+    inner: T,
+    faults: FaultInjector,
+    /// Whether a request was written since the last response was read
+    awaiting_response: bool,
+    delay: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl<T> FaultyIo<T> {
+    pub(crate) fn new(inner: T, faults: FaultInjector) -> Self {
+        Self {
+            inner,
+            faults,
+            awaiting_response: false,
+            delay: None,
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for FaultyIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.awaiting_response {
+            self.awaiting_response = false;
+            self.delay = self.faults.delay().map(|d| Box::pin(tokio::time::sleep(d)));
+        }
+        if let Some(delay) = self.delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            if buf.filled().len() > before {
+                if self.faults.drop_connection() {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::ConnectionReset,
+                        "connection dropped by fault injection",
+                    )));
+                }
+                if self.faults.malform() {
+                    let read = &mut buf.filled_mut()[before..];
+                    let index = rand::random::<usize>() % read.len();
+                    read[index] ^= 0xff;
+                }
+            }
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for FaultyIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(_)) = poll {
+            self.awaiting_response = true;
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_faulty_io() {
+        let faults = FaultInjector::new(FaultPlan {
+            latency: Duration::from_millis(20),
+            stale_blocks: 10,
+            ..FaultPlan::none()
+        });
+        assert_eq!(faults.stale_height(100), 90);
+        assert_eq!(faults.stale_height(5), 0);
+
+        // Responses are delayed, then corrupted, then the connection drops
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut io = FaultyIo::new(client, faults.clone());
+        io.write_all(b"ping").await.unwrap();
+        server.write_all(b"pong").await.unwrap();
+        let start = std::time::Instant::now();
+        let mut buf = [0u8; 4];
+        io.read_exact(&mut buf).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(&buf, b"pong");
+
+        faults.set_plan(FaultPlan {
+            malformed_rate: 1.0,
+            ..FaultPlan::none()
+        });
+        server.write_all(b"pong").await.unwrap();
+        io.read_exact(&mut buf).await.unwrap();
+        assert_ne!(&buf, b"pong");
+
+        faults.set_plan(FaultPlan::outage());
+        server.write_all(b"pong").await.unwrap();
+        let err = io.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        assert_eq!(
+            faults.stats(),
+            FaultStats {
+                delayed: 1,
+                dropped: 1,
+                malformed: 1,
+                stale_heights: 2,
+            }
+        );
+        assert!(truncate_body(br#"{"result":1}"#).len() < 12);
+    }
+}
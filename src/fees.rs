@@ -12,14 +12,28 @@
 //! - Transparent outputs
 //!
 //! See [ZIP-317](https://zips.z.cash/zip-0317) for detailed fee parameters and action accounting rules.
+//!
+//! [`analyze_transaction`] applies the exact accounting to a serialized
+//! transaction offline, e.g. to check a transaction's fee before broadcast.
 
 use crate::error::{Error, Result};
 use crate::rpc::Payment;
+use crate::transaction::decode::read_transaction;
+use crate::types::utils::zec_to_zatoshis;
+use serde::{Deserialize, Serialize};
 
 /// ZIP-317 fee parameters
 const FEE_BASE: u64 = 5000; // zatoshis per logical action
 const MIN_LOGICAL_ACTIONS: u64 = 2; // minimum logical actions for fee calculation
 
+/// Serialized size of a P2PKH input; transparent inputs count one logical
+/// action per this many bytes
+pub const P2PKH_STANDARD_INPUT_SIZE: usize = 150;
+
+/// Serialized size of a P2PKH output; transparent outputs count one logical
+/// action per this many bytes
+pub const P2PKH_STANDARD_OUTPUT_SIZE: usize = 34;
+
 /// Calculate ZIP-317 conventional fee for a transaction
 ///
 /// This calculates the fee based on logical actions in the transaction.
@@ -128,7 +142,143 @@ pub fn fee_zec_to_zatoshis(fee_zec: f64) -> Result<u64> {
     if fee_zec < 0.0 {
         return Err(Error::Transaction("Fee cannot be negative".to_string()));
     }
-    Ok((fee_zec * 100_000_000.0) as u64)
+    Ok(zec_to_zatoshis(fee_zec))
+}
+
+/// The parts of a transaction that ZIP-317 counts as logical actions
+///
+/// For a proposed transaction, count each P2PKH input as
+/// [`P2PKH_STANDARD_INPUT_SIZE`] bytes and each P2PKH output as
+/// [`P2PKH_STANDARD_OUTPUT_SIZE`] bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionShape {
+    /// Total serialized size of the transparent inputs
+    pub transparent_input_bytes: usize,
+    /// Total serialized size of the transparent outputs
+    pub transparent_output_bytes: usize,
+    pub sprout_joinsplits: usize,
+    pub sapling_spends: usize,
+    pub sapling_outputs: usize,
+    pub orchard_actions: usize,
+}
+
+impl TransactionShape {
+    /// ZIP-317 logical actions
+    pub fn logical_actions(&self) -> u64 {
+        let inputs = self
+            .transparent_input_bytes
+            .div_ceil(P2PKH_STANDARD_INPUT_SIZE);
+        let outputs = self
+            .transparent_output_bytes
+            .div_ceil(P2PKH_STANDARD_OUTPUT_SIZE);
+        (inputs.max(outputs)
+            + 2 * self.sprout_joinsplits
+            + self.sapling_spends.max(self.sapling_outputs)
+            + self.orchard_actions) as u64
+    }
+
+    /// ZIP-317 conventional fee in zatoshis
+    pub fn conventional_fee(&self) -> u64 {
+        calculate_zip317_fee(self.logical_actions())
+    }
+}
+
+/// Size and ZIP-317 accounting of a serialized transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionAnalysis {
+    pub txid: String,
+    /// Serialized size in bytes
+    pub size: usize,
+    pub transparent_inputs: usize,
+    pub transparent_outputs: usize,
+    pub shape: TransactionShape,
+    pub logical_actions: u64,
+    /// ZIP-317 conventional fee in zatoshis
+    pub conventional_fee: u64,
+    /// Fee paid in zatoshis, when it is known without the values of the
+    /// transparent inputs
+    pub fee: Option<u64>,
+}
+
+impl TransactionAnalysis {
+    /// Whether the fee paid is below the ZIP-317 conventional fee, which
+    /// nodes may refuse to relay or mine
+    ///
+    /// # Returns
+    /// `None` when the fee paid is unknown
+    pub fn is_underpaying(&self) -> Option<bool> {
+        self.fee.map(|fee| fee < self.conventional_fee)
+    }
+}
+
+/// Compute the size and ZIP-317 logical actions of a serialized transaction
+///
+/// Works offline, e.g. as a sanity check before broadcast. Trailing bytes
+/// after the transaction are rejected.
+///
+/// # Arguments
+/// * `bytes` - Serialized v4 or v5 transaction
+pub fn analyze_transaction(bytes: &[u8]) -> Result<TransactionAnalysis> {
+    let (_, _, tx) = read_transaction(bytes)?;
+    let serialized_len = |write: &dyn Fn(&mut Vec<u8>) -> std::io::Result<()>| {
+        let mut buf = Vec::new();
+        write(&mut buf)
+            .map(|_| buf.len())
+            .map_err(|e| Error::Transaction(format!("Failed to serialize transaction: {}", e)))
+    };
+
+    let size = serialized_len(&|buf| tx.write(buf))?;
+    if size != bytes.len() {
+        return Err(Error::Transaction(format!(
+            "Transaction is followed by {} trailing bytes",
+            bytes.len().saturating_sub(size)
+        )));
+    }
+
+    let mut shape = TransactionShape::default();
+    let mut transparent_inputs = 0;
+    let mut transparent_outputs = 0;
+    let mut is_coinbase = false;
+    // Net value entering the transaction from inputs and shielded pools
+    let mut value_in = 0i128;
+    if let Some(bundle) = tx.transparent_bundle() {
+        is_coinbase = bundle.is_coinbase();
+        transparent_inputs = bundle.vin.len();
+        transparent_outputs = bundle.vout.len();
+        for input in &bundle.vin {
+            shape.transparent_input_bytes += serialized_len(&|buf| input.write(buf))?;
+        }
+        for output in &bundle.vout {
+            shape.transparent_output_bytes += serialized_len(&|buf| output.write(buf))?;
+            value_in -= i128::from(u64::from(output.value()));
+        }
+    }
+    shape.sprout_joinsplits = tx.sprout_bundle().map_or(0, |b| b.joinsplits.len());
+    if let Some(bundle) = tx.sapling_bundle() {
+        shape.sapling_spends = bundle.shielded_spends().len();
+        shape.sapling_outputs = bundle.shielded_outputs().len();
+        value_in += i128::from(i64::from(*bundle.value_balance()));
+    }
+    if let Some(bundle) = tx.orchard_bundle() {
+        shape.orchard_actions = bundle.actions().len();
+        value_in += i128::from(i64::from(*bundle.value_balance()));
+    }
+
+    let fee = if transparent_inputs == 0 && !is_coinbase && shape.sprout_joinsplits == 0 {
+        u64::try_from(value_in).ok()
+    } else {
+        None
+    };
+    Ok(TransactionAnalysis {
+        txid: tx.txid().to_string(),
+        size,
+        transparent_inputs,
+        transparent_outputs,
+        shape,
+        logical_actions: shape.logical_actions(),
+        conventional_fee: shape.conventional_fee(),
+        fee,
+    })
 }
 
 #[cfg(test)]
@@ -165,6 +315,36 @@ mod tests {
         assert!(fee_zec_to_zatoshis(-0.0001).is_err());
     }
 
+    #[test]
+    fn test_transaction_shape() {
+        // Two P2PKH inputs and one output count as two logical actions
+        let shape = TransactionShape {
+            transparent_input_bytes: 2 * P2PKH_STANDARD_INPUT_SIZE,
+            transparent_output_bytes: P2PKH_STANDARD_OUTPUT_SIZE,
+            ..Default::default()
+        };
+        assert_eq!(shape.logical_actions(), 2);
+
+        // A slightly larger input rounds up; Sapling counts the larger side
+        let shape = TransactionShape {
+            transparent_input_bytes: P2PKH_STANDARD_INPUT_SIZE + 1,
+            sapling_spends: 1,
+            sapling_outputs: 2,
+            orchard_actions: 2,
+            ..Default::default()
+        };
+        assert_eq!(shape.logical_actions(), 6);
+        assert_eq!(shape.conventional_fee(), 30_000);
+
+        assert_eq!(TransactionShape::default().conventional_fee(), 10_000);
+    }
+
+    #[test]
+    fn test_analyze_transaction_rejects_garbage() {
+        assert!(analyze_transaction(&[]).is_err());
+        assert!(analyze_transaction(&[0u8; 16]).is_err());
+    }
+
     #[test]
     fn test_estimate_logical_actions_shielded() {
         let payments = vec![
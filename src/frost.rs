@@ -0,0 +1,430 @@
+//! FROST threshold multisig accounts
+//!
+//! A FROST account splits the Orchard spend authorizing key between `n`
+//! participants so that any `t` of them can jointly authorize a spend, while
+//! fewer learn nothing about the key. Signatures use re-randomized FROST over
+//! RedPallas, so each one verifies under a fresh randomized key `rk` exactly
+//! like a single-signer Orchard spend authorization.
+//!
+//! The account's full viewing key is shared by all participants: anyone
+//! holding a [`FrostAccount`] can scan, see balances and build transactions
+//! with [`Wallet::import_frost_account`](crate::wallet::Wallet::import_frost_account),
+//! but spending needs a signing ceremony:
+//! 1. The coordinator starts a session and every selected participant calls
+//!    [`FrostParticipant::round1`], sending back a [`Round1Message`]
+//! 2. Once `t` commitments arrived, [`FrostCoordinator::signing_request`]
+//!    fixes the message (the transaction sighash) and the randomizer
+//! 3. Each participant answers the [`SigningRequest`] with
+//!    [`FrostParticipant::round2`]
+//! 4. [`FrostCoordinator::aggregate`] checks the shares and combines them
+//!    into a [`FrostSignature`]
+//!
+//! The coordinator picks the randomizer, so the transaction's Orchard action
+//! must commit to the returned [`FrostSignature::randomized_key`].
+//!
+//! Messages are passed between participants by the application. Nonces are
+//! held in memory and used at most once; a participant that restarts
+//! mid-ceremony must start a new session.
+//!
+//! Keys are split by a trusted dealer ([`generate_with_dealer`]), which must
+//! erase the spending key material after distributing the shares. Sapling
+//! spends are not supported.
+//!
+//! Requires the `frost` feature.
+
+use crate::error::{Error, Result};
+use frost_rerandomized::frost_core::{Ciphersuite, Group};
+use frost_rerandomized::RandomizedParams;
+use reddsa::frost::redpallas::keys::{IdentifierList, KeyPackage, PublicKeyPackage};
+use reddsa::frost::redpallas::round1::{SigningCommitments, SigningNonces};
+use reddsa::frost::redpallas::round2::SignatureShare;
+use reddsa::frost::redpallas::{self, Identifier, PallasBlake2b512, SigningPackage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use zcash_keys::keys::UnifiedFullViewingKey;
+
+/// Maximum number of dealer attempts to find a group key usable as Orchard `ak`
+const MAX_DEALER_ATTEMPTS: usize = 64;
+
+type RandomizerPoint = <<PallasBlake2b512 as Ciphersuite>::Group as Group>::Element;
+
+/// Public description of a FROST account, shared by all participants
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrostAccount {
+    /// Number of participants needed to sign
+    pub threshold: u16,
+    /// Total number of participants
+    pub participants: u16,
+    /// Orchard full viewing key (`ak || nk || rivk`), hex encoded
+    pub orchard_fvk: String,
+}
+
+impl FrostAccount {
+    /// Unified full viewing key for importing the account into a wallet
+    pub fn ufvk(&self) -> Result<UnifiedFullViewingKey> {
+        let bytes: [u8; 96] = hex::decode(&self.orchard_fvk)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                Error::InvalidParameter("Invalid FROST account viewing key".to_string())
+            })?;
+        let fvk = orchard::keys::FullViewingKey::from_bytes(&bytes).ok_or_else(|| {
+            Error::InvalidParameter("Invalid FROST account viewing key".to_string())
+        })?;
+        UnifiedFullViewingKey::from_orchard_fvk(fvk)
+            .map_err(|e| Error::KeyDerivation(format!("Failed to build viewing key: {:?}", e)))
+    }
+}
+
+/// A participant's share of a FROST account, as handed out by the dealer
+///
+/// Deliberately not `Debug`: it holds a secret key share.
+pub struct FrostKeyShare {
+    /// Participant number, from 1 to the number of participants
+    pub participant: u16,
+    pub key_package: KeyPackage,
+    pub public_key_package: PublicKeyPackage,
+    pub account: FrostAccount,
+}
+
+/// Split a new Orchard spend authorizing key between `participants`
+///
+/// The viewing key components (`nk`, `rivk`) are drawn from a fresh random
+/// Orchard spending key; the dealer retries until the FROST group key is a
+/// valid Orchard `ak`.
+///
+/// # Arguments
+/// * `threshold` - Number of participants needed to sign (at least 2)
+/// * `participants` - Total number of participants
+///
+/// # Returns
+/// One key share per participant, to be delivered over a secure channel
+pub fn generate_with_dealer(threshold: u16, participants: u16) -> Result<Vec<FrostKeyShare>> {
+    if threshold < 2 || threshold > participants {
+        return Err(Error::InvalidParameter(format!(
+            "Invalid {}-of-{} threshold",
+            threshold, participants
+        )));
+    }
+    let identifiers = (1..=participants)
+        .map(identifier)
+        .collect::<Result<Vec<_>>>()?;
+    let mut rng = rand::rngs::OsRng;
+
+    let viewing = loop {
+        let mut seed = [0u8; 32];
+        rand::RngCore::fill_bytes(&mut rng, &mut seed);
+        if let Some(sk) = Option::from(orchard::keys::SpendingKey::from_bytes(seed)) {
+            break orchard::keys::FullViewingKey::from(&sk).to_bytes();
+        }
+    };
+
+    for _ in 0..MAX_DEALER_ATTEMPTS {
+        let (shares, public_key_package) = redpallas::keys::generate_with_dealer(
+            participants,
+            threshold,
+            IdentifierList::Custom(&identifiers),
+            &mut rng,
+        )
+        .map_err(|e| Error::KeyDerivation(format!("FROST key generation failed: {:?}", e)))?;
+
+        let mut fvk = viewing;
+        fvk[..32].copy_from_slice(&public_key_package.group_public().serialize());
+        if orchard::keys::FullViewingKey::from_bytes(&fvk).is_none() {
+            // Orchard requires ak with a canonical sign; draw a new polynomial
+            continue;
+        }
+
+        let account = FrostAccount {
+            threshold,
+            participants,
+            orchard_fvk: hex::encode(fvk),
+        };
+        let mut key_shares = Vec::with_capacity(usize::from(participants));
+        for (participant, id) in (1..=participants).zip(&identifiers) {
+            let share = shares
+                .get(id)
+                .cloned()
+                .ok_or_else(|| Error::KeyDerivation("Missing FROST key share".to_string()))?;
+            let key_package = KeyPackage::try_from(share)
+                .map_err(|e| Error::KeyDerivation(format!("Invalid FROST key share: {:?}", e)))?;
+            key_shares.push(FrostKeyShare {
+                participant,
+                key_package,
+                public_key_package: public_key_package.clone(),
+                account: account.clone(),
+            });
+        }
+        return Ok(key_shares);
+    }
+    Err(Error::KeyDerivation(
+        "Failed to generate a FROST group key usable for Orchard".to_string(),
+    ))
+}
+
+/// A participant's commitment for one signing session
+#[derive(Clone)]
+pub struct Round1Message {
+    pub session_id: String,
+    pub participant: u16,
+    pub commitments: SigningCommitments,
+}
+
+/// What the coordinator asks the participants to sign
+#[derive(Clone)]
+pub struct SigningRequest {
+    pub session_id: String,
+    pub signing_package: SigningPackage,
+    randomizer_point: RandomizerPoint,
+}
+
+/// A participant's signature share for one signing session
+#[derive(Clone)]
+pub struct Round2Message {
+    pub session_id: String,
+    pub participant: u16,
+    pub share: SignatureShare,
+}
+
+/// A spend authorization signature produced by a ceremony
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrostSignature {
+    /// RedPallas signature bytes
+    pub signature: [u8; 64],
+    /// Randomized verification key `rk` the signature verifies under
+    pub randomized_key: [u8; 32],
+}
+
+/// One participant's side of a FROST account
+///
+/// Deliberately not `Debug`: it holds a secret key share and nonces.
+pub struct FrostParticipant {
+    participant: u16,
+    key_package: KeyPackage,
+    nonces: HashMap<String, SigningNonces>,
+}
+
+impl FrostParticipant {
+    /// Create a participant from the share received from the dealer
+    pub fn new(share: FrostKeyShare) -> Self {
+        Self {
+            participant: share.participant,
+            key_package: share.key_package,
+            nonces: HashMap::new(),
+        }
+    }
+
+    /// Participant number within the account
+    pub fn participant(&self) -> u16 {
+        self.participant
+    }
+
+    /// Join a signing session by committing to fresh nonces
+    ///
+    /// Calling this again for the same session replaces the nonces, so
+    /// earlier commitments for it can no longer be used.
+    pub fn round1(&mut self, session_id: &str) -> Round1Message {
+        let (nonces, commitments) =
+            redpallas::round1::commit(self.key_package.secret_share(), &mut rand::rngs::OsRng);
+        self.nonces.insert(session_id.to_string(), nonces);
+        Round1Message {
+            session_id: session_id.to_string(),
+            participant: self.participant,
+            commitments,
+        }
+    }
+
+    /// Produce this participant's signature share for a signing request
+    ///
+    /// The session's nonces are consumed whether or not signing succeeds.
+    /// The caller is responsible for checking the message (e.g. that the
+    /// sighash belongs to a transaction the participant agreed to).
+    pub fn round2(&mut self, request: &SigningRequest) -> Result<Round2Message> {
+        let nonces = self.nonces.remove(&request.session_id).ok_or_else(|| {
+            Error::InvalidParameter(format!(
+                "No round-1 commitment for session {}",
+                request.session_id
+            ))
+        })?;
+        let share = redpallas::round2::sign(
+            &request.signing_package,
+            &nonces,
+            &self.key_package,
+            &request.randomizer_point,
+        )
+        .map_err(|e| Error::Transaction(format!("FROST signing failed: {:?}", e)))?;
+        Ok(Round2Message {
+            session_id: request.session_id.clone(),
+            participant: self.participant,
+            share,
+        })
+    }
+}
+
+/// Drives one signing session: collects commitments and aggregates shares
+///
+/// The coordinator learns no secrets and can be run by any participant or
+/// by a semi-trusted server.
+pub struct FrostCoordinator {
+    session_id: String,
+    threshold: u16,
+    public_key_package: PublicKeyPackage,
+    commitments: HashMap<u16, SigningCommitments>,
+    randomized_params: Option<RandomizedParams<PallasBlake2b512>>,
+}
+
+impl FrostCoordinator {
+    /// Start a signing session
+    pub fn new(session_id: &str, threshold: u16, public_key_package: PublicKeyPackage) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            threshold,
+            public_key_package,
+            commitments: HashMap::new(),
+            randomized_params: None,
+        }
+    }
+
+    /// Session ID participants must commit to
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Record a participant's round-1 commitment
+    pub fn add_commitment(&mut self, message: Round1Message) -> Result<()> {
+        self.check_session(&message.session_id)?;
+        if self.randomized_params.is_some() {
+            return Err(Error::InvalidParameter(
+                "Signing request already issued for this session".to_string(),
+            ));
+        }
+        self.commitments
+            .insert(message.participant, message.commitments);
+        Ok(())
+    }
+
+    /// Fix the message to sign and pick the randomizer
+    ///
+    /// # Arguments
+    /// * `message` - The data to sign, i.e. the transaction sighash
+    pub fn signing_request(&mut self, message: &[u8]) -> Result<SigningRequest> {
+        if self.commitments.len() < usize::from(self.threshold) {
+            return Err(Error::InvalidParameter(format!(
+                "Need {} commitments, have {}",
+                self.threshold,
+                self.commitments.len()
+            )));
+        }
+        let commitments = self
+            .commitments
+            .iter()
+            .map(|(participant, commitments)| Ok((identifier(*participant)?, *commitments)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let signing_package = SigningPackage::new(commitments, message);
+        let params =
+            RandomizedParams::new(self.public_key_package.group_public(), rand::rngs::OsRng);
+        let request = SigningRequest {
+            session_id: self.session_id.clone(),
+            signing_package,
+            randomizer_point: *params.randomizer_point(),
+        };
+        self.randomized_params = Some(params);
+        Ok(request)
+    }
+
+    /// Verify the participants' shares and combine them into a signature
+    ///
+    /// Fails if a share is missing or invalid; the error names the
+    /// misbehaving participant where FROST can identify one.
+    pub fn aggregate(
+        &self,
+        request: &SigningRequest,
+        shares: Vec<Round2Message>,
+    ) -> Result<FrostSignature> {
+        self.check_session(&request.session_id)?;
+        let params = self.randomized_params.as_ref().ok_or_else(|| {
+            Error::InvalidParameter("No signing request issued for this session".to_string())
+        })?;
+        let mut signature_shares = HashMap::new();
+        for message in shares {
+            self.check_session(&message.session_id)?;
+            if !self.commitments.contains_key(&message.participant) {
+                return Err(Error::InvalidParameter(format!(
+                    "Participant {} did not commit in round 1",
+                    message.participant
+                )));
+            }
+            signature_shares.insert(identifier(message.participant)?, message.share);
+        }
+        let signature = redpallas::aggregate(
+            &request.signing_package,
+            &signature_shares,
+            &self.public_key_package,
+            params,
+        )
+        .map_err(|e| Error::Transaction(format!("FROST aggregation failed: {:?}", e)))?;
+        Ok(FrostSignature {
+            signature: signature.serialize(),
+            randomized_key: params.randomized_group_public_key().serialize(),
+        })
+    }
+
+    fn check_session(&self, session_id: &str) -> Result<()> {
+        if session_id != self.session_id {
+            return Err(Error::InvalidParameter(format!(
+                "Message for session {} sent to session {}",
+                session_id, self.session_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn identifier(participant: u16) -> Result<Identifier> {
+    Identifier::try_from(participant)
+        .map_err(|_| Error::InvalidParameter(format!("Invalid participant {}", participant)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_of_three_ceremony() {
+        assert!(generate_with_dealer(1, 3).is_err());
+        assert!(generate_with_dealer(4, 3).is_err());
+
+        let shares = generate_with_dealer(2, 3).unwrap();
+        let account = shares[0].account.clone();
+        assert!(account.ufvk().unwrap().orchard().is_some());
+        let public_key_package = shares[0].public_key_package.clone();
+        let mut participants: Vec<_> = shares.into_iter().map(FrostParticipant::new).collect();
+
+        let mut coordinator = FrostCoordinator::new("s1", account.threshold, public_key_package);
+        coordinator
+            .add_commitment(participants[0].round1("s1"))
+            .unwrap();
+        assert!(coordinator.signing_request(b"sighash").is_err());
+        coordinator
+            .add_commitment(participants[2].round1("s1"))
+            .unwrap();
+        let request = coordinator.signing_request(b"sighash").unwrap();
+
+        // Participant 2 never committed, so it has nothing to sign with
+        assert!(participants[1].round2(&request).is_err());
+        let shares = vec![
+            participants[0].round2(&request).unwrap(),
+            participants[2].round2(&request).unwrap(),
+        ];
+        // Nonces are single-use
+        assert!(participants[0].round2(&request).is_err());
+
+        let signature = coordinator.aggregate(&request, shares).unwrap();
+        let rk = reddsa::VerificationKey::<reddsa::orchard::SpendAuth>::try_from(
+            signature.randomized_key,
+        )
+        .unwrap();
+        let sig = reddsa::Signature::<reddsa::orchard::SpendAuth>::from(signature.signature);
+        assert!(rk.verify(b"sighash", &sig).is_ok());
+    }
+}
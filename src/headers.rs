@@ -0,0 +1,153 @@
+//! User-Agent and extra headers for outgoing requests
+//!
+//! [`RpcClient`](crate::client::RpcClient) and
+//! [`LightClient`](crate::light_client::LightClient) send a
+//! [`RequestHeaders`] with every request: a User-Agent that identifies the
+//! application (so node operators can attribute traffic) and any extra
+//! headers, e.g. the API key or bearer token a managed node provider
+//! requires.
+//!
+//! Header names are sent lowercase, as HTTP/2 (and so gRPC) requires.
+
+use crate::error::{Error, Result};
+
+/// User-Agent sent when none is configured
+pub const DEFAULT_USER_AGENT: &str = concat!("zcash-numi-sdk/", env!("CARGO_PKG_VERSION"));
+
+/// Headers managed by the clients themselves, which cannot be overridden
+const RESERVED_HEADERS: &[&str] = &["content-length", "content-type", "host", "te", "user-agent"];
+
+/// User-Agent and extra headers sent with every request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHeaders {
+    user_agent: String,
+    extra: Vec<(String, String)>,
+}
+
+impl Default for RequestHeaders {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl RequestHeaders {
+    /// Headers with the default User-Agent and no extra headers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the User-Agent
+    pub fn with_user_agent(mut self, user_agent: &str) -> Result<Self> {
+        validate_value("User-Agent", user_agent)?;
+        self.user_agent = user_agent.to_string();
+        Ok(self)
+    }
+
+    /// Add a header, replacing any previous value of the same name
+    ///
+    /// # Arguments
+    /// * `name` - Header name (case-insensitive); the User-Agent and
+    ///   protocol headers such as `Content-Type` cannot be set here. An
+    ///   `Authorization` header replaces an RPC client's basic-auth
+    ///   credentials
+    /// * `value` - Header value (printable ASCII)
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self> {
+        let name = name.to_ascii_lowercase();
+        if name.is_empty() || !name.bytes().all(is_token_byte) || name.ends_with("-bin") {
+            return Err(Error::InvalidParameter(format!(
+                "Invalid header name: {:?}",
+                name
+            )));
+        }
+        if RESERVED_HEADERS.contains(&name.as_str()) {
+            return Err(Error::InvalidParameter(format!(
+                "Header {} is set by the client and cannot be overridden",
+                name
+            )));
+        }
+        validate_value(&name, value)?;
+        self.extra.retain(|(existing, _)| *existing != name);
+        self.extra.push((name, value.to_string()));
+        Ok(self)
+    }
+
+    /// The User-Agent
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// Value of an extra header
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.extra
+            .iter()
+            .find(|(existing, _)| *existing == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Extra headers as lowercase name and value pairs
+    pub fn extra(&self) -> &[(String, String)] {
+        &self.extra
+    }
+
+    /// Add the User-Agent and extra headers to an HTTP request
+    pub(crate) fn apply(&self, mut req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        req = req.header(reqwest::header::USER_AGENT, &self.user_agent);
+        for (name, value) in &self.extra {
+            req = req.header(name.as_str(), value.as_str());
+        }
+        req
+    }
+}
+
+/// Token characters allowed in header names (RFC 9110), lowercase only
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_lowercase() || b.is_ascii_digit() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Header values must be printable ASCII so they are also valid gRPC metadata
+fn validate_value(name: &str, value: &str) -> Result<()> {
+    if value.is_empty() || !value.bytes().all(|b| b == b' ' || b.is_ascii_graphic()) {
+        return Err(Error::InvalidParameter(format!(
+            "Invalid value for header {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_headers() {
+        let headers = RequestHeaders::new()
+            .with_user_agent("payouts/2.1")
+            .unwrap()
+            .with_header("X-Api-Key", "old")
+            .unwrap()
+            .with_header("x-api-key", "secret")
+            .unwrap();
+        assert_eq!(headers.user_agent(), "payouts/2.1");
+        assert_eq!(
+            headers.extra(),
+            &[("x-api-key".to_string(), "secret".to_string())]
+        );
+        assert_eq!(headers.get("X-API-KEY"), Some("secret"));
+        assert_eq!(RequestHeaders::new().user_agent(), DEFAULT_USER_AGENT);
+
+        assert!(RequestHeaders::new().with_header("bad name", "v").is_err());
+        assert!(RequestHeaders::new()
+            .with_header("x-token", "a\r\nb")
+            .is_err());
+        assert!(RequestHeaders::new()
+            .with_header("Content-Type", "v")
+            .is_err());
+        assert!(RequestHeaders::new().with_header("x-key-bin", "v").is_err());
+        assert!(RequestHeaders::new().with_user_agent("").is_err());
+    }
+}
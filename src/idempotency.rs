@@ -0,0 +1,256 @@
+//! Idempotent sends
+//!
+//! Applications that retry a payout after a timeout or crash must not pay it
+//! twice. Each send can carry a client-provided idempotency key; the key, a
+//! fingerprint of the request, and the resulting operation are recorded in a
+//! `numi_send_requests` table in the wallet database before and after the
+//! send is submitted. Retrying with the same key returns the original
+//! operation instead of sending again. Sends built locally and broadcast
+//! through lightwalletd record their transaction ID as the operation.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::rpc::Payment;
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// State of a send recorded under an idempotency key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SendState {
+    /// The key was reserved but the send has not been confirmed as submitted
+    ///
+    /// This happens when a previous attempt crashed or lost its connection
+    /// mid-request; the payment may or may not have been created.
+    InFlight,
+    /// The send was submitted and produced an operation ID
+    Submitted { operation_id: String },
+    /// The send completed with a transaction ID
+    Completed { operation_id: String, txid: String },
+}
+
+/// Outcome of reserving an idempotency key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reservation {
+    /// The key is new; the caller should perform the send
+    New,
+    /// The key was used before for the same request
+    Existing(SendState),
+}
+
+/// Fingerprint of a `send_many` request, used to detect key reuse
+pub fn request_fingerprint(
+    from_address: &str,
+    payments: &[Payment],
+    minconf: Option<u32>,
+    fee: Option<f64>,
+) -> Result<String> {
+    let encoded = serde_json::to_vec(&(from_address, payments, minconf, fee))?;
+    Ok(fingerprint(&encoded))
+}
+
+/// Fingerprint of a ZIP-321 payment request sent through a local signer,
+/// used to detect key reuse
+pub fn payment_request_fingerprint(request_uri: &str) -> String {
+    fingerprint(request_uri.as_bytes())
+}
+
+fn fingerprint(encoded: &[u8]) -> String {
+    let hash = blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(b"NumiSendRequest_")
+        .hash(encoded);
+    hex::encode(hash.as_bytes())
+}
+
+const DB_CONTEXT: &str = "Idempotency store error";
+
+/// Persistent record of sends by idempotency key
+pub struct IdempotencyStore {
+    conn: Connection,
+}
+
+impl IdempotencyStore {
+    /// Open (or create) the store in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_send_requests (
+                idempotency_key TEXT PRIMARY KEY,
+                fingerprint TEXT NOT NULL,
+                operation_id TEXT,
+                txid TEXT,
+                created_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    /// Open the store in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Reserve `key` for a request with the given fingerprint
+    ///
+    /// # Returns
+    /// [`Reservation::New`] if the caller should send, or the recorded state
+    /// if the key was already used for the same request
+    ///
+    /// # Errors
+    /// Fails if the key was already used for a different request
+    pub fn reserve(&self, key: &str, fingerprint: &str) -> Result<Reservation> {
+        if key.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Idempotency key must not be empty".to_string(),
+            ));
+        }
+
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO numi_send_requests (idempotency_key, fingerprint, created_at)
+                 VALUES (?1, ?2, ?3)",
+                params![key, fingerprint, unix_now() as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        if inserted > 0 {
+            return Ok(Reservation::New);
+        }
+
+        let (stored_fingerprint, state) = self
+            .lookup(key)?
+            .ok_or_else(|| Error::Database(format!("Idempotency key {} vanished", key)))?;
+        if stored_fingerprint != fingerprint {
+            return Err(Error::InvalidParameter(format!(
+                "Idempotency key {} was already used for a different request",
+                key
+            )));
+        }
+        Ok(Reservation::Existing(state))
+    }
+
+    fn lookup(&self, key: &str) -> Result<Option<(String, SendState)>> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT fingerprint, operation_id, txid FROM numi_send_requests
+                 WHERE idempotency_key = ?1",
+                params![key],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?;
+
+        Ok(row.map(|(fingerprint, operation_id, txid)| {
+            let state = match (operation_id, txid) {
+                (Some(operation_id), Some(txid)) => SendState::Completed { operation_id, txid },
+                (Some(operation_id), None) => SendState::Submitted { operation_id },
+                _ => SendState::InFlight,
+            };
+            (fingerprint, state)
+        }))
+    }
+
+    /// Get the recorded state of a key
+    pub fn get(&self, key: &str) -> Result<Option<SendState>> {
+        Ok(self.lookup(key)?.map(|(_, state)| state))
+    }
+
+    /// Record the operation ID produced by the send
+    pub fn record_operation(&self, key: &str, operation_id: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE numi_send_requests SET operation_id = ?1 WHERE idempotency_key = ?2",
+                params![operation_id, key],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Record the transaction ID once the operation completes
+    pub fn record_txid(&self, key: &str, txid: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE numi_send_requests SET txid = ?1 WHERE idempotency_key = ?2",
+                params![txid, key],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Release a key whose send definitely did not happen, so it can be retried
+    pub fn release(&self, key: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM numi_send_requests
+                 WHERE idempotency_key = ?1 AND operation_id IS NULL",
+                params![key],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn temp_store() -> (TempDb, IdempotencyStore) {
+        let path = TempDb::new();
+        let store = IdempotencyStore::open(&path).unwrap();
+        (path, store)
+    }
+
+    fn payments(amount: f64) -> Vec<Payment> {
+        vec![Payment {
+            address: "u1recipient".to_string(),
+            amount,
+            memo: None,
+        }]
+    }
+
+    #[test]
+    fn test_key_lifecycle() {
+        let (_db, store) = temp_store();
+        let fp = request_fingerprint("u1from", &payments(1.0), None, None).unwrap();
+
+        assert_eq!(store.reserve("payout-1", &fp).unwrap(), Reservation::New);
+        assert_eq!(
+            store.reserve("payout-1", &fp).unwrap(),
+            Reservation::Existing(SendState::InFlight)
+        );
+
+        store.record_operation("payout-1", "opid-1").unwrap();
+        store.release("payout-1").unwrap();
+        assert_eq!(
+            store.get("payout-1").unwrap(),
+            Some(SendState::Submitted {
+                operation_id: "opid-1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_key_reuse_with_different_request_rejected() {
+        let (_db, store) = temp_store();
+        let fp1 = request_fingerprint("u1from", &payments(1.0), None, None).unwrap();
+        let fp2 = request_fingerprint("u1from", &payments(2.0), None, None).unwrap();
+
+        store.reserve("payout-1", &fp1).unwrap();
+        assert!(store.reserve("payout-1", &fp2).is_err());
+
+        store.release("payout-1").unwrap();
+        assert_eq!(store.reserve("payout-1", &fp2).unwrap(), Reservation::New);
+    }
+}
@@ -0,0 +1,431 @@
+//! Transparent address index
+//!
+//! zcashd only answers address queries (`getaddressdeltas`,
+//! `getaddresstxids`) when started with `-insightexplorer` or
+//! `-lightwalletd`, which managed nodes and pruned nodes usually are not.
+//! [`AddressIndexer`] builds that index itself: it walks the chain with
+//! batched `getblock` calls, records every transparent output and the spends
+//! of outputs it has seen, and answers `address -> txids` queries from tables
+//! in a local SQLite database.
+//!
+//! Spends are attributed by looking up the spent output in the index, so an
+//! address's history is complete from the indexer's start height on; spends
+//! of outputs created before it are not recorded. The hashes of the last
+//! [`MAX_REORG_DEPTH`] indexed blocks are kept, and a chain reorganization is
+//! rolled back before indexing continues.
+//!
+//! lightwalletd's compact blocks carry no transparent data, so the index is
+//! built over RPC only; lightwalletd answers `GetTaddressTxids` itself.
+
+use crate::client::RpcClient;
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::events::MAX_REORG_DEPTH;
+use crate::explorer::AddressActivityEntry;
+use crate::rpc::Block;
+use crate::store::db_error;
+use crate::types::utils::zec_to_zatoshis;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Blocks fetched and indexed per batch by default
+pub const DEFAULT_INDEX_BATCH_BLOCKS: u64 = 100;
+
+const DB_CONTEXT: &str = "Address index error";
+
+/// Outcome of [`AddressIndexer::index_to`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexSyncReport {
+    /// Highest indexed block after the run
+    pub indexed_height: Option<u64>,
+    pub blocks_indexed: u64,
+    /// Blocks removed from the index because of chain reorganizations
+    pub blocks_rolled_back: u64,
+}
+
+/// Indexes transparent address activity from a zcashd node
+pub struct AddressIndexer {
+    db_path: PathBuf,
+    client: RpcClient,
+    start_height: u64,
+    batch_blocks: u64,
+}
+
+impl AddressIndexer {
+    /// Create an indexer storing its index in the SQLite database at `db_path`
+    ///
+    /// The index tables are created if they do not exist. Indexing starts
+    /// at the genesis block unless [`with_start_height`](Self::with_start_height)
+    /// is used.
+    pub fn new(db_path: impl AsRef<Path>, client: RpcClient) -> Result<Self> {
+        let indexer = Self {
+            db_path: db_path.as_ref().to_path_buf(),
+            client,
+            start_height: 0,
+            batch_blocks: DEFAULT_INDEX_BATCH_BLOCKS,
+        };
+        indexer.connection()?;
+        Ok(indexer)
+    }
+
+    /// Start indexing at `height` instead of the genesis block
+    ///
+    /// Only applies to an empty index; activity below `height` is not
+    /// indexed.
+    pub fn with_start_height(mut self, height: u64) -> Self {
+        self.start_height = height;
+        self
+    }
+
+    /// Fetch and index `blocks` blocks per batch (at least 1)
+    pub fn with_batch_size(mut self, blocks: u64) -> Self {
+        self.batch_blocks = blocks.max(1);
+        self
+    }
+
+    fn connection(&self) -> Result<Connection> {
+        let conn = open_connection(&self.db_path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_index_blocks (
+                height INTEGER PRIMARY KEY,
+                hash TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS numi_index_outputs (
+                txid TEXT NOT NULL,
+                output_index INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                value INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                PRIMARY KEY (txid, output_index)
+            );
+            CREATE INDEX IF NOT EXISTS numi_index_outputs_height
+                ON numi_index_outputs (height);
+            CREATE TABLE IF NOT EXISTS numi_index_activity (
+                address TEXT NOT NULL,
+                txid TEXT NOT NULL,
+                height INTEGER NOT NULL,
+                delta INTEGER NOT NULL,
+                PRIMARY KEY (address, txid)
+            );
+            CREATE INDEX IF NOT EXISTS numi_index_activity_height
+                ON numi_index_activity (height);",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(conn)
+    }
+
+    /// Height of the highest indexed block, or `None` if nothing is indexed
+    pub fn indexed_height(&self) -> Result<Option<u64>> {
+        indexed_tip(&self.connection()?).map(|tip| tip.map(|(height, _)| height))
+    }
+
+    /// Index up to the node's current chain tip
+    pub async fn sync(&self) -> Result<IndexSyncReport> {
+        let tip = self.client.get_block_count().await?;
+        self.index_to(tip).await
+    }
+
+    /// Index the blocks after the indexed height up to `end_height`
+    ///
+    /// Each batch is stored in one database transaction, so an interrupted
+    /// run resumes after the last complete batch.
+    ///
+    /// # Returns
+    /// The blocks indexed and rolled back
+    pub async fn index_to(&self, end_height: u64) -> Result<IndexSyncReport> {
+        let mut report = IndexSyncReport {
+            blocks_rolled_back: self.roll_back_reorg().await?,
+            ..IndexSyncReport::default()
+        };
+        loop {
+            let next = match self.indexed_height()? {
+                Some(height) => height + 1,
+                None => self.start_height,
+            };
+            if next > end_height {
+                break;
+            }
+            let batch_end = end_height.min(next.saturating_add(self.batch_blocks - 1));
+            let blocks = self.client.get_blocks_range(next..=batch_end).await?;
+            if apply_blocks(&mut self.connection()?, &blocks)? {
+                report.blocks_indexed += blocks.len() as u64;
+                continue;
+            }
+
+            // The chain changed under the index since the last batch
+            let rolled_back = self.roll_back_reorg().await?;
+            if rolled_back == 0 {
+                return Err(Error::Rpc(format!(
+                    "Block {} from the node does not extend the indexed chain",
+                    next
+                )));
+            }
+            report.blocks_rolled_back += rolled_back;
+        }
+        report.indexed_height = self.indexed_height()?;
+        Ok(report)
+    }
+
+    /// Remove indexed blocks that are no longer in the node's chain
+    async fn roll_back_reorg(&self) -> Result<u64> {
+        let recent = {
+            let conn = self.connection()?;
+            let mut stmt = conn
+                .prepare("SELECT height, hash FROM numi_index_blocks ORDER BY height DESC")
+                .map_err(db_error(DB_CONTEXT))?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?)))
+                .map_err(db_error(DB_CONTEXT))?;
+            rows.collect::<rusqlite::Result<Vec<(u64, String)>>>()
+                .map_err(db_error(DB_CONTEXT))?
+        };
+        if recent.is_empty() {
+            return Ok(0);
+        }
+
+        let tip = self.client.get_block_count().await?;
+        for (depth, (height, hash)) in recent.iter().enumerate() {
+            if *height <= tip && self.client.get_block_hash(*height).await? == *hash {
+                if depth > 0 {
+                    tracing::warn!(
+                        "Chain reorganization: rolling the address index back to block {}",
+                        height
+                    );
+                    rewind(&mut self.connection()?, *height)?;
+                }
+                return Ok(depth as u64);
+            }
+        }
+        Err(Error::Rpc(format!(
+            "Chain reorganization deeper than the {} blocks the address index keeps; \
+             rebuild the index",
+            recent.len()
+        )))
+    }
+
+    /// IDs of the transactions that paid or spent from `address`, newest first
+    pub fn txids(&self, address: &str) -> Result<Vec<String>> {
+        Ok(self
+            .activity(address, None)?
+            .into_iter()
+            .map(|entry| entry.txid)
+            .collect())
+    }
+
+    /// Net effect of each indexed transaction on `address`, newest first
+    ///
+    /// # Arguments
+    /// * `address` - Transparent address
+    /// * `range` - Optional inclusive (start, end) block height range
+    pub fn activity(
+        &self,
+        address: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<Vec<AddressActivityEntry>> {
+        let (start, end) = range.unwrap_or((0, i64::MAX as u64));
+        let conn = self.connection()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT txid, height, delta FROM numi_index_activity
+                 WHERE address = ?1 AND height BETWEEN ?2 AND ?3
+                 ORDER BY height DESC, txid",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let entries = stmt
+            .query_map(params![address, start as i64, end as i64], |row| {
+                Ok(AddressActivityEntry {
+                    txid: row.get(0)?,
+                    height: row.get::<_, i64>(1)? as u64,
+                    delta: row.get(2)?,
+                })
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(entries)
+    }
+}
+
+/// Highest indexed block's height and hash
+fn indexed_tip(conn: &Connection) -> Result<Option<(u64, String)>> {
+    conn.query_row(
+        "SELECT height, hash FROM numi_index_blocks ORDER BY height DESC LIMIT 1",
+        [],
+        |row| Ok((row.get::<_, i64>(0)? as u64, row.get(1)?)),
+    )
+    .optional()
+    .map_err(db_error(DB_CONTEXT))
+}
+
+/// Index consecutive blocks following the indexed tip
+///
+/// # Returns
+/// `false`, leaving the index unchanged, if the blocks do not extend the
+/// indexed chain
+fn apply_blocks(conn: &mut Connection, blocks: &[Block]) -> Result<bool> {
+    let tx = conn.transaction().map_err(db_error(DB_CONTEXT))?;
+    let mut tip = indexed_tip(&tx)?;
+    for block in blocks {
+        if let Some((height, hash)) = &tip {
+            if block.height != height + 1 || block.previousblockhash.as_ref() != Some(hash) {
+                return Ok(false);
+            }
+        }
+        let height = block.height as i64;
+        let record = |address: &str, txid: &str, delta: i64| {
+            tx.execute(
+                "INSERT INTO numi_index_activity (address, txid, height, delta)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(address, txid) DO UPDATE SET delta = delta + excluded.delta",
+                params![address, txid, height, delta],
+            )
+            .map_err(db_error(DB_CONTEXT))
+        };
+
+        // Outputs first, so spends of outputs earlier in the block resolve
+        for transaction in &block.tx {
+            for output in &transaction.vout {
+                // Bare multisig outputs have no single address to credit
+                let [address] = output.script_pub_key.addresses.as_slice() else {
+                    continue;
+                };
+                let value = output
+                    .value_zat
+                    .unwrap_or_else(|| zec_to_zatoshis(output.value))
+                    as i64;
+                tx.execute(
+                    "INSERT OR REPLACE INTO numi_index_outputs
+                         (txid, output_index, address, value, height)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![transaction.txid, output.n, address, value, height],
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+                record(address, &transaction.txid, value)?;
+            }
+            for input in &transaction.vin {
+                let (Some(prev_txid), Some(prev_index)) = (&input.txid, input.vout) else {
+                    continue;
+                };
+                let spent: Option<(String, i64)> = tx
+                    .query_row(
+                        "SELECT address, value FROM numi_index_outputs
+                         WHERE txid = ?1 AND output_index = ?2",
+                        params![prev_txid, prev_index],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .optional()
+                    .map_err(db_error(DB_CONTEXT))?;
+                if let Some((address, value)) = spent {
+                    record(&address, &transaction.txid, -value)?;
+                }
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO numi_index_blocks (height, hash) VALUES (?1, ?2)",
+            params![height, block.hash],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        tip = Some((block.height, block.hash.clone()));
+    }
+
+    if let Some((height, _)) = tip {
+        tx.execute(
+            "DELETE FROM numi_index_blocks WHERE height <= ?1",
+            [height.saturating_sub(MAX_REORG_DEPTH) as i64],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+    }
+    tx.commit().map_err(db_error(DB_CONTEXT))?;
+    Ok(true)
+}
+
+/// Remove everything indexed above `height`
+fn rewind(conn: &mut Connection, height: u64) -> Result<()> {
+    let tx = conn.transaction().map_err(db_error(DB_CONTEXT))?;
+    for table in [
+        "numi_index_blocks",
+        "numi_index_outputs",
+        "numi_index_activity",
+    ] {
+        tx.execute(
+            &format!("DELETE FROM {} WHERE height > ?1", table),
+            [height as i64],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+    }
+    tx.commit().map_err(db_error(DB_CONTEXT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn block(height: u64, hash: &str, previous: &str, tx: serde_json::Value) -> Block {
+        serde_json::from_value(serde_json::json!({
+            "hash": hash,
+            "confirmations": 1,
+            "height": height,
+            "time": 0,
+            "previousblockhash": previous,
+            "tx": tx,
+        }))
+        .unwrap()
+    }
+
+    fn output(n: u32, value_zat: u64, address: &str) -> serde_json::Value {
+        serde_json::json!({
+            "value": value_zat as f64 / 1e8,
+            "valueZat": value_zat,
+            "n": n,
+            "scriptPubKey": { "type": "pubkeyhash", "addresses": [address] }
+        })
+    }
+
+    #[test]
+    fn test_index_blocks() {
+        let path = TempDb::new();
+        let indexer = AddressIndexer::new(&path, RpcClient::new("http://127.0.0.1:8232")).unwrap();
+        let mut conn = indexer.connection().unwrap();
+
+        let first = block(
+            10,
+            "b10",
+            "b9",
+            serde_json::json!([{
+                "txid": "aa",
+                "vin": [{ "coinbase": "03" }],
+                "vout": [output(0, 5_000, "t1alice"), output(1, 1_000, "t1bob")]
+            }]),
+        );
+        let second = block(
+            11,
+            "b11",
+            "b10",
+            serde_json::json!([{
+                "txid": "bb",
+                "vin": [{ "txid": "aa", "vout": 0 }, { "txid": "unknown", "vout": 3 }],
+                "vout": [output(0, 4_000, "t1bob"), output(1, 900, "t1alice")]
+            }]),
+        );
+        assert!(apply_blocks(&mut conn, &[first, second]).unwrap());
+        assert_eq!(indexer.indexed_height().unwrap(), Some(11));
+        assert_eq!(indexer.txids("t1alice").unwrap(), vec!["bb", "aa"]);
+        let activity = indexer.activity("t1alice", None).unwrap();
+        assert_eq!(activity[0].delta, 900 - 5_000);
+        assert_eq!(activity[1].delta, 5_000);
+        assert_eq!(indexer.activity("t1bob", Some((11, 11))).unwrap().len(), 1);
+        assert!(indexer.txids("t1carol").unwrap().is_empty());
+
+        // A block that does not build on the indexed tip is refused
+        let fork = block(12, "c12", "c11", serde_json::json!([]));
+        assert!(!apply_blocks(&mut conn, &[fork]).unwrap());
+        assert_eq!(indexer.indexed_height().unwrap(), Some(11));
+
+        rewind(&mut conn, 10).unwrap();
+        assert_eq!(indexer.indexed_height().unwrap(), Some(10));
+        assert_eq!(indexer.txids("t1alice").unwrap(), vec!["aa"]);
+    }
+}
@@ -0,0 +1,414 @@
+//! Wallet interchange format
+//!
+//! Moving between Zcash wallets without losing anything needs more than the
+//! seed: account birthdays (so the new wallet does not rescan from Sapling
+//! activation), the addresses handed out and their labels, and the owner's
+//! notes on past transactions. [`WalletInterchange`] follows the data model
+//! of ZeWIF, the Zcash wallet interchange format: a wallet with its seed
+//! material and accounts, each account with its birthday, viewing key and
+//! addresses, and the wallet's transactions with their memos and
+//! annotations. It is serialized as JSON; ZeWIF tooling converts between
+//! this JSON and other wallets' formats.
+//!
+//! A document is produced by
+//! [`Wallet::interchange_document`](crate::wallet::Wallet::interchange_document)
+//! and restored with
+//! [`Wallet::import_interchange_document`](crate::wallet::Wallet::import_interchange_document).
+//! Since it holds the seed, the file written by
+//! [`Wallet::export_interchange`](crate::wallet::Wallet::export_interchange)
+//! is sealed with a passphrase like a [`crate::backup`], with the magic
+//! `NUMIZWF1`.
+//!
+//! Transactions themselves are not carried: they are found again on chain
+//! when the restored accounts are synced from their birthdays, and the
+//! annotations are matched to them by transaction ID.
+
+use crate::address_book::AddressLabel;
+use crate::backup::{seal, unseal, BackupAccount, WalletBackup, BACKUP_VERSION};
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::db_error;
+use crate::types::Network;
+use crate::wallet::contacts::Contact;
+use crate::wallet::memo_text;
+use bip0039::Mnemonic;
+use secrecy::zeroize::{Zeroize, Zeroizing};
+use secrecy::{ExposeSecret, SecretVec};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Format name recorded in every document
+pub const INTERCHANGE_FORMAT: &str = "zewif-json";
+
+/// Current interchange format version
+pub const INTERCHANGE_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"NUMIZWF1";
+
+/// Purpose of the default unified address of an account
+pub const PURPOSE_DEFAULT: &str = "default";
+
+/// Purpose of a transparent address handed out to payers
+pub const PURPOSE_RECEIVE: &str = "receive";
+
+/// An address of an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterchangeAddress {
+    pub address: String,
+    /// Why the address was created, e.g. [`PURPOSE_DEFAULT`]
+    pub purpose: Option<String>,
+    /// Address book label
+    pub label: Option<String>,
+}
+
+/// An account of the wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterchangeAccount {
+    pub name: Option<String>,
+    /// ZIP-32 account index, or `None` for imported viewing keys
+    pub zip32_account_index: Option<u32>,
+    /// Encoded unified full viewing key
+    pub ufvk: Option<String>,
+    /// Height of the first block that may contain funds for the account
+    pub birthday_height: u64,
+    #[serde(default)]
+    pub addresses: Vec<InterchangeAddress>,
+}
+
+/// A transaction of the wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterchangeTransaction {
+    /// Transaction ID, as shown by explorers
+    pub txid: String,
+    pub mined_height: Option<u64>,
+    /// Block time (Unix seconds)
+    pub block_time: Option<u64>,
+    /// Text memos of the transaction's outputs to or from the wallet
+    #[serde(default)]
+    pub memos: Vec<String>,
+    /// The owner's note on the transaction (see
+    /// [`TransactionAnnotations`](crate::wallet::annotations::TransactionAnnotations))
+    pub annotation: Option<String>,
+}
+
+/// A wallet in the interchange format
+///
+/// Deliberately not `Debug`: it contains the wallet seed. The seed and
+/// mnemonic are zeroized when the document is dropped.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WalletInterchange {
+    /// Always [`INTERCHANGE_FORMAT`]
+    pub format: String,
+    pub version: u32,
+    pub network: Network,
+    /// Height of the wallet's chain tip when it was exported
+    pub export_height: Option<u64>,
+    /// Wallet seed (hex encoded), if known
+    pub seed: Option<String>,
+    /// ZIP-339 mnemonic phrase, if known
+    pub mnemonic: Option<String>,
+    /// ZIP-32 index of the account selected in the wallet
+    #[serde(default)]
+    pub selected_account: u32,
+    pub accounts: Vec<InterchangeAccount>,
+    /// Labels of addresses that belong to no account, e.g. payees
+    #[serde(default)]
+    pub address_labels: Vec<AddressLabel>,
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+    #[serde(default)]
+    pub transactions: Vec<InterchangeTransaction>,
+    /// Unix time the document was created
+    pub created_at: u64,
+}
+
+impl Drop for WalletInterchange {
+    fn drop(&mut self) {
+        self.seed.zeroize();
+        self.mnemonic.zeroize();
+    }
+}
+
+impl WalletInterchange {
+    /// Check the format name and version, and that the document has seed
+    /// material
+    pub fn validate(&self) -> Result<()> {
+        if self.format != INTERCHANGE_FORMAT {
+            return Err(Error::InvalidParameter(format!(
+                "Unsupported interchange format '{}'",
+                self.format
+            )));
+        }
+        if self.version > INTERCHANGE_VERSION {
+            return Err(Error::InvalidParameter(format!(
+                "Unsupported interchange version {}",
+                self.version
+            )));
+        }
+        if self.seed.is_none() && self.mnemonic.is_none() {
+            return Err(Error::InvalidParameter(
+                "Interchange document contains no seed or mnemonic".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Convert the document to a backup, e.g. to recreate its accounts with
+    /// [`LightClient::restore_accounts`](crate::light_client::LightClient::restore_accounts)
+    ///
+    /// Address labels of the accounts' addresses join the other labels in
+    /// the address book. A document without a seed has it derived from the
+    /// mnemonic with an empty passphrase, as other Zcash wallets do.
+    pub fn to_backup(&self) -> Result<WalletBackup> {
+        self.validate()?;
+        let seed = match (&self.seed, &self.mnemonic) {
+            (Some(seed), _) => seed.clone(),
+            (None, Some(phrase)) => {
+                let mnemonic = <Mnemonic>::from_phrase(phrase.as_str()).map_err(|e| {
+                    Error::InvalidParameter(format!("Invalid mnemonic phrase: {}", e))
+                })?;
+                let seed = Zeroizing::new(mnemonic.to_seed(""));
+                hex::encode(seed.as_slice())
+            }
+            (None, None) => unreachable!("checked by validate"),
+        };
+        let mut address_book = self.address_labels.clone();
+        for account in &self.accounts {
+            for address in &account.addresses {
+                if let Some(label) = &address.label {
+                    address_book.push(AddressLabel {
+                        address: address.address.clone(),
+                        label: label.clone(),
+                        updated_at: self.created_at,
+                    });
+                }
+            }
+        }
+
+        Ok(WalletBackup {
+            version: BACKUP_VERSION,
+            network: self.network,
+            seed,
+            mnemonic: self.mnemonic.clone(),
+            selected_account: self.selected_account,
+            birthday_height: self.accounts.iter().map(|a| a.birthday_height).min(),
+            accounts: self
+                .accounts
+                .iter()
+                .map(|account| BackupAccount {
+                    index: account.zip32_account_index,
+                    name: account.name.clone(),
+                    ufvk: account.ufvk.clone(),
+                    birthday_height: account.birthday_height,
+                })
+                .collect(),
+            address_book,
+            contacts: self.contacts.clone(),
+            created_at: self.created_at,
+        })
+    }
+}
+
+/// Encrypt an interchange document with a passphrase
+///
+/// # Returns
+/// The complete file contents
+pub fn encrypt_interchange(document: &WalletInterchange, passphrase: &str) -> Result<Vec<u8>> {
+    let plaintext = SecretVec::new(serde_json::to_vec(document)?);
+    seal(MAGIC, plaintext.expose_secret(), passphrase, "interchange")
+}
+
+/// Decrypt interchange file contents
+///
+/// Fails with [`Error::Wallet`] if the passphrase is wrong or the file was
+/// modified.
+pub fn decrypt_interchange(data: &[u8], passphrase: &str) -> Result<WalletInterchange> {
+    let plaintext = unseal(MAGIC, data, passphrase, "interchange")?;
+    let document: WalletInterchange = serde_json::from_slice(plaintext.expose_secret())?;
+    document.validate()?;
+    Ok(document)
+}
+
+/// Read and decrypt an interchange file without restoring it
+pub fn read_interchange(path: &Path, passphrase: &str) -> Result<WalletInterchange> {
+    decrypt_interchange(&std::fs::read(path)?, passphrase)
+}
+
+const DB_CONTEXT: &str = "Interchange export error";
+
+/// Read the transactions of every account in a wallet database
+///
+/// Expired transactions are left out. Annotations of transactions not in
+/// the database (e.g. imported before the first sync) are kept as
+/// transactions without height.
+///
+/// # Arguments
+/// * `path` - Wallet database path
+/// * `annotations` - Annotation of each annotated transaction ID
+pub(crate) fn read_transactions(
+    path: &Path,
+    annotations: &BTreeMap<String, String>,
+) -> Result<Vec<InterchangeTransaction>> {
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT txid, MIN(mined_height), MIN(block_time) FROM v_transactions
+             WHERE NOT COALESCE(expired_unmined, 0)
+             GROUP BY txid
+             ORDER BY MIN(mined_height) IS NULL, MIN(mined_height), txid",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, Vec<u8>>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+            ))
+        })
+        .map_err(db_error(DB_CONTEXT))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_error(DB_CONTEXT))?;
+    let mut memos = conn
+        .prepare(
+            "SELECT memo FROM v_tx_outputs WHERE txid = ?1 AND memo IS NOT NULL
+             ORDER BY output_pool, output_index",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+
+    let mut unmatched = annotations.clone();
+    let mut transactions = Vec::with_capacity(rows.len());
+    for (raw_txid, mined_height, block_time) in rows {
+        let texts = memos
+            .query_map([&raw_txid], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        // Transaction IDs are displayed in reverse byte order
+        let txid = hex::encode(raw_txid.iter().rev().copied().collect::<Vec<u8>>());
+        transactions.push(InterchangeTransaction {
+            annotation: unmatched.remove(&txid),
+            txid,
+            mined_height: mined_height.map(|height| height as u64),
+            block_time: block_time.map(|time| time as u64),
+            memos: texts.iter().filter_map(|memo| memo_text(memo)).collect(),
+        });
+    }
+    transactions.extend(
+        unmatched
+            .into_iter()
+            .map(|(txid, annotation)| InterchangeTransaction {
+                txid,
+                mined_height: None,
+                block_time: None,
+                memos: Vec::new(),
+                annotation: Some(annotation),
+            }),
+    );
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+    use rusqlite::Connection;
+
+    fn document() -> WalletInterchange {
+        WalletInterchange {
+            format: INTERCHANGE_FORMAT.to_string(),
+            version: INTERCHANGE_VERSION,
+            network: Network::Testnet,
+            export_height: Some(2_000),
+            seed: None,
+            mnemonic: Some(
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon abandon about"
+                    .to_string(),
+            ),
+            selected_account: 0,
+            accounts: vec![InterchangeAccount {
+                name: Some("Main".to_string()),
+                zip32_account_index: Some(0),
+                ufvk: None,
+                birthday_height: 1_000,
+                addresses: vec![InterchangeAddress {
+                    address: "u1main".to_string(),
+                    purpose: Some(PURPOSE_DEFAULT.to_string()),
+                    label: Some("Donations".to_string()),
+                }],
+            }],
+            address_labels: Vec::new(),
+            contacts: Vec::new(),
+            transactions: Vec::new(),
+            created_at: 5,
+        }
+    }
+
+    #[test]
+    fn test_interchange_roundtrip() {
+        let data = encrypt_interchange(&document(), "correct horse").unwrap();
+        let restored = decrypt_interchange(&data, "correct horse").unwrap();
+        assert_eq!(restored.accounts, document().accounts);
+        assert!(decrypt_interchange(&data, "wrong").is_err());
+
+        let backup = restored.to_backup().unwrap();
+        // BIP-39 test vector seed of the all-"abandon" phrase
+        assert!(backup
+            .seed
+            .starts_with("5eb00bbddcf069084889a8ab9155568165f5c453"));
+        assert_eq!(backup.birthday_height, Some(1_000));
+        assert_eq!(backup.address_book[0].label, "Donations");
+
+        let mut unknown = document();
+        unknown.format = "other".to_string();
+        assert!(unknown.validate().is_err());
+        let mut keyless = document();
+        keyless.mnemonic = None;
+        assert!(keyless.to_backup().is_err());
+    }
+
+    #[test]
+    fn test_read_transactions() {
+        let path = TempDb::new();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE v_transactions (txid BLOB, account_uuid BLOB, mined_height INTEGER,
+                                          block_time INTEGER, expired_unmined INTEGER);
+             CREATE TABLE v_tx_outputs (txid BLOB, output_pool INTEGER, output_index INTEGER,
+                                        memo BLOB);
+             -- A transfer between two accounts appears once per account
+             INSERT INTO v_transactions VALUES (x'0102', x'aa', 10, 100, 0);
+             INSERT INTO v_transactions VALUES (x'0102', x'bb', 10, 100, 0);
+             INSERT INTO v_transactions VALUES (x'0304', x'aa', NULL, NULL, 0);
+             INSERT INTO v_transactions VALUES (x'0506', x'aa', NULL, NULL, 1);",
+        )
+        .unwrap();
+        let mut memo = b"Rent".to_vec();
+        memo.resize(512, 0);
+        conn.execute(
+            "INSERT INTO v_tx_outputs VALUES (x'0102', 3, 0, ?1)",
+            [&memo],
+        )
+        .unwrap();
+        drop(conn);
+
+        let annotations = BTreeMap::from([
+            ("0201".to_string(), "March rent".to_string()),
+            ("ff".repeat(32), "Not synced yet".to_string()),
+        ]);
+        let transactions = read_transactions(&path, &annotations).unwrap();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(transactions[0].txid, "0201");
+        assert_eq!(transactions[0].mined_height, Some(10));
+        assert_eq!(transactions[0].memos, vec!["Rent"]);
+        assert_eq!(transactions[0].annotation.as_deref(), Some("March rent"));
+        assert_eq!(transactions[1].txid, "0403");
+        assert_eq!(transactions[1].annotation, None);
+        assert_eq!(
+            transactions[2].annotation.as_deref(),
+            Some("Not synced yet")
+        );
+    }
+}
@@ -0,0 +1,642 @@
+//! Merchant invoicing
+//!
+//! An invoice binds a requested amount, memo, and expiry to a fresh
+//! diversified address and a ZIP-321 payment URI. Payment state is tracked
+//! from [`WalletEvent`]s as funds appear in the mempool and confirm:
+//!
+//! - `Unpaid` → `SeenInMempool` → `Paid`
+//! - `Underpaid` / `Overpaid` when confirmed funds don't match the amount
+//! - `Expired` when nothing arrived before the expiry time
+//!
+//! A reorg that removes the block of a settled invoice's payment re-opens
+//! the invoice. Payments to an expired invoice leave it expired and are
+//! reported as late payments, e.g. to be refunded.
+
+use crate::block_time::{BlockTimeEstimator, ConfirmationEta};
+use crate::deposits::ConfirmationPolicy;
+use crate::error::{Error, Result};
+use crate::events::{ReceivedPayment, WalletEvent};
+use crate::store::unix_now;
+use crate::types::format_zec;
+use crate::wallet::Wallet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+
+/// Payment state of an invoice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InvoiceStatus {
+    /// Nothing received yet
+    Unpaid,
+    /// A payment is in the mempool or not yet sufficiently confirmed
+    SeenInMempool,
+    /// Confirmed funds exactly match the requested amount
+    Paid,
+    /// Confirmed funds are less than the requested amount
+    Underpaid,
+    /// Confirmed funds exceed the requested amount
+    Overpaid,
+    /// Nothing was received before the invoice expired
+    Expired,
+}
+
+impl InvoiceStatus {
+    /// Whether the invoice no longer changes state as payments arrive
+    ///
+    /// Paid and overpaid invoices can still be re-opened by a reorg.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            InvoiceStatus::Paid | InvoiceStatus::Overpaid | InvoiceStatus::Expired
+        )
+    }
+}
+
+/// A payment output credited to an invoice
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvoicePayment {
+    pub txid: String,
+    pub output_index: u32,
+    /// Amount in zatoshis
+    pub amount: u64,
+    /// Mined height, or `None` while in the mempool
+    pub height: Option<u64>,
+    /// Name of the confirmation profile that applies to the payment
+    #[serde(default)]
+    pub profile: String,
+}
+
+/// A merchant invoice
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invoice {
+    /// Unique invoice identifier
+    pub id: String,
+    /// Address the payer should send to
+    pub address: String,
+    /// Diversifier index of the address, if derived from the wallet
+    pub diversifier_index: Option<u64>,
+    /// Requested amount in zatoshis
+    pub amount: u64,
+    /// Memo requested from the payer
+    pub memo: Option<String>,
+    /// Creation time (unix seconds)
+    pub created_at: u64,
+    /// Expiry time (unix seconds)
+    pub expires_at: u64,
+    /// Current payment state
+    pub status: InvoiceStatus,
+    /// Payments received to the invoice address
+    pub payments: Vec<InvoicePayment>,
+    /// Total of payments that reached the confirmation target (zatoshis)
+    pub confirmed_amount: u64,
+    /// Total of payments not yet sufficiently confirmed (zatoshis)
+    pub pending_amount: u64,
+}
+
+impl Invoice {
+    /// ZIP-321 payment URI for this invoice
+    pub fn payment_uri(&self) -> String {
+        zip321_uri(&self.address, self.amount, self.memo.as_deref())
+    }
+
+    /// Seconds until the invoice expires (0 once expired), for countdowns
+    pub fn expires_in(&self, now: u64) -> u64 {
+        self.expires_at.saturating_sub(now)
+    }
+
+    /// Approximate chain height at the invoice's expiry time
+    ///
+    /// Returns `None` if the estimator has no samples.
+    pub fn expiry_height(&self, estimator: &BlockTimeEstimator) -> Option<u64> {
+        estimator.height_at(self.expires_at)
+    }
+}
+
+/// Status change emitted by the [`InvoiceManager`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvoiceUpdate {
+    pub invoice_id: String,
+    pub previous: InvoiceStatus,
+    pub status: InvoiceStatus,
+    /// Payment received or mined after the invoice expired; `previous` and
+    /// `status` are then both [`InvoiceStatus::Expired`]
+    #[serde(default)]
+    pub late_payment: Option<InvoicePayment>,
+}
+
+/// Creates invoices and tracks their payment state
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct InvoiceManager {
+    policy: ConfirmationPolicy,
+    invoices: HashMap<String, Invoice>,
+    /// Address -> invoice ID
+    by_address: HashMap<String, String>,
+    tip: Option<u64>,
+}
+
+impl InvoiceManager {
+    /// Create an invoice manager using the given confirmation policy
+    pub fn new(policy: ConfirmationPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Create an invoice paid to a caller-provided address
+    ///
+    /// The address must not already be in use by another invoice.
+    ///
+    /// # Arguments
+    /// * `address` - Receiving address (should be unique to this invoice)
+    /// * `amount` - Requested amount in zatoshis
+    /// * `memo` - Memo the payer should attach (shielded addresses only)
+    /// * `ttl` - How long the invoice remains payable
+    pub fn create_invoice(
+        &mut self,
+        address: impl Into<String>,
+        amount: u64,
+        memo: Option<String>,
+        ttl: Duration,
+    ) -> Result<Invoice> {
+        self.insert_invoice(address.into(), None, amount, memo, ttl)
+    }
+
+    /// Create an invoice bound to a fresh diversified address from the wallet
+    ///
+    /// The address is allocated like
+    /// [`Wallet::get_next_unified_address`], so it is never the wallet's
+    /// default address and is not handed out again, also across restarts
+    /// and invoice managers sharing the wallet.
+    pub fn create_invoice_for_wallet(
+        &mut self,
+        wallet: &Wallet,
+        amount: u64,
+        memo: Option<String>,
+        ttl: Duration,
+    ) -> Result<Invoice> {
+        let (address, index) = wallet.get_next_unified_address()?;
+        self.insert_invoice(address, Some(index), amount, memo, ttl)
+    }
+
+    fn insert_invoice(
+        &mut self,
+        address: String,
+        diversifier_index: Option<u64>,
+        amount: u64,
+        memo: Option<String>,
+        ttl: Duration,
+    ) -> Result<Invoice> {
+        if amount == 0 {
+            return Err(Error::InvalidParameter(
+                "Invoice amount must be positive".to_string(),
+            ));
+        }
+        if self.by_address.contains_key(&address) {
+            return Err(Error::InvalidParameter(format!(
+                "Address {} is already assigned to an invoice",
+                address
+            )));
+        }
+
+        let created_at = unix_now();
+        let invoice = Invoice {
+            id: hex::encode(rand::random::<[u8; 16]>()),
+            address: address.clone(),
+            diversifier_index,
+            amount,
+            memo,
+            created_at,
+            expires_at: created_at.saturating_add(ttl.as_secs()),
+            status: InvoiceStatus::Unpaid,
+            payments: Vec::new(),
+            confirmed_amount: 0,
+            pending_amount: 0,
+        };
+
+        self.by_address.insert(address, invoice.id.clone());
+        self.invoices.insert(invoice.id.clone(), invoice.clone());
+        Ok(invoice)
+    }
+
+    /// Look up an invoice by ID
+    pub fn get(&self, invoice_id: &str) -> Option<&Invoice> {
+        self.invoices.get(invoice_id)
+    }
+
+    /// Look up the invoice assigned to an address
+    pub fn get_by_address(&self, address: &str) -> Option<&Invoice> {
+        self.by_address
+            .get(address)
+            .and_then(|id| self.invoices.get(id))
+    }
+
+    /// List all invoices, newest first
+    pub fn list(&self) -> Vec<&Invoice> {
+        let mut invoices: Vec<&Invoice> = self.invoices.values().collect();
+        invoices.sort_by_key(|invoice| std::cmp::Reverse(invoice.created_at));
+        invoices
+    }
+
+    /// List invoices in the given state, newest first
+    pub fn list_by_status(&self, status: InvoiceStatus) -> Vec<&Invoice> {
+        self.list()
+            .into_iter()
+            .filter(|invoice| invoice.status == status)
+            .collect()
+    }
+
+    /// Estimate when an invoice's pending payments are all confirmed
+    ///
+    /// Uses the confirmation target of each payment's profile. Returns
+    /// `None` if the invoice is unknown or has no pending payments.
+    pub fn payment_eta(
+        &self,
+        invoice_id: &str,
+        estimator: &BlockTimeEstimator,
+        now: u64,
+    ) -> Option<ConfirmationEta> {
+        let invoice = self.invoices.get(invoice_id)?;
+        if invoice.pending_amount == 0 {
+            return None;
+        }
+        invoice
+            .payments
+            .iter()
+            .map(|payment| {
+                let required = self.policy.required_confirmations(payment.amount);
+                estimator.confirmation_eta(payment.height, required, now)
+            })
+            .filter(|eta| eta.remaining_blocks > 0)
+            .max_by_key(|eta| eta.estimated_at)
+    }
+
+    /// Process a single event, returning any invoice status changes
+    pub fn handle_event(&mut self, event: &WalletEvent) -> Vec<InvoiceUpdate> {
+        match event {
+            WalletEvent::ChainTip { height } => self.tip = Some(*height),
+            WalletEvent::BlocksScanned { end_height, .. } => {
+                if self.tip.is_none_or(|tip| *end_height > tip) {
+                    self.tip = Some(*end_height);
+                }
+            }
+            WalletEvent::PaymentReceived(payment) => {
+                if let Some(update) = self.record_payment(payment) {
+                    let mut updates = vec![update];
+                    updates.extend(self.refresh(unix_now()));
+                    return updates;
+                }
+            }
+            WalletEvent::Reorg {
+                depth,
+                old_tip,
+                new_tip,
+            } => {
+                let mut updates = self.handle_reorg(old_tip.saturating_sub(*depth), *new_tip);
+                updates.extend(self.refresh(unix_now()));
+                return updates;
+            }
+            WalletEvent::BandwidthCapReached { .. } => {}
+        }
+        self.refresh(unix_now())
+    }
+
+    /// Treat payments mined above `fork_height` as unconfirmed again
+    ///
+    /// Paid and overpaid invoices that lose confirmed funds are re-opened.
+    /// Expired invoices stay expired.
+    fn handle_reorg(&mut self, fork_height: u64, new_tip: u64) -> Vec<InvoiceUpdate> {
+        self.tip = Some(new_tip);
+        let mut updates = Vec::new();
+        for invoice in self.invoices.values_mut() {
+            let mut reorged = false;
+            for payment in &mut invoice.payments {
+                if payment.height.is_some_and(|h| h > fork_height) {
+                    payment.height = None;
+                    reorged = true;
+                }
+            }
+            let settled = matches!(
+                invoice.status,
+                InvoiceStatus::Paid | InvoiceStatus::Overpaid
+            );
+            if !reorged || !settled {
+                continue;
+            }
+
+            let (confirmed, pending, status) = settle(invoice, &self.policy, self.tip, unix_now());
+            invoice.confirmed_amount = confirmed;
+            invoice.pending_amount = pending;
+            if status != invoice.status {
+                tracing::warn!(
+                    "Invoice {} re-opened by a reorg to height {}",
+                    invoice.id,
+                    fork_height
+                );
+                updates.push(InvoiceUpdate {
+                    invoice_id: invoice.id.clone(),
+                    previous: invoice.status,
+                    status,
+                    late_payment: None,
+                });
+                invoice.status = status;
+            }
+        }
+        updates
+    }
+
+    /// Record a payment to an invoice address
+    ///
+    /// # Returns
+    /// A late payment update if the invoice has expired and the payment is
+    /// new or was mined
+    fn record_payment(&mut self, payment: &ReceivedPayment) -> Option<InvoiceUpdate> {
+        if let Some(height) = payment.height {
+            if self.tip.is_none_or(|tip| height > tip) {
+                self.tip = Some(height);
+            }
+        }
+        let invoice = self
+            .by_address
+            .get(&payment.address)
+            .and_then(|id| self.invoices.get_mut(id))?;
+
+        let entry = InvoicePayment {
+            txid: payment.txid.clone(),
+            output_index: payment.output_index,
+            amount: payment.amount,
+            height: payment.height,
+            profile: self.policy.profile_for(payment.amount).name,
+        };
+        let changed = match invoice
+            .payments
+            .iter_mut()
+            .find(|p| p.txid == entry.txid && p.output_index == entry.output_index)
+        {
+            Some(existing) => {
+                let changed = existing.height != entry.height;
+                *existing = entry.clone();
+                changed
+            }
+            None => {
+                invoice.payments.push(entry.clone());
+                true
+            }
+        };
+
+        if invoice.status != InvoiceStatus::Expired || !changed {
+            return None;
+        }
+        tracing::warn!(
+            "Late payment of {} zat to expired invoice {} in {}",
+            entry.amount,
+            invoice.id,
+            entry.txid
+        );
+        Some(InvoiceUpdate {
+            invoice_id: invoice.id.clone(),
+            previous: InvoiceStatus::Expired,
+            status: InvoiceStatus::Expired,
+            late_payment: Some(entry),
+        })
+    }
+
+    /// Recompute invoice states against the current tip and time
+    ///
+    /// Called automatically by [`handle_event`](Self::handle_event); call it
+    /// periodically to expire invoices when no events arrive.
+    pub fn refresh(&mut self, now: u64) -> Vec<InvoiceUpdate> {
+        let mut updates = Vec::new();
+        for invoice in self.invoices.values_mut() {
+            if invoice.status.is_final() {
+                continue;
+            }
+
+            let (confirmed, pending, status) = settle(invoice, &self.policy, self.tip, now);
+            invoice.confirmed_amount = confirmed;
+            invoice.pending_amount = pending;
+
+            if status != invoice.status {
+                updates.push(InvoiceUpdate {
+                    invoice_id: invoice.id.clone(),
+                    previous: invoice.status,
+                    status,
+                    late_payment: None,
+                });
+                invoice.status = status;
+            }
+        }
+        updates
+    }
+
+    /// Consume events from a subscription and forward invoice status changes
+    ///
+    /// Runs until the event bus is closed or the output channel is dropped.
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<WalletEvent>,
+        output: mpsc::Sender<InvoiceUpdate>,
+    ) -> Result<()> {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Invoice manager lagged, skipped {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            for update in self.handle_event(&event) {
+                if output.send(update).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Confirmed and pending totals of an invoice and the state they put it in
+fn settle(
+    invoice: &Invoice,
+    policy: &ConfirmationPolicy,
+    tip: Option<u64>,
+    now: u64,
+) -> (u64, u64, InvoiceStatus) {
+    let mut confirmed = 0u64;
+    let mut pending = 0u64;
+    for payment in &invoice.payments {
+        let confirmations = match (payment.height, tip) {
+            (Some(h), Some(tip)) if tip >= h => tip - h + 1,
+            _ => 0,
+        };
+        let required = u64::from(policy.required_confirmations(payment.amount));
+        if payment.height.is_some() && confirmations >= required {
+            confirmed = confirmed.saturating_add(payment.amount);
+        } else {
+            pending = pending.saturating_add(payment.amount);
+        }
+    }
+
+    let status = if confirmed > invoice.amount {
+        InvoiceStatus::Overpaid
+    } else if confirmed == invoice.amount {
+        InvoiceStatus::Paid
+    } else if pending > 0 {
+        InvoiceStatus::SeenInMempool
+    } else if confirmed > 0 {
+        InvoiceStatus::Underpaid
+    } else if now >= invoice.expires_at {
+        InvoiceStatus::Expired
+    } else {
+        InvoiceStatus::Unpaid
+    };
+    (confirmed, pending, status)
+}
+
+/// Build a single-payment ZIP-321 URI
+fn zip321_uri(address: &str, amount: u64, memo: Option<&str>) -> String {
+    use base64::Engine;
+
+    let mut uri = format!("zcash:{}?amount={}", address, format_zec(amount));
+    if let Some(memo) = memo {
+        uri.push_str("&memo=");
+        uri.push_str(&base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(memo.as_bytes()));
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(address: &str, amount: u64, height: Option<u64>) -> WalletEvent {
+        WalletEvent::PaymentReceived(ReceivedPayment {
+            txid: "bb".to_string(),
+            output_index: 0,
+            address: address.to_string(),
+            amount,
+            memo: None,
+            height,
+        })
+    }
+
+    #[test]
+    fn test_zip321_uri() {
+        assert_eq!(zip321_uri("zs1abc", 150_000_000, None), "zcash:zs1abc?amount=1.5");
+        assert_eq!(
+            zip321_uri("zs1abc", 1, Some("hi")),
+            "zcash:zs1abc?amount=0.00000001&memo=aGk"
+        );
+    }
+
+    #[test]
+    fn test_invoice_lifecycle() {
+        let mut manager = InvoiceManager::new(ConfirmationPolicy::new(1));
+        let invoice = manager
+            .create_invoice("u1inv", 1_000, None, Duration::from_secs(3600))
+            .unwrap();
+
+        let updates = manager.handle_event(&payment("u1inv", 1_000, None));
+        assert_eq!(updates[0].status, InvoiceStatus::SeenInMempool);
+
+        let updates = manager.handle_event(&payment("u1inv", 1_000, Some(10)));
+        assert_eq!(updates[0].status, InvoiceStatus::Paid);
+        assert_eq!(manager.get(&invoice.id).unwrap().confirmed_amount, 1_000);
+    }
+
+    #[test]
+    fn test_payment_eta_and_expiry() {
+        let mut manager = InvoiceManager::new(ConfirmationPolicy::new(3));
+        let invoice = manager
+            .create_invoice("u1eta", 1_000, None, Duration::from_secs(600))
+            .unwrap();
+        assert_eq!(invoice.expires_in(invoice.created_at + 100), 500);
+        assert_eq!(invoice.expires_in(invoice.created_at + 900), 0);
+
+        let estimator = BlockTimeEstimator::from_headers([(10, 1_000), (12, 1_150)]);
+        assert!(manager
+            .payment_eta(&invoice.id, &estimator, 1_150)
+            .is_none());
+
+        manager.handle_event(&payment("u1eta", 1_000, Some(12)));
+        let eta = manager.payment_eta(&invoice.id, &estimator, 1_150).unwrap();
+        assert_eq!(eta.remaining_blocks, 2);
+        assert_eq!(eta.seconds, 150);
+
+        let expiry = Invoice {
+            expires_at: 1_300,
+            ..invoice
+        };
+        assert_eq!(expiry.expiry_height(&estimator), Some(14));
+    }
+
+    #[test]
+    fn test_invoice_underpaid_and_expired() {
+        let mut manager = InvoiceManager::new(ConfirmationPolicy::new(1));
+        let short = manager
+            .create_invoice("u1short", 1_000, None, Duration::from_secs(0))
+            .unwrap();
+        let expired = manager
+            .create_invoice("u1none", 1_000, None, Duration::from_secs(0))
+            .unwrap();
+
+        manager.handle_event(&payment("u1short", 400, Some(5)));
+        assert_eq!(manager.get(&short.id).unwrap().status, InvoiceStatus::Underpaid);
+        assert_eq!(manager.get(&expired.id).unwrap().status, InvoiceStatus::Expired);
+    }
+
+    #[test]
+    fn test_reorg_reopens_paid_invoice() {
+        let mut manager = InvoiceManager::new(ConfirmationPolicy::new(1));
+        let invoice = manager
+            .create_invoice("u1reorg", 1_000, None, Duration::from_secs(3600))
+            .unwrap();
+        manager.handle_event(&payment("u1reorg", 1_000, Some(10)));
+        assert_eq!(
+            manager.get(&invoice.id).unwrap().status,
+            InvoiceStatus::Paid
+        );
+
+        let updates = manager.handle_event(&WalletEvent::Reorg {
+            depth: 3,
+            old_tip: 12,
+            new_tip: 12,
+        });
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].previous, InvoiceStatus::Paid);
+        assert_eq!(updates[0].status, InvoiceStatus::SeenInMempool);
+        assert_eq!(manager.get(&invoice.id).unwrap().confirmed_amount, 0);
+
+        let updates = manager.handle_event(&payment("u1reorg", 1_000, Some(11)));
+        assert_eq!(updates[0].status, InvoiceStatus::Paid);
+    }
+
+    #[test]
+    fn test_late_payment_to_expired_invoice() {
+        let mut manager = InvoiceManager::new(ConfirmationPolicy::new(1));
+        let invoice = manager
+            .create_invoice("u1late", 1_000, None, Duration::from_secs(0))
+            .unwrap();
+        manager.refresh(unix_now());
+        assert_eq!(
+            manager.get(&invoice.id).unwrap().status,
+            InvoiceStatus::Expired
+        );
+
+        let updates = manager.handle_event(&payment("u1late", 1_000, None));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].status, InvoiceStatus::Expired);
+        assert_eq!(updates[0].late_payment.as_ref().unwrap().amount, 1_000);
+        // Seen again unchanged: no new update; once mined: reported again
+        assert!(manager
+            .handle_event(&payment("u1late", 1_000, None))
+            .is_empty());
+        let updates = manager.handle_event(&payment("u1late", 1_000, Some(7)));
+        assert_eq!(updates[0].late_payment.as_ref().unwrap().height, Some(7));
+        assert_eq!(
+            manager.get(&invoice.id).unwrap().status,
+            InvoiceStatus::Expired
+        );
+    }
+}
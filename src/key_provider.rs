@@ -0,0 +1,162 @@
+//! External key providers
+//!
+//! A [`Wallet`](crate::wallet::Wallet) normally derives every key from a seed
+//! it holds in memory. With a [`KeyProvider`] the seed stays in an HSM, the OS
+//! keychain or a secure enclave instead: the wallet asks the provider for
+//! the unified full viewing keys of its accounts, handles only those
+//! locally, and spends through the provider's [`Signer`]. Open such a wallet
+//! with [`Wallet::with_key_provider`](crate::wallet::Wallet::with_key_provider).
+//!
+//! [`SeedKeyProvider`] is the in-memory implementation, e.g. for tests or as
+//! a template for new providers.
+//!
+//! The trait is called `KeyProvider` rather than `KeyStore` because
+//! [`wallet::key_store::KeyStore`](crate::wallet::key_store::KeyStore)
+//! already names the public store of passphrase-sealed keys in the wallet
+//! database; renaming that would break its users.
+
+use crate::error::{Error, Result};
+use crate::signer::Signer;
+use crate::types::Network;
+use crate::wallet::consensus_network;
+use secrecy::{ExposeSecret, SecretVec};
+use zcash_keys::keys::{UnifiedFullViewingKey, UnifiedSpendingKey};
+use zip32::{fingerprint::SeedFingerprint, AccountId};
+
+/// Holds a wallet seed and derives keys from it on request
+///
+/// Implementations never hand out the seed or spending keys; the wallet only
+/// receives viewing keys and spends through [`signer`](Self::signer).
+pub trait KeyProvider: Send + Sync {
+    /// Name shown to users, e.g. in errors about operations needing the seed
+    fn name(&self) -> &str;
+
+    /// ZIP-32 fingerprint of the seed
+    ///
+    /// Recorded with the wallet's accounts so their transactions can be
+    /// signed by the provider, and checked against the wallet database when
+    /// the wallet is opened.
+    fn seed_fingerprint(&self) -> Result<SeedFingerprint>;
+
+    /// Derive the unified full viewing key of a ZIP-32 account
+    ///
+    /// # Arguments
+    /// * `network` - Network the key is used on
+    /// * `account` - ZIP-32 account index
+    fn unified_full_viewing_key(
+        &self,
+        network: Network,
+        account: AccountId,
+    ) -> Result<UnifiedFullViewingKey>;
+
+    /// Signer for the spends of a ZIP-32 account
+    ///
+    /// # Arguments
+    /// * `network` - Network the signed transactions are for
+    /// * `account` - ZIP-32 account index
+    fn signer(&self, network: Network, account: AccountId) -> Result<Box<dyn Signer>>;
+}
+
+/// Key provider backed by a seed in memory
+///
+/// Deliberately not `Debug`: it holds the seed.
+pub struct SeedKeyProvider {
+    seed: SecretVec<u8>,
+}
+
+impl SeedKeyProvider {
+    /// Create a provider for a seed of 32 to 252 bytes
+    pub fn new(seed: Vec<u8>) -> Result<Self> {
+        if !(32..=252).contains(&seed.len()) {
+            return Err(Error::InvalidParameter(format!(
+                "Seed must be 32 to 252 bytes, got {}",
+                seed.len()
+            )));
+        }
+        Ok(Self {
+            seed: SecretVec::new(seed),
+        })
+    }
+
+    fn spending_key(&self, network: Network, account: AccountId) -> Result<UnifiedSpendingKey> {
+        UnifiedSpendingKey::from_seed(
+            &consensus_network(network),
+            self.seed.expose_secret(),
+            account,
+        )
+        .map_err(|e| Error::KeyDerivation(format!("Failed to derive unified spending key: {}", e)))
+    }
+}
+
+impl KeyProvider for SeedKeyProvider {
+    fn name(&self) -> &str {
+        "in-memory seed"
+    }
+
+    fn seed_fingerprint(&self) -> Result<SeedFingerprint> {
+        SeedFingerprint::from_seed(self.seed.expose_secret())
+            .ok_or_else(|| Error::KeyDerivation("Failed to fingerprint seed".to_string()))
+    }
+
+    fn unified_full_viewing_key(
+        &self,
+        network: Network,
+        account: AccountId,
+    ) -> Result<UnifiedFullViewingKey> {
+        Ok(self
+            .spending_key(network, account)?
+            .to_unified_full_viewing_key())
+    }
+
+    #[cfg(feature = "pczt")]
+    fn signer(&self, network: Network, account: AccountId) -> Result<Box<dyn Signer>> {
+        Ok(Box::new(crate::signer::UskSigner::new(
+            self.spending_key(network, account)?,
+            network,
+        )))
+    }
+
+    #[cfg(not(feature = "pczt"))]
+    fn signer(&self, _network: Network, _account: AccountId) -> Result<Box<dyn Signer>> {
+        Err(Error::InvalidParameter(
+            "Signing with a seed key provider requires the SDK's pczt feature".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_wallet_with_key_provider() {
+        let seed = vec![7u8; 32];
+        let path = TempDb::new();
+        let provider = Arc::new(SeedKeyProvider::new(seed.clone()).unwrap());
+        let wallet = Wallet::with_key_provider(path.to_path_buf(), provider).unwrap();
+
+        // Viewing keys and addresses match those of a wallet holding the seed
+        let seeded_path = TempDb::new();
+        let seeded = Wallet::with_path_and_seed(seeded_path.to_path_buf(), Some(seed)).unwrap();
+        assert_eq!(
+            wallet.seed_fingerprint().unwrap(),
+            seeded.seed_fingerprint().unwrap()
+        );
+        assert_eq!(
+            wallet.get_unified_address().unwrap(),
+            seeded.get_unified_address().unwrap()
+        );
+
+        // The seed itself never reaches the wallet
+        assert!(matches!(wallet.export_mnemonic(), Err(Error::Wallet(_))));
+        assert!(matches!(
+            wallet.store_seed("passphrase"),
+            Err(Error::Wallet(_))
+        ));
+        assert!(!wallet.is_locked());
+        assert!(SeedKeyProvider::new(vec![0u8; 16]).is_err());
+    }
+}
@@ -42,15 +42,71 @@
 //! ```
 
 pub mod address;
+pub mod address_book;
+pub mod airgap;
+pub mod analytics_export;
+pub mod alerts;
+pub mod approval;
+pub mod audit;
+pub mod auto_backup;
+pub mod backup;
+pub mod bandwidth;
+pub mod block_cache;
+pub mod block_time;
+pub mod broadcast;
+pub mod broadcaster;
 pub mod client;
+pub mod correlation;
+pub mod counterparty;
+pub mod db_encryption;
+pub mod doctor;
+pub mod dual_control;
 pub mod error;
+pub mod fault_injection;
 pub mod fees;
+#[cfg(feature = "frost")]
+pub mod frost;
+pub mod headers;
+pub mod idempotency;
+pub mod indexer;
+pub mod interchange;
+pub mod invoices;
+pub mod key_export;
+pub mod key_provider;
 pub mod compliance;
+pub mod deposits;
+pub mod events;
+pub mod explorer;
+pub mod export_schema;
 pub mod light_client;
+pub mod maintenance;
+pub mod memo_commands;
+pub mod mempool;
+pub mod migration;
+pub mod monitor;
+pub mod node_import;
+pub mod params;
+pub mod payment_request;
+pub mod policy;
+pub mod price_history;
+pub mod providers;
+pub mod receipt;
+pub mod reconcile;
+pub mod regtest;
+pub mod replay;
 pub mod rpc;
+pub mod scheduler;
+pub mod server_registry;
+pub mod signer;
+pub mod snapshot;
+mod store;
+pub mod throttle;
 pub mod transaction;
+pub mod tuning;
 pub mod types;
 pub mod wallet;
+pub mod watcher;
+pub mod zcashd_dump;
 
 pub use error::{Error, Result};
 
@@ -65,3 +121,6 @@ pub use fees::{calculate_zip317_fee, calculate_fee_from_payments, fee_zatoshis_t
 
 /// Re-export compliance helpers
 pub use compliance::*;
+
+/// Re-export the startup self-check
+pub use doctor::doctor;
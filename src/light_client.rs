@@ -26,22 +26,69 @@
 //! - GetLatestBlock (tested with grpcurl)
 //! - GetBlockRange (tested with grpcurl)
 
+use crate::auto_backup::AutoBackup;
+use crate::backup::WalletBackup;
+use crate::bandwidth::{BandwidthMeter, BandwidthUsage, MeteredIo};
+use crate::block_cache::BlockCache;
+use crate::block_time::BlockTimeEstimator;
 use crate::error::{Error, Result};
-use crate::types::Network;
-use crate::wallet::Wallet;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use zcash_client_backend::data_api::{WalletRead, WalletWrite};
-use zcash_client_backend::data_api::chain::{self, BlockSource};
-use zcash_client_backend::scanning::{ScanningKeys};
+use crate::events::{EventBus, ReceivedPayment, WalletEvent};
+use crate::fault_injection::{FaultInjector, FaultyIo};
+use crate::headers::RequestHeaders;
+use crate::params::NetworkParams;
+use crate::replay::{read_replay, ReplayRecord, ReplayRecorder};
+use crate::server_registry::{pinned_channel, KnownServer, ServerRegistry};
+use crate::throttle::{SyncThrottle, DEFAULT_SYNC_BATCH_SIZE};
+use crate::tuning::{
+    benchmark_blocks, tune, ScanBenchmark, ScanTuning, DEFAULT_TARGET_BATCH_TIME,
+};
+use crate::types::{Balance, Network};
+use crate::wallet::account_metadata::AccountMetadataStore;
+use crate::wallet::balance_history::BalanceHistory;
+use crate::wallet::pool::WalletDbPool;
+use crate::wallet::transparent::{
+    TransparentAddressInfo, TransparentAddresses, TransparentChain, DEFAULT_GAP_LIMIT,
+};
+use crate::wallet::{address_at_index, wallet_balance, Wallet};
+use prost::Message;
+use secrecy::{ExposeSecret, SecretVec};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
+use zcash_client_backend::data_api::{NullifierQuery, ScannedBlock, WalletRead, WalletWrite};
+use zcash_client_backend::data_api::chain::ChainState;
+use zcash_client_backend::scanning::{scan_block, Nullifiers, ScanningKeys};
+use zcash_client_backend::wallet::WalletTx;
 use zcash_client_backend::proto::compact_formats::CompactBlock;
 use zcash_client_backend::proto::service::compact_tx_streamer_client::CompactTxStreamerClient;
-use zcash_client_backend::proto::service::{BlockId, BlockRange, ChainSpec, RawTransaction, TxFilter};
-use zcash_client_sqlite::{util::SystemClock, WalletDb};
-use zcash_keys::keys::UnifiedFullViewingKey;
-use zcash_protocol::consensus::Network as ConsensusNetwork;
+use zcash_client_backend::proto::service::{
+    AddressList, BlockId, BlockRange, ChainSpec, Empty, RawTransaction,
+    TransparentAddressBlockFilter, TreeState, TxFilter,
+};
+use zcash_client_sqlite::AccountUuid;
+use zcash_keys::keys::{UnifiedFullViewingKey, UnifiedSpendingKey};
+use zcash_primitives::consensus::Parameters;
 use zip32::AccountId;
 
+/// Version and features of a lightwalletd server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// lightwalletd version, e.g. `v0.4.17`
+    pub version: String,
+    pub vendor: String,
+    /// Chain the server follows: `main`, `test` or `regtest`
+    pub chain_name: String,
+    /// Whether the server answers transparent address queries
+    pub taddr_support: bool,
+    /// Height of the server's chain tip when queried
+    pub block_height: u64,
+    /// User agent of the full node behind the server
+    pub node_subversion: String,
+}
+
 /// Light client for connecting to lightwalletd servers
 ///
 /// This client connects to a lightwalletd server via gRPC to sync blockchain data
@@ -51,18 +98,46 @@ pub struct LightClient {
     /// gRPC endpoint URL
     endpoint: String,
     /// Wallet database for storing synced data
-    wallet_db: Arc<Mutex<WalletDb<rusqlite::Connection, ConsensusNetwork, SystemClock, rand::rngs::ThreadRng>>>,
+    wallet_db: WalletDbPool,
     /// Network (mainnet/testnet/regtest)
     network: Network,
     /// Unified full viewing key for scanning
     ufvk: UnifiedFullViewingKey,
     /// Consensus network type
-    consensus_network: ConsensusNetwork,
+    consensus_network: NetworkParams,
+    /// Optional event bus for sync progress events
+    event_bus: Option<EventBus>,
+    /// Optional shared block cache and this client's ID in it
+    block_cache: Option<(BlockCache, String)>,
+    /// Registry entry whose certificate pins are enforced, if any
+    pinned: Option<KnownServer>,
+    /// Optional byte counter with a soft cap
+    bandwidth: Option<BandwidthMeter>,
+    /// Optional pause/batch/yield controls driven by the host app
+    throttle: Option<SyncThrottle>,
+    /// Optional log of every block and tree state consumed
+    replay: Option<ReplayRecorder>,
+    /// Tree states loaded from a replay log, by height
+    replay_tree_states: HashMap<u64, TreeState>,
+    /// Transparent address chains of the account, if its key has a
+    /// transparent component
+    transparent: Option<TransparentAddresses>,
+    /// Server version and features, queried on first use
+    server_info: Option<ServerInfo>,
+    /// User-Agent and extra headers sent with every request
+    headers: RequestHeaders,
+    /// Optional backups taken after scanned blocks and sent transactions
+    auto_backup: Option<AutoBackup>,
+    /// Optional faults injected into the connection, for resilience tests
+    faults: Option<FaultInjector>,
 }
 
 impl LightClient {
     /// Create a new light client and connect to a lightwalletd server
     ///
+    /// Queries the server's info and fails if it follows a different chain
+    /// than the wallet's network, before anything is synced from it.
+    ///
     /// # Arguments
     /// * `endpoint` - gRPC endpoint URL (e.g., "https://lightwalletd.example.com:9067")
     /// * `wallet` - Wallet instance to use for key management and storage
@@ -82,6 +157,15 @@ impl LightClient {
     /// # }
     /// ```
     pub async fn connect(endpoint: String, wallet: Wallet) -> Result<Self> {
+        let mut client = Self::open(endpoint, wallet)?;
+        client.get_server_info().await?;
+        Ok(client)
+    }
+
+    /// Create a light client without contacting the server, so pins and
+    /// headers can be set before [`get_server_info`](Self::get_server_info)
+    /// validates it
+    pub(crate) fn open(endpoint: String, wallet: Wallet) -> Result<Self> {
         // Validate endpoint URL format
         endpoint.parse::<tonic::transport::Uri>()
             .map_err(|e| Error::InvalidParameter(format!("Invalid endpoint URL: {}", e)))?;
@@ -90,10 +174,14 @@ impl LightClient {
         let ufvk = wallet.unified_full_viewing_key()?;
         
         // Get wallet database
-        let wallet_db = Arc::new(Mutex::new(wallet.wallet_db()?));
+        let wallet_db = wallet.db_pool()?;
         
         let network = wallet.network();
         let consensus_network = wallet.consensus_network();
+        let transparent = match ufvk.transparent() {
+            Some(_) => Some(TransparentAddresses::for_wallet(&wallet)?),
+            None => None,
+        };
 
         Ok(Self {
             endpoint,
@@ -101,35 +189,239 @@ impl LightClient {
             network,
             ufvk,
             consensus_network,
+            event_bus: None,
+            block_cache: None,
+            pinned: None,
+            bandwidth: None,
+            throttle: None,
+            replay: None,
+            replay_tree_states: HashMap::new(),
+            transparent,
+            server_info: None,
+            headers: RequestHeaders::default(),
+            auto_backup: None,
+            faults: None,
         })
     }
 
+    /// Connect to a lightwalletd server, enforcing the registry's pins
+    ///
+    /// If the endpoint is registered with pinned certificates, every
+    /// connection made by this client verifies the server certificate
+    /// against them. In strict mode, endpoints that are not registered and
+    /// pinned for the wallet's network are refused.
+    ///
+    /// # Arguments
+    /// * `endpoint` - gRPC endpoint URL
+    /// * `wallet` - Wallet instance to use for key management and storage
+    /// * `registry` - Known-server registry
+    pub async fn connect_with_registry(
+        endpoint: String,
+        wallet: Wallet,
+        registry: &ServerRegistry,
+    ) -> Result<Self> {
+        let pinned = registry
+            .resolve(&endpoint, wallet.network())?
+            .filter(|server| server.is_pinned())
+            .cloned();
+        let mut client = Self::open(endpoint, wallet)?;
+        client.pinned = pinned;
+        client.get_server_info().await?;
+        Ok(client)
+    }
+
+    /// Version and features of the server, as queried when connecting
+    ///
+    /// Fails if the server follows a different chain than the wallet's
+    /// network.
+    pub async fn server_info(&mut self) -> Result<ServerInfo> {
+        match &self.server_info {
+            Some(info) => Ok(self.with_reported_height(info.clone())),
+            None => self.get_server_info().await,
+        }
+    }
+
+    /// Query the server's version, features and chain tip with
+    /// `GetLightdInfo`
+    ///
+    /// # Returns
+    /// The server info, or an error if the server follows a different chain
+    /// (`main`, `test` or `regtest`) than the wallet's network
+    pub async fn get_server_info(&mut self) -> Result<ServerInfo> {
+        let info = self.query_server_info().await?;
+        check_chain(&self.endpoint, &info.chain_name, self.network)?;
+        tracing::debug!("Server {} runs lightwalletd {}", self.endpoint, info.version);
+        self.server_info = Some(info.clone());
+        Ok(self.with_reported_height(info))
+    }
+
+    /// Query the server info with `GetLightdInfo`, without checking its chain
+    pub(crate) async fn query_server_info(&mut self) -> Result<ServerInfo> {
+        let mut client = streamer(self.channel()?);
+        let response = client
+            .get_lightd_info(Empty {})
+            .await
+            .map_err(|e| Error::Rpc(format!("Failed to get server info: {}", e)))?
+            .into_inner();
+        self.record_download(response.encoded_len());
+        let info = ServerInfo {
+            version: response.version,
+            vendor: response.vendor,
+            chain_name: response.chain_name,
+            taddr_support: response.taddr_support,
+            block_height: response.block_height,
+            node_subversion: response.zcashd_subversion,
+        };
+        Ok(info)
+    }
+
+    /// A tip height as reported with the injected staleness
+    fn reported_height(&self, height: u64) -> u64 {
+        match self.faults {
+            Some(ref faults) => faults.stale_height(height),
+            None => height,
+        }
+    }
+
+    fn with_reported_height(&self, mut info: ServerInfo) -> ServerInfo {
+        info.block_height = self.reported_height(info.block_height);
+        info
+    }
+
+    /// Create a channel to the server, pinned if the server is registered
+    fn channel(&self) -> Result<LightwalletdChannel> {
+        if let Some(faults) = &self.faults {
+            return faulty_channel(
+                &self.endpoint,
+                faults.clone(),
+                self.bandwidth.clone(),
+                &self.headers,
+            );
+        }
+        match (&self.pinned, &self.bandwidth) {
+            (Some(server), meter) => pinned_channel(server, meter.clone(), &self.headers),
+            (None, Some(meter)) if !self.endpoint.starts_with("https") => {
+                metered_channel(&self.endpoint, meter.clone(), &self.headers)
+            }
+            _ => lazy_channel(&self.endpoint, &self.headers),
+        }
+    }
+
     /// Get the current network
     pub fn network(&self) -> Network {
         self.network
     }
 
+    /// Publish sync progress ([`WalletEvent::ChainTip`] and
+    /// [`WalletEvent::BlocksScanned`]) onto the given event bus
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.event_bus = Some(bus);
+    }
+
+    /// Load compact blocks through a cache shared with other clients
+    ///
+    /// `wallet_id` identifies this client in the cache; blocks are released
+    /// once every registered client has synced past them.
+    pub fn set_block_cache(&mut self, cache: BlockCache, wallet_id: impl Into<String>) {
+        self.block_cache = Some((cache, wallet_id.into()));
+    }
+
+    /// Count bytes exchanged with lightwalletd and enforce the meter's soft cap
+    ///
+    /// When a shared [`BlockCache`] is used, give it the same meter with
+    /// [`BlockCache::set_bandwidth_meter`] so cached downloads are counted too.
+    ///
+    /// For plain-HTTP and pinned endpoints the connection's socket traffic is
+    /// also counted, so [`BandwidthUsage::compression_ratio`] reports how much
+    /// lightwalletd's response compression saves.
+    pub fn set_bandwidth_meter(&mut self, meter: BandwidthMeter) {
+        self.bandwidth = Some(meter);
+    }
+
+    /// Inject latency, dropped connections, corrupted responses and stale
+    /// tip heights, for resilience tests
+    ///
+    /// Faults are injected at the socket, so they also hit streams such as
+    /// block ranges mid-way. Only plain-HTTP endpoints are supported; see
+    /// [`fault_injection`](crate::fault_injection).
+    pub fn set_fault_injector(&mut self, faults: FaultInjector) {
+        self.faults = Some(faults);
+    }
+
+    /// Let the host app pause sync and change its batch size and yield
+    /// interval (e.g. from OS lifecycle events) through `throttle`
+    pub fn set_sync_throttle(&mut self, throttle: SyncThrottle) {
+        self.throttle = Some(throttle);
+    }
+
+    /// Send a custom User-Agent and extra headers (e.g. a managed provider's
+    /// API key) with every request to lightwalletd
+    pub fn set_headers(&mut self, headers: RequestHeaders) {
+        self.headers = headers;
+    }
+
+    /// Back the wallet up as configured in `backup` while syncing and sending
+    ///
+    /// Scanned batches and successfully sent transactions are counted
+    /// towards the backup's intervals. A failed backup is logged and does
+    /// not interrupt sync or sending.
+    pub fn set_auto_backup(&mut self, backup: AutoBackup) {
+        self.auto_backup = Some(backup);
+    }
+
+    /// Record every compact block scanned and tree state used to a replay log
+    ///
+    /// See [`crate::replay`] and [`replay`](Self::replay).
+    pub fn set_replay_recorder(&mut self, recorder: ReplayRecorder) {
+        self.replay = Some(recorder);
+    }
+
+    /// Bytes exchanged so far, if a meter is set
+    pub fn bandwidth_usage(&self) -> Option<BandwidthUsage> {
+        self.bandwidth.as_ref().map(BandwidthMeter::usage)
+    }
+
+    fn record_download(&self, bytes: usize) {
+        if let Some(meter) = &self.bandwidth {
+            meter.record_download(bytes as u64);
+        }
+    }
+
+    /// Publish [`WalletEvent::BandwidthCapReached`] if the soft cap is exceeded
+    ///
+    /// # Returns
+    /// Whether sync should pause
+    fn bandwidth_cap_reached(&self, next_height: u64) -> bool {
+        let Some(meter) = self.bandwidth.as_ref().filter(|m| m.is_over_cap()) else {
+            return false;
+        };
+        let usage = meter.usage();
+        tracing::info!(
+            "Bandwidth soft cap reached ({} bytes), pausing sync at height {}",
+            usage.total(),
+            next_height
+        );
+        self.publish(WalletEvent::BandwidthCapReached {
+            next_height,
+            downloaded: usage.downloaded,
+            uploaded: usage.uploaded,
+            cap: meter.soft_cap().unwrap_or_default(),
+        });
+        true
+    }
+
+    fn publish(&self, event: WalletEvent) {
+        if let Some(ref bus) = self.event_bus {
+            bus.publish(event);
+        }
+    }
+
     /// Get the latest block height from the lightwalletd server
     ///
     /// This queries the lightwalletd server to determine the current blockchain height.
     pub async fn get_latest_block_height(&mut self) -> Result<u64> {
-        // Create gRPC client - use Endpoint with connect_lazy for compatibility
-        use tonic::transport::Endpoint;
-        
-        let channel = Endpoint::from_shared(self.endpoint.clone())
-            .map_err(|e| Error::InvalidParameter(format!("Invalid endpoint URL: {}", e)))?
-            .connect_lazy();
-
-        let mut client = CompactTxStreamerClient::new(channel);
-        let request = tonic::Request::new(ChainSpec {});
-        
-        let response = client
-            .get_latest_block(request)
-            .await
-            .map_err(|e| Error::Rpc(format!("Failed to get latest block: {}", e)))?;
-
-        let block = response.into_inner();
-        Ok(block.height)
+        let height = fetch_latest_height(self.channel()?).await?;
+        Ok(self.reported_height(height))
     }
 
     /// Get compact blocks for a given height range
@@ -145,49 +437,50 @@ impl LightClient {
         start_height: u64,
         end_height: u64,
     ) -> Result<Vec<CompactBlock>> {
-        // Create gRPC client - use Endpoint with connect_lazy for compatibility
-        use tonic::transport::Endpoint;
-        
-        let channel = Endpoint::from_shared(self.endpoint.clone())
-            .map_err(|e| Error::InvalidParameter(format!("Invalid endpoint URL: {}", e)))?
-            .connect_lazy();
-
-        let mut client = CompactTxStreamerClient::new(channel);
-        let mut blocks = Vec::new();
-        
-        let request = tonic::Request::new(BlockRange {
-            start: Some(BlockId {
-                height: start_height,
-                hash: vec![],
-            }),
-            end: Some(BlockId {
-                height: end_height,
-                hash: vec![],
-            }),
-        });
-
-        let mut stream = client
-            .get_block_range(request)
-            .await
-            .map_err(|e| Error::Rpc(format!("Failed to get block range: {}", e)))?
-            .into_inner();
-
-        while let Some(compact_block) = stream
-            .message()
-            .await
-            .map_err(|e| Error::Rpc(format!("Failed to receive block: {}", e)))?
-        {
-            blocks.push(compact_block);
-        }
-
+        let blocks = fetch_compact_blocks(self.channel()?, start_height, end_height).await?;
+        self.record_download(blocks.iter().map(|b| b.encoded_len()).sum());
         Ok(blocks)
     }
 
+    /// Sample recent block header times for time and ETA estimates
+    ///
+    /// # Arguments
+    /// * `sample_blocks` - Number of blocks up to the chain tip to sample
+    ///   (see [`DEFAULT_SAMPLE_BLOCKS`](crate::block_time::DEFAULT_SAMPLE_BLOCKS))
+    pub async fn block_time_estimator(&mut self, sample_blocks: u64) -> Result<BlockTimeEstimator> {
+        let tip = self.get_latest_block_height().await?;
+        let start = tip.saturating_sub(sample_blocks.saturating_sub(1));
+        let blocks = self.get_compact_blocks(start, tip).await?;
+        Ok(BlockTimeEstimator::from_headers(
+            blocks.iter().map(|block| (block.height, u64::from(block.time))),
+        ))
+    }
+
     /// Sync the wallet with the blockchain by scanning blocks
     ///
     /// This method fetches compact blocks from lightwalletd and scans them
     /// using the wallet's viewing keys to find transactions relevant to the wallet.
     ///
+    /// If a [`BandwidthMeter`] with a soft cap is set and the cap is exceeded,
+    /// sync stops before the next batch and publishes
+    /// [`WalletEvent::BandwidthCapReached`] with the height to resume from.
+    ///
+    /// If a [`SyncThrottle`] is set, sync waits before each batch while it is
+    /// paused, uses its batch size, and sleeps for its yield interval between
+    /// batches.
+    ///
+    /// Once all blocks are scanned, transparent addresses of the account are
+    /// discovered up to the end height (see
+    /// [`discover_transparent_addresses`](Self::discover_transparent_addresses)).
+    ///
+    /// Payments received by the wallet's accounts are published as
+    /// [`WalletEvent::PaymentReceived`] once mined, without memos, since
+    /// compact blocks do not carry them.
+    ///
+    /// Blocks scanned while a re-enabled account was archived (see
+    /// [`Wallet::unarchive_account`]) are queued for a rescan; if the queue
+    /// starts below `start_height`, sync starts there instead.
+    ///
     /// # Arguments
     /// * `start_height` - Starting block height to scan from
     /// * `end_height` - Ending block height to scan to (use None for latest)
@@ -209,7 +502,9 @@ impl LightClient {
         let end = if let Some(height) = end_height {
             height
         } else {
-            self.get_latest_block_height().await?
+            let tip = self.get_latest_block_height().await?;
+            self.publish(WalletEvent::ChainTip { height: tip });
+            tip
         };
 
         if start_height > end {
@@ -219,23 +514,57 @@ impl LightClient {
             )));
         }
 
+        // Rescan what unarchived accounts missed while they were archived
+        let account_metadata = AccountMetadataStore::open(self.wallet_db.path())?;
+        let start_height = match account_metadata.pending_rescan()? {
+            Some(rescan) if *rescan.start() < start_height => {
+                tracing::info!(
+                    "Rescanning from height {} for unarchived accounts",
+                    rescan.start()
+                );
+                *rescan.start()
+            }
+            _ => start_height,
+        };
+
         tracing::info!("Starting sync from height {} to {}", start_height, end);
 
+        if let Some((cache, wallet_id)) = &self.block_cache {
+            cache.register_wallet(wallet_id.clone(), start_height);
+        }
+
         // Get the account ID (using AccountId::ZERO for the default account)
         let _account_id = AccountId::ZERO;
 
         // Fetch compact blocks from lightwalletd in batches to avoid memory issues
-        const BATCH_SIZE: u64 = 100; // Process 100 blocks at a time
         let mut current_height = start_height;
         let mut total_blocks_scanned = 0;
 
         while current_height <= end {
-            let batch_end = std::cmp::min(current_height + BATCH_SIZE - 1, end);
+            if let Some(throttle) = &self.throttle {
+                throttle.wait_until_resumed().await;
+            }
+            if self.bandwidth_cap_reached(current_height) {
+                return Ok(());
+            }
+
+            let batch_size = self
+                .throttle
+                .as_ref()
+                .map_or(DEFAULT_SYNC_BATCH_SIZE, SyncThrottle::batch_size);
+            let batch_end = std::cmp::min(current_height + batch_size - 1, end);
             
             tracing::debug!("Fetching blocks {} to {}", current_height, batch_end);
             
             // Fetch compact blocks for this batch
-            let compact_blocks = self.get_compact_blocks(current_height, batch_end).await?;
+            let compact_blocks = match &self.block_cache {
+                Some((cache, _)) => {
+                    cache
+                        .get_or_fetch(self.channel()?, current_height, batch_end)
+                        .await?
+                }
+                None => self.get_compact_blocks(current_height, batch_end).await?,
+            };
 
             if compact_blocks.is_empty() {
                 tracing::warn!("No blocks returned for range {} to {}", current_height, batch_end);
@@ -250,150 +579,18 @@ impl LightClient {
                 batch_end
             );
 
-            // Lock the wallet database for scanning
-            let mut wallet_db = self.wallet_db.lock().await;
-
-            // Get or import the AccountUuid for the UFVK
-            // The wallet database uses AccountUuid internally, so we need to get/import an account
-            use zcash_client_backend::data_api::{AccountBirthday, AccountPurpose, chain::ChainState};
-            
-            // Create a minimal AccountBirthday for account import
-            let birthday = AccountBirthday::from_parts(
-                ChainState::empty(
-                    zcash_primitives::consensus::BlockHeight::from_u32(0),
-                    zcash_primitives::block::BlockHash([0u8; 32]),
-                ),
-                None,
-            );
-            
-            let _account_uuid = match wallet_db.get_account_for_ufvk(&self.ufvk) {
-                Ok(Some(_account)) => {
-                    // Account exists - re-import to get the UUID
-                    // import_account_ufvk returns the UUID even if account already exists
-                    wallet_db
-                        .import_account_ufvk(
-                            "", // account name - empty for default
-                            &self.ufvk,
-                            &birthday,
-                            AccountPurpose::ViewOnly,
-                            None, // seed
-                        )
-                        .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?
-                }
-                Ok(None) => {
-                    // Account doesn't exist, import it
-                    wallet_db
-                        .import_account_ufvk(
-                            "", // account name - empty for default
-                            &self.ufvk,
-                            &birthday,
-                            AccountPurpose::ViewOnly,
-                            None, // seed
-                        )
-                        .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?
-                }
-                Err(e) => {
-                    return Err(Error::Database(format!("Failed to get account for UFVK: {}", e)));
-                }
-            };
-
-            // Build scanning keys from the unified full viewing key
-            let account_id = AccountId::ZERO;
-            
-            // Create scanning keys from the unified full viewing key
-            // from_account_ufvks takes an iterator of (account_id, ufvk) tuples with owned values
-            let _scanning_keys = ScanningKeys::from_account_ufvks(
-                std::iter::once((account_id, self.ufvk.clone()))
-            );
-
-            // Get nullifiers from wallet database for checking spent notes
-            // Note: For scanning, we use empty nullifiers. The scan_block function will
-            // check against nullifiers in the wallet database automatically, and the
-            // scanned results will update the database with new nullifiers.
-            use zcash_client_backend::scanning::Nullifiers;
-            
-            // Use empty nullifiers - the scanning process will handle nullifier tracking
-            // through the wallet database. The scan_block function uses nullifiers primarily
-            // for checking if notes have been spent, which is handled by the database.
-            let _nullifiers: Nullifiers<AccountId> = Nullifiers::empty();
-
-            // Prepare ChainState from prior metadata (or empty at genesis)
-            let max_scanned_metadata = wallet_db
-                .block_max_scanned()
-                .map_err(|e| Error::Database(format!("Failed to get max scanned height: {}", e)))?;
-            let chain_state = if let Some(metadata) = max_scanned_metadata {
-                zcash_client_backend::data_api::chain::ChainState::empty(
-                    metadata.block_height(),
-                    metadata.block_hash(),
-                )
-            } else {
-                zcash_client_backend::data_api::chain::ChainState::empty(
-                    zcash_primitives::consensus::BlockHeight::from_u32(0),
-                    zcash_primitives::block::BlockHash([0u8; 32]),
-                )
-            };
-
-            // Adapt fetched compact blocks into a BlockSource and scan+persist them
-            struct VecBlockSource {
-                blocks: Vec<CompactBlock>,
-            }
-            impl BlockSource for VecBlockSource {
-                type Error = std::convert::Infallible;
-                fn with_blocks<F, DbErrT>(
-                    &self,
-                    from_height: Option<zcash_protocol::consensus::BlockHeight>,
-                    limit: Option<usize>,
-                    mut with_row: F,
-                ) -> std::result::Result<(), zcash_client_backend::data_api::chain::error::Error<DbErrT, Self::Error>>
-                where
-                    F: FnMut(CompactBlock) -> std::result::Result<(), zcash_client_backend::data_api::chain::error::Error<DbErrT, Self::Error>>,
-                {
-                    let start = from_height.map(|h| u32::from(h)).unwrap_or(0);
-                    let mut count = 0usize;
-                    for b in &self.blocks {
-                        if u32::from(b.height()) >= start {
-                            with_row(b.clone())?;
-                            count += 1;
-                            if let Some(lim) = limit {
-                                if count >= lim {
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                    Ok(())
-                }
-            }
-
-            let source = VecBlockSource { blocks: compact_blocks };
-            let from_h = zcash_protocol::consensus::BlockHeight::from_u32(current_height as u32);
-            // Limit to batch size
-            let limit = (batch_end - current_height + 1) as usize;
-            match chain::scan_cached_blocks(
-                &self.consensus_network,
-                &source,
-                &mut *wallet_db,
-                from_h,
-                &chain_state,
-                limit,
-            ) {
-                Ok(summary) => {
-                    let range = summary.scanned_range();
-                    tracing::debug!(
-                        "Scanned {} blocks ({}..={})",
-                        (range.end - range.start) as u64,
-                        current_height,
-                        batch_end
-                    );
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to scan cached blocks: {:?}", e);
-                }
+            self.scan_blocks(current_height, compact_blocks).await?;
+            if let Some((cache, wallet_id)) = &self.block_cache {
+                cache.mark_scanned(wallet_id, current_height + blocks_count as u64 - 1);
             }
 
             total_blocks_scanned += blocks_count;
             current_height = batch_end + 1;
 
+            if let Some(throttle) = self.throttle.as_ref().filter(|_| current_height <= end) {
+                throttle.yield_between_batches().await;
+            }
+
             tracing::debug!(
                 "Scanned {} blocks, progress: {}/{}",
                 blocks_count,
@@ -402,6 +599,27 @@ impl LightClient {
             );
         }
 
+        if current_height > start_height {
+            account_metadata.complete_rescan(start_height..=current_height - 1)?;
+        }
+
+        if self.transparent.is_some() && !self.server_info().await?.taddr_support {
+            tracing::warn!(
+                "Server {} does not support transparent address queries; \
+                 skipping transparent address discovery",
+                self.endpoint
+            );
+        } else if self.transparent.is_some() {
+            let birthday = self
+                .wallet_db
+                .read()?
+                .get_wallet_birthday()
+                .map_err(|e| Error::Database(format!("Failed to read wallet birthday: {}", e)))?
+                .map_or(start_height, |height| u64::from(u32::from(height)));
+            self.discover_transparent_addresses(birthday, end, DEFAULT_GAP_LIMIT)
+                .await?;
+        }
+
         tracing::info!(
             "Sync completed: scanned {} blocks from height {} to {}",
             total_blocks_scanned,
@@ -412,6 +630,269 @@ impl LightClient {
         Ok(())
     }
 
+    /// Serve the tree states in a replay log from [`account_birthday`](Self::account_birthday)
+    ///
+    /// Call this before re-importing accounts when reproducing a recorded
+    /// sync, so their birthdays match the recording without a server.
+    ///
+    /// # Returns
+    /// The number of tree states loaded
+    pub fn load_replay_tree_states(&mut self, path: &Path) -> Result<usize> {
+        let mut loaded = 0;
+        for record in read_replay(path)? {
+            if let ReplayRecord::TreeState(tree_state) = record {
+                self.replay_tree_states.insert(tree_state.height, tree_state);
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Re-run scanning from a replay log instead of the server
+    ///
+    /// Loads the log's tree states (see
+    /// [`load_replay_tree_states`](Self::load_replay_tree_states)), then
+    /// scans the recorded blocks in order, in runs of contiguous heights.
+    /// Use a fresh wallet database to reproduce the recorded sync. Nothing is
+    /// recorded while replaying.
+    ///
+    /// # Returns
+    /// The number of blocks scanned
+    pub async fn replay(&mut self, path: &Path) -> Result<usize> {
+        self.load_replay_tree_states(path)?;
+        let recorder = self.replay.take();
+        let result = self.replay_blocks(path).await;
+        self.replay = recorder;
+        result
+    }
+
+    async fn replay_blocks(&mut self, path: &Path) -> Result<usize> {
+        let mut scanned = 0;
+        let mut run: Vec<CompactBlock> = Vec::new();
+        let blocks = read_replay(path)?.into_iter().filter_map(|record| match record {
+            ReplayRecord::Block(block) => Some(block),
+            ReplayRecord::TreeState(_) => None,
+        });
+        for block in blocks {
+            let contiguous = run.last().map_or(true, |last| block.height == last.height + 1);
+            if !contiguous || run.len() as u64 >= DEFAULT_SYNC_BATCH_SIZE {
+                let from_height = run[0].height;
+                scanned += run.len();
+                self.scan_blocks(from_height, std::mem::take(&mut run)).await?;
+            }
+            run.push(block);
+        }
+        if let Some(first) = run.first() {
+            let from_height = first.height;
+            scanned += run.len();
+            self.scan_blocks(from_height, run).await?;
+        }
+        Ok(scanned)
+    }
+
+    /// Measure trial-decryption throughput on this device
+    ///
+    /// Downloads the `sample_blocks` blocks below the chain tip and
+    /// trial-decrypts their outputs with the wallet's keys, without writing to
+    /// the wallet database, using 1 up to the number of available CPU threads.
+    ///
+    /// # Arguments
+    /// * `sample_blocks` - Number of recent blocks to sample
+    pub async fn benchmark_trial_decryption(
+        &mut self,
+        sample_blocks: u64,
+    ) -> Result<ScanBenchmark> {
+        let tip = self.get_latest_block_height().await?;
+        let start = tip.saturating_sub(sample_blocks.max(1) - 1);
+        let blocks = self.get_compact_blocks(start, tip).await?;
+
+        let scanning_keys =
+            ScanningKeys::from_account_ufvks(std::iter::once((0u32, self.ufvk.clone())));
+        let network = self.consensus_network;
+        let max_workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        // Trial decryption is CPU bound; keep it off the async worker threads
+        tokio::task::spawn_blocking(move || {
+            let nullifiers = Nullifiers::<u32>::empty();
+            benchmark_blocks(&blocks, max_workers, |block| {
+                // Errors (e.g. missing tree sizes) only happen after decryption
+                let _ = scan_block(&network, block.clone(), &scanning_keys, &nullifiers, None);
+            })
+        })
+        .await
+        .map_err(|e| Error::Wallet(format!("Trial-decryption benchmark failed: {}", e)))
+    }
+
+    /// Benchmark trial decryption and tune the sync batch size to the device
+    ///
+    /// Applies the tuned batch size as the base batch size of this client's
+    /// [`SyncThrottle`] (installing one if none is set), so lifecycle presets
+    /// scale from it. The returned worker count is for hosts that scan
+    /// several wallets in parallel.
+    pub async fn auto_tune_scanning(&mut self) -> Result<ScanTuning> {
+        const SAMPLE_BLOCKS: u64 = 50;
+
+        let benchmark = self.benchmark_trial_decryption(SAMPLE_BLOCKS).await?;
+        let tuning = tune(&benchmark, DEFAULT_TARGET_BATCH_TIME);
+        tracing::info!(
+            "Trial decryption: {:.0} outputs/s with {} worker(s), batch size {}",
+            tuning.outputs_per_sec,
+            tuning.workers,
+            tuning.batch_size
+        );
+        self.throttle
+            .get_or_insert_with(SyncThrottle::new)
+            .set_base_batch_size(tuning.batch_size);
+        Ok(tuning)
+    }
+
+    /// Scan compact blocks that were fetched elsewhere into the wallet database
+    ///
+    /// Blocks must be contiguous and start at `from_height`. This is used by
+    /// [`sync`](Self::sync) and lets a caller that shares one download across
+    /// several wallets (see [`crate::scheduler`]) scan the same blocks for each.
+    ///
+    /// # Arguments
+    /// * `from_height` - Height of the first block
+    /// * `compact_blocks` - Blocks to scan, in height order
+    pub async fn scan_blocks(
+        &mut self,
+        from_height: u64,
+        compact_blocks: Vec<CompactBlock>,
+    ) -> Result<()> {
+        if compact_blocks.is_empty() {
+            return Ok(());
+        }
+        if let Some(recorder) = &self.replay {
+            recorder.record_blocks(&compact_blocks)?;
+        }
+        let current_height = from_height;
+        let batch_end = from_height + compact_blocks.len() as u64 - 1;
+        let batch_end_time = compact_blocks
+            .last()
+            .map_or(0, |block| u64::from(block.time));
+
+        // Lock the wallet database for scanning
+        let mut wallet_db = self.wallet_db.write()?;
+
+        // Get or import the AccountUuid for the UFVK
+        // The wallet database uses AccountUuid internally, so we need to get/import an account
+        use zcash_client_backend::data_api::{AccountBirthday, AccountPurpose};
+        
+        // Create a minimal AccountBirthday for account import
+        let birthday = AccountBirthday::from_parts(
+            ChainState::empty(
+                zcash_primitives::consensus::BlockHeight::from_u32(0),
+                zcash_primitives::block::BlockHash([0u8; 32]),
+            ),
+            None,
+        );
+        
+        let _account_uuid = match wallet_db.get_account_for_ufvk(&self.ufvk) {
+            Ok(Some(_account)) => {
+                // Account exists - re-import to get the UUID
+                // import_account_ufvk returns the UUID even if account already exists
+                wallet_db
+                    .import_account_ufvk(
+                        "", // account name - empty for default
+                        &self.ufvk,
+                        &birthday,
+                        AccountPurpose::ViewOnly,
+                        None, // seed
+                    )
+                    .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?
+            }
+            Ok(None) => {
+                // Account doesn't exist, import it
+                wallet_db
+                    .import_account_ufvk(
+                        "", // account name - empty for default
+                        &self.ufvk,
+                        &birthday,
+                        AccountPurpose::ViewOnly,
+                        None, // seed
+                    )
+                    .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?
+            }
+            Err(e) => {
+                return Err(Error::Database(format!("Failed to get account for UFVK: {}", e)));
+            }
+        };
+
+        // Build scanning keys from the unified full viewing key
+        let account_id = AccountId::ZERO;
+        
+        // Create scanning keys from the unified full viewing key
+        // from_account_ufvks takes an iterator of (account_id, ufvk) tuples with owned values
+        let _scanning_keys = ScanningKeys::from_account_ufvks(
+            std::iter::once((account_id, self.ufvk.clone()))
+        );
+
+        // Get nullifiers from wallet database for checking spent notes
+        // Note: For scanning, we use empty nullifiers. The scan_block function will
+        // check against nullifiers in the wallet database automatically, and the
+        // scanned results will update the database with new nullifiers.
+        
+        // Use empty nullifiers - the scanning process will handle nullifier tracking
+        // through the wallet database. The scan_block function uses nullifiers primarily
+        // for checking if notes have been spent, which is handled by the database.
+        let _nullifiers: Nullifiers<AccountId> = Nullifiers::empty();
+
+        // Prepare ChainState from prior metadata (or empty at genesis)
+        let max_scanned_metadata = wallet_db
+            .block_max_scanned()
+            .map_err(|e| Error::Database(format!("Failed to get max scanned height: {}", e)))?;
+        let chain_state = if let Some(metadata) = max_scanned_metadata {
+            ChainState::empty(
+                metadata.block_height(),
+                metadata.block_hash(),
+            )
+        } else {
+            ChainState::empty(
+                zcash_primitives::consensus::BlockHeight::from_u32(0),
+                zcash_primitives::block::BlockHash([0u8; 32]),
+            )
+        };
+
+        // Archived accounts are left out of trial decryption
+        let archived = AccountMetadataStore::open(self.wallet_db.path())?.archived()?;
+        let payments = match scan_active_accounts(
+            &self.consensus_network,
+            &mut *wallet_db,
+            &chain_state,
+            compact_blocks,
+            &archived,
+        ) {
+            Ok(payments) => {
+                tracing::debug!("Scanned blocks {}..={}", current_height, batch_end);
+                payments
+            }
+            Err(e) => {
+                tracing::warn!("Failed to scan blocks: {}", e);
+                Vec::new()
+            }
+        };
+        drop(wallet_db);
+
+        let balance = wallet_balance(&self.wallet_db.read()?, &archived)?;
+        BalanceHistory::open(self.wallet_db.path())?.record(batch_end, batch_end_time, &balance)?;
+        if let Some(backup) = &mut self.auto_backup {
+            if let Err(e) = backup.blocks_scanned(batch_end - current_height + 1) {
+                tracing::warn!("Automatic backup failed: {}", e);
+            }
+        }
+
+        for payment in payments {
+            self.publish(WalletEvent::PaymentReceived(payment));
+        }
+        self.publish(WalletEvent::BlocksScanned {
+            start_height: current_height,
+            end_height: batch_end,
+        });
+
+        Ok(())
+    }
+
     /// Submit a transaction to the network via lightwalletd
     ///
     /// # Arguments
@@ -424,19 +905,84 @@ impl LightClient {
     /// This is a placeholder implementation. The actual implementation requires
     /// using the CompactTxStreamerClient from zcash_client_backend::proto.
     pub async fn submit_transaction(&mut self, raw_tx: &[u8]) -> Result<String> {
-        use tonic::transport::Endpoint;
-        let channel = Endpoint::from_shared(self.endpoint.clone())
-            .map_err(|e| Error::InvalidParameter(format!("Invalid endpoint URL: {}", e)))?
-            .connect_lazy();
-        let mut client = CompactTxStreamerClient::new(channel);
+        let (error_code, error_message) = self.send_raw_transaction(raw_tx).await?;
+        // Return a status string; lightwalletd typically provides error info fields.
+        Ok(format!("code:{} message:{}", error_code, error_message))
+    }
+
+    /// Broadcast a transaction and verify the txid the server reports
+    ///
+    /// The txid is computed locally (see [`crate::transaction::txid`]) and
+    /// compared with the one lightwalletd returns on success.
+    ///
+    /// # Returns
+    /// The transaction ID (hex encoded)
+    pub async fn broadcast_transaction(&mut self, raw_tx: &[u8]) -> Result<String> {
+        let txid = crate::transaction::txid::txid(raw_tx)?;
+        let (error_code, error_message) = self.send_raw_transaction(raw_tx).await?;
+        if error_code != 0 {
+            return Err(Error::Transaction(format!(
+                "Server rejected transaction {}: code {}: {}",
+                txid, error_code, error_message
+            )));
+        }
+        // lightwalletd returns the txid as the message; older servers send nothing
+        let reported = error_message.trim().trim_matches('"');
+        if crate::transaction::txid::is_txid(reported) {
+            crate::transaction::txid::check_reported_txid(&txid, reported)?;
+        }
+        Ok(txid)
+    }
+
+    /// Send a raw transaction and return lightwalletd's response
+    ///
+    /// # Returns
+    /// The server's error code (0 on success) and error message
+    pub async fn send_raw_transaction(&mut self, raw_tx: &[u8]) -> Result<(i32, String)> {
+        let mut client = streamer(self.channel()?);
         let request = tonic::Request::new(RawTransaction { data: raw_tx.to_vec(), height: 0 });
+        if let Some(meter) = &self.bandwidth {
+            meter.record_upload(request.get_ref().encoded_len() as u64);
+        }
         let response = client
             .send_transaction(request)
             .await
             .map_err(|e| Error::Rpc(format!("Failed to send transaction: {}", e)))?;
         let res = response.into_inner();
-        // Return a status string; lightwalletd typically provides error info fields.
-        Ok(format!("code:{} message:{}", res.error_code, res.error_message))
+        if res.error_code == 0 {
+            if let Some(backup) = &mut self.auto_backup {
+                if let Err(e) = backup.transaction_sent() {
+                    tracing::warn!("Automatic backup failed: {}", e);
+                }
+            }
+        }
+        Ok((res.error_code, res.error_message))
+    }
+
+    /// Get the transactions currently in the server's mempool
+    ///
+    /// lightwalletd's mempool stream sends the current mempool and then
+    /// stays open until the next block; reading stops once no transaction
+    /// arrives for `idle`.
+    pub async fn get_mempool_transactions(&mut self, idle: Duration) -> Result<Vec<Vec<u8>>> {
+        let mut client = streamer(self.channel()?);
+        let mut stream = client
+            .get_mempool_stream(Empty {})
+            .await
+            .map_err(|e| Error::Rpc(format!("Failed to get mempool: {}", e)))?
+            .into_inner();
+
+        let mut transactions = Vec::new();
+        while let Ok(next) = tokio::time::timeout(idle, stream.message()).await {
+            match next.map_err(|e| Error::Rpc(format!("Failed to receive transaction: {}", e)))? {
+                Some(tx) => {
+                    self.record_download(tx.encoded_len());
+                    transactions.push(tx.data);
+                }
+                None => break,
+            }
+        }
+        Ok(transactions)
     }
 
     /// Get transaction details by transaction ID
@@ -451,11 +997,8 @@ impl LightClient {
     /// This is a placeholder implementation. The actual implementation requires
     /// using the CompactTxStreamerClient from zcash_client_backend::proto.
     pub async fn get_transaction(&mut self, txid_hex: &str) -> Result<Option<Vec<u8>>> {
-        use tonic::transport::Endpoint;
-        let channel = Endpoint::from_shared(self.endpoint.clone())
-            .map_err(|e| Error::InvalidParameter(format!("Invalid endpoint URL: {}", e)))?
-            .connect_lazy();
-        let mut client = CompactTxStreamerClient::new(channel);
+        let channel = self.channel()?;
+        let mut client = streamer(channel);
         let txid = hex::decode(txid_hex)
             .map_err(|e| Error::InvalidParameter(format!("Invalid txid hex: {}", e)))?;
         let mut filter = TxFilter::default();
@@ -467,6 +1010,7 @@ impl LightClient {
             .await
             .map_err(|e| Error::Rpc(format!("Failed to get transaction: {}", e)))?
             .into_inner();
+        self.record_download(response.encoded_len());
         if response.data.is_empty() {
             Ok(None)
         } else {
@@ -474,26 +1018,347 @@ impl LightClient {
         }
     }
 
-    /// Get the tip (latest block) information
+    /// Build an account birthday from the server's tree state
     ///
-    /// Returns information about the latest block known to the lightwalletd server.
+    /// Use this with [`Wallet::create_account`] so a new account is only
+    /// scanned from the height it could first have received funds.
     ///
-    /// # Note
-    /// This is a placeholder implementation. The actual implementation requires
-    /// using the CompactTxStreamerClient from zcash_client_backend::proto.
+    /// # Arguments
+    /// * `birthday_height` - Height of the first block that may contain funds
+    pub async fn account_birthday(
+        &self,
+        birthday_height: u64,
+    ) -> Result<zcash_client_backend::data_api::AccountBirthday> {
+        use zcash_client_backend::data_api::AccountBirthday;
+
+        // The birthday is the block after the tree state it is built from
+        let height = birthday_height.saturating_sub(1);
+        let tree_state = match self.replay_tree_states.get(&height) {
+            Some(tree_state) => tree_state.clone(),
+            None => {
+                let mut client = streamer(self.channel()?);
+                let request = tonic::Request::new(BlockId {
+                    height,
+                    hash: vec![],
+                });
+                let tree_state = client
+                    .get_tree_state(request)
+                    .await
+                    .map_err(|e| Error::Rpc(format!("Failed to get tree state: {}", e)))?
+                    .into_inner();
+                self.record_download(tree_state.encoded_len());
+                tree_state
+            }
+        };
+        if let Some(recorder) = &self.replay {
+            recorder.record_tree_state(&tree_state)?;
+        }
+        AccountBirthday::from_treestate(tree_state, None)
+            .map_err(|_| Error::Rpc("Invalid tree state returned by server".to_string()))
+    }
+
+    /// Import a unified full viewing key as a view-only account
+    ///
+    /// The account is stored in this client's wallet database, so subsequent
+    /// calls to [`sync`](Self::sync) track its notes alongside the wallet's own.
+    /// Importing a key that is already present is a no-op.
+    ///
+    /// # Arguments
+    /// * `name` - Account name stored in the wallet database
+    /// * `ufvk` - Viewing key to import
+    /// * `birthday_height` - Height of the first block that may contain funds for the key
+    pub async fn import_viewing_key(
+        &mut self,
+        name: &str,
+        ufvk: &UnifiedFullViewingKey,
+        birthday_height: u64,
+    ) -> Result<()> {
+        use zcash_client_backend::data_api::AccountPurpose;
+
+        {
+            let wallet_db = self.wallet_db.read()?;
+            let existing = wallet_db
+                .get_account_for_ufvk(ufvk)
+                .map_err(|e| Error::Database(format!("Failed to get account for UFVK: {}", e)))?;
+            if existing.is_some() {
+                return Ok(());
+            }
+        }
+
+        let birthday = self.account_birthday(birthday_height).await?;
+
+        let mut wallet_db = self.wallet_db.write()?;
+        wallet_db
+            .import_account_ufvk(name, ufvk, &birthday, AccountPurpose::ViewOnly, None)
+            .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?;
+        Ok(())
+    }
+
+    /// Recreate the accounts recorded in a wallet backup at their birthdays
+    ///
+    /// Spending accounts are derived from the backup seed at their ZIP-32
+    /// index; view-only accounts are imported from their viewing key.
+    /// Accounts already present in the wallet database are skipped, so this
+    /// can be called again after a failure.
+    ///
+    /// # Returns
+    /// The number of accounts created
+    pub async fn restore_accounts(&mut self, backup: &WalletBackup) -> Result<usize> {
+        let seed = SecretVec::new(
+            hex::decode(&backup.seed)
+                .map_err(|e| Error::InvalidParameter(format!("Invalid seed in backup: {}", e)))?,
+        );
+        let mut restored = 0;
+        for account in &backup.accounts {
+            let name = account.name.as_deref().unwrap_or("");
+            let derived = account
+                .index
+                .map(|index| {
+                    AccountId::try_from(index).map_err(|_| {
+                        Error::InvalidParameter(format!("Invalid ZIP-32 account index {}", index))
+                    })
+                })
+                .transpose()?;
+            let ufvk = match (derived, &account.ufvk) {
+                (Some(account_index), _) => {
+                    UnifiedSpendingKey::from_seed(
+                        &self.consensus_network,
+                        seed.expose_secret(),
+                        account_index,
+                    )
+                    .map_err(|e| {
+                        Error::KeyDerivation(format!("Failed to derive account key: {}", e))
+                    })?
+                    .to_unified_full_viewing_key()
+                }
+                (None, Some(encoded)) => {
+                    UnifiedFullViewingKey::decode(&self.consensus_network, encoded).map_err(|e| {
+                        Error::InvalidParameter(format!("Invalid UFVK in backup: {}", e))
+                    })?
+                }
+                (None, None) => continue,
+            };
+
+            let exists = self
+                .wallet_db
+                .read()?
+                .get_account_for_ufvk(&ufvk)
+                .map_err(|e| Error::Database(format!("Failed to get account for UFVK: {}", e)))?
+                .is_some();
+            if exists {
+                continue;
+            }
+
+            match derived {
+                Some(account_index) => {
+                    let birthday = self.account_birthday(account.birthday_height).await?;
+                    self.wallet_db
+                        .write()?
+                        .import_account_hd(
+                            name,
+                            &seed,
+                            account_index,
+                            &birthday,
+                            None,
+                        )
+                        .map_err(|e| Error::Database(format!("Failed to restore account: {}", e)))?;
+                }
+                None => self.import_viewing_key(name, &ufvk, account.birthday_height).await?,
+            }
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    /// Get the scanned balance of an account by its viewing key
+    ///
+    /// # Returns
+    /// The account balance, or `None` if the key has not been imported or
+    /// the wallet has not been synced yet
+    pub async fn account_balance(&self, ufvk: &UnifiedFullViewingKey) -> Result<Option<Balance>> {
+        use zcash_client_backend::data_api::wallet::ConfirmationsPolicy;
+        use zcash_client_backend::data_api::Account;
+
+        let wallet_db = self.wallet_db.read()?;
+        let Some(account) = wallet_db
+            .get_account_for_ufvk(ufvk)
+            .map_err(|e| Error::Database(format!("Failed to get account for UFVK: {}", e)))?
+        else {
+            return Ok(None);
+        };
+        let Some(summary) = wallet_db
+            .get_wallet_summary(ConfirmationsPolicy::default())
+            .map_err(|e| Error::Database(format!("Failed to read wallet summary: {}", e)))?
+        else {
+            return Ok(None);
+        };
+
+        Ok(summary.account_balances().get(&account.id()).map(|b| {
+            let transparent = u64::from(b.unshielded_balance().total());
+            let sapling = u64::from(b.sapling_balance().total());
+            let orchard = u64::from(b.orchard_balance().total());
+            Balance {
+                transparent,
+                sapling,
+                orchard,
+                total: transparent + sapling + orchard,
+            }
+        }))
+    }
+
+    /// Get the combined balance of transparent addresses from the server
+    ///
+    /// # Returns
+    /// Balance in zatoshis
+    pub async fn get_transparent_balance(&mut self, addresses: &[String]) -> Result<u64> {
+        self.require_taddr_support().await?;
+        let channel = self.channel()?;
+        let mut client = streamer(channel);
+        let request = tonic::Request::new(AddressList {
+            addresses: addresses.to_vec(),
+        });
+        let balance = client
+            .get_taddress_balance(request)
+            .await
+            .map_err(|e| Error::Rpc(format!("Failed to get address balance: {}", e)))?
+            .into_inner();
+        u64::try_from(balance.value_zat)
+            .map_err(|_| Error::Rpc(format!("Invalid balance: {}", balance.value_zat)))
+    }
+
+    /// Find transparent addresses with transactions by gap-limit discovery
+    ///
+    /// Walks the external and internal chains in index order, asking the
+    /// server whether each address has transactions, until `gap_limit`
+    /// consecutive addresses past the last used and the last handed out one
+    /// have none. Each address is only checked for blocks after the height it
+    /// was last checked at, and addresses already known to be used are not
+    /// checked again. Called at the end of [`sync`](Self::sync).
+    ///
+    /// # Arguments
+    /// * `birthday_height` - Height to check never-checked addresses from
+    /// * `end_height` - Height to check addresses up to
+    /// * `gap_limit` - Consecutive unused addresses after which a chain ends
+    ///
+    /// # Returns
+    /// The addresses found to be used by this call
+    pub async fn discover_transparent_addresses(
+        &mut self,
+        birthday_height: u64,
+        end_height: u64,
+        gap_limit: u32,
+    ) -> Result<Vec<TransparentAddressInfo>> {
+        self.require_taddr_support().await?;
+        let addresses = self.transparent.as_ref().ok_or_else(|| {
+            Error::Address("No transparent component in unified key".to_string())
+        })?;
+        let mut found = Vec::new();
+        for chain in TransparentChain::ALL {
+            let last_issued = addresses.highest_issued(chain)?;
+            let mut index = 0u32;
+            let mut unused_run = 0u32;
+            while unused_run < gap_limit || last_issued.is_some_and(|last| index <= last) {
+                let known = addresses.get(chain, index)?;
+                let used = match &known {
+                    Some(info) if info.used => true,
+                    _ => {
+                        let from_height = known
+                            .as_ref()
+                            .and_then(|info| info.checked_height)
+                            .map_or(birthday_height, |height| height + 1);
+                        let address = addresses.derive(chain, index)?;
+                        let used = from_height <= end_height
+                            && self
+                                .address_has_transactions(&address, from_height, end_height)
+                                .await?;
+                        addresses.record_check(chain, index, end_height, used)?;
+                        if used {
+                            tracing::info!("Found transparent activity at {:?}/{}", chain, index);
+                            found.extend(addresses.get(chain, index)?);
+                        }
+                        used
+                    }
+                };
+                unused_run = if used { 0 } else { unused_run + 1 };
+                index = match index.checked_add(1) {
+                    Some(next) if next < 1 << 31 => next,
+                    _ => break,
+                };
+            }
+        }
+        Ok(found)
+    }
+
+    /// Whether the server knows of any transaction involving a transparent
+    /// address in an inclusive height range
+    async fn address_has_transactions(
+        &self,
+        address: &str,
+        start_height: u64,
+        end_height: u64,
+    ) -> Result<bool> {
+        let mut client = streamer(self.channel()?);
+        let request = tonic::Request::new(TransparentAddressBlockFilter {
+            address: address.to_string(),
+            range: Some(BlockRange {
+                start: Some(BlockId {
+                    height: start_height,
+                    hash: vec![],
+                }),
+                end: Some(BlockId {
+                    height: end_height,
+                    hash: vec![],
+                }),
+            }),
+        });
+        let mut stream = client
+            .get_taddress_txids(request)
+            .await
+            .map_err(|e| Error::Rpc(format!("Failed to get address transactions: {}", e)))?
+            .into_inner();
+        let first = stream
+            .message()
+            .await
+            .map_err(|e| Error::Rpc(format!("Failed to receive transaction: {}", e)))?;
+        if let Some(tx) = &first {
+            self.record_download(tx.encoded_len());
+        }
+        Ok(first.is_some())
+    }
+
+    /// Get the combined balance of every discovered transparent address
+    ///
+    /// # Returns
+    /// Balance in zatoshis
+    pub async fn get_discovered_transparent_balance(&mut self) -> Result<u64> {
+        let used = match &self.transparent {
+            Some(addresses) => addresses.used_addresses()?,
+            None => Vec::new(),
+        };
+        if used.is_empty() {
+            return Ok(0);
+        }
+        self.get_transparent_balance(&used).await
+    }
+
+    /// Get the height and hash of the latest block known to the server
+    ///
+    /// # Returns
+    /// The tip height and block hash, as sent by the server
     pub async fn get_tip(&mut self) -> Result<(u64, Vec<u8>)> {
-        // TODO: Implement using CompactTxStreamerClient::get_latest_block
-        // Example:
-        // use zcash_client_backend::proto::service::compact_tx_streamer_client::CompactTxStreamerClient;
-        // let mut client = CompactTxStreamerClient::new(self.channel.clone());
-        // let request = tonic::Request::new(());
-        // let response = client.get_latest_block(request).await?;
-        // let block = response.into_inner();
-        // Ok((block.height, block.hash))
-        
-        Err(Error::Rpc(
-            "get_tip not yet implemented. See zcash_client_backend::proto for API details.".to_string()
-        ))
+        let block = fetch_latest_block(self.channel()?).await?;
+        self.record_download(block.encoded_len());
+        Ok((block.height, block.hash))
+    }
+
+    async fn require_taddr_support(&mut self) -> Result<()> {
+        if self.server_info().await?.taddr_support {
+            return Ok(());
+        }
+        Err(Error::Rpc(format!(
+            "Server {} does not support transparent address queries",
+            self.endpoint
+        )))
     }
 
     /// Get the endpoint URL
@@ -502,6 +1367,333 @@ impl LightClient {
     }
 }
 
+/// gRPC channel to lightwalletd that adds the configured headers to every
+/// request
+pub type LightwalletdChannel = InterceptedService<tonic::transport::Channel, RequestHeaders>;
+
+/// Adds the extra headers as gRPC metadata; the User-Agent is set on the
+/// [`Endpoint`](tonic::transport::Endpoint) by [`grpc_endpoint`]
+impl tonic::service::Interceptor for RequestHeaders {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> std::result::Result<tonic::Request<()>, tonic::Status> {
+        use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue};
+
+        for (name, value) in self.extra() {
+            let key = AsciiMetadataKey::from_bytes(name.as_bytes())
+                .map_err(|_| tonic::Status::internal(format!("Invalid header name {}", name)))?;
+            let value: AsciiMetadataValue = value
+                .parse()
+                .map_err(|_| tonic::Status::internal(format!("Invalid value for {}", name)))?;
+            request.metadata_mut().insert(key, value);
+        }
+        Ok(request)
+    }
+}
+
+/// Parse a lightwalletd endpoint URL and set the User-Agent on it
+pub(crate) fn grpc_endpoint(
+    endpoint: &str,
+    headers: &RequestHeaders,
+) -> Result<tonic::transport::Endpoint> {
+    tonic::transport::Endpoint::from_shared(endpoint.to_string())
+        .map_err(|e| Error::InvalidParameter(format!("Invalid endpoint URL: {}", e)))?
+        .user_agent(headers.user_agent())
+        .map_err(|e| Error::InvalidParameter(format!("Invalid User-Agent: {}", e)))
+}
+
+/// Create a lazily connected gRPC channel to a lightwalletd endpoint
+///
+/// Channels are cheap to clone and multiplex requests over one connection.
+pub(crate) fn lazy_channel(
+    endpoint: &str,
+    headers: &RequestHeaders,
+) -> Result<LightwalletdChannel> {
+    let channel = grpc_endpoint(endpoint, headers)?.connect_lazy();
+    Ok(InterceptedService::new(channel, headers.clone()))
+}
+
+/// Create a lazily connected plain-HTTP channel whose socket traffic is
+/// counted on `meter` as wire bytes
+pub(crate) fn metered_channel(
+    endpoint: &str,
+    meter: BandwidthMeter,
+    headers: &RequestHeaders,
+) -> Result<LightwalletdChannel> {
+    use hyper_util::rt::TokioIo;
+    use tokio::net::TcpStream;
+    use tonic::transport::Uri;
+
+    let endpoint = grpc_endpoint(endpoint, headers)?;
+    let channel = endpoint.connect_with_connector_lazy(tower::service_fn(move |uri: Uri| {
+        let meter = meter.clone();
+        async move {
+            let host = uri.host().unwrap_or_default().to_string();
+            let tcp = TcpStream::connect((host, uri.port_u16().unwrap_or(80))).await?;
+            Ok::<_, std::io::Error>(TokioIo::new(MeteredIo::new(tcp, Some(meter))))
+        }
+    }));
+    Ok(InterceptedService::new(channel, headers.clone()))
+}
+
+/// Create a lazily connected plain-HTTP channel whose responses are delayed,
+/// dropped or corrupted by `faults`
+///
+/// Socket traffic is counted on `meter`, if any, as by [`metered_channel`].
+pub(crate) fn faulty_channel(
+    endpoint: &str,
+    faults: FaultInjector,
+    meter: Option<BandwidthMeter>,
+    headers: &RequestHeaders,
+) -> Result<LightwalletdChannel> {
+    use hyper_util::rt::TokioIo;
+    use tokio::net::TcpStream;
+    use tonic::transport::Uri;
+
+    if endpoint.starts_with("https") {
+        return Err(Error::InvalidParameter(format!(
+            "Fault injection requires a plain-HTTP endpoint, got {}",
+            endpoint
+        )));
+    }
+    let endpoint = grpc_endpoint(endpoint, headers)?;
+    let channel = endpoint.connect_with_connector_lazy(tower::service_fn(move |uri: Uri| {
+        let faults = faults.clone();
+        let meter = meter.clone();
+        async move {
+            let host = uri.host().unwrap_or_default().to_string();
+            let tcp = TcpStream::connect((host, uri.port_u16().unwrap_or(80))).await?;
+            let io = FaultyIo::new(MeteredIo::new(tcp, meter), faults);
+            Ok::<_, std::io::Error>(TokioIo::new(io))
+        }
+    }));
+    Ok(InterceptedService::new(channel, headers.clone()))
+}
+
+/// Check that a server follows the chain of `network`
+///
+/// # Arguments
+/// * `chain_name` - Chain reported by lightwalletd or zcashd: `main`, `test`
+///   or `regtest`
+pub(crate) fn check_chain(endpoint: &str, chain_name: &str, network: Network) -> Result<()> {
+    let expected = match network {
+        Network::Mainnet => "main",
+        Network::Testnet => "test",
+        Network::Regtest => "regtest",
+    };
+    if chain_name != expected {
+        return Err(Error::Rpc(format!(
+            "Server {} follows chain {}, but the wallet is on {:?}",
+            endpoint, chain_name, network
+        )));
+    }
+    Ok(())
+}
+
+/// Create a `CompactTxStreamer` client that accepts compressed responses
+///
+/// The client advertises zstd and gzip in `grpc-accept-encoding`; lightwalletd
+/// compresses responses with one of them if it is configured to, and sends
+/// them uncompressed otherwise.
+fn streamer(channel: LightwalletdChannel) -> CompactTxStreamerClient<LightwalletdChannel> {
+    CompactTxStreamerClient::new(channel)
+        .accept_compressed(CompressionEncoding::Zstd)
+        .accept_compressed(CompressionEncoding::Gzip)
+}
+
+/// Get the latest block height over an existing channel
+pub(crate) async fn fetch_latest_height(channel: LightwalletdChannel) -> Result<u64> {
+    Ok(fetch_latest_block(channel).await?.height)
+}
+
+/// Fetch the ID of the chain tip over an existing channel
+async fn fetch_latest_block(channel: LightwalletdChannel) -> Result<BlockId> {
+    let mut client = streamer(channel);
+    let request = tonic::Request::new(ChainSpec {});
+
+    let response = client
+        .get_latest_block(request)
+        .await
+        .map_err(|e| Error::Rpc(format!("Failed to get latest block: {}", e)))?;
+
+    Ok(response.into_inner())
+}
+
+/// Fetch compact blocks for an inclusive height range over an existing channel
+pub(crate) async fn fetch_compact_blocks(
+    channel: LightwalletdChannel,
+    start_height: u64,
+    end_height: u64,
+) -> Result<Vec<CompactBlock>> {
+    let mut client = streamer(channel);
+    let mut blocks = Vec::new();
+
+    let request = tonic::Request::new(BlockRange {
+        start: Some(BlockId {
+            height: start_height,
+            hash: vec![],
+        }),
+        end: Some(BlockId {
+            height: end_height,
+            hash: vec![],
+        }),
+    });
+
+    let mut stream = client
+        .get_block_range(request)
+        .await
+        .map_err(|e| Error::Rpc(format!("Failed to get block range: {}", e)))?
+        .into_inner();
+
+    while let Some(compact_block) = stream
+        .message()
+        .await
+        .map_err(|e| Error::Rpc(format!("Failed to receive block: {}", e)))?
+    {
+        blocks.push(compact_block);
+    }
+
+    Ok(blocks)
+}
+
+/// Scan contiguous blocks with the keys of every account that is not
+/// archived and store the results
+///
+/// Does what `chain::scan_cached_blocks` does, except that it always uses
+/// the keys of every account in the wallet database. Notes received by an
+/// archived account while it is archived are not found; re-enabling it
+/// queues that range for a rescan (see [`LightClient::sync`]).
+///
+/// # Returns
+/// Payments received by the scanned accounts (see [`received_payments`])
+fn scan_active_accounts<P, DbT>(
+    params: &P,
+    wallet_db: &mut DbT,
+    from_state: &ChainState,
+    blocks: Vec<CompactBlock>,
+    archived: &HashSet<String>,
+) -> Result<Vec<ReceivedPayment>>
+where
+    P: Parameters + Send + 'static,
+    DbT: WalletWrite<AccountId = AccountUuid>,
+    DbT::Error: std::fmt::Display,
+{
+    let scan_error = |e: DbT::Error| Error::Database(format!("Failed to scan blocks: {}", e));
+    let ufvks: HashMap<_, _> = wallet_db
+        .get_unified_full_viewing_keys()
+        .map_err(scan_error)?
+        .into_iter()
+        .filter(|(uuid, _)| !archived.contains(&uuid.expose_uuid().to_string()))
+        .collect();
+    let scanning_keys = ScanningKeys::from_account_ufvks(ufvks.clone());
+    let mut nullifiers = Nullifiers::new(
+        wallet_db
+            .get_sapling_nullifiers(NullifierQuery::Unspent)
+            .map_err(scan_error)?,
+        wallet_db
+            .get_orchard_nullifiers(NullifierQuery::Unspent)
+            .map_err(scan_error)?,
+    );
+    let mut prior = match blocks.first() {
+        Some(block) => wallet_db
+            .block_metadata(block.height() - 1)
+            .map_err(scan_error)?,
+        None => None,
+    };
+
+    let mut scanned = Vec::with_capacity(blocks.len());
+    let mut payments = Vec::new();
+    for block in blocks {
+        let block = scan_block(params, block, &scanning_keys, &nullifiers, prior.as_ref())
+            .map_err(|e| Error::Wallet(format!("Failed to scan block: {}", e)))?;
+        // Notes received in this batch can be spent later in it
+        let transactions = block.transactions();
+        let spent_sapling: HashSet<_> = transactions
+            .iter()
+            .flat_map(|tx| tx.sapling_spends().iter().map(|spend| *spend.nf()))
+            .collect();
+        let spent_orchard: HashSet<_> = transactions
+            .iter()
+            .flat_map(|tx| tx.orchard_spends().iter().map(|spend| *spend.nf()))
+            .collect();
+        nullifiers.retain_sapling(|(_, nf)| !spent_sapling.contains(nf));
+        nullifiers.retain_orchard(|(_, nf)| !spent_orchard.contains(nf));
+        nullifiers.extend_sapling(
+            transactions
+                .iter()
+                .flat_map(WalletTx::sapling_outputs)
+                .flat_map(|output| output.nf().map(|nf| (*output.account_id(), *nf))),
+        );
+        nullifiers.extend_orchard(
+            transactions
+                .iter()
+                .flat_map(WalletTx::orchard_outputs)
+                .flat_map(|output| output.nf().map(|nf| (*output.account_id(), *nf))),
+        );
+        prior = Some(block.to_block_metadata());
+        payments.extend(received_payments(params, &ufvks, &block));
+        scanned.push(block);
+    }
+    wallet_db
+        .put_blocks(from_state, scanned)
+        .map_err(scan_error)?;
+    Ok(payments)
+}
+
+/// Payments to the wallet's addresses in a scanned block
+///
+/// Change outputs and outputs to addresses the wallet did not hand out are
+/// skipped. Compact blocks carry no memos, so `memo` is always `None`.
+fn received_payments<P: Parameters>(
+    params: &P,
+    ufvks: &HashMap<AccountUuid, UnifiedFullViewingKey>,
+    block: &ScannedBlock<AccountUuid>,
+) -> Vec<ReceivedPayment> {
+    let height = Some(u64::from(u32::from(block.height())));
+    let mut payments = Vec::new();
+    for tx in block.transactions() {
+        let sapling = tx
+            .sapling_outputs()
+            .iter()
+            .filter(|output| !output.is_change())
+            .filter_map(|output| {
+                let ufvk = ufvks.get(output.account_id())?;
+                let (index, _) = ufvk
+                    .sapling()?
+                    .decrypt_diversifier(&output.note().recipient())?;
+                Some((output.index(), output.note().value().inner(), ufvk, index))
+            });
+        let orchard = tx
+            .orchard_outputs()
+            .iter()
+            .filter(|output| !output.is_change())
+            .filter_map(|output| {
+                let ufvk = ufvks.get(output.account_id())?;
+                let fvk = ufvk.orchard()?;
+                let recipient = output.note().recipient();
+                let index = fvk
+                    .to_ivk(fvk.scope_for_address(&recipient)?)
+                    .diversifier_index(&recipient)?;
+                Some((output.index(), output.note().value().inner(), ufvk, index))
+            });
+        for (output_index, amount, ufvk, index) in sapling.chain(orchard) {
+            let Some(address) = address_at_index(params, ufvk, index) else {
+                continue;
+            };
+            payments.push(ReceivedPayment {
+                txid: tx.txid().to_string(),
+                output_index: output_index as u32,
+                address,
+                amount,
+                memo: None,
+                height,
+            });
+        }
+    }
+    payments
+}
+
 /// Helper function to get default lightwalletd endpoints
 ///
 /// Returns common public lightwalletd endpoints for mainnet and testnet.
@@ -539,4 +1731,12 @@ mod tests {
         // This will fail if lightwalletd is not running
         let _client = LightClient::connect(endpoint, wallet).await;
     }
+
+    #[test]
+    fn test_check_chain() {
+        let endpoint = "https://testnet.lightwalletd.com:9067";
+        assert!(check_chain(endpoint, "test", Network::Testnet).is_ok());
+        let err = check_chain(endpoint, "test", Network::Mainnet).unwrap_err();
+        assert!(err.to_string().contains("follows chain test"));
+    }
 }
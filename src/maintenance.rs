@@ -0,0 +1,779 @@
+//! Wallet database size management
+//!
+//! A long-lived wallet database only grows: a metadata row is kept for every
+//! scanned block, and SQLite does not return pages freed by deletions to the
+//! file system. The wallet exposes three maintenance operations built on the
+//! helpers here:
+//! - [`Wallet::database_size`](crate::wallet::Wallet::database_size) reports
+//!   the file size, free pages and on-disk size of each table
+//! - [`Wallet::prune_block_metadata`](crate::wallet::Wallet::prune_block_metadata)
+//!   deletes metadata of old scanned blocks that no transaction refers to
+//! - [`Wallet::vacuum_database`](crate::wallet::Wallet::vacuum_database)
+//!   rebuilds the file so freed pages are released
+//!
+//! [`Wallet::verify_integrity`](crate::wallet::Wallet::verify_integrity)
+//! checks a database suspected to be corrupted, e.g. after a crash or a full
+//! disk, and reports what can be repaired and how.
+//! [`Wallet::check_note_witnesses`](crate::wallet::Wallet::check_note_witnesses)
+//! finds unspent notes that cannot be witnessed against the current tree
+//! state, and so cannot be spent although they are counted in the balance.
+
+use crate::db_encryption::open_connection;
+use crate::error::Result;
+use crate::store::db_error;
+use crate::types::{NoteId, Pool};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Blocks below the scanned tip whose metadata is always kept, so the wallet
+/// can still be rewound over a chain reorganization
+pub const MIN_RETAINED_BLOCKS: u64 = 100;
+
+/// On-disk size of one table, including its indexes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableSize {
+    pub name: String,
+    /// Bytes used by the table's and its indexes' pages
+    pub bytes: u64,
+    pub rows: u64,
+}
+
+/// On-disk size of a wallet database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseSize {
+    /// Size of the database file in bytes
+    pub file_bytes: u64,
+    /// Bytes in free pages that a vacuum would release
+    pub free_bytes: u64,
+    /// Tables ordered by size, largest first
+    pub tables: Vec<TableSize>,
+}
+
+/// File sizes before and after a vacuum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VacuumReport {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl VacuumReport {
+    /// Bytes released to the file system
+    pub fn bytes_released(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Area of the wallet database an integrity issue was found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityCheck {
+    /// SQLite's own page, record and index consistency
+    Database,
+    /// References between tables
+    ForeignKeys,
+    /// Note commitment tree positions, shards and checkpoints
+    CommitmentTree,
+    /// Nullifiers and the spends recorded for notes
+    Nullifiers,
+}
+
+/// How an integrity issue can be repaired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repair {
+    /// Rebuild the database's indexes with `REINDEX`
+    Reindex,
+    /// Rewind the wallet below `from_height` and sync again
+    Rescan { from_height: u64 },
+}
+
+/// An inconsistency found in a wallet database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    pub check: IntegrityCheck,
+    pub description: String,
+    /// `None` if the issue cannot be repaired in place; restore the wallet
+    /// from a backup or its seed instead
+    pub repair: Option<Repair>,
+}
+
+/// Result of [`Wallet::verify_integrity`](crate::wallet::Wallet::verify_integrity)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether no issues were found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Whether every issue found can be repaired in place
+    pub fn is_repairable(&self) -> bool {
+        self.issues.iter().all(|issue| issue.repair.is_some())
+    }
+
+    /// Lowest height a rescan has to start from to repair the issues found
+    pub fn rescan_from(&self) -> Option<u64> {
+        self.issues
+            .iter()
+            .filter_map(|issue| match issue.repair {
+                Some(Repair::Rescan { from_height }) => Some(from_height),
+                _ => None,
+            })
+            .min()
+    }
+
+    fn push(&mut self, check: IntegrityCheck, description: String, repair: Option<Repair>) {
+        self.issues.push(IntegrityIssue {
+            check,
+            description,
+            repair,
+        });
+    }
+}
+
+const DB_CONTEXT: &str = "Database maintenance error";
+
+/// Measure the database at `path`
+pub(crate) fn database_size(path: &Path) -> Result<DatabaseSize> {
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    let page_size: i64 = conn
+        .query_row("PRAGMA page_size", [], |row| row.get(0))
+        .map_err(db_error(DB_CONTEXT))?;
+    let free_pages: i64 = conn
+        .query_row("PRAGMA freelist_count", [], |row| row.get(0))
+        .map_err(db_error(DB_CONTEXT))?;
+
+    // Index pages are attributed to the table they index
+    let mut stmt = conn
+        .prepare(
+            "SELECT m.tbl_name, SUM(s.pgsize)
+             FROM dbstat s JOIN sqlite_master m ON m.name = s.name
+             WHERE m.tbl_name IN (SELECT name FROM sqlite_master WHERE type = 'table')
+             GROUP BY m.tbl_name",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+    let sizes = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(db_error(DB_CONTEXT))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_error(DB_CONTEXT))?;
+
+    let mut tables = Vec::with_capacity(sizes.len());
+    for (name, bytes) in sizes {
+        let rows: i64 = conn
+            .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", name), [], |row| {
+                row.get(0)
+            })
+            .map_err(db_error(DB_CONTEXT))?;
+        tables.push(TableSize {
+            name,
+            bytes: bytes as u64,
+            rows: rows as u64,
+        });
+    }
+    tables.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(DatabaseSize {
+        file_bytes: std::fs::metadata(path)?.len(),
+        free_bytes: (free_pages * page_size) as u64,
+        tables,
+    })
+}
+
+/// Delete metadata of blocks below `below_height` that no transaction was
+/// mined in
+///
+/// # Returns
+/// The number of block rows deleted
+pub(crate) fn prune_blocks(path: &Path, below_height: u64) -> Result<usize> {
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    conn.execute(
+        "DELETE FROM blocks WHERE height < ?1
+         AND height NOT IN (
+             SELECT mined_height FROM v_transactions WHERE mined_height IS NOT NULL
+         )",
+        [below_height as i64],
+    )
+    .map_err(db_error(DB_CONTEXT))
+}
+
+/// Rebuild the database at `path` to release free pages
+pub(crate) fn vacuum(path: &Path) -> Result<VacuumReport> {
+    let bytes_before = std::fs::metadata(path)?.len();
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+        .map_err(db_error(DB_CONTEXT))?;
+    drop(conn);
+    Ok(VacuumReport {
+        bytes_before,
+        bytes_after: std::fs::metadata(path)?.len(),
+    })
+}
+
+/// Why an unspent note cannot be witnessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WitnessProblem {
+    /// The note's position in the commitment tree is unknown
+    NoTreePosition,
+    /// The commitment tree shard holding the note is missing
+    MissingShard,
+    /// Blocks covering part of the note's tree shard have not been scanned
+    UnscannedShard,
+    /// No tree checkpoint at or above the note's block to anchor a witness
+    NoCheckpoint,
+    /// The note has no nullifier, so spends of it cannot be detected
+    NoNullifier,
+}
+
+/// An unspent note that needs part of the chain scanned again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteWitnessIssue {
+    pub note: NoteId,
+    /// Value in zatoshis
+    pub value: u64,
+    /// Height of the block the note was mined in
+    pub mined_height: u64,
+    pub problem: WitnessProblem,
+    /// First block of the rescan window
+    pub rescan_from: u64,
+    /// End of the rescan window (exclusive); `None` to rescan up to the tip
+    pub rescan_to: Option<u64>,
+}
+
+/// Result of [`Wallet::check_note_witnesses`](crate::wallet::Wallet::check_note_witnesses)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WitnessReport {
+    /// Height of the last scanned block
+    pub tip_height: Option<u64>,
+    /// Unspent Sapling and Orchard notes checked
+    pub notes_checked: u64,
+    pub issues: Vec<NoteWitnessIssue>,
+}
+
+impl WitnessReport {
+    /// Whether every unspent note can be witnessed
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Total value in zatoshis of the notes that cannot be spent until
+    /// rescanned
+    pub fn value_at_risk(&self) -> u64 {
+        self.issues.iter().map(|issue| issue.value).sum()
+    }
+
+    /// Lowest height a rescan has to start from to fix every issue found
+    pub fn rescan_from(&self) -> Option<u64> {
+        self.issues.iter().map(|issue| issue.rescan_from).min()
+    }
+}
+
+/// Run a query returning a count of affected rows and the lowest block
+/// height involved
+fn probe(conn: &Connection, sql: &str) -> Result<(u64, Option<u64>)> {
+    conn.query_row(sql, [], |row| {
+        Ok((
+            row.get::<_, i64>(0)? as u64,
+            row.get::<_, Option<i64>>(1)?.map(|height| height as u64),
+        ))
+    })
+    .map_err(db_error(DB_CONTEXT))
+}
+
+/// Check the wallet database at `path` for inconsistencies
+///
+/// Only reads the database; nothing is repaired.
+pub(crate) fn verify_integrity(path: &Path) -> Result<IntegrityReport> {
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    let mut report = IntegrityReport::default();
+
+    let mut stmt = conn
+        .prepare("PRAGMA integrity_check(100)")
+        .map_err(db_error(DB_CONTEXT))?;
+    let problems = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(db_error(DB_CONTEXT))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_error(DB_CONTEXT))?;
+    for problem in problems.into_iter().filter(|problem| problem != "ok") {
+        // Damaged indexes can be rebuilt from their tables
+        let repair = problem.contains(" index ").then_some(Repair::Reindex);
+        report.push(IntegrityCheck::Database, problem, repair);
+    }
+
+    let mut stmt = conn
+        .prepare("PRAGMA foreign_key_check")
+        .map_err(db_error(DB_CONTEXT))?;
+    let violations = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(2)?))
+        })
+        .map_err(db_error(DB_CONTEXT))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_error(DB_CONTEXT))?;
+    let mut counts: Vec<((String, String), u64)> = Vec::new();
+    for violation in violations {
+        match counts.iter_mut().find(|(key, _)| *key == violation) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((violation, 1)),
+        }
+    }
+    for ((table, parent), count) in counts {
+        report.push(
+            IntegrityCheck::ForeignKeys,
+            format!(
+                "{} rows of {} refer to missing {} rows",
+                count, table, parent
+            ),
+            None,
+        );
+    }
+
+    let tip: Option<i64> = conn
+        .query_row("SELECT MAX(height) FROM blocks", [], |row| row.get(0))
+        .map_err(db_error(DB_CONTEXT))?;
+    for (pool, name) in [("sapling", "Sapling"), ("orchard", "Orchard")] {
+        let notes = format!("{}_received_notes", pool);
+        let spends = format!("{}_received_note_spends", pool);
+        let note_id = format!("{}_received_note_id", pool);
+        let rescan = |height: Option<u64>| height.map(|from_height| Repair::Rescan { from_height });
+
+        // Notes mined in a scanned block always have a tree position
+        let (count, height) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(*), MIN(t.mined_height)
+                 FROM {notes} n JOIN transactions t ON t.id_tx = n.tx
+                 WHERE n.commitment_tree_position IS NULL
+                   AND t.mined_height IN (SELECT height FROM blocks)"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::CommitmentTree,
+                format!(
+                    "{} {} notes in scanned blocks have no commitment tree position",
+                    count, name
+                ),
+                rescan(height),
+            );
+        }
+
+        let (count, height) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(*), MIN(t.mined_height)
+                 FROM {notes} n JOIN transactions t ON t.id_tx = n.tx
+                 JOIN blocks b ON b.height = t.mined_height
+                 WHERE n.commitment_tree_position >= b.{pool}_commitment_tree_size"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::CommitmentTree,
+                format!(
+                    "{} {} notes have a tree position beyond the tree size of their block",
+                    count, name
+                ),
+                rescan(height),
+            );
+        }
+
+        // Shards hold 2^16 leaves; without its shard a note has no witness
+        let (count, height) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(*), MIN(t.mined_height)
+                 FROM {notes} n JOIN transactions t ON t.id_tx = n.tx
+                 WHERE n.commitment_tree_position IS NOT NULL
+                   AND n.commitment_tree_position >> 16 NOT IN
+                       (SELECT shard_index FROM {pool}_tree_shards)"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::CommitmentTree,
+                format!(
+                    "{} {} notes are in commitment tree shards missing from the database",
+                    count, name
+                ),
+                rescan(height),
+            );
+        }
+
+        let (count, _) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(*), NULL FROM {pool}_tree_checkpoints
+                 WHERE checkpoint_id > (SELECT MAX(height) FROM blocks)"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::CommitmentTree,
+                format!(
+                    "{} {} tree checkpoints are above the last scanned block",
+                    count, name
+                ),
+                rescan(tip.map(|tip| tip as u64 + 1)),
+            );
+        }
+
+        let (count, height) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(DISTINCT n.nf), MIN(t.mined_height)
+                 FROM {notes} n JOIN transactions t ON t.id_tx = n.tx
+                 WHERE n.nf IN (SELECT nf FROM {notes} WHERE nf IS NOT NULL
+                                GROUP BY nf HAVING COUNT(*) > 1)"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::Nullifiers,
+                format!("{} {} nullifiers belong to more than one note", count, name),
+                rescan(height),
+            );
+        }
+
+        // Spends are detected by nullifier, so a spent note must have one
+        let (count, height) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(*), MIN(t.mined_height)
+                 FROM {spends} s JOIN {notes} n ON n.id = s.{note_id}
+                 JOIN transactions t ON t.id_tx = n.tx
+                 WHERE n.nf IS NULL"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::Nullifiers,
+                format!("{} spent {} notes have no nullifier", count, name),
+                rescan(height),
+            );
+        }
+
+        let (count, height) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(*), MIN(t.mined_height)
+                 FROM {spends} s JOIN {notes} n ON n.id = s.{note_id}
+                 JOIN transactions t ON t.id_tx = n.tx
+                 JOIN transactions st ON st.id_tx = s.transaction_id
+                 WHERE st.mined_height < t.mined_height"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::Nullifiers,
+                format!(
+                    "{} {} notes are spent in a block before the one they were received in",
+                    count, name
+                ),
+                rescan(height),
+            );
+        }
+
+        let (count, height) = probe(
+            &conn,
+            &format!(
+                "SELECT COUNT(*), MIN(height) FROM (
+                     SELECT MIN(t.mined_height) AS height
+                     FROM {spends} s JOIN transactions t ON t.id_tx = s.transaction_id
+                     WHERE t.mined_height IS NOT NULL
+                     GROUP BY s.{note_id} HAVING COUNT(DISTINCT s.transaction_id) > 1
+                 )"
+            ),
+        )?;
+        if count > 0 {
+            report.push(
+                IntegrityCheck::Nullifiers,
+                format!(
+                    "{} {} notes are spent by more than one mined transaction",
+                    count, name
+                ),
+                rescan(height),
+            );
+        }
+    }
+
+    Ok(report)
+}
+
+/// Check that every unspent Sapling and Orchard note in the wallet database
+/// at `path` can be witnessed against the current tree state
+///
+/// Mirrors the conditions the wallet's note selection puts on spendable
+/// notes. Only reads the database.
+pub(crate) fn check_witnesses(path: &Path) -> Result<WitnessReport> {
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    let tip: Option<i64> = conn
+        .query_row("SELECT MAX(height) FROM blocks", [], |row| row.get(0))
+        .map_err(db_error(DB_CONTEXT))?;
+    let mut report = WitnessReport {
+        tip_height: tip.map(|tip| tip as u64),
+        ..WitnessReport::default()
+    };
+
+    for (pool, table, index) in [
+        (Pool::Sapling, "sapling", "output_index"),
+        (Pool::Orchard, "orchard", "action_index"),
+    ] {
+        // Shards with unscanned blocks below the tip cannot be witnessed into
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT t.txid, n.{index}, n.value, t.mined_height, n.nf IS NOT NULL,
+                        n.commitment_tree_position IS NOT NULL,
+                        n.commitment_tree_position >> 16 IN
+                            (SELECT shard_index FROM {table}_tree_shards),
+                        EXISTS (SELECT 1 FROM {table}_tree_checkpoints
+                                WHERE checkpoint_id >= t.mined_height),
+                        (SELECT MIN(r.block_range_start) FROM v_{table}_shard_unscanned_ranges r
+                         WHERE r.shard_index = n.commitment_tree_position >> 16
+                           AND r.block_range_start <= ?1),
+                        (SELECT MAX(r.block_range_end) FROM v_{table}_shard_unscanned_ranges r
+                         WHERE r.shard_index = n.commitment_tree_position >> 16
+                           AND r.block_range_start <= ?1)
+                 FROM {table}_received_notes n JOIN transactions t ON t.id_tx = n.tx
+                 WHERE t.mined_height IS NOT NULL
+                   AND n.id NOT IN (
+                       SELECT s.{table}_received_note_id FROM {table}_received_note_spends s
+                       JOIN transactions st ON st.id_tx = s.transaction_id
+                       WHERE st.mined_height IS NOT NULL)
+                 ORDER BY t.mined_height, n.{index}"
+            ))
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map([tip], |row| {
+                let mut txid: Vec<u8> = row.get(0)?;
+                txid.reverse();
+                Ok((
+                    hex::encode(txid),
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)? as u64,
+                    row.get::<_, bool>(4)?,
+                    row.get::<_, bool>(5)?,
+                    row.get::<_, Option<bool>>(6)?.unwrap_or(false),
+                    row.get::<_, bool>(7)?,
+                    row.get::<_, Option<i64>>(8)?,
+                    row.get::<_, Option<i64>>(9)?,
+                ))
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+
+        for (
+            txid,
+            output_index,
+            value,
+            mined_height,
+            has_nullifier,
+            has_position,
+            has_shard,
+            has_checkpoint,
+            unscanned_start,
+            unscanned_end,
+        ) in rows
+        {
+            report.notes_checked += 1;
+            let (problem, rescan_from, rescan_to) = if !has_position {
+                (WitnessProblem::NoTreePosition, mined_height, None)
+            } else if !has_shard {
+                (WitnessProblem::MissingShard, mined_height, None)
+            } else if let Some(start) = unscanned_start {
+                (
+                    WitnessProblem::UnscannedShard,
+                    start as u64,
+                    unscanned_end.map(|end| end as u64),
+                )
+            } else if !has_checkpoint {
+                (WitnessProblem::NoCheckpoint, mined_height, None)
+            } else if !has_nullifier {
+                (WitnessProblem::NoNullifier, mined_height, None)
+            } else {
+                continue;
+            };
+            report.issues.push(NoteWitnessIssue {
+                note: NoteId {
+                    pool,
+                    txid,
+                    output_index,
+                },
+                value,
+                mined_height,
+                problem,
+                rescan_from,
+                rescan_to,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_size_prune_and_vacuum() {
+        let path = TempDb::new();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE blocks (height INTEGER PRIMARY KEY, meta BLOB);
+             CREATE TABLE transactions (txid BLOB, mined_height INTEGER);
+             CREATE INDEX idx_mined ON transactions (mined_height);
+             CREATE VIEW v_transactions AS SELECT txid, mined_height FROM transactions;
+             INSERT INTO transactions VALUES (x'01', 150);",
+        )
+        .unwrap();
+        for height in 0..1_000 {
+            conn.execute("INSERT INTO blocks VALUES (?1, zeroblob(1000))", [height])
+                .unwrap();
+        }
+        drop(conn);
+
+        let size = database_size(&path).unwrap();
+        assert_eq!(size.tables[0].name, "blocks");
+        assert_eq!(size.tables[0].rows, 1_000);
+        assert_eq!(size.tables[1].name, "transactions");
+
+        assert_eq!(prune_blocks(&path, 900).unwrap(), 899);
+        assert!(database_size(&path).unwrap().free_bytes > 0);
+
+        let report = vacuum(&path).unwrap();
+        assert!(report.bytes_released() > 800_000);
+        assert_eq!(database_size(&path).unwrap().free_bytes, 0);
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        let path = TempDb::new();
+        let conn = Connection::open(&path).unwrap();
+        let mut schema = String::from(
+            "CREATE TABLE blocks (height INTEGER PRIMARY KEY,
+                                  sapling_commitment_tree_size INTEGER,
+                                  orchard_commitment_tree_size INTEGER);
+             CREATE TABLE transactions (id_tx INTEGER PRIMARY KEY, mined_height INTEGER);
+             INSERT INTO blocks VALUES (100, 10, 0);
+             INSERT INTO blocks VALUES (101, 12, 0);
+             INSERT INTO transactions VALUES (1, 100);
+             INSERT INTO transactions VALUES (2, 101);",
+        );
+        for pool in ["sapling", "orchard"] {
+            schema.push_str(&format!(
+                "CREATE TABLE {pool}_received_notes (id INTEGER PRIMARY KEY, tx INTEGER,
+                                                     nf BLOB, commitment_tree_position INTEGER);
+                 CREATE TABLE {pool}_received_note_spends ({pool}_received_note_id INTEGER,
+                                                           transaction_id INTEGER);
+                 CREATE TABLE {pool}_tree_shards (shard_index INTEGER PRIMARY KEY);
+                 CREATE TABLE {pool}_tree_checkpoints (checkpoint_id INTEGER PRIMARY KEY);"
+            ));
+        }
+        conn.execute_batch(&schema).unwrap();
+        conn.execute_batch(
+            "INSERT INTO sapling_tree_shards VALUES (0);
+             INSERT INTO sapling_received_notes VALUES (1, 1, x'01', 9);
+             INSERT INTO sapling_received_note_spends VALUES (1, 2);
+             INSERT INTO sapling_tree_checkpoints VALUES (101);",
+        )
+        .unwrap();
+        assert!(verify_integrity(&path).unwrap().is_ok());
+
+        // A note without a position, spent twice, and a checkpoint above the tip
+        conn.execute_batch(
+            "INSERT INTO sapling_received_notes VALUES (2, 2, x'02', NULL);
+             INSERT INTO transactions VALUES (3, 101);
+             INSERT INTO sapling_received_note_spends VALUES (1, 3);
+             INSERT INTO sapling_tree_checkpoints VALUES (105);",
+        )
+        .unwrap();
+        drop(conn);
+        let report = verify_integrity(&path).unwrap();
+        assert_eq!(report.issues.len(), 3, "{:?}", report.issues);
+        assert!(report
+            .issues
+            .iter()
+            .all(|issue| issue.check != IntegrityCheck::Database));
+        assert!(report.is_repairable());
+        assert_eq!(report.rescan_from(), Some(101));
+    }
+
+    #[test]
+    fn test_check_witnesses() {
+        let path = TempDb::new();
+        let conn = Connection::open(&path).unwrap();
+        let mut schema = String::from(
+            "CREATE TABLE blocks (height INTEGER PRIMARY KEY);
+             CREATE TABLE transactions (id_tx INTEGER PRIMARY KEY, txid BLOB,
+                                        mined_height INTEGER);
+             INSERT INTO blocks VALUES (100);
+             INSERT INTO blocks VALUES (200);
+             INSERT INTO transactions VALUES (1, x'01', 100);
+             INSERT INTO transactions VALUES (2, x'02', 150);
+             INSERT INTO transactions VALUES (3, x'03', NULL);",
+        );
+        for (pool, index) in [("sapling", "output_index"), ("orchard", "action_index")] {
+            schema.push_str(&format!(
+                "CREATE TABLE {pool}_received_notes (id INTEGER PRIMARY KEY, tx INTEGER,
+                                                     {index} INTEGER, value INTEGER, nf BLOB,
+                                                     commitment_tree_position INTEGER);
+                 CREATE TABLE {pool}_received_note_spends ({pool}_received_note_id INTEGER,
+                                                           transaction_id INTEGER);
+                 CREATE TABLE {pool}_tree_shards (shard_index INTEGER PRIMARY KEY);
+                 CREATE TABLE {pool}_tree_checkpoints (checkpoint_id INTEGER PRIMARY KEY);
+                 CREATE TABLE v_{pool}_shard_unscanned_ranges (shard_index INTEGER,
+                                                               block_range_start INTEGER,
+                                                               block_range_end INTEGER);
+                 INSERT INTO {pool}_tree_shards VALUES (0);
+                 INSERT INTO {pool}_tree_checkpoints VALUES (200);"
+            ));
+        }
+        conn.execute_batch(&schema).unwrap();
+        // A witnessable note, and a spent note whose witness no longer matters
+        conn.execute_batch(
+            "INSERT INTO sapling_received_notes VALUES (1, 1, 0, 5000, x'aa', 3);
+             INSERT INTO sapling_received_notes VALUES (2, 1, 1, 7000, NULL, NULL);
+             INSERT INTO sapling_received_note_spends VALUES (2, 2);
+             INSERT INTO orchard_received_notes VALUES (1, 3, 0, 9000, NULL, NULL);",
+        )
+        .unwrap();
+        let report = check_witnesses(&path).unwrap();
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.notes_checked, 1);
+        assert_eq!(report.tip_height, Some(200));
+
+        // An unscanned range in shard 0, and an Orchard note in a missing shard
+        conn.execute_batch(
+            "INSERT INTO v_sapling_shard_unscanned_ranges VALUES (0, 120, 180);
+             INSERT INTO v_sapling_shard_unscanned_ranges VALUES (0, 300, 400);
+             INSERT INTO orchard_received_notes VALUES (2, 2, 4, 3000, x'bb', 70000);",
+        )
+        .unwrap();
+        drop(conn);
+        let report = check_witnesses(&path).unwrap();
+        assert_eq!(report.notes_checked, 2);
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(report.issues[0].problem, WitnessProblem::UnscannedShard);
+        assert_eq!(report.issues[0].note.txid, "01");
+        assert_eq!(
+            (report.issues[0].rescan_from, report.issues[0].rescan_to),
+            (120, Some(180))
+        );
+        assert_eq!(report.issues[1].problem, WitnessProblem::MissingShard);
+        assert_eq!(report.issues[1].note.output_index, 4);
+        assert_eq!(report.value_at_risk(), 8000);
+        assert_eq!(report.rescan_from(), Some(120));
+    }
+}
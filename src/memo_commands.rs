@@ -0,0 +1,281 @@
+//! Commands carried in the memos of incoming payments
+//!
+//! A payer can attach a structured memo to a payment to drive a workflow on
+//! the receiving side, such as activating a license or matching an order.
+//! Two memo formats are understood, both naming the command with a `cmd`
+//! key:
+//! - a JSON object: `{"cmd": "activate", "license": "ABCD-1234"}`
+//! - `key=value` pairs separated by `&`, `;` or newlines:
+//!   `cmd=activate&license=ABCD-1234`
+//!
+//! Memos without a `cmd` key, such as plain text notes, are not commands.
+//!
+//! A [`MemoDispatcher`] follows [`WalletEvent::PaymentReceived`] events and
+//! calls the [`MemoHandler`] registered for each command. Every payment
+//! output is dispatched at most once; by default only once it is mined, so a
+//! command is not acted on for a transaction that may never confirm.
+
+use crate::error::Result;
+use crate::events::{ReceivedPayment, WalletEvent};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio::sync::{broadcast, mpsc};
+
+/// Key naming the command in a memo
+pub const COMMAND_KEY: &str = "cmd";
+
+/// A command parsed from a memo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoCommand {
+    /// Command name, lowercased
+    pub name: String,
+    /// Every other key and its value; non-string JSON values are kept as
+    /// JSON text
+    pub args: BTreeMap<String, String>,
+}
+
+impl MemoCommand {
+    /// Value of an argument
+    pub fn arg(&self, key: &str) -> Option<&str> {
+        self.args.get(key).map(String::as_str)
+    }
+}
+
+/// Parse a command from memo text
+///
+/// # Returns
+/// `None` if the memo is not in a command format or names no command
+pub fn parse_memo_command(memo: &str) -> Option<MemoCommand> {
+    let memo = memo.trim();
+    let mut args: BTreeMap<String, String> = if memo.starts_with('{') {
+        let serde_json::Value::Object(object) = serde_json::from_str(memo).ok()? else {
+            return None;
+        };
+        object
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(value) => (key, value),
+                other => (key, other.to_string()),
+            })
+            .collect()
+    } else {
+        memo.split(['&', ';', '\n'])
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                pair.split_once('=')
+                    .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            })
+            .collect::<Option<_>>()?
+    };
+    let name = args.remove(COMMAND_KEY)?.to_ascii_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+    Some(MemoCommand { name, args })
+}
+
+/// Acts on a memo command
+pub trait MemoHandler: Send + Sync {
+    /// Handle `command`, received with `payment`
+    fn handle(&self, command: &MemoCommand, payment: &ReceivedPayment) -> Result<()>;
+}
+
+impl<F> MemoHandler for F
+where
+    F: Fn(&MemoCommand, &ReceivedPayment) -> Result<()> + Send + Sync,
+{
+    fn handle(&self, command: &MemoCommand, payment: &ReceivedPayment) -> Result<()> {
+        self(command, payment)
+    }
+}
+
+/// What happened to a memo command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispatchOutcome {
+    /// The registered handler succeeded
+    Handled,
+    /// The registered handler failed; the command is dispatched again the
+    /// next time the payment is seen
+    Failed(String),
+    /// No handler is registered for the command
+    Unhandled,
+}
+
+/// A memo command dispatched by a [`MemoDispatcher`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoDispatch {
+    pub command: MemoCommand,
+    pub payment: ReceivedPayment,
+    pub outcome: DispatchOutcome,
+}
+
+/// Dispatches memo commands of incoming payments to registered handlers
+#[derive(Default)]
+pub struct MemoDispatcher {
+    handlers: HashMap<String, Box<dyn MemoHandler>>,
+    /// Dispatch payments still in the mempool
+    unconfirmed: bool,
+    /// Payment outputs already dispatched, as `txid:output_index`
+    dispatched: HashSet<String>,
+}
+
+impl MemoDispatcher {
+    /// Create a dispatcher with no handlers, dispatching mined payments only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the handler for a command, replacing any previous one
+    ///
+    /// # Arguments
+    /// * `name` - Command name (case-insensitive)
+    /// * `handler` - Handler, e.g. a closure
+    pub fn register(&mut self, name: &str, handler: impl MemoHandler + 'static) {
+        self.handlers
+            .insert(name.to_ascii_lowercase(), Box::new(handler));
+    }
+
+    /// Also dispatch commands of payments still in the mempool
+    pub fn with_unconfirmed(mut self, unconfirmed: bool) -> Self {
+        self.unconfirmed = unconfirmed;
+        self
+    }
+
+    /// Record a payment output as already dispatched (e.g. after a restart)
+    pub fn mark_dispatched(&mut self, txid: &str, output_index: u32) {
+        self.dispatched.insert(format!("{}:{}", txid, output_index));
+    }
+
+    /// Process a single event, returning the command dispatched, if any
+    pub fn handle_event(&mut self, event: &WalletEvent) -> Option<MemoDispatch> {
+        let WalletEvent::PaymentReceived(payment) = event else {
+            return None;
+        };
+        if payment.height.is_none() && !self.unconfirmed {
+            return None;
+        }
+        let key = format!("{}:{}", payment.txid, payment.output_index);
+        if self.dispatched.contains(&key) {
+            return None;
+        }
+        let command = parse_memo_command(payment.memo.as_deref()?)?;
+
+        let outcome = match self.handlers.get(&command.name) {
+            Some(handler) => match handler.handle(&command, payment) {
+                Ok(()) => DispatchOutcome::Handled,
+                Err(e) => DispatchOutcome::Failed(e.to_string()),
+            },
+            None => DispatchOutcome::Unhandled,
+        };
+        if !matches!(outcome, DispatchOutcome::Failed(_)) {
+            self.dispatched.insert(key);
+        }
+        Some(MemoDispatch {
+            command,
+            payment: payment.clone(),
+            outcome,
+        })
+    }
+
+    /// Consume events from a subscription and forward dispatched commands
+    ///
+    /// Runs until the event bus is closed or the output channel is dropped.
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<WalletEvent>,
+        output: mpsc::Sender<MemoDispatch>,
+    ) -> Result<()> {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Memo dispatcher lagged, skipped {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            if let Some(dispatch) = self.handle_event(&event) {
+                if output.send(dispatch).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn payment(memo: &str, height: Option<u64>) -> WalletEvent {
+        WalletEvent::PaymentReceived(ReceivedPayment {
+            txid: "aa".to_string(),
+            output_index: 1,
+            address: "u1shop".to_string(),
+            amount: 50_000,
+            memo: Some(memo.to_string()),
+            height,
+        })
+    }
+
+    #[test]
+    fn test_parse_memo_command() {
+        let command =
+            parse_memo_command(r#"{"cmd": "Activate", "license": "AB-12", "seats": 3}"#).unwrap();
+        assert_eq!(command.name, "activate");
+        assert_eq!(command.arg("license"), Some("AB-12"));
+        assert_eq!(command.arg("seats"), Some("3"));
+
+        let command = parse_memo_command("cmd=order; id = 42\n").unwrap();
+        assert_eq!(command.name, "order");
+        assert_eq!(command.arg("id"), Some("42"));
+
+        assert!(parse_memo_command("Thanks for lunch!").is_none());
+        assert!(parse_memo_command("id=42").is_none());
+        assert!(parse_memo_command(r#"["cmd"]"#).is_none());
+        assert!(parse_memo_command("cmd=").is_none());
+    }
+
+    #[test]
+    fn test_dispatch_once_when_mined() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut dispatcher = MemoDispatcher::new();
+        dispatcher.register(
+            "activate",
+            move |command: &MemoCommand, _: &ReceivedPayment| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                match command.arg("license") {
+                    Some(_) => Ok(()),
+                    None => Err(Error::InvalidParameter("Missing license".to_string())),
+                }
+            },
+        );
+
+        let memo = "cmd=activate&license=AB-12";
+        assert!(dispatcher.handle_event(&payment(memo, None)).is_none());
+        let dispatch = dispatcher.handle_event(&payment(memo, Some(100))).unwrap();
+        assert_eq!(dispatch.outcome, DispatchOutcome::Handled);
+        assert!(dispatcher.handle_event(&payment(memo, Some(100))).is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let mut dispatcher = dispatcher.with_unconfirmed(true);
+        dispatcher.dispatched.clear();
+        let failed = dispatcher
+            .handle_event(&payment("cmd=activate", None))
+            .unwrap();
+        assert!(matches!(failed.outcome, DispatchOutcome::Failed(_)));
+        assert!(dispatcher
+            .handle_event(&payment("cmd=activate", None))
+            .is_some());
+        assert_eq!(
+            dispatcher
+                .handle_event(&payment("cmd=refund", Some(100)))
+                .map(|dispatch| dispatch.outcome),
+            Some(DispatchOutcome::Unhandled)
+        );
+    }
+}
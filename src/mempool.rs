@@ -0,0 +1,204 @@
+//! Mempool conflict detection
+//!
+//! A transaction that spends a transparent outpoint or note already spent by
+//! a pending transaction is rejected by the node with a generic
+//! `txn-mempool-conflict` or `bad-txns-nullifier-exists` message, or worse,
+//! accepted by one server and dropped by the rest of the network. Checking
+//! the mempool first with [`check_mempool`] turns this into an
+//! [`Error::MempoolConflict`] naming the pending transaction and the inputs
+//! it shares with ours.
+//!
+//! Mempool contents come from a [`MempoolSource`]: zcashd through
+//! `getrawmempool`, or lightwalletd through its mempool stream.
+
+use crate::client::RpcClient;
+use crate::error::{Error, Result};
+use crate::transaction::decode::{read_transaction, txid_hex};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use thiserror::Error as ThisError;
+
+/// An input consumed by a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SpentInput {
+    /// A transparent output, by transaction ID and output index
+    Transparent { txid: String, index: u32 },
+    /// A Sapling note, by nullifier (hex)
+    Sapling(String),
+    /// An Orchard note, by nullifier (hex)
+    Orchard(String),
+}
+
+impl fmt::Display for SpentInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpentInput::Transparent { txid, index } => write!(f, "outpoint {}:{}", txid, index),
+            SpentInput::Sapling(nullifier) => write!(f, "Sapling nullifier {}", nullifier),
+            SpentInput::Orchard(nullifier) => write!(f, "Orchard nullifier {}", nullifier),
+        }
+    }
+}
+
+/// The inputs of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingSpends {
+    pub txid: String,
+    pub inputs: Vec<SpentInput>,
+}
+
+impl PendingSpends {
+    /// Read the inputs of a serialized transaction
+    pub fn from_raw(raw: &[u8]) -> Result<Self> {
+        let (_, _, tx) = read_transaction(raw)?;
+        let mut inputs = Vec::new();
+        if let Some(bundle) = tx.transparent_bundle().filter(|b| !b.is_coinbase()) {
+            inputs.extend(bundle.vin.iter().map(|input| SpentInput::Transparent {
+                txid: txid_hex(input.prevout().hash()),
+                index: input.prevout().n(),
+            }));
+        }
+        if let Some(bundle) = tx.sapling_bundle() {
+            inputs.extend(
+                bundle
+                    .shielded_spends()
+                    .iter()
+                    .map(|spend| SpentInput::Sapling(hex::encode(spend.nullifier().0))),
+            );
+        }
+        if let Some(bundle) = tx.orchard_bundle() {
+            inputs.extend(
+                bundle
+                    .actions()
+                    .iter()
+                    .map(|action| SpentInput::Orchard(hex::encode(action.nullifier().to_bytes()))),
+            );
+        }
+        Ok(Self {
+            txid: tx.txid().to_string(),
+            inputs,
+        })
+    }
+}
+
+/// A pending transaction spending the same inputs as ours
+#[derive(ThisError, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error(
+    "transaction {txid} spends {} input(s) already spent by pending transaction {conflicting_txid}",
+    .inputs.len()
+)]
+pub struct MempoolConflict {
+    pub txid: String,
+    pub conflicting_txid: String,
+    /// Inputs spent by both transactions
+    pub inputs: Vec<SpentInput>,
+}
+
+/// Find the first mempool transaction that double-spends an input of `tx`
+///
+/// `tx` itself is ignored if it is already in the mempool.
+pub fn find_conflict(tx: &PendingSpends, mempool: &[PendingSpends]) -> Option<MempoolConflict> {
+    let ours: HashSet<&SpentInput> = tx.inputs.iter().collect();
+    mempool
+        .iter()
+        .filter(|pending| pending.txid != tx.txid)
+        .find_map(|pending| {
+            let shared: Vec<SpentInput> = pending
+                .inputs
+                .iter()
+                .filter(|input| ours.contains(input))
+                .cloned()
+                .collect();
+            (!shared.is_empty()).then(|| MempoolConflict {
+                txid: tx.txid.clone(),
+                conflicting_txid: pending.txid.clone(),
+                inputs: shared,
+            })
+        })
+}
+
+/// A view of the pending transactions of a node or server
+#[async_trait]
+pub trait MempoolSource: Send + Sync {
+    /// Serialized transactions currently in the mempool
+    async fn mempool_transactions(&self) -> Result<Vec<Vec<u8>>>;
+}
+
+#[async_trait]
+impl MempoolSource for RpcClient {
+    async fn mempool_transactions(&self) -> Result<Vec<Vec<u8>>> {
+        let mut transactions = Vec::new();
+        for txid in self.get_raw_mempool().await? {
+            // Transactions mined or evicted since the listing are skipped
+            match self.get_raw_transaction_bytes(&txid).await {
+                Ok(raw) => transactions.push(raw),
+                Err(e) => tracing::debug!("Mempool transaction {} is gone: {}", txid, e),
+            }
+        }
+        Ok(transactions)
+    }
+}
+
+/// Check that no pending transaction spends an input of `raw_tx`
+///
+/// Mempool transactions that cannot be parsed are skipped.
+///
+/// # Returns
+/// `Ok(())` if there is no conflict, otherwise [`Error::MempoolConflict`]
+pub async fn check_mempool(source: &dyn MempoolSource, raw_tx: &[u8]) -> Result<()> {
+    let tx = PendingSpends::from_raw(raw_tx)?;
+    let mempool: Vec<PendingSpends> = source
+        .mempool_transactions()
+        .await?
+        .iter()
+        .filter_map(|raw| match PendingSpends::from_raw(raw) {
+            Ok(pending) => Some(pending),
+            Err(e) => {
+                tracing::debug!("Skipping unparseable mempool transaction: {}", e);
+                None
+            }
+        })
+        .collect();
+    match find_conflict(&tx, &mempool) {
+        Some(conflict) => Err(Error::MempoolConflict(conflict)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outpoint(byte: &str, index: u32) -> SpentInput {
+        SpentInput::Transparent {
+            txid: byte.repeat(32),
+            index,
+        }
+    }
+
+    #[test]
+    fn test_find_conflict() {
+        let ours = PendingSpends {
+            txid: "01".repeat(32),
+            inputs: vec![outpoint("aa", 0), SpentInput::Orchard("bb".repeat(32))],
+        };
+        let unrelated = PendingSpends {
+            txid: "02".repeat(32),
+            inputs: vec![outpoint("aa", 1), SpentInput::Sapling("bb".repeat(32))],
+        };
+        assert_eq!(
+            find_conflict(&ours, &[unrelated.clone(), ours.clone()]),
+            None
+        );
+
+        let double_spend = PendingSpends {
+            txid: "03".repeat(32),
+            inputs: vec![SpentInput::Orchard("bb".repeat(32)), outpoint("cc", 0)],
+        };
+        let conflict = find_conflict(&ours, &[unrelated, double_spend]).unwrap();
+        assert_eq!(conflict.conflicting_txid, "03".repeat(32));
+        assert_eq!(conflict.inputs, vec![SpentInput::Orchard("bb".repeat(32))]);
+        assert!(conflict.to_string().contains("spends 1 input(s)"));
+    }
+}
@@ -0,0 +1,417 @@
+//! Wallet migration (key rotation)
+//!
+//! When a seed is suspected to be compromised, every spendable note and
+//! transparent output controlled by it should be moved to a wallet derived
+//! from a fresh seed as quickly as possible. [`migrate_wallet`] sweeps the
+//! Orchard, Sapling and transparent pools of every account of the old
+//! wallet into the new wallet's default unified address through zcashd.
+//!
+//! Funds are swept with one `z_sendmany` per source address and pool. zcashd
+//! selects the notes and computes the fee itself; each sweep asks for the
+//! value of the address's notes in that pool less the ZIP-317 fee of
+//! spending all of them, which is what zcashd charges when it does. If it
+//! selects other notes, the sweep can leave change in the old wallet or fail
+//! for lack of funds; run the migration again to sweep what is left. Dust
+//! notes that are not worth the marginal fee to spend are left behind and
+//! reported.
+
+use crate::client::RpcClient;
+use crate::error::{Error, Result};
+use crate::fees::calculate_zip317_fee;
+use crate::policy::SpendingPolicyEngine;
+use crate::rpc::{Payment, PrivacyPolicy};
+use crate::types::utils::{zatoshis_to_zec, zec_to_zatoshis};
+use crate::wallet::Wallet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Inputs at or below this value (zatoshis) cost at least as much to spend as they are worth
+pub const DUST_THRESHOLD: u64 = 5_000;
+
+/// Value pool of a migration input
+pub use crate::types::Pool;
+
+impl Pool {
+    fn from_zcashd(pool: &str) -> Option<Self> {
+        match pool {
+            "sapling" => Some(Pool::Sapling),
+            "orchard" => Some(Pool::Orchard),
+            "transparent" => Some(Pool::Transparent),
+            _ => None,
+        }
+    }
+}
+
+/// A spendable input owned by the old wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationInput {
+    pub txid: String,
+    pub index: u32,
+    pub pool: Pool,
+    /// Address the input was received on
+    pub address: String,
+    /// Value in zatoshis
+    pub amount: u64,
+}
+
+/// Options controlling a migration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationOptions {
+    /// Minimum confirmations for inputs to be swept
+    pub minconf: u32,
+    /// Maximum time to wait for each batch operation (seconds)
+    pub max_wait_seconds: u64,
+}
+
+impl Default for MigrationOptions {
+    fn default() -> Self {
+        Self {
+            minconf: 1,
+            max_wait_seconds: 300,
+        }
+    }
+}
+
+/// One sweep transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationBatch {
+    /// Source address the batch spends from
+    pub from_address: String,
+    pub pool: Pool,
+    /// Inputs expected to be spent
+    pub inputs: Vec<MigrationInput>,
+    /// Total input value (zatoshis)
+    pub input_total: u64,
+    /// Estimated ZIP-317 fee of spending every input (zatoshis)
+    pub fee: u64,
+    /// Value requested for the new wallet (zatoshis)
+    pub amount: u64,
+}
+
+/// The full set of batches needed to empty the old wallet
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationPlan {
+    pub batches: Vec<MigrationBatch>,
+    /// Inputs left behind because they are not worth spending
+    pub dust: Vec<MigrationInput>,
+}
+
+impl MigrationPlan {
+    /// Total estimated fees across all batches (zatoshis)
+    pub fn total_fee(&self) -> u64 {
+        self.batches.iter().map(|b| b.fee).sum()
+    }
+
+    /// Total value requested for the new wallet (zatoshis)
+    pub fn total_amount(&self) -> u64 {
+        self.batches.iter().map(|b| b.amount).sum()
+    }
+}
+
+/// Progress notifications emitted by [`migrate_wallet`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MigrationProgress {
+    /// The plan was computed
+    Planned {
+        batches: usize,
+        total_amount: u64,
+        total_fee: u64,
+    },
+    /// A batch was handed to zcashd
+    BatchSubmitted { batch: usize, operation_id: String },
+    /// A batch transaction was created and broadcast
+    BatchCompleted { batch: usize, txid: String },
+    /// All batches have been processed
+    Finished { txids: Vec<String> },
+}
+
+/// Outcome of a migration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationReport {
+    /// Address the funds were swept to
+    pub destination: String,
+    pub plan: MigrationPlan,
+    /// Transaction IDs of completed batches, in plan order
+    pub txids: Vec<String>,
+}
+
+/// ZIP-317 logical actions for sweeping `inputs` notes from `pool` into one Orchard output
+fn sweep_logical_actions(pool: Pool, inputs: usize) -> u64 {
+    let inputs = inputs as u64;
+    match pool {
+        // Orchard spends and outputs share actions
+        Pool::Orchard => inputs.max(1),
+        Pool::Sapling | Pool::Transparent => inputs + 1,
+    }
+}
+
+/// Group inputs into one sweep per source address and pool
+///
+/// Orchard is swept first, as it needs no pool crossing and is the cheapest
+/// to move. Inputs whose value does not exceed their marginal fee are
+/// reported as dust.
+///
+/// # Arguments
+/// * `inputs` - Spendable inputs of the old wallet
+pub fn plan_migration(inputs: Vec<MigrationInput>) -> MigrationPlan {
+    let mut groups: BTreeMap<(Pool, String), Vec<MigrationInput>> = BTreeMap::new();
+    let mut plan = MigrationPlan::default();
+
+    for input in inputs {
+        if input.amount <= DUST_THRESHOLD {
+            plan.dust.push(input);
+        } else {
+            groups
+                .entry((input.pool, input.address.clone()))
+                .or_default()
+                .push(input);
+        }
+    }
+
+    for ((pool, address), group) in groups.into_iter().rev() {
+        let input_total: u64 = group.iter().map(|input| input.amount).sum();
+        let fee = calculate_zip317_fee(sweep_logical_actions(pool, group.len()));
+        if input_total <= fee {
+            plan.dust.extend(group);
+            continue;
+        }
+        plan.batches.push(MigrationBatch {
+            from_address: address,
+            pool,
+            inputs: group,
+            input_total,
+            fee,
+            amount: input_total - fee,
+        });
+    }
+
+    plan
+}
+
+/// Collect the spendable inputs of `wallet` from zcashd
+///
+/// Looks up the notes and outputs of every address of every account in the
+/// wallet database, not only the selected account's default addresses.
+async fn collect_inputs(
+    wallet: &Wallet,
+    rpc: &RpcClient,
+    minconf: u32,
+) -> Result<Vec<MigrationInput>> {
+    let shielded_addresses = wallet.all_shielded_addresses()?;
+    let transparent_addresses = wallet.all_transparent_addresses()?;
+    let mut inputs = Vec::new();
+
+    // An empty filter would list the whole node wallet, new wallet included
+    let notes = if shielded_addresses.is_empty() {
+        Vec::new()
+    } else {
+        rpc.z_listunspent(Some(minconf), None, false, &shielded_addresses)
+            .await?
+    };
+    for note in notes {
+        if !note.spendable {
+            continue;
+        }
+        let (Some(pool), Some(address)) = (Pool::from_zcashd(&note.pool), note.address) else {
+            continue;
+        };
+        inputs.push(MigrationInput {
+            txid: note.txid,
+            index: note.outindex.unwrap_or(0),
+            pool,
+            address,
+            amount: note
+                .amount_zat
+                .unwrap_or_else(|| zec_to_zatoshis(note.amount)),
+        });
+    }
+
+    let utxos = if transparent_addresses.is_empty() {
+        Vec::new()
+    } else {
+        rpc.listunspent(Some(minconf), None, &transparent_addresses)
+            .await?
+    };
+    for utxo in utxos {
+        if !utxo.spendable {
+            continue;
+        }
+        let Some(address) = utxo.address else {
+            continue;
+        };
+        inputs.push(MigrationInput {
+            txid: utxo.txid,
+            index: utxo.vout,
+            pool: Pool::Transparent,
+            address,
+            amount: utxo
+                .amount_zat
+                .unwrap_or_else(|| zec_to_zatoshis(utxo.amount)),
+        });
+    }
+
+    Ok(inputs)
+}
+
+/// Sweep all funds from `old_wallet` to `new_wallet`
+///
+/// Both wallets' keys must be loaded into the zcashd node behind `rpc` (the
+/// old one so it can spend, the new one is only used for its address).
+/// Batches are submitted one at a time and each is awaited before the next,
+/// so an interruption leaves every completed batch safely in the new wallet.
+/// zcashd computes the fee of each batch; see the [module docs](self) for
+/// when a batch leaves funds behind. Each batch is evaluated against the
+/// spending policies stored in the old wallet's database and recorded in its
+/// audit log.
+///
+/// # Arguments
+/// * `old_wallet` - Wallet derived from the compromised seed
+/// * `new_wallet` - Wallet derived from the replacement seed
+/// * `rpc` - zcashd RPC client holding the old wallet's keys
+/// * `options` - Migration options
+/// * `progress` - Callback invoked with progress updates
+///
+/// # Returns
+/// A report containing the executed plan and the resulting transaction IDs
+pub async fn migrate_wallet(
+    old_wallet: &Wallet,
+    new_wallet: &Wallet,
+    rpc: &RpcClient,
+    options: MigrationOptions,
+    mut progress: impl FnMut(&MigrationProgress),
+) -> Result<MigrationReport> {
+    if old_wallet.network() != new_wallet.network() {
+        return Err(Error::InvalidParameter(
+            "Old and new wallets are on different networks".to_string(),
+        ));
+    }
+
+    // Default addresses: the rotated ones would allocate a new address
+    let destination = new_wallet.default_unified_address()?;
+    if destination == old_wallet.default_unified_address()? {
+        return Err(Error::InvalidParameter(
+            "Old and new wallets are derived from the same seed".to_string(),
+        ));
+    }
+
+    let inputs = collect_inputs(old_wallet, rpc, options.minconf).await?;
+    let plan = plan_migration(inputs);
+    progress(&MigrationProgress::Planned {
+        batches: plan.batches.len(),
+        total_amount: plan.total_amount(),
+        total_fee: plan.total_fee(),
+    });
+
+    let policy = SpendingPolicyEngine::for_wallet(old_wallet)?;
+    let account = old_wallet.account_index();
+    let mut txids = Vec::with_capacity(plan.batches.len());
+    for (index, batch) in plan.batches.iter().enumerate() {
+        let privacy_policy = match batch.pool {
+            Pool::Orchard => PrivacyPolicy::FullPrivacy,
+            Pool::Sapling => PrivacyPolicy::AllowRevealedAmounts,
+            Pool::Transparent => PrivacyPolicy::AllowRevealedSenders,
+        };
+        let payments = vec![Payment {
+            address: destination.clone(),
+            amount: zatoshis_to_zec(batch.amount),
+            memo: None,
+        }];
+        policy.evaluate(
+            account,
+            &batch.from_address,
+            &payments,
+            Some(privacy_policy),
+            None,
+        )?;
+
+        let operation_id = rpc
+            .z_sendmany_with_policy(
+                &batch.from_address,
+                payments.clone(),
+                Some(options.minconf),
+                None,
+                privacy_policy,
+            )
+            .await?;
+        policy.record_submitted(
+            account,
+            &batch.from_address,
+            &payments,
+            Some(&operation_id),
+            None,
+        )?;
+        progress(&MigrationProgress::BatchSubmitted {
+            batch: index,
+            operation_id: operation_id.clone(),
+        });
+
+        let txid = rpc
+            .wait_for_operation(&operation_id, Some(options.max_wait_seconds))
+            .await?;
+        progress(&MigrationProgress::BatchCompleted {
+            batch: index,
+            txid: txid.clone(),
+        });
+        txids.push(txid);
+    }
+
+    progress(&MigrationProgress::Finished {
+        txids: txids.clone(),
+    });
+
+    Ok(MigrationReport {
+        destination,
+        plan,
+        txids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(pool: Pool, address: &str, amount: u64) -> MigrationInput {
+        MigrationInput {
+            txid: format!("{:x}", amount),
+            index: 0,
+            pool,
+            address: address.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_plan_batches_by_pool_and_address() {
+        let inputs = vec![
+            input(Pool::Sapling, "zs1old", 100_000),
+            input(Pool::Sapling, "zs1old", 300_000),
+            input(Pool::Sapling, "zs1old", 200_000),
+            input(Pool::Sapling, "zs1other", 100_000),
+            input(Pool::Orchard, "u1old", 50_000),
+            input(Pool::Transparent, "t1old", 1_000),
+        ];
+
+        let plan = plan_migration(inputs);
+        assert_eq!(plan.batches.len(), 3);
+        assert_eq!(plan.dust.len(), 1);
+
+        // Orchard first, one action at the minimum fee
+        assert_eq!(plan.batches[0].pool, Pool::Orchard);
+        assert_eq!(plan.batches[0].fee, 10_000);
+        assert_eq!(plan.batches[0].amount, 40_000);
+
+        // Every Sapling note of an address in one sweep: three spends + one output
+        let sapling = &plan.batches[2];
+        assert_eq!(sapling.from_address, "zs1old");
+        assert_eq!(sapling.input_total, 600_000);
+        assert_eq!(sapling.fee, 20_000);
+        assert_eq!(plan.total_amount(), 40_000 + 90_000 + 580_000);
+    }
+
+    #[test]
+    fn test_plan_skips_batches_not_worth_fee() {
+        let plan = plan_migration(vec![input(Pool::Sapling, "zs1old", 6_000)]);
+        assert!(plan.batches.is_empty());
+        assert_eq!(plan.dust.len(), 1);
+    }
+}
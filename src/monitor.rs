@@ -0,0 +1,299 @@
+//! Watch-only fleet monitoring
+//!
+//! Tracks the balances of many wallets that this instance cannot spend from,
+//! e.g. a treasury's cold wallets, from a single view-only deployment:
+//! - Unified full viewing keys are imported as view-only accounts into the
+//!   light client's wallet database, so every [`LightClient::sync`] scans them
+//! - Transparent addresses are queried from lightwalletd's address index
+//!
+//! After each sync, [`FleetMonitor::refresh`] reports which monitored wallets
+//! changed balance since the previous refresh.
+
+use crate::error::{Error, Result};
+use crate::light_client::LightClient;
+use crate::params::NetworkParams;
+use crate::types::{Balance, Network};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use zcash_address::ZcashAddress;
+use zcash_keys::address::Address;
+use zcash_keys::keys::UnifiedFullViewingKey;
+use zcash_protocol::consensus::Parameters;
+
+/// Balances of all monitored wallets at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FleetSnapshot {
+    /// Balance per monitored wallet label
+    pub balances: BTreeMap<String, Balance>,
+    /// Sum across all monitored wallets
+    pub total: Balance,
+}
+
+/// Balance change of one monitored wallet between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub label: String,
+    pub previous: Balance,
+    pub current: Balance,
+    /// Change in total balance (zatoshis)
+    pub delta: i64,
+}
+
+/// Monitors balances of view-only wallets and transparent addresses
+pub struct FleetMonitor {
+    network: Network,
+    /// Label -> viewing key
+    viewing_keys: BTreeMap<String, UnifiedFullViewingKey>,
+    /// Label -> transparent address
+    addresses: BTreeMap<String, String>,
+    last: Option<FleetSnapshot>,
+}
+
+impl FleetMonitor {
+    /// Create an empty monitor for the given network
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            viewing_keys: BTreeMap::new(),
+            addresses: BTreeMap::new(),
+            last: None,
+        }
+    }
+
+    fn consensus_network(&self) -> NetworkParams {
+        NetworkParams::from(self.network)
+    }
+
+    fn check_label(&self, label: &str) -> Result<()> {
+        if self.viewing_keys.contains_key(label) || self.addresses.contains_key(label) {
+            return Err(Error::InvalidParameter(format!(
+                "A monitored wallet named {} already exists",
+                label
+            )));
+        }
+        Ok(())
+    }
+
+    /// Monitor a wallet by its unified full viewing key
+    ///
+    /// The key is imported into the light client's wallet database and is
+    /// scanned from `birthday_height` on the next sync.
+    ///
+    /// # Arguments
+    /// * `client` - Light client whose sync should track the key
+    /// * `label` - Unique name for the monitored wallet
+    /// * `ufvk` - Encoded unified full viewing key
+    /// * `birthday_height` - Height of the first block that may contain funds for the key
+    pub async fn add_viewing_key(
+        &mut self,
+        client: &mut LightClient,
+        label: impl Into<String>,
+        ufvk: &str,
+        birthday_height: u64,
+    ) -> Result<()> {
+        let label = label.into();
+        self.check_label(&label)?;
+        if client.network() != self.network {
+            return Err(Error::InvalidParameter(
+                "Light client is on a different network than the monitor".to_string(),
+            ));
+        }
+
+        let ufvk = UnifiedFullViewingKey::decode(&self.consensus_network(), ufvk)
+            .map_err(|e| Error::KeyDerivation(format!("Invalid viewing key: {}", e)))?;
+        client
+            .import_viewing_key(&label, &ufvk, birthday_height)
+            .await?;
+        self.viewing_keys.insert(label, ufvk);
+        Ok(())
+    }
+
+    /// Monitor a transparent address
+    ///
+    /// # Arguments
+    /// * `label` - Unique name for the monitored wallet
+    /// * `address` - Transparent address on the monitor's network
+    pub fn add_transparent_address(
+        &mut self,
+        label: impl Into<String>,
+        address: impl Into<String>,
+    ) -> Result<()> {
+        let label = label.into();
+        let address = address.into();
+        self.check_label(&label)?;
+        let decoded = address
+            .parse::<ZcashAddress>()
+            .map_err(|e| Error::Address(format!("Invalid address {}: {}", address, e)))?
+            .convert_if_network::<Address>(self.consensus_network().network_type())
+            .map_err(|e| Error::Address(format!("Unusable address {}: {}", address, e)))?;
+        if !matches!(decoded, Address::Transparent(_)) {
+            return Err(Error::Address(format!(
+                "Not a transparent address: {}",
+                address
+            )));
+        }
+        self.addresses.insert(label, address);
+        Ok(())
+    }
+
+    /// Stop monitoring a wallet
+    ///
+    /// Imported viewing keys remain in the wallet database but are no longer reported.
+    pub fn remove(&mut self, label: &str) -> bool {
+        let removed =
+            self.viewing_keys.remove(label).is_some() || self.addresses.remove(label).is_some();
+        if removed {
+            if let Some(last) = self.last.as_mut() {
+                last.balances.remove(label);
+            }
+        }
+        removed
+    }
+
+    /// Labels of all monitored wallets
+    pub fn labels(&self) -> Vec<&str> {
+        self.viewing_keys
+            .keys()
+            .chain(self.addresses.keys())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The most recent snapshot taken by [`refresh`](Self::refresh)
+    pub fn last_snapshot(&self) -> Option<&FleetSnapshot> {
+        self.last.as_ref()
+    }
+
+    /// Read the current balances of all monitored wallets
+    ///
+    /// Viewing key balances reflect the light client's last sync; transparent
+    /// address balances are queried from the server.
+    pub async fn snapshot(&self, client: &mut LightClient) -> Result<FleetSnapshot> {
+        let mut balances = BTreeMap::new();
+
+        for (label, ufvk) in &self.viewing_keys {
+            let balance = client.account_balance(ufvk).await?.unwrap_or_default();
+            balances.insert(label.clone(), balance);
+        }
+        for (label, address) in &self.addresses {
+            let value = client
+                .get_transparent_balance(std::slice::from_ref(address))
+                .await?;
+            balances.insert(
+                label.clone(),
+                Balance {
+                    transparent: value,
+                    total: value,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let total = balances
+            .values()
+            .fold(Balance::default(), |acc, b| Balance {
+                transparent: acc.transparent.saturating_add(b.transparent),
+                sapling: acc.sapling.saturating_add(b.sapling),
+                orchard: acc.orchard.saturating_add(b.orchard),
+                total: acc.total.saturating_add(b.total),
+            });
+
+        Ok(FleetSnapshot { balances, total })
+    }
+
+    /// Take a new snapshot and report balance changes since the previous one
+    ///
+    /// Call after each [`LightClient::sync`]. On the first call every
+    /// wallet with a non-zero balance is reported as changed.
+    pub async fn refresh(&mut self, client: &mut LightClient) -> Result<Vec<BalanceChange>> {
+        let snapshot = self.snapshot(client).await?;
+        let changes = balance_changes(self.last.as_ref(), &snapshot);
+        self.last = Some(snapshot);
+        Ok(changes)
+    }
+}
+
+/// Compute per-wallet balance changes between two snapshots
+fn balance_changes(
+    previous: Option<&FleetSnapshot>,
+    current: &FleetSnapshot,
+) -> Vec<BalanceChange> {
+    current
+        .balances
+        .iter()
+        .filter_map(|(label, balance)| {
+            let before = previous
+                .and_then(|p| p.balances.get(label))
+                .cloned()
+                .unwrap_or_default();
+            if &before == balance {
+                return None;
+            }
+            Some(BalanceChange {
+                label: label.clone(),
+                delta: balance.total as i64 - before.total as i64,
+                previous: before,
+                current: balance.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_transparent::address::TransparentAddress;
+
+    fn snapshot(entries: &[(&str, u64)]) -> FleetSnapshot {
+        FleetSnapshot {
+            balances: entries
+                .iter()
+                .map(|(label, orchard)| {
+                    (
+                        label.to_string(),
+                        Balance {
+                            orchard: *orchard,
+                            total: *orchard,
+                            ..Default::default()
+                        },
+                    )
+                })
+                .collect(),
+            total: Balance::default(),
+        }
+    }
+
+    #[test]
+    fn test_balance_changes() {
+        let first = snapshot(&[("cold-1", 100), ("cold-2", 0)]);
+        let changes = balance_changes(None, &first);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].label, "cold-1");
+
+        let second = snapshot(&[("cold-1", 40), ("cold-2", 0)]);
+        let changes = balance_changes(Some(&first), &second);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].delta, -60);
+    }
+
+    #[test]
+    fn test_add_transparent_address() {
+        let address = |network: Network, hash: u8| {
+            Address::Transparent(TransparentAddress::PublicKeyHash([hash; 20]))
+                .encode(&NetworkParams::from(network))
+        };
+        let mut monitor = FleetMonitor::new(Network::Testnet);
+        monitor
+            .add_transparent_address("cold", address(Network::Testnet, 1))
+            .unwrap();
+        assert!(monitor
+            .add_transparent_address("cold", address(Network::Testnet, 2))
+            .is_err());
+        assert!(monitor
+            .add_transparent_address("main", address(Network::Mainnet, 3))
+            .is_err());
+        assert!(monitor.add_transparent_address("bad", "tmXyz").is_err());
+        assert!(monitor.add_transparent_address("zs", "zs1abc").is_err());
+        assert_eq!(monitor.labels(), vec!["cold"]);
+    }
+}
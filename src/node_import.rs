@@ -0,0 +1,226 @@
+//! Watch-only import of the SDK wallet into zcashd
+//!
+//! zcashd's balance and `z_listreceivedbyaddress`/`listreceivedbyaddress`
+//! calls only cover keys and addresses in the node's own wallet. A
+//! [`NodeImporter`] imports the SDK wallet's Sapling viewing key and its
+//! transparent addresses into the node as watch-only, so those calls (and an
+//! [`RpcWatcher`](crate::watcher::RpcWatcher)) reflect the SDK wallet.
+//!
+//! zcashd cannot import Orchard or unified viewing keys, so Orchard funds
+//! stay invisible to the node. Imports are recorded per node endpoint in the
+//! wallet database, and later runs only import what is new.
+
+use crate::client::RpcClient;
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Label given to imported transparent addresses
+pub const IMPORT_LABEL: &str = "numi";
+
+/// When the node rescans the chain for imported keys and addresses
+///
+/// A rescan is needed to find payments received before the import, and can
+/// take hours on mainnet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rescan {
+    /// Never rescan; only payments after the import are found
+    Never,
+    /// Rescan once if anything new was imported
+    #[default]
+    WhenNew,
+    /// Rescan on every import run
+    Always,
+}
+
+/// Outcome of an import run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Whether the Sapling viewing key was imported in this run
+    pub sapling_key_imported: bool,
+    /// Transparent addresses imported in this run
+    pub addresses_imported: Vec<String>,
+    /// Whether the node was asked to rescan
+    pub rescanned: bool,
+}
+
+const DB_CONTEXT: &str = "Node import record error";
+
+/// Keys and addresses already imported into each node
+struct ImportRecords {
+    conn: Connection,
+}
+
+impl ImportRecords {
+    fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_node_imports (
+                endpoint TEXT NOT NULL,
+                item TEXT NOT NULL,
+                imported_at INTEGER NOT NULL,
+                PRIMARY KEY (endpoint, item)
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    fn is_imported(&self, endpoint: &str, item: &str) -> Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM numi_node_imports WHERE endpoint = ?1 AND item = ?2",
+                params![endpoint, item],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|found| found.is_some())
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    fn record(&self, endpoint: &str, item: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO numi_node_imports (endpoint, item, imported_at)
+                 VALUES (?1, ?2, ?3)",
+                params![endpoint, item, unix_now() as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+}
+
+/// Imports an SDK wallet into zcashd as watch-only
+pub struct NodeImporter {
+    wallet: Wallet,
+    rescan: Rescan,
+    start_height: Option<u64>,
+}
+
+impl NodeImporter {
+    /// Create an importer for a wallet
+    pub fn new(wallet: Wallet) -> Self {
+        Self {
+            wallet,
+            rescan: Rescan::default(),
+            start_height: None,
+        }
+    }
+
+    /// Set when the node rescans the chain
+    pub fn with_rescan(mut self, rescan: Rescan) -> Self {
+        self.rescan = rescan;
+        self
+    }
+
+    /// Start rescans at this height, e.g. the wallet birthday
+    pub fn with_start_height(mut self, height: u64) -> Self {
+        self.start_height = Some(height);
+        self
+    }
+
+    /// Addresses the node can report payments for once imported: the
+    /// Sapling address and the transparent addresses
+    pub fn watch_addresses(&self) -> Result<Vec<String>> {
+        let mut addresses = vec![self.wallet.get_sapling_address()?];
+        addresses.extend(self.transparent_addresses()?);
+        Ok(addresses)
+    }
+
+    fn transparent_addresses(&self) -> Result<Vec<String>> {
+        let mut addresses = vec![self.wallet.get_transparent_address()?];
+        for info in self.wallet.transparent_addresses()? {
+            if !addresses.contains(&info.address) {
+                addresses.push(info.address);
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// Import the wallet's Sapling viewing key and transparent addresses
+    ///
+    /// Only zcashd supports watch-only imports. The node rescans at most
+    /// once per run, as set by [`with_rescan`](Self::with_rescan).
+    pub async fn import(&self, client: &RpcClient) -> Result<ImportReport> {
+        if !client.capabilities().await?.zcashd {
+            return Err(Error::Rpc(format!(
+                "Node {} does not support watch-only imports (zcashd only)",
+                client.endpoint()
+            )));
+        }
+        let records = ImportRecords::open(self.wallet.db_path())?;
+        let endpoint = client.endpoint();
+
+        let viewing_key = self.wallet.sapling_viewing_key()?;
+        // The key is recorded by its default address to keep it out of the
+        // table
+        let key_item = self.wallet.get_sapling_address()?;
+        let import_key = !records.is_imported(endpoint, &key_item)?;
+        let mut new_addresses = Vec::new();
+        for address in self.transparent_addresses()? {
+            if !records.is_imported(endpoint, &address)? {
+                new_addresses.push(address);
+            }
+        }
+
+        let rescan = match self.rescan {
+            Rescan::Never => false,
+            Rescan::WhenNew => import_key || !new_addresses.is_empty(),
+            Rescan::Always => true,
+        };
+        let mut report = ImportReport {
+            rescanned: rescan,
+            ..Default::default()
+        };
+
+        // A rescan covers the whole wallet, so addresses are imported without
+        // one and the viewing key import (which honours the start height)
+        // rescans for everything
+        for address in &new_addresses {
+            client.import_address(address, IMPORT_LABEL, false).await?;
+            records.record(endpoint, address)?;
+            report.addresses_imported.push(address.clone());
+        }
+        if import_key || rescan {
+            let mode = if rescan { "yes" } else { "no" };
+            client
+                .z_importviewingkey(&viewing_key, mode, self.start_height)
+                .await?;
+            records.record(endpoint, &key_item)?;
+            report.sapling_key_imported = import_key;
+        }
+
+        tracing::info!(
+            "Imported {} address(es){} into {}{}",
+            report.addresses_imported.len(),
+            if import_key {
+                " and the Sapling viewing key"
+            } else {
+                ""
+            },
+            endpoint,
+            if rescan { ", rescanning" } else { "" }
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_records() {
+        let records = ImportRecords::open(Path::new(":memory:")).unwrap();
+        assert!(!records.is_imported("http://node:8232", "t1abc").unwrap());
+        records.record("http://node:8232", "t1abc").unwrap();
+        records.record("http://node:8232", "t1abc").unwrap();
+        assert!(records.is_imported("http://node:8232", "t1abc").unwrap());
+        // Each node gets its own imports
+        assert!(!records.is_imported("http://other:8232", "t1abc").unwrap());
+    }
+}
@@ -0,0 +1,227 @@
+//! Consensus parameters for mainnet, testnet and regtest
+//!
+//! `zcash_protocol`'s [`Network`](zcash_protocol::consensus::Network) only
+//! covers mainnet and testnet. Using testnet parameters for a regtest node
+//! gives the wrong network upgrade activation heights and the wrong address
+//! encodings (`ztestsapling…` instead of `zregtestsapling…`, `utest…` instead
+//! of `uregtest…`). [`NetworkParams`] adds regtest, with the activation
+//! heights of the node's chain in [`RegtestUpgrades`]. By default every
+//! network upgrade is active from height 1, as on zebrad's default regtest
+//! network and zcashd started with `-nuparams` for each upgrade at height 1;
+//! read a node's own heights with
+//! [`Faucet::regtest_upgrades`](crate::regtest::Faucet::regtest_upgrades).
+
+use crate::error::{Error, Result};
+use crate::types::Network;
+use serde::{Deserialize, Serialize};
+use zcash_protocol::consensus::{
+    BlockHeight, BranchId, NetworkType, NetworkUpgrade, Parameters, MAIN_NETWORK, TEST_NETWORK,
+};
+
+/// Height at which every network upgrade activates on regtest by default
+pub const REGTEST_ACTIVATION_HEIGHT: u32 = 1;
+
+/// Maximum number of network upgrades with their own regtest activation height
+pub const MAX_REGTEST_UPGRADES: usize = 16;
+
+/// Network upgrade activation heights of a regtest chain
+///
+/// Upgrades are keyed by consensus branch ID, as `getblockchaininfo` lists
+/// them. Upgrades without their own height activate at the default height,
+/// or never if there is none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RegtestUpgrades {
+    default_height: Option<u32>,
+    /// Consensus branch ID and activation height of each listed upgrade
+    activations: [Option<(u32, u32)>; MAX_REGTEST_UPGRADES],
+}
+
+impl Default for RegtestUpgrades {
+    fn default() -> Self {
+        Self::all_at(REGTEST_ACTIVATION_HEIGHT)
+    }
+}
+
+impl RegtestUpgrades {
+    /// Every network upgrade active from `height`
+    pub fn all_at(height: u32) -> Self {
+        Self {
+            default_height: Some(height),
+            activations: [None; MAX_REGTEST_UPGRADES],
+        }
+    }
+
+    /// No network upgrade active; add them with
+    /// [`with_activation`](Self::with_activation)
+    pub fn none() -> Self {
+        Self {
+            default_height: None,
+            activations: [None; MAX_REGTEST_UPGRADES],
+        }
+    }
+
+    /// Activate `upgrade` at `height`
+    pub fn with_activation(self, upgrade: NetworkUpgrade, height: u32) -> Result<Self> {
+        let branch_id = upgrade_branch_id(upgrade).ok_or_else(|| {
+            Error::InvalidParameter(format!("Unknown network upgrade {:?}", upgrade))
+        })?;
+        self.with_branch_activation(branch_id, height)
+    }
+
+    /// Activate the upgrade with consensus branch ID `branch_id` at `height`
+    ///
+    /// # Errors
+    /// [`Error::InvalidParameter`] if [`MAX_REGTEST_UPGRADES`] upgrades
+    /// already have their own height
+    pub fn with_branch_activation(mut self, branch_id: u32, height: u32) -> Result<Self> {
+        let slot = match self
+            .activations
+            .iter()
+            .position(|entry| entry.is_some_and(|(id, _)| id == branch_id))
+        {
+            Some(index) => index,
+            None => self
+                .activations
+                .iter()
+                .position(Option::is_none)
+                .ok_or_else(|| {
+                    Error::InvalidParameter(format!(
+                        "At most {} regtest upgrade heights can be set",
+                        MAX_REGTEST_UPGRADES
+                    ))
+                })?,
+        };
+        self.activations[slot] = Some((branch_id, height));
+        Ok(self)
+    }
+
+    /// Activation height of `upgrade`, if it activates
+    pub fn activation_height(&self, upgrade: NetworkUpgrade) -> Option<BlockHeight> {
+        let branch_id = upgrade_branch_id(upgrade);
+        self.activations
+            .iter()
+            .flatten()
+            .find(|(id, _)| Some(*id) == branch_id)
+            .map(|(_, height)| *height)
+            .or(self.default_height)
+            .map(BlockHeight::from_u32)
+    }
+}
+
+/// Consensus branch ID of a network upgrade this crate knows
+fn upgrade_branch_id(upgrade: NetworkUpgrade) -> Option<u32> {
+    #[allow(unreachable_patterns)]
+    let branch_id = match upgrade {
+        NetworkUpgrade::Overwinter => BranchId::Overwinter,
+        NetworkUpgrade::Sapling => BranchId::Sapling,
+        NetworkUpgrade::Blossom => BranchId::Blossom,
+        NetworkUpgrade::Heartwood => BranchId::Heartwood,
+        NetworkUpgrade::Canopy => BranchId::Canopy,
+        NetworkUpgrade::Nu5 => BranchId::Nu5,
+        NetworkUpgrade::Nu6 => BranchId::Nu6,
+        _ => return None,
+    };
+    Some(u32::from(branch_id))
+}
+
+/// Consensus parameters of a [`Network`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkParams {
+    Mainnet,
+    Testnet,
+    Regtest(RegtestUpgrades),
+}
+
+/// Parameters of `network`, with the default regtest activation heights
+impl From<Network> for NetworkParams {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Mainnet => NetworkParams::Mainnet,
+            Network::Testnet => NetworkParams::Testnet,
+            Network::Regtest => NetworkParams::Regtest(RegtestUpgrades::default()),
+        }
+    }
+}
+
+impl From<NetworkParams> for Network {
+    fn from(params: NetworkParams) -> Self {
+        match params {
+            NetworkParams::Mainnet => Network::Mainnet,
+            NetworkParams::Testnet => Network::Testnet,
+            NetworkParams::Regtest(_) => Network::Regtest,
+        }
+    }
+}
+
+impl Parameters for NetworkParams {
+    fn network_type(&self) -> NetworkType {
+        match self {
+            NetworkParams::Mainnet => NetworkType::Main,
+            NetworkParams::Testnet => NetworkType::Test,
+            NetworkParams::Regtest(_) => NetworkType::Regtest,
+        }
+    }
+
+    fn activation_height(&self, nu: NetworkUpgrade) -> Option<BlockHeight> {
+        match self {
+            NetworkParams::Mainnet => MAIN_NETWORK.activation_height(nu),
+            NetworkParams::Testnet => TEST_NETWORK.activation_height(nu),
+            NetworkParams::Regtest(upgrades) => upgrades.activation_height(nu),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_protocol::consensus::NetworkConstants;
+
+    #[test]
+    fn test_regtest_params() {
+        let regtest = NetworkParams::from(Network::Regtest);
+        assert_eq!(regtest.network_type(), NetworkType::Regtest);
+        assert_eq!(regtest.hrp_sapling_payment_address(), "zregtestsapling");
+        assert_eq!(
+            regtest.activation_height(NetworkUpgrade::Nu5),
+            Some(BlockHeight::from_u32(1))
+        );
+
+        let testnet = NetworkParams::Testnet;
+        assert_eq!(testnet.hrp_sapling_payment_address(), "ztestsapling");
+        assert_eq!(
+            testnet.activation_height(NetworkUpgrade::Sapling),
+            TEST_NETWORK.activation_height(NetworkUpgrade::Sapling)
+        );
+        assert_eq!(Network::from(regtest), Network::Regtest);
+    }
+
+    #[test]
+    fn test_regtest_upgrade_heights() {
+        let upgrades = RegtestUpgrades::none()
+            .with_activation(NetworkUpgrade::Overwinter, 1)
+            .unwrap()
+            .with_activation(NetworkUpgrade::Canopy, 1)
+            .unwrap()
+            .with_branch_activation(0xc2d6d0b4, 5)
+            .unwrap()
+            .with_activation(NetworkUpgrade::Canopy, 2)
+            .unwrap();
+        let regtest = NetworkParams::Regtest(upgrades);
+        assert_eq!(
+            regtest.activation_height(NetworkUpgrade::Nu5),
+            Some(BlockHeight::from_u32(5))
+        );
+        assert_eq!(
+            regtest.activation_height(NetworkUpgrade::Canopy),
+            Some(BlockHeight::from_u32(2))
+        );
+        assert_eq!(regtest.activation_height(NetworkUpgrade::Sapling), None);
+        assert_eq!(Network::from(regtest), Network::Regtest);
+
+        let mut full = RegtestUpgrades::none();
+        for branch_id in 0..MAX_REGTEST_UPGRADES as u32 {
+            full = full.with_branch_activation(branch_id, 1).unwrap();
+        }
+        assert!(full.with_activation(NetworkUpgrade::Nu5, 1).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! ZIP-321 payment request generation
+//!
+//! A [`PaymentRequest`] lists one or more payments and encodes them as a
+//! single `zcash:` URI, e.g. for a QR code that asks for a price and a tip
+//! to separate addresses. With one payment the address goes in the URI path
+//! (`zcash:<address>?amount=…`); with several, the parameters of payment
+//! *i* > 0 carry an index suffix (`address.1`, `amount.1`, `memo.1`, …) as
+//! specified by ZIP-321.
+
+use crate::error::{Error, Result};
+use crate::types::format_zec;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use zcash_address::ZcashAddress;
+use zcash_protocol::{PoolType, ShieldedProtocol};
+
+/// Maximum memo size in bytes
+const MAX_MEMO_SIZE: usize = 512;
+
+/// One payment of a [`PaymentRequest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestedPayment {
+    /// Recipient address
+    pub address: String,
+    /// Amount in zatoshis, or `None` to let the payer choose (e.g. a tip)
+    pub amount: Option<u64>,
+    /// Memo bytes (shielded recipients only)
+    pub memo: Option<Vec<u8>>,
+    /// Label for the recipient, shown by the payer's wallet
+    pub label: Option<String>,
+    /// Message shown to the payer
+    pub message: Option<String>,
+}
+
+impl RequestedPayment {
+    /// Create a payment of `amount` zatoshis to `address`
+    pub fn new(address: impl Into<String>, amount: Option<u64>) -> Self {
+        Self {
+            address: address.into(),
+            amount,
+            memo: None,
+            label: None,
+            message: None,
+        }
+    }
+
+    /// Attach a memo
+    pub fn with_memo(mut self, memo: impl Into<Vec<u8>>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    /// Attach a recipient label
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Attach a message for the payer
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+/// A ZIP-321 request for one or more payments
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub payments: Vec<RequestedPayment>,
+}
+
+impl PaymentRequest {
+    /// Create a request for the given payments
+    pub fn new(payments: Vec<RequestedPayment>) -> Self {
+        Self { payments }
+    }
+
+    /// Add a payment to the request
+    pub fn add_payment(&mut self, payment: RequestedPayment) -> &mut Self {
+        self.payments.push(payment);
+        self
+    }
+
+    /// Total of the fixed amounts in zatoshis
+    pub fn total(&self) -> u64 {
+        self.payments.iter().filter_map(|p| p.amount).sum()
+    }
+
+    /// Encode the request as a ZIP-321 URI
+    ///
+    /// Fails if the request is empty, an address does not parse, or a memo
+    /// is too long or sent to an address that cannot receive memos.
+    pub fn to_uri(&self) -> Result<String> {
+        if self.payments.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Payment request has no payments".to_string(),
+            ));
+        }
+        for (idx, payment) in self.payments.iter().enumerate() {
+            validate_payment(idx, payment)?;
+        }
+
+        if let [payment] = self.payments.as_slice() {
+            let params = payment_params(payment, "");
+            return Ok(if params.is_empty() {
+                format!("zcash:{}", payment.address)
+            } else {
+                format!("zcash:{}?{}", payment.address, params.join("&"))
+            });
+        }
+
+        let params: Vec<String> = self
+            .payments
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, payment)| {
+                let suffix = if idx == 0 {
+                    String::new()
+                } else {
+                    format!(".{}", idx)
+                };
+                std::iter::once(format!("address{}={}", suffix, payment.address))
+                    .chain(payment_params(payment, &suffix))
+            })
+            .collect();
+        Ok(format!("zcash:?{}", params.join("&")))
+    }
+}
+
+fn validate_payment(idx: usize, payment: &RequestedPayment) -> Result<()> {
+    let address = payment
+        .address
+        .parse::<ZcashAddress>()
+        .map_err(|e| Error::Address(format!("Payment {} has invalid address: {}", idx, e)))?;
+    if let Some(memo) = &payment.memo {
+        if memo.len() > MAX_MEMO_SIZE {
+            return Err(Error::InvalidParameter(format!(
+                "Payment {} has memo exceeding {} bytes: {} bytes",
+                idx,
+                MAX_MEMO_SIZE,
+                memo.len()
+            )));
+        }
+        let shielded = address.can_receive_as(PoolType::Shielded(ShieldedProtocol::Sapling))
+            || address.can_receive_as(PoolType::Shielded(ShieldedProtocol::Orchard));
+        if !shielded {
+            return Err(Error::InvalidParameter(format!(
+                "Payment {} includes memo but recipient address is transparent",
+                idx
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Query parameters of a payment other than its address
+fn payment_params(payment: &RequestedPayment, suffix: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    if let Some(amount) = payment.amount {
+        params.push(format!("amount{}={}", suffix, format_zec(amount)));
+    }
+    if let Some(memo) = &payment.memo {
+        params.push(format!(
+            "memo{}={}",
+            suffix,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(memo)
+        ));
+    }
+    if let Some(label) = &payment.label {
+        params.push(format!("label{}={}", suffix, percent_encode(label)));
+    }
+    if let Some(message) = &payment.message {
+        params.push(format!("message{}={}", suffix, percent_encode(message)));
+    }
+    params
+}
+
+/// Percent-encode everything outside the ZIP-321 `qchar` set
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'!'
+            | b'$'
+            | b'\''
+            | b'('
+            | b')'
+            | b'*'
+            | b'+'
+            | b','
+            | b';'
+            | b':'
+            | b'@' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAPLING: &str =
+        "ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez";
+    const TRANSPARENT: &str = "tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU";
+
+    #[test]
+    fn test_single_payment_uri() {
+        let request = PaymentRequest::new(vec![RequestedPayment::new(SAPLING, Some(100_000_000))
+            .with_memo("This is a simple memo.")
+            .with_message("Thank you for your purchase")]);
+        assert_eq!(
+            request.to_uri().unwrap(),
+            format!(
+                "zcash:{}?amount=1&memo=VGhpcyBpcyBhIHNpbXBsZSBtZW1vLg\
+                 &message=Thank%20you%20for%20your%20purchase",
+                SAPLING
+            )
+        );
+    }
+
+    #[test]
+    fn test_multi_payment_uri() {
+        let mut request = PaymentRequest::default();
+        request
+            .add_payment(RequestedPayment::new(TRANSPARENT, Some(12_345_600_000)))
+            .add_payment(
+                RequestedPayment::new(SAPLING, Some(78_900_000))
+                    .with_memo("This is a unicode memo ✨🦄🏆🎉"),
+            )
+            .add_payment(RequestedPayment::new(SAPLING, None).with_label("Tip jar"));
+        assert_eq!(request.total(), 12_424_500_000);
+
+        let uri = request.to_uri().unwrap();
+        assert_eq!(
+            uri,
+            format!(
+                "zcash:?address={t}&amount=123.456&address.1={z}&amount.1=0.789\
+                 &memo.1=VGhpcyBpcyBhIHVuaWNvZGUgbWVtbyDinKjwn6aE8J-PhvCfjok\
+                 &address.2={z}&label.2=Tip%20jar",
+                t = TRANSPARENT,
+                z = SAPLING
+            )
+        );
+        let parsed = zip321::TransactionRequest::from_uri(&uri).unwrap();
+        assert_eq!(parsed.payments().len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        assert!(PaymentRequest::default().to_uri().is_err());
+        let memo_to_transparent = RequestedPayment::new(TRANSPARENT, Some(1)).with_memo("hi");
+        assert!(PaymentRequest::new(vec![memo_to_transparent])
+            .to_uri()
+            .is_err());
+        let bad_address = RequestedPayment::new("zs1nope", Some(1));
+        assert!(PaymentRequest::new(vec![bad_address]).to_uri().is_err());
+    }
+}
@@ -0,0 +1,708 @@
+//! Spending policy engine
+//!
+//! Operator-defined limits evaluated before every send:
+//! - Maximum amount per transaction
+//! - Aggregate limit over a rolling 24 hour window
+//! - Recipient allowlist
+//! - Minimum privacy policy for zcashd sends
+//! - Duplicate payment detection: a payment to the same recipient with the
+//!   same amount and memo as one submitted within a configured window, as
+//!   when a payout file is submitted twice, is warned about or blocked
+//!
+//! Policies are configured per ZIP-32 account. Violations are returned as
+//! [`Error::PolicyViolation`] and recorded in the [`AuditLog`], which also
+//! provides the send history for the daily limit.
+//!
+//! An engine opened with [`SpendingPolicyEngine::for_wallet`] stores its
+//! policies in the wallet database. Sends that do not go through a
+//! [`TransactionBuilder`](crate::transaction::TransactionBuilder) are
+//! evaluated against those stored policies and recorded in the same audit
+//! log: [`signer::send_with_signer`](crate::signer), the local builder of
+//! [`airgap::create_signing_request`](crate::airgap) and the sweeps of
+//! [`migrate_wallet`](crate::migration::migrate_wallet).
+
+use crate::audit::{AuditEvent, AuditLog, AuditPayment};
+use crate::correlation::CorrelationId;
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::rpc::{Payment, PrivacyPolicy};
+use crate::store::{db_error, unix_now};
+use crate::types::utils::{zatoshis_to_zec, zec_to_zatoshis};
+use crate::wallet::{memo_text, Wallet};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use thiserror::Error as ThisError;
+
+/// Length of the aggregate limit window in seconds
+pub const DAILY_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+const DB_CONTEXT: &str = "Spending policy error";
+
+/// Account column of the stored default policy
+const DEFAULT_POLICY_ACCOUNT: i64 = -1;
+
+/// Why a send was blocked
+#[derive(ThisError, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PolicyViolation {
+    #[error("amount {amount} zat exceeds the per-transaction limit of {limit} zat")]
+    AmountExceedsLimit { amount: u64, limit: u64 },
+
+    #[error("amount {amount} zat would bring the 24h total to {total} zat, above the limit of {limit} zat")]
+    DailyLimitExceeded { amount: u64, total: u64, limit: u64 },
+
+    #[error("recipient {address} is not on the allowlist")]
+    RecipientNotAllowed { address: String },
+
+    #[error("privacy policy {requested:?} is weaker than the required {required:?}")]
+    InsufficientPrivacy {
+        requested: PrivacyPolicy,
+        required: PrivacyPolicy,
+    },
+
+    #[error("payment of {} zat to {} repeats one submitted at {}", .0.amount, .0.address, .0.previous_submitted_at)]
+    DuplicatePayment(DuplicatePayment),
+
+    #[error("amount {amount} zat reaches the co-approval threshold of {threshold} zat; send it through an approved proposal")]
+    ApprovalRequired { amount: u64, threshold: u64 },
+}
+
+/// A payment matching one submitted within the duplicate window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicatePayment {
+    pub address: String,
+    /// Amount in zatoshis
+    pub amount: u64,
+    pub memo: Option<String>,
+    /// Unix time the earlier payment was submitted
+    pub previous_submitted_at: u64,
+    /// Operation ID of the earlier send, if known
+    pub previous_operation_id: Option<String>,
+}
+
+/// Limits applied to sends from one account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendingPolicy {
+    /// Maximum total of a single send (zatoshis)
+    pub max_per_tx: Option<u64>,
+    /// Maximum total of all sends in a rolling 24h window (zatoshis)
+    pub daily_limit: Option<u64>,
+    /// If set, only these recipient addresses may be paid
+    pub allowed_recipients: Option<HashSet<String>>,
+    /// Weakest privacy policy zcashd may use for sends
+    pub required_privacy: Option<PrivacyPolicy>,
+    /// Seconds within which a repeated payment counts as a duplicate
+    #[serde(default)]
+    pub duplicate_window: Option<u64>,
+    /// Block duplicate payments instead of only warning about them
+    #[serde(default)]
+    pub block_duplicates: bool,
+}
+
+impl SpendingPolicy {
+    /// A policy with no restrictions
+    pub fn unrestricted() -> Self {
+        Self::default()
+    }
+
+    /// Limit the total of a single send
+    pub fn with_max_per_tx(mut self, zatoshis: u64) -> Self {
+        self.max_per_tx = Some(zatoshis);
+        self
+    }
+
+    /// Limit the total of all sends in a rolling 24h window
+    pub fn with_daily_limit(mut self, zatoshis: u64) -> Self {
+        self.daily_limit = Some(zatoshis);
+        self
+    }
+
+    /// Only allow payments to the given addresses
+    pub fn with_allowed_recipients<I, S>(mut self, addresses: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_recipients = Some(addresses.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Require zcashd sends to use this privacy policy or a stricter one
+    pub fn with_required_privacy(mut self, policy: PrivacyPolicy) -> Self {
+        self.required_privacy = Some(policy);
+        self
+    }
+
+    /// Detect payments repeating one submitted in the last `window_secs`
+    ///
+    /// Duplicates are logged and recorded in the audit log; with `block`
+    /// they are rejected as [`PolicyViolation::DuplicatePayment`].
+    pub fn with_duplicate_detection(mut self, window_secs: u64, block: bool) -> Self {
+        self.duplicate_window = Some(window_secs);
+        self.block_duplicates = block;
+        self
+    }
+}
+
+/// How much a privacy policy allows zcashd to reveal (higher reveals more)
+fn leakage_rank(policy: PrivacyPolicy) -> u8 {
+    match policy {
+        PrivacyPolicy::FullPrivacy => 0,
+        PrivacyPolicy::AllowRevealedAmounts => 1,
+        PrivacyPolicy::AllowRevealedRecipients => 2,
+        PrivacyPolicy::AllowRevealedSenders => 3,
+        PrivacyPolicy::AllowFullyTransparent => 4,
+        PrivacyPolicy::AllowLinkingAccountAddresses => 5,
+        PrivacyPolicy::NoPrivacy => 6,
+    }
+}
+
+/// Amount of a payment in zatoshis
+fn payment_zatoshis(payment: &Payment) -> u64 {
+    zec_to_zatoshis(payment.amount)
+}
+
+/// Total of a set of payments in zatoshis
+pub fn payments_total(payments: &[Payment]) -> u64 {
+    payments.iter().map(payment_zatoshis).sum()
+}
+
+/// Payments of a ZIP-321 request, for evaluation and the audit log
+///
+/// Memos that are not text are left out.
+pub(crate) fn request_payments(request: &zip321::TransactionRequest) -> Vec<Payment> {
+    request
+        .payments()
+        .values()
+        .map(|payment| Payment {
+            address: payment.recipient_address().encode(),
+            amount: zatoshis_to_zec(payment.amount().map_or(0, u64::from)),
+            memo: payment.memo().and_then(|memo| memo_text(memo.as_slice())),
+        })
+        .collect()
+}
+
+/// Evaluates sends against per-account spending policies
+pub struct SpendingPolicyEngine {
+    default_policy: SpendingPolicy,
+    accounts: HashMap<u32, SpendingPolicy>,
+    audit: AuditLog,
+    /// Database the policies are stored in, if opened from one
+    store: Option<Connection>,
+}
+
+impl SpendingPolicyEngine {
+    /// Create an engine with no restrictions that records to `audit`
+    ///
+    /// Its policies are only kept in memory; see [`open`](Self::open).
+    pub fn new(audit: AuditLog) -> Self {
+        Self {
+            default_policy: SpendingPolicy::default(),
+            accounts: HashMap::new(),
+            audit,
+            store: None,
+        }
+    }
+
+    /// Open (or create) the engine stored in the SQLite database at `path`
+    ///
+    /// Policies set with [`set_default_policy`](Self::set_default_policy)
+    /// and [`set_account_policy`](Self::set_account_policy) are stored in
+    /// the database, and sends are recorded in the audit log of the same
+    /// database.
+    pub fn open(path: &Path) -> Result<Self> {
+        let audit = AuditLog::open(path)?;
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_spending_policies (
+                account INTEGER PRIMARY KEY,
+                policy TEXT NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+
+        let mut engine = Self::new(audit);
+        let mut stmt = conn
+            .prepare("SELECT account, policy FROM numi_spending_policies")
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(db_error(DB_CONTEXT))?;
+        for row in rows {
+            let (account, json) = row.map_err(db_error(DB_CONTEXT))?;
+            let policy: SpendingPolicy = serde_json::from_str(&json)?;
+            match u32::try_from(account) {
+                Ok(account) => {
+                    engine.accounts.insert(account, policy);
+                }
+                Err(_) => engine.default_policy = policy,
+            }
+        }
+        drop(stmt);
+
+        engine.store = Some(conn);
+        Ok(engine)
+    }
+
+    /// Open the engine stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Set the policy for accounts without their own policy
+    ///
+    /// The policy is not stored; use
+    /// [`set_default_policy`](Self::set_default_policy) for that.
+    pub fn with_default_policy(mut self, policy: SpendingPolicy) -> Self {
+        self.default_policy = policy;
+        self
+    }
+
+    /// Set and store the policy for accounts without their own policy
+    pub fn set_default_policy(&mut self, policy: SpendingPolicy) -> Result<()> {
+        self.store_policy(DEFAULT_POLICY_ACCOUNT, &policy)?;
+        self.default_policy = policy;
+        Ok(())
+    }
+
+    /// Set the policy for one account
+    ///
+    /// The policy is stored if the engine was opened from a database.
+    pub fn set_account_policy(&mut self, account: u32, policy: SpendingPolicy) -> Result<()> {
+        self.store_policy(i64::from(account), &policy)?;
+        self.accounts.insert(account, policy);
+        Ok(())
+    }
+
+    fn store_policy(&self, account: i64, policy: &SpendingPolicy) -> Result<()> {
+        let Some(conn) = &self.store else {
+            return Ok(());
+        };
+        conn.execute(
+            "INSERT INTO numi_spending_policies (account, policy) VALUES (?1, ?2)
+             ON CONFLICT(account) DO UPDATE SET policy = excluded.policy",
+            params![account, serde_json::to_string(policy)?],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// The policy that applies to `account`
+    pub fn policy_for(&self, account: u32) -> &SpendingPolicy {
+        self.accounts.get(&account).unwrap_or(&self.default_policy)
+    }
+
+    /// The audit log used by this engine
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Privacy policy a send should use: the requested one, or the required minimum
+    pub fn effective_privacy(
+        &self,
+        account: u32,
+        requested: Option<PrivacyPolicy>,
+    ) -> Option<PrivacyPolicy> {
+        requested.or(self.policy_for(account).required_privacy)
+    }
+
+    /// Check a send against the account policy without recording anything
+    ///
+    /// # Arguments
+    /// * `account` - ZIP-32 account index of the sender
+    /// * `payments` - Payments in the send
+    /// * `privacy` - Privacy policy the send will use (`None` for the node default)
+    /// * `now` - Current time (unix seconds), for the daily window
+    pub fn check(
+        &self,
+        account: u32,
+        payments: &[Payment],
+        privacy: Option<PrivacyPolicy>,
+        now: u64,
+    ) -> Result<std::result::Result<(), PolicyViolation>> {
+        let policy = self.policy_for(account);
+        let amount = payments_total(payments);
+
+        if let Some(limit) = policy.max_per_tx {
+            if amount > limit {
+                return Ok(Err(PolicyViolation::AmountExceedsLimit { amount, limit }));
+            }
+        }
+
+        if let Some(allowed) = &policy.allowed_recipients {
+            if let Some(payment) = payments.iter().find(|p| !allowed.contains(&p.address)) {
+                return Ok(Err(PolicyViolation::RecipientNotAllowed {
+                    address: payment.address.clone(),
+                }));
+            }
+        }
+
+        if let (Some(required), Some(requested)) = (policy.required_privacy, privacy) {
+            if leakage_rank(requested) > leakage_rank(required) {
+                return Ok(Err(PolicyViolation::InsufficientPrivacy {
+                    requested,
+                    required,
+                }));
+            }
+        }
+
+        if policy.block_duplicates {
+            if let Some(duplicate) = self.duplicate_payments(account, payments, now)?.pop() {
+                return Ok(Err(PolicyViolation::DuplicatePayment(duplicate)));
+            }
+        }
+
+        if let Some(limit) = policy.daily_limit {
+            let since = now.saturating_sub(DAILY_WINDOW_SECS);
+            let total = self
+                .audit
+                .submitted_total_since(account, since)?
+                .saturating_add(amount);
+            if total > limit {
+                return Ok(Err(PolicyViolation::DailyLimitExceeded {
+                    amount,
+                    total,
+                    limit,
+                }));
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// Payments of a send that repeat one submitted within the account's
+    /// duplicate window
+    ///
+    /// Empty if the account's policy has no duplicate detection.
+    ///
+    /// # Arguments
+    /// * `now` - Current time (unix seconds), for the window
+    pub fn duplicate_payments(
+        &self,
+        account: u32,
+        payments: &[Payment],
+        now: u64,
+    ) -> Result<Vec<DuplicatePayment>> {
+        let Some(window) = self.policy_for(account).duplicate_window else {
+            return Ok(Vec::new());
+        };
+        let recent = self
+            .audit
+            .entries_since(account, now.saturating_sub(window))?;
+
+        let mut duplicates = Vec::new();
+        for payment in payments {
+            let amount = payment_zatoshis(payment);
+            // Most recent matching send
+            let previous = recent.iter().rev().find_map(|entry| match &entry.event {
+                AuditEvent::SendSubmitted {
+                    payments,
+                    operation_id,
+                    ..
+                } => payments
+                    .iter()
+                    .any(|p| {
+                        p.address == payment.address && p.amount == amount && p.memo == payment.memo
+                    })
+                    .then(|| (entry.timestamp, operation_id.clone())),
+                _ => None,
+            });
+            if let Some((previous_submitted_at, previous_operation_id)) = previous {
+                duplicates.push(DuplicatePayment {
+                    address: payment.address.clone(),
+                    amount,
+                    memo: payment.memo.clone(),
+                    previous_submitted_at,
+                    previous_operation_id,
+                });
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Evaluate a send, recording and returning any violation
+    ///
+    /// Audit entries are recorded with the send's `correlation_id`, if any.
+    ///
+    /// # Returns
+    /// `Ok(())` if the send is allowed, otherwise [`Error::PolicyViolation`]
+    pub fn evaluate(
+        &self,
+        account: u32,
+        from_address: &str,
+        payments: &[Payment],
+        privacy: Option<PrivacyPolicy>,
+        correlation_id: Option<&CorrelationId>,
+    ) -> Result<()> {
+        self.evaluate_with_warnings(account, from_address, payments, privacy, correlation_id)
+            .map(|_| ())
+    }
+
+    /// Evaluate a send like [`evaluate`](Self::evaluate), also returning the
+    /// duplicate payments it was allowed with
+    ///
+    /// Each duplicate is logged and recorded in the audit log, so callers
+    /// can ask for confirmation before broadcasting.
+    pub fn evaluate_with_warnings(
+        &self,
+        account: u32,
+        from_address: &str,
+        payments: &[Payment],
+        privacy: Option<PrivacyPolicy>,
+        correlation_id: Option<&CorrelationId>,
+    ) -> Result<Vec<DuplicatePayment>> {
+        let now = unix_now();
+        if let Err(violation) = self.check(account, payments, privacy, now)? {
+            tracing::warn!("Send from account {} blocked: {}", account, violation);
+            self.audit.record_correlated(
+                account,
+                AuditEvent::PolicyViolation {
+                    from_address: from_address.to_string(),
+                    amount: payments_total(payments),
+                    violation: violation.to_string(),
+                },
+                correlation_id,
+            )?;
+            return Err(Error::PolicyViolation(violation));
+        }
+
+        let duplicates = self.duplicate_payments(account, payments, now)?;
+        for duplicate in &duplicates {
+            tracing::warn!(
+                "Payment of {} zat to {} from account {} repeats one submitted at {}",
+                duplicate.amount,
+                duplicate.address,
+                account,
+                duplicate.previous_submitted_at
+            );
+            self.audit.record_correlated(
+                account,
+                AuditEvent::DuplicatePaymentWarning {
+                    from_address: from_address.to_string(),
+                    address: duplicate.address.clone(),
+                    amount: duplicate.amount,
+                },
+                correlation_id,
+            )?;
+        }
+        Ok(duplicates)
+    }
+
+    /// Record a submitted send so it counts towards the daily limit
+    pub fn record_submitted(
+        &self,
+        account: u32,
+        from_address: &str,
+        payments: &[Payment],
+        operation_id: Option<&str>,
+        correlation_id: Option<&CorrelationId>,
+    ) -> Result<()> {
+        self.audit.record_correlated(
+            account,
+            AuditEvent::SendSubmitted {
+                from_address: from_address.to_string(),
+                recipients: payments.iter().map(|p| p.address.clone()).collect(),
+                amount: payments_total(payments),
+                operation_id: operation_id.map(str::to_string),
+                payments: payments
+                    .iter()
+                    .map(|p| AuditPayment {
+                        address: p.address.clone(),
+                        amount: payment_zatoshis(p),
+                        memo: p.memo.clone(),
+                    })
+                    .collect(),
+            },
+            correlation_id,
+        )?;
+        Ok(())
+    }
+
+    /// Record an allowed send that the node or light client did not accept
+    pub fn record_failed(
+        &self,
+        account: u32,
+        from_address: &str,
+        payments: &[Payment],
+        reason: &str,
+        correlation_id: Option<&CorrelationId>,
+    ) -> Result<()> {
+        self.audit.record_correlated(
+            account,
+            AuditEvent::SendFailed {
+                from_address: from_address.to_string(),
+                amount: payments_total(payments),
+                reason: reason.to_string(),
+            },
+            correlation_id,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn engine(policy: SpendingPolicy) -> (TempDb, SpendingPolicyEngine) {
+        let path = TempDb::new();
+        let engine =
+            SpendingPolicyEngine::new(AuditLog::open(&path).unwrap()).with_default_policy(policy);
+        (path, engine)
+    }
+
+    fn pay(address: &str, amount: f64) -> Vec<Payment> {
+        vec![Payment {
+            address: address.to_string(),
+            amount,
+            memo: None,
+        }]
+    }
+
+    #[test]
+    fn test_per_tx_and_allowlist() {
+        let (_db, engine) = engine(
+            SpendingPolicy::unrestricted()
+                .with_max_per_tx(100_000_000)
+                .with_allowed_recipients(["u1ok"]),
+        );
+        assert_eq!(engine.check(0, &pay("u1ok", 1.0), None, 0).unwrap(), Ok(()));
+        assert!(matches!(
+            engine.check(0, &pay("u1ok", 1.5), None, 0).unwrap(),
+            Err(PolicyViolation::AmountExceedsLimit { .. })
+        ));
+        assert!(matches!(
+            engine.evaluate(0, "u1from", &pay("u1other", 0.1), None, None),
+            Err(Error::PolicyViolation(
+                PolicyViolation::RecipientNotAllowed { .. }
+            ))
+        ));
+        assert_eq!(engine.audit_log().entries_since(0, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_payments() {
+        let (_db, engine) =
+            engine(SpendingPolicy::unrestricted().with_duplicate_detection(3_600, false));
+        let payout = vec![
+            Payment {
+                address: "u1alice".to_string(),
+                amount: 0.5,
+                memo: Some("payout 7".to_string()),
+            },
+            Payment {
+                address: "u1bob".to_string(),
+                amount: 0.25,
+                memo: None,
+            },
+        ];
+        assert!(engine
+            .evaluate_with_warnings(0, "u1from", &payout, None, None)
+            .unwrap()
+            .is_empty());
+        engine
+            .record_submitted(0, "u1from", &payout, Some("opid-1"), None)
+            .unwrap();
+
+        // Resubmitting the payout file warns about both payments
+        let duplicates = engine
+            .evaluate_with_warnings(0, "u1from", &payout, None, None)
+            .unwrap();
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates[0].amount, 50_000_000);
+        assert_eq!(
+            duplicates[0].previous_operation_id.as_deref(),
+            Some("opid-1")
+        );
+
+        // A different memo or amount is not a duplicate
+        let mut next = payout[..1].to_vec();
+        next[0].memo = Some("payout 8".to_string());
+        assert!(engine
+            .duplicate_payments(0, &next, unix_now())
+            .unwrap()
+            .is_empty());
+        // Outside the window
+        assert!(engine
+            .duplicate_payments(0, &payout, unix_now() + 7_200)
+            .unwrap()
+            .is_empty());
+
+        let mut engine = engine;
+        engine
+            .set_account_policy(
+                0,
+                SpendingPolicy::unrestricted().with_duplicate_detection(3_600, true),
+            )
+            .unwrap();
+        assert!(matches!(
+            engine.evaluate(0, "u1from", &payout, None, None),
+            Err(Error::PolicyViolation(PolicyViolation::DuplicatePayment(_)))
+        ));
+    }
+
+    #[test]
+    fn test_daily_limit_and_privacy() {
+        let (_db, engine) = engine(
+            SpendingPolicy::unrestricted()
+                .with_daily_limit(150_000_000)
+                .with_required_privacy(PrivacyPolicy::AllowRevealedAmounts),
+        );
+        engine
+            .record_submitted(0, "u1from", &pay("u1to", 1.0), Some("opid"), None)
+            .unwrap();
+        assert!(matches!(
+            engine
+                .check(0, &pay("u1to", 1.0), None, unix_now())
+                .unwrap(),
+            Err(PolicyViolation::DailyLimitExceeded { .. })
+        ));
+        // Other accounts have their own history
+        assert_eq!(
+            engine
+                .check(1, &pay("u1to", 1.0), None, unix_now())
+                .unwrap(),
+            Ok(())
+        );
+
+        assert!(matches!(
+            engine
+                .check(1, &pay("u1to", 0.1), Some(PrivacyPolicy::NoPrivacy), 0)
+                .unwrap(),
+            Err(PolicyViolation::InsufficientPrivacy { .. })
+        ));
+        assert_eq!(
+            engine.effective_privacy(1, None),
+            Some(PrivacyPolicy::AllowRevealedAmounts)
+        );
+    }
+
+    #[test]
+    fn test_policies_stored() {
+        let path = TempDb::new();
+        let mut engine = SpendingPolicyEngine::open(&path).unwrap();
+        engine
+            .set_default_policy(SpendingPolicy::unrestricted().with_max_per_tx(100))
+            .unwrap();
+        engine
+            .set_account_policy(2, SpendingPolicy::unrestricted().with_daily_limit(500))
+            .unwrap();
+        engine
+            .record_submitted(2, "u1from", &pay("u1to", 0.000004), None, None)
+            .unwrap();
+
+        let engine = SpendingPolicyEngine::open(&path).unwrap();
+        assert_eq!(engine.policy_for(0).max_per_tx, Some(100));
+        assert_eq!(engine.policy_for(2).daily_limit, Some(500));
+        assert!(matches!(
+            engine
+                .check(2, &pay("u1to", 0.000002), None, unix_now())
+                .unwrap(),
+            Err(PolicyViolation::DailyLimitExceeded { total: 600, .. })
+        ));
+    }
+}
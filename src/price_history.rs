@@ -0,0 +1,303 @@
+//! Historical fiat values of transactions
+//!
+//! Tax and audit reports need the fiat value of each transaction at the
+//! time it happened, not at the time the report is run. A [`PriceBackfill`]
+//! asks a [`PriceProvider`] for the ZEC price at each transaction's block
+//! time and stores the result in a `numi_fiat_values` table of the wallet
+//! database, so history is priced once and later runs only price new
+//! transactions. [`compliance::export_transactions_csv_with_fiat`] adds the
+//! stored values to the audit CSV.
+//!
+//! Providers are pluggable: implement [`PriceProvider`] over an exchange or
+//! market data API, or a local price file. Pending transactions, which have
+//! no block time yet, are skipped until they are mined.
+//!
+//! [`compliance::export_transactions_csv_with_fiat`]: crate::compliance::export_transactions_csv_with_fiat
+
+use crate::db_encryption::open_connection;
+use crate::error::Result;
+use crate::store::db_error;
+use crate::types::Transaction;
+use crate::wallet::Wallet;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+const ZATOSHIS_PER_ZEC: f64 = 100_000_000.0;
+
+/// Source of historical ZEC prices
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    /// Name stored with each value, e.g. the API's host name
+    fn name(&self) -> &str;
+
+    /// Price of one ZEC in a fiat currency at a point in time
+    ///
+    /// # Arguments
+    /// * `currency` - ISO 4217 code, e.g. `USD`
+    /// * `time` - Unix time, in seconds
+    ///
+    /// # Returns
+    /// The price, or `None` if the provider has none for that time
+    async fn price_at(&self, currency: &str, time: u64) -> Result<Option<f64>>;
+}
+
+/// Fiat value of a transaction at its block time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FiatValue {
+    pub txid: String,
+    /// ISO 4217 code
+    pub currency: String,
+    /// Price of one ZEC
+    pub price: f64,
+    /// Value of the transaction's net amount (negative for sends)
+    pub value: f64,
+    /// Block time the price is for (Unix seconds)
+    pub time: u64,
+    /// [`PriceProvider::name`] of the provider that priced it
+    pub source: String,
+}
+
+const DB_CONTEXT: &str = "Price history error";
+
+/// Fiat values stored in a wallet database
+pub struct PriceHistory {
+    conn: Connection,
+}
+
+impl PriceHistory {
+    /// Open (or create) the fiat values in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_fiat_values (
+                txid TEXT NOT NULL,
+                currency TEXT NOT NULL,
+                price REAL NOT NULL,
+                value REAL NOT NULL,
+                time INTEGER NOT NULL,
+                source TEXT NOT NULL,
+                PRIMARY KEY (txid, currency)
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    /// Open the fiat values stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Store a fiat value, replacing any earlier value of the transaction in
+    /// the same currency
+    pub fn record(&self, value: &FiatValue) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO numi_fiat_values
+                 (txid, currency, price, value, time, source)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    value.txid,
+                    value.currency,
+                    value.price,
+                    value.value,
+                    value.time as i64,
+                    value.source
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Fiat value of a transaction in a currency, if priced
+    pub fn get(&self, txid: &str, currency: &str) -> Result<Option<FiatValue>> {
+        self.conn
+            .query_row(
+                "SELECT txid, currency, price, value, time, source FROM numi_fiat_values
+                 WHERE txid = ?1 AND currency = ?2",
+                params![txid, currency],
+                read_value,
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// All fiat values in a currency, by txid
+    pub fn values(&self, currency: &str) -> Result<HashMap<String, FiatValue>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT txid, currency, price, value, time, source FROM numi_fiat_values
+                 WHERE currency = ?1",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let values = stmt
+            .query_map([currency], read_value)
+            .map_err(db_error(DB_CONTEXT))?
+            .map(|value| value.map(|value| (value.txid.clone(), value)))
+            .collect::<rusqlite::Result<HashMap<_, _>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(values)
+    }
+}
+
+fn read_value(row: &rusqlite::Row<'_>) -> rusqlite::Result<FiatValue> {
+    Ok(FiatValue {
+        txid: row.get(0)?,
+        currency: row.get(1)?,
+        price: row.get(2)?,
+        value: row.get(3)?,
+        time: row.get::<_, i64>(4)? as u64,
+        source: row.get(5)?,
+    })
+}
+
+/// Outcome of a [`PriceBackfill`] run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillReport {
+    /// Transactions priced by this run
+    pub priced: usize,
+    /// Transactions that already had a value in the currency
+    pub already_priced: usize,
+    /// Transactions without a block time yet
+    pub pending: usize,
+    /// Transactions the provider had no price for; retried on the next run
+    pub unavailable: Vec<String>,
+}
+
+/// Job annotating transaction history with fiat values
+pub struct PriceBackfill {
+    provider: Arc<dyn PriceProvider>,
+    currency: String,
+}
+
+impl PriceBackfill {
+    /// Price transactions in `currency` (ISO 4217, e.g. `USD`) with `provider`
+    pub fn new(provider: Arc<dyn PriceProvider>, currency: impl Into<String>) -> Self {
+        Self {
+            provider,
+            currency: currency.into().to_uppercase(),
+        }
+    }
+
+    /// Price the selected account's transactions that have no value yet
+    ///
+    /// # Returns
+    /// What was priced, skipped or could not be priced
+    pub async fn run(&self, wallet: &Wallet) -> Result<BackfillReport> {
+        let history = PriceHistory::for_wallet(wallet)?;
+        self.backfill(&history, &wallet.get_transactions(None)?)
+            .await
+    }
+
+    /// Price the given transactions that have no value in `history` yet
+    ///
+    /// Provider errors abort the run; values stored until then are kept.
+    pub async fn backfill(
+        &self,
+        history: &PriceHistory,
+        transactions: &[Transaction],
+    ) -> Result<BackfillReport> {
+        let priced = history.values(&self.currency)?;
+        let mut report = BackfillReport::default();
+        for tx in transactions {
+            if priced.contains_key(&tx.txid) {
+                report.already_priced += 1;
+                continue;
+            }
+            let Some(time) = tx.timestamp else {
+                report.pending += 1;
+                continue;
+            };
+            match self.provider.price_at(&self.currency, time).await? {
+                Some(price) => {
+                    history.record(&FiatValue {
+                        txid: tx.txid.clone(),
+                        currency: self.currency.clone(),
+                        price,
+                        value: tx.amount as f64 / ZATOSHIS_PER_ZEC * price,
+                        time,
+                        source: self.provider.name().to_string(),
+                    })?;
+                    report.priced += 1;
+                }
+                None => report.unavailable.push(tx.txid.clone()),
+            }
+        }
+        tracing::info!(
+            "Priced {} transactions in {} with {} ({} unavailable)",
+            report.priced,
+            self.currency,
+            self.provider.name(),
+            report.unavailable.len()
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+    use crate::types::TransactionStatus;
+
+    /// Prices from a fixed daily table
+    struct DailyPrices(HashMap<u64, f64>);
+
+    #[async_trait]
+    impl PriceProvider for DailyPrices {
+        fn name(&self) -> &str {
+            "daily table"
+        }
+
+        async fn price_at(&self, currency: &str, time: u64) -> Result<Option<f64>> {
+            assert_eq!(currency, "USD");
+            Ok(self.0.get(&(time / 86_400)).copied())
+        }
+    }
+
+    fn tx(txid: &str, amount: i64, timestamp: Option<u64>) -> Transaction {
+        Transaction {
+            txid: txid.to_string(),
+            status: match timestamp {
+                Some(_) => TransactionStatus::Confirmed { height: 100 },
+                None => TransactionStatus::Pending,
+            },
+            amount,
+            fee: 0,
+            memo: None,
+            timestamp,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill() {
+        let path = TempDb::new();
+        let history = PriceHistory::open(&path).unwrap();
+        let provider = Arc::new(DailyPrices(HashMap::from([(19_000, 40.0), (19_001, 50.0)])));
+        let backfill = PriceBackfill::new(provider, "usd");
+        let transactions = vec![
+            tx("a", 200_000_000, Some(19_000 * 86_400 + 60)),
+            tx("b", -50_000_000, Some(19_001 * 86_400)),
+            tx("c", 10_000, Some(30_000 * 86_400)),
+            tx("d", 10_000, None),
+        ];
+
+        let report = backfill.backfill(&history, &transactions).await.unwrap();
+        assert_eq!(report.priced, 2);
+        assert_eq!(report.pending, 1);
+        assert_eq!(report.unavailable, vec!["c".to_string()]);
+        let sent = history.get("b", "USD").unwrap().unwrap();
+        assert_eq!((sent.price, sent.value), (50.0, -25.0));
+        assert_eq!(history.values("USD").unwrap()["a"].value, 80.0);
+        assert!(history.get("a", "EUR").unwrap().is_none());
+
+        // Later runs only price what is still missing
+        let report = backfill.backfill(&history, &transactions).await.unwrap();
+        assert_eq!((report.priced, report.already_priced), (0, 2));
+    }
+}
@@ -0,0 +1,302 @@
+//! Connection presets for managed Zcash infrastructure
+//!
+//! Managed node providers each have their own endpoint URLs and their own way
+//! of passing an API key: in the URL path, or in a provider-specific header.
+//! A [`ProviderConnection`] assembles the endpoint and headers from a
+//! [`Provider`] and an API key, so a client can be created from a provider
+//! name and key alone:
+//!
+//! ```no_run
+//! use zcash_numi_sdk::providers::{Provider, ProviderConnection};
+//! use zcash_numi_sdk::types::Network;
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let provider: Provider = "nownodes".parse()?;
+//! let client = ProviderConnection::new(provider, Network::Mainnet)
+//!     .with_api_key("my-api-key")
+//!     .rpc_client()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Note
+//! Endpoints and key schemes follow each provider's documentation at the time
+//! of writing. Providers change them; check the provider's current
+//! documentation if a connection is refused.
+
+use crate::client::RpcClient;
+use crate::error::{Error, Result};
+use crate::headers::RequestHeaders;
+use crate::light_client::LightClient;
+use crate::types::Network;
+use crate::wallet::Wallet;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A managed Zcash infrastructure provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// GetBlock zcashd-compatible RPC; the access token is part of the URL
+    GetBlock,
+    /// NOWNodes zcashd-compatible RPC; the key is sent in an `api-key` header
+    NowNodes,
+    /// Tatum RPC gateway; the key is sent in an `x-api-key` header
+    Tatum,
+    /// Zec.rocks public lightwalletd servers on port 443; no key required
+    ZecRocks,
+}
+
+/// How a provider expects the API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyScheme {
+    /// No key is used
+    None,
+    /// The key is appended to the endpoint URL as a path segment
+    UrlPath,
+    /// The key is sent in the named header
+    Header(&'static str),
+}
+
+impl Provider {
+    /// Every supported provider
+    pub const ALL: [Provider; 4] = [
+        Provider::GetBlock,
+        Provider::NowNodes,
+        Provider::Tatum,
+        Provider::ZecRocks,
+    ];
+
+    /// Lowercase name, as accepted by [`FromStr`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::GetBlock => "getblock",
+            Provider::NowNodes => "nownodes",
+            Provider::Tatum => "tatum",
+            Provider::ZecRocks => "zecrocks",
+        }
+    }
+
+    /// How the provider expects the API key
+    pub fn key_scheme(&self) -> KeyScheme {
+        match self {
+            Provider::GetBlock => KeyScheme::UrlPath,
+            Provider::NowNodes => KeyScheme::Header("api-key"),
+            Provider::Tatum => KeyScheme::Header("x-api-key"),
+            Provider::ZecRocks => KeyScheme::None,
+        }
+    }
+
+    /// zcashd-compatible JSON-RPC endpoint, without the API key
+    ///
+    /// # Returns
+    /// `None` if the provider has no RPC service on the network
+    pub fn rpc_url(&self, network: Network) -> Option<&'static str> {
+        match (self, network) {
+            (Provider::GetBlock, Network::Mainnet) => Some("https://go.getblock.io"),
+            (Provider::NowNodes, Network::Mainnet) => Some("https://zec.nownodes.io"),
+            (Provider::Tatum, Network::Mainnet) => Some("https://zcash-mainnet.gateway.tatum.io"),
+            _ => None,
+        }
+    }
+
+    /// lightwalletd gRPC endpoint
+    ///
+    /// # Returns
+    /// `None` if the provider has no lightwalletd service on the network
+    pub fn lightwalletd_url(&self, network: Network) -> Option<&'static str> {
+        match (self, network) {
+            (Provider::ZecRocks, Network::Mainnet) => Some("https://zec.rocks:443"),
+            (Provider::ZecRocks, Network::Testnet) => Some("https://testnet.zec.rocks:443"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Provider {
+    type Err = Error;
+
+    /// Parse a provider name, ignoring case, dashes and dots
+    /// (`"NOWNodes"`, `"zec.rocks"`)
+    fn from_str(name: &str) -> Result<Self> {
+        let normalized: String = name
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_' | '.' | ' '))
+            .collect::<String>()
+            .to_ascii_lowercase();
+        Provider::ALL
+            .into_iter()
+            .find(|provider| provider.name() == normalized)
+            .ok_or_else(|| Error::InvalidParameter(format!("Unknown provider: {}", name)))
+    }
+}
+
+/// Settings for connecting to a provider
+pub struct ProviderConnection {
+    provider: Provider,
+    network: Network,
+    api_key: Option<SecretString>,
+    headers: RequestHeaders,
+}
+
+impl ProviderConnection {
+    /// Connect to `provider` on `network`
+    pub fn new(provider: Provider, network: Network) -> Self {
+        Self {
+            provider,
+            network,
+            api_key: None,
+            headers: RequestHeaders::default(),
+        }
+    }
+
+    /// Set the API key or access token issued by the provider
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(SecretString::new(api_key.to_string()));
+        self
+    }
+
+    /// Send this User-Agent and these extra headers along with the provider's
+    pub fn with_headers(mut self, headers: RequestHeaders) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Create an RPC client for the provider's JSON-RPC endpoint
+    ///
+    /// With a URL-path key scheme the key is part of
+    /// [`RpcClient::endpoint`], so avoid logging the endpoint.
+    pub fn rpc_client(&self) -> Result<RpcClient> {
+        let url = self.provider.rpc_url(self.network).ok_or_else(|| {
+            Error::InvalidParameter(format!(
+                "{} has no RPC service on {:?}",
+                self.provider, self.network
+            ))
+        })?;
+        let (url, headers) = self.apply_key(url)?;
+        Ok(RpcClient::new(url).with_headers(headers))
+    }
+
+    /// Connect a light client for `wallet` to the provider's lightwalletd
+    ///
+    /// Fails if the wallet is on a different network than the connection.
+    pub async fn light_client(&self, wallet: Wallet) -> Result<LightClient> {
+        if wallet.network() != self.network {
+            return Err(Error::InvalidParameter(format!(
+                "Wallet is on {:?}, but the connection is for {:?}",
+                wallet.network(),
+                self.network
+            )));
+        }
+        let url = self
+            .provider
+            .lightwalletd_url(self.network)
+            .ok_or_else(|| {
+                Error::InvalidParameter(format!(
+                    "{} has no lightwalletd service on {:?}",
+                    self.provider, self.network
+                ))
+            })?;
+        let (url, headers) = self.apply_key(url)?;
+        let mut client = LightClient::open(url, wallet)?;
+        client.set_headers(headers);
+        client.get_server_info().await?;
+        Ok(client)
+    }
+
+    /// Add the API key to the endpoint URL or headers, as the provider
+    /// expects
+    fn apply_key(&self, url: &str) -> Result<(String, RequestHeaders)> {
+        let key = self
+            .api_key
+            .as_ref()
+            .map(|key| key.expose_secret().as_str());
+        match (self.provider.key_scheme(), key) {
+            (KeyScheme::None, _) => Ok((url.to_string(), self.headers.clone())),
+            (_, None) => Err(Error::InvalidParameter(format!(
+                "{} requires an API key",
+                self.provider
+            ))),
+            (KeyScheme::UrlPath, Some(key)) => {
+                if key.is_empty()
+                    || !key
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+                {
+                    return Err(Error::InvalidParameter(format!(
+                        "Invalid {} access token",
+                        self.provider
+                    )));
+                }
+                Ok((format!("{}/{}", url, key), self.headers.clone()))
+            }
+            (KeyScheme::Header(name), Some(key)) => Ok((
+                url.to_string(),
+                self.headers.clone().with_header(name, key)?,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_names() {
+        assert_eq!("NOWNodes".parse::<Provider>().unwrap(), Provider::NowNodes);
+        assert_eq!("zec.rocks".parse::<Provider>().unwrap(), Provider::ZecRocks);
+        assert_eq!("get-block".parse::<Provider>().unwrap(), Provider::GetBlock);
+        assert!("infura".parse::<Provider>().is_err());
+        for provider in Provider::ALL {
+            assert_eq!(provider.to_string().parse::<Provider>().unwrap(), provider);
+        }
+    }
+
+    #[test]
+    fn test_rpc_client_key_schemes() {
+        let client = ProviderConnection::new(Provider::NowNodes, Network::Mainnet)
+            .with_api_key("secret")
+            .rpc_client()
+            .unwrap();
+        assert_eq!(client.endpoint(), "https://zec.nownodes.io");
+        assert_eq!(client.headers().get("api-key"), Some("secret"));
+
+        let client = ProviderConnection::new(Provider::GetBlock, Network::Mainnet)
+            .with_api_key("abc123")
+            .rpc_client()
+            .unwrap();
+        assert_eq!(client.endpoint(), "https://go.getblock.io/abc123");
+        assert!(client.headers().extra().is_empty());
+
+        // Missing or unusable keys and unsupported networks are refused
+        assert!(ProviderConnection::new(Provider::Tatum, Network::Mainnet)
+            .rpc_client()
+            .is_err());
+        assert!(
+            ProviderConnection::new(Provider::GetBlock, Network::Mainnet)
+                .with_api_key("../admin")
+                .rpc_client()
+                .is_err()
+        );
+        assert!(
+            ProviderConnection::new(Provider::NowNodes, Network::Testnet)
+                .with_api_key("secret")
+                .rpc_client()
+                .is_err()
+        );
+        assert!(
+            ProviderConnection::new(Provider::ZecRocks, Network::Mainnet)
+                .rpc_client()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Received-payment receipts for customer support
+//!
+//! When a customer disputes a payment, support needs more than a balance:
+//! what arrived, in which transaction and block, and how a third party can
+//! check it. A [`PaymentReceipt`] for an incoming transaction lists the
+//! outputs the wallet received with their amounts, addresses and memos,
+//! together with the block it was mined in and a link to a public explorer.
+//!
+//! Anyone can confirm on an explorer that the transaction was mined in the
+//! stated block, and transparent outputs show their address and value
+//! directly. Shielded outputs are encrypted on chain; for those the receipt
+//! carries the note opening (diversifier and commitment randomness), which
+//! together with the recipient address and value lets a verifier recompute
+//! the note commitment and compare it with the output's commitment shown by
+//! the explorer, without being given a viewing key.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::db_error;
+use crate::types::{utils::zatoshis_to_zec, Network, Pool};
+use crate::wallet::memo_text;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Transaction page of the public mainnet explorer
+pub const MAINNET_EXPLORER_URL: &str = "https://mainnet.zcashexplorer.app/transactions/";
+
+/// Transaction page of the public testnet explorer
+pub const TESTNET_EXPLORER_URL: &str = "https://testnet.zcashexplorer.app/transactions/";
+
+/// Values that, with the address and value, determine a shielded note's
+/// on-chain commitment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteOpening {
+    /// Diversifier of the receiving address, hex encoded
+    pub diversifier: String,
+    /// Sapling commitment randomness `rcm`, hex encoded
+    pub rcm: Option<String>,
+    /// Orchard `rho`, hex encoded
+    pub rho: Option<String>,
+    /// Orchard `rseed`, hex encoded
+    pub rseed: Option<String>,
+}
+
+/// One output of the transaction received by the wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceivedOutput {
+    pub pool: Pool,
+    /// Output index, or action index for Orchard
+    pub output_index: u32,
+    /// Value in zatoshis
+    pub value: u64,
+    /// Receiving address, when recorded by the wallet
+    pub address: Option<String>,
+    pub memo: Option<String>,
+    /// Opening of a shielded note; `None` for transparent outputs
+    pub opening: Option<NoteOpening>,
+}
+
+/// Receipt for a payment received by the wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentReceipt {
+    pub txid: String,
+    pub network: Network,
+    /// Total received in zatoshis, excluding change
+    pub amount: u64,
+    /// Received outputs, excluding change
+    pub outputs: Vec<ReceivedOutput>,
+    /// Height of the block the transaction was mined in, or `None` while
+    /// it is unmined
+    pub height: Option<u64>,
+    /// Hash of that block, when the wallet still has its metadata
+    pub block_hash: Option<String>,
+    /// Block time as a Unix timestamp
+    pub block_time: Option<u64>,
+    /// Confirmations as of the wallet's scanned tip
+    pub confirmations: u64,
+    /// Explorer page of the transaction; `None` on regtest
+    pub explorer_url: Option<String>,
+}
+
+impl PaymentReceipt {
+    /// Format the receipt for a support ticket or email
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "Payment receipt\nTransaction: {}\nAmount: {} ZEC\n",
+            self.txid,
+            zatoshis_to_zec(self.amount)
+        );
+        match self.height {
+            Some(height) => text.push_str(&format!(
+                "Block: {} ({})\nConfirmations: {}\n",
+                height,
+                self.block_hash.as_deref().unwrap_or("hash unknown"),
+                self.confirmations
+            )),
+            None => text.push_str("Block: not yet mined\n"),
+        }
+        for output in &self.outputs {
+            text.push_str(&format!(
+                "Output {:?} #{}: {} ZEC to {}\n",
+                output.pool,
+                output.output_index,
+                zatoshis_to_zec(output.value),
+                output
+                    .address
+                    .as_deref()
+                    .unwrap_or("(address not recorded)")
+            ));
+            if let Some(memo) = &output.memo {
+                text.push_str(&format!("  Memo: {}\n", memo));
+            }
+        }
+        if let Some(url) = &self.explorer_url {
+            text.push_str(&format!("Verify: {}\n", url));
+        }
+        text
+    }
+}
+
+const DB_CONTEXT: &str = "Failed to read payment";
+
+fn hex_column(bytes: Option<Vec<u8>>) -> Option<String> {
+    bytes.map(hex::encode)
+}
+
+/// Build the receipt for `txid` from the wallet database at `path`
+///
+/// # Arguments
+/// * `account_index` - ZIP-32 index of the receiving account
+/// * `txid` - Transaction ID in display (reversed hex) order
+pub(crate) fn read_receipt(
+    path: &Path,
+    network: Network,
+    account_index: u32,
+    txid: &str,
+) -> Result<PaymentReceipt> {
+    let mut txid_bytes = hex::decode(txid)
+        .ok()
+        .filter(|bytes| bytes.len() == 32)
+        .ok_or_else(|| Error::InvalidParameter(format!("Invalid transaction ID {}", txid)))?;
+    // Stored in internal byte order
+    txid_bytes.reverse();
+
+    let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+    let mined = conn
+        .query_row(
+            "SELECT t.mined_height, b.hash, b.time, (SELECT MAX(height) FROM blocks)
+             FROM transactions t LEFT JOIN blocks b ON b.height = t.mined_height
+             WHERE t.txid = ?1",
+            [&txid_bytes],
+            |row| {
+                Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<Vec<u8>>>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(db_error(DB_CONTEXT))?;
+    let Some((height, hash, time, tip)) = mined else {
+        return Err(Error::InvalidParameter(format!(
+            "Transaction {} is not in the wallet",
+            txid
+        )));
+    };
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT n.pool, n.output_index, n.value, n.memo, n.diversifier, n.rcm, n.rho,
+                    n.rseed,
+                    (SELECT o.to_address FROM v_tx_outputs o
+                     WHERE o.txid = t.txid AND o.output_pool = n.pool_code
+                       AND o.output_index = n.output_index)
+             FROM (
+                 SELECT 'sapling' AS pool, 2 AS pool_code, tx AS tx_id, output_index, value,
+                        memo, diversifier, rcm, NULL AS rho, NULL AS rseed, is_change,
+                        account_id
+                 FROM sapling_received_notes
+                 UNION ALL
+                 SELECT 'orchard', 3, tx, action_index, value, memo, diversifier, NULL, rho,
+                        rseed, is_change, account_id
+                 FROM orchard_received_notes
+                 UNION ALL
+                 SELECT 'transparent', 0, transaction_id, output_index, value_zat, NULL, NULL,
+                        NULL, NULL, NULL, 0, account_id
+                 FROM transparent_received_outputs
+             ) n
+             JOIN transactions t ON t.id_tx = n.tx_id
+             WHERE t.txid = ?1 AND NOT n.is_change
+               AND n.account_id IN (SELECT id FROM accounts WHERE hd_account_index = ?2)
+             ORDER BY n.pool, n.output_index",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+    let outputs = stmt
+        .query_map(rusqlite::params![txid_bytes, account_index], |row| {
+            let pool = match row.get::<_, String>(0)?.as_str() {
+                "sapling" => Pool::Sapling,
+                "orchard" => Pool::Orchard,
+                _ => Pool::Transparent,
+            };
+            let opening = row
+                .get::<_, Option<Vec<u8>>>(4)?
+                .map(|diversifier| -> rusqlite::Result<_> {
+                    Ok(NoteOpening {
+                        diversifier: hex::encode(diversifier),
+                        rcm: hex_column(row.get(5)?),
+                        rho: hex_column(row.get(6)?),
+                        rseed: hex_column(row.get(7)?),
+                    })
+                })
+                .transpose()?;
+            Ok(ReceivedOutput {
+                pool,
+                output_index: row.get(1)?,
+                value: row.get::<_, i64>(2)? as u64,
+                address: row.get(8)?,
+                memo: row
+                    .get::<_, Option<Vec<u8>>>(3)?
+                    .and_then(|memo| memo_text(&memo)),
+                opening,
+            })
+        })
+        .map_err(db_error(DB_CONTEXT))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_error(DB_CONTEXT))?;
+    if outputs.is_empty() {
+        return Err(Error::InvalidParameter(format!(
+            "Transaction {} did not pay this account",
+            txid
+        )));
+    }
+
+    let height = height.map(|h| h as u64);
+    let confirmations = match (height, tip) {
+        (Some(height), Some(tip)) => (tip as u64 + 1).saturating_sub(height),
+        _ => 0,
+    };
+    let explorer_url = match network {
+        Network::Mainnet => Some(format!("{}{}", MAINNET_EXPLORER_URL, txid)),
+        Network::Testnet => Some(format!("{}{}", TESTNET_EXPLORER_URL, txid)),
+        Network::Regtest => None,
+    };
+    Ok(PaymentReceipt {
+        txid: txid.to_string(),
+        network,
+        amount: outputs.iter().map(|output| output.value).sum(),
+        outputs,
+        height,
+        block_hash: hash.map(|mut hash| {
+            // Block hashes are displayed in reverse byte order
+            hash.reverse();
+            hex::encode(hash)
+        }),
+        block_time: time.map(|time| time as u64),
+        confirmations,
+        explorer_url,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_receipt_of_shielded_payment() {
+        let path = TempDb::new();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE accounts (id INTEGER PRIMARY KEY, hd_account_index INTEGER);
+             CREATE TABLE blocks (height INTEGER PRIMARY KEY, hash BLOB, time INTEGER);
+             CREATE TABLE transactions (id_tx INTEGER PRIMARY KEY, txid BLOB,
+                                        mined_height INTEGER);
+             CREATE TABLE sapling_received_notes (tx INTEGER, output_index INTEGER,
+                 account_id INTEGER, diversifier BLOB, value INTEGER, rcm BLOB, memo BLOB,
+                 is_change INTEGER);
+             CREATE TABLE orchard_received_notes (tx INTEGER, action_index INTEGER,
+                 account_id INTEGER, diversifier BLOB, value INTEGER, rho BLOB, rseed BLOB,
+                 memo BLOB, is_change INTEGER);
+             CREATE TABLE transparent_received_outputs (transaction_id INTEGER,
+                 output_index INTEGER, account_id INTEGER, value_zat INTEGER);
+             CREATE VIEW v_tx_outputs AS
+                 SELECT x'00' AS txid, 0 AS output_pool, 0 AS output_index,
+                        NULL AS to_address WHERE 0;
+             INSERT INTO accounts VALUES (1, 0);
+             INSERT INTO blocks VALUES (100, x'01020304', 1700000000);
+             INSERT INTO blocks VALUES (104, x'05', 1700000600);
+             INSERT INTO sapling_received_notes
+                 VALUES (7, 1, 1, x'0102', 150000000, x'ff', NULL, 0);
+             INSERT INTO sapling_received_notes
+                 VALUES (7, 2, 1, x'0304', 5, x'ee', NULL, 1);",
+        )
+        .unwrap();
+        let txid = format!("bbaa{}", "00".repeat(30));
+        let mut stored = hex::decode(&txid).unwrap();
+        stored.reverse();
+        conn.execute("INSERT INTO transactions VALUES (7, ?1, 100)", [stored])
+            .unwrap();
+        drop(conn);
+
+        assert!(read_receipt(&path, Network::Mainnet, 0, "zz").is_err());
+        assert!(read_receipt(&path, Network::Mainnet, 1, &txid).is_err());
+
+        let receipt = read_receipt(&path, Network::Mainnet, 0, &txid).unwrap();
+        assert_eq!(receipt.amount, 150_000_000);
+        assert_eq!(receipt.outputs.len(), 1);
+        assert_eq!(
+            receipt.outputs[0].opening.as_ref().unwrap().rcm.as_deref(),
+            Some("ff")
+        );
+        assert_eq!(receipt.height, Some(100));
+        assert_eq!(receipt.block_hash.as_deref(), Some("04030201"));
+        assert_eq!(receipt.confirmations, 5);
+        assert!(receipt.to_text().contains("Amount: 1.5 ZEC"));
+        assert!(receipt.explorer_url.unwrap().ends_with(&txid));
+    }
+}
@@ -0,0 +1,385 @@
+//! Transaction reconciliation
+//!
+//! [`Reconciler::reconcile`] cross-checks what the local wallet database
+//! recorded for a transaction against what zcashd reports for it
+//! (`z_viewtransaction` and `getrawtransaction`), and lists every mismatch in
+//! mined height, confirmations, fee, per-address amounts and memos. It is
+//! meant for post-incident verification, e.g. after a crash during a send or
+//! a suspected reorg.
+//!
+//! Outputs are compared per recipient address; wallet outputs without a
+//! recorded address and change outputs are not compared.
+
+use crate::client::RpcClient;
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::rpc::TransactionDetails;
+use crate::store::db_error;
+use crate::types::utils::zec_to_zatoshis;
+use crate::wallet::Wallet;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// ZIP-302 memo marker for "no memo"
+const EMPTY_MEMO: u8 = 0xF6;
+
+/// What one side recorded for a transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    /// Height the transaction was mined at, if mined
+    pub mined_height: Option<u64>,
+    /// Fee in zatoshis, if known
+    pub fee: Option<u64>,
+    /// Amount paid to each address (zatoshis)
+    pub amounts: BTreeMap<String, u64>,
+    /// Memo text sent to each address
+    pub memos: BTreeMap<String, String>,
+}
+
+/// A mismatch between the wallet and the node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Discrepancy {
+    /// The node knows the transaction but the wallet database does not
+    MissingFromWallet,
+    /// The wallet database has the transaction but the node does not
+    MissingFromNode,
+    MinedHeight {
+        wallet: Option<u64>,
+        node: Option<u64>,
+    },
+    Confirmations {
+        wallet: Option<u64>,
+        node: Option<u64>,
+    },
+    Fee {
+        wallet: Option<u64>,
+        node: Option<u64>,
+    },
+    Amount {
+        address: String,
+        wallet: Option<u64>,
+        node: Option<u64>,
+    },
+    Memo {
+        address: String,
+        wallet: Option<String>,
+        node: Option<String>,
+    },
+}
+
+/// Result of reconciling one transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    pub txid: String,
+    /// Chain tip height used for confirmation counts
+    pub tip_height: u64,
+    pub wallet: Option<TransactionRecord>,
+    pub node: Option<TransactionRecord>,
+    /// Confirmations reported by the node
+    pub node_confirmations: Option<u64>,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl ReconciliationReport {
+    /// Whether the wallet and node agree on everything compared
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Decode a ZIP-302 memo to text
+///
+/// Returns `None` for the empty memo, the UTF-8 text (without zero padding)
+/// for text memos, and hex for arbitrary data memos.
+pub fn memo_text(memo: &[u8]) -> Option<String> {
+    match memo.first() {
+        None | Some(&EMPTY_MEMO) => None,
+        Some(&first) if first <= 0xF4 => {
+            let end = memo.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            Some(String::from_utf8_lossy(&memo[..end]).into_owned())
+        }
+        Some(_) => Some(hex::encode(memo)),
+    }
+}
+
+/// Confirmations of a transaction mined at `height` with the chain at `tip`
+fn confirmations(height: Option<u64>, tip: u64) -> Option<u64> {
+    height.map(|h| tip.saturating_sub(h) + 1)
+}
+
+/// Build the node's view of a transaction
+fn node_record(details: &TransactionDetails, height: Option<u64>) -> TransactionRecord {
+    let mut record = TransactionRecord {
+        mined_height: height.or(details.blockheight),
+        fee: details.fee.map(|fee| zec_to_zatoshis(fee.abs())),
+        ..Default::default()
+    };
+    for detail in &details.details {
+        let Some(address) = &detail.address else {
+            continue;
+        };
+        *record.amounts.entry(address.clone()).or_default() += zec_to_zatoshis(detail.amount.abs());
+        // zcashd reports memos as hex; fall back to the raw string otherwise
+        let memo = match &detail.memo {
+            Some(memo) => match hex::decode(memo) {
+                Ok(bytes) => memo_text(&bytes),
+                Err(_) => Some(memo.clone()).filter(|m| !m.is_empty()),
+            },
+            None => None,
+        };
+        if let Some(memo) = memo {
+            record.memos.insert(address.clone(), memo);
+        }
+    }
+    record
+}
+
+/// Compare the wallet and node records of a transaction
+///
+/// # Arguments
+/// * `wallet` - Wallet database record, if any
+/// * `node` - Node record, if any
+/// * `node_confirmations` - Confirmations reported by the node
+/// * `tip_height` - Current chain tip, used to compute wallet confirmations
+pub fn compare_records(
+    wallet: Option<&TransactionRecord>,
+    node: Option<&TransactionRecord>,
+    node_confirmations: Option<u64>,
+    tip_height: u64,
+) -> Vec<Discrepancy> {
+    let (wallet, node) = match (wallet, node) {
+        (Some(wallet), Some(node)) => (wallet, node),
+        (None, Some(_)) => return vec![Discrepancy::MissingFromWallet],
+        (Some(_), None) => return vec![Discrepancy::MissingFromNode],
+        (None, None) => return Vec::new(),
+    };
+
+    let mut discrepancies = Vec::new();
+    if wallet.mined_height != node.mined_height {
+        discrepancies.push(Discrepancy::MinedHeight {
+            wallet: wallet.mined_height,
+            node: node.mined_height,
+        });
+    }
+    let wallet_confirmations = confirmations(wallet.mined_height, tip_height);
+    let node_confirmations = node_confirmations.filter(|c| *c > 0);
+    if wallet_confirmations != node_confirmations {
+        discrepancies.push(Discrepancy::Confirmations {
+            wallet: wallet_confirmations,
+            node: node_confirmations,
+        });
+    }
+    if let (Some(w), Some(n)) = (wallet.fee, node.fee) {
+        if w != n {
+            discrepancies.push(Discrepancy::Fee {
+                wallet: Some(w),
+                node: Some(n),
+            });
+        }
+    }
+
+    let mut addresses: Vec<&String> = wallet.amounts.keys().chain(node.amounts.keys()).collect();
+    addresses.sort();
+    addresses.dedup();
+    for address in addresses {
+        let (w, n) = (wallet.amounts.get(address), node.amounts.get(address));
+        if w != n {
+            discrepancies.push(Discrepancy::Amount {
+                address: address.clone(),
+                wallet: w.copied(),
+                node: n.copied(),
+            });
+        }
+        let (w, n) = (wallet.memos.get(address), node.memos.get(address));
+        if w != n {
+            discrepancies.push(Discrepancy::Memo {
+                address: address.clone(),
+                wallet: w.cloned(),
+                node: n.cloned(),
+            });
+        }
+    }
+    discrepancies
+}
+
+const DB_CONTEXT: &str = "Reconciliation query error";
+
+/// Cross-checks wallet database records against zcashd
+pub struct Reconciler {
+    db_path: PathBuf,
+    client: RpcClient,
+}
+
+impl Reconciler {
+    /// Create a reconciler for the wallet database at `db_path`
+    pub fn new(db_path: impl AsRef<Path>, client: RpcClient) -> Self {
+        Self {
+            db_path: db_path.as_ref().to_path_buf(),
+            client,
+        }
+    }
+
+    /// Create a reconciler for a wallet's database
+    pub fn for_wallet(wallet: &Wallet, client: RpcClient) -> Self {
+        Self::new(wallet.db_path(), client)
+    }
+
+    /// Read the wallet database's record of a transaction
+    ///
+    /// # Arguments
+    /// * `txid` - Transaction ID (hex, display byte order)
+    pub fn wallet_record(&self, txid: &str) -> Result<Option<TransactionRecord>> {
+        let mut txid_bytes = hex::decode(txid)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid txid: {}", e)))?;
+        // The wallet database stores txids in internal byte order
+        txid_bytes.reverse();
+
+        let conn = open_connection(&self.db_path).map_err(db_error(DB_CONTEXT))?;
+        let mut stmt = conn
+            .prepare("SELECT mined_height, fee_paid FROM v_transactions WHERE txid = ?1")
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map(params![txid_bytes], |row| {
+                Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?))
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        // One row per involved account; they share height and fee
+        let mut record = TransactionRecord {
+            mined_height: rows.iter().find_map(|(h, _)| *h).map(|h| h as u64),
+            fee: rows.iter().find_map(|(_, f)| *f).map(|f| f as u64),
+            ..Default::default()
+        };
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT to_address, value, memo FROM v_tx_outputs
+                 WHERE txid = ?1 AND is_change = 0 AND to_address IS NOT NULL",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let outputs = stmt
+            .query_map(params![txid_bytes], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<Vec<u8>>>(2)?,
+                ))
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        for (address, value, memo) in outputs {
+            *record.amounts.entry(address.clone()).or_default() += value as u64;
+            if let Some(memo) = memo.as_deref().and_then(memo_text) {
+                record.memos.insert(address, memo);
+            }
+        }
+        Ok(Some(record))
+    }
+
+    /// Cross-check a transaction between the wallet database and zcashd
+    ///
+    /// # Arguments
+    /// * `txid` - Transaction ID (hex)
+    pub async fn reconcile(&self, txid: &str) -> Result<ReconciliationReport> {
+        let wallet = self.wallet_record(txid)?;
+        let tip_height = self.client.get_block_count().await?;
+
+        // Both calls fail for transactions the node does not know about
+        let details = self.client.z_viewtransaction(txid).await.ok();
+        let raw = self.client.get_raw_transaction(txid).await.ok();
+        let node_confirmations = raw
+            .as_ref()
+            .and_then(|r| r.confirmations)
+            .or(details.as_ref().and_then(|d| d.confirmations));
+        let node = match (&details, &raw) {
+            (Some(details), raw) => Some(node_record(details, raw.as_ref().and_then(|r| r.height))),
+            (None, Some(raw)) => Some(TransactionRecord {
+                mined_height: raw.height,
+                ..Default::default()
+            }),
+            (None, None) => None,
+        };
+
+        let discrepancies = compare_records(
+            wallet.as_ref(),
+            node.as_ref(),
+            node_confirmations,
+            tip_height,
+        );
+        if !discrepancies.is_empty() {
+            tracing::warn!(
+                "Transaction {} has {} discrepancies between wallet and node",
+                txid,
+                discrepancies.len()
+            );
+        }
+        Ok(ReconciliationReport {
+            txid: txid.to_string(),
+            tip_height,
+            wallet,
+            node,
+            node_confirmations,
+            discrepancies,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memo_text() {
+        let mut memo = [0u8; 512];
+        memo[..5].copy_from_slice(b"hello");
+        assert_eq!(memo_text(&memo).as_deref(), Some("hello"));
+        memo[0] = EMPTY_MEMO;
+        assert_eq!(memo_text(&memo), None);
+    }
+
+    #[test]
+    fn test_compare_records() {
+        let wallet = TransactionRecord {
+            mined_height: Some(100),
+            fee: Some(10_000),
+            amounts: BTreeMap::from([("u1a".to_string(), 5_000)]),
+            memos: BTreeMap::from([("u1a".to_string(), "invoice 7".to_string())]),
+        };
+        assert!(compare_records(Some(&wallet), Some(&wallet), Some(11), 110).is_empty());
+
+        let mut node = wallet.clone();
+        node.amounts.insert("u1a".to_string(), 6_000);
+        node.memos.clear();
+        assert_eq!(
+            compare_records(Some(&wallet), Some(&node), Some(3), 110),
+            vec![
+                Discrepancy::Confirmations {
+                    wallet: Some(11),
+                    node: Some(3)
+                },
+                Discrepancy::Amount {
+                    address: "u1a".to_string(),
+                    wallet: Some(5_000),
+                    node: Some(6_000)
+                },
+                Discrepancy::Memo {
+                    address: "u1a".to_string(),
+                    wallet: Some("invoice 7".to_string()),
+                    node: None
+                },
+            ]
+        );
+        assert_eq!(
+            compare_records(None, Some(&node), None, 110),
+            vec![Discrepancy::MissingFromWallet]
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Regtest faucet utilities
+//!
+//! Helpers for end-to-end examples and downstream tests against a local
+//! regtest node:
+//! - Mine blocks, optionally to a given address
+//! - Fast-forward the chain to a height, or past network upgrade activations
+//!   and coinbase maturity
+//! - Read the chain's activation heights for a regtest
+//!   [`Wallet`](crate::wallet::Wallet)
+//! - Fund an address (e.g. a [`Wallet`](crate::wallet::Wallet)'s unified
+//!   address) from the node's own wallet
+//!
+//! Mining requires a node started with `-regtest` (zcashd) or
+//! `network = "Regtest"` (zebrad). Funding requires a node wallet (zcashd)
+//! that holds mined funds; see [`Faucet::shield_coinbase`].
+
+use crate::client::{rpc_error_code, RpcClient};
+use crate::error::{Error, Result};
+use crate::params::RegtestUpgrades;
+use crate::rpc::{PrivacyPolicy, RawPayment};
+
+/// Zatoshis in one ZEC
+pub const ZATOSHIS_PER_ZEC: u64 = 100_000_000;
+
+/// Confirmations before a coinbase output can be spent
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// JSON-RPC error code for an unknown method
+const RPC_METHOD_NOT_FOUND: i64 = -32601;
+
+/// Seconds to wait for the node to build a funding transaction
+const FUNDING_TIMEOUT_SECS: u64 = 120;
+
+/// Network upgrade activation reported by `getblockchaininfo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeActivation {
+    /// Upgrade name, e.g. `NU5`
+    pub name: String,
+    /// Consensus branch ID the upgrade is listed under
+    pub branch_id: Option<u32>,
+    pub activation_height: u64,
+}
+
+/// Faucet over the RPC client of a regtest node
+pub struct Faucet {
+    client: RpcClient,
+    /// Shielded address of the node wallet that funds are sent from
+    source: Option<String>,
+}
+
+impl Faucet {
+    /// Create a faucet for a regtest node
+    ///
+    /// Fails unless the node reports the `regtest` chain, so a faucet can
+    /// never mine or spend on a public network.
+    pub async fn new(client: RpcClient) -> Result<Self> {
+        let info = client.get_blockchain_info().await?;
+        if info.chain != "regtest" {
+            return Err(Error::InvalidParameter(format!(
+                "Faucet requires a regtest node, {} is on {}",
+                client.endpoint(),
+                info.chain
+            )));
+        }
+        Ok(Self {
+            client,
+            source: None,
+        })
+    }
+
+    /// Send funds from a shielded address of the node wallet
+    ///
+    /// Required by [`fund`](Self::fund) unless
+    /// [`shield_coinbase`](Self::shield_coinbase) has set one.
+    pub fn with_source(mut self, address: impl Into<String>) -> Self {
+        self.source = Some(address.into());
+        self
+    }
+
+    /// The underlying RPC client
+    pub fn client(&self) -> &RpcClient {
+        &self.client
+    }
+
+    /// Mine blocks to the node's configured miner address
+    ///
+    /// # Arguments
+    /// * `blocks` - Number of blocks to mine
+    ///
+    /// # Returns
+    /// Hashes of the mined blocks
+    pub async fn generate(&self, blocks: u64) -> Result<Vec<String>> {
+        if blocks == 0 {
+            return Ok(Vec::new());
+        }
+        self.client
+            .call("generate", serde_json::json!([blocks]))
+            .await
+    }
+
+    /// Mine blocks whose coinbase pays an address
+    ///
+    /// Uses `generatetoaddress`. zcashd and zebrad do not have it and always
+    /// mine to the address they were started with (`-mineraddress`,
+    /// `mining.miner_address`); with those nodes this fails with an error
+    /// saying so.
+    ///
+    /// # Arguments
+    /// * `blocks` - Number of blocks to mine
+    /// * `address` - Address receiving the block rewards
+    ///
+    /// # Returns
+    /// Hashes of the mined blocks
+    pub async fn generate_to_address(&self, blocks: u64, address: &str) -> Result<Vec<String>> {
+        if blocks == 0 {
+            return Ok(Vec::new());
+        }
+        self.client
+            .call("generatetoaddress", serde_json::json!([blocks, address]))
+            .await
+            .map_err(|e| {
+                if rpc_error_code(&e) == Some(RPC_METHOD_NOT_FOUND) {
+                    Error::Rpc(format!(
+                        "Node has no generatetoaddress; configure {} as its miner \
+                         address and use generate",
+                        address
+                    ))
+                } else {
+                    e
+                }
+            })
+    }
+
+    /// Mine blocks until the chain reaches a height
+    ///
+    /// # Arguments
+    /// * `height` - Height of the chain tip to reach
+    ///
+    /// # Returns
+    /// Hashes of the mined blocks; empty if the chain is already there
+    pub async fn fast_forward_to(&self, height: u64) -> Result<Vec<String>> {
+        let tip = self.client.get_block_count().await?;
+        self.generate(height.saturating_sub(tip)).await
+    }
+
+    /// Network upgrades of the node's chain and their activation heights
+    ///
+    /// Read from `getblockchaininfo`, so heights set with zcashd's
+    /// `-nuparams` are taken into account.
+    pub async fn upgrades(&self) -> Result<Vec<UpgradeActivation>> {
+        let info = self.client.get_blockchain_info_raw().await?;
+        Ok(parse_upgrades(&info))
+    }
+
+    /// Activation heights of the node's chain, for
+    /// [`Wallet::set_regtest_upgrades`](crate::wallet::Wallet::set_regtest_upgrades)
+    ///
+    /// Upgrades the node does not list are never active.
+    pub async fn regtest_upgrades(&self) -> Result<RegtestUpgrades> {
+        let mut upgrades = RegtestUpgrades::none();
+        for upgrade in self.upgrades().await? {
+            let Some(branch_id) = upgrade.branch_id else {
+                continue;
+            };
+            let height = u32::try_from(upgrade.activation_height).map_err(|_| {
+                Error::Rpc(format!(
+                    "Invalid activation height {} of {}",
+                    upgrade.activation_height, upgrade.name
+                ))
+            })?;
+            upgrades = upgrades.with_branch_activation(branch_id, height)?;
+        }
+        Ok(upgrades)
+    }
+
+    /// Mine blocks until a network upgrade is active
+    ///
+    /// # Arguments
+    /// * `name` - Upgrade name as reported by the node, e.g. `NU5`
+    ///   (case-insensitive)
+    pub async fn fast_forward_past(&self, name: &str) -> Result<Vec<String>> {
+        let upgrade = self
+            .upgrades()
+            .await?
+            .into_iter()
+            .find(|upgrade| upgrade.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                Error::InvalidParameter(format!("Node does not schedule upgrade {}", name))
+            })?;
+        self.fast_forward_to(upgrade.activation_height).await
+    }
+
+    /// Mine blocks until every scheduled network upgrade is active
+    pub async fn fast_forward_past_activations(&self) -> Result<Vec<String>> {
+        let height = self
+            .upgrades()
+            .await?
+            .iter()
+            .map(|upgrade| upgrade.activation_height)
+            .max()
+            .unwrap_or(0);
+        self.fast_forward_to(height).await
+    }
+
+    /// Mine enough blocks for the coinbase of every mined block to mature
+    pub async fn mature_coinbase(&self) -> Result<Vec<String>> {
+        self.generate(COINBASE_MATURITY).await
+    }
+
+    /// Move the node wallet's mature coinbase funds to a shielded address
+    ///
+    /// Coinbase outputs can only be spent to shielded addresses, so mined
+    /// funds must be shielded before they can [`fund`](Self::fund) other
+    /// addresses. The address becomes the faucet's source, and a block is
+    /// mined to confirm the transaction.
+    ///
+    /// # Arguments
+    /// * `to_address` - Shielded or unified address of the node wallet
+    ///
+    /// # Returns
+    /// ID of the shielding transaction
+    pub async fn shield_coinbase(&mut self, to_address: &str) -> Result<String> {
+        let result: serde_json::Value = self
+            .client
+            .call("z_shieldcoinbase", serde_json::json!(["*", to_address]))
+            .await?;
+        let operation_id = result
+            .get("opid")
+            .and_then(|opid| opid.as_str())
+            .ok_or_else(|| Error::Rpc("z_shieldcoinbase returned no operation ID".to_string()))?;
+        let txid = self
+            .client
+            .wait_for_operation(operation_id, Some(FUNDING_TIMEOUT_SECS))
+            .await?;
+        self.generate(1).await?;
+        self.source = Some(to_address.to_string());
+        Ok(txid)
+    }
+
+    /// Send funds to an address and mine a block confirming them
+    ///
+    /// # Arguments
+    /// * `address` - Recipient, e.g. a wallet's unified address
+    /// * `amount` - Amount in zatoshis, e.g. `5 * ZATOSHIS_PER_ZEC`
+    ///
+    /// # Returns
+    /// ID of the funding transaction
+    pub async fn fund(&self, address: &str, amount: u64) -> Result<String> {
+        let source = self.source.as_deref().ok_or_else(|| {
+            Error::InvalidParameter(
+                "Faucet has no source address; call shield_coinbase or with_source".to_string(),
+            )
+        })?;
+        let payment = RawPayment {
+            address: address.to_string(),
+            amount,
+            memo: None,
+        };
+        let operation_id = self
+            .client
+            .z_sendmany_raw(
+                source,
+                &[payment],
+                Some(1),
+                None,
+                Some(PrivacyPolicy::AllowRevealedRecipients),
+            )
+            .await?;
+        let txid = self
+            .client
+            .wait_for_operation(&operation_id, Some(FUNDING_TIMEOUT_SECS))
+            .await?;
+        self.generate(1).await?;
+        Ok(txid)
+    }
+}
+
+/// Upgrades listed under `upgrades` in a `getblockchaininfo` response
+///
+/// zcashd keys them by consensus branch ID; entries without a name or
+/// activation height are skipped. Sorted by activation height.
+fn parse_upgrades(info: &serde_json::Value) -> Vec<UpgradeActivation> {
+    let mut upgrades: Vec<UpgradeActivation> = info
+        .get("upgrades")
+        .and_then(|upgrades| upgrades.as_object())
+        .into_iter()
+        .flat_map(|upgrades| upgrades.iter())
+        .filter_map(|(branch_id, upgrade)| {
+            Some(UpgradeActivation {
+                name: upgrade.get("name")?.as_str()?.to_string(),
+                branch_id: u32::from_str_radix(branch_id, 16).ok(),
+                activation_height: upgrade.get("activationheight")?.as_u64()?,
+            })
+        })
+        .collect();
+    upgrades.sort_by_key(|upgrade| upgrade.activation_height);
+    upgrades
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_upgrades() {
+        let info = serde_json::json!({
+            "chain": "regtest",
+            "blocks": 0,
+            "upgrades": {
+                "c2d6d0b4": { "name": "NU5", "activationheight": 5, "status": "pending" },
+                "5ba81b19": { "name": "Overwinter", "activationheight": 1, "status": "pending" },
+                "76b809bb": { "name": "Sapling", "activationheight": 1, "status": "pending" },
+                "ffffffff": { "status": "pending" },
+            }
+        });
+        let upgrades = parse_upgrades(&info);
+        assert_eq!(upgrades.len(), 3);
+        assert_eq!(upgrades.last().unwrap().name, "NU5");
+        assert_eq!(upgrades.last().unwrap().activation_height, 5);
+        assert_eq!(upgrades.last().unwrap().branch_id, Some(0xc2d6d0b4));
+        assert!(parse_upgrades(&serde_json::json!({ "chain": "regtest" })).is_empty());
+    }
+}
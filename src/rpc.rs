@@ -1,5 +1,6 @@
 //! RPC client implementation for zcashd
 
+use crate::types::format_zec;
 use serde::{Deserialize, Serialize};
 
 /// RPC request structure
@@ -43,6 +44,49 @@ pub struct Payment {
     pub memo: Option<String>,
 }
 
+/// Payment for z_sendmany with an exact amount and raw memo bytes
+///
+/// [`Payment`] carries the amount as an `f64` and the memo as text. A raw
+/// payment is sent with the amount as a decimal string and the memo hex
+/// encoded, as zcashd expects, so neither is altered on the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawPayment {
+    /// Recipient address
+    pub address: String,
+    /// Amount in zatoshis
+    pub amount: u64,
+    /// Memo bytes without trailing zero padding (shielded addresses only)
+    pub memo: Option<Vec<u8>>,
+}
+
+impl RawPayment {
+    /// Entry of z_sendmany's `amounts` parameter
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let mut payment = serde_json::json!({
+            "address": self.address,
+            "amount": format_zec(self.amount),
+        });
+        if let Some(memo) = &self.memo {
+            payment["memo"] = serde_json::json!(hex::encode(memo));
+        }
+        payment
+    }
+
+    /// Approximate [`Payment`] view, for fee estimation and spending policies
+    ///
+    /// Memos that are not UTF-8 text are left out.
+    pub fn to_payment(&self) -> Payment {
+        Payment {
+            address: self.address.clone(),
+            amount: self.amount as f64 / 100_000_000.0,
+            memo: self
+                .memo
+                .as_ref()
+                .and_then(|memo| String::from_utf8(memo.clone()).ok()),
+        }
+    }
+}
+
 /// Blockchain info response
 #[derive(Debug, Deserialize)]
 pub struct BlockchainInfo {
@@ -55,6 +99,20 @@ pub struct BlockchainInfo {
     pub chainwork: String,
     pub pruned: bool,
     pub commitments: u64,
+    /// Whether the node has finished initial block download (zcashd 5.0+)
+    #[serde(default)]
+    pub initial_block_download_complete: Option<bool>,
+}
+
+impl BlockchainInfo {
+    /// Whether the node has caught up with the chain
+    ///
+    /// Uses `initial_block_download_complete` when the node reports it,
+    /// otherwise whether every known header has a validated block.
+    pub fn is_synced(&self) -> bool {
+        self.initial_block_download_complete
+            .unwrap_or(self.blocks >= self.headers)
+    }
 }
 
 /// Transaction details from z_viewtransaction
@@ -90,3 +148,378 @@ pub struct AddressInfo {
     pub balance: Option<f64>,
     pub receivedby: Option<f64>,
 }
+
+/// Addresses of one key source, from `listaddresses` (zcashd 4.7.0 and later)
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddressSource {
+    /// Origin of the keys, e.g. `mnemonic_seed`, `legacy_random` or `imported`
+    pub source: String,
+    pub transparent: Option<TransparentAddressList>,
+    #[serde(default)]
+    pub sapling: Vec<SaplingAddressList>,
+    #[serde(default)]
+    pub unified: Vec<UnifiedAddressList>,
+}
+
+/// Transparent addresses of an [`AddressSource`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransparentAddressList {
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    #[serde(default, rename = "changeAddresses")]
+    pub change_addresses: Vec<String>,
+}
+
+/// Sapling addresses of one key in an [`AddressSource`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct SaplingAddressList {
+    /// ZIP-32 path of the key, absent for imported keys
+    #[serde(rename = "zip32KeyPath")]
+    pub zip32_key_path: Option<String>,
+    pub addresses: Vec<String>,
+}
+
+/// Unified addresses of one account in an [`AddressSource`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnifiedAddressList {
+    /// ZIP-32 account index
+    pub account: u32,
+    /// Fingerprint of the seed the account is derived from
+    pub seedfp: Option<String>,
+    pub addresses: Vec<UnifiedAddressEntry>,
+}
+
+/// A unified address in a [`UnifiedAddressList`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnifiedAddressEntry {
+    pub address: String,
+    /// Receivers of the address, e.g. `["sapling", "orchard"]`
+    #[serde(default)]
+    pub receiver_types: Vec<String>,
+}
+
+/// Privacy policy for `z_sendmany`
+///
+/// Controls which information zcashd is allowed to reveal when building a
+/// transaction. See the zcashd `z_sendmany` help for the exact semantics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrivacyPolicy {
+    FullPrivacy,
+    AllowRevealedAmounts,
+    AllowRevealedRecipients,
+    AllowRevealedSenders,
+    AllowFullyTransparent,
+    AllowLinkingAccountAddresses,
+    NoPrivacy,
+}
+
+impl PrivacyPolicy {
+    /// The policy name as accepted by zcashd
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrivacyPolicy::FullPrivacy => "FullPrivacy",
+            PrivacyPolicy::AllowRevealedAmounts => "AllowRevealedAmounts",
+            PrivacyPolicy::AllowRevealedRecipients => "AllowRevealedRecipients",
+            PrivacyPolicy::AllowRevealedSenders => "AllowRevealedSenders",
+            PrivacyPolicy::AllowFullyTransparent => "AllowFullyTransparent",
+            PrivacyPolicy::AllowLinkingAccountAddresses => "AllowLinkingAccountAddresses",
+            PrivacyPolicy::NoPrivacy => "NoPrivacy",
+        }
+    }
+}
+
+/// Features of the node behind an [`RpcClient`](crate::client::RpcClient)
+///
+/// Detected on first use from `getinfo` and a probe of the address index,
+/// so methods can pick call forms the node understands or fail with a clear
+/// error instead of an obscure RPC one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Numeric client version (zcashd: `1_000_000 * major + 10_000 * minor
+    /// + 100 * patch + build`), 0 if not reported
+    pub version: u64,
+    /// User agent, e.g. `/MagicBean:5.6.0/` for zcashd or `/Zebra:1.6.0/`
+    pub subversion: String,
+    /// Whether the node is zcashd, the only node with a wallet
+    pub zcashd: bool,
+    /// Unified accounts API (`z_getnewaccount`, `z_getaddressforaccount`,
+    /// `listaddresses`), zcashd 4.7.0 and later
+    pub unified_accounts: bool,
+    /// `privacyPolicy` argument of `z_sendmany`, zcashd 5.0.0 and later
+    pub privacy_policy: bool,
+    /// Address index RPCs (`getaddressbalance`, `getaddressdeltas`), which
+    /// zcashd only serves with `-insightexplorer` or `-lightwalletd`
+    pub address_index: bool,
+}
+
+impl Capabilities {
+    /// First zcashd version with the unified accounts API (4.7.0)
+    pub const UNIFIED_ACCOUNTS_VERSION: u64 = 4_070_000;
+    /// First zcashd version accepting a `privacyPolicy` (5.0.0)
+    pub const PRIVACY_POLICY_VERSION: u64 = 5_000_000;
+
+    /// Derive capabilities from a `getinfo` response
+    ///
+    /// # Arguments
+    /// * `info` - Result of `getinfo`
+    /// * `address_index` - Whether an address index RPC succeeded
+    pub fn from_info(info: &serde_json::Value, address_index: bool) -> Self {
+        let version = info.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+        let subversion = info
+            .get("subversion")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let zcashd = subversion.contains("MagicBean");
+        Self {
+            version,
+            zcashd,
+            unified_accounts: zcashd && version >= Self::UNIFIED_ACCOUNTS_VERSION,
+            privacy_policy: zcashd && version >= Self::PRIVACY_POLICY_VERSION,
+            address_index,
+            subversion,
+        }
+    }
+}
+
+/// Unspent shielded note from z_listunspent
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnspentNote {
+    pub txid: String,
+    /// Value pool ("sapling" or "orchard")
+    pub pool: String,
+    /// Output index within the pool (Sapling `outindex`, Orchard `actionidx`)
+    #[serde(default, alias = "actionidx")]
+    pub outindex: Option<u32>,
+    pub confirmations: u64,
+    #[serde(default)]
+    pub spendable: bool,
+    pub address: Option<String>,
+    pub amount: f64,
+    #[serde(rename = "amountZat", default)]
+    pub amount_zat: Option<u64>,
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub change: bool,
+}
+
+/// Unspent transparent output from listunspent
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnspentOutput {
+    pub txid: String,
+    pub vout: u32,
+    pub address: Option<String>,
+    pub amount: f64,
+    #[serde(rename = "amountZat", default)]
+    pub amount_zat: Option<u64>,
+    pub confirmations: u64,
+    #[serde(default)]
+    pub spendable: bool,
+}
+
+/// Block with fully decoded transactions (getblock verbosity 2)
+#[derive(Debug, Clone, Deserialize)]
+pub struct Block {
+    pub hash: String,
+    pub confirmations: i64,
+    pub size: Option<u64>,
+    pub height: u64,
+    pub version: Option<u32>,
+    pub merkleroot: Option<String>,
+    pub time: u64,
+    pub previousblockhash: Option<String>,
+    pub nextblockhash: Option<String>,
+    #[serde(default)]
+    pub tx: Vec<RawTransaction>,
+}
+
+/// Decoded transaction from getrawtransaction (verbose) or getblock
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawTransaction {
+    pub txid: String,
+    pub hex: Option<String>,
+    pub version: Option<u32>,
+    pub locktime: Option<u32>,
+    #[serde(rename = "expiryheight")]
+    pub expiry_height: Option<u64>,
+    #[serde(default)]
+    pub vin: Vec<TxInput>,
+    #[serde(default)]
+    pub vout: Vec<TxOutput>,
+    #[serde(rename = "vShieldedSpend", default)]
+    pub shielded_spends: Vec<serde_json::Value>,
+    #[serde(rename = "vShieldedOutput", default)]
+    pub shielded_outputs: Vec<serde_json::Value>,
+    pub orchard: Option<OrchardBundle>,
+    #[serde(rename = "valueBalanceZat")]
+    pub value_balance_zat: Option<i64>,
+    pub blockhash: Option<String>,
+    pub height: Option<u64>,
+    pub confirmations: Option<u64>,
+    pub time: Option<u64>,
+    pub blocktime: Option<u64>,
+}
+
+/// Transparent input of a decoded transaction
+#[derive(Debug, Clone, Deserialize)]
+pub struct TxInput {
+    /// Present for coinbase inputs only
+    pub coinbase: Option<String>,
+    pub txid: Option<String>,
+    pub vout: Option<u32>,
+    pub sequence: Option<u32>,
+    /// Spent value (zcashd with -insightexplorer)
+    #[serde(rename = "valueSat")]
+    pub value_sat: Option<u64>,
+    /// Spent address (zcashd with -insightexplorer)
+    pub address: Option<String>,
+}
+
+/// Transparent output of a decoded transaction
+#[derive(Debug, Clone, Deserialize)]
+pub struct TxOutput {
+    pub value: f64,
+    #[serde(rename = "valueZat")]
+    pub value_zat: Option<u64>,
+    pub n: u32,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: ScriptPubKey,
+}
+
+/// Output script of a transparent output
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptPubKey {
+    pub asm: Option<String>,
+    pub hex: Option<String>,
+    #[serde(rename = "type")]
+    pub script_type: Option<String>,
+    #[serde(default)]
+    pub addresses: Vec<String>,
+}
+
+/// Orchard bundle summary of a decoded transaction
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrchardBundle {
+    #[serde(default)]
+    pub actions: Vec<serde_json::Value>,
+    #[serde(rename = "valueBalanceZat")]
+    pub value_balance_zat: Option<i64>,
+}
+
+/// Balance entry from getaddressbalance (requires -insightexplorer)
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddressBalance {
+    /// Current balance in zatoshis
+    pub balance: i64,
+    /// Total received in zatoshis
+    pub received: i64,
+}
+
+/// Balance change from getaddressdeltas (requires -insightexplorer)
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddressDelta {
+    pub address: String,
+    pub txid: String,
+    pub index: u32,
+    pub height: u64,
+    /// Change in zatoshis (negative for spends)
+    pub satoshis: i64,
+}
+
+/// Block template from getblocktemplate (BIP 22, with Zcash extensions)
+///
+/// zcashd and zebrad fill in the coinbase transaction themselves, paying
+/// the configured miner address, so a pool only has to assemble the header
+/// from the default roots and search for a solution.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockTemplate {
+    pub version: u32,
+    pub height: u64,
+    #[serde(rename = "previousblockhash")]
+    pub previous_block_hash: String,
+    /// Header commitments for the template's coinbase and transactions
+    #[serde(rename = "defaultroots")]
+    pub default_roots: DefaultRoots,
+    /// Block commitments hash (`hashBlockCommitments`) of the default roots
+    #[serde(rename = "blockcommitmentshash")]
+    pub block_commitments_hash: Option<String>,
+    #[serde(rename = "coinbasetxn")]
+    pub coinbase_txn: TemplateTransaction,
+    #[serde(default)]
+    pub transactions: Vec<TemplateTransaction>,
+    /// Target the block hash must not exceed, as hex
+    pub target: String,
+    /// Compact form of the target
+    pub bits: String,
+    #[serde(rename = "curtime")]
+    pub cur_time: u64,
+    #[serde(rename = "mintime")]
+    pub min_time: u64,
+    /// Pass back to wait for the next template (long polling)
+    #[serde(rename = "longpollid")]
+    pub long_poll_id: Option<String>,
+    #[serde(default)]
+    pub mutable: Vec<String>,
+    #[serde(rename = "noncerange")]
+    pub nonce_range: Option<String>,
+    #[serde(rename = "sigoplimit")]
+    pub sigop_limit: Option<u64>,
+    #[serde(rename = "sizelimit")]
+    pub size_limit: Option<u64>,
+}
+
+/// Header roots of a [`BlockTemplate`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct DefaultRoots {
+    #[serde(rename = "merkleroot")]
+    pub merkle_root: String,
+    #[serde(rename = "chainhistoryroot")]
+    pub chain_history_root: String,
+    #[serde(rename = "authdataroot")]
+    pub auth_data_root: String,
+    #[serde(rename = "blockcommitmentshash")]
+    pub block_commitments_hash: String,
+}
+
+/// Transaction of a [`BlockTemplate`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateTransaction {
+    /// Serialized transaction, as hex
+    pub data: String,
+    pub hash: String,
+    #[serde(rename = "authdigest")]
+    pub auth_digest: Option<String>,
+    /// 1-based indexes of template transactions this one spends from
+    #[serde(default)]
+    pub depends: Vec<u64>,
+    /// Fee in zatoshis; for the coinbase, minus the total fees of the block
+    pub fee: i64,
+    #[serde(default)]
+    pub sigops: u64,
+    /// Whether the transaction must be included (coinbase only)
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Outcome of submitblock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmitBlockOutcome {
+    /// The block was valid and accepted
+    Accepted,
+    /// The node already had the block (`duplicate`)
+    Duplicate,
+    /// The block was not accepted, with the node's reason, e.g. `rejected`,
+    /// `high-hash` or `inconclusive`
+    Rejected(String),
+}
+
+impl SubmitBlockOutcome {
+    /// Outcome from the result of submitblock, `null` when accepted
+    pub fn from_result(result: Option<&str>) -> Self {
+        match result {
+            None => SubmitBlockOutcome::Accepted,
+            Some("duplicate") => SubmitBlockOutcome::Duplicate,
+            Some(reason) => SubmitBlockOutcome::Rejected(reason.to_string()),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Multi-wallet sync scheduler
+//!
+//! A hosted-wallet service that syncs each wallet with its own
+//! [`LightClient::sync`] downloads every compact block once per wallet. The
+//! [`SyncScheduler`] instead drives many wallets over one shared lightwalletd
+//! channel:
+//! - Each round downloads one batch of blocks and scans it for every wallet
+//!   that needs it
+//! - Rounds alternate between the wallet closest to the chain tip and the
+//!   most-lagging wallet, so wallets that are in sync keep up with new blocks
+//!   while others catch up; wallets at the same height share every download
+//! - Per-wallet progress is reported after every scanned batch, including
+//!   scan errors, which do not hold up the other wallets
+//!
+//! Downloads go through a [`BlockCache`], which can be shared with other
+//! schedulers or light clients on the same server.
+
+use crate::block_cache::BlockCache;
+use crate::error::{Error, Result};
+use crate::headers::RequestHeaders;
+use crate::light_client::{fetch_latest_height, lazy_channel, LightClient, LightwalletdChannel};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Default number of blocks downloaded per round
+pub const DEFAULT_SCHEDULER_BATCH_SIZE: u64 = 100;
+
+/// Sync progress of one scheduled wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletSyncProgress {
+    pub wallet_id: String,
+    /// Highest height scanned, or `None` before the first batch
+    pub scanned_height: Option<u64>,
+    /// Chain tip the wallet is syncing towards
+    pub target_height: u64,
+    /// Why the last batch could not be scanned, if it failed
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl WalletSyncProgress {
+    /// Whether the wallet has scanned up to its target
+    pub fn is_synced(&self) -> bool {
+        self.scanned_height
+            .is_some_and(|height| height >= self.target_height)
+    }
+}
+
+struct ScheduledWallet {
+    client: LightClient,
+    /// Next height to scan
+    next_height: u64,
+}
+
+/// Syncs many wallets against one lightwalletd server, sharing downloads
+pub struct SyncScheduler {
+    channel: LightwalletdChannel,
+    batch_size: u64,
+    wallets: BTreeMap<String, ScheduledWallet>,
+    cache: BlockCache,
+    tip: u64,
+    /// Whether the next round serves the most-lagging wallet
+    catch_up_turn: bool,
+}
+
+impl SyncScheduler {
+    /// Create a scheduler for the given lightwalletd endpoint
+    ///
+    /// Registered wallets should be connected to the same server.
+    pub fn new(endpoint: &str) -> Result<Self> {
+        Self::with_headers(endpoint, &RequestHeaders::default())
+    }
+
+    /// Create a scheduler that sends a custom User-Agent and extra headers
+    /// with every request to the endpoint
+    pub fn with_headers(endpoint: &str, headers: &RequestHeaders) -> Result<Self> {
+        Ok(Self {
+            channel: lazy_channel(endpoint, headers)?,
+            batch_size: DEFAULT_SCHEDULER_BATCH_SIZE,
+            wallets: BTreeMap::new(),
+            cache: BlockCache::new(),
+            tip: 0,
+            catch_up_turn: false,
+        })
+    }
+
+    /// Set the number of blocks downloaded per round
+    pub fn with_batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Use a block cache shared with other schedulers or light clients
+    pub fn with_block_cache(mut self, cache: BlockCache) -> Self {
+        for (wallet_id, wallet) in &self.wallets {
+            self.cache.unregister_wallet(wallet_id);
+            cache.register_wallet(wallet_id.clone(), wallet.next_height);
+        }
+        self.cache = cache;
+        self
+    }
+
+    /// The block cache used by this scheduler
+    pub fn block_cache(&self) -> &BlockCache {
+        &self.cache
+    }
+
+    /// Register a wallet to be synced from `start_height`
+    ///
+    /// # Arguments
+    /// * `wallet_id` - Unique identifier used in progress reports
+    /// * `client` - Light client for the wallet
+    /// * `start_height` - First height to scan (e.g. the wallet birthday or last synced height + 1)
+    pub fn add_wallet(
+        &mut self,
+        wallet_id: impl Into<String>,
+        client: LightClient,
+        start_height: u64,
+    ) -> Result<()> {
+        let wallet_id = wallet_id.into();
+        if self.wallets.contains_key(&wallet_id) {
+            return Err(Error::InvalidParameter(format!(
+                "Wallet {} is already scheduled",
+                wallet_id
+            )));
+        }
+        self.cache.register_wallet(wallet_id.clone(), start_height);
+        self.wallets.insert(
+            wallet_id,
+            ScheduledWallet {
+                client,
+                next_height: start_height,
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove a wallet from the schedule, returning its light client
+    pub fn remove_wallet(&mut self, wallet_id: &str) -> Option<LightClient> {
+        self.cache.unregister_wallet(wallet_id);
+        self.wallets.remove(wallet_id).map(|w| w.client)
+    }
+
+    /// Current progress of every scheduled wallet
+    pub fn progress(&self) -> Vec<WalletSyncProgress> {
+        self.wallets
+            .iter()
+            .map(|(id, wallet)| self.progress_for(id, wallet, None))
+            .collect()
+    }
+
+    fn progress_for(
+        &self,
+        wallet_id: &str,
+        wallet: &ScheduledWallet,
+        error: Option<String>,
+    ) -> WalletSyncProgress {
+        WalletSyncProgress {
+            wallet_id: wallet_id.to_string(),
+            scanned_height: wallet.next_height.checked_sub(1),
+            target_height: self.tip,
+            error,
+        }
+    }
+
+    /// Run one scheduling round
+    ///
+    /// Refreshes the chain tip, loads the next batch once, and scans it for
+    /// every wallet whose next height falls inside it. Rounds alternate
+    /// between starting the batch at the wallet closest to the tip and at
+    /// the most-lagging wallet.
+    ///
+    /// # Returns
+    /// Progress of the wallets scanned this round (empty when all are
+    /// synced); a wallet whose scan failed is reported with the error and
+    /// retried from the same height in a later round
+    pub async fn run_round(&mut self) -> Result<Vec<WalletSyncProgress>> {
+        self.run_round_skipping(&HashSet::new()).await
+    }
+
+    async fn run_round_skipping(
+        &mut self,
+        skipped: &HashSet<String>,
+    ) -> Result<Vec<WalletSyncProgress>> {
+        self.tip = fetch_latest_height(self.channel.clone()).await?;
+
+        let next_heights: Vec<u64> = self
+            .wallets
+            .iter()
+            .filter(|(id, _)| !skipped.contains(*id))
+            .map(|(_, w)| w.next_height)
+            .collect();
+        let catch_up = self.catch_up_turn;
+        self.catch_up_turn = !catch_up;
+        let Some((start, end)) = next_batch(&next_heights, self.tip, self.batch_size, catch_up)
+        else {
+            return Ok(Vec::new());
+        };
+
+        tracing::debug!("Scheduler fetching blocks {} to {}", start, end);
+        let blocks = self
+            .cache
+            .get_or_fetch(self.channel.clone(), start, end)
+            .await?;
+        if blocks.is_empty() {
+            return Err(Error::Rpc(format!(
+                "No blocks returned for range {} to {}",
+                start, end
+            )));
+        }
+
+        let mut scanned = Vec::new();
+        for (wallet_id, wallet) in self.wallets.iter_mut() {
+            if skipped.contains(wallet_id) || wallet.next_height < start || wallet.next_height > end
+            {
+                continue;
+            }
+            let offset = (wallet.next_height - start) as usize;
+            let Some(batch) = blocks.get(offset..) else {
+                continue;
+            };
+            let result = wallet
+                .client
+                .scan_blocks(wallet.next_height, batch.to_vec())
+                .await;
+            match result {
+                Ok(()) => {
+                    wallet.next_height = start + blocks.len() as u64;
+                    self.cache.mark_scanned(wallet_id, wallet.next_height - 1);
+                    scanned.push((wallet_id.clone(), None));
+                }
+                Err(e) => {
+                    tracing::warn!("Scheduler failed to scan wallet {}: {}", wallet_id, e);
+                    scanned.push((wallet_id.clone(), Some(e.to_string())));
+                }
+            }
+        }
+
+        Ok(scanned
+            .into_iter()
+            .filter_map(|(id, error)| {
+                let wallet = self.wallets.get(&id)?;
+                Some(self.progress_for(&id, wallet, error))
+            })
+            .collect())
+    }
+
+    /// Sync all scheduled wallets to the chain tip
+    ///
+    /// A wallet whose scan fails sits out the remaining rounds, so the
+    /// others still reach the tip.
+    ///
+    /// # Arguments
+    /// * `progress` - Callback invoked after each wallet finishes or fails a
+    ///   batch
+    ///
+    /// # Returns
+    /// An error naming the wallets that failed, once the others are synced
+    pub async fn sync_all(&mut self, mut progress: impl FnMut(&WalletSyncProgress)) -> Result<()> {
+        let mut failed = HashSet::new();
+        loop {
+            let updates = self.run_round_skipping(&failed).await?;
+            if updates.is_empty() {
+                break;
+            }
+            for update in &updates {
+                progress(update);
+                if update.error.is_some() {
+                    failed.insert(update.wallet_id.clone());
+                }
+            }
+        }
+        if failed.is_empty() {
+            return Ok(());
+        }
+        let mut failed: Vec<String> = failed.into_iter().collect();
+        failed.sort();
+        Err(Error::Wallet(format!(
+            "Failed to sync wallets: {}",
+            failed.join(", ")
+        )))
+    }
+}
+
+/// Choose the next inclusive block range to download
+///
+/// Starts at the lowest next height when catching up, so the most-lagging
+/// wallets advance, and otherwise at the highest next height, so wallets
+/// close to the tip receive new blocks.
+fn next_batch(
+    next_heights: &[u64],
+    tip: u64,
+    batch_size: u64,
+    catch_up: bool,
+) -> Option<(u64, u64)> {
+    let pending = next_heights.iter().copied().filter(|height| *height <= tip);
+    let start = if catch_up {
+        pending.min()?
+    } else {
+        pending.max()?
+    };
+    Some((start, tip.min(start.saturating_add(batch_size - 1))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_batch_alternates_between_tip_and_lagging_wallets() {
+        assert_eq!(
+            next_batch(&[500, 120, 900], 1_000, 100, true),
+            Some((120, 219))
+        );
+        assert_eq!(
+            next_batch(&[500, 120, 900], 1_000, 100, false),
+            Some((900, 999))
+        );
+        assert_eq!(
+            next_batch(&[120, 1_001], 1_000, 100, false),
+            Some((120, 219))
+        );
+        assert_eq!(next_batch(&[990], 1_000, 100, true), Some((990, 1_000)));
+        assert_eq!(next_batch(&[1_001, 1_001], 1_000, 100, false), None);
+        assert_eq!(next_batch(&[], 1_000, 100, true), None);
+    }
+}
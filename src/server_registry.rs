@@ -0,0 +1,399 @@
+//! Known lightwalletd servers with pinned TLS identities
+//!
+//! Public lightwalletd endpoints are an attractive target for
+//! man-in-the-middle attacks: a malicious server can withhold transactions or
+//! learn which blocks a wallet is interested in. [`ServerRegistry`] records
+//! trusted servers together with the SHA-256 fingerprints of their TLS leaf
+//! certificates. Connections to a pinned server only succeed if the server
+//! presents one of the pinned certificates, regardless of which CA signed it.
+//!
+//! In strict mode the registry refuses endpoints that are not registered with
+//! at least one pin, so a wallet can never silently fall back to an
+//! unverified server.
+//!
+//! Fingerprints can be obtained with:
+//! ```text
+//! openssl s_client -connect host:9067 </dev/null | openssl x509 -noout -fingerprint -sha256
+//! ```
+
+use crate::bandwidth::{BandwidthMeter, MeteredIo};
+use crate::error::{Error, Result};
+use crate::headers::RequestHeaders;
+use crate::light_client::{grpc_endpoint, LightwalletdChannel};
+use crate::types::Network;
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{
+    ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::Uri;
+
+/// A trusted lightwalletd server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownServer {
+    /// gRPC endpoint URL (e.g. "https://lightwalletd.example.com:9067")
+    pub endpoint: String,
+    pub network: Network,
+    pub label: Option<String>,
+    /// Hex SHA-256 fingerprints of accepted leaf certificates
+    ///
+    /// More than one pin allows certificate rotation without downtime.
+    pub fingerprints: Vec<String>,
+}
+
+impl KnownServer {
+    /// Create an entry without pins
+    pub fn new(endpoint: impl Into<String>, network: Network) -> Self {
+        Self {
+            endpoint: normalize_endpoint(&endpoint.into()),
+            network,
+            label: None,
+            fingerprints: Vec::new(),
+        }
+    }
+
+    /// Set a human-readable name
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Pin a certificate fingerprint
+    ///
+    /// Accepts hex with or without `:` separators, in either case.
+    pub fn with_fingerprint(mut self, fingerprint: &str) -> Result<Self> {
+        let fingerprint = normalize_fingerprint(fingerprint)?;
+        if !self.fingerprints.contains(&fingerprint) {
+            self.fingerprints.push(fingerprint);
+        }
+        Ok(self)
+    }
+
+    /// Whether the server has at least one pinned certificate
+    pub fn is_pinned(&self) -> bool {
+        !self.fingerprints.is_empty()
+    }
+}
+
+/// SHA-256 fingerprint of a DER-encoded certificate, as lowercase hex
+pub fn certificate_fingerprint(der: &[u8]) -> String {
+    hex::encode(Sha256::digest(der))
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> Result<String> {
+    let normalized: String = fingerprint
+        .chars()
+        .filter(|c| *c != ':')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match hex::decode(&normalized) {
+        Ok(bytes) if bytes.len() == 32 => Ok(normalized),
+        _ => Err(Error::InvalidParameter(format!(
+            "Invalid SHA-256 certificate fingerprint: {}",
+            fingerprint
+        ))),
+    }
+}
+
+fn normalize_endpoint(endpoint: &str) -> String {
+    endpoint.trim().trim_end_matches('/').to_string()
+}
+
+/// Registry of trusted lightwalletd servers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerRegistry {
+    servers: Vec<KnownServer>,
+    strict: bool,
+}
+
+impl ServerRegistry {
+    /// Create an empty, non-strict registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a registry saved with [`save`](Self::save)
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Save the registry as JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Only allow connections to registered, pinned servers
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Whether strict mode is enabled
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Add a server, replacing any entry with the same endpoint
+    pub fn add(&mut self, server: KnownServer) {
+        self.remove(&server.endpoint);
+        self.servers.push(server);
+    }
+
+    /// Remove a server
+    pub fn remove(&mut self, endpoint: &str) -> Option<KnownServer> {
+        let endpoint = normalize_endpoint(endpoint);
+        let index = self.servers.iter().position(|s| s.endpoint == endpoint)?;
+        Some(self.servers.remove(index))
+    }
+
+    /// Look up a server by endpoint
+    pub fn get(&self, endpoint: &str) -> Option<&KnownServer> {
+        let endpoint = normalize_endpoint(endpoint);
+        self.servers.iter().find(|s| s.endpoint == endpoint)
+    }
+
+    /// All registered servers
+    pub fn servers(&self) -> &[KnownServer] {
+        &self.servers
+    }
+
+    /// Registered servers for a network
+    pub fn servers_for(&self, network: Network) -> impl Iterator<Item = &KnownServer> {
+        self.servers.iter().filter(move |s| s.network == network)
+    }
+
+    /// Decide how to connect to an endpoint
+    ///
+    /// # Returns
+    /// The registered entry if the endpoint is known (its pins must then be
+    /// honoured), or `None` to connect without pinning
+    ///
+    /// # Errors
+    /// In strict mode, fails for endpoints that are unknown, unpinned, or
+    /// registered for a different network
+    pub fn resolve(&self, endpoint: &str, network: Network) -> Result<Option<&KnownServer>> {
+        let server = self.get(endpoint);
+        if !self.strict {
+            return Ok(server);
+        }
+        match server {
+            Some(server) if server.is_pinned() && server.network == network => Ok(Some(server)),
+            Some(server) if server.network != network => Err(Error::InvalidParameter(format!(
+                "Server {} is registered for {:?}, not {:?}",
+                endpoint, server.network, network
+            ))),
+            Some(_) => Err(Error::InvalidParameter(format!(
+                "Strict mode: server {} has no pinned certificate",
+                endpoint
+            ))),
+            None => Err(Error::InvalidParameter(format!(
+                "Strict mode: server {} is not in the registry",
+                endpoint
+            ))),
+        }
+    }
+}
+
+/// Accepts exactly the pinned leaf certificates
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    pins: HashSet<String>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        let fingerprint = certificate_fingerprint(end_entity.as_ref());
+        if self.pins.contains(&fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            tracing::warn!("Rejected unpinned server certificate {}", fingerprint);
+            Err(tokio_rustls::rustls::Error::General(format!(
+                "certificate {} does not match any pinned fingerprint",
+                fingerprint
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Create a lazily connected gRPC channel that only trusts the server's pins
+///
+/// If `meter` is given, the encrypted socket traffic is counted on it as wire
+/// bytes.
+pub(crate) fn pinned_channel(
+    server: &KnownServer,
+    meter: Option<BandwidthMeter>,
+    headers: &RequestHeaders,
+) -> Result<LightwalletdChannel> {
+    let uri: Uri = server
+        .endpoint
+        .parse()
+        .map_err(|e| Error::InvalidParameter(format!("Invalid endpoint URL: {}", e)))?;
+    if uri.scheme_str() != Some("https") {
+        return Err(Error::InvalidParameter(format!(
+            "Pinned server {} must use https",
+            server.endpoint
+        )));
+    }
+    if !server.is_pinned() {
+        return Err(Error::InvalidParameter(format!(
+            "Server {} has no pinned certificate",
+            server.endpoint
+        )));
+    }
+    let host = uri
+        .host()
+        .ok_or_else(|| Error::InvalidParameter("Endpoint URL has no host".to_string()))?
+        .to_string();
+    let port = uri.port_u16().unwrap_or(443);
+    let server_name = ServerName::try_from(host.clone())
+        .map_err(|e| Error::InvalidParameter(format!("Invalid server name: {}", e)))?;
+
+    let provider = Arc::new(ring::default_provider());
+    let verifier = PinnedCertVerifier {
+        pins: server
+            .fingerprints
+            .iter()
+            .map(|f| normalize_fingerprint(f))
+            .collect::<Result<_>>()?,
+        provider: provider.clone(),
+    };
+    let mut config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Protocol(format!("Failed to configure TLS: {}", e)))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+
+    // TLS is performed by the connector below, so tonic sees plain HTTP/2
+    let address = format!("{}:{}", host, port);
+    let endpoint = grpc_endpoint(&format!("http://{}", address), headers)?;
+    let channel = endpoint.connect_with_connector_lazy(tower::service_fn(move |_: Uri| {
+        let connector = connector.clone();
+        let server_name = server_name.clone();
+        let address = address.clone();
+        let meter = meter.clone();
+        async move {
+            let tcp = MeteredIo::new(TcpStream::connect(address).await?, meter);
+            let tls = connector.connect(server_name, tcp).await?;
+            Ok::<_, std::io::Error>(TokioIo::new(tls))
+        }
+    }));
+    Ok(InterceptedService::new(channel, headers.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIN: &str = "AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89";
+
+    #[test]
+    fn test_fingerprint_normalization() {
+        let server = KnownServer::new("https://lwd.example.com:9067/", Network::Mainnet)
+            .with_fingerprint(PIN)
+            .unwrap();
+        assert_eq!(server.endpoint, "https://lwd.example.com:9067");
+        assert_eq!(server.fingerprints[0], PIN.replace(':', "").to_lowercase());
+        assert!(KnownServer::new("https://x", Network::Mainnet)
+            .with_fingerprint("abcd")
+            .is_err());
+        assert_eq!(certificate_fingerprint(b"").len(), 64);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let mut registry = ServerRegistry::new();
+        registry.add(
+            KnownServer::new("https://pinned.example.com:9067", Network::Mainnet)
+                .with_fingerprint(PIN)
+                .unwrap(),
+        );
+        registry.add(KnownServer::new(
+            "https://unpinned.example.com:9067",
+            Network::Mainnet,
+        ));
+
+        // Non-strict: unknown servers connect without pinning
+        assert!(registry
+            .resolve("https://other.example.com:9067", Network::Mainnet)
+            .unwrap()
+            .is_none());
+
+        registry.set_strict(true);
+        assert!(registry
+            .resolve("https://pinned.example.com:9067/", Network::Mainnet)
+            .unwrap()
+            .is_some());
+        assert!(registry
+            .resolve("https://pinned.example.com:9067", Network::Testnet)
+            .is_err());
+        assert!(registry
+            .resolve("https://unpinned.example.com:9067", Network::Mainnet)
+            .is_err());
+        assert!(registry
+            .resolve("https://other.example.com:9067", Network::Mainnet)
+            .is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("numi_servers_{}.json", rand::random::<u64>()));
+        registry.save(&path).unwrap();
+        assert_eq!(ServerRegistry::load(&path).unwrap(), registry);
+    }
+}
@@ -0,0 +1,233 @@
+//! Spend authorization
+//!
+//! Transactions are built as Partially Created Zcash Transactions (PCZTs)
+//! and handed to a [`Signer`] for their spend authorization signatures, so
+//! the keys can live outside the SDK: on a hardware wallet (Ledger,
+//! Keystone), in an HSM, or on an air-gapped machine. Implementations add
+//! the signatures they can and return the PCZT; proving, extraction and
+//! broadcast stay with the wallet.
+//!
+//! [`UskSigner`], the default implementation backed by the wallet's
+//! in-memory unified spending key, and [`send_with_signer`] require the
+//! `pczt` feature.
+
+use crate::error::Result;
+use crate::types::Network;
+use async_trait::async_trait;
+
+/// Adds spend authorization signatures to PCZTs
+#[async_trait]
+pub trait Signer: Send + Sync {
+    /// Name shown to users, e.g. when asking them to confirm on a device
+    fn name(&self) -> &str;
+
+    /// Sign every spend of a serialized PCZT that this signer holds a key for
+    ///
+    /// Spends of other keys are left untouched. Fails if the PCZT is for
+    /// another network or contains no spend this signer can authorize.
+    ///
+    /// # Returns
+    /// The serialized signed PCZT
+    async fn sign_pczt(&self, network: Network, pczt: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+#[cfg(feature = "pczt")]
+pub use self::usk::{send_with_signer, send_with_signer_idempotent, UskSigner};
+
+#[cfg(feature = "pczt")]
+mod usk {
+    use super::Signer;
+    use crate::airgap::{
+        create_signing_request, extract_and_broadcast, sign_request_with, AirgapEnvelope,
+    };
+    use crate::error::{Error, Result};
+    use crate::idempotency::{
+        payment_request_fingerprint, IdempotencyStore, Reservation, SendState,
+    };
+    use crate::light_client::LightClient;
+    use crate::types::Network;
+    use crate::wallet::Wallet;
+    use async_trait::async_trait;
+    use pczt::roles::signer::Signer as PcztSigner;
+    use pczt::Pczt;
+    use zcash_keys::keys::UnifiedSpendingKey;
+    use zcash_transparent::keys::NonHardenedChildIndex;
+
+    /// Number of transparent child indices tried per scope when signing
+    const TRANSPARENT_SIGNING_GAP: u32 = 20;
+
+    /// Signer backed by an in-memory unified spending key
+    ///
+    /// Deliberately not `Debug`: it holds the spending key.
+    pub struct UskSigner {
+        usk: UnifiedSpendingKey,
+        network: Network,
+    }
+
+    impl UskSigner {
+        /// Create a signer for a spending key
+        pub fn new(usk: UnifiedSpendingKey, network: Network) -> Self {
+            Self { usk, network }
+        }
+
+        /// Create a signer for the wallet's selected account
+        pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+            Ok(Self::new(
+                wallet.get_unified_spending_key()?,
+                wallet.network(),
+            ))
+        }
+
+        /// Sign every Orchard, Sapling and transparent spend this key can
+        /// authorize
+        pub fn sign(&self, pczt: Pczt) -> Result<Pczt> {
+            let transparent_inputs = pczt.transparent().inputs().len();
+            let sapling_spends = pczt.sapling().spends().len();
+            let orchard_actions = pczt.orchard().actions().len();
+
+            let mut signer = PcztSigner::new(pczt)
+                .map_err(|e| Error::Transaction(format!("Failed to initialize signer: {:?}", e)))?;
+            let mut signed = 0usize;
+
+            let orchard_ask = orchard::keys::SpendAuthorizingKey::from(self.usk.orchard());
+            for index in 0..orchard_actions {
+                if signer.sign_orchard(index, &orchard_ask).is_ok() {
+                    signed += 1;
+                }
+            }
+
+            let sapling_ask = &self.usk.sapling().expsk.ask;
+            for index in 0..sapling_spends {
+                if signer.sign_sapling(index, sapling_ask).is_ok() {
+                    signed += 1;
+                }
+            }
+
+            let account_key = self.usk.transparent();
+            let mut transparent_keys = Vec::new();
+            for i in 0..TRANSPARENT_SIGNING_GAP {
+                let child = NonHardenedChildIndex::from_index(i)
+                    .ok_or_else(|| Error::KeyDerivation(format!("Invalid child index {}", i)))?;
+                for key in [
+                    account_key.derive_external_secret_key(child),
+                    account_key.derive_internal_secret_key(child),
+                ] {
+                    transparent_keys.push(key.map_err(|e| {
+                        Error::KeyDerivation(format!("Failed to derive transparent key: {:?}", e))
+                    })?);
+                }
+            }
+            for index in 0..transparent_inputs {
+                if transparent_keys
+                    .iter()
+                    .any(|sk| signer.sign_transparent(index, sk).is_ok())
+                {
+                    signed += 1;
+                }
+            }
+
+            if signed == 0 {
+                return Err(Error::Transaction(
+                    "PCZT contains no spends this wallet can sign".to_string(),
+                ));
+            }
+            tracing::info!("Signed {} spends in PCZT", signed);
+            Ok(signer.finish())
+        }
+    }
+
+    #[async_trait]
+    impl Signer for UskSigner {
+        fn name(&self) -> &str {
+            "in-memory spending key"
+        }
+
+        async fn sign_pczt(&self, network: Network, pczt: Vec<u8>) -> Result<Vec<u8>> {
+            if network != self.network {
+                return Err(Error::InvalidParameter(format!(
+                    "PCZT is for {:?} but the key is for {:?}",
+                    network, self.network
+                )));
+            }
+            let pczt = Pczt::parse(&pczt)
+                .map_err(|e| Error::Transaction(format!("Failed to parse PCZT: {:?}", e)))?;
+            Ok(self.sign(pczt)?.serialize())
+        }
+    }
+
+    /// Build, sign, prove and broadcast a payment with any [`Signer`]
+    ///
+    /// The wallet only needs its viewing key and sync state; spend
+    /// authorization comes from `signer`. The transaction is built with
+    /// [`create_signing_request`], so it is checked against the wallet's
+    /// stored spending policies and recorded in its audit log, and payments
+    /// at or above the wallet's approval threshold are rejected.
+    ///
+    /// # Returns
+    /// The transaction ID (hex encoded)
+    pub async fn send_with_signer(
+        wallet: &Wallet,
+        request: zip321::TransactionRequest,
+        signer: &dyn Signer,
+        light_client: &mut LightClient,
+    ) -> Result<String> {
+        let signed = build_and_sign(wallet, request, signer).await?;
+        extract_and_broadcast(wallet, &signed, light_client).await
+    }
+
+    /// Send like [`send_with_signer`], but at most once per idempotency key
+    ///
+    /// Keys are recorded in the wallet's [`IdempotencyStore`] like those of
+    /// [`send_many_idempotent`](crate::transaction::TransactionBuilder::send_many_idempotent),
+    /// with the transaction ID as the operation. Retrying with the same key
+    /// and request returns the original transaction ID.
+    ///
+    /// # Note
+    /// The key is released for another attempt only if building or signing
+    /// the transaction fails. If proving, storing or broadcasting fails, the
+    /// outcome is unknown and retrying returns an error rather than risk a
+    /// double spend. Check the wallet's transactions and then call
+    /// [`IdempotencyStore::record_operation`] or [`IdempotencyStore::release`].
+    pub async fn send_with_signer_idempotent(
+        idempotency_key: &str,
+        wallet: &Wallet,
+        request: zip321::TransactionRequest,
+        signer: &dyn Signer,
+        light_client: &mut LightClient,
+    ) -> Result<String> {
+        let store = IdempotencyStore::for_wallet(wallet)?;
+        let fingerprint = payment_request_fingerprint(&request.to_uri());
+
+        match store.reserve(idempotency_key, &fingerprint)? {
+            Reservation::New => {}
+            Reservation::Existing(SendState::Submitted { operation_id: txid })
+            | Reservation::Existing(SendState::Completed { txid, .. }) => return Ok(txid),
+            Reservation::Existing(SendState::InFlight) => {
+                return Err(Error::Transaction(format!(
+                    "Outcome of the previous send with idempotency key {} is unknown",
+                    idempotency_key
+                )));
+            }
+        }
+
+        // Nothing leaves the wallet before the transaction is signed
+        let signed = match build_and_sign(wallet, request, signer).await {
+            Ok(signed) => signed,
+            Err(e) => return store.release(idempotency_key).and(Err(e)),
+        };
+        let txid = extract_and_broadcast(wallet, &signed, light_client).await?;
+        store.record_operation(idempotency_key, &txid)?;
+        store.record_txid(idempotency_key, &txid)?;
+        Ok(txid)
+    }
+
+    async fn build_and_sign(
+        wallet: &Wallet,
+        request: zip321::TransactionRequest,
+        signer: &dyn Signer,
+    ) -> Result<AirgapEnvelope> {
+        let unsigned = create_signing_request(wallet, request)?;
+        tracing::info!("Requesting signatures from {}", signer.name());
+        sign_request_with(signer, &unsigned).await
+    }
+}
@@ -0,0 +1,248 @@
+//! Warm-start wallet snapshots
+//!
+//! The first sync of a wallet scans every block since its birthday, which can
+//! take a long time on a phone. A provisioning service can instead sync the
+//! wallet's viewing key server-side, export a snapshot with
+//! [`Wallet::export_snapshot`](crate::wallet::Wallet::export_snapshot) and
+//! ship it to the device, which installs it with
+//! [`Wallet::import_snapshot`](crate::wallet::Wallet::import_snapshot) and
+//! continues syncing from the snapshot's height.
+//!
+//! A snapshot is a compacted copy of the wallet database: scan progress, note
+//! commitment trees and witnesses, notes and transactions. It holds viewing
+//! keys but never the seed or spending keys. SDK tables (`numi_*`, such as the
+//! address book and contacts) are local to each device and are left out;
+//! the device's own tables are kept on import. A `numi_snapshot` table
+//! records a [`SnapshotInfo`] so the device can check the snapshot belongs to
+//! its wallet before installing it.
+
+use crate::db_encryption::{is_encrypted, open_connection};
+use crate::error::{Error, Result};
+use crate::store::db_error;
+use crate::types::{Balance, Network};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Current snapshot format version
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Metadata stored in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub version: u32,
+    pub network: Network,
+    /// Unix time the snapshot was taken
+    pub created_at: u64,
+    /// Highest scanned block height, or `None` if nothing was scanned
+    pub scanned_height: Option<u64>,
+    /// Encoded unified full viewing keys of the accounts in the snapshot
+    pub ufvks: Vec<String>,
+    /// Wallet balance at `scanned_height`
+    pub balance: Balance,
+}
+
+const DB_CONTEXT: &str = "Snapshot error";
+
+/// Names and schemas of the SDK tables in a database
+fn sdk_tables(conn: &Connection, schema: &str) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT name, sql FROM {}.sqlite_master
+             WHERE type = 'table' AND name LIKE 'numi\\_%' ESCAPE '\\'
+             AND name != 'numi_snapshot'",
+            schema
+        ))
+        .map_err(db_error(DB_CONTEXT))?;
+    let tables = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(db_error(DB_CONTEXT))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(db_error(DB_CONTEXT))?;
+    Ok(tables)
+}
+
+/// Snapshots are plain SQLite files, so they cannot carry or be merged into
+/// an encrypted database's pages
+fn reject_encrypted(db_path: &Path) -> Result<()> {
+    if is_encrypted(db_path) {
+        return Err(Error::InvalidParameter(
+            "Snapshots are not supported for encrypted wallet databases".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Write a snapshot of the database at `db_path` to `dest`
+///
+/// `dest` must not exist.
+pub(crate) fn write_snapshot(db_path: &Path, info: &SnapshotInfo, dest: &Path) -> Result<()> {
+    reject_encrypted(db_path)?;
+    if dest.exists() {
+        return Err(Error::InvalidParameter(format!(
+            "Snapshot file {} already exists",
+            dest.display()
+        )));
+    }
+    let dest_str = dest
+        .to_str()
+        .ok_or_else(|| Error::InvalidParameter("Snapshot path is not valid UTF-8".to_string()))?;
+
+    // VACUUM INTO takes a transactionally consistent, compacted copy
+    open_connection(db_path)
+        .and_then(|conn| conn.execute("VACUUM INTO ?1", [dest_str]))
+        .map_err(db_error(DB_CONTEXT))?;
+
+    let snapshot = Connection::open(dest).map_err(db_error(DB_CONTEXT))?;
+    for (table, _) in sdk_tables(&snapshot, "main")? {
+        snapshot
+            .execute(&format!("DROP TABLE \"{}\"", table), [])
+            .map_err(db_error(DB_CONTEXT))?;
+    }
+    snapshot
+        .execute_batch("CREATE TABLE numi_snapshot (info TEXT NOT NULL);")
+        .map_err(db_error(DB_CONTEXT))?;
+    snapshot
+        .execute(
+            "INSERT INTO numi_snapshot (info) VALUES (?1)",
+            [serde_json::to_string(info)?],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+    snapshot
+        .execute("VACUUM", [])
+        .map_err(db_error(DB_CONTEXT))?;
+    Ok(())
+}
+
+/// Read the metadata of a snapshot file without installing it
+pub fn read_snapshot_info(path: &Path) -> Result<SnapshotInfo> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(db_error(DB_CONTEXT))?;
+    let info: String = conn
+        .query_row("SELECT info FROM numi_snapshot", [], |row| row.get(0))
+        .map_err(|_| Error::InvalidParameter("Not a wallet snapshot file".to_string()))?;
+    let info: SnapshotInfo = serde_json::from_str(&info)?;
+    if info.version > SNAPSHOT_VERSION {
+        return Err(Error::InvalidParameter(format!(
+            "Unsupported snapshot version {}",
+            info.version
+        )));
+    }
+    Ok(info)
+}
+
+/// Replace the database at `db_path` with a snapshot, keeping its SDK tables
+///
+/// The snapshot is prepared in a temporary file next to the database and
+/// moved into place, so a failure leaves the existing database untouched.
+pub(crate) fn install_snapshot(snapshot: &Path, db_path: &Path) -> Result<()> {
+    reject_encrypted(db_path)?;
+    let staging = sibling(db_path, "snapshot-import");
+    std::fs::copy(snapshot, &staging)?;
+
+    let prepared = (|| {
+        let conn = Connection::open(&staging).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch("DROP TABLE numi_snapshot;")
+            .map_err(db_error(DB_CONTEXT))?;
+        if db_path.exists() {
+            let db_str = db_path.to_str().ok_or_else(|| {
+                Error::InvalidParameter("Wallet path is not valid UTF-8".to_string())
+            })?;
+            conn.execute("ATTACH DATABASE ?1 AS device", [db_str])
+                .map_err(db_error(DB_CONTEXT))?;
+            for (table, sql) in sdk_tables(&conn, "device")? {
+                conn.execute(&format!("DROP TABLE IF EXISTS main.\"{}\"", table), [])
+                    .map_err(db_error(DB_CONTEXT))?;
+                conn.execute(&sql, []).map_err(db_error(DB_CONTEXT))?;
+                conn.execute(
+                    &format!(
+                        "INSERT INTO main.\"{0}\" SELECT * FROM device.\"{0}\"",
+                        table
+                    ),
+                    [],
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+            }
+            conn.execute("DETACH DATABASE device", [])
+                .map_err(db_error(DB_CONTEXT))?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = prepared {
+        let _ = std::fs::remove_file(&staging);
+        return Err(e);
+    }
+
+    for suffix in ["wal", "shm"] {
+        let _ = std::fs::remove_file(sibling(db_path, suffix));
+    }
+    std::fs::rename(&staging, db_path)?;
+    Ok(())
+}
+
+/// `db_path` with `-suffix` appended to the file name
+fn sibling(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("-{}", suffix));
+    db_path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn info() -> SnapshotInfo {
+        SnapshotInfo {
+            version: SNAPSHOT_VERSION,
+            network: Network::Testnet,
+            created_at: 1,
+            scanned_height: Some(2_000_000),
+            ufvks: vec!["uviewtest1".to_string()],
+            balance: Balance::default(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_keeps_device_tables() {
+        let server = TempDb::new();
+        let conn = Connection::open(&server).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE blocks (height INTEGER PRIMARY KEY);
+             INSERT INTO blocks VALUES (2000000);
+             CREATE TABLE numi_contacts (label TEXT PRIMARY KEY, address TEXT);
+             INSERT INTO numi_contacts VALUES ('server', 'addr');",
+        )
+        .unwrap();
+
+        let snapshot = TempDb::new();
+        write_snapshot(&server, &info(), &snapshot).unwrap();
+        assert_eq!(read_snapshot_info(&snapshot).unwrap(), info());
+        assert!(write_snapshot(&server, &info(), &snapshot).is_err());
+
+        let device = TempDb::new();
+        Connection::open(&device)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE numi_contacts (label TEXT PRIMARY KEY, address TEXT);
+                 INSERT INTO numi_contacts VALUES ('device', 'addr');",
+            )
+            .unwrap();
+        install_snapshot(&snapshot, &device).unwrap();
+
+        let conn = Connection::open(&device).unwrap();
+        let height: i64 = conn
+            .query_row("SELECT height FROM blocks", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(height, 2_000_000);
+        let labels: Vec<String> = conn
+            .prepare("SELECT label FROM numi_contacts")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(labels, vec!["device".to_string()]);
+        assert!(read_snapshot_info(&device).is_err());
+    }
+}
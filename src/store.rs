@@ -0,0 +1,66 @@
+//! Helpers shared by the SDK's SQLite stores
+
+use crate::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Map SQLite errors to [`Error::Database`], prefixed with `context`
+///
+/// Used as `.map_err(db_error(DB_CONTEXT))`, with the store's context
+/// (e.g. "Audit log error") in a `DB_CONTEXT` constant.
+pub(crate) fn db_error(context: &'static str) -> impl Fn(rusqlite::Error) -> Error {
+    move |e| Error::Database(format!("{}: {}", context, e))
+}
+
+/// Current Unix time in seconds, or 0 if the clock is set before 1970
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Current Unix time in milliseconds, or 0 if the clock is set before 1970
+pub(crate) fn unix_now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+/// Database path in a fresh temporary directory, for tests
+///
+/// The directory is deleted with the database and SQLite's journal files
+/// when the value is dropped, so it must outlive every connection to it.
+#[cfg(test)]
+pub(crate) struct TempDb {
+    _dir: tempfile::TempDir,
+    path: std::path::PathBuf,
+}
+
+#[cfg(test)]
+impl TempDb {
+    pub(crate) fn new() -> Self {
+        let dir = tempfile::Builder::new()
+            .prefix("numi_test_")
+            .tempdir()
+            .expect("Failed to create temporary directory");
+        let path = dir.path().join("test.db");
+        Self { _dir: dir, path }
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDb {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempDb {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.path
+    }
+}
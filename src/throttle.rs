@@ -0,0 +1,222 @@
+//! Sync throttling for mobile hosts
+//!
+//! A [`SyncThrottle`] is a shared handle that a host app drives from OS
+//! lifecycle events (foreground/background, low-power mode) while a
+//! [`LightClient::sync`](crate::light_client::LightClient::sync) is running:
+//! - [`pause`](SyncThrottle::pause) holds sync before the next batch until
+//!   [`resume`](SyncThrottle::resume) is called
+//! - A smaller batch size bounds the work done per wake-up
+//! - A yield interval sleeps between batches so the CPU and radio can idle
+//!
+//! [`SyncThrottle::set_lifecycle`] applies a preset for each
+//! [`LifecycleState`]; the individual controls can be adjusted afterwards.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Default number of blocks fetched and scanned per batch
+pub const DEFAULT_SYNC_BATCH_SIZE: u64 = 100;
+
+/// App lifecycle states reported by the host OS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LifecycleState {
+    /// App is visible; sync at full speed
+    Foreground,
+    /// App is in the background; sync in small, spaced-out batches
+    Background,
+    /// Device is in low-power mode or on a low battery
+    LowPower,
+    /// App is about to be suspended; hold sync until resumed
+    Suspended,
+}
+
+#[derive(Debug)]
+struct State {
+    paused: AtomicBool,
+    /// Foreground batch size the lifecycle presets scale from
+    base_batch_size: AtomicU64,
+    batch_size: AtomicU64,
+    yield_millis: AtomicU64,
+    resumed: Notify,
+}
+
+/// Shared pause, batch size and yield controls for sync
+///
+/// Cloning the throttle is cheap; all clones control the same syncs.
+#[derive(Debug, Clone)]
+pub struct SyncThrottle {
+    state: Arc<State>,
+}
+
+impl SyncThrottle {
+    /// Create a throttle that does not limit sync
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(State {
+                paused: AtomicBool::new(false),
+                base_batch_size: AtomicU64::new(DEFAULT_SYNC_BATCH_SIZE),
+                batch_size: AtomicU64::new(DEFAULT_SYNC_BATCH_SIZE),
+                yield_millis: AtomicU64::new(0),
+                resumed: Notify::new(),
+            }),
+        }
+    }
+
+    /// Hold sync before its next batch
+    pub fn pause(&self) {
+        self.state.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Let paused syncs continue
+    pub fn resume(&self) {
+        self.state.paused.store(false, Ordering::SeqCst);
+        self.state.resumed.notify_waiters();
+    }
+
+    /// Whether sync is paused
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::SeqCst)
+    }
+
+    /// Set the number of blocks fetched and scanned per batch (at least 1)
+    pub fn set_batch_size(&self, blocks: u64) {
+        self.state
+            .batch_size
+            .store(blocks.max(1), Ordering::Relaxed);
+    }
+
+    /// Number of blocks fetched and scanned per batch
+    pub fn batch_size(&self) -> u64 {
+        self.state.batch_size.load(Ordering::Relaxed)
+    }
+
+    /// Set the foreground batch size the lifecycle presets scale from
+    ///
+    /// Also applies it as the current batch size. Used by scan auto-tuning
+    /// (see [`crate::tuning`]) so the presets keep their proportions on fast
+    /// and slow devices.
+    pub fn set_base_batch_size(&self, blocks: u64) {
+        self.state
+            .base_batch_size
+            .store(blocks.max(1), Ordering::Relaxed);
+        self.set_batch_size(blocks);
+    }
+
+    /// Set how long sync sleeps between batches
+    pub fn set_yield_interval(&self, interval: Duration) {
+        let millis = u64::try_from(interval.as_millis()).unwrap_or(u64::MAX);
+        self.state.yield_millis.store(millis, Ordering::Relaxed);
+    }
+
+    /// How long sync sleeps between batches
+    pub fn yield_interval(&self) -> Duration {
+        Duration::from_millis(self.state.yield_millis.load(Ordering::Relaxed))
+    }
+
+    /// Apply the preset controls for an app lifecycle state
+    ///
+    /// Batch sizes are relative to the base batch size (100 by default):
+    ///
+    /// | State        | Paused | Batch size | Yield interval |
+    /// |--------------|--------|------------|----------------|
+    /// | `Foreground` | no     | base       | none           |
+    /// | `Background` | no     | base / 5   | 500 ms         |
+    /// | `LowPower`   | no     | base / 10  | 2 s            |
+    /// | `Suspended`  | yes    | unchanged  | unchanged      |
+    pub fn set_lifecycle(&self, state: LifecycleState) {
+        let base = self.state.base_batch_size.load(Ordering::Relaxed);
+        let (batch_size, yield_interval) = match state {
+            LifecycleState::Suspended => {
+                self.pause();
+                return;
+            }
+            LifecycleState::Foreground => (base, Duration::ZERO),
+            LifecycleState::Background => (base / 5, Duration::from_millis(500)),
+            LifecycleState::LowPower => (base / 10, Duration::from_secs(2)),
+        };
+        self.set_batch_size(batch_size);
+        self.set_yield_interval(yield_interval);
+        self.resume();
+    }
+
+    /// Wait until sync is not paused
+    pub async fn wait_until_resumed(&self) {
+        loop {
+            // Register for the wake-up before checking the flag so a resume
+            // between the check and the await is not missed
+            let resumed = self.state.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+            tracing::debug!("Sync paused, waiting to resume");
+            resumed.await;
+        }
+    }
+
+    /// Sleep for the yield interval, if any
+    pub async fn yield_between_batches(&self) {
+        let interval = self.yield_interval();
+        if !interval.is_zero() {
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+impl Default for SyncThrottle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_presets() {
+        let throttle = SyncThrottle::new();
+        let host = throttle.clone();
+
+        host.set_lifecycle(LifecycleState::Background);
+        assert_eq!(throttle.batch_size(), 20);
+        assert_eq!(throttle.yield_interval(), Duration::from_millis(500));
+
+        host.set_lifecycle(LifecycleState::Suspended);
+        assert!(throttle.is_paused());
+        assert_eq!(throttle.batch_size(), 20);
+
+        host.set_lifecycle(LifecycleState::Foreground);
+        assert!(!throttle.is_paused());
+        assert_eq!(throttle.batch_size(), DEFAULT_SYNC_BATCH_SIZE);
+        assert_eq!(throttle.yield_interval(), Duration::ZERO);
+
+        host.set_batch_size(0);
+        assert_eq!(throttle.batch_size(), 1);
+
+        host.set_base_batch_size(1_000);
+        host.set_lifecycle(LifecycleState::LowPower);
+        assert_eq!(throttle.batch_size(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_pause_holds_until_resumed() {
+        let throttle = SyncThrottle::new();
+        throttle.pause();
+
+        let waiter = {
+            let throttle = throttle.clone();
+            tokio::spawn(async move { throttle.wait_until_resumed().await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        throttle.resume();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("sync should resume")
+            .unwrap();
+    }
+}
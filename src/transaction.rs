@@ -3,19 +3,39 @@
 //! This module provides transaction building capabilities for Zcash using the
 //! official Zcash Payment API (z_sendmany) via RPC, which is the recommended
 //! approach for new integrations according to the Zcash Integration Guide.
+//!
+//! Every send is logged under a [`CorrelationId`], from validation through
+//! the zcashd operation to the confirmations of its transaction.
+
+pub mod amount_policy;
+pub mod decode;
+pub mod txid;
 
 use crate::address::{is_shielded_address, parse_address};
-use crate::client::RpcClient;
+use crate::approval::{check_threshold, ApprovalWorkflow, Claim, ProposalStatus};
+use crate::audit::AuditEvent;
+use crate::client::{rpc_error_code, RpcClient};
+use crate::correlation::CorrelationId;
 use crate::error::{Error, Result};
 use crate::fees::{calculate_fee_from_payments, fee_zatoshis_to_zec};
-use crate::rpc::Payment;
-use crate::wallet::Wallet;
+use crate::idempotency::{request_fingerprint, IdempotencyStore, Reservation, SendState};
+use crate::params::NetworkParams;
+use crate::policy::SpendingPolicyEngine;
+use crate::rpc::{Payment, PrivacyPolicy, RawPayment};
+use crate::wallet::contacts::Contacts;
+use crate::wallet::{ChangePolicy, Wallet};
+use amount_policy::{payment_zatoshis, AmountPolicy};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::Instrument;
+use zcash_protocol::{PoolType, ShieldedProtocol};
 
 /// Maximum memo size in bytes (Zcash protocol limit)
 const MAX_MEMO_SIZE: usize = 512;
 
-/// Maximum ZEC amount (sanity check - 21 million ZEC total supply)
-const MAX_ZEC_AMOUNT: f64 = 21_000_000.0;
+/// How often [`TransactionBuilder::wait_for_confirmations`] polls zcashd
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 /// Transaction builder for creating and sending Zcash transactions
 ///
@@ -25,6 +45,11 @@ const MAX_ZEC_AMOUNT: f64 = 21_000_000.0;
 pub struct TransactionBuilder {
     wallet: Wallet,
     rpc_client: Option<RpcClient>,
+    spending_policy: Option<SpendingPolicyEngine>,
+    amount_policy: AmountPolicy,
+    approval: Option<ApprovalWorkflow>,
+    /// Correlation IDs of sends by operation ID and txid
+    correlations: Mutex<HashMap<String, CorrelationId>>,
 }
 
 impl TransactionBuilder {
@@ -36,6 +61,10 @@ impl TransactionBuilder {
         TransactionBuilder {
             wallet,
             rpc_client: None,
+            spending_policy: None,
+            amount_policy: AmountPolicy::default(),
+            approval: None,
+            correlations: Mutex::new(HashMap::new()),
         }
     }
 
@@ -46,6 +75,10 @@ impl TransactionBuilder {
         TransactionBuilder {
             wallet,
             rpc_client: Some(rpc_client),
+            spending_policy: None,
+            amount_policy: AmountPolicy::default(),
+            approval: None,
+            correlations: Mutex::new(HashMap::new()),
         }
     }
 
@@ -54,6 +87,49 @@ impl TransactionBuilder {
         self.rpc_client = Some(rpc_client);
     }
 
+    /// Evaluate every send against a spending policy before submitting it
+    ///
+    /// Blocked sends fail with [`Error::PolicyViolation`]; allowed sends are
+    /// recorded in the engine's audit log. If the policy requires a privacy
+    /// policy, it is passed to zcashd with each send.
+    pub fn set_spending_policy(&mut self, engine: SpendingPolicyEngine) {
+        self.spending_policy = Some(engine);
+    }
+
+    /// The spending policy engine, if one is configured
+    pub fn spending_policy(&self) -> Option<&SpendingPolicyEngine> {
+        self.spending_policy.as_ref()
+    }
+
+    /// Check payment amounts against `policy` instead of the default limits
+    ///
+    /// Sends with a payment or total outside the policy fail with
+    /// [`Error::Transaction`] before reaching zcashd.
+    pub fn set_amount_policy(&mut self, policy: AmountPolicy) {
+        self.amount_policy = policy;
+    }
+
+    /// The payment amount limits
+    pub fn amount_policy(&self) -> &AmountPolicy {
+        &self.amount_policy
+    }
+
+    /// Send approved proposals of a two-person approval workflow
+    ///
+    /// Sends at or above the workflow's threshold fail with
+    /// [`Error::PolicyViolation`] unless made with
+    /// [`execute_proposal`](Self::execute_proposal). The threshold is
+    /// recorded in the wallet database, so this holds for every builder on
+    /// the wallet once the workflow has been opened.
+    pub fn set_approval_workflow(&mut self, workflow: ApprovalWorkflow) {
+        self.approval = Some(workflow);
+    }
+
+    /// The approval workflow, if one is configured
+    pub fn approval_workflow(&self) -> Option<&ApprovalWorkflow> {
+        self.approval.as_ref()
+    }
+
     /// Estimate ZIP-317 fee for a transaction based on payments
     ///
     /// This estimates the fee using ZIP-317 fee calculation:
@@ -106,33 +182,151 @@ impl TransactionBuilder {
         minconf: Option<u32>,
         fee: Option<f64>,
     ) -> Result<String> {
-        let rpc_client = self
-            .rpc_client
+        self.send_many_correlated(CorrelationId::new(), from_address, payments, minconf, fee)
+            .await
+    }
+
+    /// Send like [`send_many`](Self::send_many) under a chosen correlation ID
+    ///
+    /// Pass the caller's own identifier, e.g. a payout ID, to find the log
+    /// events and audit log entries of the send by it.
+    pub async fn send_many_correlated(
+        &self,
+        correlation_id: CorrelationId,
+        from_address: &str,
+        payments: Vec<Payment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<String> {
+        let span = correlation_id.span();
+        self.send_many_inner(&correlation_id, from_address, payments, minconf, fee)
+            .instrument(span)
+            .await
+    }
+
+    /// Send an approved proposal of the [approval workflow](Self::set_approval_workflow)
+    ///
+    /// Proposals at or above the threshold can only be sent this way.
+    /// Executing a proposal again returns its operation ID without sending.
+    ///
+    /// # Arguments
+    /// * `proposal_id` - ID returned by [`ApprovalWorkflow::propose`]
+    ///
+    /// # Returns
+    /// Operation ID that can be used to check transaction status
+    ///
+    /// # Note
+    /// Like [`send_many_idempotent`](Self::send_many_idempotent), a proposal
+    /// is released for another attempt only if the send failed its checks or
+    /// zcashd rejected it with a JSON-RPC error.
+    pub async fn execute_proposal(&self, proposal_id: &str) -> Result<String> {
+        let workflow = self
+            .approval
             .as_ref()
-            .ok_or_else(|| Error::Transaction("RPC client not configured".to_string()))?;
+            .ok_or_else(|| Error::Transaction("Approval workflow not configured".to_string()))?;
+        let proposal = match workflow.claim(proposal_id, false)? {
+            Claim::Ready(proposal) => proposal,
+            Claim::Executed(operation_id) => return Ok(operation_id),
+        };
+
+        let correlation_id = CorrelationId::new();
+        let span = correlation_id.span();
+        let send = async {
+            let checked = self.check_send(
+                &correlation_id,
+                &proposal.from_address,
+                &proposal.payments,
+                proposal.fee,
+                true,
+            );
+            let privacy = match checked {
+                Ok(privacy) => privacy,
+                Err(e) => return (false, Err(e)),
+            };
+            let submitted = self
+                .submit_send(
+                    &correlation_id,
+                    &proposal.from_address,
+                    proposal.payments.clone(),
+                    proposal.minconf,
+                    proposal.fee,
+                    privacy,
+                )
+                .await;
+            (true, submitted)
+        };
+        let (submitted, result) = send.instrument(span).await;
+        match result {
+            Ok(operation_id) => {
+                let status = ProposalStatus::Executed {
+                    operation_id: operation_id.clone(),
+                };
+                workflow.finish(proposal, Some(status))?;
+                Ok(operation_id)
+            }
+            // Nothing was sent
+            Err(e) if !submitted || rpc_error_code(&e).is_some() => {
+                workflow.finish(proposal, None)?;
+                Err(e)
+            }
+            // The outcome is unknown; keep the proposal claimed
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn send_many_inner(
+        &self,
+        correlation_id: &CorrelationId,
+        from_address: &str,
+        payments: Vec<Payment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<String> {
+        let privacy = self.check_send(correlation_id, from_address, &payments, fee, false)?;
+        self.submit_send(
+            correlation_id,
+            from_address,
+            payments,
+            minconf,
+            fee,
+            privacy,
+        )
+        .await
+    }
+
+    /// Validate a send and check it against the amount and spending policies
+    /// and, unless it is an `approved` proposal, the approval threshold
+    ///
+    /// # Returns
+    /// The privacy policy set by the spending policy, if any
+    fn check_send(
+        &self,
+        correlation_id: &CorrelationId,
+        from_address: &str,
+        payments: &[Payment],
+        fee: Option<f64>,
+        approved: bool,
+    ) -> Result<Option<PrivacyPolicy>> {
+        if self.rpc_client.is_none() {
+            return Err(Error::Transaction("RPC client not configured".to_string()));
+        }
 
         // Validate the from address format
         let network = self.wallet.consensus_network();
         parse_address(from_address, network)?;
+        check_change_pool(self.wallet.change_policy(), from_address, network)?;
 
         // Validate all payment addresses and payments
+        let mut amounts = Vec::with_capacity(payments.len());
         for (idx, payment) in payments.iter().enumerate() {
             // Validate address format
             parse_address(&payment.address, network)?;
 
             // Validate amount
-            if payment.amount <= 0.0 {
-                return Err(Error::Transaction(format!(
-                    "Payment {} has invalid amount: {} ZEC (must be positive)",
-                    idx, payment.amount
-                )));
-            }
-            if payment.amount > MAX_ZEC_AMOUNT {
-                return Err(Error::Transaction(format!(
-                    "Payment {} has excessive amount: {} ZEC (max: {} ZEC)",
-                    idx, payment.amount, MAX_ZEC_AMOUNT
-                )));
-            }
+            let amount = payment_zatoshis(idx, payment.amount)?;
+            let has_memo = payment.memo.as_ref().is_some_and(|memo| !memo.is_empty());
+            self.amount_policy.check_payment(idx, amount, has_memo)?;
+            amounts.push(amount);
 
             // Validate memo
             if let Some(ref memo) = payment.memo {
@@ -155,10 +349,285 @@ impl TransactionBuilder {
                 }
             }
         }
+        let total = amounts
+            .iter()
+            .fold(0u64, |total, amount| total.saturating_add(*amount));
+        self.amount_policy.check_batch(amounts)?;
+        if !approved {
+            check_threshold(self.wallet.db_path(), total)?;
+        }
 
-        rpc_client
-            .z_sendmany(from_address, payments, minconf, fee)
-            .await
+        tracing::debug!(
+            "Validated {} payment(s) from {}",
+            payments.len(),
+            from_address
+        );
+        self.log_fee(payments, from_address, fee)?;
+
+        let account = self.wallet.account_index();
+        let privacy = match &self.spending_policy {
+            Some(policy) => {
+                let privacy = policy.effective_privacy(account, None);
+                policy.evaluate(
+                    account,
+                    from_address,
+                    payments,
+                    privacy,
+                    Some(correlation_id),
+                )?;
+                privacy
+            }
+            None => None,
+        };
+        Ok(privacy)
+    }
+
+    /// Submit a checked send to zcashd with `z_sendmany`
+    async fn submit_send(
+        &self,
+        correlation_id: &CorrelationId,
+        from_address: &str,
+        payments: Vec<Payment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+        privacy: Option<PrivacyPolicy>,
+    ) -> Result<String> {
+        let rpc_client = self
+            .rpc_client
+            .as_ref()
+            .ok_or_else(|| Error::Transaction("RPC client not configured".to_string()))?;
+        tracing::info!("Submitting {} payment(s) to zcashd", payments.len());
+        let submitted = match privacy {
+            Some(privacy) => {
+                rpc_client
+                    .z_sendmany_with_policy(from_address, payments.clone(), minconf, fee, privacy)
+                    .await
+            }
+            None => {
+                rpc_client
+                    .z_sendmany(from_address, payments.clone(), minconf, fee)
+                    .await
+            }
+        };
+        self.record_submission(correlation_id, from_address, &payments, submitted)
+    }
+
+    /// Log the fee a send will pay: the requested one or the ZIP-317 estimate
+    fn log_fee(&self, payments: &[Payment], from_address: &str, fee: Option<f64>) -> Result<()> {
+        match fee {
+            Some(fee) => tracing::debug!("Fee: {} ZEC (requested)", fee),
+            None => {
+                let estimate = self.estimate_fee(payments, from_address)?;
+                tracing::debug!("Fee: {} ZEC (ZIP-317 estimate)", estimate);
+            }
+        }
+        Ok(())
+    }
+
+    /// Log and audit the outcome of a z_sendmany call
+    fn record_submission(
+        &self,
+        correlation_id: &CorrelationId,
+        from_address: &str,
+        payments: &[Payment],
+        submitted: Result<String>,
+    ) -> Result<String> {
+        let account = self.wallet.account_index();
+        let operation_id = match submitted {
+            Ok(operation_id) => operation_id,
+            Err(e) => {
+                tracing::warn!("Send failed: {}", e);
+                if let Some(policy) = &self.spending_policy {
+                    policy.record_failed(
+                        account,
+                        from_address,
+                        payments,
+                        &e.to_string(),
+                        Some(correlation_id),
+                    )?;
+                }
+                return Err(e);
+            }
+        };
+        tracing::info!("Submitted as operation {}", operation_id);
+        self.remember(&operation_id, correlation_id);
+        if let Some(policy) = &self.spending_policy {
+            policy.record_submitted(
+                account,
+                from_address,
+                payments,
+                Some(&operation_id),
+                Some(correlation_id),
+            )?;
+        }
+        Ok(operation_id)
+    }
+
+    fn remember(&self, key: &str, correlation_id: &CorrelationId) {
+        self.correlations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string(), correlation_id.clone());
+    }
+
+    /// Correlation ID of the send with this operation ID or txid
+    ///
+    /// Sends made by other builders are found through the spending policy's
+    /// audit log, if one is configured.
+    pub fn correlation_id(&self, operation_id_or_txid: &str) -> Option<CorrelationId> {
+        let known = self
+            .correlations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(operation_id_or_txid)
+            .cloned();
+        known.or_else(|| {
+            let audit = self.spending_policy.as_ref()?.audit_log();
+            audit
+                .correlation_for(operation_id_or_txid)
+                .unwrap_or_else(|e| {
+                    tracing::debug!("Correlation lookup failed: {}", e);
+                    None
+                })
+        })
+    }
+
+    /// Span for the steps of a send after submission
+    fn send_span(&self, operation_id_or_txid: &str) -> (Option<CorrelationId>, tracing::Span) {
+        let correlation_id = self.correlation_id(operation_id_or_txid);
+        let span = match &correlation_id {
+            Some(id) => id.span(),
+            None => tracing::Span::none(),
+        };
+        (correlation_id, span)
+    }
+
+    /// Record an audit event of a send, if a spending policy is configured
+    fn audit(&self, event: AuditEvent, correlation_id: Option<&CorrelationId>) -> Result<()> {
+        if let Some(policy) = &self.spending_policy {
+            policy.audit_log().record_correlated(
+                self.wallet.account_index(),
+                event,
+                correlation_id,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Send like [`send_many`](Self::send_many), but at most once per idempotency key
+    ///
+    /// The key and request are recorded in the wallet database before the
+    /// send. Retrying with the same key and request returns the original
+    /// operation ID instead of sending again; reusing a key for a different
+    /// request is an error.
+    ///
+    /// # Arguments
+    /// * `idempotency_key` - Client-provided key identifying this payout
+    /// * `from_address` - Source address (must be in the wallet managed by zcashd)
+    /// * `payments` - Vector of payments to send
+    /// * `minconf` - Minimum confirmations for source funds (default: 1)
+    /// * `fee` - Optional transaction fee in ZEC
+    ///
+    /// # Returns
+    /// Operation ID of the (original) send
+    ///
+    /// # Note
+    /// The key is released for another attempt only if the send failed its
+    /// checks or zcashd rejected it with a JSON-RPC error. After any other
+    /// failure, e.g. a lost connection or an HTTP error from a proxy, the
+    /// outcome is unknown and retrying returns an error rather than risk a
+    /// double spend. Check `z_listoperationids` and then call
+    /// [`IdempotencyStore::record_operation`] or [`IdempotencyStore::release`].
+    pub async fn send_many_idempotent(
+        &self,
+        idempotency_key: &str,
+        from_address: &str,
+        payments: Vec<Payment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<String> {
+        let store = IdempotencyStore::for_wallet(&self.wallet)?;
+        let fingerprint = request_fingerprint(from_address, &payments, minconf, fee)?;
+
+        match store.reserve(idempotency_key, &fingerprint)? {
+            Reservation::New => {}
+            Reservation::Existing(SendState::Submitted { operation_id })
+            | Reservation::Existing(SendState::Completed { operation_id, .. }) => {
+                return Ok(operation_id);
+            }
+            Reservation::Existing(SendState::InFlight) => {
+                return Err(Error::Transaction(format!(
+                    "Outcome of the previous send with idempotency key {} is unknown",
+                    idempotency_key
+                )));
+            }
+        }
+
+        let correlation_id = CorrelationId::new();
+        let span = correlation_id.span();
+        let send = async {
+            // Nothing is sent before the checks pass
+            let privacy =
+                match self.check_send(&correlation_id, from_address, &payments, fee, false) {
+                    Ok(privacy) => privacy,
+                    Err(e) => return store.release(idempotency_key).and(Err(e)),
+                };
+            match self
+                .submit_send(
+                    &correlation_id,
+                    from_address,
+                    payments,
+                    minconf,
+                    fee,
+                    privacy,
+                )
+                .await
+            {
+                Ok(operation_id) => store
+                    .record_operation(idempotency_key, &operation_id)
+                    .map(|()| operation_id),
+                // zcashd answered with a JSON-RPC error, so it created no operation
+                Err(e) if rpc_error_code(&e).is_some() => {
+                    store.release(idempotency_key).and(Err(e))
+                }
+                // A lost connection, a proxy's 502 or 504, or a failure after
+                // the call leaves the outcome unknown; keep the key reserved
+                Err(e) => Err(e),
+            }
+        };
+        send.instrument(span).await
+    }
+
+    /// Wait for an idempotent send to complete and record its transaction ID
+    ///
+    /// # Arguments
+    /// * `idempotency_key` - Key passed to [`send_many_idempotent`](Self::send_many_idempotent)
+    /// * `max_wait_seconds` - Maximum time to wait in seconds (default: 300)
+    ///
+    /// # Returns
+    /// Transaction ID when the operation completes successfully
+    pub async fn wait_for_idempotent_send(
+        &self,
+        idempotency_key: &str,
+        max_wait_seconds: Option<u64>,
+    ) -> Result<String> {
+        let store = IdempotencyStore::for_wallet(&self.wallet)?;
+        let operation_id = match store.get(idempotency_key)? {
+            Some(SendState::Completed { txid, .. }) => return Ok(txid),
+            Some(SendState::Submitted { operation_id }) => operation_id,
+            _ => {
+                return Err(Error::Transaction(format!(
+                    "No submitted send for idempotency key {}",
+                    idempotency_key
+                )))
+            }
+        };
+
+        let txid = self
+            .wait_for_operation(&operation_id, max_wait_seconds)
+            .await?;
+        store.record_txid(idempotency_key, &txid)?;
+        Ok(txid)
     }
 
     /// Send a simple payment to a single address
@@ -185,18 +654,9 @@ impl TransactionBuilder {
         fee: Option<f64>,
     ) -> Result<String> {
         // Validate amount before creating payment
-        if amount_zec <= 0.0 {
-            return Err(Error::Transaction(format!(
-                "Invalid amount: {} ZEC (must be positive)",
-                amount_zec
-            )));
-        }
-        if amount_zec > MAX_ZEC_AMOUNT {
-            return Err(Error::Transaction(format!(
-                "Excessive amount: {} ZEC (max: {} ZEC)",
-                amount_zec, MAX_ZEC_AMOUNT
-            )));
-        }
+        let has_memo = memo.as_ref().is_some_and(|memo| !memo.is_empty());
+        self.amount_policy
+            .check_payment(0, payment_zatoshis(0, amount_zec)?, has_memo)?;
 
         // Validate memo if provided
         if let Some(ref memo) = memo {
@@ -227,9 +687,72 @@ impl TransactionBuilder {
         self.send_many(from_address, payments, minconf, fee).await
     }
 
+    /// Build a payment to a saved contact
+    ///
+    /// The label is resolved through the wallet's [`Contacts`] (ignoring
+    /// case) and the contact's address is validated for the wallet's network.
+    /// Use this to address [`send_many`](Self::send_many) payments by label.
+    ///
+    /// # Arguments
+    /// * `label` - Contact label
+    /// * `amount_zec` - Amount to send in ZEC
+    /// * `memo` - Optional memo (for shielded addresses only)
+    pub fn contact_payment(
+        &self,
+        label: &str,
+        amount_zec: f64,
+        memo: Option<String>,
+    ) -> Result<Payment> {
+        let address = Contacts::for_wallet(&self.wallet)?.resolve(label)?;
+        if memo.is_some() && !is_shielded_address(&address, self.wallet.consensus_network())? {
+            return Err(Error::Transaction(format!(
+                "Memo provided but contact '{}' has a transparent address",
+                label
+            )));
+        }
+        Ok(Payment {
+            address,
+            amount: amount_zec,
+            memo,
+        })
+    }
+
+    /// Send ZEC to a saved contact
+    ///
+    /// Resolves the label like [`contact_payment`](Self::contact_payment) and
+    /// sends with [`send_to_address`](Self::send_to_address).
+    ///
+    /// # Arguments
+    /// * `from_address` - Source address (must be in the wallet managed by zcashd)
+    /// * `label` - Contact label
+    /// * `amount_zec` - Amount to send in ZEC
+    /// * `memo` - Optional memo (for shielded addresses only)
+    /// * `minconf` - Minimum confirmations for source funds (default: 1)
+    /// * `fee` - Optional transaction fee in ZEC
+    ///
+    /// # Returns
+    /// Operation ID (string) that can be used to check transaction status
+    pub async fn send_to_contact(
+        &self,
+        from_address: &str,
+        label: &str,
+        amount_zec: f64,
+        memo: Option<String>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<String> {
+        let to_address = Contacts::for_wallet(&self.wallet)?.resolve(label)?;
+        self.send_to_address(from_address, &to_address, amount_zec, memo, minconf, fee)
+            .await
+    }
+
     /// Build and send a transaction using ZIP-321 payment requests
     ///
-    /// Converts ZIP-321 Payment objects to the format required by z_sendmany.
+    /// Amounts are passed to zcashd in exact zatoshis and memos as their raw
+    /// bytes, so binary (non-UTF-8) memos are sent unchanged. For the local
+    /// PCZT builder, pass the request to
+    /// [`airgap::create_signing_request`](crate::airgap::create_signing_request)
+    /// instead.
     ///
     /// # Arguments
     /// * `from_address` - Source address (must be in the wallet managed by zcashd)
@@ -246,70 +769,158 @@ impl TransactionBuilder {
         minconf: Option<u32>,
         fee: Option<f64>,
     ) -> Result<String> {
-        let network = self.wallet.consensus_network();
-        
-        // Convert ZIP-321 payments to RPC Payment format
-        let rpc_payments: Result<Vec<Payment>> = payments
-            .into_iter()
-            .enumerate()
-            .map(|(idx, p)| {
-                // Extract address string from ZIP-321 payment
-                // zip321::Payment uses ZcashAddress which can be encoded directly
-                let address_str = p.recipient_address().encode();
-
-                // Validate address format matches network
-                parse_address(&address_str, network)
-                    .map_err(|e| Error::Transaction(format!(
-                        "ZIP-321 payment {} has invalid address: {}",
-                        idx, e
-                    )))?;
-
-                // Extract memo if present
-                let memo = p.memo().and_then(|m| {
-                    // Convert memo bytes to string if possible
-                    // ZIP-321 memos are UTF-8 encoded
-                    String::from_utf8(m.as_array().to_vec()).ok()
-                });
-
-                // Validate memo size if present
-                if let Some(ref memo_str) = memo {
-                    let memo_bytes = memo_str.as_bytes();
-                    if memo_bytes.len() > MAX_MEMO_SIZE {
-                        return Err(Error::Transaction(format!(
-                            "ZIP-321 payment {} has memo exceeding {} bytes: {} bytes",
-                            idx, MAX_MEMO_SIZE, memo_bytes.len()
-                        )));
-                    }
-                }
+        let payments = raw_payments_from_zip321(
+            &payments,
+            self.wallet.consensus_network(),
+            &self.amount_policy,
+        )?;
+        self.send_raw(from_address, payments, minconf, fee).await
+    }
 
-                // Convert amount from zatoshis to ZEC
-                // Zatoshis implements From<Zatoshis> for u64
-                let zatoshis: u64 = p.amount().into();
-                let amount_zec = zatoshis as f64 / 100_000_000.0;
+    /// Send payments with exact amounts and raw memo bytes
+    ///
+    /// Validated and checked against the spending policy like
+    /// [`send_many`](Self::send_many), but submitted with
+    /// [`RpcClient::z_sendmany_raw`].
+    ///
+    /// # Arguments
+    /// * `from_address` - Source address (must be in the wallet managed by zcashd)
+    /// * `payments` - Payments to send
+    /// * `minconf` - Minimum confirmations for source funds (default: 1)
+    /// * `fee` - Optional transaction fee in ZEC
+    ///
+    /// # Returns
+    /// Operation ID (string) that can be used to check transaction status
+    pub async fn send_raw(
+        &self,
+        from_address: &str,
+        payments: Vec<RawPayment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<String> {
+        self.send_raw_correlated(CorrelationId::new(), from_address, payments, minconf, fee)
+            .await
+    }
 
-                // Validate amount
-                if amount_zec <= 0.0 {
-                    return Err(Error::Transaction(format!(
-                        "ZIP-321 payment {} has invalid amount: {} ZEC (must be positive)",
-                        idx, amount_zec
-                    )));
-                }
-                if amount_zec > MAX_ZEC_AMOUNT {
-                    return Err(Error::Transaction(format!(
-                        "ZIP-321 payment {} has excessive amount: {} ZEC (max: {} ZEC)",
-                        idx, amount_zec, MAX_ZEC_AMOUNT
-                    )));
-                }
+    /// Send like [`send_raw`](Self::send_raw) under a chosen correlation ID
+    pub async fn send_raw_correlated(
+        &self,
+        correlation_id: CorrelationId,
+        from_address: &str,
+        payments: Vec<RawPayment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<String> {
+        let span = correlation_id.span();
+        self.send_raw_inner(
+            &correlation_id,
+            from_address,
+            payments,
+            minconf,
+            fee,
+            &self.amount_policy,
+        )
+        .instrument(span)
+        .await
+    }
 
-                Ok(Payment {
-                    address: address_str,
-                    amount: amount_zec,
-                    memo,
-                })
-            })
-            .collect();
+    /// Send a zero-value shielded output that only carries a memo
+    ///
+    /// For on-chain messages and acknowledgements, e.g. confirming an
+    /// invoice to the address that paid it. The sender pays only the fee.
+    /// Allowed whatever the [`AmountPolicy`] says about memo-only payments,
+    /// as the caller asks for one explicitly.
+    ///
+    /// # Arguments
+    /// * `from_address` - Source address paying the fee (must be in the wallet managed by zcashd)
+    /// * `to_address` - Shielded recipient address (Unified or Sapling)
+    /// * `memo` - Memo bytes, at most 512 and not empty
+    /// * `minconf` - Minimum confirmations for source funds (default: 1)
+    /// * `fee` - Optional transaction fee in ZEC
+    ///
+    /// # Returns
+    /// Operation ID (string) that can be used to check transaction status
+    pub async fn send_memo(
+        &self,
+        from_address: &str,
+        to_address: &str,
+        memo: &[u8],
+        minconf: Option<u32>,
+        fee: Option<f64>,
+    ) -> Result<String> {
+        let payment = memo_only_payment(to_address, memo, self.wallet.consensus_network())?;
+        let policy = self.amount_policy.with_memo_only_payments(true);
+        let correlation_id = CorrelationId::new();
+        let span = correlation_id.span();
+        self.send_raw_inner(
+            &correlation_id,
+            from_address,
+            vec![payment],
+            minconf,
+            fee,
+            &policy,
+        )
+        .instrument(span)
+        .await
+    }
+
+    async fn send_raw_inner(
+        &self,
+        correlation_id: &CorrelationId,
+        from_address: &str,
+        payments: Vec<RawPayment>,
+        minconf: Option<u32>,
+        fee: Option<f64>,
+        policy: &AmountPolicy,
+    ) -> Result<String> {
+        let rpc_client = self
+            .rpc_client
+            .as_ref()
+            .ok_or_else(|| Error::Transaction("RPC client not configured".to_string()))?;
+
+        let network = self.wallet.consensus_network();
+        parse_address(from_address, network)?;
+        check_change_pool(self.wallet.change_policy(), from_address, network)?;
+        for (idx, payment) in payments.iter().enumerate() {
+            validate_raw_payment(idx, payment, network, policy)?;
+        }
+        policy.check_batch(payments.iter().map(|payment| payment.amount))?;
+        check_threshold(
+            self.wallet.db_path(),
+            payments
+                .iter()
+                .fold(0u64, |total, payment| total.saturating_add(payment.amount)),
+        )?;
+
+        let approximate: Vec<Payment> = payments.iter().map(RawPayment::to_payment).collect();
+        tracing::debug!(
+            "Validated {} payment(s) from {}",
+            payments.len(),
+            from_address
+        );
+        self.log_fee(&approximate, from_address, fee)?;
 
-        self.send_many(from_address, rpc_payments?, minconf, fee).await
+        let account = self.wallet.account_index();
+        let privacy = match &self.spending_policy {
+            Some(policy) => {
+                let privacy = policy.effective_privacy(account, None);
+                policy.evaluate(
+                    account,
+                    from_address,
+                    &approximate,
+                    privacy,
+                    Some(correlation_id),
+                )?;
+                privacy
+            }
+            None => None,
+        };
+
+        tracing::info!("Submitting {} payment(s) to zcashd", payments.len());
+        let submitted = rpc_client
+            .z_sendmany_raw(from_address, &payments, minconf, fee, privacy)
+            .await;
+        self.record_submission(correlation_id, from_address, &approximate, submitted)
     }
 
     /// Check the status of a transaction operation
@@ -365,43 +976,344 @@ impl TransactionBuilder {
         operation_id: &str,
         max_wait_seconds: Option<u64>,
     ) -> Result<String> {
-        use std::time::Duration;
-        use tokio::time::sleep;
+        let rpc_client = self
+            .rpc_client
+            .as_ref()
+            .ok_or_else(|| Error::Transaction("RPC client not configured".to_string()))?;
 
-        let max_wait = max_wait_seconds.unwrap_or(300);
-        let start = std::time::Instant::now();
+        let (correlation_id, span) = self.send_span(operation_id);
+        self.wait_for_operation_inner(
+            rpc_client,
+            correlation_id.as_ref(),
+            operation_id,
+            max_wait_seconds,
+        )
+        .instrument(span)
+        .await
+    }
 
+    async fn wait_for_operation_inner(
+        &self,
+        rpc_client: &RpcClient,
+        correlation_id: Option<&CorrelationId>,
+        operation_id: &str,
+        max_wait_seconds: Option<u64>,
+    ) -> Result<String> {
+        let txid = match rpc_client
+            .wait_for_operation(operation_id, max_wait_seconds)
+            .await
+        {
+            Ok(txid) => txid,
+            Err(e) => {
+                tracing::warn!("Operation {} did not complete: {}", operation_id, e);
+                return Err(e);
+            }
+        };
+        tracing::info!("Operation {} completed with txid {}", operation_id, txid);
+        if let Some(correlation_id) = correlation_id {
+            self.remember(&txid, correlation_id);
+        }
+        self.audit(
+            AuditEvent::SendCompleted {
+                operation_id: operation_id.to_string(),
+                txid: txid.clone(),
+            },
+            correlation_id,
+        )?;
+        Ok(txid)
+    }
+
+    /// Wait until a sent transaction has enough confirmations
+    ///
+    /// Polls zcashd every 10 seconds and logs each new confirmation count
+    /// under the send's correlation ID.
+    ///
+    /// # Arguments
+    /// * `txid` - Transaction ID returned by [`wait_for_operation`](Self::wait_for_operation)
+    /// * `confirmations` - Number of confirmations to wait for
+    /// * `max_wait_seconds` - Maximum time to wait in seconds (default: 3600)
+    ///
+    /// # Returns
+    /// The confirmation count reached
+    pub async fn wait_for_confirmations(
+        &self,
+        txid: &str,
+        confirmations: u64,
+        max_wait_seconds: Option<u64>,
+    ) -> Result<u64> {
+        let rpc_client = self
+            .rpc_client
+            .as_ref()
+            .ok_or_else(|| Error::Transaction("RPC client not configured".to_string()))?;
+
+        let (correlation_id, span) = self.send_span(txid);
+        let max_wait = Duration::from_secs(max_wait_seconds.unwrap_or(3600));
+        self.wait_for_confirmations_inner(
+            rpc_client,
+            correlation_id.as_ref(),
+            txid,
+            confirmations,
+            max_wait,
+        )
+        .instrument(span)
+        .await
+    }
+
+    async fn wait_for_confirmations_inner(
+        &self,
+        rpc_client: &RpcClient,
+        correlation_id: Option<&CorrelationId>,
+        txid: &str,
+        confirmations: u64,
+        max_wait: Duration,
+    ) -> Result<u64> {
+        let start = std::time::Instant::now();
+        let mut last = None;
         loop {
-            if start.elapsed().as_secs() > max_wait {
+            let current = rpc_client
+                .get_raw_transaction(txid)
+                .await?
+                .confirmations
+                .unwrap_or(0);
+            if last != Some(current) {
+                tracing::debug!("Transaction {} has {} confirmation(s)", txid, current);
+                last = Some(current);
+            }
+            if current >= confirmations {
+                tracing::info!("Transaction {} confirmed ({} confirmations)", txid, current);
+                self.audit(
+                    AuditEvent::SendConfirmed {
+                        txid: txid.to_string(),
+                        confirmations: current,
+                    },
+                    correlation_id,
+                )?;
+                return Ok(current);
+            }
+            if start.elapsed() > max_wait {
                 return Err(Error::Transaction(format!(
-                    "Operation {} timed out after {} seconds",
-                    operation_id, max_wait
+                    "Transaction {} has {} of {} confirmations after {} seconds",
+                    txid,
+                    current,
+                    confirmations,
+                    max_wait.as_secs()
                 )));
             }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+        }
+    }
+}
 
-            let results = self.get_operation_result(operation_id).await?;
-
-            for result in results {
-                if let Some(status) = result.get("status") {
-                    if status == "success" {
-                        if let Some(txid) = result.get("txid").and_then(|t| t.as_str()) {
-                            return Ok(txid.to_string());
-                        }
-                    } else if status == "failed" {
-                        let error = result
-                            .get("error")
-                            .and_then(|e| e.as_str())
-                            .unwrap_or("Unknown error");
-                        return Err(Error::Transaction(format!(
-                            "Operation {} failed: {}",
-                            operation_id, error
-                        )));
-                    }
-                }
-            }
+/// Convert ZIP-321 payments to z_sendmany payments without loss
+///
+/// Amounts are kept in zatoshis and memos as their bytes, with the zero
+/// padding to 512 bytes removed (zcashd pads memos again).
+pub(crate) fn raw_payments_from_zip321(
+    payments: &[zip321::Payment],
+    network: NetworkParams,
+    policy: &AmountPolicy,
+) -> Result<Vec<RawPayment>> {
+    payments
+        .iter()
+        .enumerate()
+        .map(|(idx, p)| {
+            let address = p.recipient_address().encode();
+            parse_address(&address, network).map_err(|e| {
+                Error::Transaction(format!("ZIP-321 payment {} has invalid address: {}", idx, e))
+            })?;
+            let amount = p.amount().map(u64::from).ok_or_else(|| {
+                Error::Transaction(format!("ZIP-321 payment {} has no amount", idx))
+            })?;
+            let memo = p.memo().map(|memo| {
+                let bytes = memo.as_slice();
+                let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+                bytes[..len].to_vec()
+            });
+            let payment = RawPayment {
+                address,
+                amount,
+                memo,
+            };
+            validate_raw_payment(idx, &payment, network, policy)?;
+            Ok(payment)
+        })
+        .collect()
+}
+
+/// Check that zcashd will send the change of a send from `from_address` to
+/// the pool the change policy requires
+///
+/// `z_sendmany` has no change pool parameter: change returns to the source
+/// address, so it can only reach Orchard from an address with an Orchard
+/// receiver and is only guaranteed to stay in Sapling for Sapling-only
+/// sources. Sends that could put change elsewhere are rejected rather than
+/// silently ignoring the policy.
+fn check_change_pool(
+    policy: ChangePolicy,
+    from_address: &str,
+    network: NetworkParams,
+) -> Result<()> {
+    let Some(required) = policy.pool() else {
+        return Ok(());
+    };
+    let address = parse_address(from_address, network)?;
+    let orchard = address.can_receive_as(PoolType::Shielded(ShieldedProtocol::Orchard));
+    let sapling = address.can_receive_as(PoolType::Shielded(ShieldedProtocol::Sapling));
+    let compatible = match required {
+        ShieldedProtocol::Orchard => orchard,
+        ShieldedProtocol::Sapling => sapling && !orchard,
+    };
+    if !compatible {
+        return Err(Error::Transaction(format!(
+            "zcashd cannot send change from {} to the {:?} pool required by the change policy",
+            from_address, required
+        )));
+    }
+    Ok(())
+}
+
+/// Zero-value payment of `memo` to the shielded address `to_address`
+fn memo_only_payment(to_address: &str, memo: &[u8], network: NetworkParams) -> Result<RawPayment> {
+    if memo.is_empty() {
+        return Err(Error::Transaction(
+            "A memo-only payment needs a memo".to_string(),
+        ));
+    }
+    if !is_shielded_address(to_address, network)? {
+        return Err(Error::Transaction(
+            "Memo-only payments need a shielded recipient address".to_string(),
+        ));
+    }
+    let payment = RawPayment {
+        address: to_address.to_string(),
+        amount: 0,
+        memo: Some(memo.to_vec()),
+    };
+    validate_raw_payment(
+        0,
+        &payment,
+        network,
+        &AmountPolicy::default().with_memo_only_payments(true),
+    )?;
+    Ok(payment)
+}
 
-            // Wait before polling again
-            sleep(Duration::from_secs(2)).await;
+fn validate_raw_payment(
+    idx: usize,
+    payment: &RawPayment,
+    network: NetworkParams,
+    policy: &AmountPolicy,
+) -> Result<()> {
+    parse_address(&payment.address, network)?;
+    let has_memo = payment.memo.as_ref().is_some_and(|memo| !memo.is_empty());
+    policy.check_payment(idx, payment.amount, has_memo)?;
+    if let Some(memo) = &payment.memo {
+        if memo.len() > MAX_MEMO_SIZE {
+            return Err(Error::Transaction(format!(
+                "Payment {} has memo exceeding {} bytes: {} bytes",
+                idx,
+                MAX_MEMO_SIZE,
+                memo.len()
+            )));
+        }
+        if !is_shielded_address(&payment.address, network)? {
+            return Err(Error::Transaction(format!(
+                "Payment {} includes memo but recipient address is transparent",
+                idx
+            )));
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payments(uri: &str) -> Vec<zip321::Payment> {
+        zip321::TransactionRequest::from_uri(uri)
+            .unwrap()
+            .payments()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_zip321_vectors_convert_exactly() {
+        // Test vectors from ZIP 321
+        let single = payments(
+            "zcash:ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez?amount=1&memo=VGhpcyBpcyBhIHNpbXBsZSBtZW1vLg&message=Thank%20you%20for%20your%20purchase",
+        );
+        let raw =
+            raw_payments_from_zip321(&single, NetworkParams::Testnet, &AmountPolicy::default())
+                .unwrap();
+        assert_eq!(raw[0].amount, 100_000_000);
+        assert_eq!(raw[0].memo.as_deref(), Some(&b"This is a simple memo."[..]));
+
+        let multi = payments(
+            "zcash:?address=tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU&amount=123.456&address.1=ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez&amount.1=0.789&memo.1=VGhpcyBpcyBhIHVuaWNvZGUgbWVtbyDinKjwn6aE8J-PhvCfjok",
+        );
+        let raw =
+            raw_payments_from_zip321(&multi, NetworkParams::Testnet, &AmountPolicy::default())
+                .unwrap();
+        assert_eq!(raw[0].amount, 12_345_600_000);
+        assert_eq!(raw[0].memo, None);
+        assert_eq!(raw[0].to_json()["amount"], "123.456");
+        assert_eq!(raw[1].amount, 78_900_000);
+        assert_eq!(
+            raw[1].memo.as_deref(),
+            Some("This is a unicode memo ✨🦄🏆🎉".as_bytes())
+        );
+        assert_eq!(raw[1].to_json()["amount"], "0.789");
+    }
+
+    #[test]
+    fn test_binary_memo_is_preserved() {
+        let binary = payments(
+            "zcash:ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez?amount=0.00000001&memo=_wAB",
+        );
+        let raw =
+            raw_payments_from_zip321(&binary, NetworkParams::Testnet, &AmountPolicy::default())
+                .unwrap();
+        assert_eq!(raw[0].amount, 1);
+        assert_eq!(raw[0].memo.as_deref(), Some(&[0xff, 0x00, 0x01][..]));
+        assert_eq!(raw[0].to_json()["memo"], "ff0001");
+        assert_eq!(raw[0].to_json()["amount"], "0.00000001");
+        // Not text, so the approximate view carries no memo
+        assert_eq!(raw[0].to_payment().memo, None);
+    }
+
+    #[test]
+    fn test_memo_only_payment() {
+        let network = NetworkParams::Testnet;
+        let sapling = "ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez";
+        let payment = memo_only_payment(sapling, b"Invoice 117 received", network).unwrap();
+        assert_eq!(payment.amount, 0);
+        assert_eq!(payment.to_json()["amount"], "0");
+        assert!(memo_only_payment(sapling, b"", network).is_err());
+        assert!(memo_only_payment(sapling, &[1; 513], network).is_err());
+        assert!(memo_only_payment("tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU", b"hi", network).is_err());
+
+        // Other sends only allow them when the amount policy does
+        assert!(validate_raw_payment(0, &payment, network, &AmountPolicy::default()).is_err());
+    }
+
+    #[test]
+    fn test_change_pool_check() {
+        let network = NetworkParams::Testnet;
+        let sapling = "ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez";
+        let transparent = "tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU";
+        assert!(check_change_pool(ChangePolicy::SameAsInput, transparent, network).is_ok());
+        assert!(check_change_pool(ChangePolicy::Sapling, sapling, network).is_ok());
+        assert!(check_change_pool(ChangePolicy::Orchard, sapling, network).is_err());
+        assert!(check_change_pool(ChangePolicy::Sapling, transparent, network).is_err());
+
+        let unified = Wallet::ephemeral(crate::types::Network::Testnet)
+            .unwrap()
+            .get_unified_address()
+            .unwrap();
+        assert!(check_change_pool(ChangePolicy::Orchard, &unified, network).is_ok());
+        assert!(check_change_pool(ChangePolicy::Sapling, &unified, network).is_err());
+    }
 }
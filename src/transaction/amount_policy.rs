@@ -0,0 +1,181 @@
+//! Payment amount limits
+//!
+//! [`TransactionBuilder`](crate::transaction::TransactionBuilder) checks the
+//! amount of every payment before a send reaches zcashd. The default
+//! [`AmountPolicy`] only rejects what cannot be valid: zero amounts and
+//! amounts above the 21 million ZEC supply. Services tighten it to catch
+//! mistakes such as a ZEC amount entered in zatoshis, e.g. with a maximum
+//! per payment and per batch, or loosen it to allow zero-value payments that
+//! only carry a memo. [`TransactionBuilder::send_memo`] sends such a payment
+//! whatever the policy.
+//!
+//! [`TransactionBuilder::send_memo`]: crate::transaction::TransactionBuilder::send_memo
+
+use crate::error::{Error, Result};
+use crate::types::utils::zec_to_zatoshis;
+use serde::{Deserialize, Serialize};
+
+/// Total ZEC supply in zatoshis; no payment can be larger
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+/// Limits on payment amounts, in zatoshis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AmountPolicy {
+    /// Smallest payment with value (default 1)
+    pub min_payment: u64,
+    /// Largest payment (default [`MAX_MONEY`])
+    pub max_payment: u64,
+    /// Largest total of the payments of one send (default [`MAX_MONEY`])
+    pub max_batch_total: u64,
+    /// Whether zero-value payments carrying a memo are allowed (default
+    /// `false`)
+    pub allow_memo_only: bool,
+}
+
+impl Default for AmountPolicy {
+    fn default() -> Self {
+        Self {
+            min_payment: 1,
+            max_payment: MAX_MONEY,
+            max_batch_total: MAX_MONEY,
+            allow_memo_only: false,
+        }
+    }
+}
+
+impl AmountPolicy {
+    /// The default policy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require payments with value to be at least `zatoshis` (at least 1)
+    pub fn with_min_payment(mut self, zatoshis: u64) -> Self {
+        self.min_payment = zatoshis.max(1);
+        self
+    }
+
+    /// Reject payments above `zatoshis` (at most [`MAX_MONEY`])
+    pub fn with_max_payment(mut self, zatoshis: u64) -> Self {
+        self.max_payment = zatoshis.min(MAX_MONEY);
+        self
+    }
+
+    /// Reject sends whose payments total more than `zatoshis` (at most
+    /// [`MAX_MONEY`])
+    pub fn with_max_batch_total(mut self, zatoshis: u64) -> Self {
+        self.max_batch_total = zatoshis.min(MAX_MONEY);
+        self
+    }
+
+    /// Allow or reject zero-value payments that carry a memo
+    pub fn with_memo_only_payments(mut self, allow: bool) -> Self {
+        self.allow_memo_only = allow;
+        self
+    }
+
+    /// Check one payment's amount
+    ///
+    /// # Arguments
+    /// * `idx` - Index of the payment in its send, for the error message
+    /// * `amount` - Amount in zatoshis
+    /// * `has_memo` - Whether the payment carries a memo
+    pub fn check_payment(&self, idx: usize, amount: u64, has_memo: bool) -> Result<()> {
+        if amount == 0 {
+            if self.allow_memo_only && has_memo {
+                return Ok(());
+            }
+            let reason = if self.allow_memo_only {
+                "only payments with a memo can have no value"
+            } else {
+                "must be positive"
+            };
+            return Err(Error::Transaction(format!(
+                "Payment {} has invalid amount: 0 zatoshis ({})",
+                idx, reason
+            )));
+        }
+        if amount < self.min_payment {
+            return Err(Error::Transaction(format!(
+                "Payment {} is below the minimum amount: {} zatoshis (min: {} zatoshis)",
+                idx, amount, self.min_payment
+            )));
+        }
+        if amount > self.max_payment {
+            return Err(Error::Transaction(format!(
+                "Payment {} has excessive amount: {} zatoshis (max: {} zatoshis)",
+                idx, amount, self.max_payment
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the total of a send's payments
+    ///
+    /// # Arguments
+    /// * `amounts` - Amount of each payment in zatoshis
+    pub fn check_batch(&self, amounts: impl IntoIterator<Item = u64>) -> Result<()> {
+        let total = amounts
+            .into_iter()
+            .try_fold(0u64, |total, amount| total.checked_add(amount))
+            .filter(|total| *total <= self.max_batch_total);
+        if total.is_none() {
+            return Err(Error::Transaction(format!(
+                "Payments total more than the maximum per send of {} zatoshis",
+                self.max_batch_total
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Convert a payment amount in ZEC to zatoshis, rejecting negative and
+/// non-finite amounts
+pub(crate) fn payment_zatoshis(idx: usize, amount_zec: f64) -> Result<u64> {
+    if !amount_zec.is_finite() || amount_zec < 0.0 {
+        return Err(Error::Transaction(format!(
+            "Payment {} has invalid amount: {} ZEC (must not be negative)",
+            idx, amount_zec
+        )));
+    }
+    let zatoshis = zec_to_zatoshis(amount_zec);
+    if zatoshis > MAX_MONEY {
+        return Err(Error::Transaction(format!(
+            "Payment {} has excessive amount: {} ZEC (max: 21000000 ZEC)",
+            idx, amount_zec
+        )));
+    }
+    Ok(zatoshis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_policy() {
+        let default = AmountPolicy::default();
+        assert!(default.check_payment(0, 1, false).is_ok());
+        assert!(default.check_payment(0, MAX_MONEY, false).is_ok());
+        assert!(default.check_payment(0, MAX_MONEY + 1, false).is_err());
+        assert!(default.check_payment(0, 0, true).is_err());
+
+        let policy = AmountPolicy::new()
+            .with_min_payment(10_000)
+            .with_max_payment(100_000_000)
+            .with_max_batch_total(150_000_000)
+            .with_memo_only_payments(true);
+        assert!(policy.check_payment(0, 9_999, false).is_err());
+        assert!(policy.check_payment(0, 100_000_001, false).is_err());
+        assert!(policy.check_payment(0, 0, true).is_ok());
+        assert!(policy.check_payment(0, 0, false).is_err());
+        assert!(policy.check_batch([100_000_000, 50_000_000]).is_ok());
+        assert!(policy.check_batch([100_000_000, 50_000_001]).is_err());
+        assert!(policy.check_batch([u64::MAX, 1]).is_err());
+
+        assert_eq!(payment_zatoshis(0, 0.1).unwrap(), 10_000_000);
+        assert!(payment_zatoshis(0, -0.1).is_err());
+        assert!(payment_zatoshis(0, f64::NAN).is_err());
+        assert!(payment_zatoshis(0, 21_000_000.1).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Raw transaction decoding
+//!
+//! Parses serialized v4 (Sapling) and v5 (NU5) transactions into a
+//! [`DecodedTransaction`] without a node: transparent inputs and outputs,
+//! Sapling spends and outputs, Orchard actions, expiry and value balances.
+//! Used to inspect transactions before broadcast, for audits, and by the
+//! CLI `decode` command.
+//!
+//! Transparent input values are not part of a transaction, so the fee is
+//! only known when there are none; otherwise supply the values spent to
+//! [`DecodedTransaction::fee_with_input_values`].
+
+use crate::error::{Error, Result};
+use crate::types::Network;
+use crate::wallet::consensus_network;
+use serde::{Deserialize, Serialize};
+use zcash_keys::encoding::AddressCodec;
+use zcash_primitives::transaction::Transaction;
+use zcash_protocol::consensus::BranchId;
+
+/// A transparent input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedInput {
+    /// ID of the transaction being spent from (all zeros for coinbase)
+    pub prevout_txid: String,
+    /// Output index being spent
+    pub prevout_index: u32,
+    pub sequence: u32,
+}
+
+/// A transparent output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedOutput {
+    pub index: u32,
+    /// Value in zatoshis
+    pub value: u64,
+    /// Recipient address, for P2PKH and P2SH scripts
+    pub address: Option<String>,
+}
+
+/// An Orchard action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedAction {
+    /// Nullifier of the spent note (hex)
+    pub nullifier: String,
+    /// Commitment of the created note (hex)
+    pub cmx: String,
+}
+
+/// A decoded raw transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedTransaction {
+    pub txid: String,
+    /// Transaction format version (4 or 5)
+    pub version: u32,
+    /// Consensus branch ID the transaction commits to (v5 only)
+    pub consensus_branch_id: Option<u32>,
+    pub lock_time: u32,
+    /// Last height the transaction can be mined at (0 for no expiry)
+    pub expiry_height: u64,
+    pub is_coinbase: bool,
+    pub transparent_inputs: Vec<DecodedInput>,
+    pub transparent_outputs: Vec<DecodedOutput>,
+    /// Number of Sprout JoinSplits (v4 only)
+    pub sprout_joinsplits: usize,
+    /// Nullifiers of the Sapling spends (hex)
+    pub sapling_spends: Vec<String>,
+    /// Note commitments of the Sapling outputs (hex)
+    pub sapling_outputs: Vec<String>,
+    /// Net value leaving the Sapling pool (zatoshis, negative when shielding)
+    pub sapling_value_balance: i64,
+    pub orchard_actions: Vec<DecodedAction>,
+    /// Net value leaving the Orchard pool (zatoshis, negative when shielding)
+    pub orchard_value_balance: i64,
+    /// Fee in zatoshis, when it can be derived without input values
+    pub fee: Option<u64>,
+}
+
+impl DecodedTransaction {
+    /// Total value of the transparent outputs (zatoshis)
+    pub fn transparent_output_total(&self) -> u64 {
+        self.transparent_outputs.iter().map(|o| o.value).sum()
+    }
+
+    /// Fee given the values of the transparent outputs being spent
+    ///
+    /// # Arguments
+    /// * `input_values` - Value of each transparent input in order (zatoshis)
+    ///
+    /// # Returns
+    /// `None` for coinbase and Sprout transactions, when the number of values
+    /// does not match the inputs, or when outputs exceed inputs
+    pub fn fee_with_input_values(&self, input_values: &[u64]) -> Option<u64> {
+        if self.is_coinbase
+            || self.sprout_joinsplits > 0
+            || input_values.len() != self.transparent_inputs.len()
+        {
+            return None;
+        }
+        let value_in = input_values.iter().map(|v| i128::from(*v)).sum::<i128>()
+            + i128::from(self.sapling_value_balance)
+            + i128::from(self.orchard_value_balance);
+        u64::try_from(value_in - i128::from(self.transparent_output_total())).ok()
+    }
+
+    /// Check that the transaction can still be accepted by the network
+    ///
+    /// Rejects coinbase transactions, transactions without any input, spend
+    /// or action, and transactions that expire before `next_height`.
+    pub fn check_broadcastable(&self, next_height: u64) -> Result<()> {
+        if self.is_coinbase {
+            return Err(Error::Transaction(
+                "Coinbase transactions cannot be broadcast".to_string(),
+            ));
+        }
+        if self.transparent_inputs.is_empty()
+            && self.sapling_spends.is_empty()
+            && self.orchard_actions.is_empty()
+            && self.sprout_joinsplits == 0
+        {
+            return Err(Error::Transaction(format!(
+                "Transaction {} spends nothing",
+                self.txid
+            )));
+        }
+        if self.expiry_height != 0 && self.expiry_height < next_height {
+            return Err(Error::Transaction(format!(
+                "Transaction {} expired at height {}",
+                self.txid, self.expiry_height
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Decode a serialized transaction
+///
+/// # Arguments
+/// * `raw` - Transaction bytes
+/// * `network` - Network used to encode transparent addresses
+pub fn decode(raw: &[u8], network: Network) -> Result<DecodedTransaction> {
+    let (version, branch_id, tx) = read_transaction(raw)?;
+    let params = consensus_network(network);
+
+    let (is_coinbase, transparent_inputs, transparent_outputs) = match tx.transparent_bundle() {
+        Some(bundle) => (
+            bundle.is_coinbase(),
+            bundle
+                .vin
+                .iter()
+                .map(|input| DecodedInput {
+                    prevout_txid: txid_hex(input.prevout().hash()),
+                    prevout_index: input.prevout().n(),
+                    sequence: input.sequence(),
+                })
+                .collect(),
+            bundle
+                .vout
+                .iter()
+                .enumerate()
+                .map(|(index, output)| DecodedOutput {
+                    index: index as u32,
+                    value: u64::from(output.value()),
+                    address: output
+                        .recipient_address()
+                        .map(|address| address.encode(&params)),
+                })
+                .collect(),
+        ),
+        None => (false, Vec::new(), Vec::new()),
+    };
+
+    let sprout_joinsplits = tx.sprout_bundle().map_or(0, |b| b.joinsplits.len());
+
+    let (sapling_spends, sapling_outputs, sapling_value_balance) = match tx.sapling_bundle() {
+        Some(bundle) => (
+            bundle
+                .shielded_spends()
+                .iter()
+                .map(|spend| hex::encode(spend.nullifier().0))
+                .collect(),
+            bundle
+                .shielded_outputs()
+                .iter()
+                .map(|output| hex::encode(output.cmu().to_bytes()))
+                .collect(),
+            i64::from(*bundle.value_balance()),
+        ),
+        None => (Vec::new(), Vec::new(), 0),
+    };
+
+    let (orchard_actions, orchard_value_balance) = match tx.orchard_bundle() {
+        Some(bundle) => (
+            bundle
+                .actions()
+                .iter()
+                .map(|action| DecodedAction {
+                    nullifier: hex::encode(action.nullifier().to_bytes()),
+                    cmx: hex::encode(action.cmx().to_bytes()),
+                })
+                .collect(),
+            i64::from(*bundle.value_balance()),
+        ),
+        None => (Vec::new(), 0),
+    };
+
+    let mut decoded = DecodedTransaction {
+        txid: tx.txid().to_string(),
+        version,
+        consensus_branch_id: branch_id,
+        lock_time: tx.lock_time(),
+        expiry_height: u64::from(u32::from(tx.expiry_height())),
+        is_coinbase,
+        transparent_inputs,
+        transparent_outputs,
+        sprout_joinsplits,
+        sapling_spends,
+        sapling_outputs,
+        sapling_value_balance,
+        orchard_actions,
+        orchard_value_balance,
+        fee: None,
+    };
+    if decoded.transparent_inputs.is_empty() {
+        decoded.fee = decoded.fee_with_input_values(&[]);
+    }
+    Ok(decoded)
+}
+
+/// Decode a hex-encoded transaction
+pub fn decode_hex(raw_hex: &str, network: Network) -> Result<DecodedTransaction> {
+    let raw = hex::decode(raw_hex.trim())
+        .map_err(|e| Error::InvalidParameter(format!("Invalid transaction hex: {}", e)))?;
+    decode(&raw, network)
+}
+
+/// Parse a transaction along with its version and (for v5) consensus
+/// branch ID
+pub(crate) fn read_transaction(raw: &[u8]) -> Result<(u32, Option<u32>, Transaction)> {
+    let (version, branch_id) = read_header(raw)?;
+    // v4 serialization does not depend on the branch; v5 carries its own
+    let parse_branch = match branch_id {
+        Some(id) => BranchId::try_from(id).map_err(|e| {
+            Error::Transaction(format!("Unknown consensus branch ID {:08x}: {}", id, e))
+        })?,
+        None => BranchId::Canopy,
+    };
+    let tx = Transaction::read(raw, parse_branch)
+        .map_err(|e| Error::Transaction(format!("Failed to decode transaction: {}", e)))?;
+    Ok((version, branch_id, tx))
+}
+
+/// Version and (for v5) consensus branch ID from the transaction header
+fn read_header(raw: &[u8]) -> Result<(u32, Option<u32>)> {
+    let word = |offset: usize| {
+        raw.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| Error::Transaction("Transaction is truncated".to_string()))
+    };
+    let header = word(0)?;
+    let overwintered = header & 0x8000_0000 != 0;
+    let version = header & 0x7fff_ffff;
+    match (overwintered, version) {
+        (true, 4) => Ok((4, None)),
+        (true, 5) => Ok((5, Some(word(8)?))),
+        _ => Err(Error::Transaction(format!(
+            "Unsupported transaction version {}{}",
+            version,
+            if overwintered {
+                ""
+            } else {
+                " (not overwintered)"
+            }
+        ))),
+    }
+}
+
+/// Display form of a txid: the bytes in reverse order, hex encoded
+pub(crate) fn txid_hex(bytes: &[u8; 32]) -> String {
+    let mut reversed = *bytes;
+    reversed.reverse();
+    hex::encode(reversed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded() -> DecodedTransaction {
+        DecodedTransaction {
+            txid: "aa".to_string(),
+            version: 5,
+            consensus_branch_id: Some(0xc8e7_1055),
+            lock_time: 0,
+            expiry_height: 100,
+            is_coinbase: false,
+            transparent_inputs: vec![DecodedInput {
+                prevout_txid: "bb".to_string(),
+                prevout_index: 0,
+                sequence: u32::MAX,
+            }],
+            transparent_outputs: vec![DecodedOutput {
+                index: 0,
+                value: 40_000,
+                address: None,
+            }],
+            sprout_joinsplits: 0,
+            sapling_spends: Vec::new(),
+            sapling_outputs: Vec::new(),
+            sapling_value_balance: 0,
+            orchard_actions: Vec::new(),
+            orchard_value_balance: -50_000,
+            fee: None,
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        assert!(matches!(
+            decode(&[], Network::Mainnet),
+            Err(Error::Transaction(_))
+        ));
+        // Pre-Overwinter v1 transaction
+        assert!(decode(&[1, 0, 0, 0, 0], Network::Mainnet).is_err());
+        assert_eq!(read_header(&[4, 0, 0, 0x80]).unwrap(), (4, None));
+        assert_eq!(
+            read_header(&[5, 0, 0, 0x80, 0, 0, 0, 0, 0x55, 0x10, 0xe7, 0xc8]).unwrap(),
+            (5, Some(0xc8e7_1055))
+        );
+        assert!(decode_hex("zz", Network::Mainnet).is_err());
+    }
+
+    #[test]
+    fn test_fee_and_broadcast_checks() {
+        let tx = decoded();
+        assert_eq!(tx.fee_with_input_values(&[100_000]), Some(10_000));
+        assert_eq!(tx.fee_with_input_values(&[80_000]), None);
+        assert_eq!(tx.fee_with_input_values(&[]), None);
+
+        assert!(tx.check_broadcastable(100).is_ok());
+        assert!(tx.check_broadcastable(101).is_err());
+        let mut no_spends = tx.clone();
+        no_spends.transparent_inputs.clear();
+        assert!(no_spends.check_broadcastable(50).is_err());
+    }
+}
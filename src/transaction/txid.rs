@@ -0,0 +1,71 @@
+//! Transaction ID and authorizing data digests
+//!
+//! Computes the txid of a serialized transaction locally, so it can be
+//! reported before broadcast and compared with the ID the node or mempool
+//! returns. For v5 transactions the txid is the ZIP-244 digest of the
+//! effecting data, which excludes signatures and proofs; the ZIP-244
+//! authorizing data commitment ([`auth_digest`]) covers those. v4
+//! transactions are identified by the double SHA-256 of their encoding.
+
+use super::decode::read_transaction;
+use crate::error::{Error, Result};
+
+/// Transaction ID of a serialized transaction (hex, display byte order)
+pub fn txid(raw: &[u8]) -> Result<String> {
+    Ok(read_transaction(raw)?.2.txid().to_string())
+}
+
+/// ZIP-244 authorizing data commitment of a v5 transaction (hex)
+///
+/// Fails for v4 transactions, which have no authorizing data digest.
+pub fn auth_digest(raw: &[u8]) -> Result<String> {
+    let (version, _, tx) = read_transaction(raw)?;
+    if version < 5 {
+        return Err(Error::Transaction(format!(
+            "v{} transactions have no authorizing data digest",
+            version
+        )));
+    }
+    Ok(hex::encode(tx.auth_commitment().as_bytes()))
+}
+
+/// Check a txid reported by a node against the locally computed one
+///
+/// # Returns
+/// The locally computed txid
+pub fn verify_txid(raw: &[u8], reported: &str) -> Result<String> {
+    let local = txid(raw)?;
+    check_reported_txid(&local, reported)?;
+    Ok(local)
+}
+
+pub(crate) fn check_reported_txid(local: &str, reported: &str) -> Result<()> {
+    if !local.eq_ignore_ascii_case(reported.trim()) {
+        return Err(Error::Transaction(format!(
+            "Node reported txid {} but the transaction's txid is {}",
+            reported.trim(),
+            local
+        )));
+    }
+    Ok(())
+}
+
+/// Whether a string has the form of a txid (64 hex digits)
+pub(crate) fn is_txid(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_reported_txid() {
+        let local = "ab".repeat(32);
+        assert!(is_txid(&local));
+        assert!(!is_txid("abc"));
+        assert!(check_reported_txid(&local, &"AB".repeat(32)).is_ok());
+        assert!(check_reported_txid(&local, &"cd".repeat(32)).is_err());
+        assert!(txid(&[5, 0, 0, 0x80]).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Trial-decryption benchmark and scan auto-tuning
+//!
+//! How fast a device can trial-decrypt shielded outputs varies by orders of
+//! magnitude between a server and a low-end phone, so a fixed sync batch size
+//! is either too small (per-batch overhead dominates) or too large (batches
+//! block for a long time and progress is reported rarely). At startup,
+//! [`LightClient::auto_tune_scanning`](crate::light_client::LightClient::auto_tune_scanning)
+//! trial-decrypts a sample of recent blocks with the wallet's keys, measures
+//! the throughput for one and several threads, and derives a [`ScanTuning`]:
+//! - `batch_size`: blocks per batch so one batch takes about the target time
+//! - `workers`: the number of threads beyond which scanning stops speeding up
+//!
+//! The harness ([`benchmark_blocks`]) and the tuning rule ([`tune`]) are
+//! independent of the network so they can be reused with cached blocks.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use zcash_client_backend::proto::compact_formats::CompactBlock;
+
+/// Default time one sync batch should take to scan
+pub const DEFAULT_TARGET_BATCH_TIME: Duration = Duration::from_secs(2);
+
+/// Smallest batch size chosen by [`tune`]
+pub const MIN_TUNED_BATCH_SIZE: u64 = 10;
+
+/// Largest batch size chosen by [`tune`]
+pub const MAX_TUNED_BATCH_SIZE: u64 = 5_000;
+
+/// A worker count is worth using if it reaches this share of the best throughput
+const WORKER_EFFICIENCY: f64 = 0.9;
+
+/// Measured trial-decryption throughput
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanBenchmark {
+    /// Blocks in the sample
+    pub blocks: u64,
+    /// Shielded outputs (Sapling outputs and Orchard actions) in the sample
+    pub outputs: u64,
+    /// Outputs per second, indexed by worker count minus one
+    pub outputs_per_sec: Vec<f64>,
+}
+
+impl ScanBenchmark {
+    /// Average shielded outputs per block in the sample
+    pub fn outputs_per_block(&self) -> f64 {
+        if self.blocks == 0 {
+            0.0
+        } else {
+            self.outputs as f64 / self.blocks as f64
+        }
+    }
+}
+
+/// Scan parameters derived from a [`ScanBenchmark`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScanTuning {
+    /// Blocks fetched and scanned per sync batch
+    pub batch_size: u64,
+    /// Number of threads worth scanning with concurrently
+    pub workers: usize,
+    /// Expected outputs per second with `workers` threads
+    pub outputs_per_sec: f64,
+}
+
+/// Number of shielded outputs (Sapling outputs and Orchard actions) in blocks
+pub fn count_outputs(blocks: &[CompactBlock]) -> u64 {
+    blocks
+        .iter()
+        .flat_map(|block| &block.vtx)
+        .map(|tx| (tx.outputs.len() + tx.actions.len()) as u64)
+        .sum()
+}
+
+/// Time `trial_decrypt` over the sample with 1 to `max_workers` threads
+///
+/// Blocks are dealt round-robin to the threads of each run.
+///
+/// # Arguments
+/// * `blocks` - Sample blocks
+/// * `max_workers` - Largest thread count to measure (at least 1)
+/// * `trial_decrypt` - Trial-decrypts every output of one block
+pub fn benchmark_blocks<F>(
+    blocks: &[CompactBlock],
+    max_workers: usize,
+    trial_decrypt: F,
+) -> ScanBenchmark
+where
+    F: Fn(&CompactBlock) + Sync,
+{
+    let outputs = count_outputs(blocks);
+    let outputs_per_sec = (1..=max_workers.max(1))
+        .map(|workers| {
+            let started = Instant::now();
+            std::thread::scope(|scope| {
+                for worker in 0..workers {
+                    let trial_decrypt = &trial_decrypt;
+                    scope.spawn(move || {
+                        blocks
+                            .iter()
+                            .skip(worker)
+                            .step_by(workers)
+                            .for_each(trial_decrypt)
+                    });
+                }
+            });
+            outputs as f64 / started.elapsed().as_secs_f64().max(1e-9)
+        })
+        .collect();
+
+    ScanBenchmark {
+        blocks: blocks.len() as u64,
+        outputs,
+        outputs_per_sec,
+    }
+}
+
+/// Derive scan parameters from a benchmark
+///
+/// Picks the fewest workers reaching 90% of the best measured throughput,
+/// then the batch size whose outputs take about `target` to scan at that
+/// throughput, clamped to [`MIN_TUNED_BATCH_SIZE`]..=[`MAX_TUNED_BATCH_SIZE`].
+/// A sample without outputs yields the maximum batch size.
+pub fn tune(benchmark: &ScanBenchmark, target: Duration) -> ScanTuning {
+    let best = benchmark
+        .outputs_per_sec
+        .iter()
+        .copied()
+        .fold(0.0, f64::max);
+    let (workers, outputs_per_sec) = benchmark
+        .outputs_per_sec
+        .iter()
+        .copied()
+        .enumerate()
+        .find(|(_, rate)| *rate >= best * WORKER_EFFICIENCY)
+        .map_or((1, 0.0), |(i, rate)| (i + 1, rate));
+
+    let per_block = benchmark.outputs_per_block();
+    let batch_size = if per_block > 0.0 && outputs_per_sec > 0.0 {
+        (outputs_per_sec * target.as_secs_f64() / per_block) as u64
+    } else {
+        MAX_TUNED_BATCH_SIZE
+    };
+
+    ScanTuning {
+        batch_size: batch_size.clamp(MIN_TUNED_BATCH_SIZE, MAX_TUNED_BATCH_SIZE),
+        workers,
+        outputs_per_sec,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcash_client_backend::proto::compact_formats::{CompactOrchardAction, CompactTx};
+
+    fn block(actions: usize) -> CompactBlock {
+        CompactBlock {
+            vtx: vec![CompactTx {
+                actions: vec![CompactOrchardAction::default(); actions],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tune() {
+        let benchmark = ScanBenchmark {
+            blocks: 10,
+            outputs: 200,
+            outputs_per_sec: vec![1_000.0, 1_900.0, 2_000.0, 2_050.0],
+        };
+        // Two workers already reach 90% of the best rate
+        let tuning = tune(&benchmark, Duration::from_secs(2));
+        assert_eq!(tuning.workers, 2);
+        assert_eq!(tuning.batch_size, 190);
+
+        let slow = ScanBenchmark {
+            outputs_per_sec: vec![10.0],
+            ..benchmark.clone()
+        };
+        assert_eq!(
+            tune(&slow, Duration::from_secs(2)).batch_size,
+            MIN_TUNED_BATCH_SIZE
+        );
+
+        let empty = ScanBenchmark {
+            outputs: 0,
+            ..benchmark
+        };
+        assert_eq!(
+            tune(&empty, Duration::from_secs(2)).batch_size,
+            MAX_TUNED_BATCH_SIZE
+        );
+    }
+
+    #[test]
+    fn test_benchmark_visits_every_block_once_per_run() {
+        let blocks: Vec<CompactBlock> = (0..7).map(block).collect();
+        let visited = std::sync::atomic::AtomicU64::new(0);
+        let benchmark = benchmark_blocks(&blocks, 3, |b| {
+            visited.fetch_add(
+                count_outputs(std::slice::from_ref(b)),
+                std::sync::atomic::Ordering::Relaxed,
+            );
+        });
+        assert_eq!(benchmark.outputs, 21);
+        assert_eq!(benchmark.outputs_per_sec.len(), 3);
+        assert_eq!(visited.into_inner(), 21 * 3);
+    }
+}
@@ -11,6 +11,21 @@ pub enum Network {
     Regtest,
 }
 
+/// Format a zatoshi amount as an exact decimal ZEC string
+///
+/// Trailing zeros are dropped: `150_000_000` is `"1.5"`, `1` is
+/// `"0.00000001"`.
+pub fn format_zec(zatoshis: u64) -> String {
+    let whole = zatoshis / 100_000_000;
+    let frac = zatoshis % 100_000_000;
+    if frac == 0 {
+        whole.to_string()
+    } else {
+        let frac_str = format!("{:08}", frac);
+        format!("{}.{}", whole, frac_str.trim_end_matches('0'))
+    }
+}
+
 /// Address type supported by Zcash
 /// Addresses are stored as strings for serialization compatibility
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -21,6 +36,18 @@ pub enum AddressType {
     Transparent(String),
 }
 
+/// An Orchard receiver of the wallet
+///
+/// Orchard receivers have no address encoding of their own; they are shared
+/// as a Unified Address containing only the Orchard receiver.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrchardReceiver {
+    /// Unified Address containing only this receiver
+    pub address: String,
+    /// Raw 43-byte receiver (diversifier and transmission key), hex-encoded
+    pub raw: String,
+}
+
 /// Transaction status
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransactionStatus {
@@ -30,7 +57,7 @@ pub enum TransactionStatus {
 }
 
 /// Balance information
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct Balance {
     pub transparent: u64,
     pub sapling: u64,
@@ -38,6 +65,114 @@ pub struct Balance {
     pub total: u64,
 }
 
+/// Balance of one pool, split by spendability
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct PoolBalance {
+    /// Value that can be spent now under the confirmations policy
+    pub spendable: u64,
+    /// Change from the wallet's own transactions that is not yet confirmed
+    pub pending_change: u64,
+    /// Received value that is not yet confirmed or whose notes cannot be
+    /// spent yet (e.g. the wallet is still scanning)
+    pub unconfirmed: u64,
+    pub total: u64,
+}
+
+impl PoolBalance {
+    /// Add another pool balance, failing on overflow
+    pub fn checked_add(&self, other: &PoolBalance) -> Option<PoolBalance> {
+        Some(PoolBalance {
+            spendable: self.spendable.checked_add(other.spendable)?,
+            pending_change: self.pending_change.checked_add(other.pending_change)?,
+            unconfirmed: self.unconfirmed.checked_add(other.unconfirmed)?,
+            total: self.total.checked_add(other.total)?,
+        })
+    }
+}
+
+/// Balance per pool under a confirmations policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct DetailedBalance {
+    /// Confirmations required for received value to be spendable
+    pub confirmations: u32,
+    pub transparent: PoolBalance,
+    pub sapling: PoolBalance,
+    pub orchard: PoolBalance,
+    /// Sum over all pools
+    pub total: PoolBalance,
+}
+
+/// Balance of one account, per pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct AccountBalance {
+    pub transparent: u64,
+    pub sapling: u64,
+    pub orchard: u64,
+    /// Part of the pool balances that cannot be spent yet: unconfirmed
+    /// change and received value
+    pub pending: u64,
+    pub total: u64,
+}
+
+/// Value pool a note, output or input belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Pool {
+    Transparent,
+    Sapling,
+    Orchard,
+}
+
+/// Identifies a note or transparent output by the output that created it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NoteId {
+    pub pool: Pool,
+    /// ID of the transaction that created the note
+    pub txid: String,
+    /// Output (or Orchard action) index within the transaction
+    pub output_index: u32,
+}
+
+/// A note or transparent output received by the wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletNote {
+    pub pool: Pool,
+    /// ID of the transaction that created the note
+    pub txid: String,
+    /// Output (or Orchard action) index within the transaction
+    pub output_index: u32,
+    /// Value in zatoshis
+    pub value: u64,
+    /// Height the creating transaction was mined at, if mined
+    pub height: Option<u64>,
+    /// Whether the note is change from the wallet's own transaction
+    pub is_change: bool,
+    /// Nullifier (hex), once known; always `None` for transparent outputs
+    pub nullifier: Option<String>,
+    /// Spent by a mined transaction
+    pub spent: bool,
+    /// Spent by a transaction that is not mined yet and has not expired
+    pub pending_spend: bool,
+    /// Can be selected as an input now under the confirmations policy
+    /// passed to [`Wallet::list_notes`](crate::wallet::Wallet::list_notes);
+    /// never true for frozen notes or transparent outputs
+    pub spendable: bool,
+    /// Frozen with [`Wallet::freeze_note`](crate::wallet::Wallet::freeze_note)
+    #[serde(default)]
+    pub frozen: bool,
+}
+
+impl WalletNote {
+    /// Identifier of the note, e.g. for
+    /// [`Wallet::freeze_note`](crate::wallet::Wallet::freeze_note)
+    pub fn id(&self) -> NoteId {
+        NoteId {
+            pool: self.pool,
+            txid: self.txid.clone(),
+            output_index: self.output_index,
+        }
+    }
+}
+
 /// Transaction information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
@@ -49,6 +184,34 @@ pub struct Transaction {
     pub timestamp: Option<u64>,
 }
 
+/// Filters for [`Wallet::search_transactions`](crate::wallet::Wallet::search_transactions)
+///
+/// All set filters must match. Memo filters match if any text memo of the
+/// transaction matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionQuery {
+    /// Text the memo must contain, ignoring case
+    pub memo_contains: Option<String>,
+    /// Regular expression the memo must match (case-insensitive)
+    pub memo_regex: Option<String>,
+    /// Minimum absolute amount in zatoshis
+    pub min_amount: Option<u64>,
+    /// Maximum absolute amount in zatoshis
+    pub max_amount: Option<u64>,
+    /// Maximum number of transactions to return (None for all)
+    pub limit: Option<usize>,
+}
+
+impl TransactionQuery {
+    /// Query for transactions whose memo contains `text`, ignoring case
+    pub fn memo(text: &str) -> Self {
+        Self {
+            memo_contains: Some(text.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
 /// Block information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockInfo {
@@ -77,12 +240,11 @@ pub mod utils {
     /// * `zec` - Amount in ZEC
     ///
     /// # Returns
-    /// Amount in zatoshis as u64
-    ///
-    /// # Panics
-    /// Panics if the amount exceeds u64::MAX when converted to zatoshis
+    /// Amount in zatoshis as u64, rounded to the nearest zatoshi (amounts
+    /// such as 0.29 ZEC are not exact in binary); negative amounts give 0
+    /// and amounts beyond the u64 range give u64::MAX
     pub fn zec_to_zatoshis(zec: f64) -> u64 {
-        (zec * 100_000_000.0) as u64
+        (zec * 100_000_000.0).round() as u64
     }
 
     /// Format ZEC amount as a string with proper decimal places
@@ -1,44 +1,194 @@
 //! Wallet management functionality
 
+pub mod account_metadata;
+pub mod annotations;
+pub mod async_wallet;
+pub mod balance_history;
+pub mod contacts;
+pub mod frozen_notes;
+pub mod key_store;
+mod lock;
+pub mod memo_index;
+pub mod message;
+pub mod pool;
+pub mod transparent;
+
+use crate::address_book::{AddressBook, AddressLabel};
+use crate::analytics_export::{
+    write_analytics_export, AccountHistory, AnalyticsExportInfo, ANALYTICS_EXPORT_VERSION,
+};
+use crate::backup::{encrypt_backup, read_backup, BackupAccount, WalletBackup, BACKUP_VERSION};
+use crate::counterparty::{read_counterparties, CounterpartyActivity};
+use crate::db_encryption::open_connection;
 use crate::error::{Error, Result};
-use crate::types::{Balance, Network};
+use crate::interchange::{
+    encrypt_interchange, read_interchange, read_transactions as read_interchange_transactions,
+    InterchangeAccount, InterchangeAddress, WalletInterchange, INTERCHANGE_FORMAT,
+    INTERCHANGE_VERSION, PURPOSE_DEFAULT, PURPOSE_RECEIVE,
+};
+use crate::key_export::{
+    decrypt_spending_key, encrypt_spending_key, SpendingKeyExport, KEY_EXPORT_VERSION,
+};
+use crate::key_provider::KeyProvider;
+use crate::maintenance::{
+    check_witnesses, database_size, prune_blocks, vacuum, verify_integrity, DatabaseSize,
+    IntegrityReport, VacuumReport, WitnessReport, MIN_RETAINED_BLOCKS,
+};
+use crate::params::{NetworkParams, RegtestUpgrades};
+use crate::receipt::{read_receipt, PaymentReceipt};
+use crate::snapshot::{
+    install_snapshot, read_snapshot_info, write_snapshot, SnapshotInfo, SNAPSHOT_VERSION,
+};
+use crate::store::{db_error, unix_now};
+use crate::types::{
+    AccountBalance, Balance, DetailedBalance, Network, NoteId, OrchardReceiver, Pool, PoolBalance,
+    Transaction, TransactionQuery, TransactionStatus, WalletNote,
+};
+use crate::zcashd_dump::{parse_zcashd_dump, TransparentKeyKind, ZcashdImportReport};
+use account_metadata::{AccountMetadata, AccountMetadataStore};
+use annotations::TransactionAnnotations;
+use balance_history::{BalanceHistory, BalanceSnapshot};
+use contacts::Contacts;
+use frozen_notes::FrozenNotes;
+use key_store::KeyStore;
+use lock::{decode_secrets, SeedVault};
+use memo_index::{match_query, MemoIndex};
+use pool::{
+    MigrationProgress, PooledConnection, ReadWalletDb, SchemaVersion, WalletDbPool,
+    WriteConnection, WriteWalletDb,
+};
+use transparent::{TransparentAddressInfo, TransparentAddresses, TransparentChain};
+use bip0039::Mnemonic;
 use dirs;
 use getrandom::getrandom;
-use rand::thread_rng;
-use secrecy::SecretVec;
-use std::path::PathBuf;
-use zcash_client_backend::data_api::{wallet::ConfirmationsPolicy, WalletRead};
-use zcash_client_sqlite::{util::SystemClock, wallet::init::init_wallet_db, WalletDb};
-use zcash_keys::encoding::AddressCodec;
+use regex::{Regex, RegexBuilder};
+use rusqlite::OptionalExtension;
+use secp256k1::{PublicKey, Secp256k1};
+use secrecy::zeroize::Zeroizing;
+use secrecy::{ExposeSecret, SecretString, SecretVec};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::num::NonZeroU32;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use zcash_client_backend::data_api::{
+    wallet::ConfirmationsPolicy, Account, AccountBirthday, AccountPurpose, InputSource,
+    MaxSpendMode, TargetValue, WalletRead, WalletWrite, Zip32Derivation,
+};
+use zcash_keys::address::{Address, UnifiedAddress};
+use zcash_keys::encoding::{
+    decode_extended_spending_key, encode_extended_full_viewing_key,
+    encode_extended_spending_key, AddressCodec,
+};
 use zcash_keys::keys::{
+	Era,
 	ReceiverRequirement,
 	ReceiverRequirements,
 	UnifiedAddressRequest,
 	UnifiedFullViewingKey,
 	UnifiedSpendingKey,
 };
-use zcash_protocol::consensus::{MainNetwork, Network as ConsensusNetwork, TestNetwork};
-use zip32::{AccountId, DiversifierIndex};
+use zcash_protocol::consensus::{NetworkConstants, Parameters};
+use zcash_protocol::memo::{Memo, MemoBytes};
+use zcash_protocol::ShieldedProtocol;
+use zcash_transparent::keys::pubkey_to_address;
+use zip32::{fingerprint::SeedFingerprint, AccountId, DiversifierIndex};
+
+/// A ZIP-32 account stored in the wallet database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletAccount {
+    /// ZIP-32 account index, or `None` for imported viewing keys
+    pub index: Option<u32>,
+    /// Account identifier in the wallet database
+    pub uuid: String,
+    pub name: Option<String>,
+    /// Encoded unified full viewing key
+    pub ufvk: Option<String>,
+    /// Label, color and application metadata (see [`account_metadata`])
+    #[serde(default)]
+    pub metadata: AccountMetadata,
+    /// Whether the account is archived (see [`Wallet::archive_account`])
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// How [`Wallet::get_unified_address`] chooses the address it returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AddressRotation {
+    /// Always the account's default address
+    #[default]
+    Default,
+    /// A fresh diversified address on every call
+    PerCall,
+    /// A fresh diversified address once per UTC day
+    Daily,
+}
+
+/// Pool that receives the change of the wallet's sends
+///
+/// Honored by local transaction building
+/// ([`airgap::create_signing_request`](crate::airgap::create_signing_request))
+/// and checked before
+/// [`TransactionBuilder`](crate::transaction::TransactionBuilder) sends through
+/// zcashd, whose `z_sendmany` has no change pool parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChangePolicy {
+    /// The pool the spent notes come from, so no value crosses pools;
+    /// Orchard when only transparent funds are spent
+    #[default]
+    SameAsInput,
+    /// Always Orchard
+    Orchard,
+    /// Always Sapling
+    Sapling,
+}
+
+impl ChangePolicy {
+    /// Pool change must go to, or `None` if it follows the inputs
+    pub fn pool(self) -> Option<ShieldedProtocol> {
+        match self {
+            ChangePolicy::SameAsInput => None,
+            ChangePolicy::Orchard => Some(ShieldedProtocol::Orchard),
+            ChangePolicy::Sapling => Some(ShieldedProtocol::Sapling),
+        }
+    }
+}
+
+/// Key store entry holding the seed (see [`Wallet::store_seed`])
+const STORED_SEED_NAME: &str = "wallet_seed";
+
+/// Database path prefix of in-memory wallets (see [`Wallet::ephemeral`])
+const EPHEMERAL_DB_PREFIX: &str = "file:numi-ephemeral-";
 
 /// Wallet structure for managing Zcash addresses and keys
+///
+/// Cloning is cheap: clones share the seed, its lock state and the database
+/// connections, so a wallet can be handed to concurrent tasks without
+/// reopening or re-initializing the database. The selected account,
+/// network, address rotation and change policies are settings of each clone.
+#[derive(Clone)]
 pub struct Wallet {
     db_path: PathBuf,
     network: Network,
-    seed: Vec<u8>,
+    /// Seed and mnemonic, dropped while the wallet is locked
+    vault: Arc<SeedVault>,
+    /// Holder of the seed if not `vault` (see [`Wallet::with_key_provider`])
+    key_provider: Option<Arc<dyn KeyProvider>>,
     account_id: AccountId,
+    address_rotation: AddressRotation,
+    change_policy: ChangePolicy,
+    /// Network upgrade activation heights, if `network` is regtest
+    regtest_upgrades: RegtestUpgrades,
+    /// Shared connections to `db_path`
+    pool: WalletDbPool,
 }
 
 impl Wallet {
     /// Create a new wallet with a random seed
     pub fn new() -> Result<Self> {
-        Self::with_path(
-            dirs::data_dir()
-                .ok_or_else(|| {
-                    Error::InvalidParameter("Cannot determine data directory".to_string())
-                })?
-                .join("zcash-numi-sdk")
-                .join("wallet.db"),
-        )
+        Self::with_path(Self::default_db_path()?)
     }
 
     /// Create a new wallet with a custom database path
@@ -47,330 +197,3403 @@ impl Wallet {
     }
 
     /// Create a new wallet with a custom database path and seed
+    ///
+    /// Without a seed, a new 24-word mnemonic is generated so the wallet can
+    /// be backed up with [`export_mnemonic`](Self::export_mnemonic).
     pub fn with_path_and_seed(db_path: PathBuf, seed: Option<Vec<u8>>) -> Result<Self> {
-        // Ensure parent directory exists
-        if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        let seed_bytes = match seed {
-            Some(bytes) => bytes,
+        match seed {
+            Some(bytes) => Self::from_parts(db_path, SecretVec::new(bytes), None),
             None => {
-                let mut generated = vec![0u8; 32];
-                getrandom(&mut generated).map_err(|e| {
-                    Error::KeyDerivation(format!("Failed to generate wallet seed: {}", e))
-                })?;
-                generated
+                let mnemonic = Self::random_mnemonic()?;
+                Self::from_parts(
+                    db_path,
+                    mnemonic_seed(&mnemonic, ""),
+                    Some(SecretString::new(mnemonic.phrase().to_string())),
+                )
             }
-        };
+        }
+    }
+
+    /// Create a wallet from a BIP-39 / ZIP-339 mnemonic phrase
+    ///
+    /// # Arguments
+    /// * `phrase` - English mnemonic phrase (12 to 24 words)
+    /// * `passphrase` - Optional BIP-39 passphrase; other Zcash wallets use the
+    ///   empty string
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<Self> {
+        Self::with_path_and_mnemonic(Self::default_db_path()?, phrase, passphrase)
+    }
 
+    /// Create a wallet from a mnemonic phrase with a custom database path
+    pub fn with_path_and_mnemonic(
+        db_path: PathBuf,
+        phrase: &str,
+        passphrase: &str,
+    ) -> Result<Self> {
+        let mnemonic = <Mnemonic>::from_phrase(phrase)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid mnemonic phrase: {}", e)))?;
+        Self::from_parts(
+            db_path,
+            mnemonic_seed(&mnemonic, passphrase),
+            Some(SecretString::new(mnemonic.phrase().to_string())),
+        )
+    }
+
+    /// Create a throwaway wallet whose database never touches disk
+    ///
+    /// The database is an in-memory SQLite database shared by the wallet's
+    /// connections (including SDK stores such as the address book) and
+    /// discarded once the wallet and every [`WalletDbPool`] cloned from it
+    /// are dropped. A new random mnemonic is generated. Intended for tests,
+    /// demos and short-lived signing contexts.
+    ///
+    /// The database is bound to `network` (see
+    /// [`set_network`](Self::set_network)); snapshots cannot be installed.
+    pub fn ephemeral(network: Network) -> Result<Self> {
+        let mnemonic = Self::random_mnemonic()?;
+        let db_path = PathBuf::from(format!(
+            "{}{}?mode=memory&cache=shared",
+            EPHEMERAL_DB_PREFIX,
+            hex::encode(rand::random::<[u8; 16]>())
+        ));
         let wallet = Wallet {
+            pool: WalletDbPool::new(db_path.clone(), consensus_network(network)),
             db_path,
-            network: Network::default(),
-            seed: seed_bytes,
+            network,
+            vault: Arc::new(SeedVault::new(
+                mnemonic_seed(&mnemonic, ""),
+                Some(SecretString::new(mnemonic.phrase().to_string())),
+            )),
+            key_provider: None,
             account_id: AccountId::ZERO,
+            address_rotation: AddressRotation::Default,
+            change_policy: ChangePolicy::default(),
+            regtest_upgrades: RegtestUpgrades::default(),
         };
-
         wallet.initialize_database()?;
-
+        wallet.store_network(network)?;
         Ok(wallet)
     }
 
-    /// Create a wallet from an existing seed
-    pub fn from_seed(seed: Vec<u8>) -> Result<Self> {
-        let db_path = dirs::data_dir()
-            .ok_or_else(|| Error::InvalidParameter("Cannot determine data directory".to_string()))?
-            .join("zcash-numi-sdk")
-            .join("wallet.db");
+    /// Whether the wallet was created with [`ephemeral`](Self::ephemeral)
+    pub fn is_ephemeral(&self) -> bool {
+        self.db_path
+            .to_str()
+            .is_some_and(|path| path.starts_with(EPHEMERAL_DB_PREFIX))
+    }
 
-        Self::with_path_and_seed(db_path, Some(seed))
+    /// Store the seed and mnemonic in the wallet database, sealed with
+    /// `passphrase`
+    ///
+    /// The wallet can then be opened with
+    /// [`open_with_stored_seed`](Self::open_with_stored_seed) instead of
+    /// supplying the seed. The seed is never written unencrypted; see
+    /// [`key_store`]. Key stretching makes this take about a second.
+    pub fn store_seed(&self, passphrase: &str) -> Result<()> {
+        let secrets = self.seed_vault()?.encode_secrets()?;
+        KeyStore::for_wallet(self)?.store(STORED_SEED_NAME, secrets.expose_secret(), passphrase)
     }
 
-    pub(crate) fn consensus_network(&self) -> ConsensusNetwork {
-        match self.network {
-            Network::Mainnet => ConsensusNetwork::MainNetwork,
-            Network::Testnet | Network::Regtest => ConsensusNetwork::TestNetwork,
-        }
+    /// Delete the seed stored with [`store_seed`](Self::store_seed)
+    ///
+    /// # Returns
+    /// Whether a stored seed was deleted
+    pub fn remove_stored_seed(&self) -> Result<bool> {
+        KeyStore::for_wallet(self)?.remove(STORED_SEED_NAME)
     }
 
-    fn open_initialized_wallet_db(
-        &self,
-    ) -> Result<WalletDb<rusqlite::Connection, ConsensusNetwork, SystemClock, rand::rngs::ThreadRng>>
-    {
-        let mut wallet_db = WalletDb::for_path(
-            &self.db_path,
-            self.consensus_network(),
-            SystemClock,
-            thread_rng(),
-        )
-        .map_err(|e| Error::Database(format!("Failed to open wallet database: {}", e)))?;
+    /// Open a wallet whose seed was stored with [`store_seed`](Self::store_seed)
+    ///
+    /// Fails with [`Error::Wallet`] if the passphrase is wrong or no seed is
+    /// stored in the database.
+    pub fn open_with_stored_seed(db_path: PathBuf, passphrase: &str) -> Result<Self> {
+        let secrets = KeyStore::open(&db_path)?
+            .load(STORED_SEED_NAME, passphrase)?
+            .ok_or_else(|| Error::Wallet(format!("No seed is stored in {}", db_path.display())))?;
+        let (seed, mnemonic) = decode_secrets(secrets.expose_secret())?;
+        Self::from_parts(db_path, seed, mnemonic)
+    }
 
-        init_wallet_db(&mut wallet_db, Some(SecretVec::new(self.seed.clone())))
-            .map_err(|e| Error::Database(format!("Failed to initialize wallet database: {}", e)))?;
+    /// Generate a new random 24-word mnemonic phrase
+    pub fn generate_mnemonic() -> Result<String> {
+        Ok(Self::random_mnemonic()?.phrase().to_string())
+    }
 
-        Ok(wallet_db)
+    fn random_mnemonic() -> Result<Mnemonic> {
+        let mut entropy = Zeroizing::new([0u8; 32]);
+        getrandom(&mut *entropy)
+            .map_err(|e| Error::KeyDerivation(format!("Failed to generate wallet seed: {}", e)))?;
+        <Mnemonic>::from_entropy(entropy.to_vec())
+            .map_err(|e| Error::KeyDerivation(format!("Failed to generate mnemonic: {}", e)))
     }
 
-    fn initialize_database(&self) -> Result<()> {
-        self.open_initialized_wallet_db().map(|_| ())
+    /// Export the wallet's mnemonic phrase for backup
+    ///
+    /// Only available for wallets created from or generated with a mnemonic;
+    /// wallets created from raw seed bytes have no phrase to export.
+    pub fn export_mnemonic(&self) -> Result<String> {
+        self.seed_vault()?
+            .mnemonic()?
+            .map(|phrase| phrase.expose_secret().clone())
+            .ok_or_else(|| {
+                Error::Wallet("Wallet was created from a raw seed and has no mnemonic".to_string())
+            })
     }
 
-    /// Set the network for this wallet
-    pub fn set_network(&mut self, network: Network) {
-        self.network = network;
+    /// Set the passphrase that unlocks the wallet after [`lock`](Self::lock)
+    ///
+    /// The wallet must be unlocked. Key stretching makes this take about a
+    /// second.
+    pub fn set_lock_passphrase(&self, passphrase: &str) -> Result<()> {
+        self.seed_vault()?.set_passphrase(passphrase)
     }
 
-    /// Get the current network
-    pub fn network(&self) -> Network {
-        self.network
+    /// Drop the seed and mnemonic from memory
+    ///
+    /// Until [`unlock`](Self::unlock), key derivation, signing and backups
+    /// fail with [`Error::Wallet`]; database queries keep working. Requires
+    /// a lock passphrase, since the seed would otherwise be lost.
+    pub fn lock(&self) -> Result<()> {
+        self.seed_vault()?.lock()
     }
 
-    /// Get the unified spending key for this wallet
-    fn get_unified_spending_key(&self) -> Result<UnifiedSpendingKey> {
-        match self.network {
-            Network::Mainnet => {
-                UnifiedSpendingKey::from_seed(&MainNetwork, &self.seed, self.account_id)
-            }
-            Network::Testnet => {
-                UnifiedSpendingKey::from_seed(&TestNetwork, &self.seed, self.account_id)
-            }
-            Network::Regtest => {
-                UnifiedSpendingKey::from_seed(&TestNetwork, &self.seed, self.account_id)
-            }
-        }
-        .map_err(|e| Error::KeyDerivation(format!("Failed to derive unified spending key: {}", e)))
+    /// Restore the seed with the lock passphrase
+    ///
+    /// Fails with [`Error::Wallet`] if the passphrase is wrong.
+    pub fn unlock(&self, passphrase: &str) -> Result<()> {
+        self.seed_vault()?.unlock(passphrase)
     }
 
-    /// Get the unified full viewing key for this wallet
-    fn get_unified_full_viewing_key(&self) -> Result<UnifiedFullViewingKey> {
-        let usk = self.get_unified_spending_key()?;
-        Ok(usk.to_unified_full_viewing_key())
+    /// Whether the wallet is locked, or due to lock on its next use
+    ///
+    /// Wallets whose seed is held by a [`KeyProvider`] are never locked.
+    pub fn is_locked(&self) -> bool {
+        self.key_provider.is_none() && self.vault.is_locked()
     }
 
-    /// Get the unified full viewing key (public method for light client use)
-    pub fn unified_full_viewing_key(&self) -> Result<UnifiedFullViewingKey> {
-        self.get_unified_full_viewing_key()
+    /// Lock the wallet once the seed has not been used for `timeout`
+    ///
+    /// Only takes effect once a lock passphrase is set. Without a timer the
+    /// wallet locks on the next access after the timeout; use
+    /// [`lock_if_idle`](Self::lock_if_idle) to erase the seed on time.
+    pub fn set_auto_lock(&self, timeout: Option<Duration>) {
+        self.vault.set_auto_lock(timeout)
     }
 
-    /// Generate a new unified address
-    pub fn get_unified_address(&self) -> Result<String> {
-        let ufvk = self.get_unified_full_viewing_key()?;
-        let (ua, _) = ufvk
-            .default_address(UnifiedAddressRequest::ALLOW_ALL)
-            .map_err(|e| Error::Address(format!("Failed to generate unified address: {}", e)))?;
+    /// Lock the wallet if the auto-lock timeout has passed
+    ///
+    /// # Returns
+    /// Whether the wallet was locked by this call
+    pub fn lock_if_idle(&self) -> bool {
+        self.vault.lock_if_idle()
+    }
 
-        match self.network {
-            Network::Mainnet => Ok(ua.encode(&MainNetwork)),
-            Network::Testnet => Ok(ua.encode(&TestNetwork)),
-            Network::Regtest => Ok(ua.encode(&TestNetwork)),
+    /// Open a wallet whose seed is held by a key provider
+    ///
+    /// The wallet only handles viewing keys, which it gets from `provider`:
+    /// accounts are recorded by their viewing key, and spends are signed by
+    /// the provider's [`signer`](KeyProvider::signer), e.g. with
+    /// [`send_with_signer`](crate::signer::send_with_signer). Everything that
+    /// needs the seed or a spending key in memory — locking, storing the
+    /// seed, backups, exporting spending keys — fails with [`Error::Wallet`].
+    ///
+    /// # Arguments
+    /// * `db_path` - Wallet database path
+    /// * `provider` - Holder of the wallet seed, e.g. an HSM
+    pub fn with_key_provider(db_path: PathBuf, provider: Arc<dyn KeyProvider>) -> Result<Self> {
+        Self::open_with_keys(db_path, SeedVault::empty(), Some(provider))
+    }
+
+    /// The key provider holding the wallet seed, if any
+    pub fn key_provider(&self) -> Option<&Arc<dyn KeyProvider>> {
+        self.key_provider.as_ref()
+    }
+
+    /// The seed vault; fails if the seed is held by a key provider
+    fn seed_vault(&self) -> Result<&SeedVault> {
+        match &self.key_provider {
+            Some(provider) => Err(Error::Wallet(format!(
+                "The wallet seed is held by key provider {}",
+                provider.name()
+            ))),
+            None => Ok(&self.vault),
         }
     }
-}
 
-/// ZIP-316 policy for Unified Address receiver selection
-///
-/// Policies align with priority rules:
-/// - Prefer Orchard, otherwise Sapling; optionally include/exclude transparent
-/// - Shielded-only variants never include transparent receivers
-pub enum Zip316ReceiverPolicy {
-	/// Require Orchard if available; allow Sapling; omit transparent
-	OrchardPreferred,
-	/// Require Sapling; omit Orchard and transparent
-	SaplingOnly,
-	/// Allow any shielded (Orchard or Sapling); omit transparent
-	ShieldedOnly,
-	/// Prefer shielded; allow transparent if present
-	AllowTransparent,
-}
+    fn from_parts(
+        db_path: PathBuf,
+        seed: SecretVec<u8>,
+        mnemonic: Option<SecretString>,
+    ) -> Result<Self> {
+        Self::open_with_keys(db_path, SeedVault::new(seed, mnemonic), None)
+    }
 
-impl Wallet {
-	/// Generate a unified address using a ZIP-316 receiver policy
-	pub fn get_unified_address_with_policy(&self, policy: Zip316ReceiverPolicy) -> Result<String> {
-		let ufvk = self.get_unified_full_viewing_key()?;
+    fn open_with_keys(
+        db_path: PathBuf,
+        vault: SeedVault,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<Self> {
+        // Ensure parent directory exists
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
 
-		// Map policy to receiver requirements
-		let reqs = match policy {
-			Zip316ReceiverPolicy::OrchardPreferred => {
-				// Require Orchard, allow Sapling, omit transparent
-				ReceiverRequirements::new(
-					ReceiverRequirement::Require,
-					ReceiverRequirement::Allow,
-					ReceiverRequirement::Omit,
-				)
-				.map_err(|_| Error::Address("Invalid receiver requirement combination".to_string()))?
-			}
-			Zip316ReceiverPolicy::SaplingOnly => {
-				ReceiverRequirements::new(
-					ReceiverRequirement::Omit,
-					ReceiverRequirement::Require,
-					ReceiverRequirement::Omit,
-				)
-				.map_err(|_| Error::Address("Invalid receiver requirement combination".to_string()))?
-			}
-			Zip316ReceiverPolicy::ShieldedOnly => {
-				// Any shielded allowed, no transparent
-				ReceiverRequirements::SHIELDED
-			}
-			Zip316ReceiverPolicy::AllowTransparent => {
-				// Prefer shielded but allow transparent if present
-				ReceiverRequirements::ALLOW_ALL
-			}
-		};
+        let network = Network::default();
+        let mut wallet = Wallet {
+            pool: WalletDbPool::new(db_path.clone(), consensus_network(network)),
+            db_path,
+            network,
+            vault: Arc::new(vault),
+            key_provider,
+            account_id: AccountId::ZERO,
+            address_rotation: AddressRotation::Default,
+            change_policy: ChangePolicy::default(),
+            regtest_upgrades: RegtestUpgrades::default(),
+        };
 
-		let (ua, _) = ufvk
-			.default_address(UnifiedAddressRequest::Custom(reqs))
-			.map_err(|e| Error::Address(format!("Failed to generate unified address: {}", e)))?;
+        // Existing databases keep the network they were created for
+        if let Some(stored) = wallet.stored_network()? {
+            wallet.network = stored;
+            if let Some(upgrades) = wallet.stored_regtest_upgrades()? {
+                wallet.regtest_upgrades = upgrades;
+            }
+            wallet.pool = WalletDbPool::new(wallet.db_path.clone(), wallet.consensus_network());
+        }
 
-		match self.network {
-			Network::Mainnet => Ok(ua.encode(&MainNetwork)),
-			Network::Testnet => Ok(ua.encode(&TestNetwork)),
-			Network::Regtest => Ok(ua.encode(&TestNetwork)),
-		}
-	}
+        // An outdated database is left alone until `migrate` is called
+        if !wallet.pool.needs_migration()? {
+            wallet.initialize_database()?;
+            wallet.check_seed_matches_database()?;
+        }
 
-    /// Get a Sapling address
-    pub fn get_sapling_address(&self) -> Result<String> {
-        let ufvk = self.get_unified_full_viewing_key()?;
-        let sapling_dfvk = ufvk
-            .sapling()
-            .ok_or_else(|| Error::Address("No Sapling component in unified key".to_string()))?;
+        Ok(wallet)
+    }
 
-        let sapling_address = sapling_dfvk
-            .address(DiversifierIndex::new())
-            .ok_or_else(|| Error::Address("Failed to generate Sapling address".to_string()))?;
+    /// ZIP-32 fingerprint of the wallet seed, hex encoded
+    ///
+    /// The fingerprint identifies the seed without revealing it and is the
+    /// value zcashd and other ZIP-32 wallets record for derived accounts, so
+    /// it can be used as a stable wallet identifier across apps.
+    pub fn seed_fingerprint(&self) -> Result<String> {
+        Ok(hex::encode(self.fingerprint()?.to_bytes()))
+    }
 
-        match self.network {
-            Network::Mainnet => Ok(sapling_address.encode(&MainNetwork)),
-            Network::Testnet => Ok(sapling_address.encode(&TestNetwork)),
-            Network::Regtest => Ok(sapling_address.encode(&TestNetwork)),
+    fn fingerprint(&self) -> Result<SeedFingerprint> {
+        if let Some(provider) = &self.key_provider {
+            return provider.seed_fingerprint();
         }
+        let seed = self.vault.seed()?;
+        SeedFingerprint::from_seed(seed.expose_secret()).ok_or_else(|| {
+            Error::KeyDerivation(format!(
+                "Seed must be 32 to 252 bytes to fingerprint, got {}",
+                seed.expose_secret().len()
+            ))
+        })
     }
 
-    /// Get an Orchard address
-    pub fn get_orchard_address(&self) -> Result<String> {
-        // Orchard addresses are best accessed through unified addresses
-        // Return the unified address which includes Orchard support
-        self.get_unified_address()
+    /// Fail if the database holds seed-derived accounts, none of them from
+    /// this wallet's seed
+    ///
+    /// Opening another wallet's database with the wrong seed would otherwise
+    /// succeed and derive addresses that database never scans for.
+    fn check_seed_matches_database(&self) -> Result<()> {
+        let fingerprint = self.fingerprint()?;
+        let wallet_db = self.read_wallet_db()?;
+        let mut other = None;
+        for account_uuid in wallet_db
+            .get_account_ids()
+            .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+        {
+            let derivation = wallet_db
+                .get_account(account_uuid)
+                .map_err(|e| Error::Database(format!("Failed to read account: {}", e)))?
+                .and_then(|account| account.source().key_derivation().cloned());
+            match derivation {
+                Some(derivation) if *derivation.seed_fingerprint() == fingerprint => {
+                    return Ok(())
+                }
+                Some(derivation) => other = Some(*derivation.seed_fingerprint()),
+                None => {}
+            }
+        }
+        match other {
+            Some(other) => Err(Error::Wallet(format!(
+                "Wallet database {} belongs to seed {}, not to seed {}",
+                self.db_path.display(),
+                hex::encode(other.to_bytes()),
+                hex::encode(fingerprint.to_bytes())
+            ))),
+            None => Ok(()),
+        }
     }
 
-    /// Get a transparent address
-    pub fn get_transparent_address(&self) -> Result<String> {
-        let ufvk = self.get_unified_full_viewing_key()?;
-        let transparent_dfvk = ufvk
-            .transparent()
-            .ok_or_else(|| Error::Address("No transparent component in unified key".to_string()))?;
-
-        let external_ivk = transparent_dfvk
-            .derive_external_ivk()
-            .map_err(|e| Error::Address(format!("Failed to derive external IVK: {}", e)))?;
+    fn default_db_path() -> Result<PathBuf> {
+        Ok(dirs::data_dir()
+            .ok_or_else(|| Error::InvalidParameter("Cannot determine data directory".to_string()))?
+            .join("zcash-numi-sdk")
+            .join("wallet.db"))
+    }
 
-        use zcash_transparent::keys::IncomingViewingKey;
-        let (transparent_address, _) = external_ivk.default_address();
+    /// Create a wallet from an existing seed
+    pub fn from_seed(seed: Vec<u8>) -> Result<Self> {
+        Self::with_path_and_seed(Self::default_db_path()?, Some(seed))
+    }
 
+    /// Consensus parameters of the wallet's network, with its regtest
+    /// activation heights
+    pub(crate) fn consensus_network(&self) -> NetworkParams {
         match self.network {
-            Network::Mainnet => Ok(transparent_address.encode(&MainNetwork)),
-            Network::Testnet => Ok(transparent_address.encode(&TestNetwork)),
-            Network::Regtest => Ok(transparent_address.encode(&TestNetwork)),
+            Network::Regtest => NetworkParams::Regtest(self.regtest_upgrades),
+            network => consensus_network(network),
         }
     }
 
-    /// Get the current balance
-    pub fn get_balance(&self) -> Result<Balance> {
-        let wallet_db = self.open_initialized_wallet_db()?;
+    /// Get a read handle to the wallet database (see [`pool`])
+    pub(crate) fn read_wallet_db(&self) -> Result<ReadWalletDb> {
+        self.initialize_database()?;
+        self.pool.read()
+    }
 
-        let summary = wallet_db
-            .get_wallet_summary(ConfirmationsPolicy::default())
-            .map_err(|e| Error::Database(format!("Failed to read wallet summary: {}", e)))?;
+    /// Get the wallet database's write handle (see [`pool`])
+    pub(crate) fn write_wallet_db(&self) -> Result<WriteWalletDb<'_>> {
+        self.initialize_database()?;
+        self.pool.write()
+    }
 
-        if let Some(summary) = summary {
-            let mut transparent_total = 0u64;
-            let mut sapling_total = 0u64;
-            let mut orchard_total = 0u64;
+    /// Get a query-only connection to the wallet database from the pool
+    fn read_connection(&self) -> Result<PooledConnection> {
+        self.initialize_database()?;
+        self.pool.connection()
+    }
 
-            for account_balance in summary.account_balances().values() {
-                transparent_total = transparent_total
-                    .checked_add(u64::from(account_balance.unshielded_balance().total()))
-                    .ok_or_else(|| {
-                        Error::Wallet("Transparent balance exceeds u64 range".to_string())
-                    })?;
+    fn initialize_database(&self) -> Result<()> {
+        if self.pool.is_initialized() {
+            return Ok(());
+        }
+        let seed = self.migration_seed()?;
+        self.pool.initialize(seed.as_ref().map(|seed| seed.expose_secret().as_slice()))
+    }
 
-                sapling_total = sapling_total
-                    .checked_add(u64::from(account_balance.sapling_balance().total()))
-                    .ok_or_else(|| {
-                        Error::Wallet("Sapling balance exceeds u64 range".to_string())
-                    })?;
+    /// Seed for schema migrations that check seed-derived accounts; `None`
+    /// if it is held by a key provider
+    fn migration_seed(&self) -> Result<Option<SecretVec<u8>>> {
+        match &self.key_provider {
+            Some(_) => Ok(None),
+            None => self.vault.seed().map(Some),
+        }
+    }
 
-                orchard_total = orchard_total
-                    .checked_add(u64::from(account_balance.orchard_balance().total()))
-                    .ok_or_else(|| {
-                        Error::Wallet("Orchard balance exceeds u64 range".to_string())
-                    })?;
-            }
+    /// Get the wallet's database connection pool, initializing the schema
+    /// if needed
+    ///
+    /// Clones share the wallet's connections, so a sync holding the write
+    /// handle does not block balance queries made through read handles.
+    pub fn db_pool(&self) -> Result<WalletDbPool> {
+        self.initialize_database()?;
+        Ok(self.pool.clone())
+    }
 
-            let total = transparent_total
-                .checked_add(sapling_total)
-                .and_then(|value| value.checked_add(orchard_total))
-                .ok_or_else(|| Error::Wallet("Total balance exceeds u64 range".to_string()))?;
+    /// Whether the wallet database lacks schema migrations of this SDK's
+    /// `zcash_client_sqlite` and must be migrated with
+    /// [`migrate`](Self::migrate) before use
+    ///
+    /// Until then every method that reads or writes the wallet database
+    /// fails with [`Error::Database`].
+    pub fn needs_migration(&self) -> Result<bool> {
+        self.pool.needs_migration()
+    }
 
-            Ok(Balance {
-                transparent: transparent_total,
-                sapling: sapling_total,
-                orchard: orchard_total,
-                total,
-            })
-        } else {
-            Ok(Balance::default())
+    /// Schema state of the wallet database
+    pub fn schema_version(&self) -> Result<SchemaVersion> {
+        self.pool.schema_version()
+    }
+
+    /// Run pending wallet database migrations
+    ///
+    /// Migrations of large databases can take minutes; run this once at
+    /// startup (or in a maintenance window) rather than on first use.
+    pub fn migrate(&self) -> Result<()> {
+        self.migrate_with_progress(|_| {})
+    }
+
+    /// Run pending wallet database migrations, reporting progress
+    ///
+    /// # Arguments
+    /// * `progress` - Called about every 250 ms while migrations run and
+    ///   once when they are done
+    pub fn migrate_with_progress(&self, progress: impl FnMut(MigrationProgress)) -> Result<()> {
+        let seed = self.migration_seed()?;
+        self.pool.migrate(
+            seed.as_ref().map(|seed| seed.expose_secret().as_slice()),
+            progress,
+        )?;
+        self.check_seed_matches_database()
+    }
+
+    /// Set the network for this wallet
+    ///
+    /// The wallet database is bound to the network it was first used with:
+    /// the network set here, or the one in effect when an account was first
+    /// created. Opening the database again restores that network, and
+    /// setting a different one fails, because addresses and keys would be
+    /// derived for the wrong chain.
+    ///
+    /// # Returns
+    /// [`Error::Wallet`] if the database belongs to another network; use
+    /// [`override_network`](Self::override_network) to rebind it anyway
+    pub fn set_network(&mut self, network: Network) -> Result<()> {
+        if let Some(stored) = self.stored_network()? {
+            if stored != network {
+                return Err(Error::Wallet(format!(
+                    "Wallet database {} belongs to {:?}, not {:?}",
+                    self.db_path.display(),
+                    stored,
+                    network
+                )));
+            }
         }
+        self.override_network(network)
     }
 
-    /// Get transaction history
+    /// Set the network for this wallet and rebind the database to it,
+    /// even if it was created for another network
     ///
-    /// Retrieves transaction history from the wallet database using zcash_client_backend APIs.
-    /// 
-    /// Note: Full transaction history retrieval requires scanning the blockchain and
-    /// maintaining transaction metadata. For production use, consider using zcashd RPC
-    /// methods like `z_listreceivedbyaddress` or `z_viewtransaction` for transaction details.
-    pub fn get_transactions(
-        &self,
-        _limit: Option<usize>,
-    ) -> Result<Vec<crate::types::Transaction>> {
-        // TODO: Implement full transaction history using zcash_client_backend APIs
-        // This requires:
-        // 1. Scanning blocks with viewing keys
-        // 2. Maintaining transaction metadata in the wallet database
-        // 3. Querying received/sent notes per transaction
-        // 
-        // For now, return empty vector. Use RPC client methods for transaction queries.
-        Ok(Vec::new())
+    /// Only for recovering a database bound to the wrong network, e.g. one
+    /// created before the network was set.
+    pub fn override_network(&mut self, network: Network) -> Result<()> {
+        self.store_network(network)?;
+        self.network = network;
+        self.pool = WalletDbPool::new(self.db_path.clone(), self.consensus_network());
+        Ok(())
     }
 
-    /// Get the wallet database handle for advanced operations
+    /// Use the network upgrade activation heights of a regtest node
     ///
-    /// This provides direct access to the underlying WalletDb for use with
-    /// zcash_client_backend APIs that require WalletRead/WalletWrite traits.
-    pub fn wallet_db(
-        &self,
-    ) -> Result<WalletDb<rusqlite::Connection, ConsensusNetwork, SystemClock, rand::rngs::ThreadRng>>
-    {
-        self.open_initialized_wallet_db()
+    /// Regtest nodes choose their own heights, so the defaults (every
+    /// upgrade at height 1) are only right for some of them; read the
+    /// node's heights with
+    /// [`Faucet::regtest_upgrades`](crate::regtest::Faucet::regtest_upgrades).
+    /// The heights are stored in the wallet database and used by every
+    /// wallet opened on it, and by light clients created from it.
+    ///
+    /// # Errors
+    /// [`Error::InvalidParameter`] unless the wallet is on regtest
+    pub fn set_regtest_upgrades(&mut self, upgrades: RegtestUpgrades) -> Result<()> {
+        if self.network != Network::Regtest {
+            return Err(Error::InvalidParameter(format!(
+                "Activation heights can only be set on regtest, the wallet is on {:?}",
+                self.network
+            )));
+        }
+        self.open_network_table()?
+            .execute(
+                "INSERT INTO numi_regtest_upgrades (id, upgrades) VALUES (0, ?1)
+                 ON CONFLICT(id) DO UPDATE SET upgrades = excluded.upgrades",
+                [serde_json::to_string(&upgrades)?],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        self.regtest_upgrades = upgrades;
+        self.pool = WalletDbPool::new(self.db_path.clone(), self.consensus_network());
+        Ok(())
     }
-}
 
-impl Default for Wallet {
-    fn default() -> Self {
-        Self::new().expect("Failed to create default wallet")
+    /// Regtest activation heights stored in the wallet database, if any
+    fn stored_regtest_upgrades(&self) -> Result<Option<RegtestUpgrades>> {
+        let stored: Option<String> = self
+            .open_network_table()?
+            .query_row(
+                "SELECT upgrades FROM numi_regtest_upgrades WHERE id = 0",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(stored.map(|json| serde_json::from_str(&json)).transpose()?)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Network the wallet database is bound to, if any
+    fn stored_network(&self) -> Result<Option<Network>> {
+        let conn = self.open_network_table()?;
+        let stored: Option<String> = conn
+            .query_row("SELECT network FROM numi_wallet_network WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?;
+        stored
+            .map(|name| match name.as_str() {
+                "mainnet" => Ok(Network::Mainnet),
+                "testnet" => Ok(Network::Testnet),
+                "regtest" => Ok(Network::Regtest),
+                other => Err(Error::Database(format!("Unknown wallet network {}", other))),
+            })
+            .transpose()
+    }
 
-    #[test]
-    fn test_wallet_creation() {
-        let temp_dir = std::env::temp_dir();
-        let db_path = temp_dir.join("test_wallet.db");
-        let wallet = Wallet::with_path(db_path.clone()).unwrap();
+    /// Bind the wallet database to `network`
+    fn store_network(&self, network: Network) -> Result<()> {
+        let name = match network {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        };
+        self.open_network_table()?
+            .execute(
+                "INSERT INTO numi_wallet_network (id, network) VALUES (0, ?1)
+                 ON CONFLICT(id) DO UPDATE SET network = excluded.network",
+                [name],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Bind the wallet database to the current network unless it is bound
+    fn claim_network(&self) -> Result<()> {
+        if self.stored_network()?.is_none() {
+            self.store_network(self.network)?;
+        }
+        Ok(())
+    }
+
+    fn open_network_table(&self) -> Result<rusqlite::Connection> {
+        let conn = open_connection(&self.db_path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_wallet_network (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                network TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS numi_regtest_upgrades (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                upgrades TEXT NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(conn)
+    }
+
+    /// Get the current network
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Use the given address rotation policy for
+    /// [`get_unified_address`](Self::get_unified_address)
+    pub fn with_address_rotation(mut self, rotation: AddressRotation) -> Self {
+        self.address_rotation = rotation;
+        self
+    }
+
+    /// Set the address rotation policy
+    pub fn set_address_rotation(&mut self, rotation: AddressRotation) {
+        self.address_rotation = rotation;
+    }
+
+    /// Get the address rotation policy
+    pub fn address_rotation(&self) -> AddressRotation {
+        self.address_rotation
+    }
+
+    /// Use the given change policy for sends from this wallet
+    pub fn with_change_policy(mut self, policy: ChangePolicy) -> Self {
+        self.change_policy = policy;
+        self
+    }
+
+    /// Set the change policy
+    pub fn set_change_policy(&mut self, policy: ChangePolicy) {
+        self.change_policy = policy;
+    }
+
+    /// Get the change policy
+    pub fn change_policy(&self) -> ChangePolicy {
+        self.change_policy
+    }
+
+    /// Get the unified spending key for this wallet
+    pub(crate) fn get_unified_spending_key(&self) -> Result<UnifiedSpendingKey> {
+        self.spending_key_for(self.account_id)
+    }
+
+    /// Derive the ZIP-32 unified spending key for an account
+    fn spending_key_for(&self, account_id: AccountId) -> Result<UnifiedSpendingKey> {
+        let seed = self.seed_vault()?.seed()?;
+        let seed = seed.expose_secret();
+        UnifiedSpendingKey::from_seed(&self.consensus_network(), seed, account_id).map_err(|e| {
+            Error::KeyDerivation(format!("Failed to derive unified spending key: {}", e))
+        })
+    }
+
+    /// Get the unified full viewing key for this wallet
+    fn get_unified_full_viewing_key(&self) -> Result<UnifiedFullViewingKey> {
+        self.viewing_key_for(self.account_id)
+    }
+
+    /// Derive the unified full viewing key of an account, with the key
+    /// provider if the wallet has one
+    fn viewing_key_for(&self, account_id: AccountId) -> Result<UnifiedFullViewingKey> {
+        match &self.key_provider {
+            Some(provider) => provider.unified_full_viewing_key(self.network, account_id),
+            None => Ok(self
+                .spending_key_for(account_id)?
+                .to_unified_full_viewing_key()),
+        }
+    }
+
+    /// Get the unified full viewing key (public method for light client use)
+    pub fn unified_full_viewing_key(&self) -> Result<UnifiedFullViewingKey> {
+        self.get_unified_full_viewing_key()
+    }
+
+    /// Get a unified address of the selected account
+    ///
+    /// Which address is returned depends on the wallet's
+    /// [`AddressRotation`] policy: the default address, or a diversified
+    /// address that changes per call or per day. Rotated addresses are
+    /// allocated by the wallet database, so the wallet keeps recognizing
+    /// payments to them, and have only shielded receivers.
+    pub fn get_unified_address(&self) -> Result<String> {
+        match self.address_rotation {
+            AddressRotation::Default => self.default_unified_address(),
+            AddressRotation::PerCall => Ok(self.get_next_unified_address()?.0),
+            AddressRotation::Daily => self.daily_unified_address(),
+        }
+    }
+
+    /// Default unified address of the selected account
+    ///
+    /// Unlike [`get_unified_address`](Self::get_unified_address), this
+    /// ignores the [`AddressRotation`] policy and allocates nothing.
+    pub fn default_unified_address(&self) -> Result<String> {
+        let ufvk = self.get_unified_full_viewing_key()?;
+        let (ua, _) = ufvk
+            .default_address(UnifiedAddressRequest::ALLOW_ALL)
+            .map_err(|e| Error::Address(format!("Failed to generate unified address: {}", e)))?;
+
+        Ok(ua.encode(&self.consensus_network()))
+    }
+
+    /// Generate a diversified unified address
+    ///
+    /// Not every diversifier index yields a valid Sapling receiver, so this
+    /// searches upwards from `start_index` for the first usable index.
+    ///
+    /// # Returns
+    /// The encoded address and the diversifier index it was derived at
+    pub fn get_diversified_address(&self, start_index: u64) -> Result<(String, u64)> {
+        let ufvk = self.get_unified_full_viewing_key()?;
+        let (ua, index) = ufvk
+            .find_address(
+                DiversifierIndex::from(start_index),
+                UnifiedAddressRequest::ALLOW_ALL,
+            )
+            .map_err(|e| Error::Address(format!("Failed to generate diversified address: {}", e)))?;
+        let index = u64::try_from(index)
+            .map_err(|_| Error::Address("Diversifier index exceeds u64 range".to_string()))?;
+
+        let encoded = ua.encode(&self.consensus_network());
+        Ok((encoded, index))
+    }
+
+    /// Diversified unified address assigned to an application identifier
+    ///
+    /// The same `id` (e.g. a customer ID) always maps to the same address,
+    /// so no mapping table needs to be stored. The diversifier index is a
+    /// hash of `id` keyed with the account's viewing key: every copy of the
+    /// wallet derives the same address, but without the viewing key the
+    /// address cannot be linked to the identifier. Indexes are drawn from
+    /// `[2^62, 2^63)`, above those handed out by
+    /// [`get_next_unified_address`](Self::get_next_unified_address), where
+    /// addresses have no transparent receiver.
+    ///
+    /// # Returns
+    /// The encoded address and the diversifier index it was derived at
+    pub fn address_for_external_id(&self, id: &str) -> Result<(String, u64)> {
+        if id.is_empty() {
+            return Err(Error::InvalidParameter(
+                "External ID must not be empty".to_string(),
+            ));
+        }
+        let ufvk = self.get_unified_full_viewing_key()?;
+        let key = blake2b_simd::Params::new()
+            .hash_length(32)
+            .personal(b"NumiExtIdAddrKey")
+            .hash(ufvk.encode(&self.consensus_network()).as_bytes());
+        self.get_diversified_address(external_id_index(key.as_bytes(), id))
+    }
+
+    /// Generate the next unused diversified unified address
+    ///
+    /// Each call returns a fresh address for the selected account, so every
+    /// customer or invoice can be given its own address (ZIP-316 address
+    /// rotation). Addresses are allocated and recorded by the wallet
+    /// database, so sync recognizes payments to them, and have only shielded
+    /// receivers. The selected account must have been created in the wallet
+    /// database (see [`create_account`](Self::create_account)).
+    ///
+    /// # Returns
+    /// The encoded address and the diversifier index it was derived at
+    pub fn get_next_unified_address(&self) -> Result<(String, u64)> {
+        let ufvk = self.get_unified_full_viewing_key()?;
+        let mut wallet_db = self.write_wallet_db()?;
+        let account = wallet_db
+            .get_account_for_ufvk(&ufvk)
+            .map_err(|e| Error::Database(format!("Failed to get account for UFVK: {}", e)))?
+            .ok_or_else(|| {
+                Error::Wallet("The selected account is not in the wallet database".to_string())
+            })?
+            .id();
+        let (ua, index) = wallet_db
+            .get_next_available_address(account, ROTATED_ADDRESSES)
+            .map_err(|e| Error::Address(format!("Failed to generate unified address: {}", e)))?
+            .ok_or_else(|| Error::Address("No diversifier index left".to_string()))?;
+        let index = u64::try_from(index)
+            .map_err(|_| Error::Address("Diversifier index exceeds u64 range".to_string()))?;
+
+        Ok((ua.encode(&self.consensus_network()), index))
+    }
+
+    /// Address of the current UTC day, rotated on the first call each day
+    ///
+    /// A new day's address is allocated like
+    /// [`get_next_unified_address`](Self::get_next_unified_address); only its
+    /// diversifier index is stored, to hand out the same address all day.
+    fn daily_unified_address(&self) -> Result<String> {
+        let today = unix_now() / 86_400;
+        let account_index = u32::from(self.account_id);
+
+        if let Some(index) = self.daily_index(account_index, today)? {
+            return self.rotated_address(index);
+        }
+        let (_, index) = self.get_next_unified_address()?;
+        // Another caller may have rotated first; its address wins
+        self.open_daily_address_table()?
+            .execute(
+                "INSERT INTO numi_daily_address (account_index, day, diversifier_index)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(account_index) DO UPDATE SET
+                    day = excluded.day,
+                    diversifier_index = excluded.diversifier_index
+                 WHERE numi_daily_address.day <> excluded.day",
+                rusqlite::params![account_index, today as i64, index as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let index = self.daily_index(account_index, today)?.unwrap_or(index);
+        self.rotated_address(index)
+    }
+
+    /// Diversifier index of the account's address for `day`, if one was
+    /// handed out
+    fn daily_index(&self, account_index: u32, day: u64) -> Result<Option<u64>> {
+        let stored: Option<(i64, i64)> = self
+            .open_daily_address_table()?
+            .query_row(
+                "SELECT day, diversifier_index FROM numi_daily_address WHERE account_index = ?1",
+                [account_index],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(stored
+            .filter(|(stored_day, _)| *stored_day as u64 == day)
+            .map(|(_, index)| index as u64))
+    }
+
+    /// Get the write connection, creating the daily address table
+    fn open_daily_address_table(&self) -> Result<WriteConnection<'_>> {
+        self.initialize_database()?;
+        let conn = self.pool.write_connection()?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_daily_address (
+                account_index INTEGER PRIMARY KEY,
+                day INTEGER NOT NULL,
+                diversifier_index INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(conn)
+    }
+
+    /// Rotated address of the selected account at a diversifier index
+    fn rotated_address(&self, index: u64) -> Result<String> {
+        let ua = self
+            .get_unified_full_viewing_key()?
+            .address(DiversifierIndex::from(index), ROTATED_ADDRESSES)
+            .map_err(|e| {
+                Error::Address(format!("Failed to generate diversified address: {}", e))
+            })?;
+        Ok(ua.encode(&self.consensus_network()))
+    }
+}
+
+/// Receivers of rotated addresses; the wallet database does not track
+/// transparent receivers at diversified indexes
+const ROTATED_ADDRESSES: UnifiedAddressRequest =
+    UnifiedAddressRequest::Custom(ReceiverRequirements::SHIELDED);
+
+/// Encoded address the wallet hands out at a diversifier index
+///
+/// The default address's index yields the default address and any other
+/// index a rotated address, matching what
+/// [`Wallet::default_unified_address`] and
+/// [`Wallet::get_next_unified_address`] return.
+pub(crate) fn address_at_index<P: Parameters>(
+    params: &P,
+    ufvk: &UnifiedFullViewingKey,
+    index: DiversifierIndex,
+) -> Option<String> {
+    let (default, default_index) = ufvk
+        .default_address(UnifiedAddressRequest::ALLOW_ALL)
+        .ok()?;
+    let ua = if index == default_index {
+        default
+    } else {
+        ufvk.address(index, ROTATED_ADDRESSES).ok()?
+    };
+    Some(ua.encode(params))
+}
+
+const DB_CONTEXT: &str = "Wallet database error";
+
+/// Diversifier index for an external ID, in `[2^62, 2^63)` so the upward
+/// search for a valid index stays within `u64`
+fn external_id_index(key: &[u8], id: &str) -> u64 {
+    let hash = blake2b_simd::Params::new()
+        .hash_length(8)
+        .key(key)
+        .personal(b"NumiExtIdAddress")
+        .hash(id.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(hash.as_bytes());
+    (u64::from_le_bytes(bytes) >> 2) | (1 << 62)
+}
+
+/// Total balance of every account in a wallet database, except the
+/// archived accounts whose UUIDs are in `archived`
+pub(crate) fn wallet_balance<DB>(wallet_db: &DB, archived: &HashSet<String>) -> Result<Balance>
+where
+    DB: WalletRead<AccountId = zcash_client_sqlite::AccountUuid>,
+    DB::Error: std::fmt::Display,
+{
+    let summary = wallet_db
+        .get_wallet_summary(ConfirmationsPolicy::default())
+        .map_err(|e| Error::Database(format!("Failed to read wallet summary: {}", e)))?;
+
+    if let Some(summary) = summary {
+        let mut transparent_total = 0u64;
+        let mut sapling_total = 0u64;
+        let mut orchard_total = 0u64;
+
+        for (uuid, account_balance) in summary.account_balances() {
+            if archived.contains(&uuid.expose_uuid().to_string()) {
+                continue;
+            }
+            transparent_total = transparent_total
+                .checked_add(u64::from(account_balance.unshielded_balance().total()))
+                .ok_or_else(|| {
+                    Error::Wallet("Transparent balance exceeds u64 range".to_string())
+                })?;
+
+            sapling_total = sapling_total
+                .checked_add(u64::from(account_balance.sapling_balance().total()))
+                .ok_or_else(|| Error::Wallet("Sapling balance exceeds u64 range".to_string()))?;
+
+            orchard_total = orchard_total
+                .checked_add(u64::from(account_balance.orchard_balance().total()))
+                .ok_or_else(|| Error::Wallet("Orchard balance exceeds u64 range".to_string()))?;
+        }
+
+        let total = transparent_total
+            .checked_add(sapling_total)
+            .and_then(|value| value.checked_add(orchard_total))
+            .ok_or_else(|| Error::Wallet("Total balance exceeds u64 range".to_string()))?;
+
+        Ok(Balance {
+            transparent: transparent_total,
+            sapling: sapling_total,
+            orchard: orchard_total,
+            total,
+        })
+    } else {
+        Ok(Balance::default())
+    }
+}
+
+/// Consensus parameters of `network`, with the default regtest activation
+/// heights; enough for address encoding
+pub(crate) fn consensus_network(network: Network) -> NetworkParams {
+    NetworkParams::from(network)
+}
+
+/// BIP-39 seed of a mnemonic, without leaving unzeroized copies behind
+fn mnemonic_seed(mnemonic: &Mnemonic, passphrase: &str) -> SecretVec<u8> {
+    let seed = Zeroizing::new(mnemonic.to_seed(passphrase));
+    SecretVec::new(seed.to_vec())
+}
+
+/// Text of an encoded memo, or `None` for empty and non-text memos
+pub(crate) fn memo_text(bytes: &[u8]) -> Option<String> {
+    match MemoBytes::from_bytes(bytes).ok().map(Memo::try_from)? {
+        Ok(Memo::Text(text)) => Some(text.to_string()),
+        _ => None,
+    }
+}
+
+/// Memo filters of a [`TransactionQuery`], prepared for matching
+struct MemoMatcher {
+    /// Lowercased substring
+    contains: Option<String>,
+    regex: Option<Regex>,
+}
+
+impl MemoMatcher {
+    /// Build the matcher, or `None` if the query has no memo filter
+    fn new(query: &TransactionQuery) -> Result<Option<Self>> {
+        if query.memo_contains.is_none() && query.memo_regex.is_none() {
+            return Ok(None);
+        }
+        let regex = query
+            .memo_regex
+            .as_deref()
+            .map(|pattern| RegexBuilder::new(pattern).case_insensitive(true).build())
+            .transpose()
+            .map_err(|e| Error::InvalidParameter(format!("Invalid memo pattern: {}", e)))?;
+        Ok(Some(Self {
+            contains: query.memo_contains.as_ref().map(|text| text.to_lowercase()),
+            regex,
+        }))
+    }
+
+    fn matches(&self, memo: &str) -> bool {
+        self.contains
+            .as_ref()
+            .is_none_or(|text| memo.to_lowercase().contains(text.as_str()))
+            && self.regex.as_ref().is_none_or(|regex| regex.is_match(memo))
+    }
+}
+
+/// ZIP-316 policy for Unified Address receiver selection
+///
+/// Policies align with priority rules:
+/// - Prefer Orchard, otherwise Sapling; optionally include/exclude transparent
+/// - Shielded-only variants never include transparent receivers
+pub enum Zip316ReceiverPolicy {
+	/// Require Orchard if available; allow Sapling; omit transparent
+	OrchardPreferred,
+	/// Require Sapling; omit Orchard and transparent
+	SaplingOnly,
+	/// Allow any shielded (Orchard or Sapling); omit transparent
+	ShieldedOnly,
+	/// Prefer shielded; allow transparent if present
+	AllowTransparent,
+}
+
+impl Wallet {
+	/// Generate a unified address using a ZIP-316 receiver policy
+	pub fn get_unified_address_with_policy(&self, policy: Zip316ReceiverPolicy) -> Result<String> {
+		let ufvk = self.get_unified_full_viewing_key()?;
+
+		// Map policy to receiver requirements
+		let reqs = match policy {
+			Zip316ReceiverPolicy::OrchardPreferred => {
+				// Require Orchard, allow Sapling, omit transparent
+				ReceiverRequirements::new(
+					ReceiverRequirement::Require,
+					ReceiverRequirement::Allow,
+					ReceiverRequirement::Omit,
+				)
+				.map_err(|_| Error::Address("Invalid receiver requirement combination".to_string()))?
+			}
+			Zip316ReceiverPolicy::SaplingOnly => {
+				ReceiverRequirements::new(
+					ReceiverRequirement::Omit,
+					ReceiverRequirement::Require,
+					ReceiverRequirement::Omit,
+				)
+				.map_err(|_| Error::Address("Invalid receiver requirement combination".to_string()))?
+			}
+			Zip316ReceiverPolicy::ShieldedOnly => {
+				// Any shielded allowed, no transparent
+				ReceiverRequirements::SHIELDED
+			}
+			Zip316ReceiverPolicy::AllowTransparent => {
+				// Prefer shielded but allow transparent if present
+				ReceiverRequirements::ALLOW_ALL
+			}
+		};
+
+		let (ua, _) = ufvk
+			.default_address(UnifiedAddressRequest::Custom(reqs))
+			.map_err(|e| Error::Address(format!("Failed to generate unified address: {}", e)))?;
+
+		Ok(ua.encode(&self.consensus_network()))
+	}
+
+    /// Get a Sapling address
+    pub fn get_sapling_address(&self) -> Result<String> {
+        let ufvk = self.get_unified_full_viewing_key()?;
+        let sapling_dfvk = ufvk
+            .sapling()
+            .ok_or_else(|| Error::Address("No Sapling component in unified key".to_string()))?;
+
+        let sapling_address = sapling_dfvk
+            .address(DiversifierIndex::new())
+            .ok_or_else(|| Error::Address("Failed to generate Sapling address".to_string()))?;
+
+        Ok(sapling_address.encode(&self.consensus_network()))
+    }
+
+    /// Get an Orchard address
+    ///
+    /// A Unified Address containing only the Orchard receiver of the default
+    /// unified address; see [`get_orchard_receiver`](Self::get_orchard_receiver).
+    pub fn get_orchard_address(&self) -> Result<String> {
+        Ok(self.get_orchard_receiver()?.address)
+    }
+
+    /// Get the Orchard receiver of the default unified address
+    ///
+    /// # Returns
+    /// The receiver encoded as an Orchard-only Unified Address and as raw bytes
+    pub fn get_orchard_receiver(&self) -> Result<OrchardReceiver> {
+        let ufvk = self.get_unified_full_viewing_key()?;
+        if ufvk.orchard().is_none() {
+            return Err(Error::Address("No Orchard component in unified key".to_string()));
+        }
+        let (ua, _) = ufvk
+            .default_address(UnifiedAddressRequest::ALLOW_ALL)
+            .map_err(|e| Error::Address(format!("Failed to generate unified address: {}", e)))?;
+        let orchard = ua
+            .orchard()
+            .ok_or_else(|| Error::Address("Unified address has no Orchard receiver".to_string()))?;
+        let orchard_only = UnifiedAddress::from_receivers(Some(*orchard), None, None)
+            .ok_or_else(|| Error::Address("Failed to build Orchard-only address".to_string()))?;
+
+        Ok(OrchardReceiver {
+            address: orchard_only.encode(&self.consensus_network()),
+            raw: hex::encode(orchard.to_raw_address_bytes()),
+        })
+    }
+
+    /// Get the Sapling extended full viewing key of the selected account
+    ///
+    /// Encoded as zcashd's `z_exportviewingkey` does (`zxviews…`), e.g. for
+    /// watching the account's Sapling addresses from a node.
+    pub fn sapling_viewing_key(&self) -> Result<String> {
+        let usk = self.get_unified_spending_key()?;
+        // zcashd only accepts the extended form
+        #[allow(deprecated)]
+        let extfvk = usk.sapling().to_extended_full_viewing_key();
+        Ok(encode_extended_full_viewing_key(
+            self.consensus_network().hrp_sapling_extended_full_viewing_key(),
+            &extfvk,
+        ))
+    }
+
+    /// Get a transparent address
+    pub fn get_transparent_address(&self) -> Result<String> {
+        let ufvk = self.get_unified_full_viewing_key()?;
+        let transparent_dfvk = ufvk
+            .transparent()
+            .ok_or_else(|| Error::Address("No transparent component in unified key".to_string()))?;
+
+        let external_ivk = transparent_dfvk
+            .derive_external_ivk()
+            .map_err(|e| Error::Address(format!("Failed to derive external IVK: {}", e)))?;
+
+        use zcash_transparent::keys::IncomingViewingKey;
+        let (transparent_address, _) = external_ivk.default_address();
+
+        Ok(transparent_address.encode(&self.consensus_network()))
+    }
+
+    /// Derive the transparent address at `index` on `chain`
+    ///
+    /// Index 0 of the external chain is the address returned by
+    /// [`get_transparent_address`](Self::get_transparent_address).
+    pub fn get_transparent_address_at(
+        &self,
+        chain: TransparentChain,
+        index: u32,
+    ) -> Result<String> {
+        TransparentAddresses::for_wallet(self)?.derive(chain, index)
+    }
+
+    /// Hand out the next sequential transparent address on `chain`
+    ///
+    /// Handed out addresses are always checked by gap-limit discovery during
+    /// sync (see [`transparent`]), so funds sent to them are found even if
+    /// many of them stay unused. Ephemeral addresses are reserved with
+    /// [`next_ephemeral_address`](Self::next_ephemeral_address) instead.
+    ///
+    /// # Returns
+    /// The encoded address and its index on the chain
+    pub fn get_next_transparent_address(&self, chain: TransparentChain) -> Result<(String, u32)> {
+        if chain == TransparentChain::Ephemeral {
+            return Err(Error::InvalidParameter(
+                "Ephemeral addresses are not receive addresses".to_string(),
+            ));
+        }
+        let info = TransparentAddresses::for_wallet(self)?.next_address(chain)?;
+        Ok((info.address, info.index))
+    }
+
+    /// Reserve a fresh ZIP-320 ephemeral address for a payment to a TEX
+    /// address
+    ///
+    /// Each ephemeral address must receive exactly one payment, which is then
+    /// forwarded to the TEX recipient. The address is tracked by sync like
+    /// any other transparent address, but never listed as a receive address.
+    ///
+    /// # Returns
+    /// The encoded address and its index on the ephemeral chain
+    pub fn next_ephemeral_address(&self) -> Result<(String, u32)> {
+        let info =
+            TransparentAddresses::for_wallet(self)?.next_address(TransparentChain::Ephemeral)?;
+        Ok((info.address, info.index))
+    }
+
+    /// Transparent addresses of the selected account that were handed out or
+    /// checked during sync, including change and ephemeral addresses
+    pub fn transparent_addresses(&self) -> Result<Vec<TransparentAddressInfo>> {
+        TransparentAddresses::for_wallet(self)?.list()
+    }
+
+    /// Transparent addresses of the selected account handed out to payers
+    pub fn transparent_receive_addresses(&self) -> Result<Vec<TransparentAddressInfo>> {
+        TransparentAddresses::for_wallet(self)?.receive_addresses()
+    }
+
+    /// Shielded addresses of every account in the wallet database
+    ///
+    /// Every unified address the database allocated (the default address
+    /// and any diversified or rotated one) and the Sapling address of its
+    /// Sapling receiver. Nothing new is allocated.
+    pub fn all_shielded_addresses(&self) -> Result<Vec<String>> {
+        let network = self.consensus_network();
+        let wallet_db = self.read_wallet_db()?;
+        let mut addresses = BTreeSet::new();
+        for account in wallet_db
+            .get_account_ids()
+            .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+        {
+            for info in wallet_db
+                .list_addresses(account)
+                .map_err(|e| Error::Database(format!("Failed to list addresses: {}", e)))?
+            {
+                if let Address::Unified(ua) = info.address() {
+                    addresses.insert(ua.encode(&network));
+                    if let Some(sapling) = ua.sapling() {
+                        addresses.insert(sapling.encode(&network));
+                    }
+                }
+            }
+        }
+        Ok(addresses.into_iter().collect())
+    }
+
+    /// Transparent addresses of every account in the wallet database
+    ///
+    /// Includes change, ephemeral and imported standalone addresses.
+    pub fn all_transparent_addresses(&self) -> Result<Vec<String>> {
+        let network = self.consensus_network();
+        let mut addresses = BTreeSet::new();
+        {
+            let wallet_db = self.read_wallet_db()?;
+            for account in wallet_db
+                .get_account_ids()
+                .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+            {
+                let receivers = wallet_db
+                    .get_transparent_receivers(account, true, true)
+                    .map_err(|e| {
+                        Error::Database(format!("Failed to list transparent addresses: {}", e))
+                    })?;
+                for address in receivers.into_keys() {
+                    addresses.insert(address.encode(&network));
+                }
+            }
+        }
+        addresses.extend(
+            self.transparent_addresses()?
+                .into_iter()
+                .map(|info| info.address),
+        );
+        Ok(addresses.into_iter().collect())
+    }
+
+    /// Get the current balance of all accounts except archived ones
+    pub fn get_balance(&self) -> Result<Balance> {
+        let archived = AccountMetadataStore::for_wallet(self)?.archived()?;
+        wallet_balance(&self.read_wallet_db()?, &archived)
+    }
+
+    /// Balance snapshots recorded during sync in a height range, oldest first
+    ///
+    /// One snapshot is recorded per scanned batch; see [`balance_history`]
+    /// and [`balance_history::daily`] for one point per day.
+    pub fn balance_history(&self, heights: RangeInclusive<u64>) -> Result<Vec<BalanceSnapshot>> {
+        BalanceHistory::for_wallet(self)?.range(heights)
+    }
+
+    /// Get the balance per pool split into spendable, pending change and
+    /// unconfirmed value, leaving out archived accounts
+    ///
+    /// # Arguments
+    /// * `confirmations` - Confirmations received value needs before it can
+    ///   be spent (at least 1); the wallet's own change uses the same depth
+    pub fn get_balance_detailed(&self, confirmations: u32) -> Result<DetailedBalance> {
+        let min_confirmations = NonZeroU32::new(confirmations).ok_or_else(|| {
+            Error::InvalidParameter("Confirmations must be at least 1".to_string())
+        })?;
+        let policy = ConfirmationsPolicy::new_symmetrical(min_confirmations);
+        let archived = AccountMetadataStore::for_wallet(self)?.archived()?;
+
+        let summary = self
+            .read_wallet_db()?
+            .get_wallet_summary(policy)
+            .map_err(|e| Error::Database(format!("Failed to read wallet summary: {}", e)))?;
+
+        let mut balance = DetailedBalance {
+            confirmations,
+            ..DetailedBalance::default()
+        };
+        let overflow = || Error::Wallet("Balance exceeds u64 range".to_string());
+        let account_balances = summary
+            .iter()
+            .flat_map(|s| s.account_balances())
+            .filter(|(uuid, _)| !archived.contains(&uuid.expose_uuid().to_string()));
+        for (_, account_balance) in account_balances {
+            let pools = [
+                (
+                    &mut balance.transparent,
+                    account_balance.unshielded_balance(),
+                ),
+                (&mut balance.sapling, account_balance.sapling_balance()),
+                (&mut balance.orchard, account_balance.orchard_balance()),
+            ];
+            for (total, pool) in pools {
+                let pool = PoolBalance {
+                    spendable: u64::from(pool.spendable_value()),
+                    pending_change: u64::from(pool.change_pending_confirmation()),
+                    unconfirmed: u64::from(pool.value_pending_spendability()),
+                    total: u64::from(pool.total()),
+                };
+                *total = total.checked_add(&pool).ok_or_else(overflow)?;
+            }
+        }
+        balance.total = balance
+            .transparent
+            .checked_add(&balance.sapling)
+            .and_then(|sum| sum.checked_add(&balance.orchard))
+            .ok_or_else(overflow)?;
+        Ok(balance)
+    }
+
+    /// Get the balance of every account in the wallet database, per pool
+    ///
+    /// Unlike [`get_balance`](Self::get_balance), which sums all accounts,
+    /// this keeps each account separate, e.g. for per-customer accounting.
+    /// Accounts without funds, or all accounts before the first sync, have a
+    /// zero balance. Archived accounts are left out.
+    ///
+    /// # Returns
+    /// Balances keyed by account UUID (see [`WalletAccount::uuid`])
+    pub fn get_account_balances(&self) -> Result<BTreeMap<String, AccountBalance>> {
+        let archived = AccountMetadataStore::for_wallet(self)?.archived()?;
+        let wallet_db = self.read_wallet_db()?;
+        let mut balances: BTreeMap<String, AccountBalance> = wallet_db
+            .get_account_ids()
+            .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+            .into_iter()
+            .map(|uuid| uuid.expose_uuid().to_string())
+            .filter(|uuid| !archived.contains(uuid))
+            .map(|uuid| (uuid, AccountBalance::default()))
+            .collect();
+
+        let Some(summary) = wallet_db
+            .get_wallet_summary(ConfirmationsPolicy::default())
+            .map_err(|e| Error::Database(format!("Failed to read wallet summary: {}", e)))?
+        else {
+            return Ok(balances);
+        };
+
+        for (uuid, account_balance) in summary.account_balances() {
+            if archived.contains(&uuid.expose_uuid().to_string()) {
+                continue;
+            }
+            let overflow =
+                || Error::Wallet(format!("Balance of account {} exceeds u64 range", uuid));
+            let pools = [
+                account_balance.unshielded_balance(),
+                account_balance.sapling_balance(),
+                account_balance.orchard_balance(),
+            ];
+            let mut pending = 0u64;
+            let mut total = 0u64;
+            for pool in pools {
+                pending = pending
+                    .checked_add(u64::from(pool.change_pending_confirmation()))
+                    .and_then(|sum| sum.checked_add(u64::from(pool.value_pending_spendability())))
+                    .ok_or_else(overflow)?;
+                total = total
+                    .checked_add(u64::from(pool.total()))
+                    .ok_or_else(overflow)?;
+            }
+            balances.insert(
+                uuid.expose_uuid().to_string(),
+                AccountBalance {
+                    transparent: u64::from(pools[0].total()),
+                    sapling: u64::from(pools[1].total()),
+                    orchard: u64::from(pools[2].total()),
+                    pending,
+                    total,
+                },
+            );
+        }
+        Ok(balances)
+    }
+
+    /// Get transaction history, most recent first
+    ///
+    /// Reads the transactions of the selected account from the wallet
+    /// database, as found by [`LightClient::sync`](crate::light_client::LightClient::sync).
+    /// Unmined transactions come first, then mined ones by descending height.
+    ///
+    /// # Arguments
+    /// * `limit` - Maximum number of transactions to return (None for all)
+    pub fn get_transactions(&self, limit: Option<usize>) -> Result<Vec<Transaction>> {
+        self.get_transactions_page(0, limit)
+    }
+
+    /// Get a page of transaction history, most recent first
+    ///
+    /// Each transaction has the net change of the account's balance
+    /// (negative for sent), the fee if the wallet knows it, the first text
+    /// memo of its outputs to or from the account, and its mined height in
+    /// the status. Unmined transactions past their expiry height are
+    /// reported as [`TransactionStatus::Rejected`].
+    ///
+    /// # Arguments
+    /// * `offset` - Number of transactions to skip
+    /// * `limit` - Maximum number of transactions to return (None for all)
+    pub fn get_transactions_page(
+        &self,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<Transaction>> {
+        self.query_transactions(None, offset, limit)
+    }
+
+    /// Read the selected account's transactions, optionally only those with
+    /// a memo in the memo index matching an FTS5 query
+    fn query_transactions(
+        &self,
+        memo_match: Option<&str>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<Transaction>> {
+        // Initializing the database creates the views below
+        let conn = self.read_connection()?;
+        let memo_filter = match memo_match {
+            Some(_) => {
+                "AND t.txid IN (
+                     SELECT m.txid FROM numi_memo_search s
+                     JOIN numi_memo_outputs m ON m.id = s.rowid
+                     JOIN v_tx_outputs o ON o.txid = m.txid AND o.output_pool = m.output_pool
+                                        AND o.output_index = m.output_index
+                     WHERE numi_memo_search MATCH ?4
+                       AND t.account_uuid IN (o.from_account_uuid, o.to_account_uuid))"
+            }
+            None => "",
+        };
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT t.txid, t.mined_height, t.expired_unmined, t.account_balance_delta,
+                        t.fee_paid, t.block_time,
+                        (SELECT o.memo FROM v_tx_outputs o
+                         WHERE o.txid = t.txid AND o.memo IS NOT NULL
+                           AND (o.from_account_uuid = t.account_uuid
+                                OR o.to_account_uuid = t.account_uuid)
+                         ORDER BY o.output_pool, o.output_index LIMIT 1)
+                 FROM v_transactions t
+                 JOIN accounts a ON a.uuid = t.account_uuid
+                 WHERE a.hd_account_index = ?1 {memo_filter}
+                 ORDER BY t.mined_height IS NOT NULL, t.mined_height DESC, t.txid
+                 LIMIT ?2 OFFSET ?3"
+            ))
+            .map_err(db_error(DB_CONTEXT))?;
+        let account = u32::from(self.account_id);
+        let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let offset = offset as i64;
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&account, &limit, &offset];
+        if let Some(memo_match) = &memo_match {
+            params.push(memo_match);
+        }
+        let rows = stmt
+            .query_map(params.as_slice(), |row| {
+                let mut txid: Vec<u8> = row.get(0)?;
+                // Transaction IDs are displayed in reverse byte order
+                txid.reverse();
+                let mined_height: Option<i64> = row.get(1)?;
+                let expired: Option<bool> = row.get(2)?;
+                let status = match (mined_height, expired) {
+                    (Some(height), _) => TransactionStatus::Confirmed {
+                        height: height as u64,
+                    },
+                    (None, Some(true)) => TransactionStatus::Rejected,
+                    (None, _) => TransactionStatus::Pending,
+                };
+                Ok(Transaction {
+                    txid: hex::encode(txid),
+                    status,
+                    amount: row.get(3)?,
+                    fee: row.get::<_, Option<i64>>(4)?.map_or(0, |fee| fee as u64),
+                    memo: row.get::<_, Option<Vec<u8>>>(6)?.and_then(|m| memo_text(&m)),
+                    timestamp: row.get::<_, Option<i64>>(5)?.map(|time| time as u64),
+                })
+            })
+            .map_err(db_error(DB_CONTEXT))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// Search the selected account's transaction history
+    ///
+    /// Memo filters look at every text memo of a transaction sent to or
+    /// from the account, not only the one shown in [`Transaction::memo`].
+    /// Amount filters compare the absolute value of [`Transaction::amount`].
+    ///
+    /// # Arguments
+    /// * `query` - Filters that must all match
+    ///
+    /// # Returns
+    /// Matching transactions, ordered like [`get_transactions`](Self::get_transactions)
+    pub fn search_transactions(&self, query: &TransactionQuery) -> Result<Vec<Transaction>> {
+        let matcher = MemoMatcher::new(query)?;
+        let transactions = self.get_transactions(None)?;
+        let conn = self.read_connection()?;
+        let mut memos = conn
+            .prepare(
+                "SELECT o.memo FROM v_tx_outputs o
+                 JOIN accounts a ON a.uuid IN (o.from_account_uuid, o.to_account_uuid)
+                 WHERE o.txid = ?1 AND a.hd_account_index = ?2 AND o.memo IS NOT NULL",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+
+        let mut matches = Vec::new();
+        for tx in transactions {
+            if query.limit.is_some_and(|limit| matches.len() >= limit) {
+                break;
+            }
+            let amount = tx.amount.unsigned_abs();
+            if query.min_amount.is_some_and(|min| amount < min)
+                || query.max_amount.is_some_and(|max| amount > max)
+            {
+                continue;
+            }
+            if let Some(matcher) = &matcher {
+                let mut txid = hex::decode(&tx.txid)
+                    .map_err(|e| Error::Database(format!("Invalid stored txid: {}", e)))?;
+                txid.reverse();
+                let texts = memos
+                    .query_map(
+                        rusqlite::params![txid, u32::from(self.account_id)],
+                        |row| row.get::<_, Vec<u8>>(0),
+                    )
+                    .map_err(db_error(DB_CONTEXT))?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+                    .map_err(db_error(DB_CONTEXT))?;
+                if !texts
+                    .iter()
+                    .filter_map(|memo| memo_text(memo))
+                    .any(|text| matcher.matches(&text))
+                {
+                    continue;
+                }
+            }
+            matches.push(tx);
+        }
+        Ok(matches)
+    }
+
+    /// Find the selected account's transactions by memo text
+    ///
+    /// Uses the full-text memo index (see [`memo_index`]), updated with
+    /// memos decrypted since the last search first. Every word of `query`
+    /// must appear in one memo, in any order; a word with punctuation, such
+    /// as an order ID `INV-2024-117`, matches that sequence of words. Words
+    /// match whole, ignoring case; for substring or regex matching use
+    /// [`search_transactions`](Self::search_transactions).
+    ///
+    /// # Arguments
+    /// * `query` - Words to look for
+    ///
+    /// # Returns
+    /// Matching transactions, ordered like [`get_transactions`](Self::get_transactions)
+    pub fn search_memos(&self, query: &str) -> Result<Vec<Transaction>> {
+        let memo_match = match_query(query)?;
+        self.initialize_database()?;
+        MemoIndex::for_wallet(self)?.refresh()?;
+        self.query_transactions(Some(&memo_match), 0, None)
+    }
+
+    /// Produce a receipt for a payment the selected account received
+    ///
+    /// See [`receipt`](crate::receipt) for what the receipt contains and
+    /// how it can be verified against a public explorer.
+    ///
+    /// # Arguments
+    /// * `txid` - ID of the incoming transaction, as shown by explorers
+    pub fn payment_receipt(&self, txid: &str) -> Result<PaymentReceipt> {
+        self.initialize_database()?;
+        read_receipt(&self.db_path, self.network, u32::from(self.account_id), txid)
+    }
+
+    /// Aggregate the selected account's payments by counterparty
+    ///
+    /// Addresses saved as contacts are grouped under the contact's label. See
+    /// [`crate::counterparty`] for how payments are attributed.
+    ///
+    /// # Returns
+    /// One entry per counterparty, largest total volume first
+    pub fn counterparty_report(&self) -> Result<Vec<CounterpartyActivity>> {
+        self.initialize_database()?;
+        let contacts = Contacts::for_wallet(self)?
+            .list()?
+            .into_iter()
+            .map(|contact| (contact.address, contact.label))
+            .collect();
+        read_counterparties(&self.db_path, u32::from(self.account_id), &contacts)
+    }
+
+    /// List the notes and transparent outputs received by the selected account
+    ///
+    /// Includes spent notes, flagged as such, so apps doing coin control can
+    /// show the full picture and choose inputs among the spendable ones.
+    /// Spendability is decided by the wallet backend's input selection, so
+    /// a note is spendable exactly when a transaction built now could
+    /// select it; notes frozen with [`freeze_note`](Self::freeze_note) are
+    /// never spendable. Ordered like [`get_transactions`](Self::get_transactions).
+    ///
+    /// # Arguments
+    /// * `confirmations_policy` - Confirmations a note needs to be spendable
+    ///
+    /// # Note
+    /// Transparent outputs are never spendable here: transactions the wallet
+    /// builds only spend shielded notes, and transparent funds are spent by
+    /// shielding them.
+    pub fn list_notes(&self, confirmations_policy: ConfirmationsPolicy) -> Result<Vec<WalletNote>> {
+        let frozen = FrozenNotes::for_wallet(self)?.list()?;
+        let spendable = self.spendable_notes(confirmations_policy)?;
+        let conn = self.read_connection()?;
+        let mut stmt = conn
+            .prepare(
+                "WITH tip AS (SELECT MAX(height) AS height FROM blocks),
+                 spends AS (
+                     SELECT 'sapling' AS pool, s.sapling_received_note_id AS note_id,
+                            t.mined_height, t.expiry_height
+                     FROM sapling_received_note_spends s
+                     JOIN transactions t ON t.id_tx = s.transaction_id
+                     UNION ALL
+                     SELECT 'orchard', s.orchard_received_note_id, t.mined_height, t.expiry_height
+                     FROM orchard_received_note_spends s
+                     JOIN transactions t ON t.id_tx = s.transaction_id
+                     UNION ALL
+                     SELECT 'transparent', s.transparent_received_output_id, t.mined_height,
+                            t.expiry_height
+                     FROM transparent_received_output_spends s
+                     JOIN transactions t ON t.id_tx = s.transaction_id
+                 )
+                 SELECT n.pool, t.txid, n.output_index, n.value, t.mined_height, n.is_change,
+                        n.nf,
+                        EXISTS (SELECT 1 FROM spends s
+                                WHERE s.pool = n.pool AND s.note_id = n.id
+                                  AND s.mined_height IS NOT NULL),
+                        EXISTS (SELECT 1 FROM spends s, tip
+                                WHERE s.pool = n.pool AND s.note_id = n.id
+                                  AND s.mined_height IS NULL
+                                  AND (IFNULL(s.expiry_height, 0) = 0
+                                       OR s.expiry_height >= tip.height))
+                 FROM (
+                     SELECT 'sapling' AS pool, id, tx AS tx_id, output_index, value, is_change,
+                            nf, account_id
+                     FROM sapling_received_notes
+                     UNION ALL
+                     SELECT 'orchard', id, tx, action_index, value, is_change, nf, account_id
+                     FROM orchard_received_notes
+                     UNION ALL
+                     SELECT 'transparent', id, transaction_id, output_index, value_zat, 0,
+                            NULL, account_id
+                     FROM transparent_received_outputs
+                 ) n
+                 JOIN transactions t ON t.id_tx = n.tx_id
+                 WHERE n.account_id IN (SELECT id FROM accounts WHERE hd_account_index = ?1)
+                 ORDER BY t.mined_height IS NOT NULL, t.mined_height DESC, n.pool, n.output_index",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let notes = stmt
+            .query_map([u32::from(self.account_id)], |row| {
+                let pool = match row.get::<_, String>(0)?.as_str() {
+                    "sapling" => Pool::Sapling,
+                    "orchard" => Pool::Orchard,
+                    _ => Pool::Transparent,
+                };
+                let mut txid: Vec<u8> = row.get(1)?;
+                txid.reverse();
+                let id = NoteId {
+                    pool,
+                    txid: hex::encode(txid),
+                    output_index: row.get(2)?,
+                };
+                let is_frozen = frozen.contains(&id);
+                Ok(WalletNote {
+                    pool,
+                    spendable: spendable.contains(&id) && !is_frozen,
+                    txid: id.txid,
+                    output_index: id.output_index,
+                    value: row.get::<_, i64>(3)? as u64,
+                    height: row.get::<_, Option<i64>>(4)?.map(|h| h as u64),
+                    is_change: row.get(5)?,
+                    nullifier: row.get::<_, Option<Vec<u8>>>(6)?.map(hex::encode),
+                    spent: row.get(7)?,
+                    pending_spend: row.get(8)?,
+                    frozen: is_frozen,
+                })
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(notes)
+    }
+
+    /// Shielded notes of the selected account that input selection would
+    /// consider now under `confirmations_policy`
+    fn spendable_notes(
+        &self,
+        confirmations_policy: ConfirmationsPolicy,
+    ) -> Result<HashSet<NoteId>> {
+        let ufvk = self.unified_full_viewing_key()?;
+        let wallet_db = self.read_wallet_db()?;
+        let account = wallet_db
+            .get_account_for_ufvk(&ufvk)
+            .map_err(|e| Error::Database(format!("Failed to get account for UFVK: {}", e)))?;
+        let heights = wallet_db
+            .get_target_and_anchor_heights(confirmations_policy.trusted())
+            .map_err(|e| Error::Database(format!("Failed to read chain tip: {}", e)))?;
+        let (Some(account), Some((target_height, _))) = (account, heights) else {
+            // Not synced yet, so nothing can be spent
+            return Ok(HashSet::new());
+        };
+        let notes = wallet_db
+            .select_spendable_notes(
+                account.id(),
+                TargetValue::AllFunds(MaxSpendMode::MaxSpendable),
+                &[ShieldedProtocol::Sapling, ShieldedProtocol::Orchard],
+                target_height,
+                confirmations_policy,
+                &[],
+            )
+            .map_err(|e| Error::Database(format!("Failed to select spendable notes: {}", e)))?;
+        let sapling = notes.sapling().iter().map(|note| NoteId {
+            pool: Pool::Sapling,
+            txid: note.txid().to_string(),
+            output_index: u32::from(note.output_index()),
+        });
+        let orchard = notes.orchard().iter().map(|note| NoteId {
+            pool: Pool::Orchard,
+            txid: note.txid().to_string(),
+            output_index: u32::from(note.output_index()),
+        });
+        Ok(sapling.chain(orchard).collect())
+    }
+
+    /// Freeze a note or transparent output of the selected account
+    ///
+    /// Transactions the wallet builds itself (see
+    /// [`create_signing_request`](crate::airgap::create_signing_request))
+    /// will not spend it, e.g. during a compliance hold or for coin control:
+    /// input selection excludes frozen notes, so proposals are planned with
+    /// the remaining ones. Sends through a full node's `z_sendmany` are not
+    /// affected. Frozen notes still count towards the balance.
+    ///
+    /// # Arguments
+    /// * `note_id` - Note to freeze (see [`WalletNote::id`])
+    ///
+    /// # Returns
+    /// Whether the note was not frozen already
+    pub fn freeze_note(&self, note_id: &NoteId) -> Result<bool> {
+        let known = self
+            .list_notes(ConfirmationsPolicy::default())?
+            .iter()
+            .any(|note| {
+                note.pool == note_id.pool
+                    && note.output_index == note_id.output_index
+                    && note.txid.eq_ignore_ascii_case(&note_id.txid)
+            });
+        if !known {
+            return Err(Error::InvalidParameter(format!(
+                "Unknown note {:?} output {} of transaction {}",
+                note_id.pool, note_id.output_index, note_id.txid
+            )));
+        }
+        FrozenNotes::for_wallet(self)?.freeze(note_id)
+    }
+
+    /// Unfreeze a note frozen with [`freeze_note`](Self::freeze_note)
+    ///
+    /// # Returns
+    /// Whether the note was frozen
+    pub fn unfreeze_note(&self, note_id: &NoteId) -> Result<bool> {
+        FrozenNotes::for_wallet(self)?.unfreeze(note_id)
+    }
+
+    /// ZIP-32 account index used by this wallet
+    pub fn account_index(&self) -> u32 {
+        u32::from(self.account_id)
+    }
+
+    /// Path of the wallet database file
+    ///
+    /// For [ephemeral](Self::ephemeral) wallets this is an SQLite URI that
+    /// [`rusqlite::Connection::open`] accepts, not a file.
+    pub fn db_path(&self) -> &std::path::Path {
+        &self.db_path
+    }
+
+    /// Get the wallet database handle for advanced operations
+    ///
+    /// This provides direct access to the underlying WalletDb for use with
+    /// zcash_client_backend APIs that require WalletRead/WalletWrite traits.
+    /// The handle holds the wallet's shared write connection until dropped,
+    /// so other writers (including clones of this wallet) wait for it. Use
+    /// [`db_pool`](Self::db_pool) for read handles that do not.
+    pub fn wallet_db(&self) -> Result<WriteWalletDb<'_>> {
+        self.write_wallet_db()
+    }
+}
+
+/// Multi-account management
+///
+/// Accounts are derived from the wallet seed per ZIP-32 and recorded in the
+/// wallet database. The address and balance methods without an account
+/// argument use the account selected with [`Wallet::use_account`]
+/// (account 0 by default).
+impl Wallet {
+    /// Create the next ZIP-32 account and persist it in the wallet database
+    ///
+    /// # Arguments
+    /// * `name` - Human-readable account name
+    /// * `birthday` - Chain state at the first block that may contain funds
+    ///   for the account, see
+    ///   [`LightClient::account_birthday`](crate::light_client::LightClient::account_birthday)
+    pub fn create_account(
+        &self,
+        name: &str,
+        birthday: &AccountBirthday,
+    ) -> Result<WalletAccount> {
+        self.claim_network()?;
+        if let Some(provider) = &self.key_provider {
+            let index = self.account_indexes()?.last().map_or(0, |last| last + 1);
+            let account_id = Self::zip32_account(index)?;
+            return self.import_provider_account(provider.as_ref(), account_id, name, birthday);
+        }
+        let seed = self.vault.seed()?;
+        let mut wallet_db = self.write_wallet_db()?;
+        let (account_uuid, _) = wallet_db
+            .create_account(name, &seed, birthday, None)
+            .map_err(|e| Error::Database(format!("Failed to create account: {}", e)))?;
+        let account = wallet_db
+            .get_account(account_uuid)
+            .map_err(|e| Error::Database(format!("Failed to read account: {}", e)))?
+            .ok_or_else(|| Error::Database("Created account not found".to_string()))?;
+        Ok(self.account_info(&account))
+    }
+
+    /// Create the ZIP-32 account at a chosen index and persist it
+    ///
+    /// Unlike [`create_account`](Self::create_account), which takes the next
+    /// free index, this derives the account at `index`, e.g. to restore an
+    /// account another wallet created at a gap in the index sequence.
+    ///
+    /// # Arguments
+    /// * `index` - ZIP-32 account index; fails if the account already exists
+    /// * `name` - Human-readable account name
+    /// * `birthday` - Chain state at the first block that may contain funds
+    ///   for the account
+    pub fn create_account_at(
+        &self,
+        index: u32,
+        name: &str,
+        birthday: &AccountBirthday,
+    ) -> Result<WalletAccount> {
+        let account_id = Self::zip32_account(index)?;
+        if self.account_indexes()?.contains(&index) {
+            return Err(Error::InvalidParameter(format!(
+                "Account {} already exists in the wallet database",
+                index
+            )));
+        }
+        self.claim_network()?;
+        if let Some(provider) = &self.key_provider {
+            return self.import_provider_account(provider.as_ref(), account_id, name, birthday);
+        }
+        let seed = self.vault.seed()?;
+        let mut wallet_db = self.write_wallet_db()?;
+        let (account, _) = wallet_db
+            .import_account_hd(name, &seed, account_id, birthday, None)
+            .map_err(|e| Error::Database(format!("Failed to create account: {}", e)))?;
+        Ok(self.account_info(&account))
+    }
+
+    /// Record an account whose keys are held by `provider` by its viewing
+    /// key, with its ZIP-32 derivation so the provider can sign for it
+    fn import_provider_account(
+        &self,
+        provider: &dyn KeyProvider,
+        account_id: AccountId,
+        name: &str,
+        birthday: &AccountBirthday,
+    ) -> Result<WalletAccount> {
+        let ufvk = provider.unified_full_viewing_key(self.network, account_id)?;
+        let derivation = Zip32Derivation::new(provider.seed_fingerprint()?, account_id);
+        let account = self
+            .write_wallet_db()?
+            .import_account_ufvk(
+                name,
+                &ufvk,
+                birthday,
+                AccountPurpose::Spending {
+                    derivation: Some(derivation),
+                },
+                None,
+            )
+            .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?;
+        Ok(self.account_info(&account))
+    }
+
+    /// List all accounts in the wallet database, with their metadata,
+    /// including archived accounts
+    pub fn list_accounts(&self) -> Result<Vec<WalletAccount>> {
+        let store = AccountMetadataStore::for_wallet(self)?;
+        let mut metadata = store.all()?;
+        let archived = store.archived()?;
+        let wallet_db = self.read_wallet_db()?;
+        let mut accounts = Vec::new();
+        for account_uuid in wallet_db
+            .get_account_ids()
+            .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+        {
+            if let Some(account) = wallet_db
+                .get_account(account_uuid)
+                .map_err(|e| Error::Database(format!("Failed to read account: {}", e)))?
+            {
+                let mut info = self.account_info(&account);
+                info.metadata = metadata.remove(&info.uuid).unwrap_or_default();
+                info.archived = archived.contains(&info.uuid);
+                accounts.push(info);
+            }
+        }
+        accounts.sort_by_key(|a| a.index);
+        Ok(accounts)
+    }
+
+    fn account_info(
+        &self,
+        account: &impl Account<AccountId = zcash_client_sqlite::AccountUuid>,
+    ) -> WalletAccount {
+        WalletAccount {
+            index: account
+                .source()
+                .key_derivation()
+                .map(|derivation| u32::from(derivation.account_index())),
+            uuid: account.id().expose_uuid().to_string(),
+            name: account.name().map(str::to_string),
+            ufvk: account.ufvk().map(|ufvk| ufvk.encode(&self.consensus_network())),
+            metadata: AccountMetadata::default(),
+            archived: false,
+        }
+    }
+
+    /// Archive an account, e.g. one of a customer who left
+    ///
+    /// An archived account is left out of [`get_balance`](Self::get_balance),
+    /// [`get_balance_detailed`](Self::get_balance_detailed),
+    /// [`get_account_balances`](Self::get_account_balances) and the balance
+    /// history recorded during sync, and is flagged in
+    /// [`list_accounts`](Self::list_accounts). Its keys and history are kept,
+    /// and it can be re-enabled with
+    /// [`unarchive_account`](Self::unarchive_account).
+    ///
+    /// Archived accounts are also skipped during scanning, so they no longer
+    /// cost trial-decryption time. Notes they receive while archived are not
+    /// found until the account is re-enabled: the height it was archived at
+    /// is recorded, and [`unarchive_account`](Self::unarchive_account)
+    /// queues the blocks scanned since then, which the next
+    /// [`sync`](crate::light_client::LightClient::sync) scans again.
+    ///
+    /// # Arguments
+    /// * `account_uuid` - UUID of the account (see [`WalletAccount::uuid`])
+    ///
+    /// # Returns
+    /// Whether the account was not archived already
+    pub fn archive_account(&self, account_uuid: &str) -> Result<bool> {
+        let account = self.find_account(account_uuid)?;
+        if account.index == Some(u32::from(self.account_id)) {
+            return Err(Error::InvalidParameter(
+                "The selected account cannot be archived".to_string(),
+            ));
+        }
+        let height = self.scanned_height()?.map(|height| height + 1);
+        AccountMetadataStore::for_wallet(self)?.archive(account_uuid, height)
+    }
+
+    /// Re-enable an archived account
+    ///
+    /// The blocks scanned while the account was archived are queued for a
+    /// rescan by the next [`sync`](crate::light_client::LightClient::sync).
+    ///
+    /// # Returns
+    /// Whether the account was archived
+    pub fn unarchive_account(&self, account_uuid: &str) -> Result<bool> {
+        self.find_account(account_uuid)?;
+        let height = self.scanned_height()?;
+        AccountMetadataStore::for_wallet(self)?.unarchive(account_uuid, height)
+    }
+
+    /// Height of the last block scanned into the wallet database
+    fn scanned_height(&self) -> Result<Option<u64>> {
+        Ok(self
+            .read_wallet_db()?
+            .block_max_scanned()
+            .map_err(|e| Error::Database(format!("Failed to get max scanned height: {}", e)))?
+            .map(|metadata| u64::from(u32::from(metadata.block_height()))))
+    }
+
+    fn find_account(&self, account_uuid: &str) -> Result<WalletAccount> {
+        self.list_accounts()?
+            .into_iter()
+            .find(|account| account.uuid == account_uuid)
+            .ok_or_else(|| Error::InvalidParameter(format!("Unknown account {}", account_uuid)))
+    }
+
+    /// Select the account used by the single-account methods
+    ///
+    /// Fails if the wallet database holds seed-derived accounts but none at
+    /// `index`; any index can be selected while the database has none.
+    ///
+    /// # Arguments
+    /// * `index` - ZIP-32 account index
+    pub fn use_account(&mut self, index: u32) -> Result<()> {
+        self.select_account(Self::zip32_account(index)?)
+    }
+
+    /// Use the given ZIP-32 account instead of account 0
+    ///
+    /// Builder form of [`use_account`](Self::use_account), with the same
+    /// validation against the accounts in the wallet database.
+    pub fn with_account(mut self, account: AccountId) -> Result<Self> {
+        self.select_account(account)?;
+        Ok(self)
+    }
+
+    fn select_account(&mut self, account: AccountId) -> Result<()> {
+        let index = u32::from(account);
+        let indexes = self.account_indexes()?;
+        if !indexes.is_empty() && !indexes.contains(&index) {
+            return Err(Error::InvalidParameter(format!(
+                "Account {} is not in the wallet database (accounts: {:?})",
+                index, indexes
+            )));
+        }
+        self.account_id = account;
+        Ok(())
+    }
+
+    /// ZIP-32 indexes of the seed-derived accounts in the wallet database
+    fn account_indexes(&self) -> Result<Vec<u32>> {
+        let wallet_db = self.read_wallet_db()?;
+        let mut indexes = Vec::new();
+        for account_uuid in wallet_db
+            .get_account_ids()
+            .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+        {
+            if let Some(derivation) = wallet_db
+                .get_account(account_uuid)
+                .map_err(|e| Error::Database(format!("Failed to read account: {}", e)))?
+                .and_then(|account| account.source().key_derivation().cloned())
+            {
+                indexes.push(u32::from(derivation.account_index()));
+            }
+        }
+        indexes.sort_unstable();
+        Ok(indexes)
+    }
+
+    fn zip32_account(index: u32) -> Result<AccountId> {
+        AccountId::try_from(index)
+            .map_err(|_| Error::InvalidParameter(format!("Invalid ZIP-32 account index {}", index)))
+    }
+
+    /// Get the unified full viewing key of an account
+    pub fn account_ufvk(&self, index: u32) -> Result<UnifiedFullViewingKey> {
+        self.viewing_key_for(Self::zip32_account(index)?)
+    }
+
+    /// Get the default unified address of an account
+    pub fn account_unified_address(&self, index: u32) -> Result<String> {
+        let (ua, _) = self
+            .account_ufvk(index)?
+            .default_address(UnifiedAddressRequest::ALLOW_ALL)
+            .map_err(|e| Error::Address(format!("Failed to generate unified address: {}", e)))?;
+
+        Ok(ua.encode(&self.consensus_network()))
+    }
+
+    /// Get the balance of an account
+    ///
+    /// Returns a zero balance if the account has not been created in the
+    /// wallet database or the wallet has not been synced yet.
+    pub fn account_balance(&self, index: u32) -> Result<Balance> {
+        let ufvk = self.account_ufvk(index)?;
+        let wallet_db = self.read_wallet_db()?;
+        let Some(account) = wallet_db
+            .get_account_for_ufvk(&ufvk)
+            .map_err(|e| Error::Database(format!("Failed to get account for UFVK: {}", e)))?
+        else {
+            return Ok(Balance::default());
+        };
+        let Some(summary) = wallet_db
+            .get_wallet_summary(ConfirmationsPolicy::default())
+            .map_err(|e| Error::Database(format!("Failed to read wallet summary: {}", e)))?
+        else {
+            return Ok(Balance::default());
+        };
+
+        Ok(summary
+            .account_balances()
+            .get(&account.id())
+            .map(|b| {
+                let transparent = u64::from(b.unshielded_balance().total());
+                let sapling = u64::from(b.sapling_balance().total());
+                let orchard = u64::from(b.orchard_balance().total());
+                Balance {
+                    transparent,
+                    sapling,
+                    orchard,
+                    total: transparent + sapling + orchard,
+                }
+            })
+            .unwrap_or_default())
+    }
+}
+
+impl Wallet {
+    /// Write an encrypted backup of the wallet to `path`
+    ///
+    /// The backup contains the seed, mnemonic, network, account metadata with
+    /// birthday heights, the address book and contacts (see [`crate::backup`]).
+    ///
+    /// # Arguments
+    /// * `path` - File to write; an existing file is overwritten
+    /// * `passphrase` - Passphrase the backup is encrypted with
+    pub fn export_backup(&self, path: &Path, passphrase: &str) -> Result<()> {
+        let accounts = self.backup_accounts()?;
+        let birthday_height = self
+            .read_wallet_db()?
+            .get_wallet_birthday()
+            .map_err(|e| Error::Database(format!("Failed to read wallet birthday: {}", e)))?
+            .map(|height| u64::from(u32::from(height)));
+
+        let backup = WalletBackup {
+            version: BACKUP_VERSION,
+            network: self.network,
+            seed: hex::encode(self.seed_vault()?.seed()?.expose_secret()),
+            mnemonic: self.vault.mnemonic()?.map(|m| m.expose_secret().clone()),
+            selected_account: u32::from(self.account_id),
+            birthday_height,
+            accounts,
+            address_book: AddressBook::for_wallet(self)?.list()?,
+            contacts: Contacts::for_wallet(self)?.list()?,
+            created_at: unix_now(),
+        };
+        std::fs::write(path, encrypt_backup(&backup, passphrase)?)?;
+        Ok(())
+    }
+
+    /// Every account in the wallet database with its birthday height
+    fn backup_accounts(&self) -> Result<Vec<BackupAccount>> {
+        let wallet_db = self.read_wallet_db()?;
+        let mut accounts = Vec::new();
+        for account_uuid in wallet_db
+            .get_account_ids()
+            .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+        {
+            let Some(account) = wallet_db
+                .get_account(account_uuid)
+                .map_err(|e| Error::Database(format!("Failed to read account: {}", e)))?
+            else {
+                continue;
+            };
+            let birthday = wallet_db
+                .get_account_birthday(account_uuid)
+                .map_err(|e| Error::Database(format!("Failed to read account birthday: {}", e)))?;
+            let info = self.account_info(&account);
+            accounts.push(BackupAccount {
+                index: info.index,
+                name: info.name,
+                ufvk: info.ufvk,
+                birthday_height: u64::from(u32::from(birthday)),
+            });
+        }
+        accounts.sort_by_key(|a| a.index);
+        Ok(accounts)
+    }
+
+    /// Restore a wallet from an encrypted backup
+    ///
+    /// Restores the seed, mnemonic, network, selected account, address book
+    /// and contacts into a new database at `db_path`. Accounts are recreated
+    /// at their original birthdays with
+    /// [`LightClient::restore_accounts`](crate::light_client::LightClient::restore_accounts),
+    /// which needs the server's tree state.
+    ///
+    /// # Arguments
+    /// * `path` - Backup file written by [`export_backup`](Self::export_backup)
+    /// * `passphrase` - Passphrase the backup was encrypted with
+    /// * `db_path` - Database path for the restored wallet
+    pub fn import_backup(path: &Path, passphrase: &str, db_path: PathBuf) -> Result<Self> {
+        Self::restore_backup(&mut read_backup(path, passphrase)?, db_path)
+    }
+
+    fn restore_backup(backup: &mut WalletBackup, db_path: PathBuf) -> Result<Self> {
+        let seed = hex::decode(&backup.seed)
+            .map(SecretVec::new)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid seed in backup: {}", e)))?;
+        let mnemonic = backup.mnemonic.take().map(SecretString::new);
+        let mut wallet = Self::from_parts(db_path, seed, mnemonic)?;
+        wallet.set_network(backup.network)?;
+        wallet.use_account(backup.selected_account)?;
+
+        let address_book = AddressBook::for_wallet(&wallet)?;
+        for entry in &backup.address_book {
+            address_book.set_label(&entry.address, &entry.label)?;
+        }
+        let contacts = Contacts::for_wallet(&wallet)?;
+        for contact in &backup.contacts {
+            if contacts.get(&contact.label)?.is_none() {
+                contacts.add(&contact.label, &contact.address, contact.notes.as_deref())?;
+            }
+        }
+        Ok(wallet)
+    }
+
+    /// Describe the wallet in the interchange format, for moving it to
+    /// another Zcash wallet (see [`crate::interchange`])
+    ///
+    /// Each account lists its default unified address and, for accounts
+    /// derived from the seed, the transparent addresses handed out, with
+    /// their address book labels. The document holds the seed in the clear;
+    /// prefer [`export_interchange`](Self::export_interchange) for files.
+    pub fn interchange_document(&self) -> Result<WalletInterchange> {
+        let mut labels: BTreeMap<String, AddressLabel> = AddressBook::for_wallet(self)?
+            .list()?
+            .into_iter()
+            .map(|entry| (entry.address.clone(), entry))
+            .collect();
+        let network = self.consensus_network();
+        let mut accounts = Vec::new();
+        for account in self.backup_accounts()? {
+            let mut addresses = Vec::new();
+            let ufvk = account
+                .ufvk
+                .as_deref()
+                .map(|encoded| UnifiedFullViewingKey::decode(&network, encoded))
+                .transpose()
+                .map_err(|e| Error::Database(format!("Invalid stored viewing key: {}", e)))?;
+            if let Some(ufvk) = &ufvk {
+                let (ua, _) = ufvk
+                    .default_address(UnifiedAddressRequest::ALLOW_ALL)
+                    .map_err(|e| Error::Address(format!("Failed to generate address: {}", e)))?;
+                addresses.push((ua.encode(&network), PURPOSE_DEFAULT));
+            }
+            let transparent_key = ufvk.as_ref().and_then(|ufvk| ufvk.transparent());
+            if let (Some(index), Some(key)) = (account.index, transparent_key) {
+                let transparent =
+                    TransparentAddresses::open(&self.db_path, network, index, key.clone())?;
+                for info in transparent.receive_addresses()? {
+                    addresses.push((info.address, PURPOSE_RECEIVE));
+                }
+            }
+            accounts.push(InterchangeAccount {
+                name: account.name,
+                zip32_account_index: account.index,
+                ufvk: account.ufvk,
+                birthday_height: account.birthday_height,
+                addresses: addresses
+                    .into_iter()
+                    .map(|(address, purpose)| InterchangeAddress {
+                        label: labels.remove(&address).map(|entry| entry.label),
+                        address,
+                        purpose: Some(purpose.to_string()),
+                    })
+                    .collect(),
+            });
+        }
+        let export_height = self
+            .read_wallet_db()?
+            .chain_height()
+            .map_err(|e| Error::Database(format!("Failed to read chain height: {}", e)))?
+            .map(|height| u64::from(u32::from(height)));
+        let annotations = TransactionAnnotations::for_wallet(self)?.all()?;
+
+        Ok(WalletInterchange {
+            format: INTERCHANGE_FORMAT.to_string(),
+            version: INTERCHANGE_VERSION,
+            network: self.network,
+            export_height,
+            seed: Some(hex::encode(self.seed_vault()?.seed()?.expose_secret())),
+            mnemonic: self.vault.mnemonic()?.map(|m| m.expose_secret().clone()),
+            selected_account: u32::from(self.account_id),
+            accounts,
+            address_labels: labels.into_values().collect(),
+            contacts: Contacts::for_wallet(self)?.list()?,
+            transactions: read_interchange_transactions(&self.db_path, &annotations)?,
+            created_at: unix_now(),
+        })
+    }
+
+    /// Write the wallet in the interchange format to `path`, encrypted
+    ///
+    /// # Arguments
+    /// * `path` - File to write; an existing file is overwritten
+    /// * `passphrase` - Passphrase the file is encrypted with
+    pub fn export_interchange(&self, path: &Path, passphrase: &str) -> Result<()> {
+        let document = self.interchange_document()?;
+        std::fs::write(path, encrypt_interchange(&document, passphrase)?)?;
+        Ok(())
+    }
+
+    /// Restore a wallet from an interchange document
+    ///
+    /// Restores the seed, network, selected account, address labels,
+    /// contacts and transaction annotations into a new database at
+    /// `db_path`. Like [`import_backup`](Self::import_backup), accounts are
+    /// recreated at their birthdays by passing
+    /// [`WalletInterchange::to_backup`] to
+    /// [`LightClient::restore_accounts`](crate::light_client::LightClient::restore_accounts);
+    /// annotations attach to transactions as sync finds them.
+    pub fn import_interchange_document(
+        document: &WalletInterchange,
+        db_path: PathBuf,
+    ) -> Result<Self> {
+        let wallet = Self::restore_backup(&mut document.to_backup()?, db_path)?;
+        let annotations = TransactionAnnotations::for_wallet(&wallet)?;
+        for transaction in &document.transactions {
+            if let Some(annotation) = &transaction.annotation {
+                annotations.set(&transaction.txid, annotation)?;
+            }
+        }
+        Ok(wallet)
+    }
+
+    /// Restore a wallet from a file written by
+    /// [`export_interchange`](Self::export_interchange)
+    pub fn import_interchange(path: &Path, passphrase: &str, db_path: PathBuf) -> Result<Self> {
+        Self::import_interchange_document(&read_interchange(path, passphrase)?, db_path)
+    }
+
+    /// Write the selected account's spending keys, encrypted, to `path`
+    ///
+    /// The file holds the unified spending key and the Sapling extended
+    /// spending key in their standard encodings (see [`crate::key_export`]),
+    /// for moving the account to zcashd or another SDK. Anyone with the file
+    /// and its passphrase can spend the account's funds.
+    ///
+    /// # Arguments
+    /// * `path` - File to write
+    /// * `passphrase` - Passphrase to encrypt the keys with
+    pub fn export_unified_spending_key(&self, path: &Path, passphrase: &str) -> Result<()> {
+        let usk = self.get_unified_spending_key()?;
+        let usk_bytes = Zeroizing::new(usk.to_bytes(Era::Orchard));
+        let export = SpendingKeyExport {
+            version: KEY_EXPORT_VERSION,
+            network: self.network,
+            account_index: Some(u32::from(self.account_id)),
+            unified: Some(hex::encode(&*usk_bytes)),
+            sapling: Some(encode_extended_spending_key(
+                self.consensus_network().hrp_sapling_extended_spending_key(),
+                usk.sapling(),
+            )),
+            created_at: unix_now(),
+        };
+        std::fs::write(path, encrypt_spending_key(&export, passphrase)?)?;
+        Ok(())
+    }
+
+    /// Import an account from an encrypted key export
+    ///
+    /// Prefers the unified spending key and falls back to the Sapling key
+    /// for exports that only carry one. See
+    /// [`import_encoded_spending_key`](Self::import_encoded_spending_key).
+    ///
+    /// # Arguments
+    /// * `path` - File written by
+    ///   [`export_unified_spending_key`](Self::export_unified_spending_key)
+    /// * `passphrase` - Passphrase the keys were encrypted with
+    /// * `name` - Name of the imported account
+    /// * `birthday` - Chain state at the first block that may contain funds
+    ///   for the account
+    pub fn import_spending_key(
+        &self,
+        path: &Path,
+        passphrase: &str,
+        name: &str,
+        birthday: &AccountBirthday,
+    ) -> Result<WalletAccount> {
+        let mut export = decrypt_spending_key(&std::fs::read(path)?, passphrase)?;
+        if export.network != self.network {
+            return Err(Error::InvalidParameter(format!(
+                "Key export is for {:?}, wallet is on {:?}",
+                export.network, self.network
+            )));
+        }
+        let key = export
+            .unified
+            .take()
+            .or_else(|| export.sapling.take())
+            .map(SecretString::new)
+            .ok_or_else(|| {
+                Error::KeyDerivation("Key export contains no spending key".to_string())
+            })?;
+        self.import_encoded_spending_key(key.expose_secret(), name, birthday)
+    }
+
+    /// Import an account from a spending key in a standard encoding
+    ///
+    /// Accepts a Bech32 Sapling extended spending key, as printed by
+    /// zcashd's `z_exportkey`, or hex-encoded unified spending key bytes.
+    /// The account's viewing key is recorded with spending purpose so its
+    /// funds are tracked by sync; the spending key itself is not stored in
+    /// the wallet database, so keep the key to spend the imported funds.
+    pub fn import_encoded_spending_key(
+        &self,
+        key: &str,
+        name: &str,
+        birthday: &AccountBirthday,
+    ) -> Result<WalletAccount> {
+        let hrp = self.consensus_network().hrp_sapling_extended_spending_key();
+        let ufvk = if key.starts_with(hrp) {
+            let extsk = decode_extended_spending_key(hrp, key).map_err(|e| {
+                Error::InvalidParameter(format!("Invalid Sapling spending key: {}", e))
+            })?;
+            // A Sapling-only UFVK can only be built from the extended form
+            #[allow(deprecated)]
+            let extfvk = extsk.to_extended_full_viewing_key();
+            UnifiedFullViewingKey::from_sapling_extended_full_viewing_key(extfvk).map_err(
+                |e| Error::KeyDerivation(format!("Failed to derive viewing key: {:?}", e)),
+            )?
+        } else {
+            let bytes = hex::decode(key).map(Zeroizing::new).map_err(|_| {
+                Error::InvalidParameter(format!(
+                    "Unrecognized spending key encoding for {:?}",
+                    self.network
+                ))
+            })?;
+            UnifiedSpendingKey::from_bytes(Era::Orchard, &bytes)
+                .map_err(|e| {
+                    Error::InvalidParameter(format!("Invalid unified spending key: {:?}", e))
+                })?
+                .to_unified_full_viewing_key()
+        };
+
+        let account = self
+            .write_wallet_db()?
+            .import_account_ufvk(
+                name,
+                &ufvk,
+                birthday,
+                AccountPurpose::Spending { derivation: None },
+                None,
+            )
+            .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?;
+        Ok(self.account_info(&account))
+    }
+
+    /// Import the keys of a zcashd wallet dump
+    ///
+    /// Reads a file written by zcashd's `z_exportwallet` or `dumpwallet`
+    /// (see [`crate::zcashd_dump`]):
+    /// - each Sapling key becomes an account, imported like
+    ///   [`import_encoded_spending_key`](Self::import_encoded_spending_key)
+    /// - each transparent key's public key is imported into the selected
+    ///   account as a standalone transparent address, and labels of
+    ///   receiving addresses are copied into the address book
+    /// - every spending key and the recovery phrase are sealed with
+    ///   `passphrase` in the wallet's [`KeyStore`],
+    ///   under one entry per dump
+    ///
+    /// The wallet database only learns the transparent public keys; spend
+    /// their funds with the sealed private keys. Restore the recovery phrase
+    /// into a new wallet to recover Orchard funds. Keys that fail to import,
+    /// e.g. ones already in the wallet or uncompressed transparent keys, are
+    /// listed in the report and do not stop the import.
+    ///
+    /// # Arguments
+    /// * `path` - Dump file
+    /// * `passphrase` - Passphrase the dump's secrets are sealed with
+    /// * `birthday` - Chain state at the first block that may contain funds
+    ///   for the dump's Sapling keys
+    pub fn import_zcashd_dump(
+        &self,
+        path: &Path,
+        passphrase: &str,
+        birthday: &AccountBirthday,
+    ) -> Result<ZcashdImportReport> {
+        let text = Zeroizing::new(std::fs::read_to_string(path)?);
+        let dump = parse_zcashd_dump(&text)?;
+        let network = self.consensus_network();
+        let hrp = network.hrp_sapling_extended_spending_key();
+        if dump
+            .sapling_keys
+            .iter()
+            .any(|key| !key.key.expose_secret().starts_with(hrp))
+        {
+            return Err(Error::InvalidParameter(format!(
+                "Dump contains Sapling keys for a network other than {:?}",
+                self.network
+            )));
+        }
+
+        // Seal the secrets first, so nothing is lost if an import fails
+        let digest = blake2b_simd::Params::new()
+            .hash_length(8)
+            .personal(b"NumiZcashdDump__")
+            .hash(text.as_bytes());
+        let entry = format!("zcashd-dump-{}", hex::encode(digest.as_bytes()));
+        let secrets = Zeroizing::new(serde_json::to_vec(&serde_json::json!({
+            "recovery_phrase": dump.recovery_phrase.as_ref().map(|p| p.expose_secret()),
+            "sapling_keys": dump
+                .sapling_keys
+                .iter()
+                .map(|key| key.key.expose_secret())
+                .collect::<Vec<_>>(),
+            "transparent_keys": dump
+                .transparent_keys
+                .iter()
+                .map(|key| (&key.address, key.wif.expose_secret()))
+                .collect::<Vec<_>>(),
+        }))?);
+        KeyStore::for_wallet(self)?.store(&entry, &secrets, passphrase)?;
+
+        let mut report = ZcashdImportReport {
+            recovery_phrase: dump.recovery_phrase.is_some(),
+            key_store_entry: entry,
+            ..Default::default()
+        };
+        for (i, key) in dump.sapling_keys.iter().enumerate() {
+            let name = format!("zcashd Sapling key {}", i + 1);
+            match self.import_encoded_spending_key(key.key.expose_secret(), &name, birthday) {
+                Ok(account) => report.accounts.push(account),
+                Err(e) => report.skipped.push(format!(
+                    "Sapling key {} ({}): {}",
+                    i + 1,
+                    key.address.as_deref().unwrap_or("unknown address"),
+                    e
+                )),
+            }
+        }
+        if dump.transparent_keys.is_empty() {
+            return Ok(report);
+        }
+
+        let ufvk = self.get_unified_full_viewing_key()?;
+        let secp = Secp256k1::signing_only();
+        let mut imported = Vec::new();
+        {
+            let mut wallet_db = self.write_wallet_db()?;
+            let account = wallet_db
+                .get_account_for_ufvk(&ufvk)
+                .map_err(|e| Error::Database(format!("Failed to get account for UFVK: {}", e)))?
+                .ok_or_else(|| {
+                    Error::Wallet("The selected account is not in the wallet database".to_string())
+                })?
+                .id();
+            for key in &dump.transparent_keys {
+                let result = key.secret_key().and_then(|secret_key| {
+                    let pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+                    if pubkey_to_address(&pubkey).encode(&network) != key.address {
+                        return Err(Error::InvalidParameter(
+                            "Key does not match its address".to_string(),
+                        ));
+                    }
+                    wallet_db
+                        .import_standalone_transparent_pubkey(account, pubkey)
+                        .map_err(|e| {
+                            Error::Database(format!("Failed to import transparent key: {}", e))
+                        })
+                });
+                match result {
+                    Ok(()) => imported.push(key),
+                    Err(e) => report
+                        .skipped
+                        .push(format!("Transparent key for {}: {}", key.address, e)),
+                }
+            }
+        }
+
+        let address_book = AddressBook::for_wallet(self)?;
+        for key in imported {
+            if let TransparentKeyKind::Receive { label: Some(label) } = &key.kind {
+                address_book.set_label(&key.address, label)?;
+                report.labels_imported += 1;
+            }
+            report.transparent_addresses.push(key.address.clone());
+        }
+        Ok(report)
+    }
+
+    /// Import a FROST threshold multisig account
+    ///
+    /// The account is added with its shared viewing key, so the wallet can
+    /// scan it and build transactions; spends are authorized through a
+    /// signing ceremony (see [`crate::frost`]).
+    ///
+    /// # Arguments
+    /// * `account` - Public account description from the dealer
+    /// * `name` - Human-readable name for the new account
+    /// * `birthday` - Height before which the account cannot have received funds
+    #[cfg(feature = "frost")]
+    pub fn import_frost_account(
+        &self,
+        account: &crate::frost::FrostAccount,
+        name: &str,
+        birthday: &AccountBirthday,
+    ) -> Result<WalletAccount> {
+        let ufvk = account.ufvk()?;
+        let account = self
+            .write_wallet_db()?
+            .import_account_ufvk(
+                name,
+                &ufvk,
+                birthday,
+                AccountPurpose::Spending { derivation: None },
+                None,
+            )
+            .map_err(|e| Error::Database(format!("Failed to import account: {}", e)))?;
+        Ok(self.account_info(&account))
+    }
+}
+
+impl Wallet {
+    /// Export the synced wallet state as a warm-start snapshot
+    ///
+    /// Intended for a provisioning service that synced the wallet
+    /// server-side; the device installs the snapshot with
+    /// [`import_snapshot`](Self::import_snapshot) instead of scanning from the
+    /// birthday (see [`crate::snapshot`]).
+    ///
+    /// # Arguments
+    /// * `path` - Snapshot file to create; must not exist
+    pub fn export_snapshot(&self, path: &Path) -> Result<SnapshotInfo> {
+        let wallet_db = self.read_wallet_db()?;
+        let scanned_height = wallet_db
+            .block_max_scanned()
+            .map_err(|e| Error::Database(format!("Failed to get max scanned height: {}", e)))?
+            .map(|metadata| u64::from(u32::from(metadata.block_height())));
+        let mut ufvks = Vec::new();
+        for account_uuid in wallet_db
+            .get_account_ids()
+            .map_err(|e| Error::Database(format!("Failed to list accounts: {}", e)))?
+        {
+            if let Some(account) = wallet_db
+                .get_account(account_uuid)
+                .map_err(|e| Error::Database(format!("Failed to read account: {}", e)))?
+            {
+                ufvks.extend(self.account_info(&account).ufvk);
+            }
+        }
+        drop(wallet_db);
+
+        let info = SnapshotInfo {
+            version: SNAPSHOT_VERSION,
+            network: self.network,
+            created_at: unix_now(),
+            scanned_height,
+            ufvks,
+            balance: self.get_balance()?,
+        };
+        write_snapshot(&self.db_path, &info, path)?;
+        Ok(info)
+    }
+
+    /// Export the wallet's transactions and notes for analytics
+    ///
+    /// Writes a new read-only SQLite file holding the history of every
+    /// account in the wallet database and no key material, viewing keys and
+    /// nullifiers included (see [`crate::analytics_export`]). Unlike
+    /// [`export_snapshot`](Self::export_snapshot), the export cannot be used
+    /// to restore or watch the wallet.
+    ///
+    /// # Arguments
+    /// * `path` - Export file to create; must not exist
+    ///
+    /// # Returns
+    /// The metadata stored in the export
+    pub fn export_analytics_snapshot(&self, path: &Path) -> Result<AnalyticsExportInfo> {
+        let mut indexes = self.account_indexes()?;
+        if indexes.is_empty() {
+            indexes.push(u32::from(self.account_id));
+        }
+        let mut accounts = Vec::with_capacity(indexes.len());
+        for &index in &indexes {
+            let mut wallet = self.clone();
+            wallet.use_account(index)?;
+            accounts.push(AccountHistory {
+                account: index,
+                transactions: wallet.get_transactions(None)?,
+                notes: wallet.list_notes(ConfirmationsPolicy::default())?,
+            });
+        }
+
+        let info = AnalyticsExportInfo {
+            version: ANALYTICS_EXPORT_VERSION,
+            network: self.network,
+            created_at: unix_now(),
+            accounts: indexes,
+            transactions: accounts.iter().map(|a| a.transactions.len()).sum(),
+            notes: accounts.iter().map(|a| a.notes.len()).sum(),
+        };
+        write_analytics_export(path, &info, &accounts)?;
+        Ok(info)
+    }
+
+    /// Install a warm-start snapshot into this wallet's database
+    ///
+    /// The snapshot must be for this wallet's network and contain the
+    /// selected account's viewing key, and this wallet must not have scanned
+    /// any blocks yet. The wallet's address book and contacts are kept. Sync
+    /// continues from the snapshot's scanned height.
+    ///
+    /// # Arguments
+    /// * `path` - Snapshot file written by [`export_snapshot`](Self::export_snapshot)
+    pub fn import_snapshot(&self, path: &Path) -> Result<SnapshotInfo> {
+        if self.is_ephemeral() {
+            return Err(Error::Wallet(
+                "Snapshots cannot be installed into an ephemeral wallet".to_string(),
+            ));
+        }
+        let info = read_snapshot_info(path)?;
+        if info.network != self.network {
+            return Err(Error::InvalidParameter(format!(
+                "Snapshot is for {:?}, wallet is on {:?}",
+                info.network, self.network
+            )));
+        }
+        let ufvk = self.get_unified_full_viewing_key()?.encode(&self.consensus_network());
+        if !info.ufvks.contains(&ufvk) {
+            return Err(Error::InvalidParameter(
+                "Snapshot does not contain this wallet's viewing key".to_string(),
+            ));
+        }
+        let already_scanned = self
+            .read_wallet_db()?
+            .block_max_scanned()
+            .map_err(|e| Error::Database(format!("Failed to get max scanned height: {}", e)))?
+            .is_some();
+        if already_scanned {
+            return Err(Error::Wallet(
+                "Snapshots can only be imported before the wallet's first sync".to_string(),
+            ));
+        }
+
+        // Close pooled connections to the file being replaced
+        self.pool.reset();
+        install_snapshot(path, &self.db_path)?;
+        // Apply any migrations newer than the provisioning service's SDK
+        self.initialize_database()?;
+        Ok(info)
+    }
+
+    /// Report the on-disk size of the wallet database and each of its tables
+    pub fn database_size(&self) -> Result<DatabaseSize> {
+        database_size(&self.db_path)
+    }
+
+    /// Delete metadata of old scanned blocks
+    ///
+    /// Keeps the metadata of the last `retain_blocks` scanned blocks (at
+    /// least [`MIN_RETAINED_BLOCKS`], so reorgs can still be rolled back),
+    /// of blocks the wallet's transactions were mined in, and of every block
+    /// at or above the fully scanned height. Run
+    /// [`vacuum_database`](Self::vacuum_database) afterwards to shrink the
+    /// file.
+    ///
+    /// # Returns
+    /// The number of blocks whose metadata was deleted
+    pub fn prune_block_metadata(&self, retain_blocks: u64) -> Result<usize> {
+        let wallet_db = self.read_wallet_db()?;
+        let height = |metadata: Option<zcash_client_backend::data_api::BlockMetadata>| {
+            metadata.map(|m| u64::from(u32::from(m.block_height())))
+        };
+        let max_scanned = height(
+            wallet_db
+                .block_max_scanned()
+                .map_err(|e| Error::Database(format!("Failed to read scan progress: {}", e)))?,
+        );
+        let fully_scanned = height(
+            wallet_db
+                .block_fully_scanned()
+                .map_err(|e| Error::Database(format!("Failed to read scan progress: {}", e)))?,
+        );
+        let (Some(max_scanned), Some(fully_scanned)) = (max_scanned, fully_scanned) else {
+            return Ok(0);
+        };
+        drop(wallet_db);
+
+        let below = max_scanned
+            .saturating_sub(retain_blocks.max(MIN_RETAINED_BLOCKS))
+            .min(fully_scanned);
+        prune_blocks(&self.db_path, below)
+    }
+
+    /// Rebuild the wallet database file to release space freed by deletions
+    ///
+    /// Needs free disk space for a temporary copy of the database and blocks
+    /// other connections to it while running.
+    pub fn vacuum_database(&self) -> Result<VacuumReport> {
+        vacuum(&self.db_path)
+    }
+
+    /// Check the wallet database for corruption
+    ///
+    /// Runs SQLite's integrity and foreign key checks, and checks that notes
+    /// have consistent commitment tree positions, that the trees' shards and
+    /// checkpoints match the scanned blocks, and that nullifiers and
+    /// recorded spends agree. Only reads the database.
+    ///
+    /// # Returns
+    /// The issues found, each with the repair that fixes it, if any
+    pub fn verify_integrity(&self) -> Result<IntegrityReport> {
+        verify_integrity(&self.db_path)
+    }
+
+    /// Check that every unspent shielded note can be witnessed
+    ///
+    /// A note is only spendable once the wallet can build a witness for it
+    /// against a recent anchor, which needs its tree position, its whole
+    /// tree shard scanned and a checkpoint above it. Notes that fail this
+    /// are still counted in the balance, so sending them fails with an
+    /// insufficient funds error. Each such note is reported with the block
+    /// range to rescan; notes in shards with unscanned blocks usually just
+    /// need the sync to finish. Only reads the database.
+    ///
+    /// # Returns
+    /// The notes checked and those that cannot be witnessed
+    pub fn check_note_witnesses(&self) -> Result<WitnessReport> {
+        check_witnesses(&self.db_path)
+    }
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new().expect("Failed to create default wallet")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+    use zcash_protocol::consensus::MainNetwork;
+
+    fn create_selected_account(wallet: &Wallet) {
+        let birthday = AccountBirthday::from_sapling_activation(
+            &wallet.consensus_network(),
+            zcash_primitives::block::BlockHash([0u8; 32]),
+        );
+        wallet.create_account("main", &birthday).unwrap();
+    }
+
+    #[test]
+    fn test_wallet_creation() {
+        let db_path = TempDb::new();
+        let wallet = Wallet::with_path(db_path.to_path_buf()).unwrap();
         assert_eq!(wallet.network(), Network::Mainnet);
+        assert_eq!(wallet.export_mnemonic().unwrap().split(' ').count(), 24);
+    }
+
+    #[test]
+    fn test_mnemonic_seed() {
+        let phrase = format!("{} art", ["abandon"; 23].join(" "));
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_mnemonic(db_path.to_path_buf(), &phrase, "TREZOR").unwrap();
+        // BIP-39 reference vector
+        assert_eq!(
+            hex::encode(wallet.vault.seed().unwrap().expose_secret()),
+            "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8"
+        );
+        assert_eq!(wallet.export_mnemonic().unwrap(), phrase);
+
+        let bad = format!("{} abandon", ["abandon"; 23].join(" "));
+        assert!(
+            Wallet::with_path_and_mnemonic(db_path.with_file_name("bad.db"), &bad, "").is_err()
+        );
+    }
+
+    #[test]
+    fn test_next_unified_address() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        let default = wallet.get_unified_address().unwrap();
+        assert!(matches!(
+            wallet.get_next_unified_address(),
+            Err(Error::Wallet(_))
+        ));
+        create_selected_account(&wallet);
+
+        let (first, first_index) = wallet.get_next_unified_address().unwrap();
+        let (second, second_index) = wallet.get_next_unified_address().unwrap();
+        assert_ne!(first, default);
+        assert_ne!(first, second);
+        assert!(second_index > first_index);
+
+        // The index survives reopening the wallet
+        let reopened =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        let (_, third_index) = reopened.get_next_unified_address().unwrap();
+        assert!(third_index > second_index);
+    }
+
+    #[test]
+    fn test_network_is_persisted() {
+        let db_path = TempDb::new();
+        let mut wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        wallet.set_network(Network::Testnet).unwrap();
+        assert!(wallet.get_transparent_address().unwrap().starts_with("tm"));
+
+        let mut reopened =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        assert_eq!(reopened.network(), Network::Testnet);
+        assert!(matches!(
+            reopened.set_network(Network::Mainnet),
+            Err(Error::Wallet(_))
+        ));
+        assert_eq!(reopened.network(), Network::Testnet);
+        reopened.override_network(Network::Mainnet).unwrap();
+        reopened.set_network(Network::Mainnet).unwrap();
+
+        let ephemeral = Wallet::ephemeral(Network::Regtest).unwrap();
+        assert_eq!(ephemeral.stored_network().unwrap(), Some(Network::Regtest));
+    }
+
+    #[test]
+    fn test_regtest_addresses() {
+        let wallet = Wallet::ephemeral(Network::Regtest).unwrap();
+        assert!(wallet.get_unified_address().unwrap().starts_with("uregtest"));
+        assert!(wallet
+            .get_sapling_address()
+            .unwrap()
+            .starts_with("zregtestsapling"));
+    }
+
+    #[test]
+    fn test_regtest_upgrades_are_persisted() {
+        use zcash_protocol::consensus::{BlockHeight, NetworkUpgrade, Parameters};
+
+        let db_path = TempDb::new();
+        let mut wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        let upgrades = RegtestUpgrades::all_at(1)
+            .with_activation(NetworkUpgrade::Nu5, 5)
+            .unwrap();
+        assert!(matches!(
+            wallet.set_regtest_upgrades(upgrades),
+            Err(Error::InvalidParameter(_))
+        ));
+        wallet.set_network(Network::Regtest).unwrap();
+        wallet.set_regtest_upgrades(upgrades).unwrap();
+
+        let reopened =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        let params = reopened.consensus_network();
+        assert_eq!(
+            params.activation_height(NetworkUpgrade::Nu5),
+            Some(BlockHeight::from_u32(5))
+        );
+        assert_eq!(
+            params.activation_height(NetworkUpgrade::Canopy),
+            Some(BlockHeight::from_u32(1))
+        );
+    }
+
+    #[test]
+    fn test_address_for_external_id() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        let (address, index) = wallet.address_for_external_id("customer-42").unwrap();
+        assert!(index >= 1 << 62);
+        assert_eq!(
+            wallet.address_for_external_id("customer-42").unwrap(),
+            (address.clone(), index)
+        );
+        assert_ne!(
+            wallet.address_for_external_id("customer-43").unwrap().0,
+            address
+        );
+        assert!(wallet.address_for_external_id("").is_err());
+
+        // The mapping is keyed by the account's viewing key
+        let other_path = TempDb::new();
+        let other =
+            Wallet::with_path_and_seed(other_path.to_path_buf(), Some(vec![8u8; 32])).unwrap();
+        assert_ne!(
+            other.address_for_external_id("customer-42").unwrap().0,
+            address
+        );
+        assert_ne!(external_id_index(b"key", "a"), external_id_index(b"other", "a"));
+    }
+
+    #[test]
+    fn test_orchard_receiver() {
+        let wallet = Wallet::ephemeral(Network::Mainnet).unwrap();
+        let receiver = wallet.get_orchard_receiver().unwrap();
+        assert_eq!(receiver.raw.len(), 86);
+        assert_eq!(wallet.get_orchard_address().unwrap(), receiver.address);
+        assert_ne!(receiver.address, wallet.get_unified_address().unwrap());
+
+        assert_eq!(
+            crate::address::get_address_type(&receiver.address, wallet.consensus_network())
+                .unwrap(),
+            crate::address::AddressType::Orchard
+        );
+    }
+
+    #[test]
+    fn test_address_rotation() {
+        let mut wallet = Wallet::ephemeral(Network::Mainnet).unwrap();
+        create_selected_account(&wallet);
+        assert_eq!(wallet.address_rotation(), AddressRotation::Default);
+        let default = wallet.get_unified_address().unwrap();
+        assert_eq!(wallet.get_unified_address().unwrap(), default);
+
+        wallet.set_address_rotation(AddressRotation::Daily);
+        let today = wallet.get_unified_address().unwrap();
+        assert_ne!(today, default);
+        assert_eq!(wallet.get_unified_address().unwrap(), today);
+
+        let mut wallet = wallet.with_address_rotation(AddressRotation::PerCall);
+        let first = wallet.get_unified_address().unwrap();
+        let second = wallet.get_unified_address().unwrap();
+        assert!(first != today && first != second);
+
+        // Today's address stays put while other addresses are handed out
+        wallet.set_address_rotation(AddressRotation::Daily);
+        assert_eq!(wallet.get_unified_address().unwrap(), today);
+    }
+
+    #[test]
+    fn test_transactions_of_new_wallet() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        assert!(wallet.get_transactions(None).unwrap().is_empty());
+        assert!(wallet.get_transactions_page(10, Some(5)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_database_maintenance() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        wallet.get_balance().unwrap();
+
+        let size = wallet.database_size().unwrap();
+        assert!(size.tables.iter().any(|table| table.name == "blocks"));
+        // Nothing scanned yet, so nothing to prune
+        assert_eq!(wallet.prune_block_metadata(0).unwrap(), 0);
+        wallet.vacuum_database().unwrap();
+        assert!(wallet.verify_integrity().unwrap().is_ok());
+        assert!(wallet.check_note_witnesses().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_detailed_balance_of_new_wallet() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        let balance = wallet.get_balance_detailed(10).unwrap();
+        assert_eq!(balance.confirmations, 10);
+        assert_eq!(balance.total, PoolBalance::default());
+        assert!(matches!(
+            wallet.get_balance_detailed(0),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_notes_of_new_wallet() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        assert!(wallet
+            .list_notes(ConfirmationsPolicy::default())
+            .unwrap()
+            .is_empty());
+
+        let note = NoteId {
+            pool: Pool::Orchard,
+            txid: "ab".repeat(32),
+            output_index: 0,
+        };
+        assert!(matches!(
+            wallet.freeze_note(&note),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(!wallet.unfreeze_note(&note).unwrap());
+    }
+
+    #[test]
+    fn test_seed_fingerprint() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![5u8; 32])).unwrap();
+        assert_eq!(wallet.seed_fingerprint().unwrap().len(), 64);
+
+        // A database without accounts can be opened with any seed
+        let other = Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![6u8; 32])).unwrap();
+        assert_ne!(other.seed_fingerprint().unwrap(), wallet.seed_fingerprint().unwrap());
+
+        let birthday = AccountBirthday::from_sapling_activation(
+            &MainNetwork,
+            zcash_primitives::block::BlockHash([0u8; 32]),
+        );
+        wallet.create_account("main", &birthday).unwrap();
+        drop((wallet, other));
+        assert!(matches!(
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![6u8; 32])),
+            Err(Error::Wallet(_))
+        ));
+        assert!(Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![5u8; 32])).is_ok());
+    }
+
+    #[test]
+    fn test_stored_seed() {
+        let db_path = TempDb::new();
+        assert!(Wallet::open_with_stored_seed(db_path.to_path_buf(), "hunter2").is_err());
+
+        let wallet = Wallet::with_path(db_path.to_path_buf()).unwrap();
+        wallet.store_seed("hunter2").unwrap();
+        let reopened = Wallet::open_with_stored_seed(db_path.to_path_buf(), "hunter2").unwrap();
+        assert_eq!(
+            reopened.seed_fingerprint().unwrap(),
+            wallet.seed_fingerprint().unwrap()
+        );
+        assert_eq!(
+            reopened.export_mnemonic().unwrap(),
+            wallet.export_mnemonic().unwrap()
+        );
+        assert!(matches!(
+            Wallet::open_with_stored_seed(db_path.to_path_buf(), "wrong"),
+            Err(Error::Wallet(_))
+        ));
+
+        assert!(wallet.remove_stored_seed().unwrap());
+        assert!(Wallet::open_with_stored_seed(db_path.to_path_buf(), "hunter2").is_err());
+    }
+
+    #[test]
+    fn test_ephemeral_wallet() {
+        let wallet = Wallet::ephemeral(Network::Testnet).unwrap();
+        assert!(wallet.is_ephemeral());
+        assert_eq!(wallet.network(), Network::Testnet);
+        assert!(!wallet.db_path().exists());
+        assert_eq!(wallet.get_balance().unwrap(), Balance::default());
+
+        // Stores opening their own connection see the same database
+        let address = wallet.get_unified_address().unwrap();
+        AddressBook::for_wallet(&wallet)
+            .unwrap()
+            .set_label(&address, "me")
+            .unwrap();
+        assert_eq!(
+            AddressBook::for_wallet(&wallet).unwrap().label(&address).unwrap(),
+            Some("me".to_string())
+        );
+
+        let other = Wallet::ephemeral(Network::Testnet).unwrap();
+        assert!(AddressBook::for_wallet(&other)
+            .unwrap()
+            .label(&address)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            wallet.import_snapshot(Path::new("snapshot.db")),
+            Err(Error::Wallet(_))
+        ));
+    }
+
+    #[test]
+    fn test_clones_share_state() {
+        let wallet = Wallet::ephemeral(Network::Testnet).unwrap();
+        create_selected_account(&wallet);
+        let clone = wallet.clone();
+        // Both handles advance the same stored diversifier index
+        let (_, first) = wallet.get_next_unified_address().unwrap();
+        let (_, second) = clone.get_next_unified_address().unwrap();
+        assert!(second > first);
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let wallet = wallet.clone();
+                std::thread::spawn(move || wallet.get_balance())
+            })
+            .collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap().unwrap(), Balance::default());
+        }
+    }
+
+    #[test]
+    fn test_accounts_with_metadata() {
+        let wallet = Wallet::ephemeral(Network::Mainnet).unwrap();
+        let birthday = AccountBirthday::from_sapling_activation(
+            &MainNetwork,
+            zcash_primitives::block::BlockHash([0u8; 32]),
+        );
+        let account = wallet.create_account("main", &birthday).unwrap();
+        assert_eq!(account.metadata, AccountMetadata::default());
+
+        let store = AccountMetadataStore::for_wallet(&wallet).unwrap();
+        store.set_label(&account.uuid, Some("Customer 42")).unwrap();
+        store.set_value(&account.uuid, "tenant_id", "42").unwrap();
+
+        let accounts = wallet.list_accounts().unwrap();
+        assert_eq!(accounts[0].metadata.label.as_deref(), Some("Customer 42"));
+        assert_eq!(accounts[0].metadata.values["tenant_id"], "42");
+        assert_eq!(store.find("tenant_id", "42").unwrap(), vec![account.uuid]);
+    }
+
+    #[test]
+    fn test_account_balances() {
+        let wallet = Wallet::ephemeral(Network::Mainnet).unwrap();
+        assert!(wallet.get_account_balances().unwrap().is_empty());
+
+        let birthday = AccountBirthday::from_sapling_activation(
+            &MainNetwork,
+            zcash_primitives::block::BlockHash([0u8; 32]),
+        );
+        let first = wallet.create_account("first", &birthday).unwrap();
+        let second = wallet.create_account_at(1, "second", &birthday).unwrap();
+
+        let balances = wallet.get_account_balances().unwrap();
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[&first.uuid], AccountBalance::default());
+        assert_eq!(balances[&second.uuid], AccountBalance::default());
+
+        assert!(wallet.archive_account(&second.uuid).unwrap());
+        assert!(!wallet.archive_account(&second.uuid).unwrap());
+        assert!(wallet.archive_account(&first.uuid).is_err());
+        assert!(wallet.archive_account("no-such-account").is_err());
+        let balances = wallet.get_account_balances().unwrap();
+        assert_eq!(balances.keys().collect::<Vec<_>>(), vec![&first.uuid]);
+        let accounts = wallet.list_accounts().unwrap();
+        assert!(!accounts[0].archived && accounts[1].archived);
+
+        assert!(wallet.unarchive_account(&second.uuid).unwrap());
+        assert_eq!(wallet.get_account_balances().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_custom_account_index() {
+        let wallet = Wallet::ephemeral(Network::Mainnet).unwrap();
+        // Nothing to validate against before any account exists
+        let mut wallet = wallet.with_account(AccountId::try_from(3).unwrap()).unwrap();
+        assert_eq!(wallet.account_index(), 3);
+
+        let birthday = AccountBirthday::from_sapling_activation(
+            &MainNetwork,
+            zcash_primitives::block::BlockHash([0u8; 32]),
+        );
+        let account = wallet.create_account_at(3, "third", &birthday).unwrap();
+        assert_eq!(account.index, Some(3));
+        assert!(wallet.create_account_at(3, "again", &birthday).is_err());
+
+        assert!(matches!(
+            wallet.use_account(0),
+            Err(Error::InvalidParameter(_))
+        ));
+        wallet.use_account(3).unwrap();
+    }
+
+    #[test]
+    fn test_spending_key_export_roundtrip() {
+        let source_path = TempDb::new();
+        let source =
+            Wallet::with_path_and_seed(source_path.to_path_buf(), Some(vec![7u8; 32])).unwrap();
+        let path = source_path.with_file_name("key.bin");
+        source.export_unified_spending_key(&path, "passphrase").unwrap();
+
+        let target_path = TempDb::new();
+        let target =
+            Wallet::with_path_and_seed(target_path.to_path_buf(), Some(vec![8u8; 32])).unwrap();
+        let birthday = AccountBirthday::from_sapling_activation(
+            &MainNetwork,
+            zcash_primitives::block::BlockHash([0u8; 32]),
+        );
+        assert!(target
+            .import_spending_key(&path, "wrong", "moved", &birthday)
+            .is_err());
+        let account = target
+            .import_spending_key(&path, "passphrase", "moved", &birthday)
+            .unwrap();
+        assert_eq!(account.index, None);
+        assert_eq!(
+            account.ufvk,
+            Some(source.unified_full_viewing_key().unwrap().encode(&MainNetwork))
+        );
+        assert!(target
+            .import_encoded_spending_key("not a key", "bad", &birthday)
+            .is_err());
+    }
+
+    #[test]
+    fn test_memo_matcher() {
+        assert!(MemoMatcher::new(&TransactionQuery::default()).unwrap().is_none());
+
+        let matcher = MemoMatcher::new(&TransactionQuery::memo("Invoice"))
+            .unwrap()
+            .unwrap();
+        assert!(matcher.matches("Payment for invoice 42"));
+        assert!(!matcher.matches("rent"));
+
+        let query = TransactionQuery {
+            memo_contains: Some("invoice".to_string()),
+            memo_regex: Some(r"#\d+$".to_string()),
+            ..TransactionQuery::default()
+        };
+        let matcher = MemoMatcher::new(&query).unwrap().unwrap();
+        assert!(matcher.matches("INVOICE #17"));
+        assert!(!matcher.matches("invoice #17 paid"));
+
+        let invalid = TransactionQuery {
+            memo_regex: Some("(".to_string()),
+            ..TransactionQuery::default()
+        };
+        assert!(matches!(MemoMatcher::new(&invalid), Err(Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn test_search_transactions_of_new_wallet() {
+        let db_path = TempDb::new();
+        let wallet =
+            Wallet::with_path_and_seed(db_path.to_path_buf(), Some(vec![4u8; 32])).unwrap();
+        let query = TransactionQuery {
+            min_amount: Some(1),
+            ..TransactionQuery::memo("coffee")
+        };
+        assert!(wallet.search_transactions(&query).unwrap().is_empty());
+        assert!(wallet.search_memos("INV-2024-117").unwrap().is_empty());
+        assert!(wallet.search_memos(" ").is_err());
+    }
+
+    #[test]
+    fn test_memo_text() {
+        let mut memo = vec![0u8; 512];
+        memo[..5].copy_from_slice(b"hello");
+        assert_eq!(memo_text(&memo).as_deref(), Some("hello"));
+
+        let mut empty = vec![0u8; 512];
+        empty[0] = 0xF6;
+        assert_eq!(memo_text(&empty), None);
+    }
+
+    #[test]
+    fn test_backup_roundtrip() {
+        let db_path = TempDb::new();
+        let backup_path = db_path.with_file_name("backup.bak");
+        let mut wallet = Wallet::with_path(db_path.to_path_buf()).unwrap();
+        wallet.set_network(Network::Testnet).unwrap();
+        AddressBook::for_wallet(&wallet)
+            .unwrap()
+            .set_label("tmExample", "Alice")
+            .unwrap();
+        wallet.export_backup(&backup_path, "passphrase").unwrap();
+
+        let restored_path = TempDb::new();
+        let restored =
+            Wallet::import_backup(&backup_path, "passphrase", restored_path.to_path_buf()).unwrap();
+        assert_eq!(restored.network(), Network::Testnet);
+        assert_eq!(restored.export_mnemonic().unwrap(), wallet.export_mnemonic().unwrap());
+        assert_eq!(
+            restored.get_unified_address().unwrap(),
+            wallet.get_unified_address().unwrap()
+        );
+        assert_eq!(
+            AddressBook::for_wallet(&restored).unwrap().label("tmExample").unwrap(),
+            Some("Alice".to_string())
+        );
+        assert!(
+            Wallet::import_backup(&backup_path, "wrong", db_path.with_file_name("unused.db"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_interchange_roundtrip() {
+        let wallet = Wallet::ephemeral(Network::Mainnet).unwrap();
+        let birthday = AccountBirthday::from_sapling_activation(
+            &MainNetwork,
+            zcash_primitives::block::BlockHash([0u8; 32]),
+        );
+        wallet.create_account("main", &birthday).unwrap();
+        let address = wallet.account_unified_address(0).unwrap();
+        let address_book = AddressBook::for_wallet(&wallet).unwrap();
+        address_book.set_label(&address, "Donations").unwrap();
+        address_book.set_label("t1payee", "Payee").unwrap();
+        let txid = "ab".repeat(32);
+        TransactionAnnotations::for_wallet(&wallet)
+            .unwrap()
+            .set(&txid, "Invoice 117")
+            .unwrap();
+
+        let document = wallet.interchange_document().unwrap();
+        let account = &document.accounts[0];
+        assert_eq!(account.zip32_account_index, Some(0));
+        assert_eq!(account.addresses[0].address, address);
+        assert_eq!(account.addresses[0].label.as_deref(), Some("Donations"));
+        assert_eq!(document.address_labels.len(), 1);
+        assert_eq!(
+            document.transactions[0].annotation.as_deref(),
+            Some("Invoice 117")
+        );
+
+        let db_path = TempDb::new();
+        let path = db_path.with_file_name("wallet.zwf");
+        wallet.export_interchange(&path, "passphrase").unwrap();
+        let restored =
+            Wallet::import_interchange(&path, "passphrase", db_path.to_path_buf()).unwrap();
+        assert_eq!(
+            restored.export_mnemonic().unwrap(),
+            wallet.export_mnemonic().unwrap()
+        );
+        assert_eq!(
+            AddressBook::for_wallet(&restored)
+                .unwrap()
+                .label(&address)
+                .unwrap(),
+            Some("Donations".to_string())
+        );
+        assert_eq!(
+            TransactionAnnotations::for_wallet(&restored)
+                .unwrap()
+                .get(&txid)
+                .unwrap()
+                .as_deref(),
+            Some("Invoice 117")
+        );
     }
 }
@@ -0,0 +1,422 @@
+//! Account labels and metadata
+//!
+//! Services that keep one account per customer need to know which account
+//! belongs to whom. Accounts can be given a display label, a color for UIs,
+//! and arbitrary key/value metadata (e.g. `tenant_id`). It is stored in the
+//! wallet's SQLite database, keyed by the account UUID, and returned with
+//! each account from [`Wallet::list_accounts`].
+//!
+//! The label is separate from the account name given at creation, which the
+//! wallet database does not allow changing.
+//!
+//! Accounts can also be archived: an archived account is flagged here and
+//! left out of balance totals until it is unarchived. Its keys and history
+//! stay in the wallet database. The height it was archived at is recorded,
+//! and unarchiving queues the blocks scanned in between for a rescan.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Maximum label length in bytes
+const MAX_LABEL_LEN: usize = 64;
+
+/// Maximum metadata key length in bytes
+const MAX_KEY_LEN: usize = 64;
+
+/// Labels and metadata attached to an account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountMetadata {
+    /// Display label
+    pub label: Option<String>,
+    /// Color as `#rrggbb`
+    pub color: Option<String>,
+    /// Application-defined key/value pairs
+    pub values: BTreeMap<String, String>,
+}
+
+const DB_CONTEXT: &str = "Account metadata error";
+
+/// Account metadata stored in a wallet database
+pub struct AccountMetadataStore {
+    conn: Connection,
+}
+
+impl AccountMetadataStore {
+    /// Open (or create) the account metadata in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_account_metadata (
+                account_uuid TEXT PRIMARY KEY,
+                label TEXT,
+                color TEXT,
+                account_values TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS numi_archived_accounts (
+                account_uuid TEXT PRIMARY KEY,
+                archived_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS numi_rescan_ranges (
+                account_uuid TEXT PRIMARY KEY,
+                start_height INTEGER NOT NULL,
+                end_height INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        // Accounts archived before heights were recorded have none
+        let has_height: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('numi_archived_accounts')
+                 WHERE name = 'archived_height'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(db_error(DB_CONTEXT))?
+            > 0;
+        if !has_height {
+            conn.execute_batch(
+                "ALTER TABLE numi_archived_accounts ADD COLUMN archived_height INTEGER;",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Open the account metadata stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Metadata of an account (empty if none was set)
+    pub fn get(&self, account_uuid: &str) -> Result<AccountMetadata> {
+        self.conn
+            .query_row(
+                "SELECT label, color, account_values FROM numi_account_metadata
+                 WHERE account_uuid = ?1",
+                [account_uuid],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?
+            .map_or(Ok(AccountMetadata::default()), parse_row)
+    }
+
+    /// Replace all metadata of an account
+    ///
+    /// Fails if the label is too long, the color is not `#rrggbb`, or a key
+    /// is empty or too long.
+    pub fn set(&self, account_uuid: &str, metadata: &AccountMetadata) -> Result<()> {
+        validate(metadata)?;
+        self.conn
+            .execute(
+                "INSERT INTO numi_account_metadata
+                     (account_uuid, label, color, account_values, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(account_uuid) DO UPDATE SET
+                     label = ?2, color = ?3, account_values = ?4, updated_at = ?5",
+                params![
+                    account_uuid,
+                    metadata.label,
+                    metadata.color,
+                    serde_json::to_string(&metadata.values)?,
+                    unix_now() as i64
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Set or clear an account's label
+    pub fn set_label(&self, account_uuid: &str, label: Option<&str>) -> Result<()> {
+        self.modify(account_uuid, |metadata| {
+            metadata.label = label.map(|label| label.trim().to_string())
+        })
+    }
+
+    /// Set or clear an account's color (`#rrggbb`)
+    pub fn set_color(&self, account_uuid: &str, color: Option<&str>) -> Result<()> {
+        self.modify(account_uuid, |metadata| {
+            metadata.color = color.map(str::to_ascii_lowercase)
+        })
+    }
+
+    /// Set a metadata value
+    pub fn set_value(&self, account_uuid: &str, key: &str, value: &str) -> Result<()> {
+        self.modify(account_uuid, |metadata| {
+            metadata.values.insert(key.to_string(), value.to_string());
+        })
+    }
+
+    /// Remove a metadata value
+    ///
+    /// # Returns
+    /// Whether the key was set
+    pub fn remove_value(&self, account_uuid: &str, key: &str) -> Result<bool> {
+        let mut metadata = self.get(account_uuid)?;
+        if metadata.values.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.set(account_uuid, &metadata)?;
+        Ok(true)
+    }
+
+    fn modify(&self, account_uuid: &str, f: impl FnOnce(&mut AccountMetadata)) -> Result<()> {
+        let mut metadata = self.get(account_uuid)?;
+        f(&mut metadata);
+        self.set(account_uuid, &metadata)
+    }
+
+    /// Remove all metadata of an account
+    ///
+    /// # Returns
+    /// Whether the account had metadata
+    pub fn remove(&self, account_uuid: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute(
+                "DELETE FROM numi_account_metadata WHERE account_uuid = ?1",
+                [account_uuid],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(removed > 0)
+    }
+
+    /// Metadata of every account that has any, keyed by account UUID
+    pub fn all(&self) -> Result<HashMap<String, AccountMetadata>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT account_uuid, label, color, account_values FROM numi_account_metadata")
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    (row.get(1)?, row.get(2)?, row.get(3)?),
+                ))
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        rows.into_iter()
+            .map(|(uuid, row)| Ok((uuid, parse_row(row)?)))
+            .collect()
+    }
+
+    /// UUIDs of the accounts whose metadata value for `key` is `value`
+    pub fn find(&self, key: &str, value: &str) -> Result<Vec<String>> {
+        let mut uuids: Vec<String> = self
+            .all()?
+            .into_iter()
+            .filter(|(_, metadata)| metadata.values.get(key).is_some_and(|v| v == value))
+            .map(|(uuid, _)| uuid)
+            .collect();
+        uuids.sort();
+        Ok(uuids)
+    }
+
+    /// Archive an account
+    ///
+    /// # Arguments
+    /// * `height` - First height scanned without the account, if known
+    ///
+    /// # Returns
+    /// Whether the account was not archived already
+    pub fn archive(&self, account_uuid: &str, height: Option<u64>) -> Result<bool> {
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO numi_archived_accounts
+                    (account_uuid, archived_at, archived_height)
+                 VALUES (?1, ?2, ?3)",
+                params![account_uuid, unix_now() as i64, height.map(|h| h as i64)],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(inserted > 0)
+    }
+
+    /// Unarchive an account, queueing the blocks scanned without it for a
+    /// rescan
+    ///
+    /// # Arguments
+    /// * `height` - Last height scanned while the account was archived, if any
+    ///
+    /// # Returns
+    /// Whether the account was archived
+    pub fn unarchive(&self, account_uuid: &str, height: Option<u64>) -> Result<bool> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(db_error(DB_CONTEXT))?;
+        let archived_height = tx
+            .query_row(
+                "SELECT archived_height FROM numi_archived_accounts WHERE account_uuid = ?1",
+                [account_uuid],
+                |row| row.get::<_, Option<i64>>(0),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?;
+        let Some(archived_height) = archived_height else {
+            return Ok(false);
+        };
+        tx.execute(
+            "DELETE FROM numi_archived_accounts WHERE account_uuid = ?1",
+            [account_uuid],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        if let (Some(start), Some(end)) = (archived_height, height) {
+            if end >= start as u64 {
+                tx.execute(
+                    "INSERT INTO numi_rescan_ranges (account_uuid, start_height, end_height)
+                     VALUES (?1, ?2, ?3)
+                     ON CONFLICT(account_uuid) DO UPDATE SET
+                        start_height = MIN(start_height, excluded.start_height),
+                        end_height = MAX(end_height, excluded.end_height)",
+                    params![account_uuid, start, end as i64],
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+            }
+        }
+        tx.commit().map_err(db_error(DB_CONTEXT))?;
+        Ok(true)
+    }
+
+    /// Heights that must be scanned again for unarchived accounts
+    ///
+    /// The union of the queued ranges, or `None` if nothing is queued.
+    pub fn pending_rescan(&self) -> Result<Option<RangeInclusive<u64>>> {
+        let (start, end) = self
+            .conn
+            .query_row(
+                "SELECT MIN(start_height), MAX(end_height) FROM numi_rescan_ranges",
+                [],
+                |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?)),
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(start.zip(end).map(|(start, end)| start as u64..=end as u64))
+    }
+
+    /// Remove the queued ranges within `scanned`
+    pub fn complete_rescan(&self, scanned: RangeInclusive<u64>) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM numi_rescan_ranges WHERE start_height >= ?1 AND end_height <= ?2",
+                params![*scanned.start() as i64, *scanned.end() as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// UUIDs of the archived accounts
+    pub fn archived(&self) -> Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT account_uuid FROM numi_archived_accounts")
+            .map_err(db_error(DB_CONTEXT))?;
+        let uuids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<HashSet<String>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(uuids)
+    }
+}
+
+fn validate(metadata: &AccountMetadata) -> Result<()> {
+    if let Some(label) = &metadata.label {
+        if label.is_empty() || label.len() > MAX_LABEL_LEN {
+            return Err(Error::InvalidParameter(format!(
+                "Account label must be 1 to {} bytes",
+                MAX_LABEL_LEN
+            )));
+        }
+    }
+    if let Some(color) = &metadata.color {
+        let hex = color.strip_prefix('#').unwrap_or_default();
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::InvalidParameter(format!(
+                "Invalid account color '{}', expected #rrggbb",
+                color
+            )));
+        }
+    }
+    for key in metadata.values.keys() {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(Error::InvalidParameter(format!(
+                "Account metadata keys must be 1 to {} bytes",
+                MAX_KEY_LEN
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Label, color and JSON-encoded values of a stored row
+type MetadataRow = (Option<String>, Option<String>, String);
+
+fn parse_row((label, color, values): MetadataRow) -> Result<AccountMetadata> {
+    Ok(AccountMetadata {
+        label,
+        color,
+        values: serde_json::from_str(&values)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_account_metadata() {
+        let path = TempDb::new();
+        let store = AccountMetadataStore::open(&path).unwrap();
+        assert_eq!(store.get("a").unwrap(), AccountMetadata::default());
+
+        store.set_label("a", Some(" Alice ")).unwrap();
+        store.set_color("a", Some("#FFAA00")).unwrap();
+        store.set_value("a", "tenant_id", "42").unwrap();
+        store.set_value("b", "tenant_id", "7").unwrap();
+
+        let metadata = store.get("a").unwrap();
+        assert_eq!(metadata.label.as_deref(), Some("Alice"));
+        assert_eq!(metadata.color.as_deref(), Some("#ffaa00"));
+        assert_eq!(store.find("tenant_id", "42").unwrap(), vec!["a"]);
+        assert_eq!(store.all().unwrap().len(), 2);
+
+        assert!(store.set_color("a", Some("orange")).is_err());
+        assert!(store.set_value("a", "", "x").is_err());
+        assert!(store.set_label("a", Some("")).is_err());
+
+        assert!(store.remove_value("a", "tenant_id").unwrap());
+        assert!(!store.remove_value("a", "tenant_id").unwrap());
+        assert!(store.find("tenant_id", "42").unwrap().is_empty());
+        assert!(store.remove("b").unwrap());
+        assert_eq!(store.get("b").unwrap(), AccountMetadata::default());
+
+        assert!(store.archive("a", Some(100)).unwrap());
+        assert!(!store.archive("a", Some(120)).unwrap());
+        assert!(store.archive("b", None).unwrap());
+        assert_eq!(
+            store.archived().unwrap(),
+            HashSet::from(["a".to_string(), "b".to_string()])
+        );
+        assert!(store.unarchive("a", Some(150)).unwrap());
+        assert!(!store.unarchive("a", Some(150)).unwrap());
+        assert!(store.unarchive("b", Some(150)).unwrap());
+        assert!(store.archived().unwrap().is_empty());
+
+        assert_eq!(store.pending_rescan().unwrap(), Some(100..=150));
+        store.complete_rescan(120..=200).unwrap();
+        assert_eq!(store.pending_rescan().unwrap(), Some(100..=150));
+        store.complete_rescan(90..=200).unwrap();
+        assert_eq!(store.pending_rescan().unwrap(), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Transaction annotations
+//!
+//! A free-text note per transaction (e.g. "Invoice 2024-117" or "Refund to
+//! Bob") for bookkeeping. Memos are chosen by the sender and fixed on
+//! chain; annotations are the wallet owner's own notes and can be changed at
+//! any time. They are stored in the wallet's SQLite database keyed by
+//! transaction ID, so they can be recorded before the transaction is
+//! scanned, and are carried by the wallet interchange format (see
+//! [`crate::interchange`]).
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Maximum annotation length in bytes
+const MAX_ANNOTATION_LEN: usize = 1024;
+
+const DB_CONTEXT: &str = "Transaction annotation error";
+
+/// Transaction annotations stored in a wallet database
+pub struct TransactionAnnotations {
+    conn: Connection,
+}
+
+impl TransactionAnnotations {
+    /// Open (or create) the annotations in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_transaction_annotations (
+                txid TEXT PRIMARY KEY,
+                annotation TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    /// Open the annotations stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Set a transaction's annotation, replacing any previous one
+    ///
+    /// # Arguments
+    /// * `txid` - Transaction ID, as shown by explorers
+    /// * `annotation` - Note of 1 to 1024 bytes
+    pub fn set(&self, txid: &str, annotation: &str) -> Result<()> {
+        let txid = normalize_txid(txid)?;
+        let annotation = annotation.trim();
+        if annotation.is_empty() || annotation.len() > MAX_ANNOTATION_LEN {
+            return Err(Error::InvalidParameter(format!(
+                "Transaction annotations must be 1 to {} bytes",
+                MAX_ANNOTATION_LEN
+            )));
+        }
+        self.conn
+            .execute(
+                "INSERT INTO numi_transaction_annotations (txid, annotation, updated_at)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(txid) DO UPDATE SET annotation = ?2, updated_at = ?3",
+                params![txid, annotation, unix_now() as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Annotation of a transaction, if it has one
+    pub fn get(&self, txid: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT annotation FROM numi_transaction_annotations WHERE txid = ?1",
+                [normalize_txid(txid)?],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// Remove a transaction's annotation
+    ///
+    /// # Returns
+    /// Whether the transaction had an annotation
+    pub fn remove(&self, txid: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute(
+                "DELETE FROM numi_transaction_annotations WHERE txid = ?1",
+                [normalize_txid(txid)?],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(removed > 0)
+    }
+
+    /// Every annotation, keyed by transaction ID
+    pub fn all(&self) -> Result<BTreeMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT txid, annotation FROM numi_transaction_annotations")
+            .map_err(db_error(DB_CONTEXT))?;
+        let annotations = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<BTreeMap<String, String>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(annotations)
+    }
+}
+
+/// Lowercase a transaction ID after checking it is 32 hex-encoded bytes
+fn normalize_txid(txid: &str) -> Result<String> {
+    let txid = txid.trim();
+    if txid.len() != 64 || !txid.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::InvalidParameter(format!(
+            "Invalid transaction ID '{}'",
+            txid
+        )));
+    }
+    Ok(txid.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_annotations() {
+        let path = TempDb::new();
+        let store = TransactionAnnotations::open(&path).unwrap();
+        let txid = "AB".repeat(32);
+        assert_eq!(store.get(&txid).unwrap(), None);
+
+        store.set(&txid, " Invoice 117 ").unwrap();
+        store.set(&txid, "Invoice 118").unwrap();
+        assert_eq!(
+            store.get(&txid.to_lowercase()).unwrap().as_deref(),
+            Some("Invoice 118")
+        );
+        assert_eq!(store.all().unwrap().len(), 1);
+
+        assert!(store.set(&txid, "  ").is_err());
+        assert!(store.set("abcd", "Short").is_err());
+        assert!(store.remove(&txid).unwrap());
+        assert!(!store.remove(&txid).unwrap());
+    }
+}
@@ -0,0 +1,188 @@
+//! Non-blocking wallet access for async code
+//!
+//! [`Wallet`] methods that read the wallet database do blocking SQLite I/O.
+//! Called directly from a tokio task they stall the runtime thread, and
+//! with it every other task scheduled on it, for the duration of the query.
+//! [`AsyncWallet`] wraps a wallet in an `Arc` and runs those methods on
+//! tokio's blocking thread pool instead.
+
+use crate::error::{Error, Result};
+use crate::maintenance::DatabaseSize;
+use crate::types::{Balance, DetailedBalance, Transaction, TransactionQuery};
+use crate::wallet::{Wallet, WalletAccount};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+/// A shared wallet whose database queries run on the blocking thread pool
+///
+/// Cloning is cheap; all clones share one wallet and its connection pool.
+/// Methods that do not touch the database (addresses, keys) can be called
+/// directly on [`wallet`](Self::wallet).
+#[derive(Clone)]
+pub struct AsyncWallet {
+    wallet: Arc<Wallet>,
+}
+
+impl AsyncWallet {
+    /// Wrap a wallet
+    pub fn new(wallet: Wallet) -> Self {
+        Self {
+            wallet: Arc::new(wallet),
+        }
+    }
+
+    /// The wrapped wallet, for calls that do no I/O
+    pub fn wallet(&self) -> &Wallet {
+        &self.wallet
+    }
+
+    /// Unwrap the wallet, or `None` if other clones still exist
+    pub fn into_inner(self) -> Option<Wallet> {
+        Arc::into_inner(self.wallet)
+    }
+
+    /// Run a closure with the wallet on the blocking thread pool
+    ///
+    /// Use this for wallet methods without an async variant here.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Wallet) -> Result<T> + Send + 'static,
+    {
+        let wallet = Arc::clone(&self.wallet);
+        tokio::task::spawn_blocking(move || f(&wallet))
+            .await
+            .map_err(|e| Error::Wallet(format!("Wallet task failed: {}", e)))?
+    }
+
+    /// Async variant of [`Wallet::get_balance`]
+    pub async fn get_balance(&self) -> Result<Balance> {
+        self.run(Wallet::get_balance).await
+    }
+
+    /// Async variant of [`Wallet::get_balance_detailed`]
+    pub async fn get_balance_detailed(&self, confirmations: u32) -> Result<DetailedBalance> {
+        self.run(move |wallet| wallet.get_balance_detailed(confirmations))
+            .await
+    }
+
+    /// Async variant of [`Wallet::account_balance`]
+    pub async fn account_balance(&self, index: u32) -> Result<Balance> {
+        self.run(move |wallet| wallet.account_balance(index)).await
+    }
+
+    /// Async variant of [`Wallet::get_transactions`]
+    pub async fn get_transactions(&self, limit: Option<usize>) -> Result<Vec<Transaction>> {
+        self.run(move |wallet| wallet.get_transactions(limit)).await
+    }
+
+    /// Async variant of [`Wallet::get_transactions_page`]
+    pub async fn get_transactions_page(
+        &self,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<Transaction>> {
+        self.run(move |wallet| wallet.get_transactions_page(offset, limit))
+            .await
+    }
+
+    /// Async variant of [`Wallet::search_transactions`]
+    pub async fn search_transactions(&self, query: TransactionQuery) -> Result<Vec<Transaction>> {
+        self.run(move |wallet| wallet.search_transactions(&query))
+            .await
+    }
+
+    /// Async variant of [`Wallet::list_accounts`]
+    pub async fn list_accounts(&self) -> Result<Vec<WalletAccount>> {
+        self.run(Wallet::list_accounts).await
+    }
+
+    /// Async variant of [`Wallet::get_next_unified_address`]
+    pub async fn get_next_unified_address(&self) -> Result<(String, u64)> {
+        self.run(Wallet::get_next_unified_address).await
+    }
+
+    /// Erase the seed on time once the auto-lock timeout passes
+    ///
+    /// Spawns a task that calls [`Wallet::lock_if_idle`] every
+    /// `check_interval`; it ends when the last clone of the wallet is
+    /// dropped. Set the timeout with [`Wallet::set_auto_lock`].
+    pub fn spawn_auto_lock(&self, check_interval: Duration) -> JoinHandle<()> {
+        let wallet = Arc::downgrade(&self.wallet);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(check_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(wallet) = wallet.upgrade() else {
+                    break;
+                };
+                if wallet.lock_if_idle() {
+                    tracing::info!("Wallet auto-locked after inactivity");
+                }
+            }
+        })
+    }
+
+    /// Async variant of [`Wallet::database_size`]
+    pub async fn database_size(&self) -> Result<DatabaseSize> {
+        self.run(Wallet::database_size).await
+    }
+}
+
+impl From<Wallet> for AsyncWallet {
+    fn from(wallet: Wallet) -> Self {
+        Self::new(wallet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_queries_run_off_the_runtime_thread() {
+        let path = TempDb::new();
+        let wallet = Wallet::with_path_and_seed(path.to_path_buf(), Some(vec![9u8; 32])).unwrap();
+        let wallet = AsyncWallet::new(wallet);
+
+        let runtime_thread = std::thread::current().id();
+        let query_thread = wallet
+            .run(|_| Ok(std::thread::current().id()))
+            .await
+            .unwrap();
+        assert_ne!(query_thread, runtime_thread);
+
+        assert_eq!(wallet.get_balance().await.unwrap(), Balance::default());
+        assert!(wallet.get_transactions(Some(10)).await.unwrap().is_empty());
+
+        let clone = wallet.clone();
+        assert!(clone.into_inner().is_none());
+        assert!(wallet.into_inner().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_auto_lock_timer() {
+        let wallet = AsyncWallet::new(Wallet::ephemeral(crate::types::Network::Testnet).unwrap());
+        wallet.wallet().set_lock_passphrase("hunter2").unwrap();
+        wallet
+            .wallet()
+            .set_auto_lock(Some(Duration::from_millis(20)));
+        let timer = wallet.spawn_auto_lock(Duration::from_millis(5));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(wallet.wallet().is_locked());
+        assert!(wallet.wallet().unified_full_viewing_key().is_err());
+        // Database queries keep working while locked
+        assert_eq!(wallet.get_balance().await.unwrap(), Balance::default());
+
+        wallet.wallet().unlock("hunter2").unwrap();
+        assert!(wallet.wallet().unified_full_viewing_key().is_ok());
+
+        drop(wallet);
+        timer.await.unwrap();
+    }
+}
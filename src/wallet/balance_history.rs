@@ -0,0 +1,185 @@
+//! Balance history recorded during sync
+//!
+//! Charting a treasury balance over time would otherwise mean replaying the
+//! whole transaction history. Instead, [`LightClient::sync`] records the
+//! wallet balance after every scanned batch, at the height and block time of
+//! the batch's last block, in a `numi_balance_history` table of the wallet
+//! database. See [`Wallet::balance_history`].
+//!
+//! Snapshots are taken per batch rather than per block, so a chart has one
+//! point per batch of blocks (100 by default); [`daily`] reduces them to one
+//! point per UTC day. Rescanning a range replaces its snapshots.
+//!
+//! [`LightClient::sync`]: crate::light_client::LightClient::sync
+
+use crate::db_encryption::open_connection;
+use crate::error::Result;
+use crate::store::db_error;
+use crate::types::Balance;
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Wallet balance as of a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceSnapshot {
+    pub height: u64,
+    /// Block time (Unix seconds)
+    pub time: u64,
+    pub balance: Balance,
+}
+
+const DB_CONTEXT: &str = "Balance history error";
+
+/// Balance snapshots stored in a wallet database
+pub struct BalanceHistory {
+    conn: Connection,
+}
+
+impl BalanceHistory {
+    /// Open (or create) the balance history in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_balance_history (
+                height INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL,
+                transparent INTEGER NOT NULL,
+                sapling INTEGER NOT NULL,
+                orchard INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    /// Open the balance history stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Record the balance as of a block, replacing any earlier snapshot at
+    /// that height
+    pub fn record(&self, height: u64, time: u64, balance: &Balance) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO numi_balance_history
+                 (height, time, transparent, sapling, orchard)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    height as i64,
+                    time as i64,
+                    balance.transparent as i64,
+                    balance.sapling as i64,
+                    balance.orchard as i64
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Snapshots in a height range, oldest first
+    pub fn range(&self, heights: RangeInclusive<u64>) -> Result<Vec<BalanceSnapshot>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT height, time, transparent, sapling, orchard
+                 FROM numi_balance_history
+                 WHERE height BETWEEN ?1 AND ?2
+                 ORDER BY height",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let (start, end) = (
+            (*heights.start()).min(i64::MAX as u64),
+            (*heights.end()).min(i64::MAX as u64),
+        );
+        let rows = stmt
+            .query_map(params![start as i64, end as i64], |row| {
+                let pool = |index| row.get::<_, i64>(index).map(|value| value as u64);
+                let (transparent, sapling, orchard) = (pool(2)?, pool(3)?, pool(4)?);
+                Ok(BalanceSnapshot {
+                    height: pool(0)?,
+                    time: pool(1)?,
+                    balance: Balance {
+                        transparent,
+                        sapling,
+                        orchard,
+                        total: transparent + sapling + orchard,
+                    },
+                })
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(rows)
+    }
+
+    /// Delete snapshots above `height`, e.g. after the wallet was rewound
+    ///
+    /// # Returns
+    /// Number of snapshots deleted
+    pub fn truncate_above(&self, height: u64) -> Result<usize> {
+        let height = height.min(i64::MAX as u64);
+        self.conn
+            .execute(
+                "DELETE FROM numi_balance_history WHERE height > ?1",
+                [height as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))
+    }
+}
+
+/// Reduce snapshots (oldest first) to the last snapshot of each UTC day
+pub fn daily(snapshots: &[BalanceSnapshot]) -> Vec<BalanceSnapshot> {
+    let mut days: Vec<BalanceSnapshot> = Vec::new();
+    for snapshot in snapshots {
+        match days.last_mut() {
+            Some(last) if last.time / SECONDS_PER_DAY == snapshot.time / SECONDS_PER_DAY => {
+                *last = snapshot.clone();
+            }
+            _ => days.push(snapshot.clone()),
+        }
+    }
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(sapling: u64, orchard: u64) -> Balance {
+        Balance {
+            transparent: 0,
+            sapling,
+            orchard,
+            total: sapling + orchard,
+        }
+    }
+
+    #[test]
+    fn test_record_and_range() {
+        let history = BalanceHistory::open(Path::new(":memory:")).unwrap();
+        history.record(100, 1_000, &balance(5, 0)).unwrap();
+        history.record(200, 2_000, &balance(5, 7)).unwrap();
+        history.record(300, 90_000, &balance(0, 9)).unwrap();
+        history.record(200, 2_000, &balance(5, 8)).unwrap();
+
+        let all = history.range(0..=u64::MAX).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[1].balance, balance(5, 8));
+        assert_eq!(history.range(150..=300).unwrap()[0].height, 200);
+
+        let days = daily(&all);
+        assert_eq!(
+            days.iter().map(|s| s.height).collect::<Vec<_>>(),
+            vec![200, 300]
+        );
+
+        assert_eq!(history.truncate_above(200).unwrap(), 1);
+        assert_eq!(history.range(0..=u64::MAX).unwrap().len(), 2);
+    }
+}
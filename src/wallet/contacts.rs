@@ -0,0 +1,312 @@
+//! Wallet contacts
+//!
+//! Contacts give recipient addresses a human-readable label, so payments can
+//! be addressed by label (see
+//! [`TransactionBuilder::send_to_contact`](crate::transaction::TransactionBuilder::send_to_contact)).
+//! They are stored in the wallet's SQLite database. Addresses are validated
+//! for the wallet's network when a contact is saved and again when it is
+//! resolved for a payment, and labels are unique ignoring case so a payment
+//! to "alice" cannot silently pick a different contact than "Alice".
+//!
+//! Unlike the [`AddressBook`](crate::address_book::AddressBook), which labels
+//! addresses and mirrors zcashd's labels, contacts are keyed by label and
+//! never leave the local database.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::params::NetworkParams;
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use zcash_keys::address::Address;
+
+/// Maximum contact label length in bytes
+const MAX_LABEL_LEN: usize = 64;
+
+/// A labelled recipient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub label: String,
+    pub address: String,
+    pub notes: Option<String>,
+    /// Unix time the contact was created
+    pub created_at: u64,
+    /// Unix time the contact was last changed
+    pub updated_at: u64,
+}
+
+const DB_CONTEXT: &str = "Contacts error";
+
+/// Contacts stored in a wallet database
+pub struct Contacts {
+    conn: Connection,
+    network: NetworkParams,
+}
+
+impl Contacts {
+    /// Open (or create) the contacts in the SQLite database at `path`
+    ///
+    /// # Arguments
+    /// * `path` - Database path
+    /// * `network` - Network contact addresses must belong to
+    pub fn open(path: &Path, network: NetworkParams) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_contacts (
+                label TEXT PRIMARY KEY COLLATE NOCASE,
+                address TEXT NOT NULL,
+                notes TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn, network })
+    }
+
+    /// Open the contacts stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path(), wallet.consensus_network())
+    }
+
+    /// Add a contact
+    ///
+    /// Fails if the label is empty, too long or already used (ignoring case),
+    /// or if the address is not valid for the wallet's network.
+    pub fn add(&self, label: &str, address: &str, notes: Option<&str>) -> Result<Contact> {
+        let label = validate_label(label)?;
+        self.validate_address(address)?;
+        if self.get(label)?.is_some() {
+            return Err(Error::InvalidParameter(format!(
+                "Contact '{}' already exists",
+                label
+            )));
+        }
+        let now = unix_now();
+        self.conn
+            .execute(
+                "INSERT INTO numi_contacts (label, address, notes, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4)",
+                params![label, address, notes, now as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(Contact {
+            label: label.to_string(),
+            address: address.to_string(),
+            notes: notes.map(str::to_string),
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    /// Change a contact's address
+    pub fn update_address(&self, label: &str, address: &str) -> Result<()> {
+        let label = label.trim();
+        self.validate_address(address)?;
+        self.update(
+            label,
+            "UPDATE numi_contacts SET address = ?2, updated_at = ?3 WHERE label = ?1",
+            params![label, address, unix_now() as i64],
+        )
+    }
+
+    /// Change a contact's notes
+    pub fn update_notes(&self, label: &str, notes: Option<&str>) -> Result<()> {
+        let label = label.trim();
+        self.update(
+            label,
+            "UPDATE numi_contacts SET notes = ?2, updated_at = ?3 WHERE label = ?1",
+            params![label, notes, unix_now() as i64],
+        )
+    }
+
+    /// Rename a contact
+    pub fn rename(&self, label: &str, new_label: &str) -> Result<()> {
+        let label = label.trim();
+        let new_label = validate_label(new_label)?;
+        if !new_label.eq_ignore_ascii_case(label) && self.get(new_label)?.is_some() {
+            return Err(Error::InvalidParameter(format!(
+                "Contact '{}' already exists",
+                new_label
+            )));
+        }
+        self.update(
+            label,
+            "UPDATE numi_contacts SET label = ?2, updated_at = ?3 WHERE label = ?1",
+            params![label, new_label, unix_now() as i64],
+        )
+    }
+
+    fn update(&self, label: &str, sql: &str, params: impl rusqlite::Params) -> Result<()> {
+        match self
+            .conn
+            .execute(sql, params)
+            .map_err(db_error(DB_CONTEXT))?
+        {
+            0 => Err(Error::InvalidParameter(format!(
+                "Unknown contact '{}'",
+                label
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove a contact
+    ///
+    /// # Returns
+    /// Whether the contact existed
+    pub fn remove(&self, label: &str) -> Result<bool> {
+        let label = label.trim();
+        let removed = self
+            .conn
+            .execute("DELETE FROM numi_contacts WHERE label = ?1", [label])
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(removed > 0)
+    }
+
+    /// Look up a contact by label (ignoring case)
+    pub fn get(&self, label: &str) -> Result<Option<Contact>> {
+        self.conn
+            .query_row(
+                "SELECT label, address, notes, created_at, updated_at
+                 FROM numi_contacts WHERE label = ?1",
+                [label.trim()],
+                row_to_contact,
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// Contacts with the given address
+    pub fn find_by_address(&self, address: &str) -> Result<Vec<Contact>> {
+        self.query(
+            "SELECT label, address, notes, created_at, updated_at
+             FROM numi_contacts WHERE address = ?1 ORDER BY label",
+            [address],
+        )
+    }
+
+    /// All contacts, ordered by label
+    pub fn list(&self) -> Result<Vec<Contact>> {
+        self.query(
+            "SELECT label, address, notes, created_at, updated_at
+             FROM numi_contacts ORDER BY label",
+            [],
+        )
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Contact>> {
+        let mut stmt = self.conn.prepare(sql).map_err(db_error(DB_CONTEXT))?;
+        let contacts = stmt
+            .query_map(params, row_to_contact)
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(contacts)
+    }
+
+    /// Resolve a label to a validated payment address
+    ///
+    /// Fails with [`Error::Address`] if there is no such contact or its
+    /// address is not valid for the wallet's network.
+    pub fn resolve(&self, label: &str) -> Result<String> {
+        let contact = self
+            .get(label)?
+            .ok_or_else(|| Error::Address(format!("Unknown contact '{}'", label.trim())))?;
+        self.validate_address(&contact.address)?;
+        Ok(contact.address)
+    }
+
+    fn validate_address(&self, address: &str) -> Result<()> {
+        Address::decode(&self.network, address)
+            .map(|_| ())
+            .ok_or_else(|| {
+                Error::Address(format!(
+                    "Address {} is not a valid address for this wallet's network",
+                    address
+                ))
+            })
+    }
+}
+
+fn validate_label(label: &str) -> Result<&str> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err(Error::InvalidParameter(
+            "Contact label must not be empty".to_string(),
+        ));
+    }
+    if label.len() > MAX_LABEL_LEN {
+        return Err(Error::InvalidParameter(format!(
+            "Contact label exceeds {} bytes",
+            MAX_LABEL_LEN
+        )));
+    }
+    Ok(label)
+}
+
+fn row_to_contact(row: &rusqlite::Row<'_>) -> rusqlite::Result<Contact> {
+    Ok(Contact {
+        label: row.get(0)?,
+        address: row.get(1)?,
+        notes: row.get(2)?,
+        created_at: row.get::<_, i64>(3)? as u64,
+        updated_at: row.get::<_, i64>(4)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn wallet() -> (TempDb, Wallet) {
+        let path = TempDb::new();
+        let wallet = Wallet::with_path_and_seed(path.to_path_buf(), Some(vec![3u8; 32])).unwrap();
+        (path, wallet)
+    }
+
+    #[test]
+    fn test_contacts_by_label() {
+        let (_db, wallet) = wallet();
+        let contacts = Contacts::for_wallet(&wallet).unwrap();
+        let address = wallet.get_unified_address().unwrap();
+
+        contacts.add(" Alice ", &address, Some("cafe")).unwrap();
+        assert!(contacts.add("alice", &address, None).is_err());
+        assert_eq!(contacts.resolve("ALICE").unwrap(), address);
+        assert_eq!(
+            contacts.find_by_address(&address).unwrap()[0].label,
+            "Alice"
+        );
+
+        contacts.rename("alice", "Alice Smith").unwrap();
+        assert!(contacts.get("Alice").unwrap().is_none());
+        assert!(contacts.resolve("alice smith").is_ok());
+        assert!(contacts.update_address("Bob", &address).is_err());
+
+        assert!(contacts.remove("Alice Smith").unwrap());
+        assert!(matches!(
+            contacts.resolve("Alice Smith"),
+            Err(Error::Address(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_addresses() {
+        let (_db, wallet) = wallet();
+        let contacts = Contacts::for_wallet(&wallet).unwrap();
+        assert!(contacts.add("Nobody", "not-an-address", None).is_err());
+        assert!(contacts
+            .add("", &wallet.get_unified_address().unwrap(), None)
+            .is_err());
+
+        // Mainnet wallet address is rejected by testnet contacts
+        let testnet = Contacts::open(wallet.db_path(), NetworkParams::Testnet).unwrap();
+        assert!(testnet
+            .add("Alice", &wallet.get_unified_address().unwrap(), None)
+            .is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Frozen notes
+//!
+//! Freezing a note or transparent output keeps it out of transactions the
+//! wallet builds itself, e.g. to hold funds under a compliance review or to
+//! keep a coin-control choice. Frozen outputs are stored in the wallet's
+//! SQLite database by the output that created them, and stay frozen until
+//! they are unfrozen, even across rescans.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::{db_error, unix_now};
+use crate::types::{NoteId, Pool};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::path::Path;
+
+const DB_CONTEXT: &str = "Frozen notes error";
+
+fn pool_name(pool: Pool) -> &'static str {
+    match pool {
+        Pool::Transparent => "transparent",
+        Pool::Sapling => "sapling",
+        Pool::Orchard => "orchard",
+    }
+}
+
+/// Frozen notes stored in a wallet database
+pub struct FrozenNotes {
+    conn: Connection,
+}
+
+impl FrozenNotes {
+    /// Open (or create) the frozen notes in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_frozen_notes (
+                pool TEXT NOT NULL,
+                txid TEXT NOT NULL,
+                output_index INTEGER NOT NULL,
+                frozen_at INTEGER NOT NULL,
+                PRIMARY KEY (pool, txid, output_index)
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    /// Open the frozen notes stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Freeze a note
+    ///
+    /// # Returns
+    /// Whether the note was not frozen already
+    pub fn freeze(&self, note: &NoteId) -> Result<bool> {
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO numi_frozen_notes (pool, txid, output_index, frozen_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    pool_name(note.pool),
+                    note.txid.to_ascii_lowercase(),
+                    note.output_index,
+                    unix_now() as i64
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(inserted > 0)
+    }
+
+    /// Unfreeze a note
+    ///
+    /// # Returns
+    /// Whether the note was frozen
+    pub fn unfreeze(&self, note: &NoteId) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute(
+                "DELETE FROM numi_frozen_notes
+                 WHERE pool = ?1 AND txid = ?2 AND output_index = ?3",
+                params![
+                    pool_name(note.pool),
+                    note.txid.to_ascii_lowercase(),
+                    note.output_index
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(removed > 0)
+    }
+
+    /// Every frozen note
+    pub fn list(&self) -> Result<HashSet<NoteId>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT pool, txid, output_index FROM numi_frozen_notes")
+            .map_err(db_error(DB_CONTEXT))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u32>(2)?,
+                ))
+            })
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        rows.into_iter()
+            .map(|(pool, txid, output_index)| {
+                let pool = match pool.as_str() {
+                    "transparent" => Pool::Transparent,
+                    "sapling" => Pool::Sapling,
+                    "orchard" => Pool::Orchard,
+                    other => {
+                        return Err(Error::Database(format!(
+                            "Unknown pool '{}' in frozen notes",
+                            other
+                        )))
+                    }
+                };
+                Ok(NoteId {
+                    pool,
+                    txid,
+                    output_index,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    #[test]
+    fn test_frozen_notes() {
+        let path = TempDb::new();
+        let store = FrozenNotes::open(&path).unwrap();
+        let note = NoteId {
+            pool: Pool::Orchard,
+            txid: "AB".repeat(32),
+            output_index: 1,
+        };
+        let utxo = NoteId {
+            pool: Pool::Transparent,
+            ..note.clone()
+        };
+
+        assert!(store.freeze(&note).unwrap());
+        assert!(!store.freeze(&note).unwrap());
+        assert!(store.freeze(&utxo).unwrap());
+        let frozen = store.list().unwrap();
+        assert_eq!(frozen.len(), 2);
+        assert!(frozen.contains(&NoteId {
+            txid: "ab".repeat(32),
+            ..note.clone()
+        }));
+
+        assert!(store.unfreeze(&note).unwrap());
+        assert!(!store.unfreeze(&note).unwrap());
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+}
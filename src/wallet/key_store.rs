@@ -0,0 +1,140 @@
+//! Encrypted key material in the wallet database
+//!
+//! The wallet database itself only holds viewing keys; the seed is supplied
+//! by the application every time a [`Wallet`] is opened. Services that would
+//! rather keep it next to the wallet store it in a [`KeyStore`], which never
+//! writes key material in the clear: every entry is sealed with a passphrase
+//! in the format of [`crate::backup`] with the magic `NUMIKST1`, in a
+//! `numi_sealed_keys` table. See [`Wallet::store_seed`] and
+//! [`Wallet::open_with_stored_seed`].
+
+use crate::backup::{seal, unseal};
+use crate::db_encryption::open_connection;
+use crate::error::Result;
+use crate::store::{db_error, unix_now};
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use secrecy::SecretVec;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"NUMIKST1";
+
+const DB_CONTEXT: &str = "Key store error";
+
+/// Passphrase-sealed secrets stored in a wallet database
+pub struct KeyStore {
+    conn: Connection,
+}
+
+impl KeyStore {
+    /// Open (or create) the key store in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_sealed_keys (
+                name TEXT PRIMARY KEY,
+                sealed BLOB NOT NULL,
+                updated_at INTEGER NOT NULL
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    /// Open the key store in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Seal `secret` with `passphrase` and store it under `name`, replacing
+    /// any previous entry
+    ///
+    /// Key stretching makes this take about a second.
+    pub fn store(&self, name: &str, secret: &[u8], passphrase: &str) -> Result<()> {
+        let sealed = seal(MAGIC, secret, passphrase, "stored key")?;
+        self.conn
+            .execute(
+                "INSERT INTO numi_sealed_keys (name, sealed, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(name) DO UPDATE SET
+                    sealed = excluded.sealed,
+                    updated_at = excluded.updated_at",
+                params![name, sealed, unix_now() as i64],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Unseal the secret stored under `name`
+    ///
+    /// # Returns
+    /// `None` if nothing is stored under `name`;
+    /// [`Error::Wallet`](crate::error::Error::Wallet) if the passphrase is
+    /// wrong
+    pub fn load(&self, name: &str, passphrase: &str) -> Result<Option<SecretVec<u8>>> {
+        let sealed: Option<Vec<u8>> = self
+            .conn
+            .query_row(
+                "SELECT sealed FROM numi_sealed_keys WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))?;
+        sealed
+            .map(|sealed| unseal(MAGIC, &sealed, passphrase, "stored key"))
+            .transpose()
+    }
+
+    /// Delete the secret stored under `name`
+    ///
+    /// # Returns
+    /// Whether an entry was deleted
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM numi_sealed_keys WHERE name = ?1", [name])
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(deleted > 0)
+    }
+
+    /// Names of the stored secrets
+    pub fn names(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM numi_sealed_keys ORDER BY name")
+            .map_err(db_error(DB_CONTEXT))?;
+        let names = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn test_store_and_load() {
+        let store = KeyStore::open(Path::new(":memory:")).unwrap();
+        assert!(store.load("seed", "hunter2").unwrap().is_none());
+
+        store.store("seed", &[7u8; 32], "hunter2").unwrap();
+        let sealed: Vec<u8> = store
+            .conn
+            .query_row("SELECT sealed FROM numi_sealed_keys", [], |row| row.get(0))
+            .unwrap();
+        assert!(!sealed.windows(32).any(|w| w == [7u8; 32]));
+
+        let loaded = store.load("seed", "hunter2").unwrap().unwrap();
+        assert_eq!(loaded.expose_secret(), &vec![7u8; 32]);
+        assert!(matches!(store.load("seed", "wrong"), Err(Error::Wallet(_))));
+        assert_eq!(store.names().unwrap(), vec!["seed"]);
+        assert!(store.remove("seed").unwrap());
+        assert!(store.names().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! Wallet locking
+//!
+//! A long-running service keeps its wallet open for days, and with it the
+//! seed in plain memory. Locking drops the seed and mnemonic (their buffers
+//! are zeroized) and keeps only a copy encrypted with a lock passphrase,
+//! using the sealed format of [`crate::backup`] with the magic `NUMILOCK`.
+//! While locked, everything that needs the seed — deriving keys and
+//! addresses, signing, exporting backups — fails with [`Error::Wallet`];
+//! queries answered from the wallet database keep working.
+//!
+//! With an auto-lock timeout the wallet locks itself once the seed has not
+//! been used for that long: lazily on the next access, or from a timer via
+//! [`Wallet::lock_if_idle`](crate::wallet::Wallet::lock_if_idle) (see
+//! [`AsyncWallet::spawn_auto_lock`](crate::wallet::async_wallet::AsyncWallet::spawn_auto_lock)).
+
+use crate::backup::{seal, unseal};
+use crate::error::{Error, Result};
+use secrecy::{ExposeSecret, SecretString, SecretVec};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 8] = b"NUMILOCK";
+
+struct VaultState {
+    seed: Option<SecretVec<u8>>,
+    /// ZIP-339 phrase the seed was derived from, if known
+    mnemonic: Option<SecretString>,
+    /// Seed and mnemonic encrypted with the lock passphrase
+    sealed: Option<Vec<u8>>,
+    auto_lock: Option<Duration>,
+    last_used: Instant,
+}
+
+impl VaultState {
+    /// Whether the auto-lock timeout has passed; never without a lock
+    /// passphrase, since locking would lose the seed
+    fn is_idle(&self) -> bool {
+        self.sealed.is_some()
+            && self
+                .auto_lock
+                .is_some_and(|timeout| self.last_used.elapsed() >= timeout)
+    }
+
+    fn lock(&mut self) {
+        // Dropping the secrets zeroizes them
+        self.seed = None;
+        self.mnemonic = None;
+    }
+}
+
+/// Holds the wallet seed and locks it away on request
+pub(crate) struct SeedVault {
+    state: Mutex<VaultState>,
+}
+
+impl SeedVault {
+    pub(crate) fn new(seed: SecretVec<u8>, mnemonic: Option<SecretString>) -> Self {
+        Self {
+            state: Mutex::new(VaultState {
+                seed: Some(seed),
+                mnemonic,
+                sealed: None,
+                auto_lock: None,
+                last_used: Instant::now(),
+            }),
+        }
+    }
+
+    /// Vault without a seed, for wallets whose seed is held by a
+    /// [`KeyProvider`](crate::key_provider::KeyProvider)
+    pub(crate) fn empty() -> Self {
+        Self {
+            state: Mutex::new(VaultState {
+                seed: None,
+                mnemonic: None,
+                sealed: None,
+                auto_lock: None,
+                last_used: Instant::now(),
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, VaultState> {
+        // The state is consistent after every assignment, so a panic in
+        // another thread cannot leave it half-updated
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Unlocked state, applying the auto-lock timeout first
+    fn unlocked(&self) -> Result<MutexGuard<'_, VaultState>> {
+        let mut state = self.state();
+        if state.is_idle() {
+            state.lock();
+        }
+        if state.seed.is_none() {
+            return Err(Error::Wallet("Wallet is locked".to_string()));
+        }
+        state.last_used = Instant::now();
+        Ok(state)
+    }
+
+    /// A copy of the seed, zeroized when dropped
+    pub(crate) fn seed(&self) -> Result<SecretVec<u8>> {
+        let state = self.unlocked()?;
+        let seed = state.seed.as_ref().expect("checked by unlocked");
+        Ok(SecretVec::new(seed.expose_secret().clone()))
+    }
+
+    /// The mnemonic phrase, or `None` for wallets created from raw seeds
+    pub(crate) fn mnemonic(&self) -> Result<Option<SecretString>> {
+        let state = self.unlocked()?;
+        Ok(state
+            .mnemonic
+            .as_ref()
+            .map(|phrase| SecretString::new(phrase.expose_secret().clone())))
+    }
+
+    /// Seed and mnemonic encoded for sealing: the seed length as one byte,
+    /// the seed, then the mnemonic phrase (if any)
+    pub(crate) fn encode_secrets(&self) -> Result<SecretVec<u8>> {
+        let state = self.unlocked()?;
+        let seed = state.seed.as_ref().expect("checked by unlocked");
+        let mut plaintext = Vec::with_capacity(1 + seed.expose_secret().len());
+        plaintext.push(seed.expose_secret().len() as u8);
+        plaintext.extend_from_slice(seed.expose_secret());
+        if let Some(phrase) = &state.mnemonic {
+            plaintext.extend_from_slice(phrase.expose_secret().as_bytes());
+        }
+        Ok(SecretVec::new(plaintext))
+    }
+
+    pub(crate) fn set_passphrase(&self, passphrase: &str) -> Result<()> {
+        let plaintext = self.encode_secrets()?;
+        // Key stretching is slow; do it without holding the lock
+        let sealed = seal(MAGIC, plaintext.expose_secret(), passphrase, "lock")?;
+        self.state().sealed = Some(sealed);
+        Ok(())
+    }
+
+    pub(crate) fn lock(&self) -> Result<()> {
+        let mut state = self.state();
+        if state.sealed.is_none() {
+            return Err(Error::Wallet(
+                "Set a lock passphrase before locking the wallet".to_string(),
+            ));
+        }
+        state.lock();
+        Ok(())
+    }
+
+    pub(crate) fn unlock(&self, passphrase: &str) -> Result<()> {
+        let sealed = self
+            .state()
+            .sealed
+            .clone()
+            .ok_or_else(|| Error::Wallet("Wallet has no lock passphrase".to_string()))?;
+        let plaintext = unseal(MAGIC, &sealed, passphrase, "lock")?;
+        let (seed, mnemonic) = decode_secrets(plaintext.expose_secret())?;
+
+        let mut state = self.state();
+        state.seed = Some(seed);
+        state.mnemonic = mnemonic;
+        state.last_used = Instant::now();
+        Ok(())
+    }
+
+    pub(crate) fn is_locked(&self) -> bool {
+        let state = self.state();
+        state.seed.is_none() || state.is_idle()
+    }
+
+    pub(crate) fn set_auto_lock(&self, timeout: Option<Duration>) {
+        let mut state = self.state();
+        state.auto_lock = timeout;
+        state.last_used = Instant::now();
+    }
+
+    pub(crate) fn lock_if_idle(&self) -> bool {
+        let mut state = self.state();
+        if state.seed.is_some() && state.is_idle() {
+            state.lock();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Decode the output of [`SeedVault::encode_secrets`]
+pub(crate) fn decode_secrets(plaintext: &[u8]) -> Result<(SecretVec<u8>, Option<SecretString>)> {
+    let seed_len = usize::from(*plaintext.first().unwrap_or(&0));
+    if seed_len == 0 || plaintext.len() < 1 + seed_len {
+        return Err(Error::Wallet("Corrupt sealed seed".to_string()));
+    }
+    let mnemonic = std::str::from_utf8(&plaintext[1 + seed_len..])
+        .map_err(|_| Error::Wallet("Corrupt sealed mnemonic".to_string()))?;
+    Ok((
+        SecretVec::new(plaintext[1..1 + seed_len].to_vec()),
+        (!mnemonic.is_empty()).then(|| SecretString::new(mnemonic.to_string())),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_and_unlock() {
+        let vault = SeedVault::new(
+            SecretVec::new(vec![3u8; 32]),
+            Some(SecretString::new("a b c".to_string())),
+        );
+        assert!(!vault.is_locked());
+        // Locking without a passphrase would lose the seed
+        assert!(vault.lock().is_err());
+
+        vault.set_passphrase("hunter2").unwrap();
+        vault.lock().unwrap();
+        assert!(vault.is_locked());
+        assert!(matches!(vault.seed(), Err(Error::Wallet(_))));
+        assert!(vault.mnemonic().is_err());
+
+        assert!(vault.unlock("wrong").is_err());
+        vault.unlock("hunter2").unwrap();
+        assert_eq!(vault.seed().unwrap().expose_secret(), &vec![3u8; 32]);
+        assert_eq!(vault.mnemonic().unwrap().unwrap().expose_secret(), "a b c");
+    }
+
+    #[test]
+    fn test_auto_lock() {
+        let vault = SeedVault::new(SecretVec::new(vec![3u8; 32]), None);
+        vault.set_passphrase("hunter2").unwrap();
+        vault.set_auto_lock(Some(Duration::from_secs(3600)));
+        assert!(!vault.lock_if_idle());
+        assert!(vault.seed().is_ok());
+
+        vault.set_auto_lock(Some(Duration::ZERO));
+        assert!(vault.is_locked());
+        assert!(vault.lock_if_idle());
+        vault.unlock("hunter2").unwrap();
+        // Still idle immediately: the timeout applies on every access
+        assert!(vault.seed().is_err());
+
+        vault.set_auto_lock(None);
+        vault.unlock("hunter2").unwrap();
+        assert!(vault.mnemonic().unwrap().is_none());
+    }
+}
@@ -0,0 +1,176 @@
+//! Full-text index of memos
+//!
+//! Invoicing systems put order IDs and references in memos and need to find
+//! the transaction carrying one. Scanning and decoding every memo for each
+//! lookup gets slow as the history grows, so the text memos of the wallet's
+//! outputs are copied into an SQLite FTS5 table in the wallet database.
+//! The index is brought up to date with [`MemoIndex::refresh`] before each
+//! search; memos decrypted since the last refresh are added then.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::store::db_error;
+use crate::wallet::{memo_text, Wallet};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const DB_CONTEXT: &str = "Memo index error";
+
+/// Full-text index of the memos in a wallet database
+pub struct MemoIndex {
+    conn: Connection,
+}
+
+impl MemoIndex {
+    /// Open (or create) the memo index in the SQLite database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_memo_outputs (
+                id INTEGER PRIMARY KEY,
+                txid BLOB NOT NULL,
+                output_pool INTEGER NOT NULL,
+                output_index INTEGER NOT NULL,
+                UNIQUE (txid, output_pool, output_index)
+            );
+            CREATE VIRTUAL TABLE IF NOT EXISTS numi_memo_search USING fts5(memo);",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self { conn })
+    }
+
+    /// Open the memo index stored in the wallet's database
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        Self::open(wallet.db_path())
+    }
+
+    /// Index the text memos added to the wallet database since the last
+    /// refresh
+    ///
+    /// # Returns
+    /// The number of memos indexed
+    pub fn refresh(&mut self) -> Result<usize> {
+        let tx = self.conn.transaction().map_err(db_error(DB_CONTEXT))?;
+        let outputs = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT o.txid, o.output_pool, o.output_index, o.memo FROM v_tx_outputs o
+                     WHERE o.memo IS NOT NULL
+                       AND NOT EXISTS (SELECT 1 FROM numi_memo_outputs m
+                                       WHERE m.txid = o.txid AND m.output_pool = o.output_pool
+                                         AND m.output_index = o.output_index)",
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, Vec<u8>>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, Vec<u8>>(3)?,
+                    ))
+                })
+                .map_err(db_error(DB_CONTEXT))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(db_error(DB_CONTEXT))?
+        };
+
+        let mut indexed = 0;
+        for (txid, pool, index, memo) in outputs {
+            // Outputs are recorded even without text, so they are read once
+            tx.execute(
+                "INSERT INTO numi_memo_outputs (txid, output_pool, output_index)
+                 VALUES (?1, ?2, ?3)",
+                params![txid, pool, index],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+            if let Some(text) = memo_text(&memo) {
+                tx.execute(
+                    "INSERT INTO numi_memo_search (rowid, memo) VALUES (?1, ?2)",
+                    params![tx.last_insert_rowid(), text],
+                )
+                .map_err(db_error(DB_CONTEXT))?;
+                indexed += 1;
+            }
+        }
+        tx.commit().map_err(db_error(DB_CONTEXT))?;
+        Ok(indexed)
+    }
+}
+
+/// Turn a search string into an FTS5 query matching all of its words
+///
+/// Each whitespace-separated word is quoted, so punctuation in order IDs
+/// such as `INV-2024-117` is matched literally instead of being read as
+/// query syntax.
+pub(crate) fn match_query(query: &str) -> Result<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        return Err(Error::InvalidParameter(
+            "Memo search query is empty".to_string(),
+        ));
+    }
+    Ok(words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn text_memo(text: &str) -> Vec<u8> {
+        let mut memo = text.as_bytes().to_vec();
+        memo.resize(512, 0);
+        memo
+    }
+
+    #[test]
+    fn test_memo_index() {
+        let path = TempDb::new();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE v_tx_outputs (txid BLOB, output_pool INTEGER,
+                                        output_index INTEGER, memo BLOB);",
+        )
+        .unwrap();
+        let insert = |txid: u8, index: i64, memo: Option<Vec<u8>>| {
+            conn.execute(
+                "INSERT INTO v_tx_outputs VALUES (?1, 3, ?2, ?3)",
+                params![vec![txid], index, memo],
+            )
+            .unwrap();
+        };
+        insert(1, 0, Some(text_memo("Order INV-2024-117 paid")));
+        insert(2, 0, Some(text_memo("Refund for order INV-2024-118")));
+        insert(2, 1, None);
+
+        let mut index = MemoIndex::open(&path).unwrap();
+        assert_eq!(index.refresh().unwrap(), 2);
+        assert_eq!(index.refresh().unwrap(), 0);
+        insert(3, 0, Some(text_memo("inv-2024-117 second instalment")));
+        insert(3, 1, Some(vec![0xF6]));
+        assert_eq!(index.refresh().unwrap(), 1);
+
+        let search = |query: &str| -> Vec<Vec<u8>> {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT m.txid FROM numi_memo_search s
+                     JOIN numi_memo_outputs m ON m.id = s.rowid
+                     WHERE numi_memo_search MATCH ?1 ORDER BY m.txid",
+                )
+                .unwrap();
+            stmt.query_map([match_query(query).unwrap()], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap()
+        };
+        assert_eq!(search("INV-2024-117"), vec![vec![1], vec![3]]);
+        assert_eq!(search("order INV-2024-118"), vec![vec![2]]);
+        assert_eq!(search("INV-2024").len(), 3);
+        assert!(search("\"unbalanced").is_empty());
+        assert!(match_query("  ").is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! Message signing and address ownership attestations
+//!
+//! Transparent addresses sign messages the way zcashd's `signmessage` does:
+//! a compact recoverable secp256k1 signature over the double SHA-256 of
+//! `"Zcash Signed Message:\n"` and the message, base64 encoded. Signatures
+//! made by zcashd verify here and vice versa.
+//!
+//! Shielded addresses have no message signing standard. An
+//! [`AddressAttestation`] instead proves control of a unified address with
+//! an Orchard receiver: it carries the Orchard full viewing key, which the
+//! verifier uses to check that the address belongs to it, and a RedPallas
+//! signature by the spend authorizing key over the address and message.
+//! **The full viewing key reveals every Orchard transaction of the
+//! account**; only hand attestations to parties that may see them, such as
+//! auditors or an exchange's compliance team.
+
+use crate::error::{Error, Result};
+use crate::params::NetworkParams;
+use crate::types::Network;
+use crate::wallet::transparent::TransparentChain;
+use crate::wallet::{consensus_network, Wallet};
+use base64::Engine;
+use orchard::keys::{FullViewingKey, SpendAuthorizingKey};
+use orchard::primitives::redpallas::{Signature, SpendAuth, VerificationKey};
+use pasta_curves::group::ff::Field;
+use pasta_curves::pallas;
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zcash_keys::address::Address;
+use zcash_keys::encoding::AddressCodec;
+use zcash_transparent::address::TransparentAddress;
+use zcash_transparent::keys::{pubkey_to_address, NonHardenedChildIndex, TransparentKeyScope};
+
+/// Prefix zcashd hashes in front of signed messages
+const MESSAGE_MAGIC: &str = "Zcash Signed Message:\n";
+
+/// Domain separator of address attestation signatures
+const ATTESTATION_DOMAIN: &str = "Zcash Numi Address Attestation:\n";
+
+/// Number of transparent child indices searched per chain for the key of an
+/// address that was never handed out
+const TRANSPARENT_KEY_GAP: u32 = 20;
+
+/// Compact-size length prefix followed by the bytes, as zcashd serializes
+/// strings
+fn write_var_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    let len = bytes.len() as u64;
+    if len < 0xfd {
+        out.push(len as u8);
+    } else if len <= 0xffff {
+        out.push(0xfd);
+        out.extend_from_slice(&(len as u16).to_le_bytes());
+    } else if len <= 0xffff_ffff {
+        out.push(0xfe);
+        out.extend_from_slice(&(len as u32).to_le_bytes());
+    } else {
+        out.push(0xff);
+        out.extend_from_slice(&len.to_le_bytes());
+    }
+    out.extend_from_slice(bytes);
+}
+
+/// Double SHA-256 of the magic-prefixed message
+fn message_digest(message: &str) -> Message {
+    let mut preimage = Vec::new();
+    write_var_bytes(&mut preimage, MESSAGE_MAGIC.as_bytes());
+    write_var_bytes(&mut preimage, message.as_bytes());
+    let digest: [u8; 32] = Sha256::digest(Sha256::digest(&preimage)).into();
+    Message::from_digest(digest)
+}
+
+/// Sign a message with a transparent secret key (zcashd `signmessage` format)
+///
+/// # Returns
+/// The base64 encoded compact signature
+pub fn sign_transparent_message(secret_key: &SecretKey, message: &str) -> String {
+    let secp = Secp256k1::signing_only();
+    let (recovery_id, compact) = secp
+        .sign_ecdsa_recoverable(&message_digest(message), secret_key)
+        .serialize_compact();
+    let mut signature = Vec::with_capacity(65);
+    // 27 + recovery ID, + 4 for a compressed public key
+    signature.push(31 + recovery_id.to_i32() as u8);
+    signature.extend_from_slice(&compact);
+    base64::engine::general_purpose::STANDARD.encode(signature)
+}
+
+/// Verify a message signature of a transparent P2PKH address
+///
+/// Accepts signatures made with compressed public keys, which is what
+/// zcashd and this SDK produce.
+///
+/// # Returns
+/// Whether the signature was made by the address's key; malformed
+/// signatures and addresses are errors
+pub fn verify_transparent_message(
+    address: &str,
+    signature: &str,
+    message: &str,
+    network: Network,
+) -> Result<bool> {
+    let params = consensus_network(network);
+    let address = TransparentAddress::decode(&params, address)
+        .map_err(|e| Error::Address(format!("Invalid transparent address: {:?}", e)))?;
+    if !matches!(address, TransparentAddress::PublicKeyHash(_)) {
+        return Err(Error::Address(
+            "Only P2PKH addresses can sign messages".to_string(),
+        ));
+    }
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(signature.trim())
+        .map_err(|e| Error::InvalidParameter(format!("Invalid signature encoding: {}", e)))?;
+    if bytes.len() != 65 {
+        return Err(Error::InvalidParameter(format!(
+            "Signature must be 65 bytes, got {}",
+            bytes.len()
+        )));
+    }
+    let header = bytes[0];
+    if !(31..=34).contains(&header) {
+        return Err(Error::InvalidParameter(format!(
+            "Unsupported signature header {} (only compressed keys are supported)",
+            header
+        )));
+    }
+    let recovery_id = RecoveryId::from_i32(i32::from(header - 31))
+        .map_err(|e| Error::InvalidParameter(format!("Invalid recovery ID: {}", e)))?;
+    let signature = RecoverableSignature::from_compact(&bytes[1..], recovery_id)
+        .map_err(|e| Error::InvalidParameter(format!("Invalid signature: {}", e)))?;
+
+    Ok(Secp256k1::verification_only()
+        .recover_ecdsa(&message_digest(message), &signature)
+        .is_ok_and(|public_key| pubkey_to_address(&public_key) == address))
+}
+
+/// Signed proof of control of a shielded address
+///
+/// See the [module documentation](self) for what it discloses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressAttestation {
+    /// Unified address whose Orchard receiver is attested
+    pub address: String,
+    /// Statement being signed, e.g. a challenge from the verifier
+    pub message: String,
+    /// Orchard full viewing key the address belongs to (hex)
+    pub full_viewing_key: String,
+    /// RedPallas signature by the spend authorizing key (hex)
+    pub signature: String,
+}
+
+impl AddressAttestation {
+    /// Create an attestation with an Orchard spending key
+    ///
+    /// Fails if `address` does not have an Orchard receiver derived from
+    /// the key.
+    pub fn sign(
+        spending_key: &orchard::keys::SpendingKey,
+        address: &str,
+        message: &str,
+        network: Network,
+    ) -> Result<Self> {
+        let fvk = FullViewingKey::from(spending_key);
+        let receiver = orchard_receiver(address, consensus_network(network))?;
+        if fvk.scope_for_address(&receiver).is_none() {
+            return Err(Error::Address(format!(
+                "Address {} does not belong to this wallet",
+                address
+            )));
+        }
+        let signing_key = SpendAuthorizingKey::from(spending_key).randomize(&pallas::Scalar::ZERO);
+        let signature =
+            signing_key.sign(rand::rngs::OsRng, &attestation_preimage(address, message));
+        Ok(Self {
+            address: address.to_string(),
+            message: message.to_string(),
+            full_viewing_key: hex::encode(fvk.to_bytes()),
+            signature: hex::encode(<[u8; 64]>::from(&signature)),
+        })
+    }
+
+    /// Check that the address derives from the full viewing key and the
+    /// signature is valid
+    pub fn verify(&self, network: Network) -> Result<bool> {
+        let fvk_bytes: [u8; 96] = hex::decode(&self.full_viewing_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| Error::InvalidParameter("Invalid full viewing key".to_string()))?;
+        let fvk = FullViewingKey::from_bytes(&fvk_bytes)
+            .ok_or_else(|| Error::InvalidParameter("Invalid full viewing key".to_string()))?;
+        let signature: [u8; 64] = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| Error::InvalidParameter("Invalid signature encoding".to_string()))?;
+
+        let receiver = orchard_receiver(&self.address, consensus_network(network))?;
+        if fvk.scope_for_address(&receiver).is_none() {
+            return Ok(false);
+        }
+        // The first 32 bytes of the full viewing key are the spend validating key
+        let ak: [u8; 32] = fvk_bytes[..32].try_into().expect("32 bytes");
+        let verification_key = VerificationKey::<SpendAuth>::try_from(ak)
+            .map_err(|_| Error::InvalidParameter("Invalid spend validating key".to_string()))?;
+        Ok(verification_key
+            .verify(
+                &attestation_preimage(&self.address, &self.message),
+                &Signature::<SpendAuth>::from(signature),
+            )
+            .is_ok())
+    }
+}
+
+fn attestation_preimage(address: &str, message: &str) -> Vec<u8> {
+    let mut preimage = Vec::new();
+    write_var_bytes(&mut preimage, ATTESTATION_DOMAIN.as_bytes());
+    write_var_bytes(&mut preimage, address.as_bytes());
+    write_var_bytes(&mut preimage, message.as_bytes());
+    preimage
+}
+
+fn orchard_receiver(address: &str, params: NetworkParams) -> Result<orchard::Address> {
+    match Address::decode(&params, address) {
+        Some(Address::Unified(ua)) => ua
+            .orchard()
+            .copied()
+            .ok_or_else(|| Error::Address("Unified address has no Orchard receiver".to_string())),
+        Some(_) => Err(Error::Address(
+            "Attestations require a unified address with an Orchard receiver".to_string(),
+        )),
+        None => Err(Error::Address(format!("Invalid address: {}", address))),
+    }
+}
+
+/// Message signing
+impl Wallet {
+    /// Sign a message with the key of one of the wallet's transparent
+    /// addresses (zcashd `signmessage` format)
+    ///
+    /// # Arguments
+    /// * `address` - Transparent address of the selected account
+    /// * `message` - Message to sign
+    ///
+    /// # Returns
+    /// The base64 encoded signature
+    pub fn sign_message(&self, address: &str, message: &str) -> Result<String> {
+        let secret_key = self.transparent_secret_key(address)?;
+        Ok(sign_transparent_message(&secret_key, message))
+    }
+
+    /// Verify a transparent message signature on the wallet's network
+    pub fn verify_message(&self, address: &str, signature: &str, message: &str) -> Result<bool> {
+        verify_transparent_message(address, signature, message, self.network())
+    }
+
+    /// Attest control of one of the wallet's unified addresses
+    ///
+    /// The attestation discloses the account's Orchard full viewing key;
+    /// see [`AddressAttestation`].
+    pub fn attest_address(&self, address: &str, message: &str) -> Result<AddressAttestation> {
+        let usk = self.get_unified_spending_key()?;
+        AddressAttestation::sign(usk.orchard(), address, message, self.network())
+    }
+
+    /// Secret key of a transparent address of the selected account
+    ///
+    /// Searches the addresses handed out or seen during sync, then the first
+    /// [`TRANSPARENT_KEY_GAP`] indices of each chain.
+    fn transparent_secret_key(&self, address: &str) -> Result<SecretKey> {
+        let usk = self.get_unified_spending_key()?;
+        let account_key = usk.transparent();
+        let mut candidates: Vec<(TransparentChain, u32)> = self
+            .transparent_addresses()?
+            .into_iter()
+            .filter(|info| info.address == address)
+            .map(|info| (info.chain, info.index))
+            .collect();
+        for index in 0..TRANSPARENT_KEY_GAP {
+            for chain in TransparentChain::ALL {
+                candidates.push((chain, index));
+            }
+        }
+
+        let params = self.consensus_network();
+        let secp = Secp256k1::signing_only();
+        for (chain, index) in candidates {
+            let child = NonHardenedChildIndex::from_index(index)
+                .ok_or_else(|| Error::KeyDerivation(format!("Invalid child index {}", index)))?;
+            let secret_key = match chain {
+                TransparentChain::External => account_key.derive_external_secret_key(child),
+                TransparentChain::Internal => account_key.derive_internal_secret_key(child),
+                TransparentChain::Ephemeral => {
+                    account_key.derive_secret_key(TransparentKeyScope::EPHEMERAL, child)
+                }
+            }
+            .map_err(|e| {
+                Error::KeyDerivation(format!("Failed to derive transparent key: {:?}", e))
+            })?;
+            let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+            if pubkey_to_address(&public_key).encode(&params) == address {
+                return Ok(secret_key);
+            }
+        }
+        Err(Error::Address(format!(
+            "Address {} is not a transparent address of this wallet",
+            address
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transparent_message_signature() {
+        let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        let address = pubkey_to_address(&public_key).encode(&NetworkParams::Mainnet);
+        assert!(address.starts_with("t1"));
+
+        let signature = sign_transparent_message(&secret_key, "I control this address");
+        assert!(verify_transparent_message(
+            &address,
+            &signature,
+            "I control this address",
+            Network::Mainnet
+        )
+        .unwrap());
+        assert!(!verify_transparent_message(
+            &address,
+            &signature,
+            "Something else",
+            Network::Mainnet
+        )
+        .unwrap());
+        assert!(verify_transparent_message(&address, "AAAA", "x", Network::Mainnet).is_err());
+    }
+
+    #[test]
+    fn test_wallet_message_signing() {
+        let wallet = Wallet::ephemeral(Network::Mainnet).unwrap();
+        let address = wallet.get_transparent_address().unwrap();
+        let signature = wallet.sign_message(&address, "hello").unwrap();
+        assert!(wallet
+            .verify_message(&address, &signature, "hello")
+            .unwrap());
+
+        let unified = wallet.get_unified_address().unwrap();
+        let attestation = wallet.attest_address(&unified, "challenge 1234").unwrap();
+        assert!(attestation.verify(Network::Mainnet).unwrap());
+
+        let mut forged = attestation.clone();
+        forged.message = "challenge 5678".to_string();
+        assert!(!forged.verify(Network::Mainnet).unwrap());
+
+        let other = Wallet::ephemeral(Network::Mainnet).unwrap();
+        assert!(other.attest_address(&unified, "challenge").is_err());
+        assert!(other.sign_message(&address, "hello").is_err());
+    }
+}
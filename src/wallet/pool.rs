@@ -0,0 +1,600 @@
+//! Shared wallet database connections
+//!
+//! Opening a [`WalletDb`] used to open a new SQLite connection and run the
+//! schema initialization and migrations every time, including for every
+//! balance query made while a sync was writing to the same file. A
+//! [`WalletDbPool`] initializes the schema once and then hands out:
+//! - read handles ([`WalletDbPool::read`]) backed by a small pool of
+//!   query-only connections, which can be used concurrently with a sync
+//! - the single write handle ([`WalletDbPool::write`]), whose connection is
+//!   shared and serialized by a mutex
+//!
+//! The same connections serve SQL the wallet database traits do not cover,
+//! through [`WalletDbPool::connection`].
+//!
+//! The database is switched to WAL journaling on initialization, so readers
+//! see the last committed state instead of waiting for the writer.
+//!
+//! Schema migrations are explicit. A new database is created on first use,
+//! but an existing one is only migrated by [`WalletDbPool::migrate`]; until
+//! then [`initialize`](WalletDbPool::initialize) fails instead of stalling
+//! on a long migration. A database needs migration when its
+//! `schemer_migrations` table lacks a migration the linked
+//! `zcash_client_sqlite` knows; the known migrations are listed once per
+//! process by creating a schema in memory. The SDK version that last
+//! migrated the database is recorded in a `numi_schema` table.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::params::NetworkParams;
+use crate::store::{db_error, unix_now};
+use rand::rngs::ThreadRng;
+use rand::thread_rng;
+use rusqlite::Connection;
+use secrecy::SecretVec;
+use std::borrow::{Borrow, BorrowMut};
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+use zcash_client_sqlite::{util::SystemClock, wallet::init::init_wallet_db, WalletDb};
+
+/// Idle read connections kept open for reuse
+pub const MAX_IDLE_READERS: usize = 4;
+
+/// How long a connection waits for a lock held by another connection
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wallet database handle backed by a pooled read connection
+pub type ReadWalletDb = WalletDb<PooledConnection, NetworkParams, SystemClock, ThreadRng>;
+
+/// Wallet database handle backed by the pool's write connection
+pub type WriteWalletDb<'a> =
+    WalletDb<WriteConnection<'a>, NetworkParams, SystemClock, ThreadRng>;
+
+/// How often migration progress is reported
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// SDK version recorded after a successful migration
+const SDK_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const DB_CONTEXT: &str = "Failed to open wallet database";
+
+/// Schema state of a wallet database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaVersion {
+    /// Number of wallet schema migrations applied to the database
+    pub applied_migrations: usize,
+    /// SDK version that last migrated the database, if any
+    pub migrated_by: Option<String>,
+}
+
+/// Progress of a running schema migration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationProgress {
+    /// Migrations applied so far, including those applied before this run
+    pub applied_migrations: usize,
+    pub elapsed: Duration,
+    /// Whether the migration has finished
+    pub done: bool,
+}
+
+fn has_table(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+}
+
+fn schema_version(conn: &Connection) -> rusqlite::Result<SchemaVersion> {
+    let applied_migrations = if has_table(conn, "schemer_migrations")? {
+        conn.query_row("SELECT COUNT(*) FROM schemer_migrations", [], |row| {
+            row.get::<_, i64>(0)
+        })? as usize
+    } else {
+        0
+    };
+    let migrated_by = if has_table(conn, "numi_schema")? {
+        conn.query_row(
+            "SELECT sdk_version FROM numi_schema WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )
+        .ok()
+    } else {
+        None
+    };
+    Ok(SchemaVersion {
+        applied_migrations,
+        migrated_by,
+    })
+}
+
+/// IDs of the wallet schema migrations applied to a database
+fn applied_migrations(conn: &Connection) -> rusqlite::Result<HashSet<String>> {
+    if !has_table(conn, "schemer_migrations")? {
+        return Ok(HashSet::new());
+    }
+    let mut stmt = conn.prepare("SELECT hex(id) FROM schemer_migrations")?;
+    let ids = stmt.query_map([], |row| row.get(0))?.collect();
+    ids
+}
+
+/// IDs of every wallet schema migration the linked `zcash_client_sqlite`
+/// knows, read from a schema created in memory once per process
+fn known_migrations(network: NetworkParams) -> Result<&'static HashSet<String>> {
+    static KNOWN: OnceLock<HashSet<String>> = OnceLock::new();
+    if let Some(known) = KNOWN.get() {
+        return Ok(known);
+    }
+    let mut conn = Connection::open_in_memory().map_err(db_error(DB_CONTEXT))?;
+    init_schema(&mut conn, network, None)?;
+    let known = applied_migrations(&conn).map_err(db_error(DB_CONTEXT))?;
+    Ok(KNOWN.get_or_init(|| known))
+}
+
+/// Whether a database has a schema with migrations still to apply
+///
+/// A database without a schema does not count: it is created on first use.
+fn needs_migration(conn: &Connection, network: NetworkParams) -> Result<bool> {
+    let applied = applied_migrations(conn).map_err(db_error(DB_CONTEXT))?;
+    Ok(!applied.is_empty() && !known_migrations(network)?.is_subset(&applied))
+}
+
+/// Create or migrate the wallet schema
+fn init_schema(conn: &mut Connection, network: NetworkParams, seed: Option<&[u8]>) -> Result<()> {
+    let mut wallet_db = WalletDb::from_connection(conn, network, SystemClock, thread_rng());
+    let seed = seed.map(|seed| SecretVec::new(seed.to_vec()));
+    init_wallet_db(&mut wallet_db, seed)
+        .map_err(|e| Error::Database(format!("Failed to migrate wallet database: {}", e)))
+}
+
+fn record_migration(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS numi_schema (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            sdk_version TEXT NOT NULL,
+            migrated_at INTEGER NOT NULL
+        );",
+    )?;
+    conn.execute(
+        "INSERT INTO numi_schema (id, sdk_version, migrated_at) VALUES (1, ?1, ?2)
+         ON CONFLICT(id) DO UPDATE SET sdk_version = ?1, migrated_at = ?2",
+        rusqlite::params![SDK_VERSION, unix_now() as i64],
+    )?;
+    Ok(())
+}
+
+struct PoolInner {
+    path: PathBuf,
+    network: NetworkParams,
+    /// The write connection, opened by initialization
+    writer: Mutex<Option<Connection>>,
+    /// Set once the schema is initialized, so readers need not wait for the
+    /// writer lock to check
+    initialized: AtomicBool,
+    readers: Mutex<Vec<Connection>>,
+    /// Bumped by [`WalletDbPool::reset`] so connections checked out before a
+    /// reset are not returned to the pool
+    generation: AtomicU64,
+}
+
+/// Pool of connections to one wallet database
+///
+/// Cloning the pool is cheap; all clones share the same connections.
+#[derive(Clone)]
+pub struct WalletDbPool {
+    inner: Arc<PoolInner>,
+}
+
+impl WalletDbPool {
+    /// Create a pool for the database at `path`; no connection is opened
+    /// until [`initialize`](Self::initialize)
+    pub fn new(path: PathBuf, network: NetworkParams) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                path,
+                network,
+                writer: Mutex::new(None),
+                initialized: AtomicBool::new(false),
+                readers: Mutex::new(Vec::new()),
+                generation: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Database path
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Open the write connection, creating the schema of a new database
+    ///
+    /// Does nothing if the pool is already initialized. Fails if an existing
+    /// database needs [`migrate`](Self::migrate). The wallet seed, if
+    /// available, lets migrations check the seed-derived accounts.
+    pub fn initialize(&self, seed: Option<&[u8]>) -> Result<()> {
+        let mut writer = lock(&self.inner.writer);
+        if writer.is_some() {
+            return Ok(());
+        }
+        let mut conn = self.open_writer()?;
+        if needs_migration(&conn, self.inner.network)? {
+            return Err(Error::Database(format!(
+                "Wallet database {} needs a schema migration; call Wallet::migrate",
+                self.inner.path.display()
+            )));
+        }
+        if schema_version(&conn)
+            .map_err(db_error(DB_CONTEXT))?
+            .applied_migrations
+            == 0
+        {
+            Self::run_migrations(&mut conn, self.inner.network, seed)?;
+        }
+        *writer = Some(conn);
+        self.inner.initialized.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Whether the database has a schema lacking migrations of the linked
+    /// `zcash_client_sqlite`, which must be applied before use
+    ///
+    /// A database without a schema is created on first use and does not
+    /// count as needing migration.
+    pub fn needs_migration(&self) -> Result<bool> {
+        self.with_connection(|conn| needs_migration(conn, self.inner.network))
+    }
+
+    /// Current schema state of the database
+    pub fn schema_version(&self) -> Result<SchemaVersion> {
+        self.with_connection(|conn| schema_version(conn).map_err(db_error(DB_CONTEXT)))
+    }
+
+    /// Run `f` on the write connection if it is open, or on a new one
+    fn with_connection<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        if let Some(conn) = lock(&self.inner.writer).as_ref() {
+            return f(conn);
+        }
+        let conn = open_connection(&self.inner.path).map_err(db_error(DB_CONTEXT))?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .map_err(db_error(DB_CONTEXT))?;
+        f(&conn)
+    }
+
+    /// Create or migrate the schema and initialize the pool
+    ///
+    /// Runs every pending wallet schema migration. `progress` is called on
+    /// the calling thread about every 250 ms while migrations run, and once
+    /// more when they are done. Other handles of the pool fail until the
+    /// migration has finished.
+    pub fn migrate(
+        &self,
+        seed: Option<&[u8]>,
+        mut progress: impl FnMut(MigrationProgress),
+    ) -> Result<()> {
+        let mut writer = lock(&self.inner.writer);
+        self.inner.initialized.store(false, Ordering::SeqCst);
+        *writer = None;
+
+        let mut conn = self.open_writer()?;
+        let network = self.inner.network;
+        let seed = seed.map(<[u8]>::to_vec);
+        let started = Instant::now();
+        let monitor = open_connection(&self.inner.path).map_err(db_error(DB_CONTEXT))?;
+        monitor
+            .busy_timeout(BUSY_TIMEOUT)
+            .map_err(db_error(DB_CONTEXT))?;
+
+        let conn = std::thread::scope(|scope| {
+            let migration = scope.spawn(move || {
+                Self::run_migrations(&mut conn, network, seed.as_deref())?;
+                Ok(conn)
+            });
+            while !migration.is_finished() {
+                if let Ok(version) = schema_version(&monitor) {
+                    progress(MigrationProgress {
+                        applied_migrations: version.applied_migrations,
+                        elapsed: started.elapsed(),
+                        done: false,
+                    });
+                }
+                std::thread::sleep(PROGRESS_INTERVAL);
+            }
+            migration
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })?;
+
+        let version = schema_version(&conn).map_err(db_error(DB_CONTEXT))?;
+        progress(MigrationProgress {
+            applied_migrations: version.applied_migrations,
+            elapsed: started.elapsed(),
+            done: true,
+        });
+        *writer = Some(conn);
+        self.inner.initialized.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn open_writer(&self) -> Result<Connection> {
+        let conn = open_connection(&self.inner.path).map_err(db_error(DB_CONTEXT))?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .map_err(db_error(DB_CONTEXT))?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(conn)
+    }
+
+    /// Run the wallet schema migrations and record the SDK version
+    fn run_migrations(
+        conn: &mut Connection,
+        network: NetworkParams,
+        seed: Option<&[u8]>,
+    ) -> Result<()> {
+        init_schema(conn, network, seed)?;
+        record_migration(conn).map_err(db_error(DB_CONTEXT))
+    }
+
+    /// Whether [`initialize`](Self::initialize) has succeeded
+    pub fn is_initialized(&self) -> bool {
+        self.inner.initialized.load(Ordering::SeqCst)
+    }
+
+    /// Get a read handle
+    ///
+    /// Read handles never block each other or the writer. Writing through a
+    /// read handle fails.
+    pub fn read(&self) -> Result<ReadWalletDb> {
+        Ok(WalletDb::from_connection(
+            self.connection()?,
+            self.inner.network,
+            SystemClock,
+            thread_rng(),
+        ))
+    }
+
+    /// Get a query-only connection from the pool, for SQL queries on the
+    /// wallet database
+    ///
+    /// The connection returns to the pool when dropped.
+    pub fn connection(&self) -> Result<PooledConnection> {
+        self.check_initialized()?;
+        let idle = lock(&self.inner.readers).pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => {
+                let conn = open_connection(&self.inner.path).map_err(db_error(DB_CONTEXT))?;
+                conn.busy_timeout(BUSY_TIMEOUT)
+                    .map_err(db_error(DB_CONTEXT))?;
+                conn.pragma_update(None, "query_only", true)
+                    .map_err(db_error(DB_CONTEXT))?;
+                conn
+            }
+        };
+        Ok(PooledConnection {
+            conn: Some(conn),
+            pool: Arc::clone(&self.inner),
+            generation: self.inner.generation.load(Ordering::SeqCst),
+        })
+    }
+
+    /// Get the write handle, waiting while another thread holds it
+    pub fn write(&self) -> Result<WriteWalletDb<'_>> {
+        Ok(WalletDb::from_connection(
+            self.write_connection()?,
+            self.inner.network,
+            SystemClock,
+            thread_rng(),
+        ))
+    }
+
+    /// Get the write connection, waiting while another thread holds it
+    ///
+    /// For the SDK's own tables in the wallet database; writes to the
+    /// wallet schema go through [`write`](Self::write).
+    pub(crate) fn write_connection(&self) -> Result<WriteConnection<'_>> {
+        let guard = lock(&self.inner.writer);
+        if guard.is_none() {
+            return Err(Error::Database(
+                "Wallet database is not initialized".to_string(),
+            ));
+        }
+        Ok(WriteConnection(guard))
+    }
+
+    /// Close every connection, e.g. before the database file is replaced
+    ///
+    /// The pool must be initialized again before it is used.
+    pub fn reset(&self) {
+        let mut writer = lock(&self.inner.writer);
+        self.inner.initialized.store(false, Ordering::SeqCst);
+        self.inner.generation.fetch_add(1, Ordering::SeqCst);
+        lock(&self.inner.readers).clear();
+        *writer = None;
+    }
+
+    fn check_initialized(&self) -> Result<()> {
+        if self.is_initialized() {
+            Ok(())
+        } else {
+            Err(Error::Database(
+                "Wallet database is not initialized".to_string(),
+            ))
+        }
+    }
+}
+
+/// Lock a mutex, ignoring poisoning: connections stay usable after a panic
+/// in another thread because SQLite rolls back unfinished transactions
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A read connection that returns to its pool when dropped
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Arc<PoolInner>,
+    generation: u64,
+}
+
+impl Borrow<Connection> for PooledConnection {
+    fn borrow(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is present until drop")
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.borrow()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        if self.generation != self.pool.generation.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut readers = lock(&self.pool.readers);
+        if readers.len() < MAX_IDLE_READERS {
+            readers.push(conn);
+        }
+    }
+}
+
+/// The pool's write connection, held exclusively until dropped
+pub struct WriteConnection<'a>(MutexGuard<'a, Option<Connection>>);
+
+impl Borrow<Connection> for WriteConnection<'_> {
+    fn borrow(&self) -> &Connection {
+        self.0
+            .as_ref()
+            .expect("checked when the handle was created")
+    }
+}
+
+impl BorrowMut<Connection> for WriteConnection<'_> {
+    fn borrow_mut(&mut self) -> &mut Connection {
+        self.0
+            .as_mut()
+            .expect("checked when the handle was created")
+    }
+}
+
+impl Deref for WriteConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.borrow()
+    }
+}
+
+impl DerefMut for WriteConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.borrow_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+    use zcash_client_backend::data_api::WalletRead;
+
+    fn pool() -> (TempDb, WalletDbPool) {
+        let path = TempDb::new();
+        let pool = WalletDbPool::new(path.to_path_buf(), NetworkParams::Testnet);
+        (path, pool)
+    }
+
+    #[test]
+    fn test_reads_share_connections() {
+        let (_db, pool) = pool();
+        assert!(pool.read().is_err());
+        pool.initialize(Some(&[1u8; 32])).unwrap();
+        pool.initialize(Some(&[1u8; 32])).unwrap();
+
+        // A read handle works while the write handle is held
+        let writer = pool.write().unwrap();
+        let first = pool.read().unwrap();
+        let second = pool.read().unwrap();
+        assert!(first.get_account_ids().unwrap().is_empty());
+        assert!(second.get_account_ids().unwrap().is_empty());
+        drop((first, second, writer));
+        assert_eq!(lock(&pool.inner.readers).len(), 2);
+
+        // Plain connections come from the same pool
+        let conn = pool.connection().unwrap();
+        assert!(conn.execute("CREATE TABLE t (x INTEGER)", []).is_err());
+        drop(conn);
+        assert_eq!(lock(&pool.inner.readers).len(), 2);
+
+        assert!(!pool.needs_migration().unwrap());
+        assert!(pool.schema_version().unwrap().applied_migrations > 0);
+
+        let stale = pool.read().unwrap();
+        pool.reset();
+        drop(stale);
+        assert!(lock(&pool.inner.readers).is_empty());
+        assert!(!pool.is_initialized());
+    }
+
+    #[test]
+    fn test_explicit_migration() {
+        let (_db, pool) = pool();
+        pool.initialize(Some(&[1u8; 32])).unwrap();
+        // Another SDK version with the same schema needs no migration
+        let writer = lock(&pool.inner.writer);
+        let conn = writer.as_ref().unwrap();
+        conn.execute("UPDATE numi_schema SET sdk_version = '0.0.1'", [])
+            .unwrap();
+        assert!(!needs_migration(conn, NetworkParams::Testnet).unwrap());
+
+        // Pretend a migration is pending
+        let id: String = conn
+            .query_row(
+                "SELECT hex(id) FROM schemer_migrations LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        conn.execute(
+            "CREATE TABLE applied AS SELECT * FROM schemer_migrations",
+            [],
+        )
+        .unwrap();
+        conn.execute("DELETE FROM schemer_migrations WHERE hex(id) = ?1", [&id])
+            .unwrap();
+        drop(writer);
+        pool.reset();
+        assert!(pool.needs_migration().unwrap());
+        assert!(pool.initialize(Some(&[1u8; 32])).is_err());
+
+        // Restore it, as migrating would re-run it
+        let conn = open_connection(pool.path()).unwrap();
+        conn.execute(
+            "INSERT INTO schemer_migrations SELECT * FROM applied WHERE hex(id) = ?1",
+            [&id],
+        )
+        .unwrap();
+        assert!(!pool.needs_migration().unwrap());
+
+        let mut reports = Vec::new();
+        pool.migrate(Some(&[1u8; 32]), |progress| reports.push(progress))
+            .unwrap();
+        assert!(reports.last().unwrap().done);
+        assert!(!pool.needs_migration().unwrap());
+        assert!(pool.read().is_ok());
+    }
+}
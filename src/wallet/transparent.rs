@@ -0,0 +1,440 @@
+//! Transparent HD address chains
+//!
+//! Each account has two BIP-44 style chains of transparent addresses: the
+//! external chain, whose addresses are handed out to payers, and the internal
+//! (change) chain. [`Wallet::get_transparent_address`] only returns the
+//! default external address; [`TransparentAddresses`] derives sequential
+//! addresses on any chain and records which ones were handed out, which
+//! have been seen on chain, and up to which height each was checked.
+//!
+//! ZIP-320 adds a third, ephemeral chain. A payment to a TEX address is made
+//! in two steps: the shielded funds are first sent to a fresh ephemeral
+//! address, which is then spent to the TEX recipient. Ephemeral addresses are
+//! internal like change addresses, so they are never handed out to payers,
+//! and each one is used for a single payment.
+//!
+//! Funds sent to a later index are found by gap-limit discovery (see
+//! [`LightClient::discover_transparent_addresses`](crate::light_client::LightClient::discover_transparent_addresses)):
+//! addresses are checked in index order until [`DEFAULT_GAP_LIMIT`]
+//! consecutive addresses past the last used (or handed out) one have no
+//! transactions.
+
+use crate::db_encryption::open_connection;
+use crate::error::{Error, Result};
+use crate::params::NetworkParams;
+use crate::store::db_error;
+use crate::wallet::Wallet;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use zcash_keys::encoding::AddressCodec;
+use zcash_transparent::keys::{AccountPubKey, IncomingViewingKey, NonHardenedChildIndex};
+
+/// Number of consecutive unused addresses after which discovery stops
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// A BIP-44 transparent address chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransparentChain {
+    /// Receiving addresses handed out to payers
+    External,
+    /// Change addresses
+    Internal,
+    /// ZIP-320 ephemeral addresses used for payments to TEX addresses
+    Ephemeral,
+}
+
+impl TransparentChain {
+    /// Every chain, in derivation order
+    pub const ALL: [TransparentChain; 3] = [
+        TransparentChain::External,
+        TransparentChain::Internal,
+        TransparentChain::Ephemeral,
+    ];
+
+    /// Whether addresses on the chain are only used by the wallet itself and
+    /// never handed out as receive addresses
+    pub fn is_internal(self) -> bool {
+        self != TransparentChain::External
+    }
+
+    /// BIP-44 change index (ZIP-320 key scope) of the chain
+    fn index(self) -> u32 {
+        match self {
+            TransparentChain::External => 0,
+            TransparentChain::Internal => 1,
+            TransparentChain::Ephemeral => 2,
+        }
+    }
+
+    fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|chain| chain.index() == index)
+    }
+}
+
+/// A derived transparent address and what is known about it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransparentAddressInfo {
+    pub chain: TransparentChain,
+    /// Address index within the chain
+    pub index: u32,
+    pub address: String,
+    /// Whether the address was handed out
+    pub issued: bool,
+    /// Whether any transaction involving the address was found
+    pub used: bool,
+    /// Height up to which the address was checked for transactions
+    pub checked_height: Option<u64>,
+}
+
+const DB_CONTEXT: &str = "Transparent address error";
+
+/// Transparent addresses of one account, stored in a wallet database
+pub struct TransparentAddresses {
+    conn: Connection,
+    network: NetworkParams,
+    account_index: u32,
+    account_key: AccountPubKey,
+}
+
+impl TransparentAddresses {
+    /// Open (or create) the transparent addresses in the database at `path`
+    ///
+    /// # Arguments
+    /// * `path` - Database path
+    /// * `network` - Network addresses are encoded for
+    /// * `account_index` - ZIP-32 index of the account
+    /// * `account_key` - Transparent account public key of the account
+    pub fn open(
+        path: &Path,
+        network: NetworkParams,
+        account_index: u32,
+        account_key: AccountPubKey,
+    ) -> Result<Self> {
+        let conn = open_connection(path).map_err(db_error(DB_CONTEXT))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS numi_transparent_addresses (
+                account_index INTEGER NOT NULL,
+                chain INTEGER NOT NULL,
+                address_index INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                issued INTEGER NOT NULL DEFAULT 0,
+                used INTEGER NOT NULL DEFAULT 0,
+                checked_height INTEGER,
+                PRIMARY KEY (account_index, chain, address_index)
+            );",
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        Ok(Self {
+            conn,
+            network,
+            account_index,
+            account_key,
+        })
+    }
+
+    /// Open the transparent addresses of the wallet's selected account
+    pub fn for_wallet(wallet: &Wallet) -> Result<Self> {
+        let ufvk = wallet.unified_full_viewing_key()?;
+        let account_key = ufvk
+            .transparent()
+            .cloned()
+            .ok_or_else(|| Error::Address("No transparent component in unified key".to_string()))?;
+        Self::open(
+            wallet.db_path(),
+            wallet.consensus_network(),
+            wallet.account_index(),
+            account_key,
+        )
+    }
+
+    /// Derive the encoded address at `index` on `chain`
+    pub fn derive(&self, chain: TransparentChain, index: u32) -> Result<String> {
+        derive_address(&self.account_key, &self.network, chain, index)
+    }
+
+    /// Hand out the next address on `chain`
+    ///
+    /// Index 0 of the external chain is the default address returned by
+    /// [`Wallet::get_transparent_address`], so the first external address
+    /// handed out here is index 1.
+    pub fn next_address(&mut self, chain: TransparentChain) -> Result<TransparentAddressInfo> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(db_error(DB_CONTEXT))?;
+        let highest: Option<u32> = tx
+            .query_row(
+                "SELECT MAX(address_index) FROM numi_transparent_addresses
+                 WHERE account_index = ?1 AND chain = ?2 AND issued = 1",
+                params![self.account_index, chain.index()],
+                |row| row.get(0),
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let index = match (highest, chain) {
+            (Some(highest), _) => highest.checked_add(1).ok_or_else(|| {
+                Error::Address("Transparent address chain is exhausted".to_string())
+            })?,
+            (None, TransparentChain::External) => 1,
+            (None, _) => 0,
+        };
+        let address = derive_address(&self.account_key, &self.network, chain, index)?;
+        tx.execute(
+            "INSERT INTO numi_transparent_addresses
+                (account_index, chain, address_index, address, issued)
+             VALUES (?1, ?2, ?3, ?4, 1)
+             ON CONFLICT(account_index, chain, address_index) DO UPDATE SET issued = 1",
+            params![self.account_index, chain.index(), index, address],
+        )
+        .map_err(db_error(DB_CONTEXT))?;
+        tx.commit().map_err(db_error(DB_CONTEXT))?;
+        self.get(chain, index)?
+            .ok_or_else(|| Error::Database("Issued address was not stored".to_string()))
+    }
+
+    /// Record that an address was checked for transactions up to `height`
+    ///
+    /// An address stays used once a transaction was found for it.
+    pub fn record_check(
+        &self,
+        chain: TransparentChain,
+        index: u32,
+        height: u64,
+        used: bool,
+    ) -> Result<()> {
+        let address = self.derive(chain, index)?;
+        self.conn
+            .execute(
+                "INSERT INTO numi_transparent_addresses
+                    (account_index, chain, address_index, address, used, checked_height)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(account_index, chain, address_index) DO UPDATE SET
+                    used = MAX(used, excluded.used),
+                    checked_height = MAX(IFNULL(checked_height, 0), excluded.checked_height)",
+                params![
+                    self.account_index,
+                    chain.index(),
+                    index,
+                    address,
+                    used,
+                    height as i64
+                ],
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(())
+    }
+
+    /// Look up a stored address by chain and index
+    pub fn get(
+        &self,
+        chain: TransparentChain,
+        index: u32,
+    ) -> Result<Option<TransparentAddressInfo>> {
+        self.conn
+            .query_row(
+                "SELECT chain, address_index, address, issued, used, checked_height
+                 FROM numi_transparent_addresses
+                 WHERE account_index = ?1 AND chain = ?2 AND address_index = ?3",
+                params![self.account_index, chain.index(), index],
+                row_to_info,
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// Highest handed out address index on `chain`
+    pub fn highest_issued(&self, chain: TransparentChain) -> Result<Option<u32>> {
+        self.highest(chain, "issued")
+    }
+
+    /// Highest address index on `chain` with transactions
+    pub fn highest_used(&self, chain: TransparentChain) -> Result<Option<u32>> {
+        self.highest(chain, "used")
+    }
+
+    fn highest(&self, chain: TransparentChain, column: &str) -> Result<Option<u32>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT MAX(address_index) FROM numi_transparent_addresses
+                     WHERE account_index = ?1 AND chain = ?2 AND {} = 1",
+                    column
+                ),
+                params![self.account_index, chain.index()],
+                |row| row.get(0),
+            )
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// All stored addresses of the account, ordered by chain and index
+    pub fn list(&self) -> Result<Vec<TransparentAddressInfo>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT chain, address_index, address, issued, used, checked_height
+                 FROM numi_transparent_addresses WHERE account_index = ?1
+                 ORDER BY chain, address_index",
+            )
+            .map_err(db_error(DB_CONTEXT))?;
+        let addresses = stmt
+            .query_map([self.account_index], row_to_info)
+            .map_err(db_error(DB_CONTEXT))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error(DB_CONTEXT))?;
+        Ok(addresses)
+    }
+
+    /// Addresses handed out to payers, excluding change and ephemeral
+    /// addresses
+    pub fn receive_addresses(&self) -> Result<Vec<TransparentAddressInfo>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|info| !info.chain.is_internal() && info.issued)
+            .collect())
+    }
+
+    /// Look up a stored address by its encoding
+    pub fn find(&self, address: &str) -> Result<Option<TransparentAddressInfo>> {
+        self.conn
+            .query_row(
+                "SELECT chain, address_index, address, issued, used, checked_height
+                 FROM numi_transparent_addresses
+                 WHERE account_index = ?1 AND address = ?2",
+                params![self.account_index, address],
+                row_to_info,
+            )
+            .optional()
+            .map_err(db_error(DB_CONTEXT))
+    }
+
+    /// Encoded addresses of the account that have transactions
+    pub fn used_addresses(&self) -> Result<Vec<String>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|info| info.used)
+            .map(|info| info.address)
+            .collect())
+    }
+}
+
+/// Derive and encode the address at `index` on `chain` of an account
+fn derive_address(
+    account_key: &AccountPubKey,
+    network: &NetworkParams,
+    chain: TransparentChain,
+    index: u32,
+) -> Result<String> {
+    let child = NonHardenedChildIndex::from_index(index).ok_or_else(|| {
+        Error::InvalidParameter(format!("Address index {} is out of range", index))
+    })?;
+    let address = match chain {
+        TransparentChain::External => account_key
+            .derive_external_ivk()
+            .and_then(|ivk| ivk.derive_address(child)),
+        TransparentChain::Internal => account_key
+            .derive_internal_ivk()
+            .and_then(|ivk| ivk.derive_address(child)),
+        TransparentChain::Ephemeral => account_key
+            .derive_ephemeral_ivk()
+            .and_then(|ivk| ivk.derive_ephemeral_address(child)),
+    }
+    .map_err(|e| Error::Address(format!("Failed to derive transparent address: {}", e)))?;
+    Ok(address.encode(network))
+}
+
+fn row_to_info(row: &rusqlite::Row<'_>) -> rusqlite::Result<TransparentAddressInfo> {
+    Ok(TransparentAddressInfo {
+        chain: TransparentChain::from_index(row.get(0)?).unwrap_or(TransparentChain::Internal),
+        index: row.get(1)?,
+        address: row.get(2)?,
+        issued: row.get(3)?,
+        used: row.get(4)?,
+        checked_height: row.get::<_, Option<i64>>(5)?.map(|h| h as u64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::TempDb;
+
+    fn wallet() -> (TempDb, Wallet) {
+        let path = TempDb::new();
+        let wallet = Wallet::with_path_and_seed(path.to_path_buf(), Some(vec![5u8; 32])).unwrap();
+        (path, wallet)
+    }
+
+    #[test]
+    fn test_sequential_addresses() {
+        let (_db, wallet) = wallet();
+        let mut addresses = TransparentAddresses::for_wallet(&wallet).unwrap();
+        assert_eq!(
+            addresses.derive(TransparentChain::External, 0).unwrap(),
+            wallet.get_transparent_address().unwrap()
+        );
+        assert_ne!(
+            addresses.derive(TransparentChain::External, 0).unwrap(),
+            addresses.derive(TransparentChain::Internal, 0).unwrap()
+        );
+
+        let first = addresses.next_address(TransparentChain::External).unwrap();
+        let second = addresses.next_address(TransparentChain::External).unwrap();
+        let change = addresses.next_address(TransparentChain::Internal).unwrap();
+        assert_eq!((first.index, second.index, change.index), (1, 2, 0));
+        assert!(first.issued && !first.used);
+        assert_eq!(
+            addresses
+                .highest_issued(TransparentChain::External)
+                .unwrap(),
+            Some(2)
+        );
+        assert!(addresses
+            .derive(TransparentChain::External, 1 << 31)
+            .is_err());
+    }
+
+    #[test]
+    fn test_ephemeral_addresses() {
+        let (_db, wallet) = wallet();
+        let mut addresses = TransparentAddresses::for_wallet(&wallet).unwrap();
+        let receive = addresses.next_address(TransparentChain::External).unwrap();
+        let ephemeral = addresses.next_address(TransparentChain::Ephemeral).unwrap();
+        assert_eq!(ephemeral.index, 0);
+        assert_ne!(
+            ephemeral.address,
+            addresses.derive(TransparentChain::Internal, 0).unwrap()
+        );
+        assert!(TransparentChain::Ephemeral.is_internal());
+
+        let found = addresses.find(&ephemeral.address).unwrap().unwrap();
+        assert_eq!(found.chain, TransparentChain::Ephemeral);
+        assert_eq!(addresses.list().unwrap().len(), 2);
+        assert_eq!(addresses.receive_addresses().unwrap(), vec![receive]);
+    }
+
+    #[test]
+    fn test_record_check_keeps_used() {
+        let (_db, wallet) = wallet();
+        let addresses = TransparentAddresses::for_wallet(&wallet).unwrap();
+        addresses
+            .record_check(TransparentChain::External, 7, 100, true)
+            .unwrap();
+        addresses
+            .record_check(TransparentChain::External, 7, 200, false)
+            .unwrap();
+
+        let info = addresses
+            .get(TransparentChain::External, 7)
+            .unwrap()
+            .unwrap();
+        assert!(info.used && !info.issued);
+        assert_eq!(info.checked_height, Some(200));
+        assert_eq!(
+            addresses.highest_used(TransparentChain::External).unwrap(),
+            Some(7)
+        );
+        assert_eq!(addresses.used_addresses().unwrap(), vec![info.address]);
+    }
+}
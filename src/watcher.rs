@@ -0,0 +1,407 @@
+//! Chain watcher for zcashd
+//!
+//! [`RpcWatcher`] polls a zcashd node for new blocks and for payments received
+//! by a set of watched addresses (including unconfirmed mempool payments), and
+//! publishes them as [`WalletEvent`]s on an [`EventBus`].
+//!
+//! The watcher keeps the hashes of the last [`MAX_REORG_DEPTH`] blocks. When
+//! a recorded block is no longer on the node's best chain it publishes
+//! [`WalletEvent::Reorg`], and payments from the disconnected blocks are
+//! published again once they are mined on the new chain.
+//!
+//! With [`RpcWatcher::with_wallet_import`], the SDK wallet's viewing key and
+//! transparent addresses are imported into the node on the first poll and
+//! its addresses are watched, so payments to the SDK wallet are reported.
+
+use crate::client::RpcClient;
+use crate::error::Result;
+use crate::events::{EventBus, ReceivedPayment, WalletEvent, MAX_REORG_DEPTH};
+use crate::node_import::NodeImporter;
+use crate::types::utils::zec_to_zatoshis;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+/// Polls zcashd and publishes chain and payment events
+pub struct RpcWatcher {
+    client: RpcClient,
+    bus: EventBus,
+    addresses: Vec<String>,
+    last_tip: Option<u64>,
+    /// Hashes of the most recent blocks by height, for reorg detection
+    hashes: BTreeMap<u64, String>,
+    /// Payments already published
+    seen: SeenPayments,
+    /// Wallet still to be imported into the node
+    pending_import: Option<NodeImporter>,
+}
+
+impl RpcWatcher {
+    /// Create a watcher publishing onto the given event bus
+    pub fn new(client: RpcClient, bus: EventBus) -> Self {
+        Self {
+            client,
+            bus,
+            addresses: Vec::new(),
+            last_tip: None,
+            hashes: BTreeMap::new(),
+            seen: SeenPayments::default(),
+            pending_import: None,
+        }
+    }
+
+    /// Import an SDK wallet into the node on the first poll and watch its
+    /// Sapling and transparent addresses
+    ///
+    /// A failed import is retried on the next poll.
+    pub fn with_wallet_import(mut self, importer: NodeImporter) -> Self {
+        self.pending_import = Some(importer);
+        self
+    }
+
+    /// Start watching an address for incoming payments
+    ///
+    /// The address must be known to the node's wallet (for shielded addresses)
+    /// or imported as watch-only (for transparent addresses).
+    pub fn watch_address(&mut self, address: impl Into<String>) {
+        let address = address.into();
+        if !self.addresses.contains(&address) {
+            self.addresses.push(address);
+        }
+    }
+
+    /// Stop watching an address
+    pub fn unwatch_address(&mut self, address: &str) {
+        self.addresses.retain(|a| a != address);
+        self.seen.settled.remove(address);
+    }
+
+    /// Get the event bus this watcher publishes to
+    pub fn event_bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    /// Poll the node once, publishing any new events
+    ///
+    /// A payment is published once when first seen in the mempool and again
+    /// once it is mined, so subscribers can follow its confirmation progress.
+    /// Payments mined more than [`MAX_REORG_DEPTH`] blocks below the tip are
+    /// no longer tracked individually; the node keeps listing them, but they
+    /// are not published again.
+    pub async fn poll(&mut self) -> Result<()> {
+        if let Some(importer) = &self.pending_import {
+            importer.import(&self.client).await?;
+            for address in importer.watch_addresses()? {
+                self.watch_address(address);
+            }
+            self.pending_import = None;
+        }
+
+        let tip = self.client.get_block_count().await?;
+        self.detect_reorg(tip).await?;
+        if self.last_tip != Some(tip) {
+            self.last_tip = Some(tip);
+            self.bus.publish(WalletEvent::ChainTip { height: tip });
+        }
+
+        let mut unconfirmed = HashSet::new();
+        for address in self.addresses.clone() {
+            let entries = self.client.z_listreceivedbyaddress(&address, Some(0)).await?;
+            for entry in &entries {
+                let Some(payment) = parse_received_entry(&address, entry, tip) else {
+                    tracing::debug!("Skipping unrecognised received entry for {}", address);
+                    continue;
+                };
+                if payment.height.is_none() {
+                    unconfirmed.insert((payment.txid.clone(), payment.output_index));
+                }
+                if self.seen.insert(&payment) {
+                    self.bus.publish(WalletEvent::PaymentReceived(payment));
+                }
+            }
+        }
+        self.seen.settle(
+            &self.addresses,
+            tip.saturating_sub(MAX_REORG_DEPTH),
+            &unconfirmed,
+        );
+
+        Ok(())
+    }
+
+    /// Compare the recorded block hashes with the node's best chain,
+    /// publishing [`WalletEvent::Reorg`] if recorded blocks were disconnected
+    async fn detect_reorg(&mut self, tip: u64) -> Result<()> {
+        if let Some(&old_tip) = self.hashes.keys().next_back() {
+            let fork_height = self.fork_height(tip).await?;
+            let depth = old_tip.saturating_sub(fork_height);
+            if depth > 0 {
+                tracing::warn!(
+                    "Reorg of {} block(s) from tip {} to {}",
+                    depth,
+                    old_tip,
+                    tip
+                );
+                self.hashes.split_off(&(fork_height + 1));
+                // Payments from disconnected blocks are published again once
+                // mined on the new chain
+                self.seen.disconnect_above(fork_height);
+                self.bus.publish(WalletEvent::Reorg {
+                    depth,
+                    old_tip,
+                    new_tip: tip,
+                });
+            }
+        }
+        self.record_hashes(tip).await
+    }
+
+    /// Highest recorded height whose block is still on the best chain
+    async fn fork_height(&self, tip: u64) -> Result<u64> {
+        // A block on the best chain implies its ancestors are too, so
+        // normally a single lookup is needed
+        let Some((&height, hash)) = self.hashes.range(..=tip).next_back() else {
+            return Ok(tip);
+        };
+        if &self.client.get_block_hash(height).await? == hash {
+            return Ok(height);
+        }
+        let heights: Vec<u64> = self.hashes.range(..=tip).map(|(h, _)| *h).collect();
+        let current: Vec<String> = self
+            .client
+            .call_batch("getblockhash", heights.iter().map(|h| [h]))
+            .await?;
+        Ok(
+            fork_point(&self.hashes, heights.iter().copied().zip(current)).unwrap_or_else(|| {
+                tracing::warn!("Reorg deeper than {} blocks", MAX_REORG_DEPTH);
+                heights[0].saturating_sub(1)
+            }),
+        )
+    }
+
+    /// Record the hashes of blocks up to `tip` that are not yet recorded,
+    /// keeping the last [`MAX_REORG_DEPTH`]
+    async fn record_hashes(&mut self, tip: u64) -> Result<()> {
+        let window_start = tip.saturating_sub(MAX_REORG_DEPTH - 1);
+        let start = self
+            .hashes
+            .keys()
+            .next_back()
+            .map_or(tip, |h| h + 1)
+            .max(window_start);
+        if start <= tip {
+            let hashes: Vec<String> = self
+                .client
+                .call_batch("getblockhash", (start..=tip).map(|h| [h]))
+                .await?;
+            self.hashes.extend((start..=tip).zip(hashes));
+        }
+        self.hashes = self.hashes.split_off(&window_start);
+        Ok(())
+    }
+
+    /// Poll the node forever at the given interval
+    ///
+    /// Transient RPC failures are logged and retried on the next tick.
+    pub async fn run(mut self, interval: Duration) {
+        loop {
+            if let Err(e) = self.poll().await {
+                tracing::warn!("Watcher poll failed: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Payments already published by a watcher
+///
+/// Only payments that can still change are kept individually; older ones
+/// are covered by a settled height per address, so memory stays bounded
+/// while the node keeps listing every payment an address ever received.
+#[derive(Debug, Default)]
+struct SeenPayments {
+    /// (txid, output index, height) triples of unconfirmed payments and of
+    /// payments mined within [`MAX_REORG_DEPTH`] blocks of the tip
+    recent: HashSet<(String, u32, Option<u64>)>,
+    /// Height per address at or below which every listed payment was
+    /// published
+    settled: HashMap<String, u64>,
+}
+
+impl SeenPayments {
+    /// Record a listed payment, returning whether it is not published yet
+    fn insert(&mut self, payment: &ReceivedPayment) -> bool {
+        if let (Some(height), Some(&settled)) = (payment.height, self.settled.get(&payment.address))
+        {
+            if height <= settled {
+                return false;
+            }
+        }
+        self.recent
+            .insert((payment.txid.clone(), payment.output_index, payment.height))
+    }
+
+    /// Forget payments mined above `fork_height`, so they are published again
+    /// once mined on the new chain
+    fn disconnect_above(&mut self, fork_height: u64) {
+        self.recent
+            .retain(|(_, _, height)| height.is_none_or(|h| h <= fork_height));
+        for settled in self.settled.values_mut() {
+            *settled = (*settled).min(fork_height);
+        }
+    }
+
+    /// Settle the payments of `addresses` mined at or below `height` after
+    /// all of them were listed, and forget unconfirmed payments that are no
+    /// longer listed as unconfirmed
+    fn settle(&mut self, addresses: &[String], height: u64, unconfirmed: &HashSet<(String, u32)>) {
+        for address in addresses {
+            self.settled.insert(address.clone(), height);
+        }
+        self.recent.retain(|(txid, index, mined)| match mined {
+            Some(mined) => *mined > height,
+            None => unconfirmed.contains(&(txid.clone(), *index)),
+        });
+    }
+}
+
+/// Highest recorded height whose hash matches the best chain's
+fn fork_point(
+    recorded: &BTreeMap<u64, String>,
+    current: impl IntoIterator<Item = (u64, String)>,
+) -> Option<u64> {
+    current
+        .into_iter()
+        .filter(|(height, hash)| recorded.get(height) == Some(hash))
+        .map(|(height, _)| height)
+        .max()
+}
+
+/// Convert a `z_listreceivedbyaddress` entry into a [`ReceivedPayment`]
+///
+/// Change outputs are skipped. Returns `None` if the entry is missing the
+/// txid or amount.
+pub(crate) fn parse_received_entry(
+    address: &str,
+    entry: &serde_json::Value,
+    tip: u64,
+) -> Option<ReceivedPayment> {
+    if entry.get("change").and_then(|c| c.as_bool()) == Some(true) {
+        return None;
+    }
+
+    let txid = entry.get("txid")?.as_str()?.to_string();
+    let amount = match entry.get("amountZat").and_then(|a| a.as_u64()) {
+        Some(zat) => zat,
+        None => zec_to_zatoshis(entry.get("amount")?.as_f64()?),
+    };
+
+    let output_index = ["outindex", "actionidx", "vout", "jsoutindex"]
+        .iter()
+        .find_map(|key| entry.get(*key).and_then(|v| v.as_u64()))
+        .unwrap_or(0) as u32;
+
+    let confirmations = entry
+        .get("confirmations")
+        .and_then(|c| c.as_u64())
+        .unwrap_or(0);
+    let height = if confirmations == 0 {
+        None
+    } else {
+        entry
+            .get("blockheight")
+            .and_then(|h| h.as_u64())
+            .or_else(|| Some(tip.saturating_sub(confirmations - 1)))
+    };
+
+    let memo = entry
+        .get("memoStr")
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string());
+
+    Some(ReceivedPayment {
+        txid,
+        output_index,
+        address: address.to_string(),
+        amount,
+        memo,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fork_point() {
+        let recorded: BTreeMap<u64, String> =
+            (100..=105).map(|h| (h, format!("old{}", h))).collect();
+        let current = (100..=105).map(|h| {
+            let chain = if h <= 102 { "old" } else { "new" };
+            (h, format!("{}{}", chain, h))
+        });
+        assert_eq!(fork_point(&recorded, current), Some(102));
+
+        let replaced = (100..=105).map(|h| (h, format!("new{}", h)));
+        assert_eq!(fork_point(&recorded, replaced), None);
+    }
+
+    #[test]
+    fn test_seen_payments_are_pruned() {
+        let payment = |txid: &str, height| ReceivedPayment {
+            txid: txid.to_string(),
+            output_index: 0,
+            address: "zs1addr".to_string(),
+            amount: 1,
+            memo: None,
+            height,
+        };
+        let addresses = vec!["zs1addr".to_string()];
+        let mut seen = SeenPayments::default();
+        assert!(seen.insert(&payment("old", None)));
+        assert!(seen.insert(&payment("old", Some(10))));
+        assert!(seen.insert(&payment("new", Some(200))));
+        assert!(!seen.insert(&payment("new", Some(200))));
+
+        // The mined payment below the window and the stale mempool entry
+        // are pruned, but not published again
+        seen.settle(&addresses, 100, &HashSet::new());
+        assert_eq!(seen.recent.len(), 1);
+        assert!(!seen.insert(&payment("old", Some(10))));
+        assert!(!seen.insert(&payment("new", Some(200))));
+
+        // A reorg below the settled height republishes re-mined payments
+        seen.disconnect_above(5);
+        assert!(seen.insert(&payment("old", Some(10))));
+        assert!(seen.insert(&payment("new", Some(201))));
+    }
+
+    #[test]
+    fn test_parse_received_entry_confirmed() {
+        let entry = serde_json::json!({
+            "txid": "ab",
+            "amount": 0.5,
+            "amountZat": 50_000_000u64,
+            "outindex": 1,
+            "confirmations": 3,
+            "blockheight": 100,
+            "memoStr": "order-42",
+        });
+        let payment = parse_received_entry("zs1addr", &entry, 102).unwrap();
+        assert_eq!(payment.amount, 50_000_000);
+        assert_eq!(payment.output_index, 1);
+        assert_eq!(payment.height, Some(100));
+        assert_eq!(payment.memo.as_deref(), Some("order-42"));
+    }
+
+    #[test]
+    fn test_parse_received_entry_mempool_and_change() {
+        let entry = serde_json::json!({ "txid": "ab", "amount": 0.1, "confirmations": 0 });
+        let payment = parse_received_entry("zs1addr", &entry, 102).unwrap();
+        assert_eq!(payment.amount, 10_000_000);
+        assert_eq!(payment.height, None);
+
+        let change = serde_json::json!({ "txid": "ab", "amount": 0.1, "change": true });
+        assert!(parse_received_entry("zs1addr", &change, 102).is_none());
+    }
+}
@@ -0,0 +1,298 @@
+//! zcashd wallet dumps
+//!
+//! `z_exportwallet` (and the transparent-only `dumpwallet`) write a text
+//! file with one key per line, followed by the key's creation time,
+//! attributes and, after a `#`, the address it controls:
+//! ```text
+//! # Wallet dump created by Zcash v5.4.0
+//! # * Best block at time of backup was 2100000 (0000...),
+//! # - recovery_phrase="abandon abandon ..."
+//! secret-extended-key-main1q... 2022-05-01T10:00:00Z # zaddr=zs1...
+//! L1aW4a... 2022-05-01T10:00:00Z label=Savings # addr=t1...
+//! KxZ... 2022-05-01T10:00:00Z change=1 # addr=t1...
+//! ```
+//! [`parse_zcashd_dump`] reads such a file and
+//! [`Wallet::import_zcashd_dump`](crate::wallet::Wallet::import_zcashd_dump)
+//! moves it into an SDK wallet. Orchard keys are not dumped by zcashd; they
+//! are derived from the recovery phrase.
+
+use crate::error::{Error, Result};
+use crate::wallet::WalletAccount;
+use secp256k1::SecretKey;
+use secrecy::zeroize::Zeroizing;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Prefix of Bech32 Sapling extended spending keys on every network
+const SAPLING_KEY_PREFIX: &str = "secret-extended-key-";
+
+/// A Sapling spending key from a dump
+pub struct DumpSaplingKey {
+    /// Bech32 extended spending key
+    pub key: SecretString,
+    /// Sapling address of the key, from the dump
+    pub address: Option<String>,
+}
+
+/// Why zcashd created a transparent key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransparentKeyKind {
+    /// A receiving address, with its label if it has one
+    Receive { label: Option<String> },
+    /// A change address
+    Change,
+    /// An unused key from the key pool
+    Reserve,
+}
+
+/// A transparent private key from a dump
+pub struct DumpTransparentKey {
+    /// WIF-encoded private key
+    pub wif: SecretString,
+    /// P2PKH address of the key, from the dump
+    pub address: String,
+    pub kind: TransparentKeyKind,
+}
+
+impl DumpTransparentKey {
+    /// Decode the private key
+    ///
+    /// # Returns
+    /// The key; [`Error::InvalidParameter`] for keys zcashd stored
+    /// uncompressed, whose P2PKH address differs from the one the wallet
+    /// database derives from their public key
+    pub fn secret_key(&self) -> Result<SecretKey> {
+        let payload = decode_wif(self.wif.expose_secret())?;
+        if payload.len() != 34 {
+            return Err(Error::InvalidParameter(format!(
+                "Key for {} is uncompressed",
+                self.address
+            )));
+        }
+        SecretKey::from_slice(&payload[1..33]).map_err(|e| {
+            Error::InvalidParameter(format!("Invalid key for {}: {}", self.address, e))
+        })
+    }
+}
+
+/// Contents of a zcashd wallet dump
+#[derive(Default)]
+pub struct ZcashdDump {
+    /// zcashd version line, e.g. `Zcash v5.4.0`
+    pub created_by: Option<String>,
+    /// Best block height when the dump was written
+    pub best_block_height: Option<u64>,
+    /// Mnemonic of zcashd's HD seed (zcashd v4.7 and later)
+    pub recovery_phrase: Option<SecretString>,
+    pub sapling_keys: Vec<DumpSaplingKey>,
+    pub transparent_keys: Vec<DumpTransparentKey>,
+}
+
+/// Outcome of [`Wallet::import_zcashd_dump`](crate::wallet::Wallet::import_zcashd_dump)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZcashdImportReport {
+    /// Accounts created for the dump's Sapling keys
+    pub accounts: Vec<WalletAccount>,
+    /// Addresses of the transparent keys imported into the selected account
+    pub transparent_addresses: Vec<String>,
+    /// Address labels copied into the address book
+    pub labels_imported: usize,
+    /// Whether the dump contained a recovery phrase
+    pub recovery_phrase: bool,
+    /// Key store entry holding the dump's spending keys and recovery phrase
+    pub key_store_entry: String,
+    /// Keys that could not be imported, with the reason
+    pub skipped: Vec<String>,
+}
+
+/// Undo zcashd's `%XX` escaping of labels
+fn decode_dump_string(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Check a WIF private key's Base58Check encoding
+///
+/// # Returns
+/// The version byte, 32-byte key and, for compressed keys, the `0x01` flag
+fn decode_wif(wif: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let invalid = || Error::InvalidParameter("Invalid WIF private key in dump".to_string());
+    let mut data = Zeroizing::new(bs58::decode(wif).into_vec().map_err(|_| invalid())?);
+    // Version byte, 32-byte key, optional compression flag, 4-byte checksum
+    if !(data.len() == 37 || (data.len() == 38 && data[33] == 0x01)) {
+        return Err(invalid());
+    }
+    let (payload, checksum) = data.split_at(data.len() - 4);
+    if Sha256::digest(Sha256::digest(payload))[..4] != *checksum {
+        return Err(invalid());
+    }
+    let payload_len = payload.len();
+    data.truncate(payload_len);
+    Ok(data)
+}
+
+/// Parse a wallet dump written by zcashd's `z_exportwallet` or `dumpwallet`
+///
+/// # Returns
+/// The dump's keys; [`Error::InvalidParameter`] naming the line if a key
+/// line is malformed
+pub fn parse_zcashd_dump(text: &str) -> Result<ZcashdDump> {
+    let mut dump = ZcashdDump::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim();
+            if let Some(version) = comment.strip_prefix("Wallet dump created by ") {
+                dump.created_by = Some(version.trim().to_string());
+            } else if let Some(rest) = comment.strip_prefix("* Best block at time of backup was ") {
+                dump.best_block_height =
+                    rest.split_whitespace().next().and_then(|h| h.parse().ok());
+            } else if let Some(phrase) = comment.strip_prefix("- recovery_phrase=") {
+                dump.recovery_phrase =
+                    Some(SecretString::new(phrase.trim_matches('"').to_string()));
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let malformed =
+            || Error::InvalidParameter(format!("Malformed key on line {} of the dump", number + 1));
+        let (fields, comment) = line.split_once('#').ok_or_else(malformed)?;
+        let mut fields = fields.split_whitespace();
+        let key = fields.next().ok_or_else(malformed)?;
+        let _created = fields.next().ok_or_else(malformed)?;
+        let attribute = |name: &str| {
+            comment
+                .split_whitespace()
+                .chain(fields.clone())
+                .find_map(|field| field.strip_prefix(name)?.strip_prefix('='))
+        };
+
+        if key.starts_with(SAPLING_KEY_PREFIX) {
+            dump.sapling_keys.push(DumpSaplingKey {
+                key: SecretString::new(key.to_string()),
+                address: attribute("zaddr").map(str::to_string),
+            });
+        } else {
+            decode_wif(key).map_err(|_| malformed())?;
+            let address = attribute("addr").ok_or_else(malformed)?.to_string();
+            let kind = if attribute("change") == Some("1") {
+                TransparentKeyKind::Change
+            } else if attribute("reserve") == Some("1") {
+                TransparentKeyKind::Reserve
+            } else {
+                TransparentKeyKind::Receive {
+                    label: attribute("label")
+                        .map(decode_dump_string)
+                        .filter(|label| !label.is_empty()),
+                }
+            };
+            dump.transparent_keys.push(DumpTransparentKey {
+                wif: SecretString::new(key.to_string()),
+                address,
+                kind,
+            });
+        }
+    }
+    Ok(dump)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::{PublicKey, Secp256k1};
+
+    /// WIF of private key 1 (compressed), from the Bitcoin test vectors
+    const WIF: &str = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+
+    #[test]
+    fn test_parse_dump() {
+        let text = format!(
+            "# Wallet dump created by Zcash v5.4.0\n\
+             # * Created on 2023-01-01T00:00:00Z\n\
+             # * Best block at time of backup was 2100000 (00000000abcd),\n\
+             #   mined on 2023-01-01T00:00:00Z\n\
+             # - recovery_phrase=\"abandon art\"\n\
+             \n\
+             secret-extended-key-main1qqqq 2022-05-01T10:00:00Z # zaddr=zs1abc\n\
+             {wif} 2022-05-01T10:00:00Z label=Cold%20storage # addr=t1one\n\
+             {wif} 2022-05-01T10:00:00Z change=1 # addr=t1two\n\
+             {wif} 1970-01-01T00:00:01Z reserve=1 # addr=t1three\n\
+             # End of dump\n",
+            wif = WIF
+        );
+        let dump = parse_zcashd_dump(&text).unwrap();
+        assert_eq!(dump.created_by.as_deref(), Some("Zcash v5.4.0"));
+        assert_eq!(dump.best_block_height, Some(2_100_000));
+        assert_eq!(dump.recovery_phrase.unwrap().expose_secret(), "abandon art");
+        assert_eq!(dump.sapling_keys.len(), 1);
+        assert_eq!(dump.sapling_keys[0].address.as_deref(), Some("zs1abc"));
+        let kinds: Vec<_> = dump
+            .transparent_keys
+            .iter()
+            .map(|k| k.kind.clone())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TransparentKeyKind::Receive {
+                    label: Some("Cold storage".to_string())
+                },
+                TransparentKeyKind::Change,
+                TransparentKeyKind::Reserve,
+            ]
+        );
+        assert_eq!(dump.transparent_keys[2].address, "t1three");
+
+        let corrupted = format!(
+            "{}X 2022-05-01T10:00:00Z # addr=t1one",
+            &WIF[..WIF.len() - 1]
+        );
+        assert!(parse_zcashd_dump(&corrupted).is_err());
+        assert!(parse_zcashd_dump("secret-extended-key-main1qqqq").is_err());
+    }
+
+    #[test]
+    fn test_transparent_secret_key() {
+        let key = DumpTransparentKey {
+            wif: SecretString::new(WIF.to_string()),
+            address: "t1one".to_string(),
+            kind: TransparentKeyKind::Change,
+        };
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &key.secret_key().unwrap());
+        assert_eq!(
+            hex::encode(public_key.serialize()),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+
+        // Private key 1, uncompressed
+        let uncompressed = DumpTransparentKey {
+            wif: SecretString::new(
+                "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf".to_string(),
+            ),
+            ..key
+        };
+        assert!(uncompressed.secret_key().is_err());
+    }
+}
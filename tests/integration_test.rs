@@ -25,10 +25,12 @@ fn test_wallet_network_switching() {
 
     let mut wallet = Wallet::with_path(db_path.clone()).unwrap();
 
-    wallet.set_network(zcash_numi_sdk::types::Network::Testnet);
+    wallet.set_network(zcash_numi_sdk::types::Network::Testnet).unwrap();
     assert_eq!(wallet.network(), zcash_numi_sdk::types::Network::Testnet);
 
-    wallet.set_network(zcash_numi_sdk::types::Network::Mainnet);
+    // The database is bound to testnet now
+    assert!(wallet.set_network(zcash_numi_sdk::types::Network::Mainnet).is_err());
+    wallet.override_network(zcash_numi_sdk::types::Network::Mainnet).unwrap();
     assert_eq!(wallet.network(), zcash_numi_sdk::types::Network::Mainnet);
 
     let _ = std::fs::remove_file(&db_path);